@@ -0,0 +1,1083 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Avro output format
+//!
+//! Writes [Avro object container files] whose record schema is derived from
+//! the Arrow schema of the batches: integers become `int` or `long`,
+//! strings `string`, binary columns such as the WKB geometries `bytes`, and
+//! nullable columns a `["null", T]` union. Decimals, dates and timestamps
+//! keep their meaning through Avro logical types.
+//!
+//! The sync marker of a file is derived from its schema, so the same data is
+//! always written to the same bytes.
+//!
+//! [Avro object container files]: https://avro.apache.org/docs/1.11.1/specification/#object-container-files
+
+use arrow::array::{
+    Array, ArrayRef, AsArray, BinaryArray, BooleanArray, Decimal128Array, Float32Array,
+    Float64Array, Int32Array, Int64Array, RecordBatch, StringArray, StructArray,
+};
+use arrow::compute::cast;
+use arrow::datatypes::{DataType, Field, Fields, Schema, SchemaRef, TimeUnit};
+use clap::ValueEnum;
+use futures::StreamExt;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use spatialbench_arrow::RecordBatchIterator;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+const MAGIC: &[u8; 4] = b"Obj\x01";
+
+/// Namespace of the generated record schemas
+pub const AVRO_NAMESPACE: &str = "spatialbench";
+
+/// Uncompressed size after which a block is closed, about the sync
+/// interval of the Java implementation
+const BLOCK_BYTES: usize = 64 * 1024;
+
+/// Block compression of Avro files
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum AvroCodec {
+    /// Uncompressed blocks
+    Null,
+    /// Raw deflate
+    #[default]
+    Deflate,
+    /// Snappy, followed by the CRC32 of the uncompressed block
+    Snappy,
+}
+
+impl AvroCodec {
+    /// The `avro.codec` name
+    pub fn name(self) -> &'static str {
+        match self {
+            AvroCodec::Null => "null",
+            AvroCodec::Deflate => "deflate",
+            AvroCodec::Snappy => "snappy",
+        }
+    }
+
+    fn compress(self, block: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            AvroCodec::Null => Ok(block.to_vec()),
+            AvroCodec::Deflate => {
+                let mut encoder =
+                    flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(block)?;
+                encoder.finish()
+            }
+            AvroCodec::Snappy => {
+                let mut compressed = snap::raw::Encoder::new()
+                    .compress_vec(block)
+                    .map_err(io::Error::other)?;
+                compressed.extend_from_slice(&crc32fast::hash(block).to_be_bytes());
+                Ok(compressed)
+            }
+        }
+    }
+}
+
+/// The Avro record schema named `name` of batches with `schema`
+pub fn avro_schema(name: &str, schema: &Schema) -> io::Result<Value> {
+    Ok(json!({
+        "type": "record",
+        "name": name,
+        "namespace": AVRO_NAMESPACE,
+        "fields": record_fields(schema.fields())?,
+    }))
+}
+
+/// Writes the schema of [`avro_schema`] to `path`, the `.avsc` registered
+/// with a schema registry
+pub fn write_avro_schema(path: &Path, name: &str, schema: &Schema) -> io::Result<()> {
+    let mut avsc = serde_json::to_string_pretty(&avro_schema(name, schema)?)?;
+    avsc.push('\n');
+    std::fs::write(path, avsc)
+}
+
+fn record_fields(fields: &Fields) -> io::Result<Vec<Value>> {
+    fields
+        .iter()
+        .map(|field| {
+            let avro_type = field_type(field)?;
+            Ok(match field.is_nullable() {
+                true => json!({"name": field.name(), "type": ["null", avro_type], "default": null}),
+                false => json!({"name": field.name(), "type": avro_type}),
+            })
+        })
+        .collect()
+}
+
+/// The Avro type of the values of `field`, without the null branch
+fn field_type(field: &Field) -> io::Result<Value> {
+    Ok(match field.data_type() {
+        DataType::Boolean => json!("boolean"),
+        DataType::Int8 | DataType::Int16 | DataType::Int32 | DataType::UInt8 | DataType::UInt16 => {
+            json!("int")
+        }
+        DataType::Int64 | DataType::UInt32 => json!("long"),
+        DataType::Float32 => json!("float"),
+        DataType::Float64 => json!("double"),
+        DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View => json!("string"),
+        DataType::Binary | DataType::LargeBinary | DataType::BinaryView => json!("bytes"),
+        DataType::Decimal128(precision, scale) => json!({
+            "type": "bytes",
+            "logicalType": "decimal",
+            "precision": precision,
+            "scale": scale,
+        }),
+        DataType::Date32 => json!({"type": "int", "logicalType": "date"}),
+        DataType::Timestamp(unit, tz) => {
+            let precision = match unit {
+                TimeUnit::Second | TimeUnit::Millisecond => "millis",
+                TimeUnit::Microsecond => "micros",
+                TimeUnit::Nanosecond => "nanos",
+            };
+            // Timestamps without a time zone are wall clock times
+            let logical_type = match tz {
+                Some(_) => format!("timestamp-{precision}"),
+                None => format!("local-timestamp-{precision}"),
+            };
+            json!({"type": "long", "logicalType": logical_type})
+        }
+        DataType::Struct(fields) => json!({
+            "type": "record",
+            "name": field.name(),
+            "fields": record_fields(fields)?,
+        }),
+        other => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Column {} of type {other} can't be written as Avro",
+                    field.name()
+                ),
+            ))
+        }
+    })
+}
+
+/// The header of an Avro file: its schema, codec and sync marker
+#[derive(Clone, Debug)]
+pub struct AvroHeader {
+    schema: SchemaRef,
+    schema_json: String,
+    codec: AvroCodec,
+    sync: [u8; 16],
+    metadata: Vec<(String, String)>,
+}
+
+impl AvroHeader {
+    /// The header of files of records named `name` with `schema`
+    pub fn try_new(name: &str, schema: SchemaRef, codec: AvroCodec) -> io::Result<Self> {
+        let schema_json = avro_schema(name, &schema)?.to_string();
+        let digest = Sha256::digest(schema_json.as_bytes());
+        let mut sync = [0; 16];
+        sync.copy_from_slice(&digest[..16]);
+        Ok(Self {
+            schema,
+            schema_json,
+            codec,
+            sync,
+            metadata: vec![],
+        })
+    }
+
+    /// Adds `key` to the file metadata
+    pub fn append_metadata(&mut self, key: impl Into<String>, value: impl Into<String>) {
+        self.metadata.push((key.into(), value.into()));
+    }
+
+    /// The Avro schema as written to the file
+    pub fn schema_json(&self) -> &str {
+        &self.schema_json
+    }
+
+    fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+        let mut header = MAGIC.to_vec();
+        let entries = [
+            ("avro.schema", self.schema_json.as_str()),
+            ("avro.codec", self.codec.name()),
+        ];
+        let metadata = self.metadata.iter().map(|(k, v)| (k.as_str(), v.as_str()));
+        let entries: Vec<_> = entries.into_iter().chain(metadata).collect();
+        put_long(&mut header, entries.len() as i64);
+        for (key, value) in entries {
+            put_bytes(&mut header, key.as_bytes());
+            put_bytes(&mut header, value.as_bytes());
+        }
+        put_long(&mut header, 0);
+        header.extend_from_slice(&self.sync);
+        out.write_all(&header)
+    }
+
+    fn block_encoder(&self) -> BlockEncoder {
+        BlockEncoder {
+            schema: Arc::clone(&self.schema),
+            codec: self.codec,
+            sync: self.sync,
+            block: Vec::new(),
+            rows: 0,
+        }
+    }
+}
+
+/// Encodes batches into the data blocks of a file
+struct BlockEncoder {
+    schema: SchemaRef,
+    codec: AvroCodec,
+    sync: [u8; 16],
+    /// Uncompressed records of the open block
+    block: Vec<u8>,
+    rows: i64,
+}
+
+impl BlockEncoder {
+    /// Encodes `batch`, writing the blocks it fills to `out`
+    fn write(&mut self, batch: &RecordBatch, out: &mut impl Write) -> io::Result<()> {
+        if batch.schema().fields() != self.schema.fields() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Batch schema doesn't match the Avro file schema",
+            ));
+        }
+        let columns = batch
+            .columns()
+            .iter()
+            .zip(self.schema.fields())
+            .map(|(array, field)| Ok((Column::try_new(array)?, field.is_nullable())))
+            .collect::<io::Result<Vec<_>>>()?;
+        for row in 0..batch.num_rows() {
+            encode_record(&columns, row, &mut self.block);
+            self.rows += 1;
+            if self.block.len() >= BLOCK_BYTES {
+                self.flush(out)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the open block, if any, to `out`
+    fn flush(&mut self, out: &mut impl Write) -> io::Result<()> {
+        if self.rows == 0 {
+            return Ok(());
+        }
+        let data = self.codec.compress(&self.block)?;
+        let mut prefix = Vec::with_capacity(20);
+        put_long(&mut prefix, self.rows);
+        put_long(&mut prefix, data.len() as i64);
+        out.write_all(&prefix)?;
+        out.write_all(&data)?;
+        out.write_all(&self.sync)?;
+        self.block.clear();
+        self.rows = 0;
+        Ok(())
+    }
+}
+
+/// Writes record batches to an Avro object container file
+pub struct AvroWriter<W: Write> {
+    sink: W,
+    encoder: BlockEncoder,
+}
+
+impl<W: Write> AvroWriter<W> {
+    /// Writes `header` to `sink`
+    pub fn try_new(mut sink: W, header: &AvroHeader) -> io::Result<Self> {
+        header.write_to(&mut sink)?;
+        Ok(Self {
+            sink,
+            encoder: header.block_encoder(),
+        })
+    }
+
+    pub fn write(&mut self, batch: &RecordBatch) -> io::Result<()> {
+        self.encoder.write(batch, &mut self.sink)
+    }
+
+    /// Writes the last block, returning the sink
+    pub fn finish(mut self) -> io::Result<W> {
+        self.encoder.flush(&mut self.sink)?;
+        self.sink.flush()?;
+        Ok(self.sink)
+    }
+}
+
+/// Converts a set of [`RecordBatchIterator`]s into an Avro file, returning
+/// `writer` and the number of blocks written
+///
+/// Uses `num_threads` to encode the blocks of the iterators in parallel;
+/// the blocks of each iterator are written in order.
+pub async fn generate_avro<W: Write + Send + 'static, I>(
+    mut writer: W,
+    name: &str,
+    iter_iter: I,
+    num_threads: usize,
+    codec: AvroCodec,
+) -> io::Result<W>
+where
+    I: Iterator<Item: RecordBatchIterator> + 'static,
+{
+    let mut iter_iter = iter_iter.peekable();
+    let Some(first_iter) = iter_iter.peek() else {
+        return Ok(writer);
+    };
+    let header = AvroHeader::try_new(name, Arc::clone(first_iter.schema()), codec)?;
+    header.write_to(&mut writer)?;
+
+    let mut blocks = futures::stream::iter(iter_iter)
+        .map(|iter| {
+            let mut encoder = header.block_encoder();
+            // run on a separate thread
+            async move {
+                tokio::task::spawn_blocking(move || {
+                    let mut blocks = Vec::new();
+                    for batch in iter {
+                        encoder.write(&batch, &mut blocks)?;
+                    }
+                    encoder.flush(&mut blocks)?;
+                    Ok(blocks) as io::Result<Vec<u8>>
+                })
+                .await
+                .expect("Inner task panicked")
+            }
+        })
+        .buffered(num_threads);
+
+    // Writes on a blocking task to avoid having a thread waiting on IO
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<Vec<u8>>(num_threads);
+    let writer_task = tokio::task::spawn_blocking(move || {
+        while let Some(blocks) = rx.blocking_recv() {
+            writer.write_all(&blocks)?;
+        }
+        writer.flush()?;
+        Ok(writer) as io::Result<W>
+    });
+    while let Some(blocks) = blocks.next().await {
+        if tx.send(blocks?).await.is_err() {
+            break; // the writer failed, its error is returned below
+        }
+    }
+    drop(tx);
+    writer_task.await.map_err(io::Error::other)?
+}
+
+/// A column of a batch, cast to the Arrow type matching its Avro encoding
+enum Column {
+    Boolean(BooleanArray),
+    Int(Int32Array),
+    Long(Int64Array),
+    Float(Float32Array),
+    Double(Float64Array),
+    String(StringArray),
+    Bytes(BinaryArray),
+    Decimal(Decimal128Array),
+    Record(StructArray, Vec<(Column, bool)>),
+}
+
+impl Column {
+    /// Must accept the types [`field_type`] maps
+    fn try_new(array: &ArrayRef) -> io::Result<Self> {
+        let cast_to = |data_type: &DataType| cast(array, data_type).map_err(io::Error::other);
+        Ok(match array.data_type() {
+            DataType::Boolean => Column::Boolean(array.as_boolean().clone()),
+            DataType::Int8
+            | DataType::Int16
+            | DataType::Int32
+            | DataType::UInt8
+            | DataType::UInt16
+            | DataType::Date32 => Column::Int(cast_to(&DataType::Int32)?.as_primitive().clone()),
+            DataType::Int64 | DataType::UInt32 => {
+                Column::Long(cast_to(&DataType::Int64)?.as_primitive().clone())
+            }
+            DataType::Timestamp(TimeUnit::Second, tz) => {
+                let millis = cast_to(&DataType::Timestamp(TimeUnit::Millisecond, tz.clone()))?;
+                let millis = cast(&millis, &DataType::Int64).map_err(io::Error::other)?;
+                Column::Long(millis.as_primitive().clone())
+            }
+            DataType::Timestamp(_, _) => {
+                Column::Long(cast_to(&DataType::Int64)?.as_primitive().clone())
+            }
+            DataType::Float32 => Column::Float(array.as_primitive().clone()),
+            DataType::Float64 => Column::Double(array.as_primitive().clone()),
+            DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View => {
+                Column::String(cast_to(&DataType::Utf8)?.as_string().clone())
+            }
+            DataType::Binary | DataType::LargeBinary | DataType::BinaryView => {
+                Column::Bytes(cast_to(&DataType::Binary)?.as_binary().clone())
+            }
+            DataType::Decimal128(_, _) => Column::Decimal(array.as_primitive().clone()),
+            DataType::Struct(fields) => {
+                let array = array.as_struct().clone();
+                let children = array
+                    .columns()
+                    .iter()
+                    .zip(fields)
+                    .map(|(child, field)| Ok((Column::try_new(child)?, field.is_nullable())))
+                    .collect::<io::Result<Vec<_>>>()?;
+                Column::Record(array, children)
+            }
+            other => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Arrays of type {other} can't be written as Avro"),
+                ))
+            }
+        })
+    }
+
+    fn is_null(&self, row: usize) -> bool {
+        match self {
+            Column::Boolean(array) => array.is_null(row),
+            Column::Int(array) => array.is_null(row),
+            Column::Long(array) => array.is_null(row),
+            Column::Float(array) => array.is_null(row),
+            Column::Double(array) => array.is_null(row),
+            Column::String(array) => array.is_null(row),
+            Column::Bytes(array) => array.is_null(row),
+            Column::Decimal(array) => array.is_null(row),
+            Column::Record(array, _) => array.is_null(row),
+        }
+    }
+
+    fn encode(&self, row: usize, out: &mut Vec<u8>) {
+        match self {
+            Column::Boolean(array) => out.push(array.value(row) as u8),
+            Column::Int(array) => put_long(out, array.value(row) as i64),
+            Column::Long(array) => put_long(out, array.value(row)),
+            Column::Float(array) => out.extend_from_slice(&array.value(row).to_le_bytes()),
+            Column::Double(array) => out.extend_from_slice(&array.value(row).to_le_bytes()),
+            Column::String(array) => put_bytes(out, array.value(row).as_bytes()),
+            Column::Bytes(array) => put_bytes(out, array.value(row)),
+            Column::Decimal(array) => put_decimal(out, array.value(row)),
+            Column::Record(_, children) => encode_record(children, row, out),
+        }
+    }
+}
+
+fn encode_record(columns: &[(Column, bool)], row: usize, out: &mut Vec<u8>) {
+    for (column, nullable) in columns {
+        if *nullable {
+            // Branch of the ["null", T] union
+            if column.is_null(row) {
+                put_long(out, 0);
+                continue;
+            }
+            put_long(out, 1);
+        }
+        column.encode(row, out);
+    }
+}
+
+/// Zig-zag varint encoding of `int` and `long`
+fn put_long(out: &mut Vec<u8>, value: i64) {
+    let mut n = ((value << 1) ^ (value >> 63)) as u64;
+    while n >= 0x80 {
+        out.push((n as u8) | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+fn put_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    put_long(out, bytes.len() as i64);
+    out.extend_from_slice(bytes);
+}
+
+/// Encodes the unscaled value of a decimal as the shortest big-endian
+/// two's complement bytes
+fn put_decimal(out: &mut Vec<u8>, value: i128) {
+    let bytes = value.to_be_bytes();
+    // Drop leading bytes that only repeat the sign bit of the next one
+    let mut start = 0;
+    while start < bytes.len() - 1
+        && matches!(
+            (bytes[start], bytes[start + 1] & 0x80),
+            (0x00, 0x00) | (0xff, 0x80)
+        )
+    {
+        start += 1;
+    }
+    put_bytes(out, &bytes[start..]);
+}
+
+/// Counts the records of the Avro object container file read from
+/// `source` from its block headers, without decoding them
+pub fn count_rows(mut source: impl io::Read) -> io::Result<u64> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    let mut magic = [0u8; 4];
+    source.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid("Not an Avro object container file"));
+    }
+    // The metadata map: blocks of key and value pairs up to an empty block
+    loop {
+        let count = read_long(&mut source)?.ok_or_else(|| invalid("Truncated Avro header"))?;
+        if count == 0 {
+            break;
+        }
+        if count < 0 {
+            // A negative count is followed by the byte size of the block
+            let size = read_long(&mut source)?.ok_or_else(|| invalid("Truncated Avro header"))?;
+            skip_bytes(&mut source, size as u64)?;
+            continue;
+        }
+        for _ in 0..count * 2 {
+            let len = read_long(&mut source)?.ok_or_else(|| invalid("Truncated Avro header"))?;
+            skip_bytes(&mut source, len as u64)?;
+        }
+    }
+    skip_bytes(&mut source, 16)?;
+
+    let mut rows = 0;
+    while let Some(count) = read_long(&mut source)? {
+        let size = read_long(&mut source)?.ok_or_else(|| invalid("Truncated Avro block"))?;
+        skip_bytes(&mut source, size as u64 + 16)?;
+        rows += count as u64;
+    }
+    Ok(rows)
+}
+
+fn skip_bytes(source: impl io::Read, bytes: u64) -> io::Result<()> {
+    let skipped = io::copy(&mut source.take(bytes), &mut io::sink())?;
+    match skipped == bytes {
+        true => Ok(()),
+        false => Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "Avro file ends within a block",
+        )),
+    }
+}
+
+/// Reads a zig-zag varint, `None` at the end of `source`
+fn read_long(source: &mut impl io::Read) -> io::Result<Option<i64>> {
+    let mut n = 0u64;
+    let mut shift = 0;
+    let mut byte = [0u8];
+    loop {
+        if source.read(&mut byte)? == 0 {
+            return match shift {
+                0 => Ok(None),
+                _ => Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "Avro file ends within a number",
+                )),
+            };
+        }
+        n |= ((byte[0] & 0x7f) as u64) << shift;
+        shift += 7;
+        if byte[0] & 0x80 == 0 {
+            return Ok(Some((n >> 1) as i64 ^ -((n & 1) as i64)));
+        }
+    }
+}
+
+/// Reads back Avro files in tests, following the writer schema embedded in
+/// the file rather than the Arrow schema the data was written from
+#[cfg(test)]
+pub(crate) mod reader {
+    use super::*;
+    use arrow::array::{Date32Array, TimestampMicrosecondArray, TimestampMillisecondArray};
+    use arrow::buffer::NullBuffer;
+    use std::collections::BTreeMap;
+    use std::io::Read;
+
+    pub struct AvroFile {
+        pub schema: Value,
+        pub metadata: BTreeMap<String, String>,
+        pub batch: RecordBatch,
+        pub blocks: usize,
+    }
+
+    struct Cursor<'a> {
+        bytes: &'a [u8],
+    }
+
+    impl Cursor<'_> {
+        fn take(&mut self, n: usize) -> &[u8] {
+            let (head, tail) = self.bytes.split_at(n);
+            self.bytes = tail;
+            head
+        }
+
+        fn long(&mut self) -> i64 {
+            let mut n = 0u64;
+            let mut shift = 0;
+            loop {
+                let byte = self.take(1)[0];
+                n |= ((byte & 0x7f) as u64) << shift;
+                shift += 7;
+                if byte & 0x80 == 0 {
+                    return (n >> 1) as i64 ^ -((n & 1) as i64);
+                }
+            }
+        }
+
+        fn bytes(&mut self) -> Vec<u8> {
+            let len = self.long() as usize;
+            self.take(len).to_vec()
+        }
+    }
+
+    /// A decoded value
+    #[derive(Clone, Debug)]
+    enum Datum {
+        Null,
+        Boolean(bool),
+        Long(i64),
+        Float(f32),
+        Double(f64),
+        Bytes(Vec<u8>),
+        Record(Vec<Datum>),
+    }
+
+    pub fn read(bytes: &[u8]) -> AvroFile {
+        let mut cursor = Cursor { bytes };
+        assert_eq!(cursor.take(4), MAGIC);
+        let mut metadata = BTreeMap::new();
+        loop {
+            let count = cursor.long();
+            if count == 0 {
+                break;
+            }
+            for _ in 0..count {
+                let key = String::from_utf8(cursor.bytes()).unwrap();
+                let value = String::from_utf8(cursor.bytes()).unwrap();
+                metadata.insert(key, value);
+            }
+        }
+        let sync = cursor.take(16).to_vec();
+        let schema: Value = serde_json::from_str(&metadata["avro.schema"]).unwrap();
+
+        let mut rows = Vec::new();
+        let mut blocks = 0;
+        while !cursor.bytes.is_empty() {
+            let count = cursor.long();
+            let data = cursor.bytes();
+            let block = match metadata["avro.codec"].as_str() {
+                "null" => data,
+                "deflate" => {
+                    let mut block = Vec::new();
+                    flate2::read::DeflateDecoder::new(data.as_slice())
+                        .read_to_end(&mut block)
+                        .unwrap();
+                    block
+                }
+                "snappy" => {
+                    let (data, crc) = data.split_at(data.len() - 4);
+                    let block = snap::raw::Decoder::new().decompress_vec(data).unwrap();
+                    assert_eq!(crc, crc32fast::hash(&block).to_be_bytes());
+                    block
+                }
+                codec => panic!("unknown codec {codec}"),
+            };
+            let mut records = Cursor { bytes: &block };
+            for _ in 0..count {
+                rows.push(decode(&schema, &mut records));
+            }
+            assert!(records.bytes.is_empty(), "trailing bytes in block");
+            assert_eq!(cursor.take(16), sync);
+            blocks += 1;
+        }
+
+        let (fields, columns) = record_columns(&schema, &rows);
+        let batch = RecordBatch::try_new(Arc::new(Schema::new(fields)), columns).unwrap();
+        AvroFile {
+            schema,
+            metadata,
+            batch,
+            blocks,
+        }
+    }
+
+    fn decode(schema: &Value, cursor: &mut Cursor) -> Datum {
+        match schema {
+            Value::Array(branches) => {
+                let branch = cursor.long() as usize;
+                decode(&branches[branch], cursor)
+            }
+            Value::Object(object) if object["type"] == "record" => Datum::Record(
+                object["fields"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|field| decode(&field["type"], cursor))
+                    .collect(),
+            ),
+            Value::Object(object) => decode(&object["type"], cursor),
+            Value::String(name) => match name.as_str() {
+                "null" => Datum::Null,
+                "boolean" => Datum::Boolean(cursor.take(1)[0] != 0),
+                "int" | "long" => Datum::Long(cursor.long()),
+                "float" => Datum::Float(f32::from_le_bytes(cursor.take(4).try_into().unwrap())),
+                "double" => Datum::Double(f64::from_le_bytes(cursor.take(8).try_into().unwrap())),
+                "string" | "bytes" => Datum::Bytes(cursor.bytes()),
+                other => panic!("unexpected type {other}"),
+            },
+            other => panic!("unexpected schema {other}"),
+        }
+    }
+
+    fn record_columns(schema: &Value, rows: &[Datum]) -> (Vec<Field>, Vec<ArrayRef>) {
+        schema["fields"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .enumerate()
+            .map(|(i, field)| {
+                let values: Vec<&Datum> = rows
+                    .iter()
+                    .map(|row| match row {
+                        Datum::Record(values) => &values[i],
+                        // the fields of a null record are null
+                        Datum::Null => &Datum::Null,
+                        other => panic!("expected a record, got {other:?}"),
+                    })
+                    .collect();
+                let name = field["name"].as_str().unwrap();
+                let (field_schema, nullable) = match &field["type"] {
+                    Value::Array(branches) => (&branches[1], true),
+                    other => (other, false),
+                };
+                let array = column(field_schema, &values);
+                (Field::new(name, array.data_type().clone(), nullable), array)
+            })
+            .unzip()
+    }
+
+    /// The Arrow array of `values` of type `schema`
+    fn column(schema: &Value, values: &[&Datum]) -> ArrayRef {
+        let longs = || {
+            values.iter().map(|v| match v {
+                Datum::Long(v) => Some(*v),
+                _ => None,
+            })
+        };
+        let bytes = || {
+            values.iter().map(|v| match v {
+                Datum::Bytes(v) => Some(v.as_slice()),
+                _ => None,
+            })
+        };
+        if schema["type"] == "record" {
+            let (fields, children) = record_columns(
+                schema,
+                &values.iter().map(|v| (*v).clone()).collect::<Vec<_>>(),
+            );
+            let nulls = NullBuffer::from_iter(values.iter().map(|v| !matches!(v, Datum::Null)));
+            return Arc::new(StructArray::new(fields.into(), children, Some(nulls)));
+        }
+        let logical_type = schema["logicalType"].as_str();
+        let avro_type = schema.as_str().or(schema["type"].as_str()).unwrap();
+        match (avro_type, logical_type) {
+            ("boolean", _) => Arc::new(BooleanArray::from_iter(values.iter().map(|v| match v {
+                Datum::Boolean(v) => Some(*v),
+                _ => None,
+            }))),
+            ("int", Some("date")) => {
+                Arc::new(Date32Array::from_iter(longs().map(|v| v.map(|v| v as i32))))
+            }
+            ("int", _) => Arc::new(Int32Array::from_iter(longs().map(|v| v.map(|v| v as i32)))),
+            ("long", Some(logical_type)) => {
+                let tz = (!logical_type.starts_with("local-")).then_some("+00:00");
+                if logical_type.ends_with("millis") {
+                    Arc::new(TimestampMillisecondArray::from_iter(longs()).with_timezone_opt(tz))
+                } else {
+                    Arc::new(TimestampMicrosecondArray::from_iter(longs()).with_timezone_opt(tz))
+                }
+            }
+            ("long", None) => Arc::new(Int64Array::from_iter(longs())),
+            ("float", _) => Arc::new(Float32Array::from_iter(values.iter().map(|v| match v {
+                Datum::Float(v) => Some(*v),
+                _ => None,
+            }))),
+            ("double", _) => Arc::new(Float64Array::from_iter(values.iter().map(|v| match v {
+                Datum::Double(v) => Some(*v),
+                _ => None,
+            }))),
+            ("string", _) => Arc::new(StringArray::from_iter(
+                bytes().map(|v| v.map(|v| std::str::from_utf8(v).unwrap())),
+            )),
+            ("bytes", Some("decimal")) => {
+                let precision = schema["precision"].as_u64().unwrap() as u8;
+                let scale = schema["scale"].as_i64().unwrap() as i8;
+                let unscaled = bytes().map(|v| {
+                    v.map(|v| {
+                        // sign extend the big-endian two's complement bytes
+                        let fill = if v[0] & 0x80 != 0 { 0xff } else { 0 };
+                        let mut be = [fill; 16];
+                        be[16 - v.len()..].copy_from_slice(v);
+                        i128::from_be_bytes(be)
+                    })
+                });
+                Arc::new(
+                    Decimal128Array::from_iter(unscaled)
+                        .with_precision_and_scale(precision, scale)
+                        .unwrap(),
+                )
+            }
+            ("bytes", _) => Arc::new(BinaryArray::from_iter(bytes())),
+            other => panic!("unexpected type {other:?}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{
+        Date32Array, Int16Array, StringViewArray, TimestampMillisecondArray, TimestampSecondArray,
+    };
+    use arrow::buffer::NullBuffer;
+
+    fn batch() -> RecordBatch {
+        let point = Field::new(
+            "point",
+            DataType::Struct(Fields::from(vec![
+                Field::new("x", DataType::Float64, false),
+                Field::new("label", DataType::Utf8View, true),
+            ])),
+            true,
+        );
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("key", DataType::Int64, false),
+            Field::new("small", DataType::Int16, true),
+            Field::new("name", DataType::Utf8View, false),
+            Field::new("geometry", DataType::Binary, false),
+            Field::new("amount", DataType::Decimal128(15, 5), true),
+            Field::new("day", DataType::Date32, true),
+            Field::new(
+                "at",
+                DataType::Timestamp(TimeUnit::Millisecond, None),
+                false,
+            ),
+            Field::new(
+                "at_utc",
+                DataType::Timestamp(TimeUnit::Second, Some("+00:00".into())),
+                true,
+            ),
+            Field::new("flag", DataType::Boolean, true),
+            Field::new("ratio", DataType::Float32, false),
+            point,
+        ]));
+        let rows = 5000;
+        let point = StructArray::new(
+            match schema.field(10).data_type() {
+                DataType::Struct(fields) => fields.clone(),
+                _ => unreachable!(),
+            },
+            vec![
+                Arc::new(Float64Array::from_iter_values(
+                    (0..rows).map(|i| i as f64 / 3.0),
+                )),
+                Arc::new(StringViewArray::from_iter(
+                    (0..rows).map(|i| (i % 4 != 0).then(|| format!("label {i}"))),
+                )),
+            ],
+            Some(NullBuffer::from_iter((0..rows).map(|i| i % 7 != 0))),
+        );
+        RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(Int64Array::from_iter_values(
+                    (0..rows as i64).map(|i| i * 1_000_003 - 2_500_000_000),
+                )),
+                Arc::new(Int16Array::from_iter(
+                    (0..rows).map(|i| (i % 5 != 0).then_some(i as i16 - 2000)),
+                )),
+                Arc::new(StringViewArray::from_iter_values(
+                    (0..rows).map(|i| format!("name of row {i}")),
+                )),
+                Arc::new(BinaryArray::from_iter_values(
+                    (0..rows).map(|i| vec![i as u8; i % 40]),
+                )),
+                Arc::new(
+                    Decimal128Array::from_iter(
+                        (0..rows as i128).map(|i| {
+                            (i % 11 != 0).then_some((i - 2500) * 98_765_432_109 + i % 3 - 1)
+                        }),
+                    )
+                    .with_precision_and_scale(15, 5)
+                    .unwrap(),
+                ),
+                Arc::new(Date32Array::from_iter(
+                    (0..rows).map(|i| (i % 3 != 0).then_some(i as i32 - 100)),
+                )),
+                Arc::new(TimestampMillisecondArray::from_iter_values(
+                    (0..rows as i64).map(|i| 1_700_000_000_000 + i * 61_001),
+                )),
+                Arc::new(
+                    TimestampSecondArray::from_iter(
+                        (0..rows as i64).map(|i| (i % 2 == 0).then_some(1_700_000_000 + i)),
+                    )
+                    .with_timezone("+00:00"),
+                ),
+                Arc::new(BooleanArray::from_iter(
+                    (0..rows).map(|i| (i % 6 != 0).then_some(i % 2 == 0)),
+                )),
+                Arc::new(Float32Array::from_iter_values(
+                    (0..rows).map(|i| i as f32 * 0.25),
+                )),
+                Arc::new(point),
+            ],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_avro_schema() {
+        let schema = avro_schema("sample", &batch().schema()).unwrap();
+        let expected = json!({
+            "type": "record",
+            "name": "sample",
+            "namespace": "spatialbench",
+            "fields": [
+                {"name": "key", "type": "long"},
+                {"name": "small", "type": ["null", "int"], "default": null},
+                {"name": "name", "type": "string"},
+                {"name": "geometry", "type": "bytes"},
+                {"name": "amount", "type": ["null", {
+                    "type": "bytes", "logicalType": "decimal", "precision": 15, "scale": 5
+                }], "default": null},
+                {"name": "day", "type": ["null", {"type": "int", "logicalType": "date"}],
+                 "default": null},
+                {"name": "at", "type": {"type": "long", "logicalType": "local-timestamp-millis"}},
+                {"name": "at_utc", "type": ["null", {
+                    "type": "long", "logicalType": "timestamp-millis"
+                }], "default": null},
+                {"name": "flag", "type": ["null", "boolean"], "default": null},
+                {"name": "ratio", "type": "float"},
+                {"name": "point", "type": ["null", {
+                    "type": "record",
+                    "name": "point",
+                    "fields": [
+                        {"name": "x", "type": "double"},
+                        {"name": "label", "type": ["null", "string"], "default": null},
+                    ],
+                }], "default": null},
+            ],
+        });
+        assert_eq!(schema, expected);
+
+        let unsupported = Schema::new(vec![Field::new("d", DataType::Float16, false)]);
+        let error = avro_schema("t", &unsupported).unwrap_err();
+        assert!(
+            error.to_string().contains("Column d of type Float16"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn test_decimal_bytes() {
+        for (value, expected) in [
+            (0i128, vec![0x00]),
+            (127, vec![0x7f]),
+            (128, vec![0x00, 0x80]),
+            (-1, vec![0xff]),
+            (-128, vec![0x80]),
+            (-129, vec![0xff, 0x7f]),
+            (65_535, vec![0x00, 0xff, 0xff]),
+        ] {
+            let mut out = Vec::new();
+            put_decimal(&mut out, value);
+            assert_eq!(out[1..], expected, "{value}");
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let batch = batch();
+        for codec in [AvroCodec::Null, AvroCodec::Deflate, AvroCodec::Snappy] {
+            let mut header = AvroHeader::try_new("sample", batch.schema(), codec).unwrap();
+            header.append_metadata("spatialbench.version", "1");
+            let mut writer = AvroWriter::try_new(Vec::new(), &header).unwrap();
+            writer.write(&batch.slice(0, 1234)).unwrap();
+            writer
+                .write(&batch.slice(1234, batch.num_rows() - 1234))
+                .unwrap();
+            let bytes = writer.finish().unwrap();
+
+            let file = reader::read(&bytes);
+            assert_eq!(file.metadata["avro.codec"], codec.name());
+            assert_eq!(file.metadata["spatialbench.version"], "1");
+            assert_eq!(file.schema, avro_schema("sample", &batch.schema()).unwrap());
+            assert!(file.blocks > 1, "{codec:?} wrote {} blocks", file.blocks);
+            assert_eq!(
+                count_rows(bytes.as_slice()).unwrap(),
+                batch.num_rows() as u64
+            );
+            assert!(count_rows(&bytes[..bytes.len() - 1]).is_err());
+
+            let read = file.batch;
+            assert_eq!(read.num_rows(), batch.num_rows());
+            for (i, field) in batch.schema().fields().iter().enumerate() {
+                let expected = cast(batch.column(i), read.column(i).data_type()).unwrap();
+                assert_eq!(
+                    read.column(i).as_ref(),
+                    expected.as_ref(),
+                    "{codec:?} {}",
+                    field.name()
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_generate_avro_matches_writer() {
+        struct Batches(SchemaRef, std::vec::IntoIter<RecordBatch>);
+        impl Iterator for Batches {
+            type Item = RecordBatch;
+            fn next(&mut self) -> Option<RecordBatch> {
+                self.1.next()
+            }
+        }
+        impl RecordBatchIterator for Batches {
+            fn schema(&self) -> &SchemaRef {
+                &self.0
+            }
+        }
+
+        let batch = batch();
+        let parts: Vec<_> = (0..5).map(|i| batch.slice(i * 1000, 1000)).collect();
+        let schema = batch.schema();
+        let sources: Vec<_> = parts
+            .iter()
+            .map(|part| Batches(Arc::clone(&schema), vec![part.clone()].into_iter()))
+            .collect();
+        let generated = generate_avro(
+            Vec::new(),
+            "sample",
+            sources.into_iter(),
+            3,
+            AvroCodec::Snappy,
+        )
+        .await
+        .unwrap();
+
+        // Each part is closed as a block of its own
+        let header = AvroHeader::try_new("sample", schema, AvroCodec::Snappy).unwrap();
+        let mut writer = AvroWriter::try_new(Vec::new(), &header).unwrap();
+        for part in &parts {
+            writer.write(part).unwrap();
+            writer.encoder.flush(&mut writer.sink).unwrap();
+        }
+        assert_eq!(generated, writer.finish().unwrap());
+    }
+}
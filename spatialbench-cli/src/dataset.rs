@@ -0,0 +1,264 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! `dataset.json`, the descriptor of a generated dataset
+//!
+//! Written after every run that generates tables into files, it ties the
+//! tables together: the scale factor, and for every table its files with
+//! their row counts and sizes. Row counts are read back from the written
+//! files: Parquet footers, CSV and TBL lines, Avro block headers and, for
+//! the zone table, its manifest, which also gives the zone schema version
+//! and the input the zones were read from.
+
+use anyhow::{anyhow, Result};
+use log::debug;
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use crate::avro;
+use crate::zone::ZoneManifest;
+
+pub const DATASET_FILE_NAME: &str = "dataset.json";
+
+/// The generated tables of one output directory
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DatasetManifest {
+    pub scale_factor: f64,
+    /// Version of spatialbench-cli that generated the dataset
+    pub spatialbench_version: String,
+    /// Where the zones were read from, e.g.
+    /// `division_area=overture:2025-08-20.1`, when the zone table was
+    /// generated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input: Option<String>,
+    /// SHA-256 of the `ATTRIBUTION.txt` of the zone source, when written
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attribution_sha256: Option<String>,
+    pub tables: Vec<DatasetTable>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DatasetTable {
+    pub name: String,
+    pub rows: u64,
+    /// Numbered schema version of the files, recorded for the zone table
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema_version: Option<String>,
+    pub files: Vec<DatasetFile>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DatasetFile {
+    /// `/` separated path relative to the output directory
+    pub path: String,
+    pub rows: u64,
+    pub bytes: u64,
+}
+
+impl DatasetManifest {
+    /// Describes `tables` as written into `output_dir` in files with
+    /// `extension`
+    pub fn describe(
+        output_dir: &Path,
+        scale_factor: f64,
+        tables: &[&str],
+        extension: &str,
+    ) -> Result<Self> {
+        let mut input = None;
+        let mut attribution_sha256 = None;
+        let mut described = Vec::with_capacity(tables.len());
+        for &table in tables {
+            let described_table = if table == "zone" {
+                let manifest = ZoneManifest::read(output_dir)?
+                    .ok_or_else(|| anyhow!("No zone manifest found in {}", output_dir.display()))?;
+                input = manifest.source.clone();
+                attribution_sha256 = manifest.attribution_sha256.clone();
+                zone_table(output_dir, &manifest)?
+            } else {
+                let files = table_files(output_dir, table, extension)?
+                    .into_iter()
+                    .map(|path| describe_file(output_dir, &path, extension))
+                    .collect::<Result<Vec<_>>>()?;
+                DatasetTable {
+                    name: table.to_string(),
+                    rows: files.iter().map(|f| f.rows).sum(),
+                    schema_version: None,
+                    files,
+                }
+            };
+            described.push(described_table);
+        }
+        Ok(Self {
+            scale_factor,
+            spatialbench_version: env!("CARGO_PKG_VERSION").to_string(),
+            input,
+            attribution_sha256,
+            tables: described,
+        })
+    }
+
+    pub fn path(output_dir: &Path) -> PathBuf {
+        output_dir.join(DATASET_FILE_NAME)
+    }
+
+    pub fn read(output_dir: &Path) -> Result<Option<Self>> {
+        let path = Self::path(output_dir);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let text = std::fs::read_to_string(&path)?;
+        Ok(Some(serde_json::from_str(&text).map_err(|e| {
+            anyhow!("Failed parsing {}: {e}", path.display())
+        })?))
+    }
+
+    /// Describes the zone table anew from `manifest`, after its files were
+    /// rewritten in place
+    pub fn refresh_zone_table(&mut self, output_dir: &Path, manifest: &ZoneManifest) -> Result<()> {
+        if let Some(table) = self.tables.iter_mut().find(|t| t.name == "zone") {
+            *table = zone_table(output_dir, manifest)?;
+        }
+        Ok(())
+    }
+
+    pub fn write(&self, output_dir: &Path) -> Result<PathBuf> {
+        let path = Self::path(output_dir);
+        std::fs::write(&path, serde_json::to_string_pretty(self)? + "\n")?;
+        debug!("Wrote {}", path.display());
+        Ok(path)
+    }
+}
+
+/// The zone table as recorded in its manifest, without the merged copy
+/// that repeats the rows of the parts
+fn zone_table(output_dir: &Path, manifest: &ZoneManifest) -> Result<DatasetTable> {
+    let files = manifest
+        .files
+        .iter()
+        .map(|file| {
+            Ok(DatasetFile {
+                path: file.path.clone(),
+                rows: file.rows,
+                bytes: std::fs::metadata(output_dir.join(&file.path))?.len(),
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(DatasetTable {
+        name: "zone".to_string(),
+        rows: files.iter().map(|f| f.rows).sum(),
+        schema_version: manifest.schema_version.clone(),
+        files,
+    })
+}
+
+/// The files of `table`: `<table>.<extension>` or the files with that
+/// extension under `<table>/`, sorted by path
+fn table_files(output_dir: &Path, table: &str, extension: &str) -> Result<Vec<PathBuf>> {
+    let single = output_dir.join(format!("{table}.{extension}"));
+    if single.is_file() {
+        return Ok(vec![single]);
+    }
+    let mut files = Vec::new();
+    let mut dirs = vec![output_dir.join(table)];
+    while let Some(dir) = dirs.pop() {
+        if !dir.is_dir() {
+            continue;
+        }
+        for entry in std::fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if path.extension().is_some_and(|e| e == extension) {
+                files.push(path);
+            }
+        }
+    }
+    if files.is_empty() {
+        return Err(anyhow!(
+            "No {extension} files for table {table} in {}",
+            output_dir.display()
+        ));
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn describe_file(output_dir: &Path, path: &Path, extension: &str) -> Result<DatasetFile> {
+    let rows = match extension {
+        "parquet" => SerializedFileReader::new(File::open(path)?)?
+            .metadata()
+            .file_metadata()
+            .num_rows() as u64,
+        "avro" => avro::count_rows(BufReader::new(File::open(path)?))?,
+        "csv" | "tbl" => {
+            let lines = BufReader::new(File::open(path)?).split(b'\n').count() as u64;
+            // CSV files open with a header line
+            match extension {
+                "csv" => lines.saturating_sub(1),
+                _ => lines,
+            }
+        }
+        _ => return Err(anyhow!("Can't count the rows of {}", path.display())),
+    };
+    let relative = path.strip_prefix(output_dir).unwrap_or(path);
+    Ok(DatasetFile {
+        path: relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/"),
+        rows,
+        bytes: std::fs::metadata(path)?.len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_tables() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("vehicle.csv"), "v_key,v_kind\n1,a\n2,b\n").unwrap();
+        std::fs::create_dir_all(dir.path().join("trip")).unwrap();
+        std::fs::write(dir.path().join("trip/trip.1.tbl"), "1|x|\n2|y|\n").unwrap();
+        std::fs::write(dir.path().join("trip/trip.2.tbl"), "3|z|\n").unwrap();
+
+        let csv = DatasetManifest::describe(dir.path(), 0.1, &["vehicle"], "csv").unwrap();
+        assert_eq!(csv.tables[0].rows, 2);
+        assert_eq!(csv.tables[0].files[0].path, "vehicle.csv");
+
+        let tbl = DatasetManifest::describe(dir.path(), 0.1, &["trip"], "tbl").unwrap();
+        let rows: Vec<_> = tbl.tables[0]
+            .files
+            .iter()
+            .map(|f| (f.path.as_str(), f.rows))
+            .collect();
+        assert_eq!(rows, vec![("trip/trip.1.tbl", 2), ("trip/trip.2.tbl", 1)]);
+        assert_eq!(tbl.tables[0].rows, 3);
+
+        tbl.write(dir.path()).unwrap();
+        let read = DatasetManifest::read(dir.path()).unwrap().unwrap();
+        assert_eq!(read.tables[0].files.len(), 2);
+
+        let missing = DatasetManifest::describe(dir.path(), 0.1, &["customer"], "tbl");
+        assert!(missing.is_err());
+    }
+}
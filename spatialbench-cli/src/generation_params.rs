@@ -0,0 +1,99 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Generation parameters embedded in the footer of every written file
+//!
+//! With `--embed-params` each Parquet file, and each zone ORC or Avro
+//! file, records the run that wrote it as JSON under
+//! [`GENERATION_PARAMS_KEY`], so a file found on its own still names the
+//! scale factor, flags, input and spatialbench-cli version behind it.
+
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Footer key of the [`GenerationParams`] JSON
+pub const GENERATION_PARAMS_KEY: &str = "spatialbench.generation_params";
+
+/// The parameters of the run that wrote a file
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GenerationParams {
+    pub spatialbench_version: String,
+    pub scale_factor: f64,
+    /// Command line arguments of the run, without the program name
+    pub args: Vec<String>,
+    /// Sources the zones were read from, in the `--input-theme` syntax
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input: Option<String>,
+    /// Seconds since the Unix epoch at the start of the run, left out of
+    /// `--deterministic` runs so their files stay byte-identical
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub generated_at: Option<u64>,
+}
+
+impl GenerationParams {
+    /// The parameters of the running process at `scale_factor`
+    pub fn from_env(scale_factor: f64, deterministic: bool) -> Self {
+        Self {
+            spatialbench_version: env!("CARGO_PKG_VERSION").to_string(),
+            scale_factor,
+            args: std::env::args().skip(1).collect(),
+            input: None,
+            generated_at: (!deterministic).then(|| {
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |d| d.as_secs())
+            }),
+        }
+    }
+
+    pub fn with_input(mut self, input: String) -> Self {
+        self.input = Some(input);
+        self
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("generation parameters serialize to JSON")
+    }
+
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(json)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let params = GenerationParams::from_env(10.0, false).with_input("overture".to_string());
+        assert_eq!(params.spatialbench_version, env!("CARGO_PKG_VERSION"));
+        assert!(params.generated_at.is_some());
+        assert_eq!(
+            GenerationParams::from_json(&params.to_json()).unwrap(),
+            params
+        );
+    }
+
+    #[test]
+    fn test_deterministic_runs_leave_out_the_time() {
+        let params = GenerationParams::from_env(1.0, true);
+        assert_eq!(params.generated_at, None);
+        assert!(!params.to_json().contains("generated_at"));
+        assert!(!params.to_json().contains("input"));
+    }
+}
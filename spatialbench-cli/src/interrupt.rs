@@ -0,0 +1,81 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Ctrl-C handling for generation runs
+//!
+//! The first ctrl-C sets a shared [`CancellationFlag`]: no new parts are
+//! started, the part being written is finished or aborted according to
+//! [`OnInterrupt`], and the process exits with [`INTERRUPTED_EXIT_CODE`].
+//! A second ctrl-C exits immediately with [`FORCE_ABORT_EXIT_CODE`].
+
+use clap::ValueEnum;
+use log::warn;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Exit code after a graceful interrupt (128 + SIGINT)
+pub const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+/// Exit code when a second ctrl-C aborts without cleaning up
+pub const FORCE_ABORT_EXIT_CODE: i32 = 131;
+
+/// What to do with the part being written when interrupted
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum OnInterrupt {
+    /// Finish writing the in-flight part before exiting
+    #[default]
+    FinishPart,
+    /// Stop writing the in-flight part and delete its temporary file
+    AbortPart,
+}
+
+/// Flag shared between the signal handler and the writer loops
+#[derive(Clone, Debug, Default)]
+pub struct CancellationFlag(Arc<AtomicBool>);
+
+impl CancellationFlag {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Spawns a task that cancels `flag` on the first ctrl-C and exits the
+/// process on the second
+pub fn install_ctrl_c_handler(flag: CancellationFlag) {
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_err() {
+            return;
+        }
+        warn!("Interrupted, finishing up. Press ctrl-C again to abort immediately");
+        flag.cancel();
+
+        if tokio::signal::ctrl_c().await.is_ok() {
+            eprintln!("Aborted");
+            std::process::exit(FORCE_ABORT_EXIT_CODE);
+        }
+    });
+}
+
+/// The error returned once a run stopped because of an interrupt
+pub fn interrupted_error() -> io::Error {
+    io::Error::new(io::ErrorKind::Interrupted, "Generation interrupted")
+}
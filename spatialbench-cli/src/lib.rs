@@ -0,0 +1,35 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Library half of the `spatialbench-cli` package.
+//!
+//! Exposes the zone table pipeline so it can be embedded, for example with
+//! [`zone::register_generated`] to register freshly generated zones in an
+//! existing DataFusion `SessionContext`.
+
+pub mod avro;
+pub mod dataset;
+pub mod generation_params;
+pub mod interrupt;
+pub mod load_scripts;
+pub mod otlp;
+pub mod output_dir;
+pub mod output_lock;
+pub mod readers;
+pub mod retry;
+pub mod upload;
+pub mod zone;
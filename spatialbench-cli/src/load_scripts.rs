@@ -0,0 +1,467 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Scripts that load a generated dataset into a spatial engine
+//!
+//! The scripts are templated from the Parquet files as written: the column
+//! list comes from the Arrow schema in the first file of every table, so
+//! optional columns show up without the templates knowing about them.
+//! Geometry columns are the ones named in GeoParquet `geo` metadata, or
+//! every binary column when there is none, and are converted from WKB.
+
+use anyhow::{anyhow, Result};
+use arrow_schema::{DataType, SchemaRef};
+use clap::ValueEnum;
+use log::info;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use std::fmt::Write;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::zone::MAX_LENGTH_KEY;
+
+/// Engine a load script is written for
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum LoadScript {
+    /// `load_sedona.py` for PySpark and `load_sedona.sql` for Sedona SQL
+    SedonaSpark,
+    /// `load_postgis.sql` for psql, converting through the DuckDB CLI
+    Postgis,
+    /// `load_duckdb.sql` using the spatial extension
+    Duckdb,
+}
+
+/// A generated table and its Parquet files
+#[derive(Debug)]
+pub struct TableFiles {
+    pub name: String,
+    /// Absolute path of the single file, or of the directory of part files
+    pub path: PathBuf,
+    pub file_count: usize,
+    /// The Parquet files, sorted by path
+    pub files: Vec<PathBuf>,
+    pub schema: SchemaRef,
+    pub geometry_columns: Vec<String>,
+}
+
+impl TableFiles {
+    /// Finds the Parquet files of `table` in `output_dir`, either
+    /// `<table>.parquet` or `<table>/*.parquet`
+    pub fn find(output_dir: &Path, table: &str) -> Result<Self> {
+        let single = output_dir.join(format!("{table}.parquet"));
+        let (path, files) = if single.is_file() {
+            (single.clone(), vec![single])
+        } else {
+            let dir = output_dir.join(table);
+            let mut files = Vec::new();
+            if dir.is_dir() {
+                for entry in std::fs::read_dir(&dir)? {
+                    let path = entry?.path();
+                    if path.extension().is_some_and(|e| e == "parquet") {
+                        files.push(path);
+                    }
+                }
+            }
+            files.sort();
+            (dir, files)
+        };
+        let first = files.first().ok_or_else(|| {
+            anyhow!(
+                "No Parquet files for table {table} in {}",
+                output_dir.display()
+            )
+        })?;
+
+        let schema = ParquetRecordBatchReaderBuilder::try_new(File::open(first)?)?
+            .schema()
+            .clone();
+        let geometry_columns = geometry_columns(&schema)?;
+        Ok(Self {
+            name: table.to_string(),
+            path: std::fs::canonicalize(&path)?,
+            file_count: files.len(),
+            files,
+            schema,
+            geometry_columns,
+        })
+    }
+
+    fn is_geometry(&self, column: &str) -> bool {
+        self.geometry_columns.iter().any(|c| c == column)
+    }
+
+    /// `path` as a glob over the part files when it is a directory
+    fn glob(&self) -> String {
+        if self.path.is_dir() {
+            format!("{}/*.parquet", self.path.display())
+        } else {
+            self.path.display().to_string()
+        }
+    }
+}
+
+/// Columns listed in the GeoParquet metadata, else every binary column
+fn geometry_columns(schema: &SchemaRef) -> Result<Vec<String>> {
+    if let Some(geo) = schema.metadata().get("geo") {
+        let geo: serde_json::Value = serde_json::from_str(geo)?;
+        if let Some(columns) = geo.get("columns").and_then(|c| c.as_object()) {
+            return Ok(columns.keys().cloned().collect());
+        }
+    }
+    Ok(schema
+        .fields()
+        .iter()
+        .filter(|f| {
+            matches!(
+                f.data_type(),
+                DataType::Binary | DataType::LargeBinary | DataType::BinaryView
+            )
+        })
+        .map(|f| f.name().clone())
+        .collect())
+}
+
+/// Writes the scripts of every flavor for `tables` into `output_dir`
+pub fn write_load_scripts(
+    output_dir: &Path,
+    tables: &[&str],
+    flavors: &[LoadScript],
+) -> io::Result<Vec<PathBuf>> {
+    let tables = tables
+        .iter()
+        .map(|table| TableFiles::find(output_dir, table))
+        .collect::<Result<Vec<_>>>()
+        .map_err(io::Error::other)?;
+
+    let mut written = Vec::new();
+    for flavor in flavors {
+        let scripts = match flavor {
+            LoadScript::SedonaSpark => vec![
+                ("load_sedona.py", sedona_python(&tables)),
+                ("load_sedona.sql", sedona_sql(&tables)),
+            ],
+            LoadScript::Postgis => vec![("load_postgis.sql", postgis_sql(&tables))],
+            LoadScript::Duckdb => vec![("load_duckdb.sql", duckdb_sql(&tables))],
+        };
+        for (file_name, script) in scripts {
+            let path = output_dir.join(file_name);
+            std::fs::write(&path, script)?;
+            info!("Wrote load script {}", path.display());
+            written.push(path);
+        }
+    }
+    Ok(written)
+}
+
+/// Single quoted SQL string literal
+fn sql_string(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+fn table_names(tables: &[TableFiles]) -> String {
+    tables
+        .iter()
+        .map(|t| t.name.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn sedona_python(tables: &[TableFiles]) -> String {
+    let mut script = format!(
+        "# Loads the SpatialBench tables {} into Apache Sedona.\n\
+         # Generated by spatialbench-cli; geometry columns are converted from WKB.\n\
+         from sedona.spark import SedonaContext\n\n\
+         config = SedonaContext.builder().getOrCreate()\n\
+         sedona = SedonaContext.create(config)\n",
+        table_names(tables)
+    );
+    for table in tables {
+        let name = &table.name;
+        let _ = write!(
+            script,
+            "\n# {name}: {} file(s)\n{name} = sedona.read.parquet({:?}).selectExpr(\n",
+            table.file_count,
+            table.path.display().to_string()
+        );
+        for field in table.schema.fields() {
+            let column = field.name();
+            let expr = if table.is_geometry(column) {
+                format!("ST_GeomFromWKB(`{column}`) AS `{column}`")
+            } else {
+                format!("`{column}`")
+            };
+            let _ = writeln!(script, "    {expr:?},");
+        }
+        script.push_str(")\n");
+        if table.file_count > 1 {
+            let _ = writeln!(script, "{name} = {name}.repartition({})", table.file_count);
+        }
+        let _ = writeln!(
+            script,
+            "{name} = {name}.cache()\n{name}.createOrReplaceTempView({name:?})"
+        );
+    }
+    script
+}
+
+fn sedona_sql(tables: &[TableFiles]) -> String {
+    let mut script = format!(
+        "-- Loads the SpatialBench tables {} in Sedona SQL.\n\
+         -- Generated by spatialbench-cli; geometry columns are converted from WKB.\n",
+        table_names(tables)
+    );
+    for table in tables {
+        let name = &table.name;
+        let hint = match table.file_count {
+            1 => String::new(),
+            n => format!(" /*+ REPARTITION({n}) */"),
+        };
+        let columns = table
+            .schema
+            .fields()
+            .iter()
+            .map(|f| match f.name() {
+                c if table.is_geometry(c) => format!("  ST_GeomFromWKB(`{c}`) AS `{c}`"),
+                c => format!("  `{c}`"),
+            })
+            .collect::<Vec<_>>()
+            .join(",\n");
+        let _ = write!(
+            script,
+            "\nCREATE OR REPLACE TEMPORARY VIEW {name}_files USING parquet OPTIONS (path {});\n\
+             CREATE OR REPLACE TEMPORARY VIEW {name} AS\nSELECT{hint}\n{columns}\nFROM {name}_files;\n\
+             CACHE TABLE {name};\n",
+            sql_string(&table.path.display().to_string())
+        );
+    }
+    script
+}
+
+fn duckdb_sql(tables: &[TableFiles]) -> String {
+    let mut script = format!(
+        "-- Loads the SpatialBench tables {} into DuckDB.\n\
+         -- Generated by spatialbench-cli; geometry columns are converted from WKB.\n\
+         INSTALL spatial;\nLOAD spatial;\n",
+        table_names(tables)
+    );
+    for table in tables {
+        let columns = table
+            .schema
+            .fields()
+            .iter()
+            .map(|f| match f.name() {
+                c if table.is_geometry(c) => format!("  ST_GeomFromWKB(\"{c}\") AS \"{c}\""),
+                c => format!("  \"{c}\""),
+            })
+            .collect::<Vec<_>>()
+            .join(",\n");
+        let _ = write!(
+            script,
+            "\nCREATE OR REPLACE TABLE {} AS\nSELECT\n{columns}\nFROM read_parquet({});\n",
+            table.name,
+            sql_string(&table.glob())
+        );
+    }
+    script
+}
+
+/// PostgreSQL column type of an Arrow field
+fn postgres_type(data_type: &DataType) -> String {
+    match data_type {
+        DataType::Boolean => "boolean".to_string(),
+        DataType::Int8 | DataType::Int16 | DataType::UInt8 => "smallint".to_string(),
+        DataType::Int32 | DataType::UInt16 => "integer".to_string(),
+        DataType::Int64 | DataType::UInt32 => "bigint".to_string(),
+        DataType::UInt64 => "numeric(20, 0)".to_string(),
+        DataType::Float16 | DataType::Float32 => "real".to_string(),
+        DataType::Float64 => "double precision".to_string(),
+        DataType::Decimal128(precision, scale) | DataType::Decimal256(precision, scale) => {
+            format!("numeric({precision}, {scale})")
+        }
+        DataType::Date32 | DataType::Date64 => "date".to_string(),
+        DataType::Timestamp(_, None) => "timestamp".to_string(),
+        DataType::Timestamp(_, Some(_)) => "timestamptz".to_string(),
+        DataType::Binary | DataType::LargeBinary | DataType::BinaryView => "bytea".to_string(),
+        _ => "text".to_string(),
+    }
+}
+
+fn postgis_sql(tables: &[TableFiles]) -> String {
+    let mut script = format!(
+        "-- Loads the SpatialBench tables {} into PostGIS. Run with psql.\n\
+         -- Generated by spatialbench-cli. The DuckDB CLI converts the Parquet files to CSV\n\
+         -- with hex WKB geometries, which PostGIS parses on input.\n\
+         CREATE EXTENSION IF NOT EXISTS postgis;\n",
+        table_names(tables)
+    );
+    for table in tables {
+        let name = &table.name;
+        let fields = table.schema.fields();
+        let columns = fields
+            .iter()
+            .map(|f| {
+                let data_type = match (f.name(), f.metadata().get(MAX_LENGTH_KEY)) {
+                    (c, _) if table.is_geometry(c) => "geometry".to_string(),
+                    // The byte limit also bounds the characters varchar counts
+                    (_, Some(bytes)) => format!("varchar({bytes})"),
+                    _ => postgres_type(f.data_type()),
+                };
+                let not_null = if f.is_nullable() { "" } else { " NOT NULL" };
+                format!("  \"{}\" {data_type}{not_null}", f.name())
+            })
+            .collect::<Vec<_>>()
+            .join(",\n");
+        let select = fields
+            .iter()
+            .map(|f| match f.name() {
+                c if table.is_geometry(c) => format!("hex({c}) AS {c}"),
+                c => c.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        // psql reads \copy to the end of the line; quotes inside the program
+        // string are doubled
+        let program = format!(
+            "duckdb -csv -c \"SELECT {select} FROM read_parquet({})\"",
+            sql_string(&table.glob())
+        );
+        let _ = write!(
+            script,
+            "\nDROP TABLE IF EXISTS {name};\nCREATE TABLE {name} (\n{columns}\n);\n\
+             \\copy {name} FROM PROGRAM {} WITH (FORMAT csv, HEADER)\n",
+            sql_string(&program)
+        );
+        for column in &table.geometry_columns {
+            let _ = write!(
+                script,
+                "ALTER TABLE {name} ALTER COLUMN \"{column}\" TYPE geometry(Geometry, 4326) \
+                 USING ST_SetSRID(\"{column}\", 4326);\n\
+                 CREATE INDEX ON {name} USING GIST (\"{column}\");\n"
+            );
+        }
+        let _ = writeln!(script, "ANALYZE {name};");
+    }
+    script
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_array::{BinaryArray, Float64Array, Int64Array, RecordBatch, StringViewArray};
+    use arrow_schema::{Field, Schema};
+    use parquet::arrow::ArrowWriter;
+    use std::sync::Arc;
+
+    fn write_part(path: &Path) {
+        write_part_with_name(path, Field::new("z_name", DataType::Utf8View, false));
+    }
+
+    fn write_part_with_name(path: &Path, name: Field) {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("z_zonekey", DataType::Int64, false),
+            name,
+            Field::new("z_boundary", DataType::Binary, false),
+            Field::new("z_pop", DataType::Float64, true),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int64Array::from(vec![1])),
+                Arc::new(StringViewArray::from(vec!["a"])),
+                Arc::new(BinaryArray::from(vec![b"\x01".as_slice()])),
+                Arc::new(Float64Array::from(vec![1.5])),
+            ],
+        )
+        .unwrap();
+        let mut writer = ArrowWriter::try_new(File::create(path).unwrap(), schema, None).unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+    }
+
+    #[test]
+    fn test_scripts_follow_written_schema() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("zone")).unwrap();
+        write_part(&dir.path().join("zone/zone.1.parquet"));
+        write_part(&dir.path().join("zone/zone.2.parquet"));
+
+        let written = write_load_scripts(
+            dir.path(),
+            &["zone"],
+            &[
+                LoadScript::SedonaSpark,
+                LoadScript::Postgis,
+                LoadScript::Duckdb,
+            ],
+        )
+        .unwrap();
+        assert_eq!(written.len(), 4);
+        let read = |name: &str| std::fs::read_to_string(dir.path().join(name)).unwrap();
+        let zone_dir = std::fs::canonicalize(dir.path().join("zone")).unwrap();
+
+        let python = read("load_sedona.py");
+        assert!(python.contains(&format!(
+            "sedona.read.parquet({:?})",
+            zone_dir.display().to_string()
+        )));
+        assert!(python.contains("\"ST_GeomFromWKB(`z_boundary`) AS `z_boundary`\","));
+        assert!(python.contains("\"`z_pop`\","));
+        assert!(python.contains("zone = zone.repartition(2)"));
+        assert!(python.contains("zone.createOrReplaceTempView(\"zone\")"));
+
+        let sql = read("load_sedona.sql");
+        assert!(sql.contains("SELECT /*+ REPARTITION(2) */"));
+        assert!(sql.contains("CACHE TABLE zone;"));
+
+        let duckdb = read("load_duckdb.sql");
+        assert!(duckdb.contains("ST_GeomFromWKB(\"z_boundary\") AS \"z_boundary\""));
+        assert!(duckdb.contains(&format!("read_parquet('{}/*.parquet')", zone_dir.display())));
+
+        let postgis = read("load_postgis.sql");
+        assert!(postgis.contains("\"z_zonekey\" bigint NOT NULL"));
+        assert!(postgis.contains("\"z_name\" text NOT NULL"));
+        assert!(postgis.contains("\"z_pop\" double precision\n);"));
+        assert!(postgis.contains("\"z_boundary\" geometry NOT NULL"));
+        assert!(postgis.contains("SELECT z_zonekey, z_name, hex(z_boundary) AS z_boundary, z_pop"));
+        assert!(postgis.contains("CREATE INDEX ON zone USING GIST (\"z_boundary\");"));
+    }
+
+    #[test]
+    fn test_postgis_declares_max_string_lengths() {
+        let dir = tempfile::tempdir().unwrap();
+        let name = Field::new("z_name", DataType::Utf8View, false).with_metadata(
+            [(MAX_LENGTH_KEY.to_string(), "255".to_string())]
+                .into_iter()
+                .collect(),
+        );
+        write_part_with_name(&dir.path().join("zone.parquet"), name);
+
+        write_load_scripts(dir.path(), &["zone"], &[LoadScript::Postgis]).unwrap();
+        let postgis = std::fs::read_to_string(dir.path().join("load_postgis.sql")).unwrap();
+        assert!(
+            postgis.contains("\"z_name\" varchar(255) NOT NULL"),
+            "{postgis}"
+        );
+    }
+
+    #[test]
+    fn test_missing_table_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(write_load_scripts(dir.path(), &["trip"], &[LoadScript::Duckdb]).is_err());
+    }
+}
@@ -31,37 +31,117 @@ mod runner;
 mod spatial_config_file;
 mod statistics;
 mod tbl;
-mod zone;
 
 use crate::generate::Sink;
 use crate::output_plan::OutputPlanGenerator;
 use crate::parquet::*;
-use crate::plan::{GenerationPlan, DEFAULT_PARQUET_ROW_GROUP_BYTES};
+use crate::plan::DEFAULT_PARQUET_ROW_GROUP_BYTES;
 use crate::spatial_config_file::parse_yaml;
 use crate::statistics::WriteStatistics;
 use ::parquet::basic::Compression;
 use clap::builder::TypedValueParser;
-use clap::{Parser, ValueEnum};
-use log::{debug, info, LevelFilter};
+use clap::{ArgGroup, Args, Parser, Subcommand, ValueEnum};
+use log::{debug, info, warn, LevelFilter};
+use spatialbench::dates::{
+    format_generated_date, parse_generated_date, MIN_GENERATE_DATE, TOTAL_DATE_RANGE,
+};
 use spatialbench::distribution::Distributions;
 use spatialbench::spatial::overrides::{set_overrides, SpatialOverrides};
+use spatialbench::spatial::{PointDistribution, SpatialDefaults};
 use spatialbench::text::TextPool;
+use spatialbench_cli::avro::AvroCodec;
+use spatialbench_cli::dataset::DatasetManifest;
+use spatialbench_cli::generation_params::GenerationParams;
+use spatialbench_cli::output_dir::{prepare_output_dir, ExistingOutputs};
+use spatialbench_cli::output_lock::{config_hash, LockHolder, LockMode, OutputLock};
+use spatialbench_cli::retry::RetryPolicy;
+use spatialbench_cli::upload::Uploader;
+use spatialbench_cli::{avro, interrupt, load_scripts, otlp, readers, zone};
+use std::collections::BTreeMap;
 use std::fmt::Display;
-use std::fs::{self, File};
+use std::fs::File;
 use std::io::{self, BufWriter, Stdout, Write};
+use std::ops::Range;
 use std::path::PathBuf;
 use std::str::FromStr;
 use std::time::Instant;
+use tracing::{info_span, Instrument};
+use url::Url;
 
 #[derive(Parser)]
 #[command(name = "spatialbench")]
 #[command(version)]
 #[command(about = "SpatialBench Data Generator", long_about = None)]
+#[command(group(ArgGroup::new("simplification").multiple(true)))]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Scale factor to create
-    #[arg(short, long, default_value_t = 1.)]
+    ///
+    /// Must be a finite number above 0. The zone table has no scale factor
+    /// below 1 and is generated at 1 with a warning, unless
+    /// `--allow-fractional-scale`. Scale factors above `--max-scale-factor`
+    /// need `--yes-i-know`.
+    #[arg(short, long, default_value_t = 1., value_parser = parse_scale_factor)]
     scale_factor: f64,
 
+    /// Sanity ceiling of `--scale-factor`, guarding against a typo such as
+    /// 1e9 starting a run that can never finish
+    #[arg(long, default_value_t = zone::DEFAULT_MAX_SCALE_FACTOR, value_parser = parse_scale_factor)]
+    max_scale_factor: f64,
+
+    /// Generate a `--scale-factor` above `--max-scale-factor`
+    #[arg(long, default_value_t = false)]
+    yes_i_know: bool,
+
+    /// Generate the zone table at a `--scale-factor` below 1 as a sample of
+    /// that fraction of its zones at scale factor 1
+    ///
+    /// The sample is picked by `id` hash as for `--rows`, so every zone of a
+    /// smaller fraction is also in a larger one. `--rows zone=COUNT` takes
+    /// precedence.
+    #[arg(long, default_value_t = false)]
+    allow_fractional_scale: bool,
+
+    /// Rows to generate for a table instead of the count derived from the
+    /// scale factor, as `TABLE=COUNT`, e.g. `zone=250000` or `trip=10M`
+    ///
+    /// May be repeated or comma separated. Synthetic tables get exactly
+    /// COUNT rows; keys referring to other tables still range over their
+    /// scale factor sizes. The zone table samples COUNT of the source rows
+    /// the scale factor selects, by a hash of their id, so the same rows
+    /// are picked in every run.
+    #[arg(long, value_delimiter = ',', value_parser = parse_table_rows)]
+    rows: Vec<(Table, u64)>,
+
+    /// How `--rows zone=COUNT` picks the sampled zones
+    ///
+    /// `uniform` takes the zones with the smallest hash of their GERS id.
+    /// `stratified-country` gives every country at least
+    /// `--min-per-country` zones, when it has that many, and shares the
+    /// rest of the sample in proportion to the zones of each country,
+    /// ranking the zones of a country by a hash of `--seed` and their GERS
+    /// id. Either way the same seed and scale factor always sample the
+    /// same GERS ids, whatever `--parts` or the column options.
+    #[arg(long, value_enum, default_value_t = zone::Sampling::Uniform)]
+    sampling: zone::Sampling,
+
+    /// Zones every country keeps with `--sampling stratified-country`
+    #[arg(long, default_value_t = 1)]
+    min_per_country: u64,
+
+    /// Keep only the first N zones in key order, for quick runs during
+    /// development
+    ///
+    /// The zones selected for the scale factor, or sampled by `--rows`, are
+    /// ordered by GERS id, numbered, and cut to the first N before being
+    /// split into `--parts`, so the parts together hold exactly N rows. The
+    /// footers (`spatialbench.truncated=true`) and the manifest mark the
+    /// files as truncated, and `verify` warns about them.
+    #[arg(long, value_name = "N")]
+    limit: Option<u64>,
+
     /// Output directory for generated files (default: current directory)
     #[arg(short, long, default_value = ".")]
     output_dir: PathBuf,
@@ -74,8 +154,30 @@ struct Cli {
     #[arg(long = "config")]
     config: Option<PathBuf>,
 
+    /// How the trip pickup points are spread: `clustered` around city
+    /// hotspots, as the trips are by default, `uniform` within the continent
+    /// bounding boxes, or `population-weighted` around many towns with
+    /// heavy-tailed weights
+    ///
+    /// The points depend only on the trip key, not on `--parts`. The mode is
+    /// recorded as `spatialbench.point_distribution` in the trip Parquet
+    /// footers. Can't be combined with a `trip` section in `--config`.
+    #[arg(
+        long,
+        value_parser = clap::builder::PossibleValuesParser::new(PointDistribution::NAMES)
+            .map(|name| name.parse::<PointDistribution>().unwrap())
+    )]
+    point_distribution: Option<PointDistribution>,
+
+    /// Scale the spread of the `--point-distribution` hotspots, e.g. `2` for
+    /// clusters twice as wide. Uniform points ignore it.
+    #[arg(long, default_value_t = 1.0, requires = "point_distribution")]
+    point_dispersion: f64,
+
     /// Number of part(itions) to generate. If not specified creates a single file per table
-    #[arg(short, long)]
+    ///
+    /// Must be at least 1; more than 100000 parts are generated with a warning.
+    #[arg(short, long, value_parser = parse_parts)]
     parts: Option<i32>,
 
     /// Which part(ition) to generate (1-based). If not specified, generates all parts
@@ -87,20 +189,39 @@ struct Cli {
     #[arg(long, conflicts_with_all = ["parts", "part"])]
     mb_per_file: Option<f32>,
 
-    /// Output format: tbl, csv, parquet
+    /// Only generate the trips picked up in this window of dates, e.g.
+    /// `1994-01-01..1994-01-08` (the end date is exclusive)
+    ///
+    /// Pickup dates are then assigned in key order instead of at random, so
+    /// the trips of a window are a contiguous range of keys, computed up
+    /// front and generated without visiting the other trips. The files of
+    /// adjacent windows together form the trip table of a window spanning
+    /// them all, with the same keys. Trip files are written to
+    /// `trip/date=<start>/`. Generated pickup dates range from 1992-01-01 to
+    /// 1998-12-31.
+    #[arg(long, value_parser = parse_time_range)]
+    time_range: Option<Range<i32>>,
+
+    /// Output format: tbl, csv, parquet, orc (zone table only), avro
     #[arg(short, long, default_value = "parquet")]
     format: OutputFormat,
 
     /// The number of threads for parallel generation, defaults to the number of CPUs
-    #[arg(short, long, default_value_t = num_cpus::get())]
+    ///
+    /// Also the number of threads the zone geometry rewrites (WKB and ring
+    /// order normalization) run on.
+    #[arg(short, long, alias = "threads", default_value_t = num_cpus::get())]
     num_threads: usize,
 
     /// Parquet block compression format.
     ///
-    /// Supported values: UNCOMPRESSED, ZSTD(N), SNAPPY, GZIP, LZO, BROTLI, LZ4
+    /// Supported values: UNCOMPRESSED, ZSTD(N), SNAPPY, GZIP(N), BROTLI(N),
+    /// LZ4, LZ4_RAW
     ///
     /// Note to use zstd you must supply the "compression" level (1-22)
     /// as a number in parentheses, e.g. `ZSTD(1)` for level 1 compression.
+    /// GZIP takes levels 0-9 and BROTLI 0-11. Codecs not compiled into this
+    /// build, such as LZO, are rejected before generating.
     ///
     /// Using `ZSTD` results in the best compression, but is about 2x slower than
     /// UNCOMPRESSED. For example, for the lineitem table at SF=10
@@ -108,9 +229,89 @@ struct Cli {
     ///   ZSTD(1):      1.9G  (0.52 GB/sec)
     ///   SNAPPY:       2.4G  (0.75 GB/sec)
     ///   UNCOMPRESSED: 3.8G  (1.41 GB/sec)
-    #[arg(short = 'c', long, default_value = "SNAPPY")]
+    #[arg(short = 'c', long, default_value = "SNAPPY", value_parser = parse_compression)]
     parquet_compression: Compression,
 
+    /// Data page size limit in bytes for zone Parquet files
+    ///
+    /// Smaller pages let readers skip more of large geometry columns at the
+    /// cost of more page headers. Uses the Parquet default (1MB) when unset.
+    #[arg(long)]
+    parquet_page_size_bytes: Option<usize>,
+
+    /// Dictionary page size limit in bytes for zone Parquet files
+    ///
+    /// Columns whose dictionary outgrows this fall back to plain encoding.
+    /// Uses the Parquet default (1MB) when unset.
+    #[arg(long)]
+    parquet_dictionary_page_size_bytes: Option<usize>,
+
+    /// Keep the footers of zone Parquet files small for readers fetching
+    /// them over HTTP
+    ///
+    /// Leaves out the `z_boundary` statistics, cuts string statistics to 16
+    /// bytes and drops the embedded Arrow schema. The footer size of every
+    /// file is logged and recorded in the manifest either way.
+    #[arg(long, default_value_t = false)]
+    small_footer: bool,
+
+    /// Number of zone rows passed to the Parquet writer per call
+    ///
+    /// Lower values bound the memory buffered per write; by default whole
+    /// record batches are written at once.
+    #[arg(long)]
+    write_batch_size: Option<usize>,
+
+    /// Maximum rows per zone Parquet file, e.g. 2_000_000
+    ///
+    /// A part with more rows is written to several files, `zone.3-0.parquet`,
+    /// `zone.3-1.parquet`, ... for part 3, each listed in the manifest with
+    /// its index and row offset within the part. The rows of every part are
+    /// unchanged.
+    #[arg(long, value_parser = parse_row_count)]
+    max_rows_per_file: Option<u64>,
+
+    /// ORC compression of zone files written with `--format orc`
+    #[arg(long, value_enum, default_value_t = zone::OrcCompression::Zlib)]
+    orc_compression: zone::OrcCompression,
+
+    /// Target stripe size in bytes of zone ORC files
+    ///
+    /// The rows per stripe are derived from it as the rows per row group
+    /// are from `--parquet-row-group-bytes`.
+    #[arg(long, default_value_t = zone::DEFAULT_ORC_STRIPE_BYTES)]
+    orc_stripe_bytes: i64,
+
+    /// Block compression of files written with `--format avro`
+    #[arg(long, value_enum, default_value_t = AvroCodec::Deflate)]
+    avro_codec: AvroCodec,
+
+    /// Write the Avro schema of every generated table to `<table>.avsc` in
+    /// the output directory, for registering with a schema registry.
+    /// Requires `--format avro`
+    #[arg(long, default_value_t = false)]
+    emit_avro_schema: bool,
+
+    /// Train a ZSTD dictionary on the zone values and report its gain
+    ///
+    /// Parquet column chunks can't be compressed with an external
+    /// dictionary, so after training generation stops with the estimated
+    /// compression ratio change instead of writing files. Requires
+    /// `-c ZSTD(N)`.
+    #[arg(long, default_value_t = false)]
+    parquet_zstd_train_dict: bool,
+
+    /// Comma separated zone columns to write with dictionary encoding
+    ///
+    /// By default every column is dictionary encoded except `z_zonekey`,
+    /// `z_gersid` and `z_boundary`, whose values are mostly unique.
+    #[arg(long)]
+    dictionary_columns: Option<String>,
+
+    /// Comma separated zone columns to write without dictionary encoding
+    #[arg(long)]
+    no_dictionary_columns: Option<String>,
+
     /// Verbose output
     ///
     /// When specified, sets the log level to `info` and ignores the `RUST_LOG`
@@ -118,10 +319,52 @@ struct Cli {
     #[arg(short, long, default_value_t = false)]
     verbose: bool,
 
+    /// Send trace spans of the run to the OTLP/HTTP endpoint of this
+    /// OpenTelemetry collector, e.g. `http://collector:4318`
+    ///
+    /// The zone pipeline stages (scan, count, collect, transform, partition
+    /// and the write of every part) are exported once the run is done, as
+    /// OTLP over HTTP with a JSON body to `/v1/traces` under the endpoint.
+    /// OTLP over gRPC is not supported, and an endpoint on its port 4317 is
+    /// rejected.
+    #[arg(long)]
+    otlp_endpoint: Option<Url>,
+
     /// Write the output to stdout instead of a file.
     #[arg(long, default_value_t = false)]
     stdout: bool,
 
+    /// Write byte-identical zone files when run twice with the same inputs
+    /// and flags
+    ///
+    /// Sorts the source by `id`, pins the Parquet writer settings and replaces the Spark layout job uuid with
+    /// the nil uuid. Remaining sources of differences are the input data
+    /// itself (a changed source file or URL), the spatialbench-cli, parquet
+    /// and DataFusion versions, the input paths recorded in the manifest and
+    /// `z_source`, and file system timestamps. The other tables are always
+    /// generated deterministically. Leaves the generation time out of the
+    /// `--embed-params` footers of all tables.
+    #[arg(long, default_value_t = false)]
+    deterministic: bool,
+
+    /// Record the generation parameters in the footer of every file
+    ///
+    /// Writes the scale factor, the command line arguments, the zone input,
+    /// the spatialbench-cli version and the generation time as JSON under
+    /// the `spatialbench.generation_params` key of every Parquet file, and
+    /// of the zone ORC and Avro files. On by default.
+    #[arg(long, default_value_t = true, action = clap::ArgAction::Set)]
+    embed_params: bool,
+
+    /// Write scripts loading the generated tables into these engines
+    /// (comma separated)
+    ///
+    /// The scripts are written into the output directory and list the
+    /// columns and files actually written, converting geometry columns from
+    /// WKB. Requires Parquet output.
+    #[arg(long, value_enum, value_delimiter = ',')]
+    emit_load_scripts: Vec<load_scripts::LoadScript>,
+
     /// Target size in row group bytes in Parquet files
     ///
     /// Row groups are the typical unit of parallel processing and compression
@@ -134,8 +377,1216 @@ struct Cli {
     /// groups under this limit.
     ///
     /// Typical values range from 10MB to 100MB.
-    #[arg(long, default_value_t = DEFAULT_PARQUET_ROW_GROUP_BYTES)]
+    #[arg(long, default_value_t = DEFAULT_PARQUET_ROW_GROUP_BYTES, value_parser = parse_row_group_bytes)]
     parquet_row_group_bytes: i64,
+
+    /// Normalize zone `z_country` values to uppercase ISO 3166-1 alpha-2 codes
+    ///
+    /// Full country names, alpha-3 codes and inconsistent casing are mapped
+    /// with a built-in lookup. Unrecognized values are left untouched and
+    /// logged as warnings.
+    #[arg(long, default_value_t = false)]
+    normalize_country: bool,
+
+    /// Rewrite zone `z_region` values to ISO 3166-2 codes (`iso`), English
+    /// names (`name`), or keep the source values (`raw`)
+    ///
+    /// Codes, codes without the country prefix, English and local names are
+    /// matched with a built-in table of first level subdivisions, using the
+    /// row's `z_country`. It runs after `--region-map`. Values missing from
+    /// the table are left untouched; the run logs how many rows were
+    /// normalized and passed through.
+    #[arg(long, value_enum, default_value_t = zone::RegionNormalization::Raw)]
+    normalize_region: zone::RegionNormalization,
+
+    /// Reorder zone polygon rings to a winding convention
+    ///
+    /// Exterior rings become counterclockwise and holes clockwise. Rings
+    /// without an orientation are left as-is with a warning.
+    #[arg(long, value_enum)]
+    normalize_winding: Option<zone::WindingOrder>,
+
+    /// Simplify zone geometries with `--simplify-algorithm` at this
+    /// tolerance
+    ///
+    /// For `dp` the tolerance is a distance in degrees: every vertex farther
+    /// than it from the simplified line is kept. For `vw` it is an area in
+    /// square degrees: vertices whose triangle with their neighbours is
+    /// smaller are dropped, so `vw` tolerances are far smaller than `dp`
+    /// ones for the same detail. Rings keep at least four points, and the
+    /// simplified geometries are written as 2D WKB.
+    #[arg(long, group = "simplification")]
+    simplify_tolerance: Option<f64>,
+
+    /// Also write zone companions simplified at each of these tolerances
+    ///
+    /// Every tolerance above 0 gets a `zone_simplified_<tolerance>`
+    /// directory in the output directory, e.g. `zone_simplified_0_001` for
+    /// 0.001, with the same parts, rows and keys and its own manifest. The
+    /// levels are simplified with `--simplify-algorithm` from the
+    /// full-resolution rows in the same run, so the source is read and
+    /// keyed once. A geometry that collapses at a tolerance keeps that of the
+    /// level below, and the number of such fallbacks is logged. With
+    /// `--with-bbox-covering` each level gets its own bounds and GeoParquet
+    /// bbox; the other columns keep their full-resolution values. 0 stands
+    /// for the full-resolution files themselves.
+    #[arg(
+        long,
+        value_delimiter = ',',
+        value_name = "TOLERANCES",
+        group = "simplification"
+    )]
+    simplification_levels: Vec<f64>,
+
+    /// Algorithm `--simplify-tolerance` and `--simplification-levels`
+    /// simplify with
+    ///
+    /// `dp` (Douglas–Peucker) keeps the vertices that stray farthest from
+    /// the line. `vw` (Visvalingam–Whyatt) drops the vertices enclosing the
+    /// least area first, which keeps broad shapes and drops narrow spikes,
+    /// and often looks better at the same vertex count.
+    #[arg(long, value_enum, default_value_t = zone::SimplifyAlgorithm::DouglasPeucker, requires = "simplification")]
+    simplify_algorithm: zone::SimplifyAlgorithm,
+
+    /// Write zone `z_boundary` values exactly as read from the source
+    ///
+    /// By default every geometry is rewritten to little-endian ISO WKB
+    /// without an SRID, converting EWKB and big-endian input.
+    #[arg(long, default_value_t = false)]
+    no_wkb_normalize: bool,
+
+    /// Keep Z and M ordinates when normalizing zone WKB instead of
+    /// dropping them
+    #[arg(long, default_value_t = false, conflicts_with = "no_wkb_normalize")]
+    keep_zm: bool,
+
+    /// What to do with zone geometries that are not valid WKB
+    ///
+    /// Applies while normalizing WKB. `skip` drops the row, so the zone
+    /// table has fewer rows than the source.
+    #[arg(long, value_enum, default_value_t = zone::OnBadGeometry::Fail)]
+    on_bad_geometry: zone::OnBadGeometry,
+
+    /// Drop zones whose geometry is not valid WKB and continue, the same as
+    /// `--on-bad-geometry skip`
+    ///
+    /// The dropped rows are counted in a warning, and each one is logged
+    /// with its `z_gersid` at debug level.
+    #[arg(long, default_value_t = false, conflicts_with = "on_bad_geometry")]
+    skip_bad_geometry: bool,
+
+    /// Keep only zones whose geometry is one of these types (comma
+    /// separated), e.g. `polygon,multipolygon`
+    ///
+    /// Source rows of other types, and null or malformed geometries, are
+    /// dropped before the zone keys are numbered, so the keys stay
+    /// contiguous. The rows dropped per type are logged.
+    #[arg(long, value_enum, value_delimiter = ',')]
+    keep_geometry_type: Vec<zone::GeometryType>,
+
+    /// Add `z_parent_zonekey` and `z_admin_level` columns to the zone table
+    ///
+    /// The parent is the region zone sharing the row's `z_region`, or else
+    /// the country zone sharing its `z_country`, when that zone is part of
+    /// the generated table. The admin level is derived from `z_subtype`.
+    #[arg(long, default_value_t = false)]
+    include_hierarchy: bool,
+
+    /// Number `z_zonekey` from 1 within every value of this column, e.g.
+    /// per country, instead of over the whole table
+    ///
+    /// Keys follow `id` order within each value and the rows are written
+    /// ordered by the column, then the key. The keys are numbered over the
+    /// whole table rather than from the row offset of a part, so every
+    /// `--part` run reads the whole table, as with `--include-hierarchy`,
+    /// and a part can hold keys of several values. `z_zonekey` is then not
+    /// unique on its own; see `--composite-key`.
+    #[arg(long, value_enum, value_name = "COLUMN")]
+    key_partition_by: Option<zone::KeyPartition>,
+
+    /// With `--key-partition-by`, add a `z_compositekey` column joining the
+    /// column value and the key, e.g. `US-42`, unique over the table
+    #[arg(long, default_value_t = false)]
+    composite_key: bool,
+
+    /// Add a `z_bbox` column with the bounds of every zone and declare it
+    /// as the GeoParquet 1.1 bbox covering of `z_boundary`
+    ///
+    /// The Parquet statistics of `z_bbox` then hold the bbox of every row
+    /// group, so readers filtering by bbox can skip row groups outside the
+    /// window.
+    #[arg(long, default_value_t = false)]
+    with_bbox_covering: bool,
+
+    /// Columns the `--with-bbox-covering` bounds are written in
+    ///
+    /// `struct` writes the `z_bbox` struct declared as the GeoParquet
+    /// covering. `flat` writes the `z_xmin`, `z_ymin`, `z_xmax` and
+    /// `z_ymax` double columns for engines that handle flat columns better
+    /// than structs; they keep the row group statistics, but GeoParquet
+    /// only declares struct coverings.
+    #[arg(long, value_enum, default_value_t = zone::BboxLayout::Struct, requires = "with_bbox_covering")]
+    bbox_layout: zone::BboxLayout,
+
+    /// Expand every `--with-bbox-covering` bbox by this distance on all
+    /// sides, in degrees
+    ///
+    /// Slightly inflated boxes keep tile-index joins from missing zones
+    /// that touch a tile edge. The file bbox in the `geo` metadata covers
+    /// the inflated boxes.
+    #[arg(
+        long,
+        default_value_t = 0.0,
+        requires = "with_bbox_covering",
+        allow_negative_numbers = true
+    )]
+    bbox_inflate: f64,
+
+    /// Add a `z_area` column with the area of every zone
+    ///
+    /// The area is measured after reprojecting the boundary to
+    /// `--area-crs`; the written `z_boundary` stays in CRS84.
+    #[arg(long, default_value_t = false)]
+    with_area: bool,
+
+    /// CRS `z_area` is measured in
+    ///
+    /// The default equal-area projection gives square meters. `OGC:CRS84`
+    /// takes longitude and latitude as planar coordinates and gives square
+    /// degrees, whose size in meters depends on the latitude.
+    #[arg(
+        long,
+        value_enum,
+        ignore_case = true,
+        default_value = "EPSG:6933",
+        requires = "with_area"
+    )]
+    area_crs: zone::AreaCrs,
+
+    /// Add `z_lon` and `z_lat` double columns with the coordinates of Point
+    /// boundaries, null for every other geometry type
+    ///
+    /// `z_boundary` is still written; the plain columns let CSV output load
+    /// into databases without spatial types.
+    #[arg(long, default_value_t = false)]
+    point_as_lonlat: bool,
+
+    /// Add a `z_source` column naming the Overture release or input paths
+    /// the zones were read from
+    ///
+    /// The value is the same as the `source` recorded in the zone manifest.
+    #[arg(long, default_value_t = false)]
+    with_provenance: bool,
+
+    /// Write an `ATTRIBUTION.txt` notice of the zone source into the output
+    /// directory
+    ///
+    /// The notice names the Overture release or input paths, the license
+    /// and the attribution the Overture data requires. Its SHA-256 is
+    /// recorded in the zone manifest and `dataset.json`, and `verify` fails
+    /// when the notice is missing or changed.
+    #[arg(long, default_value_t = false)]
+    include_attribution: bool,
+
+    /// Add a constant `z_attribution` column such as `Overture Maps
+    /// Foundation 2025-08-20.1`
+    ///
+    /// The release is left out for inputs read from local paths or URLs.
+    #[arg(long, default_value_t = false, requires = "include_attribution")]
+    attribution_column: bool,
+
+    /// Add the source `names.common` map of language to name as a JSON
+    /// object string column `z_name_common`
+    #[arg(long, default_value_t = false)]
+    with_names_common: bool,
+
+    /// Comma separated languages added as `z_name_<lang>` columns (e.g. "en,fr")
+    ///
+    /// Values come from `names.common`; zones without a name in the language
+    /// get an empty string.
+    #[arg(long)]
+    names_languages: Option<String>,
+
+    /// Replace `z_name`, the `z_name_<lang>` columns and the values of
+    /// `z_name_common` with synthetic names
+    ///
+    /// Each pseudonym is derived from `--seed`, the zone's `z_gersid` and
+    /// the language, so re-generating with the same seed gives the same
+    /// names. Pseudonyms roughly keep the length of the name they replace.
+    /// Geometries and all other columns are unchanged.
+    #[arg(long, default_value_t = false)]
+    pseudonymize_names: bool,
+
+    /// Shape of the `--pseudonymize-names` names
+    #[arg(long, value_enum, default_value_t = zone::PseudonymStyle::Hex, requires = "pseudonymize_names")]
+    pseudonym_style: zone::PseudonymStyle,
+
+    /// Comma separated zone columns to write, leaving out the others
+    ///
+    /// Selects among the columns the other options produce. The columns
+    /// are written in the order of `--column-order`.
+    #[arg(long)]
+    columns: Option<String>,
+
+    /// Order of the `--columns` in the written files
+    ///
+    /// `schema` keeps the order of the full zone schema, so loaders and
+    /// load scripts expecting that order work on any selection;
+    /// `as-specified` uses the order given to `--columns`.
+    #[arg(long, value_enum, default_value_t = zone::ColumnOrder::Schema)]
+    column_order: zone::ColumnOrder,
+
+    /// Comma separated numeric columns appended to the zone table
+    ///
+    /// Each entry is `zipf:NAME:EXPONENT[:RANKS]`, `uniform:NAME:LOW..HIGH`
+    /// or `normal:NAME:MEAN:STDDEV`. Values derive from `--seed` and the
+    /// row's `z_gersid`, so they are the same for any `--parts`.
+    #[arg(long)]
+    synthetic_columns: Option<String>,
+
+    /// Comma separated helper columns describing each zone's geometry
+    ///
+    /// `vertexcount` adds `z_vertexcount`, the vertices of every ring and
+    /// part, and `geomtype` adds `z_geomtype`, the geometry type such as
+    /// `MultiPolygon`. Both are read from the WKB headers and are null for
+    /// null geometries.
+    #[arg(long, value_enum, value_delimiter = ',')]
+    extra_columns: Vec<zone::ExtraColumn>,
+
+    /// Encoding of the zone `z_boundary` column
+    ///
+    /// `geojson` writes each geometry as a GeoJSON geometry object in a
+    /// UTF-8 string column, for Parquet and CSV readers without WKB
+    /// support. Can't be combined with `--with-bbox-covering`.
+    #[arg(long, value_enum, default_value_t = zone::GeometryEncoding::Wkb)]
+    geometry_encoding: zone::GeometryEncoding,
+
+    /// Leave the `z_boundary` column out of the zone table
+    ///
+    /// Writes a compact table of the zone attributes for benchmarks of
+    /// relational operators. The source geometries are not read unless
+    /// `--keep-geometry-type` filters on them, and no GeoParquet metadata is
+    /// written. Can't be combined with the options that read the
+    /// geometries, such as `--with-bbox-covering` or `--balance vertices`.
+    #[arg(long, default_value_t = false)]
+    no_geometry: bool,
+
+    /// Seed for generated values that are not fixed by the benchmark, such
+    /// as `--synthetic-columns`
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+
+    /// CSV file of `from,to` pairs used to rename zone `z_region` values
+    ///
+    /// Matching is exact and case-sensitive unless `--region-map-ci` is given.
+    /// Regions not listed in the file are left unchanged.
+    #[arg(long)]
+    region_map: Option<PathBuf>,
+
+    /// Match `--region-map` entries case-insensitively
+    #[arg(long, default_value_t = false, requires = "region_map")]
+    region_map_ci: bool,
+
+    /// Directory layout and file naming of the zone table
+    ///
+    /// `spark` writes `zone/part-NNNNN-<uuid>.<codec>.parquet` files and a
+    /// `zone/_SUCCESS` marker once all parts requested by this invocation
+    /// are written. When each part is generated by a separate `--part`
+    /// invocation, run the `finalize` subcommand afterwards to write the
+    /// marker.
+    #[arg(long, value_enum, default_value_t = zone::ZoneLayout::Spatialbench)]
+    layout: zone::ZoneLayout,
+
+    /// File name of the zone part files, written in the `zone` directory
+    ///
+    /// `{table}`, `{part}` and `{parts}` are replaced by the table name, the
+    /// part number and the part count, and `{part:04}` pads the number with
+    /// zeros, so `{table}-part-{part:04}-of-{parts:04}.parquet` names part 1
+    /// of 16 `zone-part-0001-of-0016.parquet`. `{sf}` is the scale factor,
+    /// see `--name-with-sf`. Must contain `{part}` when generating more than
+    /// one part.
+    #[arg(long)]
+    filename_template: Option<zone::FilenameTemplate>,
+
+    /// Embed the scale factor in the zone part file names
+    ///
+    /// Writes `zone/zone.sf10.1.parquet` instead of `zone/zone.1.parquet`,
+    /// with `_` for the decimal point of fractional scale factors, e.g.
+    /// `zone.sf1_5.1.parquet`. `verify`, `diff`, `profile` and `materialize`
+    /// refuse directories whose names embed different scale factors unless
+    /// given `--force`. Same as the `{sf}` placeholder of
+    /// `--filename-template`.
+    #[arg(long, default_value_t = false, conflicts_with = "filename_template")]
+    name_with_sf: bool,
+
+    /// What zone `--parts` split evenly
+    ///
+    /// `vertices` gives each part roughly the same number of geometry
+    /// vertices instead of rows. Row order and keys are unchanged; only the
+    /// split points move. The boundaries are recorded in the zone manifest,
+    /// which single-part workers can read with `--plan-file`; without it
+    /// each worker reads the whole table to compute them.
+    #[arg(long, value_enum, default_value_t = zone::Balance::Rows)]
+    balance: zone::Balance,
+
+    /// How zones are assigned to the zone `--parts`
+    ///
+    /// `lat-bands` and `lon-bands` split the globe into one equal-width band
+    /// per part and assign every zone to the band containing its centroid.
+    /// `equal-area` splits it into latitude bands of equal surface area,
+    /// narrower towards the poles. `kdtree` recursively bisects the zone
+    /// centroids at the median, alternating between longitude and latitude,
+    /// into contiguous boxes of about equal row counts.
+    /// The band or box of every part is recorded in the `extents` of the
+    /// zone manifest. Zone boundaries may reach outside it.
+    #[arg(long, value_enum, default_value_t = zone::PartitionScheme::Rows)]
+    partition_strategy: zone::PartitionScheme,
+
+    /// Compute zone centroids for `--partition-strategy` bands with zones
+    /// crossing the 180° meridian split at the dateline
+    ///
+    /// Zone boundaries are longitude and latitude, so this is on by
+    /// default. Without it such a zone is treated as spanning the globe and
+    /// its centroid lands near the prime meridian.
+    #[arg(long, default_value_t = true, action = clap::ArgAction::Set)]
+    antimeridian_aware: bool,
+
+    /// Remove the zone parts already written by this run when a later part
+    /// fails
+    ///
+    /// Without it the completed parts and the manifest entries are kept, but
+    /// no `_SUCCESS` marker is written. Interrupted runs are never cleaned up
+    /// so they can be resumed.
+    #[arg(long, default_value_t = false)]
+    cleanup_on_failure: bool,
+
+    /// Also write the rows of all zone parts into a single `zone.parquet`
+    ///
+    /// The file is written from the rows already held for the parts, in part
+    /// order and with the same writer settings, and is recorded in the
+    /// manifest as their merged copy. Can't be combined with `--part`.
+    #[arg(long, default_value_t = false)]
+    also_merge: bool,
+
+    /// Spill the collected zone table to a temporary Parquet file before
+    /// writing the parts
+    ///
+    /// Once the table is transformed and partitioned it is written to a
+    /// hidden file in the output directory and freed, and the rows of each
+    /// part are read back as it is written, so writing holds one part in
+    /// memory rather than the whole table. The file is removed when the run
+    /// ends. Only applies when all parts are generated in one run.
+    #[arg(long, default_value_t = false)]
+    spill_collect: bool,
+
+    /// Check that `z_zonekey` ascends through every zone part before writing
+    /// it
+    ///
+    /// Zone rows are written in key order, so the `z_zonekey` statistics of
+    /// the row groups are disjoint ranges that key range queries can prune
+    /// by. The band partition strategies keep key order within each part.
+    #[arg(long, default_value_t = false)]
+    verify_key_order: bool,
+
+    /// Fail unless `z_zonekey` is `1..=count` without gaps or duplicates
+    ///
+    /// Checked after the transforms, over the whole table when every part
+    /// is generated and over the key range of the part with `--part`.
+    #[arg(long, default_value_t = false)]
+    assert_contiguous_keys: bool,
+
+    /// Comma separated byte limits of zone string columns, as
+    /// `z_name=255,z_region=100`
+    ///
+    /// Limits count UTF-8 bytes, so the values also fit a `VARCHAR(n)`
+    /// counting characters. Every part writes the ids of the values it cut
+    /// to `zone.<part>.truncated.csv` in the output directory, and the
+    /// limits are recorded in the Parquet schema, where the PostGIS load
+    /// script declares the columns `varchar(n)`.
+    #[arg(long)]
+    max_string_length: Option<String>,
+
+    /// What to do with a zone string over its `--max-string-length`
+    ///
+    /// `truncate` cuts it at the last character boundary within the limit;
+    /// `fail` fails the part.
+    #[arg(long, value_enum, default_value_t = zone::OnOverlong::Truncate)]
+    on_overlong: zone::OnOverlong,
+
+    /// Bytes buffered in front of every zone file written, e.g. 8388608
+    ///
+    /// Larger buffers hand fast disks fewer, larger writes. By default
+    /// Parquet, ORC and Avro files are written as their encoders flush and
+    /// CSV/TBL rows through an 8KiB buffer.
+    #[arg(long)]
+    write_buffer_bytes: Option<usize>,
+
+    /// When zone files are synced to disk
+    ///
+    /// `per-part` syncs every file before it is moved into place, then its
+    /// directory entry, and the `_SUCCESS` marker of the Spark layout;
+    /// `per-rowgroup` also syncs after every Parquet row group, ORC stripe
+    /// or CSV/TBL batch, spreading the writeback on network filesystems.
+    #[arg(long, value_enum, default_value_t = zone::Fsync::Never)]
+    fsync: zone::Fsync,
+
+    /// Upload the output directory to this object store URL once the run
+    /// is done, e.g. `s3://bucket/spatialbench/sf10`
+    ///
+    /// Files larger than 16MiB are uploaded in parts. Manifests and
+    /// `_SUCCESS` markers are uploaded after the data files. S3 credentials
+    /// and region are taken from the `AWS_*` environment variables.
+    #[arg(long, conflicts_with = "stdout")]
+    upload_to: Option<String>,
+
+    /// Retries of an upload request failing with a server error or
+    /// throttling; authentication and missing bucket errors fail at once
+    #[arg(long, default_value_t = 3, requires = "upload_to")]
+    write_retries: u32,
+
+    /// Milliseconds before the first retry of an upload request, doubled
+    /// for every further retry
+    #[arg(long, default_value_t = 100, requires = "upload_to")]
+    write_retry_base_ms: u64,
+
+    /// Fail unless the zone files would have this numbered schema version
+    ///
+    /// The version of the columns, types and nullability the default
+    /// options write is recorded as `spatialbench.schema_version` in every
+    /// zone file footer and in the manifest. Options adding columns, such
+    /// as `--with-bbox-covering` or `--synthetic-columns`, record `custom`
+    /// instead. Pipelines pinned to a schema can pass its version to catch
+    /// a crate upgrade that changes it.
+    #[arg(long, value_name = "N")]
+    require_schema_version: Option<u32>,
+
+    /// Print the plans of the zone generation query instead of generating
+    ///
+    /// Prints the optimized logical plan and the physical plan of the source
+    /// scan plus the transforms, then the projection and predicate every
+    /// Parquet source scan received. Filters evaluated above a scan that
+    /// received no predicate are logged as warnings. Nothing is written and
+    /// no other table is generated.
+    #[arg(long, default_value_t = false)]
+    explain: bool,
+
+    /// Like `--explain`, then run the query over its first ROWS rows and
+    /// print the physical plan with its metrics
+    #[arg(
+        long,
+        value_name = "ROWS",
+        num_args = 0..=1,
+        default_missing_value = "10000",
+        conflicts_with = "explain"
+    )]
+    explain_analyze: Option<usize>,
+
+    /// Print how the zone files of this run would differ from those of the
+    /// manifest MANIFEST, instead of generating
+    ///
+    /// Plans the zone table without writing anything: the options recorded
+    /// in the manifest, the row count of every part and the columns of the
+    /// files. Exits with 0 when nothing differs and with 3 when something
+    /// does.
+    #[arg(
+        long,
+        value_name = "MANIFEST",
+        conflicts_with_all = ["explain", "explain_analyze"]
+    )]
+    diff_against: Option<PathBuf>,
+
+    /// Filtered zone source row count, as printed by the `count` subcommand
+    ///
+    /// Skips counting the source when computing part offsets. If the source
+    /// no longer matches, the row count check after writing fails.
+    #[arg(long)]
+    total_rows: Option<i64>,
+
+    /// JSON plan file carrying `total_rows`, such as the `count` output or a
+    /// zone manifest from an earlier run. Implies `--total-rows`.
+    #[arg(long, conflicts_with = "total_rows")]
+    plan_file: Option<PathBuf>,
+
+    /// What to do with the part being written when interrupted with ctrl-C
+    ///
+    /// Either way no new parts are started, the zone manifest keeps the parts
+    /// completed so far and re-running the same command resumes. Only zone
+    /// parts can be aborted mid-write; files of other tables are finished.
+    #[arg(long, value_enum, default_value_t = interrupt::OnInterrupt::FinishPart)]
+    on_interrupt: interrupt::OnInterrupt,
+
+    /// Comma separated Overture themes unioned into the zone table
+    ///
+    /// Supported themes are `division_area` and `locality`. Each may be given
+    /// as `theme=LOCATION` to read a local path or URL instead of the
+    /// built-in source; `locality` has no built-in source and requires one.
+    #[arg(long, default_value = "division_area")]
+    input_theme: String,
+
+    /// Read the zone source, Overture `division_area`, straight from a
+    /// remote mirror, the same as `--input-theme division_area=URL`
+    ///
+    /// `http://` and `https://` URLs are read anonymously, `s3://` URLs
+    /// with the credentials, region and endpoint of the `AWS_*` environment
+    /// variables (`AWS_SKIP_SIGNATURE=true` for public buckets). Files are
+    /// read with range requests, so only the columns and row groups the
+    /// scan needs are downloaded. A URL ending in `/` reads every Parquet
+    /// file below it, which plain HTTP servers don't support.
+    #[arg(long, value_name = "URL", conflicts_with = "input_theme")]
+    input_url: Option<String>,
+
+    /// Flat column of the zone inputs holding the zone name, read instead
+    /// of the Overture `names.primary`
+    ///
+    /// Without it an input lacking a `names` struct has its names read from
+    /// a top-level `name` column. `--with-names-common` and
+    /// `--names-languages` need the `names.common` map and so a `names`
+    /// struct.
+    #[arg(long, value_name = "COLUMN")]
+    name_column: Option<String>,
+
+    /// Delete the zone outputs of an earlier run with a different scale
+    /// factor, seed or source from the output directory before generating
+    ///
+    /// Without it or `--allow-mixed-outputs` such a directory is refused.
+    /// Only the files listed in the zone manifest are deleted; files of
+    /// other tables are overwritten as they are generated.
+    #[arg(long, default_value_t = false, conflicts_with = "allow_mixed_outputs")]
+    overwrite: bool,
+
+    /// Take over the lock of the output directory from a run that still
+    /// holds it, such as a hung one
+    ///
+    /// Every run writing files locks `<output-dir>/.spatialbench.lock` and
+    /// is refused while another run holds it, unless both are `--part`
+    /// workers with otherwise identical arguments. A crashed run releases
+    /// its lock by itself.
+    #[arg(long, default_value_t = false)]
+    force_unlock: bool,
+
+    /// Write into an output directory holding outputs of an earlier run
+    /// with a different scale factor, seed or source, keeping them
+    #[arg(long, default_value_t = false)]
+    allow_mixed_outputs: bool,
+}
+
+/// Tools that operate on already generated datasets
+#[derive(Subcommand)]
+enum Command {
+    /// Verify zone content hashes, or compare two zone datasets part by part
+    Verify(VerifyArgs),
+
+    /// Print the filtered source row count of a table as JSON
+    Count {
+        /// Table to count (only zone reads a source)
+        #[arg(long, value_parser = TableValueParser, default_value = "zone")]
+        table: Table,
+
+        /// Scale factor whose source filters are applied
+        #[arg(short, long, default_value_t = 1., value_parser = parse_scale_factor)]
+        scale_factor: f64,
+
+        /// Overture themes to count, as for the generate `--input-theme`
+        #[arg(long, default_value = "division_area")]
+        input_theme: String,
+
+        /// Row count overrides, as for the generate `--rows`. The count is
+        /// that of the zone sample.
+        #[arg(long, value_delimiter = ',', value_parser = parse_table_rows)]
+        rows: Vec<(Table, u64)>,
+
+        /// Instead of counting, estimate what the source scan would read
+        /// from the source Parquet footers alone
+        ///
+        /// Prints the files, the row groups and the compressed bytes left
+        /// after pruning row groups by their subtype and is_land
+        /// statistics, with the bytes split by country. The estimate is
+        /// exact to the row group; `--rows` samples from the same reads.
+        #[arg(long, default_value_t = false)]
+        estimate_io: bool,
+
+        /// Sum the row counts in the source Parquet footers instead of
+        /// scanning the source
+        ///
+        /// Works when the subtype and is_land statistics of every row group
+        /// show that all or none of its rows are kept; otherwise the rows
+        /// are counted with a scan. The output names the method used.
+        #[arg(long, default_value_t = false, conflicts_with = "estimate_io")]
+        from_metadata: bool,
+    },
+
+    /// Measure zone generation throughput without writing files
+    ///
+    /// Runs the source scan, the transforms and the partitioning into
+    /// `--parts`, then encodes every part as Parquet into a discarding sink.
+    /// Prints rows/sec, MB/sec of Arrow data, per-stage wall times and the
+    /// peak resident memory as JSON.
+    Bench {
+        /// Table to benchmark (only zone is supported)
+        #[arg(long, value_parser = TableValueParser, default_value = "zone")]
+        table: Table,
+
+        /// Scale factor to generate
+        #[arg(short, long, default_value_t = 1., value_parser = parse_scale_factor)]
+        scale_factor: f64,
+
+        /// Overture themes to read, as for the generate `--input-theme`
+        #[arg(long, default_value = "division_area")]
+        input_theme: String,
+
+        /// Number of parts to slice and encode
+        #[arg(long)]
+        parts: Option<i32>,
+
+        /// Parquet compression used for encoding, as for generate
+        #[arg(short = 'c', long, default_value = "SNAPPY", value_parser = parse_compression)]
+        parquet_compression: Compression,
+
+        /// Times to run the pipeline; the report includes the fastest and
+        /// the median run
+        #[arg(long, default_value_t = 1)]
+        iterations: usize,
+    },
+
+    /// Report rows added, removed or changed between two zone datasets
+    ///
+    /// Rows are matched by `z_gersid`. Exits with an error if the datasets
+    /// differ.
+    Diff {
+        #[arg(value_name = "DIR_A")]
+        left: PathBuf,
+
+        #[arg(value_name = "DIR_B")]
+        right: PathBuf,
+
+        /// Largest coordinate difference at which geometries still count as
+        /// equal
+        #[arg(long, default_value_t = 0.0)]
+        tolerance: f64,
+
+        /// Write every change to this JSON file
+        #[arg(long)]
+        json: Option<PathBuf>,
+
+        /// Process datasets whose zone file names embed different scale
+        /// factors
+        #[arg(long, default_value_t = false)]
+        force: bool,
+    },
+
+    /// Report how the zone statistics changed between two datasets
+    ///
+    /// Prints row count deltas per country and subtype, the counts and
+    /// samples of added and removed `z_gersid` values, and how far the
+    /// bbox of each country moved. Both datasets are streamed, so their
+    /// size is not bounded by memory. Exits with an error if a country or
+    /// subtype count, or the added and removed ids against the old row
+    /// count, differ by more than `--tolerance-pct`.
+    DiffStats {
+        /// Output directory of the dataset compared against
+        #[arg(long)]
+        old: PathBuf,
+
+        /// Output directory of the changed dataset
+        #[arg(long)]
+        new: PathBuf,
+
+        /// Largest relative difference in percent that still succeeds
+        #[arg(long, default_value_t = 0.0)]
+        tolerance_pct: f64,
+
+        /// Number of added and removed ids listed
+        #[arg(long, default_value_t = 10)]
+        samples: usize,
+
+        /// Write the full report to this JSON file
+        #[arg(long)]
+        json: Option<PathBuf>,
+
+        /// Process datasets whose zone file names embed different scale
+        /// factors
+        #[arg(long, default_value_t = false)]
+        force: bool,
+    },
+
+    /// Report distinct counts, top values and geometry vertex counts of a
+    /// zone dataset
+    Profile {
+        /// Output directory of the generated dataset
+        #[arg(long)]
+        data_dir: PathBuf,
+
+        /// Number of most frequent values listed per column
+        #[arg(long, default_value_t = 10)]
+        top: usize,
+
+        /// Write the profile to this JSON file
+        #[arg(long)]
+        profile_json: Option<PathBuf>,
+
+        /// Process datasets whose zone file names embed different scale
+        /// factors
+        #[arg(long, default_value_t = false)]
+        force: bool,
+    },
+
+    /// Report ring orientation and validity problems of the zone
+    /// geometries without changing them
+    ///
+    /// Counts, per part file and overall, counterclockwise and clockwise
+    /// exterior rings, holes wound counterclockwise, self-intersecting,
+    /// unclosed and degenerate rings, and duplicate consecutive points.
+    /// Only `z_boundary` is read, one batch at a time, so existing outputs of
+    /// any size can be checked before choosing `--normalize-winding`.
+    ValidateGeometries {
+        /// Output directory of the generated dataset
+        #[arg(long)]
+        data_dir: PathBuf,
+
+        /// Write the report to this JSON file
+        #[arg(long)]
+        json: Option<PathBuf>,
+
+        /// Process datasets whose zone file names embed different scale
+        /// factors
+        #[arg(long, default_value_t = false)]
+        force: bool,
+    },
+
+    /// Report known reader limitations that apply to generated Parquet files
+    ///
+    /// Findings are derived from the file footers, such as compression
+    /// codecs, logical types and GeoParquet metadata.
+    CheckReaders {
+        /// Output directory of the generated dataset
+        #[arg(long)]
+        data_dir: PathBuf,
+
+        /// Write the findings to this JSON file
+        #[arg(long)]
+        json: Option<PathBuf>,
+    },
+
+    /// Write a spatial SQL query workload over the zone table
+    ///
+    /// Writes point-in-zone, zone-zone intersection and country aggregation
+    /// queries as `<kind>_NN.sql` files. Their points and boxes derive from
+    /// `--seed`, and the zone subtypes they select from the scale factor.
+    Queries {
+        /// Directory the queries are written to
+        #[arg(short, long, default_value = "queries")]
+        output_dir: PathBuf,
+
+        /// Scale factor of the dataset the queries run against
+        #[arg(short, long, default_value_t = 1., value_parser = parse_scale_factor)]
+        scale_factor: f64,
+
+        /// Seed of the query parameters
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+
+        /// Number of queries of every kind
+        #[arg(long, default_value_t = 10)]
+        count: usize,
+    },
+
+    /// Write the zones of a dataset as a PMTiles archive of vector tiles
+    ///
+    /// Tiles have one `zone` layer with `z_zonekey`, `z_name` and
+    /// `z_subtype` as feature attributes; polygons are simplified to the
+    /// resolution of each zoom. Serve the archive with the pmtiles protocol
+    /// to inspect the zones on a map.
+    ExportTiles {
+        /// Output directory of the generated dataset
+        #[arg(long)]
+        data_dir: PathBuf,
+
+        /// PMTiles archive to write
+        #[arg(long, default_value = "zone.pmtiles")]
+        output: PathBuf,
+
+        /// Lowest zoom level exported
+        #[arg(long, default_value_t = zone::DEFAULT_MIN_ZOOM)]
+        min_zoom: u8,
+
+        /// Highest zoom level exported
+        #[arg(long, default_value_t = zone::DEFAULT_MAX_ZOOM)]
+        max_zoom: u8,
+
+        /// Process datasets whose zone file names embed different scale
+        /// factors
+        #[arg(long, default_value_t = false)]
+        force: bool,
+    },
+
+    /// Precompute joins over an already generated dataset
+    #[command(subcommand)]
+    Materialize(MaterializeCommand),
+
+    /// Package a generated dataset into a zstd compressed tar archive
+    ///
+    /// Checks that the zone manifest lists every part, then streams every
+    /// file of the directory into the archive. The archive starts with
+    /// `spatialbench.package.json`, listing the dataset files, and the zone
+    /// manifest.
+    Package {
+        /// Output directory of the generated dataset
+        #[arg(long)]
+        data_dir: PathBuf,
+
+        /// Archive to write, e.g. `spatialbench-sf10.tar.zst`
+        #[arg(long)]
+        out: PathBuf,
+
+        /// Re-hash the zone files and refuse to package any that no longer
+        /// match the manifest
+        #[arg(long, default_value_t = false)]
+        check_hashes: bool,
+
+        /// Process datasets whose zone file names embed different scale
+        /// factors
+        #[arg(long, default_value_t = false)]
+        force: bool,
+    },
+
+    /// Extract an archive written by `package` and verify its content hashes
+    Unpack {
+        /// Archive written by `package`
+        #[arg(long)]
+        archive: PathBuf,
+
+        /// Directory to extract into; existing files are never overwritten
+        #[arg(long)]
+        output_dir: PathBuf,
+    },
+
+    /// Re-cut a zone dataset into another number of parts without reading
+    /// the source
+    ///
+    /// The parts must have been split by row count. The existing files are
+    /// checked against the manifest, then every new part is sliced from the
+    /// files holding its rows, with the keys and content a fresh run with
+    /// `--parts NEW_PARTS` writes. New parts holding rows of a missing or
+    /// damaged file are listed to regenerate with `--part`.
+    Extend {
+        /// Output directory of the generated dataset
+        #[arg(long)]
+        data_dir: PathBuf,
+
+        /// Number of parts to re-cut the dataset into
+        #[arg(long)]
+        new_parts: i32,
+
+        /// Parquet compression of the new parts, as for generate
+        #[arg(short = 'c', long, default_value = "SNAPPY", value_parser = parse_compression)]
+        parquet_compression: Compression,
+
+        /// Target row group size of the new parts, as for generate
+        #[arg(long, default_value_t = DEFAULT_PARQUET_ROW_GROUP_BYTES, value_parser = parse_row_group_bytes)]
+        parquet_row_group_bytes: i64,
+    },
+
+    /// Add derived columns to the zone files of a dataset without reading
+    /// the source
+    ///
+    /// Every part file and the merged copy get the columns computed from
+    /// `z_boundary`, with the values a fresh run with the matching options
+    /// writes. Files are rewritten in place one row group at a time,
+    /// keeping their rows, row groups, compression and footer metadata, and
+    /// the manifest records their new content hashes. Files already holding
+    /// the columns are skipped, so an interrupted run can be repeated.
+    Augment {
+        /// Output directory of the generated dataset
+        #[arg(long)]
+        data_dir: PathBuf,
+
+        /// Columns to add, comma separated
+        #[arg(long, value_enum, value_delimiter = ',', required = true)]
+        add_columns: Vec<zone::AugmentColumn>,
+
+        /// Columns the `bbox` bounds are written in, as for generate
+        #[arg(long, value_enum, default_value_t = zone::BboxLayout::Struct)]
+        bbox_layout: zone::BboxLayout,
+
+        /// CRS the `area` is measured in, as for generate
+        #[arg(long, value_enum, ignore_case = true, default_value = "EPSG:6933")]
+        area_crs: zone::AreaCrs,
+
+        /// Number of files rewritten in parallel
+        #[arg(long, default_value_t = num_cpus::get())]
+        jobs: usize,
+    },
+
+    /// Write the zone `_SUCCESS` marker once the manifest lists every part
+    ///
+    /// Used when each part was generated by a separate `--part` invocation.
+    Finalize {
+        /// Output directory of the generated dataset
+        #[arg(long)]
+        data_dir: PathBuf,
+    },
+}
+
+/// Joins written next to the generated tables
+#[derive(Subcommand)]
+enum MaterializeCommand {
+    /// Write the zones containing every trip pickup and dropoff point
+    ///
+    /// Writes `trip_zone.parquet` with `t_tripkey`, `pickup_zonekey` and
+    /// `dropoff_zonekey`. A point in no zone gets a null key; where zones
+    /// overlap the smallest one wins, ties going to the smallest
+    /// `z_zonekey`.
+    ZoneContainment {
+        /// Output directory holding the generated trip and zone tables
+        #[arg(long)]
+        data_dir: PathBuf,
+
+        /// Number of trip batches joined in parallel
+        #[arg(long, default_value_t = num_cpus::get())]
+        jobs: usize,
+
+        /// Process datasets whose zone file names embed different scale
+        /// factors
+        #[arg(long, default_value_t = false)]
+        force: bool,
+    },
+
+    /// Write every pair of zones whose boundaries touch or overlap
+    ///
+    /// Writes `zone_adjacency.parquet` with `z_zonekey_a` and
+    /// `z_zonekey_b`, one row per pair with the smaller key first.
+    ZoneAdjacency {
+        /// Output directory holding the generated zone table
+        #[arg(long)]
+        data_dir: PathBuf,
+
+        /// Number of zone batches tested in parallel
+        #[arg(long, default_value_t = num_cpus::get())]
+        jobs: usize,
+
+        /// Only pair zones of the same `z_subtype`
+        #[arg(long, default_value_t = false)]
+        same_subtype_only: bool,
+
+        /// Process datasets whose zone file names embed different scale
+        /// factors
+        #[arg(long, default_value_t = false)]
+        force: bool,
+    },
+}
+
+#[derive(Args)]
+struct VerifyArgs {
+    /// Dataset directory whose parts are re-hashed and checked against the
+    /// hashes recorded in the manifest and Parquet footers
+    #[arg(long, required_unless_present = "compare")]
+    data_dir: Option<PathBuf>,
+
+    /// Compare the per-part content hashes of two dataset directories
+    ///
+    /// Parts without a recorded hash are compared by streaming their content.
+    #[arg(long, num_args = 2, value_names = ["DIR_A", "DIR_B"], conflicts_with = "data_dir")]
+    compare: Option<Vec<PathBuf>>,
+
+    /// Process datasets whose zone file names embed different scale factors
+    #[arg(long, default_value_t = false)]
+    force: bool,
+}
+
+impl Command {
+    async fn run(&self) -> io::Result<()> {
+        match self {
+            Command::Verify(args) => match (&args.data_dir, &args.compare) {
+                (_, Some(dirs)) => zone::main::compare_zone(&dirs[0], &dirs[1], args.force),
+                (Some(dir), None) => zone::main::verify_zone(dir, args.force),
+                (None, None) => unreachable!("clap requires --data-dir or --compare"),
+            },
+            Command::Count {
+                table,
+                scale_factor,
+                input_theme,
+                rows,
+                estimate_io,
+                from_metadata,
+            } => {
+                if *table != Table::Zone {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("count is only supported for the zone table, not {table}"),
+                    ));
+                }
+                if *estimate_io {
+                    return zone::main::estimate_zone_io(
+                        *scale_factor,
+                        parse_input_themes(input_theme)?,
+                    )
+                    .await;
+                }
+                zone::main::count_zone(
+                    *scale_factor,
+                    row_counts(rows)?.get(table).copied(),
+                    parse_input_themes(input_theme)?,
+                    *from_metadata,
+                )
+                .await
+            }
+            Command::Bench {
+                table,
+                scale_factor,
+                input_theme,
+                parts,
+                parquet_compression,
+                iterations,
+            } => {
+                if *table != Table::Zone {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("bench is only supported for the zone table, not {table}"),
+                    ));
+                }
+                let args = zone::ZoneDfArgs::new(
+                    *scale_factor,
+                    PathBuf::new(),
+                    *parts,
+                    None,
+                    None,
+                    DEFAULT_PARQUET_ROW_GROUP_BYTES,
+                    *parquet_compression,
+                )
+                .with_themes(parse_input_themes(input_theme)?);
+                zone::main::bench_zone(args, *iterations).await
+            }
+            Command::Diff {
+                left,
+                right,
+                tolerance,
+                json,
+                force,
+            } => zone::main::diff_zone(left, right, *tolerance, json.as_deref(), *force),
+            Command::DiffStats {
+                old,
+                new,
+                tolerance_pct,
+                samples,
+                json,
+                force,
+            } => zone::main::diff_zone_stats(
+                old,
+                new,
+                *tolerance_pct,
+                *samples,
+                json.as_deref(),
+                *force,
+            ),
+            Command::Profile {
+                data_dir,
+                top,
+                profile_json,
+                force,
+            } => zone::main::profile_zone(data_dir, *top, profile_json.as_deref(), *force),
+            Command::ValidateGeometries {
+                data_dir,
+                json,
+                force,
+            } => zone::main::validate_zone_geometries(data_dir, json.as_deref(), *force),
+            Command::CheckReaders { data_dir, json } => {
+                readers::check_readers(data_dir, json.as_deref())
+            }
+            Command::Finalize { data_dir } => zone::main::finalize_zone(data_dir),
+            Command::Extend {
+                data_dir,
+                new_parts,
+                parquet_compression,
+                parquet_row_group_bytes,
+            } => {
+                if !data_dir.is_dir() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("{} is not a directory", data_dir.display()),
+                    ));
+                }
+                // Re-cutting replaces every part file, so no run may share
+                // the directory meanwhile
+                let holder = LockHolder::current(
+                    config_hash(std::env::args_os().skip(1)),
+                    LockMode::Exclusive,
+                );
+                let _output_lock = OutputLock::acquire(data_dir, &holder, false)?;
+                zone::main::extend_zone(
+                    data_dir,
+                    *new_parts,
+                    *parquet_compression,
+                    *parquet_row_group_bytes,
+                )
+            }
+            Command::Augment {
+                data_dir,
+                add_columns,
+                bbox_layout,
+                area_crs,
+                jobs,
+            } => {
+                if !data_dir.is_dir() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("{} is not a directory", data_dir.display()),
+                    ));
+                }
+                // The files are rewritten in place, so no run may share the
+                // directory meanwhile
+                let holder = LockHolder::current(
+                    config_hash(std::env::args_os().skip(1)),
+                    LockMode::Exclusive,
+                );
+                let _output_lock = OutputLock::acquire(data_dir, &holder, false)?;
+                zone::main::augment_zone(
+                    data_dir,
+                    &zone::AugmentOptions {
+                        columns: add_columns.clone(),
+                        bbox_layout: *bbox_layout,
+                        area_crs: *area_crs,
+                        jobs: (*jobs).max(1),
+                    },
+                )
+            }
+            Command::ExportTiles {
+                data_dir,
+                output,
+                min_zoom,
+                max_zoom,
+                force,
+            } => zone::main::export_zone_tiles(data_dir, output, *min_zoom, *max_zoom, *force),
+            Command::Queries {
+                output_dir,
+                scale_factor,
+                seed,
+                count,
+            } => zone::main::write_zone_queries(output_dir, *scale_factor, *seed, *count),
+            Command::Package {
+                data_dir,
+                out,
+                check_hashes,
+                force,
+            } => zone::main::package_dataset(data_dir, out, *check_hashes, *force),
+            Command::Unpack {
+                archive,
+                output_dir,
+            } => zone::main::unpack_dataset(archive, output_dir),
+            Command::Materialize(MaterializeCommand::ZoneContainment {
+                data_dir,
+                jobs,
+                force,
+            }) => zone::main::materialize_zone_containment(data_dir, *jobs, *force),
+            Command::Materialize(MaterializeCommand::ZoneAdjacency {
+                data_dir,
+                jobs,
+                same_subtype_only,
+                force,
+            }) => {
+                zone::main::materialize_zone_adjacency(data_dir, *jobs, *same_subtype_only, *force)
+            }
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
@@ -148,6 +1599,116 @@ enum Table {
     Zone,
 }
 
+fn parse_input_themes(spec: &str) -> io::Result<Vec<zone::ThemeInput>> {
+    zone::ThemeInput::parse_list(spec)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))
+}
+
+/// Parses `--time-range START..END` into a range of generated dates
+fn parse_time_range(spec: &str) -> Result<Range<i32>, String> {
+    let (start, end) = spec
+        .split_once("..")
+        .ok_or_else(|| format!("expected START..END, e.g. 1994-01-01..1994-01-08, got {spec}"))?;
+    let parse = |date: &str| {
+        parse_generated_date(date)
+            .ok_or_else(|| format!("invalid date {date:?}, expected YYYY-MM-DD"))
+    };
+    let dates = parse(start)?..parse(end)?;
+    if start >= end {
+        return Err(format!("the end of {spec} must be after its start"));
+    }
+    if dates.is_empty() {
+        return Err(format!(
+            "{spec} contains no trip pickup dates, which range from {} to {}",
+            format_generated_date(MIN_GENERATE_DATE),
+            format_generated_date(MIN_GENERATE_DATE + TOTAL_DATE_RANGE - 1)
+        ));
+    }
+    Ok(dates)
+}
+
+/// Parses `--rows TABLE=COUNT`. The count may contain `_` separators and
+/// end in `k`, `M` or `B` for thousands, millions or billions.
+fn parse_table_rows(spec: &str) -> Result<(Table, u64), String> {
+    let (table, count) = spec
+        .split_once('=')
+        .ok_or_else(|| format!("expected TABLE=COUNT, e.g. zone=250000, got {spec}"))?;
+    let table = Table::from_str(table.trim()).map_err(|_| format!("unknown table {table:?}"))?;
+    Ok((table, parse_row_count(count)?))
+}
+
+/// A positive row count such as `250000`, `250_000` or `250k`
+fn parse_row_count(count: &str) -> Result<u64, String> {
+    let count = count.trim().replace('_', "");
+    let (digits, multiplier) = match count.chars().last() {
+        Some('k' | 'K') => (&count[..count.len() - 1], 1_000),
+        Some('m' | 'M') => (&count[..count.len() - 1], 1_000_000),
+        Some('b' | 'B') => (&count[..count.len() - 1], 1_000_000_000),
+        _ => (count.as_str(), 1),
+    };
+    let rows = digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|rows| rows.checked_mul(multiplier))
+        .filter(|&rows| rows > 0 && i64::try_from(rows).is_ok())
+        .ok_or_else(|| {
+            format!(
+                "invalid row count {count:?}, expected a positive number such as 250000 or 250k"
+            )
+        })?;
+    Ok(rows)
+}
+
+/// A finite scale factor above 0
+fn parse_scale_factor(value: &str) -> Result<f64, String> {
+    let scale_factor = value
+        .trim()
+        .parse::<f64>()
+        .map_err(|_| format!("invalid scale factor {value:?}, expected a number such as 10"))?;
+    zone::check_scale_factor(scale_factor).map_err(|e| e.to_string())
+}
+
+/// A `--parts` count of at least 1
+fn parse_parts(value: &str) -> Result<i32, String> {
+    let parts = value
+        .trim()
+        .parse::<i32>()
+        .map_err(|_| format!("invalid part count {value:?}, expected a whole number"))?;
+    zone::check_parts(parts).map_err(|e| e.to_string())
+}
+
+/// A `--parquet-row-group-bytes` size of at least 64 KiB
+fn parse_row_group_bytes(value: &str) -> Result<i64, String> {
+    let bytes = value
+        .trim()
+        .parse::<i64>()
+        .map_err(|_| format!("invalid row group size {value:?}, expected a number of bytes"))?;
+    zone::check_row_group_bytes(bytes).map_err(|e| e.to_string())
+}
+
+/// The `--rows` overrides by table, refusing a table given twice
+fn row_counts(rows: &[(Table, u64)]) -> io::Result<BTreeMap<Table, u64>> {
+    let mut row_counts = BTreeMap::new();
+    for &(table, count) in rows {
+        if row_counts.insert(table, count).is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("--rows is given more than once for the {table} table"),
+            ));
+        }
+    }
+    Ok(row_counts)
+}
+
+fn parse_column_list(spec: Option<&str>) -> Vec<String> {
+    spec.into_iter()
+        .flat_map(|spec| spec.split(','))
+        .map(str::trim)
+        .filter(|column| !column.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
 impl Display for Table {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.name())
@@ -231,13 +1792,56 @@ enum OutputFormat {
     Tbl,
     Csv,
     Parquet,
+    /// Only for the zone table
+    Orc,
+    Avro,
+}
+
+impl OutputFormat {
+    /// Extension of the files written in this format
+    fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Tbl => "tbl",
+            OutputFormat::Csv => "csv",
+            OutputFormat::Parquet => "parquet",
+            OutputFormat::Orc => "orc",
+            OutputFormat::Avro => "avro",
+        }
+    }
 }
 
 #[tokio::main]
 async fn main() -> io::Result<()> {
     // Parse command line arguments
     let cli = Cli::parse();
-    cli.main().await
+    let exporter = match &cli.otlp_endpoint {
+        Some(endpoint) => Some(
+            otlp::OtlpExporter::install(endpoint)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?,
+        ),
+        None => None,
+    };
+    let run = info_span!("spatialbench", version = env!("CARGO_PKG_VERSION"));
+    let result = cli.main().instrument(run).await;
+    if let Some(exporter) = exporter {
+        match exporter.export().await {
+            Ok(spans) => info!("Exported {spans} spans to {}", exporter.url()),
+            Err(e) => warn!("Failed to export spans to {}: {e}", exporter.url()),
+        }
+    }
+    match result {
+        Err(e) if e.kind() == io::ErrorKind::Interrupted => {
+            let command: Vec<String> = std::env::args().collect();
+            eprintln!("{e}. Parts completed so far were kept; to resume, re-run:");
+            eprintln!("  {}", command.join(" "));
+            std::process::exit(interrupt::INTERRUPTED_EXIT_CODE);
+        }
+        Err(e) if zone::main::is_plan_differs(&e) => {
+            eprintln!("{e}");
+            std::process::exit(zone::main::PLAN_DIFFERS_EXIT_CODE);
+        }
+        result => result,
+    }
 }
 
 impl Cli {
@@ -252,11 +1856,31 @@ impl Cli {
             debug!("Logging configured from environment variables");
         }
 
-        // Create output directory if it doesn't exist and we are not writing to stdout.
-        if !self.stdout {
-            fs::create_dir_all(&self.output_dir)?;
+        if let Some(command) = &self.command {
+            return command.run().await;
+        }
+
+        zone::check_scale_factor(self.scale_factor)
+            .and_then(|sf| zone::check_scale_factor_ceiling(sf, self.max_scale_factor()))
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+        if self
+            .parts
+            .is_some_and(|parts| parts > zone::PARTS_WARNING_THRESHOLD)
+        {
+            eprintln!(
+                "Warning: --parts above {} writes that many files per table",
+                zone::PARTS_WARNING_THRESHOLD
+            );
+        }
+        if self.allow_fractional_scale && self.scale_factor >= 1.0 {
+            eprintln!(
+                "Warning: --allow-fractional-scale has no effect at --scale-factor 1 or above"
+            );
         }
 
+        let cancellation = interrupt::CancellationFlag::default();
+        interrupt::install_ctrl_c_handler(cancellation.clone());
+
         // Load overrides if provided or if default config file exists
         let config_path = if let Some(path) = &self.config {
             // Use explicitly provided config path
@@ -271,8 +1895,9 @@ impl Cli {
             }
         };
 
-        if let Some(path) = config_path {
-            let text = std::fs::read_to_string(&path).map_err(|e| {
+        let mut overrides = SpatialOverrides::default();
+        if let Some(path) = &config_path {
+            let text = std::fs::read_to_string(path).map_err(|e| {
                 io::Error::new(
                     io::ErrorKind::InvalidInput,
                     format!("Failed reading {}: {e}", path.display()),
@@ -283,7 +1908,7 @@ impl Cli {
                 Ok(file_cfg) => {
                     let trip = file_cfg.trip.as_ref().map(|c| c.to_generator());
                     let building = file_cfg.building.as_ref().map(|c| c.to_generator());
-                    set_overrides(SpatialOverrides { trip, building });
+                    overrides = SpatialOverrides { trip, building };
                     info!("Loaded spider configuration from {}", path.display());
                 }
                 Err(e) => {
@@ -296,6 +1921,32 @@ impl Cli {
         } else {
             info!("Using default spider configuration from spider_defaults.rs");
         }
+        if let Some(distribution) = self.point_distribution {
+            if !self.point_dispersion.is_finite() || self.point_dispersion <= 0.0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "Invalid --point-dispersion {}, expected a positive number",
+                        self.point_dispersion
+                    ),
+                ));
+            }
+            if let (Some(_), Some(path)) = (&overrides.trip, &config_path) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "--point-distribution can't be combined with the trip configuration in {}",
+                        path.display()
+                    ),
+                ));
+            }
+            overrides.trip = Some(SpatialDefaults::trip_with_distribution(
+                distribution,
+                self.point_dispersion,
+            ));
+            info!("Generating {distribution} trip pickup points");
+        }
+        set_overrides(overrides);
 
         // Determine which tables to generate
         let tables: Vec<Table> = if let Some(tables) = self.tables.as_ref() {
@@ -311,6 +1962,33 @@ impl Cli {
             ]
         };
 
+        if self.format == OutputFormat::Orc {
+            if let Some(table) = tables.iter().find(|table| **table != Table::Zone) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "--format orc is only supported for the zone table, not {table}; \
+                         pass --tables zone"
+                    ),
+                ));
+            }
+        } else if self.orc_compression != zone::OrcCompression::default()
+            || self.orc_stripe_bytes != zone::DEFAULT_ORC_STRIPE_BYTES
+        {
+            eprintln!("Warning: ORC option set but not generating ORC files");
+        }
+        if self.format != OutputFormat::Avro {
+            if self.emit_avro_schema {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "--emit-avro-schema requires --format avro",
+                ));
+            }
+            if self.avro_codec != AvroCodec::default() {
+                eprintln!("Warning: Avro codec option set but not generating Avro files");
+            }
+        }
+
         // Warn if parquet specific options are set but not generating parquet
         if self.format != OutputFormat::Parquet {
             if self.parquet_compression != Compression::SNAPPY {
@@ -323,6 +2001,119 @@ impl Cli {
                     "Warning: Parquet row group size option set but not generating Parquet files"
                 );
             }
+            if self.small_footer {
+                eprintln!("Warning: --small-footer set but not generating Parquet files");
+            }
+        }
+
+        if self.parquet_zstd_train_dict {
+            if self.format != OutputFormat::Parquet {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "--parquet-zstd-train-dict can't be combined with --format {}",
+                        self.format.extension()
+                    ),
+                ));
+            }
+            if !matches!(self.parquet_compression, Compression::ZSTD(_)) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "--parquet-zstd-train-dict requires ZSTD compression, not {}",
+                        self.parquet_compression
+                    ),
+                ));
+            }
+        }
+
+        if !self.emit_load_scripts.is_empty()
+            && (self.format != OutputFormat::Parquet || self.stdout)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "--emit-load-scripts requires Parquet output written to files",
+            ));
+        }
+        if self.time_range.is_some() {
+            if !self.emit_load_scripts.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "--emit-load-scripts can't be combined with --time-range",
+                ));
+            }
+            if !tables.contains(&Table::Trip) {
+                eprintln!("Warning: --time-range set but not generating the trip table");
+            }
+        }
+
+        let row_counts = row_counts(&self.rows)?;
+        for table in row_counts.keys() {
+            if !tables.contains(table) {
+                eprintln!("Warning: --rows set for the {table} table but not generating it");
+            }
+        }
+
+        let zone_args = if tables.contains(&Table::Zone) {
+            Some(self.zone_args(&cancellation)?)
+        } else {
+            None
+        };
+
+        if self.explain || self.explain_analyze.is_some() {
+            return match zone_args {
+                Some(args) => zone::main::explain_zone(args).await,
+                None => Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "--explain and --explain-analyze plan the zone table, which is not \
+                     being generated",
+                )),
+            };
+        }
+
+        if let Some(manifest) = &self.diff_against {
+            return match zone_args {
+                Some(args) => zone::main::diff_zone_plan(args, manifest).await,
+                None => Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "--diff-against plans the zone table, which is not being generated",
+                )),
+            };
+        }
+
+        let uploader = self
+            .upload_to
+            .as_deref()
+            .map(|url| {
+                let policy = RetryPolicy::new(self.write_retries, self.write_retry_base_ms);
+                Uploader::for_url(url, policy)
+            })
+            .transpose()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+
+        // Lock and check the output directory before any generation work,
+        // unless writing to stdout. The lock is held until the run returns.
+        let _output_lock = if self.stdout {
+            None
+        } else {
+            let mode = match self.part {
+                Some(_) => LockMode::Shared,
+                None => LockMode::Exclusive,
+            };
+            let holder = LockHolder::current(config_hash(std::env::args_os().skip(1)), mode);
+            Some(OutputLock::acquire(
+                &self.output_dir,
+                &holder,
+                self.force_unlock,
+            )?)
+        };
+        if !self.stdout {
+            prepare_output_dir(
+                &self.output_dir,
+                self.scale_factor,
+                zone_args.as_ref(),
+                ExistingOutputs::from_flags(self.overwrite, self.allow_mixed_outputs),
+            )?;
         }
 
         // Determine what files to generate
@@ -333,11 +2124,22 @@ impl Cli {
             self.parquet_row_group_bytes,
             self.stdout,
             self.output_dir.clone(),
+        )
+        .with_avro_codec(self.avro_codec)
+        .with_trip_pickup_dates(self.time_range.clone())
+        .with_trip_point_distribution(self.point_distribution)
+        .with_generation_params(self.generation_params(self.scale_factor))
+        .with_row_counts(
+            row_counts
+                .iter()
+                .filter(|(table, _)| **table != Table::Zone)
+                .map(|(&table, &count)| (table, count as i64))
+                .collect(),
         );
 
-        for table in tables {
-            if table == Table::Zone {
-                self.generate_zone().await?
+        for &table in &tables {
+            if let (Table::Zone, Some(args)) = (table, &zone_args) {
+                self.generate_zone(args.clone()).await?
             } else {
                 output_plan_generator.generate_plans(
                     table,
@@ -359,22 +2161,107 @@ impl Cli {
         info!("Created static distributions and text pools in {elapsed:?}");
 
         // Run
-        let runner = runner::PlanRunner::new(output_plans, self.num_threads);
+        let runner =
+            runner::PlanRunner::new(output_plans, self.num_threads).with_cancellation(cancellation);
         runner.run().await?;
         info!("Generation complete!");
+
+        if self.emit_avro_schema {
+            std::fs::create_dir_all(&self.output_dir)?;
+            for &table in &tables {
+                // The zone writer writes the schema of its own options
+                if let Some(schema) = runner::table_schema(table) {
+                    let path = self.output_dir.join(format!("{table}.avsc"));
+                    avro::write_avro_schema(&path, table.name(), &schema)?;
+                }
+            }
+        }
+
+        if !self.emit_load_scripts.is_empty() {
+            let names: Vec<&str> = tables.iter().map(Table::name).collect();
+            load_scripts::write_load_scripts(&self.output_dir, &names, &self.emit_load_scripts)?;
+        }
+
+        // Single-part runs may share the directory with other workers, whose
+        // files can still be in progress
+        if !self.stdout && self.part.is_none() {
+            let names: Vec<&str> = tables.iter().map(Table::name).collect();
+            let path = DatasetManifest::describe(
+                &self.output_dir,
+                self.scale_factor,
+                &names,
+                self.format.extension(),
+            )
+            .and_then(|dataset| dataset.write(&self.output_dir))
+            .map_err(io::Error::other)?;
+            info!("Wrote {}", path.display());
+        }
+
+        if let Some(uploader) = uploader {
+            uploader
+                .upload_dir(&self.output_dir)
+                .await
+                .map_err(io::Error::other)?;
+        }
         Ok(())
     }
 
-    async fn generate_zone(&self) -> io::Result<()> {
+    async fn generate_zone(&self, args: zone::ZoneDfArgs) -> io::Result<()> {
         let format = match self.format {
             OutputFormat::Parquet => zone::main::OutputFormat::Parquet,
             OutputFormat::Csv => zone::main::OutputFormat::Csv,
             OutputFormat::Tbl => zone::main::OutputFormat::Tbl,
+            OutputFormat::Orc => zone::main::OutputFormat::Orc,
+            OutputFormat::Avro => zone::main::OutputFormat::Avro,
         };
+        zone::main::generate_zone(format, args).await
+    }
 
-        zone::main::generate_zone(
-            format,
-            self.scale_factor,
+    /// The parameters recorded with `--embed-params` for tables generated
+    /// at `scale_factor`
+    fn generation_params(&self, scale_factor: f64) -> Option<GenerationParams> {
+        self.embed_params
+            .then(|| GenerationParams::from_env(scale_factor, self.deterministic))
+    }
+
+    /// The `--max-scale-factor` ceiling, lifted by `--yes-i-know`
+    fn max_scale_factor(&self) -> Option<f64> {
+        (!self.yes_i_know).then_some(self.max_scale_factor)
+    }
+
+    /// The zone options given on the command line
+    fn zone_args(
+        &self,
+        cancellation: &interrupt::CancellationFlag,
+    ) -> io::Result<zone::ZoneDfArgs> {
+        let region_map = self
+            .region_map
+            .as_ref()
+            .map(|path| zone::RegionMap::from_csv(path, self.region_map_ci))
+            .transpose()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+
+        let synthetic_columns = self
+            .synthetic_columns
+            .as_deref()
+            .map(zone::SyntheticColumn::parse_list)
+            .transpose()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?
+            .unwrap_or_default();
+
+        let scale_factor =
+            zone::main::resolve_scale_factor(self.scale_factor, self.allow_fractional_scale)?;
+        let total_rows = match (&self.plan_file, self.total_rows) {
+            (Some(path), _) => Some(zone::main::read_plan_total_rows(path, scale_factor)?),
+            (None, total_rows) => total_rows,
+        };
+        let part_boundaries = match &self.plan_file {
+            Some(path) => zone::main::read_plan_boundaries(path, scale_factor)?,
+            None => None,
+        };
+
+        Ok(zone::ZoneDfArgs::new(
+            scale_factor,
             self.output_dir.clone(),
             self.parts,
             self.part,
@@ -382,7 +2269,113 @@ impl Cli {
             self.parquet_row_group_bytes,
             self.parquet_compression,
         )
-        .await
+        .with_transform(zone::ZoneTransformOptions {
+            normalize_country: self.normalize_country,
+            region_map,
+            normalize_region: self.normalize_region,
+            normalize_winding: self.normalize_winding,
+            simplify_tolerance: self.simplify_tolerance,
+            simplify_algorithm: self.simplify_algorithm,
+            include_hierarchy: self.include_hierarchy,
+            key_partition: self.key_partition_by,
+            composite_key: self.composite_key,
+            include_provenance: self.with_provenance,
+            attribution_column: self.attribution_column,
+            bbox_covering: self.with_bbox_covering,
+            bbox_layout: self.bbox_layout,
+            bbox_inflate: self.bbox_inflate,
+            area: self.with_area.then_some(self.area_crs),
+            point_as_lonlat: self.point_as_lonlat,
+            names_common: self.with_names_common,
+            names_languages: parse_column_list(self.names_languages.as_deref()),
+            pseudonymize_names: self.pseudonymize_names.then_some(self.pseudonym_style),
+            skip_wkb_normalize: self.no_wkb_normalize,
+            keep_zm: self.keep_zm,
+            on_bad_geometry: match self.skip_bad_geometry {
+                true => zone::OnBadGeometry::Skip,
+                false => self.on_bad_geometry,
+            },
+            geometry_threads: Some(self.num_threads),
+            synthetic_columns,
+            extra_columns: self.extra_columns.clone(),
+            geometry_encoding: self.geometry_encoding,
+            no_geometry: self.no_geometry,
+            seed: self.seed,
+            columns: self.columns.as_deref().map(|columns| {
+                zone::ColumnSelection::new(parse_column_list(Some(columns)), self.column_order)
+            }),
+        })
+        .with_parquet(
+            zone::ParquetWriteOptions::new(self.parquet_compression, self.parquet_row_group_bytes)
+                .with_page_sizes(
+                    self.parquet_page_size_bytes,
+                    self.parquet_dictionary_page_size_bytes,
+                )
+                .with_write_batch_size(self.write_batch_size)
+                .with_dictionary_columns(
+                    parse_column_list(self.dictionary_columns.as_deref()),
+                    parse_column_list(self.no_dictionary_columns.as_deref()),
+                )
+                .with_small_footer(self.small_footer),
+        )
+        .with_file_format(match self.format {
+            OutputFormat::Orc => zone::ZoneFileFormat::Orc(zone::OrcWriteOptions::new(
+                self.orc_compression,
+                self.orc_stripe_bytes,
+            )),
+            OutputFormat::Avro => zone::ZoneFileFormat::Avro(self.avro_codec),
+            OutputFormat::Csv => zone::ZoneFileFormat::Csv,
+            OutputFormat::Tbl => zone::ZoneFileFormat::Tbl,
+            OutputFormat::Parquet => zone::ZoneFileFormat::Parquet,
+        })
+        .with_emit_avro_schema(self.emit_avro_schema)
+        .with_max_rows_per_file(self.max_rows_per_file.map(|rows| rows as usize))
+        .with_zstd_train_dict(self.parquet_zstd_train_dict)
+        .with_themes(match &self.input_url {
+            Some(url) => vec![zone::ThemeInput::remote(zone::Theme::DivisionArea, url)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?],
+            None => parse_input_themes(&self.input_theme)?,
+        })
+        .with_name_column(self.name_column.clone())
+        .with_allow_fractional_scale(self.allow_fractional_scale)
+        .with_max_scale_factor(self.max_scale_factor())
+        .with_layout(self.layout)
+        .with_filename_template(self.filename_template.clone())
+        .with_name_with_sf(self.name_with_sf)
+        .with_balance(self.balance, part_boundaries)
+        .with_partition_scheme(self.partition_strategy)
+        .with_antimeridian_aware(self.antimeridian_aware)
+        .with_rows(row_counts(&self.rows)?.get(&Table::Zone).copied())
+        .with_keep_geometry_types(self.keep_geometry_type.clone())
+        .with_limit(self.limit)
+        .with_sampling(self.sampling, self.min_per_country)
+        .with_cleanup_on_failure(self.cleanup_on_failure)
+        .with_also_merge(self.also_merge)
+        .with_spill_collect(self.spill_collect)
+        .with_simplification_levels(self.simplification_levels.clone())
+        .with_verify_key_order(self.verify_key_order)
+        .with_assert_contiguous_keys(self.assert_contiguous_keys)
+        .with_max_string_lengths(
+            self.max_string_length
+                .as_deref()
+                .map(zone::MaxStringLength::parse_list)
+                .transpose()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?
+                .unwrap_or_default(),
+            self.on_overlong,
+        )
+        .with_write_io(self.write_buffer_bytes, self.fsync)
+        .with_require_schema_version(self.require_schema_version)
+        .with_explain(match (self.explain, self.explain_analyze) {
+            (true, _) => Some(zone::Explain::Plan),
+            (false, Some(rows)) => Some(zone::Explain::Analyze { rows }),
+            (false, None) => None,
+        })
+        .with_deterministic(self.deterministic)
+        .with_include_attribution(self.include_attribution)
+        .with_generation_params(self.generation_params(scale_factor))
+        .with_interrupt(cancellation.clone(), self.on_interrupt)
+        .with_total_rows(total_rows))
     }
 }
 
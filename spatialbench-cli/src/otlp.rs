@@ -0,0 +1,506 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Export of the zone pipeline spans to an OpenTelemetry collector
+//!
+//! The zone pipeline runs its stages in `tracing` spans: `zone.scan`,
+//! `zone.count`, `zone.collect`, `zone.transform`, `zone.partition` and a
+//! `zone.write` per part, with the part, rows and bytes as attributes.
+//! Without a subscriber the spans are disabled and the `log` output is
+//! unchanged. [`OtlpExporter::install`] collects the spans of this crate,
+//! and [`OtlpExporter::export`] sends them to the collector once the run is
+//! done, as OTLP/HTTP with a JSON body. OTLP over gRPC is not spoken, so
+//! an endpoint on its port 4317 is rejected rather than left to fail once
+//! the run is done.
+
+use anyhow::{anyhow, Result};
+use serde_json::{json, Value};
+use std::cell::RefCell;
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+use url::Url;
+
+/// Target prefix of the collected spans; those of other crates, such as
+/// DataFusion's, are left out
+const TARGET: &str = "spatialbench_cli";
+
+/// Path of the OTLP/HTTP trace service
+const TRACES_PATH: &str = "v1/traces";
+
+/// Default port of OTLP over gRPC, which the exporter does not speak
+const OTLP_GRPC_PORT: u16 = 4317;
+
+/// Default port of OTLP over HTTP
+const OTLP_HTTP_PORT: u16 = 4318;
+
+/// `SPAN_KIND_INTERNAL`: every span is a stage of the run itself
+const SPAN_KIND_INTERNAL: u32 = 1;
+
+/// Name of the exporting service in the resource of every span
+const SERVICE_NAME: &str = "spatialbench-cli";
+
+enum AttributeValue {
+    Int(i64),
+    Double(f64),
+    Bool(bool),
+    String(String),
+}
+
+impl AttributeValue {
+    fn to_json(&self) -> Value {
+        match self {
+            // OTLP/JSON writes 64 bit integers as strings
+            Self::Int(value) => json!({ "intValue": value.to_string() }),
+            Self::Double(value) => json!({ "doubleValue": value }),
+            Self::Bool(value) => json!({ "boolValue": value }),
+            Self::String(value) => json!({ "stringValue": value }),
+        }
+    }
+}
+
+/// Records span fields as attributes, replacing earlier values
+struct AttributeVisitor<'a>(&'a mut Vec<(&'static str, AttributeValue)>);
+
+impl AttributeVisitor<'_> {
+    fn set(&mut self, field: &Field, value: AttributeValue) {
+        match self.0.iter_mut().find(|(key, _)| *key == field.name()) {
+            Some((_, existing)) => *existing = value,
+            None => self.0.push((field.name(), value)),
+        }
+    }
+}
+
+impl Visit for AttributeVisitor<'_> {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.set(field, AttributeValue::Double(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.set(field, AttributeValue::Int(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        let value = i64::try_from(value).unwrap_or(i64::MAX);
+        self.set(field, AttributeValue::Int(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.set(field, AttributeValue::Bool(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.set(field, AttributeValue::String(value.to_string()));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.set(field, AttributeValue::String(format!("{value:?}")));
+    }
+}
+
+struct SpanData {
+    name: &'static str,
+    trace_id: u128,
+    parent: Option<u64>,
+    attributes: Vec<(&'static str, AttributeValue)>,
+    start: SystemTime,
+    /// Handles to the span still alive; it ends when the last is dropped
+    refs: usize,
+}
+
+impl SpanData {
+    fn to_json(&self, id: u64, end: SystemTime) -> Value {
+        let mut span = json!({
+            "traceId": format!("{:032x}", self.trace_id),
+            "spanId": format!("{id:016x}"),
+            "name": self.name,
+            "kind": SPAN_KIND_INTERNAL,
+            "startTimeUnixNano": unix_nanos(self.start).to_string(),
+            "endTimeUnixNano": unix_nanos(end).to_string(),
+            "attributes": self
+                .attributes
+                .iter()
+                .map(|(key, value)| json!({ "key": key, "value": value.to_json() }))
+                .collect::<Vec<_>>(),
+        });
+        if let Some(parent) = self.parent {
+            span["parentSpanId"] = json!(format!("{parent:016x}"));
+        }
+        span
+    }
+}
+
+fn unix_nanos(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+}
+
+thread_local! {
+    /// Ids of the spans entered on this thread, innermost last
+    static ENTERED: RefCell<Vec<u64>> = const { RefCell::new(Vec::new()) };
+}
+
+/// A `tracing` subscriber keeping the spans of this crate until they are
+/// exported. Spans without a parent start a new trace.
+pub struct SpanCollector {
+    /// Upper half of every trace id, random for every collector
+    trace_seed: u64,
+    next_id: AtomicU64,
+    open: Mutex<HashMap<u64, SpanData>>,
+    closed: Mutex<Vec<(u64, SpanData, SystemTime)>>,
+}
+
+impl Default for SpanCollector {
+    fn default() -> Self {
+        Self {
+            trace_seed: RandomState::new().build_hasher().finish(),
+            // Span ids are never 0
+            next_id: AtomicU64::new(1),
+            open: Mutex::default(),
+            closed: Mutex::default(),
+        }
+    }
+}
+
+impl SpanCollector {
+    /// Removes the spans closed so far, returning their number and the
+    /// body of the OTLP trace export request holding them
+    pub(crate) fn take_request(&self) -> (usize, Value) {
+        let closed = std::mem::take(&mut *self.closed.lock().unwrap());
+        let spans: Vec<Value> = closed
+            .iter()
+            .map(|(id, span, end)| span.to_json(*id, *end))
+            .collect();
+        let body = json!({
+            "resourceSpans": [{
+                "resource": {
+                    "attributes": [
+                        { "key": "service.name", "value": { "stringValue": SERVICE_NAME } },
+                        {
+                            "key": "service.version",
+                            "value": { "stringValue": env!("CARGO_PKG_VERSION") },
+                        },
+                    ],
+                },
+                "scopeSpans": [{
+                    "scope": { "name": TARGET, "version": env!("CARGO_PKG_VERSION") },
+                    "spans": spans,
+                }],
+            }],
+        });
+        (closed.len(), body)
+    }
+}
+
+impl Subscriber for SpanCollector {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.is_span() && metadata.target().starts_with(TARGET)
+    }
+
+    fn new_span(&self, attributes: &Attributes<'_>) -> Id {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let parent = if attributes.is_root() {
+            None
+        } else if attributes.is_contextual() {
+            ENTERED.with(|entered| entered.borrow().last().copied())
+        } else {
+            attributes.parent().map(Id::into_u64)
+        };
+
+        let mut open = self.open.lock().unwrap();
+        let parent = parent.and_then(|parent| Some((parent, open.get(&parent)?.trace_id)));
+        let mut span = SpanData {
+            name: attributes.metadata().name(),
+            trace_id: match parent {
+                Some((_, trace_id)) => trace_id,
+                None => (u128::from(self.trace_seed) << 64) | u128::from(id),
+            },
+            parent: parent.map(|(parent, _)| parent),
+            attributes: Vec::new(),
+            start: SystemTime::now(),
+            refs: 1,
+        };
+        attributes.record(&mut AttributeVisitor(&mut span.attributes));
+        open.insert(id, span);
+        Id::from_u64(id)
+    }
+
+    fn record(&self, span: &Id, values: &Record<'_>) {
+        if let Some(span) = self.open.lock().unwrap().get_mut(&span.into_u64()) {
+            values.record(&mut AttributeVisitor(&mut span.attributes));
+        }
+    }
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, _event: &Event<'_>) {}
+
+    fn enter(&self, span: &Id) {
+        ENTERED.with(|entered| entered.borrow_mut().push(span.into_u64()));
+    }
+
+    fn exit(&self, span: &Id) {
+        ENTERED.with(|entered| {
+            let mut entered = entered.borrow_mut();
+            if let Some(index) = entered.iter().rposition(|id| *id == span.into_u64()) {
+                entered.remove(index);
+            }
+        });
+    }
+
+    fn clone_span(&self, span: &Id) -> Id {
+        if let Some(span) = self.open.lock().unwrap().get_mut(&span.into_u64()) {
+            span.refs += 1;
+        }
+        span.clone()
+    }
+
+    fn try_close(&self, span: Id) -> bool {
+        let id = span.into_u64();
+        let mut open = self.open.lock().unwrap();
+        match open.get_mut(&id) {
+            Some(span) if span.refs > 1 => {
+                span.refs -= 1;
+                false
+            }
+            Some(_) => {
+                let span = open.remove(&id).expect("span is open");
+                let end = SystemTime::now();
+                self.closed.lock().unwrap().push((id, span, end));
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Sends the spans of a run to an OTLP/HTTP collector
+pub struct OtlpExporter {
+    url: Url,
+    collector: Arc<SpanCollector>,
+}
+
+impl OtlpExporter {
+    fn new(endpoint: &Url) -> Result<Self> {
+        Ok(Self {
+            url: traces_url(endpoint)?,
+            collector: Arc::new(SpanCollector::default()),
+        })
+    }
+
+    /// Collects the spans of this process from now on, as the global
+    /// `tracing` subscriber, for the collector at `endpoint`, e.g.
+    /// `http://collector:4318`
+    pub fn install(endpoint: &Url) -> Result<Self> {
+        let exporter = Self::new(endpoint)?;
+        tracing::subscriber::set_global_default(Arc::clone(&exporter.collector))
+            .map_err(|e| anyhow!("Failed to install the OTLP exporter: {e}"))?;
+        Ok(exporter)
+    }
+
+    /// The URL the spans are sent to
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    /// Sends the spans closed so far, returning how many were sent
+    pub async fn export(&self) -> Result<usize> {
+        let (spans, body) = self.collector.take_request();
+        if spans == 0 {
+            return Ok(0);
+        }
+        reqwest::Client::new()
+            .post(self.url.clone())
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body.to_string())
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(spans)
+    }
+}
+
+/// The trace service URL of the collector at `endpoint`, which may name
+/// the service path itself
+fn traces_url(endpoint: &Url) -> Result<Url> {
+    if !matches!(endpoint.scheme(), "http" | "https") {
+        return Err(anyhow!(
+            "Invalid --otlp-endpoint {endpoint}, expected an http:// or https:// URL"
+        ));
+    }
+    if endpoint.port() == Some(OTLP_GRPC_PORT) {
+        return Err(anyhow!(
+            "Invalid --otlp-endpoint {endpoint}: port {OTLP_GRPC_PORT} is OTLP over gRPC, \
+             which is not supported; use the OTLP/HTTP port of the collector, usually \
+             {OTLP_HTTP_PORT}"
+        ));
+    }
+    let path = endpoint.path().trim_end_matches('/');
+    if path.ends_with(TRACES_PATH) {
+        return Ok(endpoint.clone());
+    }
+    let mut url = endpoint.clone();
+    url.set_path(&format!("{path}/{TRACES_PATH}"));
+    Ok(url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tracing::{field, info_span};
+
+    /// The spans of `body` by name
+    fn spans_by_name(body: &Value) -> HashMap<String, Value> {
+        body["resourceSpans"][0]["scopeSpans"][0]["spans"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|span| (span["name"].as_str().unwrap().to_string(), span.clone()))
+            .collect()
+    }
+
+    fn attribute(span: &Value, key: &str) -> Value {
+        span["attributes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|attribute| attribute["key"] == key)
+            .map(|attribute| attribute["value"].clone())
+            .unwrap_or(Value::Null)
+    }
+
+    #[test]
+    fn test_spans_nest_with_attributes() {
+        let collector = Arc::new(SpanCollector::default());
+        tracing::subscriber::with_default(Arc::clone(&collector), || {
+            let run = info_span!("run");
+            let _entered = run.enter();
+            let write = info_span!("zone.write", part = 2, rows = field::Empty);
+            write.record("rows", 10_u64);
+            // A second handle keeps the span open
+            let handle = write.clone();
+            drop(write);
+            assert_eq!(collector.take_request().0, 0);
+            drop(handle);
+            info_span!(parent: None, "other").in_scope(|| {});
+        });
+
+        let (count, body) = collector.take_request();
+        assert_eq!(count, 3);
+        let spans = spans_by_name(&body);
+        let (run, write, other) = (&spans["run"], &spans["zone.write"], &spans["other"]);
+        assert_eq!(write["parentSpanId"], run["spanId"]);
+        assert_eq!(write["traceId"], run["traceId"]);
+        assert_ne!(other["traceId"], run["traceId"]);
+        assert!(run.get("parentSpanId").is_none());
+        assert_eq!(attribute(write, "part"), json!({ "intValue": "2" }));
+        assert_eq!(attribute(write, "rows"), json!({ "intValue": "10" }));
+
+        let nanos = |span: &Value, key: &str| span[key].as_str().unwrap().parse::<u128>().unwrap();
+        assert!(nanos(run, "startTimeUnixNano") <= nanos(write, "startTimeUnixNano"));
+        assert!(nanos(write, "endTimeUnixNano") <= nanos(run, "endTimeUnixNano"));
+        // Exported spans are not sent twice
+        assert_eq!(collector.take_request().0, 0);
+    }
+
+    #[test]
+    fn test_traces_url() {
+        let url = |endpoint: &str| traces_url(&Url::parse(endpoint).unwrap());
+        assert_eq!(
+            url("http://collector:4318").unwrap().as_str(),
+            "http://collector:4318/v1/traces"
+        );
+        assert_eq!(
+            url("http://collector:4318/otlp/").unwrap().as_str(),
+            "http://collector:4318/otlp/v1/traces"
+        );
+        assert_eq!(
+            url("https://collector/v1/traces").unwrap().as_str(),
+            "https://collector/v1/traces"
+        );
+        assert!(url("grpc://collector:4317").is_err());
+        let err = url("http://collector:4317").unwrap_err();
+        assert!(
+            err.to_string().contains("port 4317 is OTLP over gRPC"),
+            "{err}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_export_posts_spans() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = Url::parse(&format!("http://{}", listener.local_addr().unwrap())).unwrap();
+        let collector = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0; 4096];
+            // The headers, then as many bytes as their content length
+            let complete = |request: &[u8]| {
+                let text = String::from_utf8_lossy(request);
+                let Some((headers, body)) = text.split_once("\r\n\r\n") else {
+                    return false;
+                };
+                let length = headers
+                    .lines()
+                    .find_map(|line| {
+                        line.to_lowercase()
+                            .strip_prefix("content-length: ")
+                            .map(str::to_string)
+                    })
+                    .map(|length| length.parse::<usize>().unwrap())
+                    .unwrap_or(0);
+                body.len() >= length
+            };
+            while !complete(&request) {
+                let read = socket.read(&mut buffer).await.unwrap();
+                request.extend_from_slice(&buffer[..read]);
+            }
+            socket
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                .await
+                .unwrap();
+            String::from_utf8(request).unwrap()
+        });
+
+        let exporter = OtlpExporter::new(&endpoint).unwrap();
+        tracing::subscriber::with_default(Arc::clone(&exporter.collector), || {
+            info_span!("zone.write", part = 1).in_scope(|| {});
+        });
+        assert_eq!(exporter.export().await.unwrap(), 1);
+
+        let request = collector.await.unwrap();
+        assert!(request.starts_with("POST /v1/traces HTTP/1.1"), "{request}");
+        let (_, body) = request.split_once("\r\n\r\n").unwrap();
+        let body: Value = serde_json::from_str(body).unwrap();
+        let spans = spans_by_name(&body);
+        assert_eq!(
+            attribute(&spans["zone.write"], "part"),
+            json!({ "intValue": "1" })
+        );
+        // Nothing is left to send
+        assert_eq!(exporter.export().await.unwrap(), 0);
+    }
+}
@@ -0,0 +1,263 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Checks of the output directory run before any generation work
+//!
+//! A directory that can't be created or written to would otherwise only
+//! fail once the first file is written, possibly after a long source scan,
+//! and outputs of an earlier run with different settings would silently
+//! end up mixed with the new ones.
+
+use log::debug;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::zone::{self, ZoneDfArgs};
+
+/// File written and removed again to check that the directory is writable
+pub const PROBE_FILE_NAME: &str = ".spatialbench-write-probe";
+
+/// What to do with outputs of an earlier run that differs from this one
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ExistingOutputs {
+    /// Stop before generating anything
+    #[default]
+    Refuse,
+    /// Delete the earlier outputs recorded in the manifest
+    Overwrite,
+    /// Keep the earlier outputs next to the new ones
+    AllowMixed,
+}
+
+impl ExistingOutputs {
+    pub fn from_flags(overwrite: bool, allow_mixed_outputs: bool) -> Self {
+        match (overwrite, allow_mixed_outputs) {
+            (true, _) => Self::Overwrite,
+            (false, true) => Self::AllowMixed,
+            (false, false) => Self::Refuse,
+        }
+    }
+}
+
+/// Creates `output_dir`, checks that files can be written into it and
+/// compares it with the outputs of an earlier run.
+///
+/// `zone` describes the zone table when it is generated; otherwise only
+/// the scale factor of the earlier run is compared.
+pub fn prepare_output_dir(
+    output_dir: &Path,
+    scale_factor: f64,
+    zone: Option<&ZoneDfArgs>,
+    existing: ExistingOutputs,
+) -> io::Result<()> {
+    fs::create_dir_all(output_dir).map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!(
+                "Can't create output directory {}: {e}",
+                output_dir.display()
+            ),
+        )
+    })?;
+
+    let probe = output_dir.join(PROBE_FILE_NAME);
+    fs::write(&probe, b"")
+        .and_then(|()| fs::remove_file(&probe))
+        .map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!(
+                    "Output directory {} is not writable: {e}",
+                    output_dir.display()
+                ),
+            )
+        })?;
+    debug!("Output directory {} is writable", output_dir.display());
+
+    zone::main::check_existing_outputs(output_dir, scale_factor, zone, existing)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zone::ThemeInput;
+    use parquet::basic::Compression;
+    use std::path::PathBuf;
+
+    /// Writes the manifest of an earlier zone run listing one part file
+    fn earlier_run(dir: &Path, scale_factor: f64, seed: u64, source: &str) -> PathBuf {
+        fs::create_dir_all(dir.join("zone")).unwrap();
+        let part = dir.join("zone").join("zone.1.parquet");
+        fs::write(&part, b"").unwrap();
+        fs::write(
+            dir.join("zone.manifest.json"),
+            serde_json::json!({
+                "table": "zone",
+                "scale_factor": scale_factor,
+                "parts": 1,
+                "source": source,
+                "seed": seed,
+                "files": [{
+                    "part": 1,
+                    "path": "zone/zone.1.parquet",
+                    "rows": 1,
+                    "content_sha256": "0".repeat(64),
+                }],
+            })
+            .to_string(),
+        )
+        .unwrap();
+        part
+    }
+
+    fn zone_args(dir: &Path, seed: u64, source: &str) -> ZoneDfArgs {
+        let mut args = ZoneDfArgs::new(
+            1.0,
+            dir.to_path_buf(),
+            None,
+            None,
+            None,
+            1024 * 1024,
+            Compression::SNAPPY,
+        )
+        .with_themes(ThemeInput::parse_list(&format!("division_area={source}")).unwrap());
+        args.transform.seed = seed;
+        args
+    }
+
+    fn refusal(result: io::Result<()>) -> String {
+        let e = result.unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::AlreadyExists);
+        e.to_string()
+    }
+
+    #[test]
+    fn test_creates_missing_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        let output_dir = dir.path().join("a").join("b");
+        prepare_output_dir(&output_dir, 1.0, None, ExistingOutputs::Refuse).unwrap();
+        assert!(output_dir.is_dir());
+        assert!(!output_dir.join(PROBE_FILE_NAME).exists());
+    }
+
+    #[test]
+    fn test_refuses_uncreatable_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("file");
+        fs::write(&file, b"").unwrap();
+
+        let e =
+            prepare_output_dir(&file.join("out"), 1.0, None, ExistingOutputs::Refuse).unwrap_err();
+        assert!(
+            e.to_string().starts_with(&format!(
+                "Can't create output directory {}",
+                file.join("out").display()
+            )),
+            "{e}"
+        );
+    }
+
+    #[test]
+    fn test_refuses_different_scale_factor() {
+        let dir = tempfile::tempdir().unwrap();
+        earlier_run(dir.path(), 1.0, 0, "a.parquet");
+
+        let e = refusal(prepare_output_dir(
+            dir.path(),
+            10.0,
+            None,
+            ExistingOutputs::Refuse,
+        ));
+        assert!(
+            e.contains("scale factor: 1 in the existing outputs, 10 requested"),
+            "{e}"
+        );
+        assert!(e.contains("--overwrite"), "{e}");
+        assert!(e.contains("--allow-mixed-outputs"), "{e}");
+
+        // Zone scale factors below 1 are generated as 1
+        prepare_output_dir(dir.path(), 0.5, None, ExistingOutputs::Refuse).unwrap();
+    }
+
+    #[test]
+    fn test_refuses_different_seed() {
+        let dir = tempfile::tempdir().unwrap();
+        earlier_run(dir.path(), 1.0, 0, "division_area=a.parquet");
+
+        let args = zone_args(dir.path(), 42, "a.parquet");
+        let e = refusal(prepare_output_dir(
+            dir.path(),
+            1.0,
+            Some(&args),
+            ExistingOutputs::Refuse,
+        ));
+        assert!(
+            e.contains("seed: 0 in the existing outputs, 42 requested"),
+            "{e}"
+        );
+        assert!(!e.contains("scale factor"), "{e}");
+        assert!(!e.contains("source"), "{e}");
+
+        // The seed only matters when generating the zone table
+        prepare_output_dir(dir.path(), 1.0, None, ExistingOutputs::Refuse).unwrap();
+    }
+
+    #[test]
+    fn test_refuses_different_source() {
+        let dir = tempfile::tempdir().unwrap();
+        earlier_run(dir.path(), 1.0, 0, "division_area=a.parquet");
+
+        let args = zone_args(dir.path(), 0, "b.parquet");
+        let e = refusal(prepare_output_dir(
+            dir.path(),
+            1.0,
+            Some(&args),
+            ExistingOutputs::Refuse,
+        ));
+        assert!(
+            e.contains(
+                "source: division_area=a.parquet in the existing outputs, \
+                 division_area=b.parquet requested"
+            ),
+            "{e}"
+        );
+
+        let args = zone_args(dir.path(), 0, "a.parquet");
+        prepare_output_dir(dir.path(), 1.0, Some(&args), ExistingOutputs::Refuse).unwrap();
+    }
+
+    #[test]
+    fn test_allow_mixed_keeps_earlier_outputs() {
+        let dir = tempfile::tempdir().unwrap();
+        let part = earlier_run(dir.path(), 1.0, 0, "a.parquet");
+
+        prepare_output_dir(dir.path(), 10.0, None, ExistingOutputs::AllowMixed).unwrap();
+        assert!(part.exists());
+        assert!(dir.path().join("zone.manifest.json").exists());
+    }
+
+    #[test]
+    fn test_overwrite_removes_earlier_outputs() {
+        let dir = tempfile::tempdir().unwrap();
+        let part = earlier_run(dir.path(), 1.0, 0, "a.parquet");
+
+        prepare_output_dir(dir.path(), 10.0, None, ExistingOutputs::Overwrite).unwrap();
+        assert!(!part.exists());
+        assert!(!dir.path().join("zone.manifest.json").exists());
+    }
+}
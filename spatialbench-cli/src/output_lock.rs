@@ -0,0 +1,385 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Advisory lock on an output directory
+//!
+//! A run writing files holds an OS file lock on
+//! `<output_dir>/.spatialbench.lock`, which records the pid and host of the
+//! run and a hash of its configuration. A run generating whole tables holds
+//! the lock exclusively. `--part` workers hold it shared, so workers of one
+//! configuration write their parts side by side while a run with another
+//! configuration is refused.
+//!
+//! The OS drops the lock when the process exits, however it ends, so the
+//! file left behind by a crashed run doesn't block the next one. The last
+//! holder to release the lock removes the file.
+
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::ffi::OsStr;
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+/// Name of the lock file in the output directory
+pub const LOCK_FILE_NAME: &str = ".spatialbench.lock";
+
+/// Attempts to take the lock while other holders briefly hold it to
+/// record themselves
+const ATTEMPTS: usize = 100;
+
+const RETRY_DELAY: Duration = Duration::from_millis(20);
+
+/// How a run holds the lock
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LockMode {
+    /// The only run writing to the directory
+    Exclusive,
+    /// One of the `--part` workers of a configuration
+    Shared,
+}
+
+/// The run recorded in the lock file
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockHolder {
+    pub pid: u32,
+    pub host: String,
+    pub config_hash: String,
+    pub mode: LockMode,
+}
+
+impl LockHolder {
+    /// This process, running the configuration hashed to `config_hash`
+    pub fn current(config_hash: impl Into<String>, mode: LockMode) -> Self {
+        Self {
+            pid: std::process::id(),
+            host: hostname(),
+            config_hash: config_hash.into(),
+            mode,
+        }
+    }
+}
+
+/// Hex SHA-256 of the command line `args`, leaving out `--part` and
+/// `--force-unlock` so the workers of one run hash alike
+pub fn config_hash<I, S>(args: I) -> String
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let mut digest = Sha256::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let arg = arg.as_ref();
+        match arg.to_str() {
+            Some("--part") => {
+                args.next();
+            }
+            Some(arg) if arg.starts_with("--part=") || arg == "--force-unlock" => {}
+            _ => {
+                digest.update(arg.as_encoded_bytes());
+                digest.update([0]);
+            }
+        }
+    }
+    digest
+        .finalize()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// A held lock on an output directory, released when dropped
+#[derive(Debug)]
+pub struct OutputLock {
+    file: File,
+    path: PathBuf,
+}
+
+impl OutputLock {
+    /// Locks `output_dir` for `holder`, creating the directory if needed.
+    ///
+    /// Fails with [`io::ErrorKind::ResourceBusy`] when a live run holds the
+    /// lock exclusively, or holds it with another configuration. With
+    /// `force_unlock` the lock file of such a run, for example a hung one,
+    /// is removed first.
+    pub fn acquire(output_dir: &Path, holder: &LockHolder, force_unlock: bool) -> io::Result<Self> {
+        fs::create_dir_all(output_dir).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!(
+                    "Can't create output directory {}: {e}",
+                    output_dir.display()
+                ),
+            )
+        })?;
+        let path = output_dir.join(LOCK_FILE_NAME);
+        if force_unlock {
+            match fs::remove_file(&path) {
+                Ok(()) => warn!("Removed the lock file {}", path.display()),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        for _ in 0..ATTEMPTS {
+            let file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(&path)?;
+
+            if try_lock(file.try_lock())? {
+                // A releasing holder may have removed the file in between
+                if !is_current(&file, &path)? {
+                    continue;
+                }
+                write_holder(&file, holder)?;
+                if holder.mode == LockMode::Exclusive {
+                    debug!("Locked {} exclusively", output_dir.display());
+                    return Ok(Self { file, path });
+                }
+                // Shared holders check the recorded configuration below, as
+                // another worker may have recorded itself in between
+                file.unlock()?;
+            }
+
+            if holder.mode == LockMode::Shared && try_lock(file.try_lock_shared())? {
+                if !is_current(&file, &path)? {
+                    continue;
+                }
+                match read_holder(&file)? {
+                    Some(other)
+                        if other.mode == LockMode::Shared
+                            && other.config_hash == holder.config_hash =>
+                    {
+                        debug!("Sharing the lock on {}", output_dir.display());
+                        return Ok(Self { file, path });
+                    }
+                    Some(other) => return Err(conflict(output_dir, holder, &other)),
+                    None => continue,
+                }
+            }
+
+            // Held exclusively, by another run or by a worker recording
+            // itself
+            if let Some(other) = read_holder(&file)? {
+                if holder.mode == LockMode::Exclusive
+                    || other.mode == LockMode::Exclusive
+                    || other.config_hash != holder.config_hash
+                {
+                    return Err(conflict(output_dir, holder, &other));
+                }
+            }
+            thread::sleep(RETRY_DELAY);
+        }
+        Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            format!("Timed out waiting for the lock on {}", output_dir.display()),
+        ))
+    }
+
+    /// Path of the lock file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for OutputLock {
+    fn drop(&mut self) {
+        // Only the last holder gets the lock back exclusively and removes the
+        // file; other workers keep holding it
+        let _ = self.file.unlock();
+        if matches!(self.file.try_lock(), Ok(()))
+            && is_current(&self.file, &self.path).unwrap_or(false)
+        {
+            let _ = self.file.set_len(0);
+            if let Err(e) = fs::remove_file(&self.path) {
+                warn!(
+                    "Failed to remove the lock file {}: {e}",
+                    self.path.display()
+                );
+            }
+        }
+    }
+}
+
+/// Whether a `try_lock` call got the lock
+fn try_lock(result: Result<(), TryLockError>) -> io::Result<bool> {
+    match result {
+        Ok(()) => Ok(true),
+        Err(TryLockError::WouldBlock) => Ok(false),
+        Err(TryLockError::Error(e)) => Err(e),
+    }
+}
+
+fn conflict(output_dir: &Path, holder: &LockHolder, other: &LockHolder) -> io::Error {
+    let reason = if other.config_hash != holder.config_hash {
+        "with a different configuration"
+    } else {
+        "with the same configuration; only --part workers can share an output directory"
+    };
+    io::Error::new(
+        io::ErrorKind::ResourceBusy,
+        format!(
+            "Output directory {} is locked by pid {} on {} {reason}. Wait for that run to \
+             finish, or pass --force-unlock if it is stuck",
+            output_dir.display(),
+            other.pid,
+            other.host,
+        ),
+    )
+}
+
+fn write_holder(mut file: &File, holder: &LockHolder) -> io::Result<()> {
+    file.set_len(0)?;
+    file.seek(SeekFrom::Start(0))?;
+    file.write_all(&serde_json::to_vec(holder)?)?;
+    file.sync_data()
+}
+
+/// The holder recorded in `file`, or `None` while it is being written
+fn read_holder(mut file: &File) -> io::Result<Option<LockHolder>> {
+    let mut contents = String::new();
+    file.seek(SeekFrom::Start(0))?;
+    file.read_to_string(&mut contents)?;
+    Ok(serde_json::from_str(&contents).ok())
+}
+
+/// Whether `file` is still the file at `path`
+#[cfg(unix)]
+pub(crate) fn is_current(file: &File, path: &Path) -> io::Result<bool> {
+    use std::os::unix::fs::MetadataExt;
+    let open = file.metadata()?;
+    match fs::metadata(path) {
+        Ok(linked) => Ok(open.dev() == linked.dev() && open.ino() == linked.ino()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn is_current(_file: &File, path: &Path) -> io::Result<bool> {
+    Ok(path.exists())
+}
+
+fn hostname() -> String {
+    std::env::var("HOSTNAME")
+        .or_else(|_| std::env::var("COMPUTERNAME"))
+        .ok()
+        .or_else(|| fs::read_to_string("/etc/hostname").ok())
+        .map(|host| host.trim().to_string())
+        .filter(|host| !host.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn busy(result: io::Result<OutputLock>) -> String {
+        let e = result.unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::ResourceBusy);
+        e.to_string()
+    }
+
+    #[test]
+    fn test_exclusive_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        let run = LockHolder::current("a", LockMode::Exclusive);
+        let lock = OutputLock::acquire(dir.path(), &run, false).unwrap();
+        let recorded: LockHolder = serde_json::from_slice(&fs::read(lock.path()).unwrap()).unwrap();
+        assert_eq!(recorded, run);
+
+        let e = busy(OutputLock::acquire(dir.path(), &run, false));
+        assert!(e.contains("with the same configuration"), "{e}");
+        let other = LockHolder::current("b", LockMode::Exclusive);
+        let e = busy(OutputLock::acquire(dir.path(), &other, false));
+        assert!(
+            e.contains(&format!("locked by pid {}", std::process::id())),
+            "{e}"
+        );
+        assert!(e.contains("with a different configuration"), "{e}");
+        let worker = LockHolder::current("a", LockMode::Shared);
+        busy(OutputLock::acquire(dir.path(), &worker, false));
+
+        drop(lock);
+        assert!(!dir.path().join(LOCK_FILE_NAME).exists());
+        OutputLock::acquire(dir.path(), &other, false).unwrap();
+    }
+
+    #[test]
+    fn test_workers_share_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        let worker = LockHolder::current("a", LockMode::Shared);
+        let first = OutputLock::acquire(dir.path(), &worker, false).unwrap();
+        let second = OutputLock::acquire(dir.path(), &worker, false).unwrap();
+
+        let other = LockHolder::current("b", LockMode::Shared);
+        let e = busy(OutputLock::acquire(dir.path(), &other, false));
+        assert!(e.contains("with a different configuration"), "{e}");
+        let run = LockHolder::current("a", LockMode::Exclusive);
+        busy(OutputLock::acquire(dir.path(), &run, false));
+
+        // The file stays until the last worker is done
+        drop(first);
+        assert!(second.path().exists());
+        busy(OutputLock::acquire(dir.path(), &other, false));
+        drop(second);
+        assert!(!dir.path().join(LOCK_FILE_NAME).exists());
+        OutputLock::acquire(dir.path(), &other, false).unwrap();
+    }
+
+    #[test]
+    fn test_stale_and_forced_locks() {
+        let dir = tempfile::tempdir().unwrap();
+        // Left behind by a crashed run, which no longer holds the OS lock
+        let crashed = LockHolder::current("a", LockMode::Exclusive);
+        fs::write(
+            dir.path().join(LOCK_FILE_NAME),
+            serde_json::to_vec(&crashed).unwrap(),
+        )
+        .unwrap();
+        let stuck = OutputLock::acquire(dir.path(), &crashed, false).unwrap();
+
+        let run = LockHolder::current("b", LockMode::Exclusive);
+        busy(OutputLock::acquire(dir.path(), &run, false));
+        let forced = OutputLock::acquire(dir.path(), &run, true).unwrap();
+
+        // Releasing the replaced lock leaves the new lock file alone
+        drop(stuck);
+        let recorded: LockHolder =
+            serde_json::from_slice(&fs::read(forced.path()).unwrap()).unwrap();
+        assert_eq!(recorded, run);
+    }
+
+    #[test]
+    fn test_config_hash_ignores_part() {
+        let hash = |args: &[&str]| config_hash(args);
+        let base = hash(&["-s", "1", "--parts", "4"]);
+        assert_eq!(base, hash(&["-s", "1", "--parts", "4", "--part", "2"]));
+        assert_eq!(base, hash(&["-s", "1", "--part=3", "--parts", "4"]));
+        assert_eq!(base, hash(&["-s", "1", "--parts", "4", "--force-unlock"]));
+        assert_ne!(base, hash(&["-s", "10", "--parts", "4"]));
+        assert_ne!(base, hash(&["-s", "1", "--parts", "8"]));
+    }
+}
@@ -19,13 +19,20 @@
 //! * [`OutputPlan`]: an output file that will be generated
 //! * [`OutputPlanGenerator`]: plans the output files to be generated
 
-use crate::plan::GenerationPlan;
+use crate::plan::{GenerationPlan, OutputSize};
 use crate::{OutputFormat, Table};
-use log::debug;
+use log::{debug, info};
 use parquet::basic::Compression;
-use std::collections::HashSet;
+use parquet::file::metadata::KeyValue;
+use spatialbench::dates::format_generated_date;
+use spatialbench::generators::TripGenerator;
+use spatialbench::spatial::PointDistribution;
+use spatialbench_cli::avro::AvroCodec;
+use spatialbench_cli::generation_params::{GenerationParams, GENERATION_PARAMS_KEY};
+use std::collections::{BTreeMap, HashSet};
 use std::fmt::{Display, Formatter};
 use std::io;
+use std::ops::Range;
 use std::path::PathBuf;
 
 /// Where a partition will be output
@@ -63,10 +70,20 @@ pub struct OutputPlan {
     output_format: OutputFormat,
     /// If the output is parquet, what compression level to use
     parquet_compression: Compression,
+    /// If the output is avro, how to compress its blocks
+    avro_codec: AvroCodec,
     /// Where to output
     output_location: OutputLocation,
     /// Plan for generating the table
     generation_plan: GenerationPlan,
+    /// For the trip table, the generated pickup dates to output
+    pickup_dates: Option<Range<i32>>,
+    /// For the trip table, the `--point-distribution` of the pickups
+    point_distribution: Option<PointDistribution>,
+    /// Total row count replacing the one derived from the scale factor
+    row_count: Option<i64>,
+    /// Parameters recorded in the Parquet footer, with `--embed-params`
+    generation_params: Option<GenerationParams>,
 }
 
 impl OutputPlan {
@@ -83,11 +100,50 @@ impl OutputPlan {
             scale_factor,
             output_format,
             parquet_compression,
+            avro_codec: AvroCodec::default(),
             output_location,
             generation_plan,
+            pickup_dates: None,
+            point_distribution: None,
+            row_count: None,
+            generation_params: None,
         }
     }
 
+    /// Compress the blocks of Avro output with `avro_codec`
+    pub fn with_avro_codec(mut self, avro_codec: AvroCodec) -> Self {
+        self.avro_codec = avro_codec;
+        self
+    }
+
+    /// Only output the trips picked up on these generated dates
+    pub fn with_pickup_dates(mut self, pickup_dates: Option<Range<i32>>) -> Self {
+        self.pickup_dates = pickup_dates;
+        self
+    }
+
+    /// Record the distribution the trip pickups were generated with
+    pub fn with_point_distribution(
+        mut self,
+        point_distribution: Option<PointDistribution>,
+    ) -> Self {
+        self.point_distribution = point_distribution;
+        self
+    }
+
+    /// Generate `row_count` rows in total instead of the number derived
+    /// from the scale factor
+    pub fn with_row_count(mut self, row_count: Option<i64>) -> Self {
+        self.row_count = row_count;
+        self
+    }
+
+    /// Record `generation_params` in the Parquet footer
+    pub fn with_generation_params(mut self, generation_params: Option<GenerationParams>) -> Self {
+        self.generation_params = generation_params;
+        self
+    }
+
     /// Return the table this partition is for
     pub fn table(&self) -> Table {
         self.table
@@ -113,6 +169,11 @@ impl OutputPlan {
         self.parquet_compression
     }
 
+    /// Return the Avro block compression for this partition
+    pub fn avro_codec(&self) -> AvroCodec {
+        self.avro_codec
+    }
+
     /// Return the number of chunks part(ition) count (the number of data chunks
     /// in the underlying generation plan)
     pub fn chunk_count(&self) -> usize {
@@ -123,6 +184,33 @@ impl OutputPlan {
     pub fn generation_plan(&self) -> &GenerationPlan {
         &self.generation_plan
     }
+
+    /// Return the trip pickup dates to output, if limited
+    pub fn pickup_dates(&self) -> Option<&Range<i32>> {
+        self.pickup_dates.as_ref()
+    }
+
+    /// Return the total row count of the table, if overridden
+    pub fn row_count(&self) -> Option<i64> {
+        self.row_count
+    }
+
+    /// Key-value metadata for the Parquet footer: the
+    /// `spatialbench.point_distribution` of a trip file, if chosen, and the
+    /// generation parameters, if embedded
+    pub fn parquet_metadata(&self) -> Vec<KeyValue> {
+        let distribution = self.point_distribution.map(|distribution| {
+            KeyValue::new(
+                "spatialbench.point_distribution".to_string(),
+                distribution.name().to_string(),
+            )
+        });
+        let params = self
+            .generation_params
+            .as_ref()
+            .map(|params| KeyValue::new(GENERATION_PARAMS_KEY.to_string(), params.to_json()));
+        distribution.into_iter().chain(params).collect()
+    }
 }
 
 impl Display for OutputPlan {
@@ -144,8 +232,17 @@ pub struct OutputPlanGenerator {
     scale_factor: f64,
     parquet_compression: Compression,
     parquet_row_group_bytes: i64,
+    avro_codec: AvroCodec,
     stdout: bool,
     output_dir: PathBuf,
+    /// Generated pickup dates to limit the trip table to
+    trip_pickup_dates: Option<Range<i32>>,
+    /// Distribution the trip pickups are generated with, if chosen
+    trip_point_distribution: Option<PointDistribution>,
+    /// Row counts replacing those derived from the scale factor
+    row_counts: BTreeMap<Table, i64>,
+    /// Parameters recorded in every Parquet footer, with `--embed-params`
+    generation_params: Option<GenerationParams>,
     /// The generated output plans
     output_plans: Vec<OutputPlan>,
     /// Output directories that have been created so far
@@ -167,13 +264,53 @@ impl OutputPlanGenerator {
             scale_factor,
             parquet_compression,
             parquet_row_group_bytes,
+            avro_codec: AvroCodec::default(),
             stdout,
             output_dir,
+            trip_pickup_dates: None,
+            trip_point_distribution: None,
+            row_counts: BTreeMap::new(),
+            generation_params: None,
             output_plans: Vec::new(),
             created_directories: HashSet::new(),
         }
     }
 
+    /// Compress the blocks of Avro files with `avro_codec`
+    pub fn with_avro_codec(mut self, avro_codec: AvroCodec) -> Self {
+        self.avro_codec = avro_codec;
+        self
+    }
+
+    /// Only generate the trips picked up on these generated dates, written
+    /// below `trip/date={start}/`
+    pub fn with_trip_pickup_dates(mut self, pickup_dates: Option<Range<i32>>) -> Self {
+        self.trip_pickup_dates = pickup_dates;
+        self
+    }
+
+    /// Record `point_distribution` in the trip Parquet files
+    pub fn with_trip_point_distribution(
+        mut self,
+        point_distribution: Option<PointDistribution>,
+    ) -> Self {
+        self.trip_point_distribution = point_distribution;
+        self
+    }
+
+    /// Generate the given number of rows of each table instead of the
+    /// number derived from the scale factor
+    pub fn with_row_counts(mut self, row_counts: BTreeMap<Table, i64>) -> Self {
+        self.row_counts = row_counts;
+        self
+    }
+
+    /// Record `generation_params` in the footer of every Parquet file
+    pub fn with_generation_params(mut self, generation_params: Option<GenerationParams>) -> Self {
+        self.generation_params = generation_params;
+        self
+    }
+
     /// Generate the output plans for the given table and partition options
     pub fn generate_plans(
         &mut self,
@@ -182,6 +319,14 @@ impl OutputPlanGenerator {
         cli_part_count: Option<i32>,
         output_file_size_mb: Option<f32>,
     ) -> io::Result<()> {
+        if let Some(row_count) = self.row_counts.get(&table) {
+            info!(
+                "--rows {table}={row_count} overrides the {} rows of scale factor {}",
+                OutputSize::row_count_for_table(table, self.scale_factor),
+                self.scale_factor
+            );
+        }
+
         // Calculate part_count from output_file_size_mb if specified
         let calculated_part_count = if let Some(max_size_mb) = output_file_size_mb {
             Some(self.calculate_parts_from_file_size(table, max_size_mb))
@@ -210,14 +355,13 @@ impl OutputPlanGenerator {
 
     /// Calculate the number of parts needed to approximate output file size
     fn calculate_parts_from_file_size(&self, table: Table, max_size_mb: f32) -> i32 {
-        use crate::plan::OutputSize;
-
         let output_size = OutputSize::new(
             table,
             self.scale_factor,
             self.format,
             self.parquet_row_group_bytes,
-        );
+        )
+        .with_row_count(self.row_counts.get(&table).copied());
 
         let total_size_bytes = output_size.total_size_bytes();
         let output_file_size_mb = max_size_mb * (1024 * 1024) as f32;
@@ -240,17 +384,39 @@ impl OutputPlanGenerator {
         cli_part: Option<i32>,
         cli_part_count: Option<i32>,
     ) -> io::Result<()> {
+        let row_count = self.row_counts.get(&table).copied();
+        let (pickup_dates, point_distribution) = match table {
+            Table::Trip => (self.trip_pickup_dates.clone(), self.trip_point_distribution),
+            _ => (None, None),
+        };
+        // The trips of a window are a contiguous range of keys, which the
+        // parts split among themselves
+        let planned_rows = match &pickup_dates {
+            Some(pickup_dates) => {
+                let total_rows = row_count
+                    .unwrap_or_else(|| OutputSize::row_count_for_table(table, self.scale_factor));
+                let rows = TripGenerator::pickup_date_rows(total_rows, pickup_dates);
+                info!(
+                    "Trips picked up in --time-range have keys {}..={}",
+                    rows.start + 1,
+                    rows.end
+                );
+                Some(rows.end - rows.start)
+            }
+            None => row_count,
+        };
         let generation_plan = GenerationPlan::try_new(
             table,
             self.format,
             self.scale_factor,
+            planned_rows,
             cli_part,
             cli_part_count,
             self.parquet_row_group_bytes,
         )
         .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
 
-        let output_location = self.output_location(table, cli_part)?;
+        let output_location = self.output_location(table, cli_part, pickup_dates.as_ref())?;
 
         let plan = OutputPlan::new(
             table,
@@ -259,7 +425,12 @@ impl OutputPlanGenerator {
             self.parquet_compression,
             output_location,
             generation_plan,
-        );
+        )
+        .with_avro_codec(self.avro_codec)
+        .with_pickup_dates(pickup_dates)
+        .with_point_distribution(point_distribution)
+        .with_row_count(row_count)
+        .with_generation_params(self.generation_params.clone());
 
         self.output_plans.push(plan);
         Ok(())
@@ -272,18 +443,34 @@ impl OutputPlanGenerator {
     /// * if part is Some(part), then the output location
     ///   will be `{output_dir}/{table}/{table}table.{part}.{extension}`
     ///   (e.g. orders/orders.1.tbl, orders/orders.2.tbl, etc.)
-    fn output_location(&mut self, table: Table, part: Option<i32>) -> io::Result<OutputLocation> {
+    ///
+    /// * with `pickup_dates`, the files are written to
+    ///   `{output_dir}/{table}/date={start}/` instead (e.g.
+    ///   trip/date=1994-01-01/trip.1.tbl)
+    fn output_location(
+        &mut self,
+        table: Table,
+        part: Option<i32>,
+        pickup_dates: Option<&Range<i32>>,
+    ) -> io::Result<OutputLocation> {
         if self.stdout {
             Ok(OutputLocation::Stdout)
         } else {
-            let extension = match self.format {
-                OutputFormat::Tbl => "tbl",
-                OutputFormat::Csv => "csv",
-                OutputFormat::Parquet => "parquet",
-            };
+            let extension = self.format.extension();
 
             let mut output_path = self.output_dir.clone();
-            if let Some(part) = part {
+            if let Some(pickup_dates) = pickup_dates {
+                output_path.push(table.to_string());
+                output_path.push(format!(
+                    "date={}",
+                    format_generated_date(pickup_dates.start)
+                ));
+                self.ensure_directory_exists(&output_path)?;
+                match part {
+                    Some(part) => output_path.push(format!("{table}.{part}.{extension}")),
+                    None => output_path.push(format!("{table}.{extension}")),
+                }
+            } else if let Some(part) = part {
                 // If a partition is specified, create a subdirectory for it
                 output_path.push(table.to_string());
                 self.ensure_directory_exists(&output_path)?;
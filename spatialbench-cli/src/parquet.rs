@@ -24,12 +24,15 @@ use log::debug;
 use parquet::arrow::arrow_writer::{compute_leaves, get_column_writers, ArrowColumnChunk};
 use parquet::arrow::ArrowSchemaConverter;
 use parquet::basic::Compression;
+use parquet::file::metadata::KeyValue;
 use parquet::file::properties::WriterProperties;
 use parquet::file::writer::SerializedFileWriter;
 use parquet::schema::types::SchemaDescPtr;
 use spatialbench_arrow::RecordBatchIterator;
 use std::io;
 use std::io::Write;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
 use std::sync::Arc;
 use tokio::sync::mpsc::{Receiver, Sender};
 
@@ -44,11 +47,14 @@ pub trait IntoSize {
 ///
 /// Note the input is an iterator of [`RecordBatchIterator`]; The batches
 /// produced by each iterator is encoded as its own row group.
+///
+/// `key_value_metadata` is added to the file footer.
 pub async fn generate_parquet<W: Write + Send + IntoSize + 'static, I>(
     writer: W,
     iter_iter: I,
     num_threads: usize,
     parquet_compression: Compression,
+    key_value_metadata: Vec<KeyValue>,
 ) -> Result<(), io::Error>
 where
     I: Iterator<Item: RecordBatchIterator> + 'static,
@@ -68,6 +74,7 @@ where
     // Compute the parquet schema
     let writer_properties = WriterProperties::builder()
         .set_compression(parquet_compression)
+        .set_key_value_metadata((!key_value_metadata.is_empty()).then_some(key_value_metadata))
         .build();
     let writer_properties = Arc::new(writer_properties);
     let parquet_schema = Arc::new(
@@ -177,3 +184,136 @@ where
         .map(|col_writer| col_writer.close().unwrap())
         .collect()
 }
+
+/// Codecs accepted by `--parquet-compression` and the levels each takes,
+/// as accepted by the parquet crate
+const CODECS: &[(&str, Option<RangeInclusive<u32>>)] = &[
+    ("UNCOMPRESSED", None),
+    ("SNAPPY", None),
+    ("GZIP", Some(0..=9)),
+    ("LZO", None),
+    ("BROTLI", Some(0..=11)),
+    ("LZ4", None),
+    ("ZSTD", Some(1..=22)),
+    ("LZ4_RAW", None),
+];
+
+/// Parses `--parquet-compression`, e.g. `SNAPPY` or `ZSTD(1)`.
+///
+/// The level is checked against the range of the codec, and a codec missing
+/// from this build is reported here rather than failing the writer later.
+pub fn parse_compression(spec: &str) -> Result<Compression, String> {
+    let (codec, level) = match spec.trim().split_once('(') {
+        Some((codec, level)) => {
+            let level = level
+                .strip_suffix(')')
+                .and_then(|level| level.trim().parse::<u32>().ok())
+                .ok_or_else(|| format!("invalid compression {spec:?}, expected e.g. ZSTD(1)"))?;
+            (codec.trim().to_uppercase(), Some(level))
+        }
+        None => (spec.trim().to_uppercase(), None),
+    };
+    let Some((name, levels)) = CODECS.iter().find(|(name, _)| *name == codec) else {
+        let names: Vec<_> = CODECS.iter().map(|(name, _)| *name).collect();
+        return Err(format!(
+            "unknown compression {codec}; expected one of {}",
+            names.join(", ")
+        ));
+    };
+    match (levels, level) {
+        (None, Some(_)) => return Err(format!("{name} takes no level")),
+        (Some(levels), None) => {
+            return Err(format!(
+                "{name} needs a level in {}..={}, e.g. {name}({})",
+                levels.start(),
+                levels.end(),
+                levels.start()
+            ))
+        }
+        (Some(levels), Some(level)) if level < *levels.start() => {
+            return Err(format!(
+                "{name} level {level} is below min {}",
+                levels.start()
+            ))
+        }
+        (Some(levels), Some(level)) if level > *levels.end() => {
+            return Err(format!("{name} level {level} exceeds max {}", levels.end()))
+        }
+        _ => {}
+    }
+
+    let compression = Compression::from_str(spec.trim()).map_err(|e| e.to_string())?;
+    if !codec_available(compression) {
+        return Err(format!(
+            "{name} is not available in this build; available codecs: {}",
+            available_codecs().join(", ")
+        ));
+    }
+    Ok(compression)
+}
+
+/// Whether this build can write `compression`.
+///
+/// The parquet dependency is built with its default features, which bring
+/// in every codec but LZO; the parquet crate has no LZO implementation.
+fn codec_available(compression: Compression) -> bool {
+    !matches!(compression, Compression::LZO)
+}
+
+/// The codecs of [`CODECS`] that this build can write, at their lowest level
+fn available_codecs() -> Vec<String> {
+    CODECS
+        .iter()
+        .map(|(name, levels)| match levels {
+            Some(levels) => format!("{name}({})", levels.start()),
+            None => name.to_string(),
+        })
+        .filter(|spec| Compression::from_str(spec).is_ok_and(codec_available))
+        .map(|spec| spec.split('(').next().unwrap().to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_compression() {
+        assert_eq!(parse_compression("SNAPPY").unwrap(), Compression::SNAPPY);
+        assert_eq!(
+            parse_compression("zstd(3)").unwrap(),
+            Compression::ZSTD(parquet::basic::ZstdLevel::try_new(3).unwrap())
+        );
+        assert_eq!(
+            parse_compression("gzip(9)").unwrap(),
+            Compression::GZIP(parquet::basic::GzipLevel::try_new(9).unwrap())
+        );
+
+        for (spec, message) in [
+            ("ZSTD(23)", "ZSTD level 23 exceeds max 22"),
+            ("ZSTD(0)", "ZSTD level 0 is below min 1"),
+            ("BROTLI(12)", "BROTLI level 12 exceeds max 11"),
+            ("ZSTD", "ZSTD needs a level in 1..=22, e.g. ZSTD(1)"),
+            ("SNAPPY(1)", "SNAPPY takes no level"),
+            (
+                "ZSTD(x)",
+                "invalid compression \"ZSTD(x)\", expected e.g. ZSTD(1)",
+            ),
+        ] {
+            assert_eq!(parse_compression(spec).unwrap_err(), message);
+        }
+        let err = parse_compression("BZIP2").unwrap_err();
+        assert!(err.starts_with("unknown compression BZIP2; expected one of UNCOMPRESSED"));
+    }
+
+    #[test]
+    fn test_parse_compression_rejects_unavailable_codec() {
+        // The parquet crate has no LZO codec
+        let err = parse_compression("LZO").unwrap_err();
+        assert!(
+            err.starts_with("LZO is not available in this build; available codecs: "),
+            "{err}"
+        );
+        assert!(!err.contains("LZO,") && err.contains("SNAPPY"), "{err}");
+    }
+}
@@ -81,6 +81,8 @@ impl GenerationPlan {
     /// Returns a GenerationPlan number of parts to generate
     ///
     /// # Arguments
+    /// * `row_count`: optional total row count replacing the one derived from
+    ///   the scale factor, `--rows` CLI argument
     /// * `cli_part`: optional part number to generate (1-based), `--part` CLI argument
     /// * `cli_part_count`: optional total number of parts, `--parts` CLI argument
     /// * `parquet_row_group_size`: optional parquet row group size, `--parquet-row-group-size` CLI argument
@@ -88,6 +90,7 @@ impl GenerationPlan {
         table: Table,
         format: OutputFormat,
         scale_factor: f64,
+        row_count: Option<i64>,
         cli_part: Option<i32>,
         cli_part_count: Option<i32>,
         parquet_row_group_bytes: i64,
@@ -108,13 +111,18 @@ impl GenerationPlan {
                 table,
                 format,
                 scale_factor,
+                row_count,
                 part,
                 part_count,
                 parquet_row_group_bytes,
             ),
-            (None, None) => {
-                Self::try_new_without_parts(table, format, scale_factor, parquet_row_group_bytes)
-            }
+            (None, None) => Self::try_new_without_parts(
+                table,
+                format,
+                scale_factor,
+                row_count,
+                parquet_row_group_bytes,
+            ),
         }
     }
 
@@ -131,6 +139,7 @@ impl GenerationPlan {
         table: Table,
         format: OutputFormat,
         scale_factor: f64,
+        row_count: Option<i64>,
         cli_part: i32,
         cli_part_count: i32,
         parquet_row_group_bytes: i64,
@@ -162,6 +171,7 @@ impl GenerationPlan {
         // scale down the row count by the number of partitions being generated
         // so that the output is consistent with the original part count
         let num_chunks = OutputSize::new(table, scale_factor, format, parquet_row_group_bytes)
+            .with_row_count(row_count)
             .with_scaled_row_count(cli_part_count)
             .part_count();
 
@@ -196,9 +206,11 @@ impl GenerationPlan {
         table: Table,
         format: OutputFormat,
         scale_factor: f64,
+        row_count: Option<i64>,
         parquet_row_group_bytes: i64,
     ) -> Result<Self, String> {
-        let output_size = OutputSize::new(table, scale_factor, format, parquet_row_group_bytes);
+        let output_size = OutputSize::new(table, scale_factor, format, parquet_row_group_bytes)
+            .with_row_count(row_count);
         let num_parts = output_size.part_count();
 
         Ok(Self {
@@ -270,7 +282,9 @@ impl OutputSize {
             // ```shell
             // datafusion-cli -c "datafusion-cli -c "select row_group_id, count(*), min(row_group_bytes)::float/min(row_group_num_rows)::float as bytes_per_row from parquet_metadata('zone.parquet') GROUP BY 1 ORDER BY 1""
             // ```
-            OutputFormat::Parquet => match table {
+            // ORC is only written for the zone table, sized like Parquet.
+            // Deflated Avro blocks come close to the Parquet sizes too
+            OutputFormat::Parquet | OutputFormat::Orc | OutputFormat::Avro => match table {
                 Table::Vehicle => 54,
                 Table::Driver => 84,
                 Table::Customer => 87,
@@ -294,13 +308,16 @@ impl OutputSize {
             // ensure small overages don't exceed the buffer size and require a
             // reallocation
             OutputFormat::Tbl | OutputFormat::Csv => 15 * 1024 * 1024,
-            OutputFormat::Parquet => parquet_row_group_bytes,
+            // Avro chunks are encoded in memory like Parquet row groups
+            OutputFormat::Parquet | OutputFormat::Orc | OutputFormat::Avro => {
+                parquet_row_group_bytes
+            }
         };
 
         // parquet files can have at most 32767 row groups so cap the number of parts at that number
         let max_part_count = match format {
-            OutputFormat::Tbl | OutputFormat::Csv => None,
-            OutputFormat::Parquet => Some(32767),
+            OutputFormat::Tbl | OutputFormat::Csv | OutputFormat::Avro => None,
+            OutputFormat::Parquet | OutputFormat::Orc => Some(32767),
         };
 
         debug!(
@@ -331,6 +348,16 @@ impl OutputSize {
         num_parts.try_into().unwrap()
     }
 
+    /// Replace the row count derived from the scale factor, if `row_count`
+    /// is given
+    pub fn with_row_count(mut self, row_count: Option<i64>) -> OutputSize {
+        if let Some(row_count) = row_count {
+            debug!("Overriding row count {} with {row_count}", self.row_count);
+            self.row_count = row_count;
+        }
+        self
+    }
+
     /// Scale the row count for the output by the number of partitions
     ///
     /// So for example if the row count is 1000 and the number of partitions is 10,
@@ -350,7 +377,8 @@ impl OutputSize {
         }
     }
 
-    fn row_count_for_table(table: Table, scale_factor: f64) -> i64 {
+    /// Return the row count of `table` at `scale_factor`
+    pub fn row_count_for_table(table: Table, scale_factor: f64) -> i64 {
         //let (avg_row_size_bytes, row_count) = match table {
         match table {
             Table::Vehicle => VehicleGenerator::calculate_row_count(scale_factor, 1, 1),
@@ -601,6 +629,34 @@ mod tests {
         }
     }
 
+    // An explicit row count replaces the one of the scale factor
+    mod row_counts {
+        use super::*;
+
+        #[test]
+        fn tbl_sf100_trip_rows() {
+            Test::new()
+                .with_table(Table::Trip)
+                .with_format(OutputFormat::Tbl)
+                .with_scale_factor(100.0)
+                .with_row_count(1_000_000)
+                .assert(10, 1..=10)
+        }
+
+        #[test]
+        fn tbl_sf1_trip_rows_cli_parts() {
+            Test::new()
+                .with_table(Table::Trip)
+                .with_format(OutputFormat::Tbl)
+                .with_scale_factor(1.0)
+                .with_row_count(10_000_000)
+                .with_cli_part(2)
+                .with_cli_part_count(2)
+                // 5M rows per part need 46 chunks
+                .assert(92, 47..=92)
+        }
+    }
+
     //  Error cases for invalid CLI parts and partition
     mod errors {
         use super::*;
@@ -765,6 +821,7 @@ mod tests {
         table: Table,
         format: OutputFormat,
         scale_factor: f64,
+        row_count: Option<i64>,
         cli_part: Option<i32>,
         cli_part_count: Option<i32>,
         parquet_row_group_bytes: i64,
@@ -782,6 +839,7 @@ mod tests {
                 self.table,
                 self.format,
                 self.scale_factor,
+                self.row_count,
                 self.cli_part,
                 self.cli_part_count,
                 self.parquet_row_group_bytes,
@@ -797,6 +855,7 @@ mod tests {
                 self.table,
                 self.format,
                 self.scale_factor,
+                self.row_count,
                 self.cli_part,
                 self.cli_part_count,
                 self.parquet_row_group_bytes,
@@ -823,6 +882,12 @@ mod tests {
             self
         }
 
+        /// Set the row count overriding the scale factor
+        fn with_row_count(mut self, row_count: i64) -> Self {
+            self.row_count = Some(row_count);
+            self
+        }
+
         /// Set CLI part
         fn with_cli_part(mut self, cli_part: i32) -> Self {
             self.cli_part = Some(cli_part);
@@ -848,6 +913,7 @@ mod tests {
                 table: Table::Trip,
                 format: OutputFormat::Tbl,
                 scale_factor: 1.0,
+                row_count: None,
                 cli_part: None,
                 cli_part_count: None,
                 parquet_row_group_bytes: DEFAULT_PARQUET_ROW_GROUP_BYTES,
@@ -0,0 +1,476 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Reader compatibility check of generated Parquet files
+//!
+//! Every file footer is inspected for the format features it uses, and each
+//! feature is matched against [`READER_LIMITATIONS`]. Only the footers are
+//! consulted, so the findings describe the files as written whatever flags
+//! produced them.
+
+use anyhow::{anyhow, Result};
+use arrow_schema::DataType;
+use log::info;
+use parquet::arrow::parquet_to_arrow_schema;
+use parquet::basic::{Compression, Encoding, LogicalType, TimeUnit, Type as PhysicalType};
+use parquet::file::metadata::ParquetMetaData;
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A Parquet format feature some readers can't handle
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Feature {
+    /// `LargeBinary` or `LargeUtf8` in the embedded Arrow schema
+    LargeBinaryOrString,
+    /// `BinaryView` or `Utf8View` in the embedded Arrow schema
+    BinaryOrStringView,
+    /// Struct or list columns
+    NestedColumn,
+    /// GeoParquet 1.1 bounding box covering columns
+    GeoParquetCovering,
+    ZstdCompression,
+    Lz4RawCompression,
+    BrotliCompression,
+    TimestampNanos,
+    UnsignedInteger,
+    Float16,
+    ByteStreamSplitEncoding,
+    DeltaEncoding,
+    /// Column index (page statistics) on binary columns
+    BinaryPageIndex,
+}
+
+/// How a limitation affects a reader
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Impact {
+    /// The file or column fails to read
+    Unreadable,
+    /// The file reads but some information or optimization is lost
+    Degraded,
+}
+
+/// A known limitation of a reader
+#[derive(Debug)]
+pub struct ReaderLimitation {
+    pub reader: &'static str,
+    pub feature: Feature,
+    pub impact: Impact,
+    /// First version without the limitation, if there is one
+    pub fixed_in: Option<&'static str>,
+    pub note: &'static str,
+}
+
+/// Known reader limitations checked by [`check_dir`]
+pub static READER_LIMITATIONS: &[ReaderLimitation] = &[
+    ReaderLimitation {
+        reader: "DuckDB",
+        feature: Feature::ByteStreamSplitEncoding,
+        impact: Impact::Unreadable,
+        fixed_in: Some("0.10"),
+        note: "BYTE_STREAM_SPLIT encoded columns are not supported",
+    },
+    ReaderLimitation {
+        reader: "DuckDB",
+        feature: Feature::GeoParquetCovering,
+        impact: Impact::Degraded,
+        fixed_in: None,
+        note: "the covering column is read as a plain struct and not used for filtering",
+    },
+    ReaderLimitation {
+        reader: "Spark",
+        feature: Feature::UnsignedInteger,
+        impact: Impact::Unreadable,
+        fixed_in: Some("3.2"),
+        note: "unsigned integer logical types are rejected",
+    },
+    ReaderLimitation {
+        reader: "Spark",
+        feature: Feature::TimestampNanos,
+        impact: Impact::Unreadable,
+        fixed_in: None,
+        note: "TIMESTAMP(NANOS) needs spark.sql.legacy.parquet.nanosAsLong and reads as bigint",
+    },
+    ReaderLimitation {
+        reader: "Spark",
+        feature: Feature::Float16,
+        impact: Impact::Unreadable,
+        fixed_in: None,
+        note: "FLOAT16 columns are not supported",
+    },
+    ReaderLimitation {
+        reader: "Spark",
+        feature: Feature::Lz4RawCompression,
+        impact: Impact::Unreadable,
+        fixed_in: Some("3.4"),
+        note: "LZ4_RAW needs parquet-mr 1.13",
+    },
+    ReaderLimitation {
+        reader: "Spark",
+        feature: Feature::BinaryPageIndex,
+        impact: Impact::Degraded,
+        fixed_in: Some("3.2"),
+        note: "page statistics on binary columns are ignored before parquet-mr 1.11",
+    },
+    ReaderLimitation {
+        reader: "Sedona",
+        feature: Feature::GeoParquetCovering,
+        impact: Impact::Degraded,
+        fixed_in: Some("1.6.1"),
+        note: "struct covering column requires a GeoParquet 1.1 reader; it is ignored otherwise",
+    },
+    ReaderLimitation {
+        reader: "GDAL",
+        feature: Feature::GeoParquetCovering,
+        impact: Impact::Degraded,
+        fixed_in: Some("3.9"),
+        note: "struct covering column requires a GeoParquet 1.1 reader; it is ignored otherwise",
+    },
+    ReaderLimitation {
+        reader: "GDAL",
+        feature: Feature::BinaryOrStringView,
+        impact: Impact::Unreadable,
+        fixed_in: None,
+        note: "view types in the Arrow schema need GDAL built against Arrow C++ 15 or newer",
+    },
+    ReaderLimitation {
+        reader: "GDAL",
+        feature: Feature::ZstdCompression,
+        impact: Impact::Unreadable,
+        fixed_in: None,
+        note: "only readable when the underlying Arrow C++ was built with zstd",
+    },
+    ReaderLimitation {
+        reader: "GDAL",
+        feature: Feature::BrotliCompression,
+        impact: Impact::Unreadable,
+        fixed_in: None,
+        note: "only readable when the underlying Arrow C++ was built with Brotli",
+    },
+];
+
+#[derive(Debug, Default, Serialize)]
+pub struct CompatibilityReport {
+    /// Files inspected, relative to the data directory
+    pub files: Vec<String>,
+    /// Features found in the files, with the files using them
+    pub features: BTreeMap<Feature, Vec<String>>,
+    pub findings: Vec<Finding>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Finding {
+    pub reader: &'static str,
+    pub feature: Feature,
+    pub impact: Impact,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fixed_in: Option<&'static str>,
+    pub note: &'static str,
+    /// Files using the feature
+    pub files: Vec<String>,
+}
+
+/// Inspects every Parquet file under `data_dir` and reports the known
+/// reader limitations they run into
+pub fn check_dir(data_dir: &Path) -> Result<CompatibilityReport> {
+    if !data_dir.is_dir() {
+        return Err(anyhow!("{} is not a directory", data_dir.display()));
+    }
+    let mut paths = Vec::new();
+    find_parquet_files(data_dir, &mut paths)?;
+    if paths.is_empty() {
+        return Err(anyhow!("No Parquet files found in {}", data_dir.display()));
+    }
+    paths.sort();
+
+    let mut report = CompatibilityReport::default();
+    for path in paths {
+        let relative = path
+            .strip_prefix(data_dir)
+            .unwrap_or(&path)
+            .to_string_lossy()
+            .into_owned();
+        let reader = SerializedFileReader::new(File::open(&path)?)
+            .map_err(|e| anyhow!("{}: {e}", path.display()))?;
+        for feature in file_features(reader.metadata())? {
+            report
+                .features
+                .entry(feature)
+                .or_default()
+                .push(relative.clone());
+        }
+        report.files.push(relative);
+    }
+
+    report.findings = READER_LIMITATIONS
+        .iter()
+        .filter_map(|limitation| {
+            let files = report.features.get(&limitation.feature)?;
+            Some(Finding {
+                reader: limitation.reader,
+                feature: limitation.feature,
+                impact: limitation.impact,
+                fixed_in: limitation.fixed_in,
+                note: limitation.note,
+                files: files.clone(),
+            })
+        })
+        .collect();
+    Ok(report)
+}
+
+/// Prints the findings for `data_dir`, optionally writing the full report
+/// as JSON
+pub fn check_readers(data_dir: &Path, json_path: Option<&Path>) -> io::Result<()> {
+    let report = check_dir(data_dir).map_err(io::Error::other)?;
+
+    println!("{}: {} file(s)", data_dir.display(), report.files.len());
+    for (feature, files) in &report.features {
+        println!("  {feature:?}: {} file(s)", files.len());
+    }
+    if report.findings.is_empty() {
+        println!("No known reader limitations apply");
+    }
+    for finding in &report.findings {
+        let fixed_in = finding
+            .fixed_in
+            .map(|v| format!(" (fixed in {v})"))
+            .unwrap_or_default();
+        println!(
+            "{:?} {}: {}{fixed_in}, {} file(s)",
+            finding.impact,
+            finding.reader,
+            finding.note,
+            finding.files.len()
+        );
+    }
+
+    if let Some(path) = json_path {
+        let json = serde_json::to_string_pretty(&report).map_err(io::Error::other)?;
+        std::fs::write(path, json)?;
+        info!("Wrote reader compatibility report to {}", path.display());
+    }
+    Ok(())
+}
+
+fn find_parquet_files(dir: &Path, paths: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_parquet_files(&path, paths)?;
+        } else if path.extension().is_some_and(|e| e == "parquet") {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Format features used by a file, derived from its footer
+fn file_features(metadata: &ParquetMetaData) -> Result<BTreeSet<Feature>> {
+    let mut features = BTreeSet::new();
+    let file_metadata = metadata.file_metadata();
+    let schema = file_metadata.schema_descr();
+
+    for column in schema.columns() {
+        if column.path().parts().len() > 1 {
+            features.insert(Feature::NestedColumn);
+        }
+        match column.logical_type() {
+            Some(LogicalType::Integer {
+                is_signed: false, ..
+            }) => {
+                features.insert(Feature::UnsignedInteger);
+            }
+            Some(LogicalType::Timestamp {
+                unit: TimeUnit::NANOS(_),
+                ..
+            }) => {
+                features.insert(Feature::TimestampNanos);
+            }
+            Some(LogicalType::Float16) => {
+                features.insert(Feature::Float16);
+            }
+            _ => {}
+        }
+    }
+
+    for row_group in metadata.row_groups() {
+        for chunk in row_group.columns() {
+            match chunk.compression() {
+                Compression::ZSTD(_) => features.insert(Feature::ZstdCompression),
+                Compression::LZ4_RAW => features.insert(Feature::Lz4RawCompression),
+                Compression::BROTLI(_) => features.insert(Feature::BrotliCompression),
+                _ => false,
+            };
+            for encoding in chunk.encodings() {
+                match encoding {
+                    Encoding::BYTE_STREAM_SPLIT => {
+                        features.insert(Feature::ByteStreamSplitEncoding)
+                    }
+                    Encoding::DELTA_BINARY_PACKED
+                    | Encoding::DELTA_LENGTH_BYTE_ARRAY
+                    | Encoding::DELTA_BYTE_ARRAY => features.insert(Feature::DeltaEncoding),
+                    _ => false,
+                };
+            }
+            if chunk.column_type() == PhysicalType::BYTE_ARRAY
+                && chunk.column_index_offset().is_some()
+            {
+                features.insert(Feature::BinaryPageIndex);
+            }
+        }
+    }
+
+    let arrow_schema = parquet_to_arrow_schema(schema, file_metadata.key_value_metadata())?;
+    for field in arrow_schema.fields() {
+        match field.data_type() {
+            DataType::LargeBinary | DataType::LargeUtf8 => {
+                features.insert(Feature::LargeBinaryOrString);
+            }
+            DataType::BinaryView | DataType::Utf8View => {
+                features.insert(Feature::BinaryOrStringView);
+            }
+            _ => {}
+        }
+    }
+
+    let geo = file_metadata
+        .key_value_metadata()
+        .into_iter()
+        .flatten()
+        .find(|kv| kv.key == "geo")
+        .and_then(|kv| kv.value.as_deref());
+    if let Some(geo) = geo {
+        let geo: serde_json::Value = serde_json::from_str(geo)?;
+        let has_covering = geo["columns"]
+            .as_object()
+            .is_some_and(|columns| columns.values().any(|c| c.get("covering").is_some()));
+        if has_covering {
+            features.insert(Feature::GeoParquetCovering);
+        }
+    }
+
+    Ok(features)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_array::{
+        ArrayRef, Int64Array, LargeBinaryArray, RecordBatch, StructArray, UInt32Array,
+    };
+    use arrow_schema::{Field, Fields, Schema};
+    use parquet::arrow::ArrowWriter;
+    use parquet::file::metadata::KeyValue;
+    use parquet::file::properties::WriterProperties;
+    use std::sync::Arc;
+
+    fn write(path: &Path, batch: &RecordBatch, props: WriterProperties) {
+        let mut writer =
+            ArrowWriter::try_new(File::create(path).unwrap(), batch.schema(), Some(props)).unwrap();
+        writer.write(batch).unwrap();
+        writer.close().unwrap();
+    }
+
+    #[test]
+    fn test_findings_follow_footer() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let plain = RecordBatch::try_from_iter([(
+            "key",
+            Arc::new(Int64Array::from(vec![1, 2])) as ArrayRef,
+        )])
+        .unwrap();
+        write(
+            &dir.path().join("plain.parquet"),
+            &plain,
+            WriterProperties::builder().build(),
+        );
+
+        let bbox = StructArray::from(vec![(
+            Arc::new(Field::new("xmin", DataType::Int64, false)),
+            Arc::new(Int64Array::from(vec![0, 1])) as ArrayRef,
+        )]);
+        let geo = RecordBatch::try_new(
+            Arc::new(Schema::new(vec![
+                Field::new("geometry", DataType::LargeBinary, false),
+                Field::new("count", DataType::UInt32, false),
+                Field::new(
+                    "bbox",
+                    DataType::Struct(Fields::from(vec![Field::new(
+                        "xmin",
+                        DataType::Int64,
+                        false,
+                    )])),
+                    false,
+                ),
+            ])),
+            vec![
+                Arc::new(LargeBinaryArray::from(vec![b"a".as_slice(), b"b"])),
+                Arc::new(UInt32Array::from(vec![1, 2])),
+                Arc::new(bbox),
+            ],
+        )
+        .unwrap();
+        let props = WriterProperties::builder()
+            .set_compression(Compression::ZSTD(Default::default()))
+            .set_key_value_metadata(Some(vec![KeyValue::new(
+                "geo".to_string(),
+                r#"{"version":"1.1.0","columns":{"geometry":{"covering":{"bbox":{}}}}}"#
+                    .to_string(),
+            )]))
+            .build();
+        std::fs::create_dir(dir.path().join("zone")).unwrap();
+        write(&dir.path().join("zone/geo.parquet"), &geo, props);
+
+        let report = check_dir(dir.path()).unwrap();
+        assert_eq!(report.files, vec!["plain.parquet", "zone/geo.parquet"]);
+        let geo_file = vec!["zone/geo.parquet".to_string()];
+        for feature in [
+            Feature::LargeBinaryOrString,
+            Feature::NestedColumn,
+            Feature::GeoParquetCovering,
+            Feature::ZstdCompression,
+            Feature::UnsignedInteger,
+            Feature::BinaryPageIndex,
+        ] {
+            assert_eq!(
+                report.features.get(&feature),
+                Some(&geo_file),
+                "{feature:?}"
+            );
+        }
+        assert!(!report.features.contains_key(&Feature::TimestampNanos));
+
+        let found = |reader: &str, feature: Feature| {
+            report
+                .findings
+                .iter()
+                .any(|f| f.reader == reader && f.feature == feature && f.files == geo_file)
+        };
+        assert!(found("Spark", Feature::UnsignedInteger));
+        assert!(found("Sedona", Feature::GeoParquetCovering));
+        assert!(found("GDAL", Feature::ZstdCompression));
+        assert!(!found("Spark", Feature::TimestampNanos));
+        assert!(report.findings.iter().all(|f| f.files == geo_file));
+    }
+}
@@ -0,0 +1,357 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Exponential backoff for object store writes
+//!
+//! `--upload-to` copies the generated files to an [`ObjectStore`] with the
+//! requests made here, so a throttled or failed request doesn't abort the
+//! run. `--write-retries` and `--write-retry-base-ms` set the policy.
+
+use log::warn;
+use object_store::path::Path;
+use object_store::{ObjectStore, PutPayload, PutResult};
+use std::future::Future;
+use std::time::Duration;
+
+/// How often and how patiently to retry a failed request
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Attempts after the first one
+    pub retries: u32,
+    /// Delay before the first retry, doubled for every further retry
+    pub base_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            retries: 3,
+            base_delay: Duration::from_millis(100),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new(retries: u32, base_delay_ms: u64) -> Self {
+        Self {
+            retries,
+            base_delay: Duration::from_millis(base_delay_ms),
+        }
+    }
+
+    fn delay(&self, retry: u32) -> Duration {
+        self.base_delay.saturating_mul(1 << retry.min(16))
+    }
+
+    /// The delay before retrying a request that failed with `error` after
+    /// `retry` earlier retries, `None` when it should fail instead
+    pub fn retry_delay(&self, retry: u32, error: &object_store::Error) -> Option<Duration> {
+        (retry < self.retries && is_retryable(error)).then(|| self.delay(retry))
+    }
+}
+
+/// Whether a request failing with `error` may succeed when repeated.
+///
+/// Errors about the request itself, such as authentication, permissions or
+/// missing objects, fail the same way every time. Server errors and
+/// throttling surface as [`object_store::Error::Generic`] and are retried.
+pub fn is_retryable(error: &object_store::Error) -> bool {
+    use object_store::Error::*;
+    !matches!(
+        error,
+        NotFound { .. }
+            | InvalidPath { .. }
+            | NotSupported { .. }
+            | AlreadyExists { .. }
+            | Precondition { .. }
+            | NotModified { .. }
+            | NotImplemented
+            | PermissionDenied { .. }
+            | Unauthenticated { .. }
+            | UnknownConfigurationKey { .. }
+    )
+}
+
+/// Runs `request` until it succeeds, fails with a non-retryable error or
+/// `policy.retries` retries are used up
+pub async fn with_retry<T, F, Fut>(
+    policy: &RetryPolicy,
+    what: &str,
+    mut request: F,
+) -> object_store::Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = object_store::Result<T>>,
+{
+    let mut retry = 0;
+    loop {
+        let error = match request().await {
+            Ok(value) => return Ok(value),
+            Err(e) => e,
+        };
+        backoff(policy, what, &mut retry, error).await?;
+    }
+}
+
+/// Waits before the next attempt of a request that failed with `error`, or
+/// returns the error when it isn't retried. `retry` counts the retries.
+pub async fn backoff(
+    policy: &RetryPolicy,
+    what: &str,
+    retry: &mut u32,
+    error: object_store::Error,
+) -> object_store::Result<()> {
+    let Some(delay) = policy.retry_delay(*retry, &error) else {
+        return Err(error);
+    };
+    *retry += 1;
+    warn!(
+        "{what} failed, retry {retry}/{} in {delay:?}: {error}",
+        policy.retries
+    );
+    tokio::time::sleep(delay).await;
+    Ok(())
+}
+
+/// [`ObjectStore::put`] retried according to `policy`
+pub async fn put_with_retry(
+    store: &dyn ObjectStore,
+    location: &Path,
+    payload: PutPayload,
+    policy: &RetryPolicy,
+) -> object_store::Result<PutResult> {
+    with_retry(policy, &format!("put {location}"), || {
+        store.put(location, payload.clone())
+    })
+    .await
+}
+
+/// An in-memory store with failing write requests, for tests
+#[cfg(test)]
+pub(crate) mod testing {
+    use async_trait::async_trait;
+    use futures::stream::BoxStream;
+    use object_store::memory::InMemory;
+    use object_store::path::Path;
+    use object_store::{
+        GetOptions, GetResult, ListResult, MultipartUpload, ObjectMeta, ObjectStore,
+        PutMultipartOptions, PutOptions, PutPayload, PutResult, UploadPart,
+    };
+    use std::fmt::{Display, Formatter};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// Counts write requests and fails the first `failures` of them, puts,
+    /// multipart parts and completions alike, with `error`
+    #[derive(Debug)]
+    struct Failures {
+        remaining: AtomicUsize,
+        requests: AtomicUsize,
+        error: fn() -> object_store::Error,
+    }
+
+    impl Failures {
+        fn next(&self) -> object_store::Result<()> {
+            self.requests.fetch_add(1, Ordering::SeqCst);
+            let failed = self
+                .remaining
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                .is_ok();
+            if failed {
+                return Err((self.error)());
+            }
+            Ok(())
+        }
+    }
+
+    /// In-memory store whose first `failures` write requests fail
+    #[derive(Debug)]
+    pub struct FlakyStore {
+        inner: InMemory,
+        failures: Arc<Failures>,
+    }
+
+    impl FlakyStore {
+        pub fn new(failures: usize, error: fn() -> object_store::Error) -> Self {
+            Self {
+                inner: InMemory::new(),
+                failures: Arc::new(Failures {
+                    remaining: AtomicUsize::new(failures),
+                    requests: AtomicUsize::new(0),
+                    error,
+                }),
+            }
+        }
+
+        /// Write requests made so far, failed or not
+        pub fn requests(&self) -> usize {
+            self.failures.requests.load(Ordering::SeqCst)
+        }
+    }
+
+    impl Display for FlakyStore {
+        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            write!(f, "FlakyStore")
+        }
+    }
+
+    #[derive(Debug)]
+    struct FlakyUpload {
+        inner: Box<dyn MultipartUpload>,
+        failures: Arc<Failures>,
+    }
+
+    #[async_trait]
+    impl MultipartUpload for FlakyUpload {
+        fn put_part(&mut self, data: PutPayload) -> UploadPart {
+            match self.failures.next() {
+                Ok(()) => self.inner.put_part(data),
+                Err(e) => Box::pin(futures::future::ready(Err(e))),
+            }
+        }
+
+        async fn complete(&mut self) -> object_store::Result<PutResult> {
+            self.failures.next()?;
+            self.inner.complete().await
+        }
+
+        async fn abort(&mut self) -> object_store::Result<()> {
+            self.inner.abort().await
+        }
+    }
+
+    #[async_trait]
+    impl ObjectStore for FlakyStore {
+        async fn put_opts(
+            &self,
+            location: &Path,
+            payload: PutPayload,
+            opts: PutOptions,
+        ) -> object_store::Result<PutResult> {
+            self.failures.next()?;
+            self.inner.put_opts(location, payload, opts).await
+        }
+
+        async fn put_multipart_opts(
+            &self,
+            location: &Path,
+            opts: PutMultipartOptions,
+        ) -> object_store::Result<Box<dyn MultipartUpload>> {
+            Ok(Box::new(FlakyUpload {
+                inner: self.inner.put_multipart_opts(location, opts).await?,
+                failures: Arc::clone(&self.failures),
+            }))
+        }
+
+        async fn get_opts(
+            &self,
+            location: &Path,
+            options: GetOptions,
+        ) -> object_store::Result<GetResult> {
+            self.inner.get_opts(location, options).await
+        }
+
+        async fn delete(&self, location: &Path) -> object_store::Result<()> {
+            self.inner.delete(location).await
+        }
+
+        fn list(
+            &self,
+            prefix: Option<&Path>,
+        ) -> BoxStream<'static, object_store::Result<ObjectMeta>> {
+            self.inner.list(prefix)
+        }
+
+        async fn list_with_delimiter(
+            &self,
+            prefix: Option<&Path>,
+        ) -> object_store::Result<ListResult> {
+            self.inner.list_with_delimiter(prefix).await
+        }
+
+        async fn copy(&self, from: &Path, to: &Path) -> object_store::Result<()> {
+            self.inner.copy(from, to).await
+        }
+
+        async fn copy_if_not_exists(&self, from: &Path, to: &Path) -> object_store::Result<()> {
+            self.inner.copy_if_not_exists(from, to).await
+        }
+    }
+
+    pub fn throttled() -> object_store::Error {
+        object_store::Error::Generic {
+            store: "S3",
+            source: "503 Slow Down".into(),
+        }
+    }
+
+    pub fn unauthenticated() -> object_store::Error {
+        object_store::Error::Unauthenticated {
+            path: "zone.parquet".to_string(),
+            source: "403 Forbidden".into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::testing::{throttled, unauthenticated, FlakyStore};
+    use super::*;
+
+    #[tokio::test]
+    async fn test_put_succeeds_after_transient_failures() {
+        let store = FlakyStore::new(2, throttled);
+        let location = Path::from("zone/zone.1.parquet");
+        put_with_retry(
+            &store,
+            &location,
+            PutPayload::from_static(b"data"),
+            &RetryPolicy::new(3, 1),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(store.requests(), 3);
+        let bytes = store.get(&location).await.unwrap().bytes().await.unwrap();
+        assert_eq!(bytes.as_ref(), b"data");
+    }
+
+    #[tokio::test]
+    async fn test_put_gives_up_after_retries() {
+        let store = FlakyStore::new(5, throttled);
+        let location = Path::from("zone.parquet");
+        let payload = PutPayload::from_static(b"data");
+        assert!(
+            put_with_retry(&store, &location, payload, &RetryPolicy::new(2, 1))
+                .await
+                .is_err()
+        );
+        assert_eq!(store.requests(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_non_retryable_error_fails_immediately() {
+        let store = FlakyStore::new(1, unauthenticated);
+        let location = Path::from("zone.parquet");
+        let payload = PutPayload::from_static(b"data");
+        let err = put_with_retry(&store, &location, payload, &RetryPolicy::new(3, 1))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, object_store::Error::Unauthenticated { .. }));
+        assert_eq!(store.requests(), 1);
+    }
+}
@@ -19,10 +19,13 @@
 
 use crate::csv::*;
 use crate::generate::{generate_in_chunks, Source};
+use crate::interrupt::{interrupted_error, CancellationFlag};
 use crate::output_plan::{OutputLocation, OutputPlan};
-use crate::parquet::generate_parquet;
+use crate::parquet::{generate_parquet, IntoSize};
+use crate::statistics::WriteStatistics;
 use crate::tbl::*;
 use crate::{OutputFormat, Table, WriterSink};
+use arrow::datatypes::SchemaRef;
 use log::{debug, info};
 use spatialbench::generators::{
     BuildingGenerator, CustomerGenerator, DriverGenerator, TripGenerator, VehicleGenerator,
@@ -30,8 +33,9 @@ use spatialbench::generators::{
 use spatialbench_arrow::{
     BuildingArrow, CustomerArrow, DriverArrow, RecordBatchIterator, TripArrow, VehicleArrow,
 };
+use spatialbench_cli::avro::generate_avro;
 use std::io;
-use std::io::BufWriter;
+use std::io::{BufWriter, Write};
 use tokio::task::{JoinError, JoinSet};
 
 /// Runs multiple [`OutputPlan`]s in parallel, managing the number of threads
@@ -40,12 +44,24 @@ use tokio::task::{JoinError, JoinSet};
 pub struct PlanRunner {
     plans: Vec<OutputPlan>,
     num_threads: usize,
+    cancellation: CancellationFlag,
 }
 
 impl PlanRunner {
     /// Create a new [`PlanRunner`] with the given plans and number of threads.
     pub fn new(plans: Vec<OutputPlan>, num_threads: usize) -> Self {
-        Self { plans, num_threads }
+        Self {
+            plans,
+            num_threads,
+            cancellation: CancellationFlag::default(),
+        }
+    }
+
+    /// Stop scheduling new plans once `cancellation` is set. Plans already
+    /// running are finished.
+    pub fn with_cancellation(mut self, cancellation: CancellationFlag) -> Self {
+        self.cancellation = cancellation;
+        self
     }
 
     /// Run all the plans in the runner.
@@ -58,6 +74,7 @@ impl PlanRunner {
         let Self {
             mut plans,
             num_threads,
+            cancellation,
         } = self;
 
         // Sort the plans by the number of parts so the largest are first
@@ -70,9 +87,21 @@ impl PlanRunner {
         // Do the actual work in parallel, using a worker queue
         let mut worker_queue = WorkerQueue::new(num_threads);
         while let Some(plan) = plans.pop() {
+            if cancellation.is_cancelled() {
+                info!(
+                    "Interrupted, not starting {} remaining plan(s)",
+                    plans.len() + 1
+                );
+                break;
+            }
             worker_queue.schedule_plan(plan).await?;
         }
-        worker_queue.join_all().await
+        worker_queue.join_all().await?;
+
+        if cancellation.is_cancelled() {
+            return Err(interrupted_error());
+        }
+        Ok(())
     }
 }
 
@@ -229,7 +258,14 @@ where
     match plan.output_location() {
         OutputLocation::Stdout => {
             let writer = BufWriter::with_capacity(32 * 1024 * 1024, io::stdout()); // 32MB buffer
-            generate_parquet(writer, sources, num_threads, plan.parquet_compression()).await
+            generate_parquet(
+                writer,
+                sources,
+                num_threads,
+                plan.parquet_compression(),
+                plan.parquet_metadata(),
+            )
+            .await
         }
         OutputLocation::File(path) => {
             // if the output already exists, skip running
@@ -243,7 +279,14 @@ where
                 io::Error::other(format!("Failed to create {temp_path:?}: {err}"))
             })?;
             let writer = BufWriter::with_capacity(32 * 1024 * 1024, file); // 32MB buffer
-            generate_parquet(writer, sources, num_threads, plan.parquet_compression()).await?;
+            generate_parquet(
+                writer,
+                sources,
+                num_threads,
+                plan.parquet_compression(),
+                plan.parquet_metadata(),
+            )
+            .await?;
             // rename the temp file to the final path
             std::fs::rename(&temp_path, path).map_err(|e| {
                 io::Error::other(format!(
@@ -255,6 +298,95 @@ where
     }
 }
 
+/// Generates an output Avro file from the sources
+async fn write_avro<I>(plan: OutputPlan, num_threads: usize, sources: I) -> Result<(), io::Error>
+where
+    I: Iterator<Item: RecordBatchIterator> + 'static,
+{
+    match plan.output_location() {
+        OutputLocation::Stdout => {
+            let writer = BufWriter::with_capacity(32 * 1024 * 1024, io::stdout()); // 32MB buffer
+            encode_avro(writer, &plan, num_threads, sources).await
+        }
+        OutputLocation::File(path) => {
+            // if the output already exists, skip running
+            if path.exists() {
+                info!("{} already exists, skipping generation", path.display());
+                return Ok(());
+            }
+            // write to a temp file and then rename to avoid partial files
+            let temp_path = path.with_extension("inprogress");
+            let file = std::fs::File::create(&temp_path).map_err(|err| {
+                io::Error::other(format!("Failed to create {temp_path:?}: {err}"))
+            })?;
+            let writer = BufWriter::with_capacity(32 * 1024 * 1024, file); // 32MB buffer
+            encode_avro(writer, &plan, num_threads, sources).await?;
+            // rename the temp file to the final path
+            std::fs::rename(&temp_path, path).map_err(|e| {
+                io::Error::other(format!(
+                    "Failed to rename {temp_path:?} to {path:?} file: {e}"
+                ))
+            })?;
+            Ok(())
+        }
+    }
+}
+
+async fn encode_avro<W, I>(
+    writer: W,
+    plan: &OutputPlan,
+    num_threads: usize,
+    sources: I,
+) -> io::Result<()>
+where
+    W: Write + Send + IntoSize + 'static,
+    I: Iterator<Item: RecordBatchIterator> + 'static,
+{
+    let mut statistics = WriteStatistics::new("parts");
+    statistics.increment_chunks(plan.chunk_count());
+    let name = plan.table().name();
+    let writer = generate_avro(writer, name, sources, num_threads, plan.avro_codec()).await?;
+    statistics.increment_bytes(writer.into_size()?);
+    Ok(())
+}
+
+/// The Arrow schema of the batches generated for `table`, or `None` for the
+/// zone table whose schema depends on its options
+pub fn table_schema(table: Table) -> Option<SchemaRef> {
+    let schema = match table {
+        Table::Vehicle => VehicleArrow::new(VehicleGenerator::new(1.0, 1, 1))
+            .schema()
+            .clone(),
+        Table::Driver => DriverArrow::new(DriverGenerator::new(1.0, 1, 1))
+            .schema()
+            .clone(),
+        Table::Customer => CustomerArrow::new(CustomerGenerator::new(1.0, 1, 1))
+            .schema()
+            .clone(),
+        Table::Trip => TripArrow::new(TripGenerator::new(1.0, 1, 1))
+            .schema()
+            .clone(),
+        Table::Building => BuildingArrow::new(BuildingGenerator::new(1.0, 1, 1))
+            .schema()
+            .clone(),
+        Table::Zone => return None,
+    };
+    Some(schema)
+}
+
+/// Returns `generator` as is, the default configuration in [`define_run`]
+fn unconfigured<G>(generator: G, _plan: &OutputPlan) -> G {
+    generator
+}
+
+/// Limits trips to the pickup dates of the plan
+fn configure_trip(generator: TripGenerator, plan: &OutputPlan) -> TripGenerator {
+    match plan.pickup_dates() {
+        Some(pickup_dates) => generator.with_pickup_dates(pickup_dates.clone()),
+        None => generator,
+    }
+}
+
 /// macro to create a function for generating a part of a particular able
 ///
 /// Arguments:
@@ -262,12 +394,21 @@ where
 /// $GENERATOR: The generator type to use
 /// $TBL_SOURCE: The [`Source`] type to use for TBL format
 /// $CSV_SOURCE: The [`Source`] type to use for CSV format
-/// $PARQUET_SOURCE: The [`RecordBatchIterator`] type to use for Parquet format
+/// $PARQUET_SOURCE: The [`RecordBatchIterator`] type to use for Parquet and Avro formats
+/// $CONFIGURE: Optional function applying the [`OutputPlan`] to each generator
 macro_rules! define_run {
     ($FUN_NAME:ident, $GENERATOR:ident, $TBL_SOURCE:ty, $CSV_SOURCE:ty, $PARQUET_SOURCE:ty) => {
+        define_run!(
+            $FUN_NAME,
+            $GENERATOR,
+            $TBL_SOURCE,
+            $CSV_SOURCE,
+            $PARQUET_SOURCE,
+            unconfigured
+        );
+    };
+    ($FUN_NAME:ident, $GENERATOR:ident, $TBL_SOURCE:ty, $CSV_SOURCE:ty, $PARQUET_SOURCE:ty, $CONFIGURE:path) => {
         async fn $FUN_NAME(plan: OutputPlan, num_threads: usize) -> io::Result<usize> {
-            use crate::GenerationPlan;
-            let scale_factor = plan.scale_factor();
             info!("Writing {plan} using {num_threads} threads");
 
             /// These interior functions are used to tell the compiler that the lifetime is 'static
@@ -278,53 +419,77 @@ macro_rules! define_run {
             ///              96 | |                 run_plan(plan, num_plan_threads).await
             ///              97 | |             });
             ///                 | |______________^ implementation of `FnOnce` is not general enough
-            fn tbl_sources(
-                generation_plan: &GenerationPlan,
-                scale_factor: f64,
-            ) -> impl Iterator<Item: Source> + 'static {
-                generation_plan
+            fn tbl_sources(plan: OutputPlan) -> impl Iterator<Item: Source> + 'static {
+                plan.generation_plan()
                     .clone()
                     .into_iter()
-                    .map(move |(part, num_parts)| $GENERATOR::new(scale_factor, part, num_parts))
+                    .map(move |(part, num_parts)| {
+                        let generator = $GENERATOR::new(plan.scale_factor(), part, num_parts);
+                        let generator = match plan.row_count() {
+                            Some(row_count) => generator.with_row_count(row_count),
+                            None => generator,
+                        };
+                        $CONFIGURE(generator, &plan)
+                    })
                     .map(<$TBL_SOURCE>::new)
             }
 
-            fn csv_sources(
-                generation_plan: &GenerationPlan,
-                scale_factor: f64,
-            ) -> impl Iterator<Item: Source> + 'static {
-                generation_plan
+            fn csv_sources(plan: OutputPlan) -> impl Iterator<Item: Source> + 'static {
+                plan.generation_plan()
                     .clone()
                     .into_iter()
-                    .map(move |(part, num_parts)| $GENERATOR::new(scale_factor, part, num_parts))
+                    .map(move |(part, num_parts)| {
+                        let generator = $GENERATOR::new(plan.scale_factor(), part, num_parts);
+                        let generator = match plan.row_count() {
+                            Some(row_count) => generator.with_row_count(row_count),
+                            None => generator,
+                        };
+                        $CONFIGURE(generator, &plan)
+                    })
                     .map(<$CSV_SOURCE>::new)
             }
 
             fn parquet_sources(
-                generation_plan: &GenerationPlan,
-                scale_factor: f64,
+                plan: OutputPlan,
             ) -> impl Iterator<Item: RecordBatchIterator> + 'static {
-                generation_plan
+                plan.generation_plan()
                     .clone()
                     .into_iter()
-                    .map(move |(part, num_parts)| $GENERATOR::new(scale_factor, part, num_parts))
+                    .map(move |(part, num_parts)| {
+                        let generator = $GENERATOR::new(plan.scale_factor(), part, num_parts);
+                        let generator = match plan.row_count() {
+                            Some(row_count) => generator.with_row_count(row_count),
+                            None => generator,
+                        };
+                        $CONFIGURE(generator, &plan)
+                    })
                     .map(<$PARQUET_SOURCE>::new)
             }
 
             // Dispach to the appropriate output format
             match plan.output_format() {
                 OutputFormat::Tbl => {
-                    let gens = tbl_sources(plan.generation_plan(), scale_factor);
+                    let gens = tbl_sources(plan.clone());
                     write_file(plan, num_threads, gens).await?
                 }
                 OutputFormat::Csv => {
-                    let gens = csv_sources(plan.generation_plan(), scale_factor);
+                    let gens = csv_sources(plan.clone());
                     write_file(plan, num_threads, gens).await?
                 }
                 OutputFormat::Parquet => {
-                    let gens = parquet_sources(plan.generation_plan(), scale_factor);
+                    let gens = parquet_sources(plan.clone());
                     write_parquet(plan, num_threads, gens).await?
                 }
+                OutputFormat::Avro => {
+                    let gens = parquet_sources(plan.clone());
+                    write_avro(plan, num_threads, gens).await?
+                }
+                OutputFormat::Orc => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("ORC output is only supported for the zone table, not {plan}"),
+                    ))
+                }
             };
             Ok(num_threads)
         }
@@ -336,7 +501,8 @@ define_run!(
     TripGenerator,
     TripTblSource,
     TripCsvSource,
-    TripArrow
+    TripArrow,
+    configure_trip
 );
 
 define_run!(
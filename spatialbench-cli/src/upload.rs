@@ -0,0 +1,313 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Copying the generated files to an object store with `--upload-to`
+//!
+//! The files are generated in `--output-dir` as usual and uploaded once the
+//! run is complete. Small files are written with a single put, larger ones
+//! as a multipart upload; every request, including the completion of a
+//! multipart upload, is retried as set by the [`RetryPolicy`]. Manifests and
+//! `_SUCCESS` markers are uploaded after the data files they describe.
+
+use anyhow::{anyhow, Context, Result};
+use log::{debug, info};
+use object_store::path::Path as ObjectPath;
+use object_store::{ObjectStore, PutPayload};
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::{info_span, Instrument};
+use url::Url;
+
+use crate::dataset::DATASET_FILE_NAME;
+use crate::output_dir::PROBE_FILE_NAME;
+use crate::output_lock::LOCK_FILE_NAME;
+use crate::retry::{backoff, put_with_retry, with_retry, RetryPolicy};
+use crate::zone::{remote_store, MANIFEST_FILE_NAME, SUCCESS_FILE_NAME};
+
+/// Size of the parts of a multipart upload, and the size above which a file
+/// is uploaded in parts
+pub const DEFAULT_UPLOAD_PART_BYTES: usize = 16 * 1024 * 1024;
+
+/// Files describing the others, uploaded last so that a reader never finds
+/// them before the files they list
+const UPLOADED_LAST: [&str; 3] = [MANIFEST_FILE_NAME, DATASET_FILE_NAME, SUCCESS_FILE_NAME];
+
+/// Files and bytes uploaded by [`Uploader::upload_dir`]
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct UploadSummary {
+    pub files: usize,
+    pub bytes: u64,
+}
+
+/// Uploads local files below a prefix of an object store
+#[derive(Debug)]
+pub struct Uploader {
+    store: Arc<dyn ObjectStore>,
+    prefix: ObjectPath,
+    policy: RetryPolicy,
+    part_bytes: usize,
+}
+
+impl Uploader {
+    pub fn new(store: Arc<dyn ObjectStore>, prefix: ObjectPath, policy: RetryPolicy) -> Self {
+        Self {
+            store,
+            prefix,
+            policy,
+            part_bytes: DEFAULT_UPLOAD_PART_BYTES,
+        }
+    }
+
+    /// An uploader for an `s3://`, `http://` or `https://` URL, its path
+    /// being the prefix of the uploaded files
+    pub fn for_url(url: &str, policy: RetryPolicy) -> Result<Self> {
+        let parsed = Url::parse(url).map_err(|e| anyhow!("Invalid upload URL {url:?}: {e}"))?;
+        let (_, store) = remote_store(&parsed)?.ok_or_else(|| {
+            anyhow!("Unsupported upload URL {url:?}, expected an s3://, http:// or https:// URL")
+        })?;
+        let prefix = ObjectPath::from_url_path(parsed.path())?;
+        Ok(Self::new(store, prefix, policy))
+    }
+
+    pub fn with_part_bytes(mut self, part_bytes: usize) -> Self {
+        self.part_bytes = part_bytes.max(1);
+        self
+    }
+
+    /// Uploads every file under `dir` by its path relative to `dir`, leaving
+    /// out the lock, the write probe and temporary files of unfinished writes
+    pub async fn upload_dir(&self, dir: &Path) -> Result<UploadSummary> {
+        let mut files = BTreeMap::new();
+        collect_files(dir, dir, &mut files)?;
+        let (last, first): (Vec<_>, Vec<_>) = files
+            .into_iter()
+            .partition(|(name, _)| UPLOADED_LAST.iter().any(|last| name.ends_with(last)));
+
+        let mut summary = UploadSummary::default();
+        for (name, path) in first.into_iter().chain(last) {
+            summary.bytes += self.upload_file(&path, &name).await?;
+            summary.files += 1;
+        }
+        info!(
+            "Uploaded {} file(s), {} bytes, to {}",
+            summary.files, summary.bytes, self.prefix
+        );
+        Ok(summary)
+    }
+
+    /// Uploads the file at `path` as `name` below the prefix, returning its
+    /// size
+    pub async fn upload_file(&self, path: &Path, name: &str) -> Result<u64> {
+        let location = self.location(name);
+        let bytes = std::fs::metadata(path)
+            .with_context(|| format!("Failed reading {}", path.display()))?
+            .len();
+        let span = info_span!("upload", path = %location, bytes);
+        async {
+            if bytes <= self.part_bytes as u64 {
+                let payload = PutPayload::from(std::fs::read(path)?);
+                put_with_retry(self.store.as_ref(), &location, payload, &self.policy).await?;
+            } else {
+                self.upload_parts(path, &location).await?;
+            }
+            debug!("Uploaded {} to {location}", path.display());
+            Ok::<_, anyhow::Error>(())
+        }
+        .instrument(span)
+        .await
+        .map_err(|e| anyhow!("Failed uploading {} to {location}: {e}", path.display()))?;
+        Ok(bytes)
+    }
+
+    fn location(&self, name: &str) -> ObjectPath {
+        self.prefix
+            .parts()
+            .chain(ObjectPath::from(name).parts())
+            .collect()
+    }
+
+    /// Uploads `path` in parts of `part_bytes`, aborting the upload if a part
+    /// or the completion still fails after its retries
+    async fn upload_parts(&self, path: &Path, location: &ObjectPath) -> Result<()> {
+        let mut upload = with_retry(&self.policy, &format!("start upload {location}"), || {
+            self.store.put_multipart(location)
+        })
+        .await?;
+
+        let result = async {
+            let mut file = std::fs::File::open(path)?;
+            let mut part = 0;
+            loop {
+                let mut chunk = Vec::with_capacity(self.part_bytes);
+                (&mut file)
+                    .take(self.part_bytes as u64)
+                    .read_to_end(&mut chunk)?;
+                if chunk.is_empty() {
+                    break;
+                }
+                part += 1;
+                let payload = PutPayload::from(chunk);
+                with_retry(&self.policy, &format!("part {part} of {location}"), || {
+                    upload.put_part(payload.clone())
+                })
+                .await?;
+            }
+
+            let mut retry = 0;
+            loop {
+                match upload.complete().await {
+                    Ok(_) => return Ok(()),
+                    Err(e) => {
+                        backoff(&self.policy, &format!("complete {location}"), &mut retry, e)
+                            .await?
+                    }
+                }
+            }
+        }
+        .await;
+
+        if result.is_err() {
+            if let Err(e) = upload.abort().await {
+                debug!("Failed aborting the upload of {location}: {e}");
+            }
+        }
+        result
+    }
+}
+
+/// Adds the files under `dir` to `files` by their path relative to `root`,
+/// leaving out the lock, the write probe and temporary files
+fn collect_files(root: &Path, dir: &Path, files: &mut BTreeMap<String, PathBuf>) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        if path.is_dir() {
+            collect_files(root, &path, files)?;
+        } else if path.extension().is_some_and(|e| e == "inprogress")
+            || file_name == LOCK_FILE_NAME
+            || file_name == PROBE_FILE_NAME
+        {
+            continue;
+        } else {
+            let relative = path.strip_prefix(root).unwrap_or(&path);
+            let name = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            files.insert(name, path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::retry::testing::{throttled, unauthenticated, FlakyStore};
+
+    fn uploader(store: &Arc<FlakyStore>, retries: u32) -> Uploader {
+        Uploader::new(
+            Arc::clone(store) as Arc<dyn ObjectStore>,
+            ObjectPath::from("bench/sf1"),
+            RetryPolicy::new(retries, 1),
+        )
+        .with_part_bytes(4)
+    }
+
+    async fn read(store: &FlakyStore, location: &str) -> Vec<u8> {
+        let location = ObjectPath::from(location);
+        let result = store.get(&location).await.unwrap();
+        result.bytes().await.unwrap().to_vec()
+    }
+
+    fn output_dir() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("zone")).unwrap();
+        std::fs::write(dir.path().join("zone/zone.1.parquet"), b"0123456789").unwrap();
+        std::fs::write(dir.path().join("zone/zone.2.parquet"), b"abc").unwrap();
+        std::fs::write(dir.path().join("zone/zone.3.inprogress"), b"partial").unwrap();
+        std::fs::write(dir.path().join("zone").join(MANIFEST_FILE_NAME), b"{}").unwrap();
+        std::fs::write(dir.path().join(LOCK_FILE_NAME), b"lock").unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn test_upload_dir_survives_transient_failures() {
+        let dir = output_dir();
+        // Every attempt of the first part, multipart or not, fails twice
+        let store = Arc::new(FlakyStore::new(2, throttled));
+        let summary = uploader(&store, 3).upload_dir(dir.path()).await.unwrap();
+
+        assert_eq!(
+            summary,
+            UploadSummary {
+                files: 3,
+                bytes: 15
+            }
+        );
+        assert_eq!(
+            read(&store, "bench/sf1/zone/zone.1.parquet").await,
+            b"0123456789"
+        );
+        assert_eq!(read(&store, "bench/sf1/zone/zone.2.parquet").await, b"abc");
+        let manifest = format!("bench/sf1/zone/{MANIFEST_FILE_NAME}");
+        assert_eq!(read(&store, &manifest).await, b"{}");
+        // Three parts and a completion for the first file, a put for each
+        // of the others, and the two failures
+        assert_eq!(store.requests(), 4 + 2 + 2);
+
+        let listed: Vec<_> = futures::TryStreamExt::try_collect::<Vec<_>>(store.list(None))
+            .await
+            .unwrap();
+        assert_eq!(listed.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_upload_fails_after_retries() {
+        let dir = output_dir();
+        let store = Arc::new(FlakyStore::new(10, throttled));
+        let err = uploader(&store, 2)
+            .upload_dir(dir.path())
+            .await
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("zone.1.parquet: Generic S3 error"),
+            "unexpected error: {err}"
+        );
+        assert_eq!(store.requests(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_upload_does_not_retry_authentication_errors() {
+        let dir = output_dir();
+        let store = Arc::new(FlakyStore::new(1, unauthenticated));
+        uploader(&store, 3)
+            .upload_dir(dir.path())
+            .await
+            .unwrap_err();
+        assert_eq!(store.requests(), 1);
+    }
+
+    #[test]
+    fn test_upload_url_must_be_remote() {
+        let err = Uploader::for_url("file:///tmp/out", RetryPolicy::default()).unwrap_err();
+        assert!(err.to_string().contains("Unsupported upload URL"), "{err}");
+    }
+}
@@ -0,0 +1,431 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Zone neighbor adjacency table
+//!
+//! Materializes every pair of zones whose boundaries touch or overlap as
+//! `zone_adjacency.parquet`, one `(z_zonekey_a, z_zonekey_b)` row per pair
+//! with `z_zonekey_a < z_zonekey_b`. All zones are indexed in an R-tree of
+//! their bounding boxes, then the zone files are streamed a second time a
+//! few batches at a time and every zone is tested against the indexed
+//! zones whose boxes it meets, so only the index and the pairs of the
+//! batches in flight are held in memory. A zone key seen more than once
+//! keeps its first boundary.
+
+use anyhow::{anyhow, Result};
+use arrow::compute::{cast, filter_record_batch};
+use arrow_array::{Array, BinaryArray, BooleanArray, Int64Array, RecordBatch, StringArray};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use geo::{BoundingRect, Geometry, Intersects};
+use geozero::wkb::Wkb;
+use geozero::ToGeo;
+use log::{info, warn};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::arrow::{ArrowWriter, ProjectionMask};
+use rstar::primitives::{GeomWithData, Rectangle};
+use rstar::{RTree, RTreeObject};
+use std::collections::HashSet;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use super::batch::map_batches;
+use super::containment::{binary_column, int64_column};
+use crate::load_scripts::TableFiles;
+
+/// File written into the data directory
+pub const OUTPUT_FILE: &str = "zone_adjacency.parquet";
+
+struct Zone {
+    zonekey: i64,
+    subtype: Option<String>,
+    geometry: Geometry,
+}
+
+/// Zone boundaries indexed by their bounding boxes
+pub struct AdjacencyIndex {
+    zones: Vec<Zone>,
+    tree: RTree<GeomWithData<Rectangle<[f64; 2]>, usize>>,
+    same_subtype_only: bool,
+}
+
+impl AdjacencyIndex {
+    /// Indexes the `z_zonekey` and `z_boundary` columns of `batches`, and
+    /// `z_subtype` when pairs must share it. Zones whose boundary is null
+    /// or not valid WKB are skipped.
+    pub fn new<'a>(
+        batches: impl IntoIterator<Item = &'a RecordBatch>,
+        same_subtype_only: bool,
+    ) -> Result<Self> {
+        let mut zones = Vec::new();
+        let mut seen = HashSet::new();
+        let mut skipped = 0;
+        for batch in batches {
+            let rows = ZoneRows::try_new(batch, same_subtype_only)?;
+            for row in 0..batch.num_rows() {
+                match rows.zone(row) {
+                    Some(zone) if seen.insert(zone.zonekey) => zones.push(zone),
+                    Some(_) => {}
+                    None => skipped += 1,
+                }
+            }
+        }
+        if skipped > 0 {
+            warn!("Skipped {skipped} zone(s) without a key or a readable boundary");
+        }
+
+        let entries = zones
+            .iter()
+            .enumerate()
+            .filter_map(|(i, zone)| Some(GeomWithData::new(envelope(&zone.geometry)?, i)))
+            .collect();
+        Ok(Self {
+            zones,
+            tree: RTree::bulk_load(entries),
+            same_subtype_only,
+        })
+    }
+
+    /// Reads the zone columns of `files`, one batch at a time
+    pub fn read(files: &[PathBuf], same_subtype_only: bool) -> Result<Self> {
+        let mut batches = Vec::new();
+        for path in files {
+            for batch in read_zones(path, same_subtype_only)? {
+                batches.push(batch?);
+            }
+        }
+        Self::new(&batches, same_subtype_only)
+    }
+
+    pub fn len(&self) -> usize {
+        self.zones.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.zones.is_empty()
+    }
+
+    /// Keys of the indexed zones with a larger key than `zone` whose
+    /// boundary intersects it, in key order
+    fn neighbors_of(&self, zone: &Zone) -> Vec<i64> {
+        let Some(rect) = envelope(&zone.geometry) else {
+            return Vec::new();
+        };
+        let mut keys: Vec<i64> = self
+            .tree
+            .locate_in_envelope_intersecting(&rect.envelope())
+            .map(|entry| &self.zones[entry.data])
+            .filter(|other| other.zonekey > zone.zonekey)
+            .filter(|other| !self.same_subtype_only || other.subtype == zone.subtype)
+            .filter(|other| other.geometry.intersects(&zone.geometry))
+            .map(|other| other.zonekey)
+            .collect();
+        keys.sort_unstable();
+        keys
+    }
+
+    /// Adjacent pairs of the zones of `batch` with the indexed zones
+    pub fn pairs(&self, batch: &RecordBatch) -> Result<RecordBatch> {
+        let rows = ZoneRows::try_new(batch, self.same_subtype_only)?;
+        let mut a = Vec::new();
+        let mut b = Vec::new();
+        for zone in (0..batch.num_rows()).filter_map(|row| rows.zone(row)) {
+            for neighbor in self.neighbors_of(&zone) {
+                a.push(zone.zonekey);
+                b.push(neighbor);
+            }
+        }
+        Ok(RecordBatch::try_new(
+            output_schema(),
+            vec![Arc::new(Int64Array::from(a)), Arc::new(Int64Array::from(b))],
+        )?)
+    }
+}
+
+/// The zone columns of one batch
+struct ZoneRows {
+    keys: Int64Array,
+    boundaries: BinaryArray,
+    subtypes: Option<StringArray>,
+}
+
+impl ZoneRows {
+    fn try_new(batch: &RecordBatch, with_subtype: bool) -> Result<Self> {
+        let boundaries = binary_column(batch, "z_boundary")?;
+        let subtypes = match with_subtype {
+            true => {
+                let column = batch
+                    .column_by_name("z_subtype")
+                    .ok_or_else(|| anyhow!("Column z_subtype not found"))?;
+                let column = cast(column, &DataType::Utf8)?;
+                Some(
+                    column
+                        .as_any()
+                        .downcast_ref::<StringArray>()
+                        .unwrap()
+                        .clone(),
+                )
+            }
+            false => None,
+        };
+        Ok(Self {
+            keys: int64_column(batch, "z_zonekey")?,
+            boundaries: boundaries
+                .as_any()
+                .downcast_ref::<BinaryArray>()
+                .unwrap()
+                .clone(),
+            subtypes,
+        })
+    }
+
+    /// The zone of `row`, `None` without a key or a readable boundary
+    fn zone(&self, row: usize) -> Option<Zone> {
+        if self.keys.is_null(row) || self.boundaries.is_null(row) {
+            return None;
+        }
+        let geometry = Wkb(self.boundaries.value(row)).to_geo().ok()?;
+        let subtype = self
+            .subtypes
+            .as_ref()
+            .filter(|subtypes| subtypes.is_valid(row))
+            .map(|subtypes| subtypes.value(row).to_string());
+        Some(Zone {
+            zonekey: self.keys.value(row),
+            subtype,
+            geometry,
+        })
+    }
+}
+
+fn envelope(geometry: &Geometry) -> Option<Rectangle<[f64; 2]>> {
+    let rect = geometry.bounding_rect()?;
+    let (min, max) = (rect.min(), rect.max());
+    Some(Rectangle::from_corners([min.x, min.y], [max.x, max.y]))
+}
+
+fn read_zones(
+    path: &Path,
+    with_subtype: bool,
+) -> Result<impl Iterator<Item = std::result::Result<RecordBatch, arrow_schema::ArrowError>>> {
+    let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(path)?)?;
+    let columns: &[&str] = match with_subtype {
+        true => &["z_zonekey", "z_boundary", "z_subtype"],
+        false => &["z_zonekey", "z_boundary"],
+    };
+    let mask = ProjectionMask::columns(builder.parquet_schema(), columns.iter().copied());
+    Ok(builder.with_projection(mask).build()?)
+}
+
+/// Schema of `zone_adjacency.parquet`
+pub fn output_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("z_zonekey_a", DataType::Int64, false),
+        Field::new("z_zonekey_b", DataType::Int64, false),
+    ]))
+}
+
+/// Zones indexed and adjacent pairs written
+#[derive(Debug, Default, PartialEq)]
+pub struct AdjacencySummary {
+    pub zones: u64,
+    pub pairs: u64,
+}
+
+/// Writes the adjacent zone pairs of `data_dir` into
+/// `zone_adjacency.parquet`, testing up to `jobs` zone batches at a time
+/// in parallel
+pub fn materialize_zone_adjacency(
+    data_dir: &Path,
+    jobs: usize,
+    same_subtype_only: bool,
+) -> Result<AdjacencySummary> {
+    let zones = TableFiles::find(data_dir, "zone")?;
+    let index = AdjacencyIndex::read(&zones.files, same_subtype_only)?;
+    info!(
+        "Indexed {} zone(s) from {}",
+        index.len(),
+        zones.path.display()
+    );
+    if index.is_empty() {
+        warn!(
+            "No readable zones in {}, no pairs will be written",
+            zones.path.display()
+        );
+    }
+
+    let output_path = data_dir.join(OUTPUT_FILE);
+    let temp_path = output_path.with_extension("inprogress");
+    let mut writer = ArrowWriter::try_new(File::create(&temp_path)?, output_schema(), None)?;
+
+    let jobs = jobs.max(1);
+    let mut summary = AdjacencySummary {
+        zones: index.len() as u64,
+        ..Default::default()
+    };
+    let mut probed = HashSet::new();
+    let mut write = |batches: &mut Vec<RecordBatch>| -> Result<()> {
+        for pairs in map_batches(batches, Some(jobs), |_, batch| index.pairs(batch))? {
+            summary.pairs += pairs.num_rows() as u64;
+            writer.write(&pairs)?;
+        }
+        batches.clear();
+        Ok(())
+    };
+    for path in &zones.files {
+        let mut pending = Vec::with_capacity(jobs);
+        for batch in read_zones(path, same_subtype_only)? {
+            // Probe every key once, as the index keeps a single boundary
+            // per key
+            let batch = batch?;
+            let keys = int64_column(&batch, "z_zonekey")?;
+            let first: BooleanArray = keys
+                .iter()
+                .map(|key| Some(key.is_some_and(|key| probed.insert(key))))
+                .collect();
+            pending.push(filter_record_batch(&batch, &first)?);
+            if pending.len() == jobs {
+                write(&mut pending)?;
+            }
+        }
+        write(&mut pending)?;
+    }
+    writer.close()?;
+    std::fs::rename(&temp_path, &output_path)?;
+
+    info!(
+        "Wrote {} adjacent pair(s) to {}",
+        summary.pairs,
+        output_path.display()
+    );
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zone::fixtures::wkb_polygon;
+
+    fn square(x: f64, y: f64) -> Vec<u8> {
+        wkb_polygon(&[
+            (x, y),
+            (x + 1.0, y),
+            (x + 1.0, y + 1.0),
+            (x, y + 1.0),
+            (x, y),
+        ])
+    }
+
+    fn zones(zones: &[(i64, &str, Vec<u8>)]) -> RecordBatch {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("z_zonekey", DataType::Int64, false),
+            Field::new("z_subtype", DataType::Utf8, false),
+            Field::new("z_boundary", DataType::Binary, false),
+        ]));
+        RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(Int64Array::from_iter_values(zones.iter().map(|z| z.0))),
+                Arc::new(StringArray::from_iter_values(zones.iter().map(|z| z.1))),
+                Arc::new(BinaryArray::from_iter_values(
+                    zones.iter().map(|z| z.2.as_slice()),
+                )),
+            ],
+        )
+        .unwrap()
+    }
+
+    /// A 2×2 grid of unit squares and one far away square
+    ///
+    /// ```text
+    /// 3 4
+    /// 1 2     9
+    /// ```
+    fn grid() -> RecordBatch {
+        zones(&[
+            (4, "county", square(1.0, 1.0)),
+            (1, "county", square(0.0, 0.0)),
+            (2, "locality", square(1.0, 0.0)),
+            (3, "county", square(0.0, 1.0)),
+            (9, "county", square(5.0, 0.0)),
+        ])
+    }
+
+    fn pair_list(index: &AdjacencyIndex, batch: &RecordBatch) -> Vec<(i64, i64)> {
+        let pairs = index.pairs(batch).unwrap();
+        let column = |i: usize| {
+            pairs
+                .column(i)
+                .as_any()
+                .downcast_ref::<Int64Array>()
+                .unwrap()
+                .values()
+                .to_vec()
+        };
+        let mut pairs: Vec<_> = column(0).into_iter().zip(column(1)).collect();
+        pairs.sort();
+        pairs
+    }
+
+    #[test]
+    fn test_touching_pairs() {
+        let index = AdjacencyIndex::new(&[grid()], false).unwrap();
+        assert_eq!(index.len(), 5);
+        // Edge and corner neighbors, each pair once with the smaller key
+        // first
+        assert_eq!(
+            pair_list(&index, &grid()),
+            vec![(1, 2), (1, 3), (1, 4), (2, 3), (2, 4), (3, 4)]
+        );
+    }
+
+    #[test]
+    fn test_same_subtype_only() {
+        let index = AdjacencyIndex::new(&[grid()], true).unwrap();
+        assert_eq!(pair_list(&index, &grid()), vec![(1, 3), (1, 4), (3, 4)]);
+    }
+
+    #[test]
+    fn test_materialize_streams_zone_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let zone_dir = dir.path().join("zone");
+        std::fs::create_dir(&zone_dir).unwrap();
+        let grid = grid();
+        // Split over two files, with zone 1 repeated in the second
+        for (name, rows) in [("zone.1.parquet", 0..3), ("zone.2.parquet", 1..5)] {
+            let batch = grid.slice(rows.start, rows.len());
+            let file = File::create(zone_dir.join(name)).unwrap();
+            let mut writer = ArrowWriter::try_new(file, grid.schema(), None).unwrap();
+            writer.write(&batch).unwrap();
+            writer.close().unwrap();
+        }
+
+        let summary = materialize_zone_adjacency(dir.path(), 2, false).unwrap();
+        assert_eq!(summary, AdjacencySummary { zones: 5, pairs: 6 });
+
+        let file = File::open(dir.path().join(OUTPUT_FILE)).unwrap();
+        let batches = ParquetRecordBatchReaderBuilder::try_new(file)
+            .unwrap()
+            .build()
+            .unwrap()
+            .map(Result::unwrap)
+            .collect::<Vec<_>>();
+        let rows: usize = batches.iter().map(|b| b.num_rows()).sum();
+        assert_eq!(rows, 6);
+        assert!(!dir.path().join("zone_adjacency.inprogress").exists());
+    }
+}
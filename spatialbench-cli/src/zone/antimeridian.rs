@@ -0,0 +1,171 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Bounding boxes and centroids of zones crossing the 180° meridian
+//!
+//! Zone boundaries are in longitude and latitude, and a zone crossing the
+//! antimeridian has vertices near both +180 and -180. Planar math then
+//! sees a zone spanning almost the whole globe, with its centroid near the
+//! prime meridian. When the geometry is narrower with its western
+//! longitudes shifted by 360°, it is treated as crossing: bounds and
+//! centroid are computed on the shifted geometry and wrapped back into
+//! -180..180, so a crossing bbox has `min_lon > max_lon` as in RFC 7946.
+
+use geo::{BoundingRect, Centroid, Coord, Geometry, MapCoords};
+
+/// Longitude and latitude bounds of a geometry. `min_lon` is the western
+/// edge, so it is greater than `max_lon` when the geometry crosses the
+/// antimeridian.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LonLatBounds {
+    pub min_lon: f64,
+    pub min_lat: f64,
+    pub max_lon: f64,
+    pub max_lat: f64,
+}
+
+/// Bounds of `geometry`, `None` when it is empty. Crossing the
+/// antimeridian is only considered when `antimeridian_aware` is set.
+pub fn bounds(geometry: &Geometry, antimeridian_aware: bool) -> Option<LonLatBounds> {
+    let rect = geometry.bounding_rect()?;
+    let (min_lon, max_lon) = match antimeridian_aware
+        .then(|| shifted_bounds(geometry))
+        .flatten()
+    {
+        Some((min, max)) => (wrap(min), wrap(max)),
+        None => (rect.min().x, rect.max().x),
+    };
+    Some(LonLatBounds {
+        min_lon,
+        min_lat: rect.min().y,
+        max_lon,
+        max_lat: rect.max().y,
+    })
+}
+
+/// Centroid of `geometry` as `(lon, lat)`, `None` when it is empty.
+/// Crossing the antimeridian is only considered when `antimeridian_aware`
+/// is set.
+pub fn centroid(geometry: &Geometry, antimeridian_aware: bool) -> Option<(f64, f64)> {
+    let point = if antimeridian_aware && shifted_bounds(geometry).is_some() {
+        geometry.map_coords(shift).centroid()?
+    } else {
+        geometry.centroid()?
+    };
+    Some((wrap(point.x()), point.y()))
+}
+
+/// The longitude range of `geometry` with its western longitudes shifted
+/// east by 360°, when that is narrower than the unshifted range
+fn shifted_bounds(geometry: &Geometry) -> Option<(f64, f64)> {
+    let rect = geometry.bounding_rect()?;
+    let shifted = geometry.map_coords(shift).bounding_rect()?;
+    let width = rect.max().x - rect.min().x;
+    let shifted_width = shifted.max().x - shifted.min().x;
+    (shifted_width < width).then(|| (shifted.min().x, shifted.max().x))
+}
+
+fn shift(coord: Coord) -> Coord {
+    if coord.x < 0.0 {
+        Coord {
+            x: coord.x + 360.0,
+            y: coord.y,
+        }
+    } else {
+        coord
+    }
+}
+
+/// Brings a shifted longitude back into -180..=180
+fn wrap(lon: f64) -> f64 {
+    if lon > 180.0 {
+        lon - 360.0
+    } else {
+        lon
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zone::fixtures::wkb_donut;
+    use geo::{polygon, Geometry};
+    use geozero::wkb::Wkb;
+    use geozero::ToGeo;
+
+    /// Fiji-like polygon from 178°E to 178°W
+    fn straddling() -> Geometry {
+        Geometry::Polygon(polygon![
+            (x: 178.0, y: -18.0),
+            (x: -178.0, y: -18.0),
+            (x: -178.0, y: -16.0),
+            (x: 178.0, y: -16.0),
+            (x: 178.0, y: -18.0),
+        ])
+    }
+
+    #[test]
+    fn test_bounds_of_polygon_straddling_antimeridian() {
+        let bounds = bounds(&straddling(), true).unwrap();
+        assert_eq!(
+            bounds,
+            LonLatBounds {
+                min_lon: 178.0,
+                min_lat: -18.0,
+                max_lon: -178.0,
+                max_lat: -16.0,
+            }
+        );
+
+        // The naive bbox spans the whole globe
+        let naive = super::bounds(&straddling(), false).unwrap();
+        assert_eq!((naive.min_lon, naive.max_lon), (-178.0, 178.0));
+    }
+
+    #[test]
+    fn test_centroid_of_polygon_straddling_antimeridian() {
+        let (lon, lat) = centroid(&straddling(), true).unwrap();
+        assert!((lon.abs() - 180.0).abs() < 1e-9, "{lon}");
+        assert!((lat + 17.0).abs() < 1e-9, "{lat}");
+
+        let (naive_lon, _) = centroid(&straddling(), false).unwrap();
+        assert!(naive_lon.abs() < 1e-9, "{naive_lon}");
+    }
+
+    #[test]
+    fn test_centroid_of_multipolygon_weighted_by_area_without_holes() {
+        let geometry = Wkb(wkb_donut()).to_geo().unwrap();
+        // The holed part (area 12, centroid (2, 2)) and the square (area 4,
+        // centroid (11, 1)); counting the hole would give (3.8, 1.8)
+        let (lon, lat) = centroid(&geometry, true).unwrap();
+        assert!((lon - 4.25).abs() < 1e-9, "{lon}");
+        assert!((lat - 1.75).abs() < 1e-9, "{lat}");
+    }
+
+    #[test]
+    fn test_polygons_away_from_antimeridian_unchanged() {
+        let geometry = Geometry::Polygon(polygon![
+            (x: -10.0, y: 0.0),
+            (x: 10.0, y: 0.0),
+            (x: 10.0, y: 5.0),
+            (x: -10.0, y: 5.0),
+            (x: -10.0, y: 0.0),
+        ]);
+        assert_eq!(bounds(&geometry, true), bounds(&geometry, false));
+        assert_eq!(centroid(&geometry, true), centroid(&geometry, false));
+    }
+}
@@ -0,0 +1,225 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! `z_area` of `z_boundary`, computed in an area CRS
+//!
+//! Geometries are reprojected to the area CRS only to measure them; the
+//! written `z_boundary` stays in CRS84. Areas in an equal-area projection
+//! are in square meters, while planar CRS84 areas are in square degrees.
+
+use anyhow::{anyhow, Result};
+use arrow::compute::cast;
+use arrow_array::{Array, BinaryArray, Float64Array, RecordBatch};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use clap::ValueEnum;
+use geo::{Area, MapCoords};
+use geozero::wkb::Wkb;
+use geozero::ToGeo;
+use std::sync::Arc;
+
+use super::batch::map_batches;
+
+pub const AREA_COLUMN: &str = "z_area";
+
+/// WGS 84 semi-major axis in meters
+const WGS84_A: f64 = 6_378_137.0;
+/// WGS 84 first eccentricity squared
+const WGS84_E2: f64 = 0.006_694_379_990_141_317;
+
+/// CRS the `z_area` of every zone is measured in
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum AreaCrs {
+    /// WGS 84 / NSIDC EASE-Grid 2.0 Global, a cylindrical equal-area
+    /// projection of the WGS 84 ellipsoid; areas in square meters
+    #[default]
+    #[value(name = "EPSG:6933")]
+    Epsg6933,
+    /// Longitude and latitude taken as planar coordinates; areas in square
+    /// degrees, which shrink in meters away from the equator
+    #[value(name = "OGC:CRS84")]
+    Crs84,
+}
+
+impl AreaCrs {
+    /// Projects a CRS84 `(lon, lat)` to this CRS
+    fn project(self, lon: f64, lat: f64) -> (f64, f64) {
+        match self {
+            Self::Crs84 => (lon, lat),
+            Self::Epsg6933 => {
+                // Lambert cylindrical equal-area (ellipsoidal), EPSG method
+                // 9835, with standard parallel 30°
+                let e = WGS84_E2.sqrt();
+                let sin_1 = 30f64.to_radians().sin();
+                let k0 = 30f64.to_radians().cos() / (1.0 - WGS84_E2 * sin_1 * sin_1).sqrt();
+                let sin = lat.to_radians().sin();
+                let q = (1.0 - WGS84_E2)
+                    * (sin / (1.0 - WGS84_E2 * sin * sin)
+                        - ((1.0 - e * sin) / (1.0 + e * sin)).ln() / (2.0 * e));
+                (WGS84_A * k0 * lon.to_radians(), WGS84_A * q / (2.0 * k0))
+            }
+        }
+    }
+
+    /// Area of a WKB geometry in this CRS, `None` for WKB that doesn't
+    /// parse
+    pub fn area(self, wkb: &[u8]) -> Option<f64> {
+        let geometry = Wkb(wkb).to_geo().ok()?;
+        let projected = geometry.map_coords(|c| {
+            let (x, y) = self.project(c.x, c.y);
+            geo::coord! { x: x, y: y }
+        });
+        Some(projected.unsigned_area())
+    }
+}
+
+/// `schema` with the `z_area` column appended
+pub fn output_schema(schema: SchemaRef) -> SchemaRef {
+    let mut fields: Vec<Field> = schema.fields().iter().map(|f| f.as_ref().clone()).collect();
+    fields.push(Field::new(AREA_COLUMN, DataType::Float64, true));
+    Arc::new(Schema::new_with_metadata(fields, schema.metadata().clone()))
+}
+
+/// Appends the `z_area` of every row's `z_boundary` in `crs`, null where the
+/// geometry is null or not valid WKB
+pub fn append_area_column(
+    batches: Vec<RecordBatch>,
+    crs: AreaCrs,
+    threads: Option<usize>,
+) -> Result<Vec<RecordBatch>> {
+    map_batches(&batches, threads, |_, batch| {
+        let geometries = batch
+            .column_by_name("z_boundary")
+            .ok_or_else(|| anyhow!("Column z_boundary not found in zone batch"))?;
+        let geometries = cast(geometries, &DataType::Binary)?;
+        let geometries = geometries
+            .as_any()
+            .downcast_ref::<BinaryArray>()
+            .ok_or_else(|| anyhow!("Column z_boundary is not a binary column"))?;
+
+        let areas: Float64Array = geometries
+            .iter()
+            .map(|wkb| wkb.and_then(|wkb| crs.area(wkb)))
+            .collect();
+        let mut columns = batch.columns().to_vec();
+        columns.push(Arc::new(areas));
+        Ok(RecordBatch::try_new(
+            output_schema(batch.schema()),
+            columns,
+        )?)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zone::fixtures::{wkb_donut, wkb_polygon};
+
+    fn square(lon: f64, lat: f64) -> Vec<u8> {
+        wkb_polygon(&[
+            (lon, lat),
+            (lon + 1.0, lat),
+            (lon + 1.0, lat + 1.0),
+            (lon, lat + 1.0),
+            (lon, lat),
+        ])
+    }
+
+    #[test]
+    fn test_equal_area_of_one_degree_box_at_equator() {
+        // An ellipsoidal 1°×1° cell at the equator covers about 12,308 km²
+        let km2 = AreaCrs::Epsg6933.area(&square(0.0, 0.0)).unwrap() / 1e6;
+        assert!((12_250.0..12_350.0).contains(&km2), "{km2} km²");
+
+        // The same cell at 60° covers about half as much
+        let km2 = AreaCrs::Epsg6933.area(&square(20.0, 60.0)).unwrap() / 1e6;
+        assert!((6_000.0..6_300.0).contains(&km2), "{km2} km²");
+        // while its planar area in degrees doesn't change
+        assert_eq!(AreaCrs::Crs84.area(&square(20.0, 60.0)), Some(1.0));
+    }
+
+    #[test]
+    fn test_area_of_multipolygon_excludes_holes() {
+        // 16 - 4 for the holed part, plus 4 for the other one
+        assert_eq!(AreaCrs::Crs84.area(&wkb_donut()), Some(16.0));
+
+        let crs = AreaCrs::Epsg6933;
+        let ring = |x0: f64, y0: f64, size: f64| {
+            wkb_polygon(&[
+                (x0, y0),
+                (x0 + size, y0),
+                (x0 + size, y0 + size),
+                (x0, y0 + size),
+                (x0, y0),
+            ])
+        };
+        let expected = crs.area(&ring(0.0, 0.0, 4.0)).unwrap()
+            - crs.area(&ring(1.0, 1.0, 2.0)).unwrap()
+            + crs.area(&ring(10.0, 0.0, 2.0)).unwrap();
+        let area = crs.area(&wkb_donut()).unwrap();
+        assert!((area - expected).abs() < 1e-6 * expected, "{area} m²");
+    }
+
+    #[test]
+    fn test_projection_matches_epsg_6933() {
+        // EASE-Grid 2.0 corners: ±180° spans ±17367530.45 m and ±85.0445664°
+        // latitude ±7314540.83 m
+        let (x, y) = AreaCrs::Epsg6933.project(180.0, 85.0445664);
+        assert!((x - 17_367_530.45).abs() < 0.01, "{x}");
+        assert!((y - 7_314_540.83).abs() < 1.0, "{y}");
+    }
+
+    #[test]
+    fn test_area_column() {
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "z_boundary",
+            DataType::Binary,
+            true,
+        )]));
+        let square = square(0.0, 0.0);
+        let batch = RecordBatch::try_new(
+            schema,
+            vec![Arc::new(BinaryArray::from(vec![
+                Some(square.as_slice()),
+                None,
+                Some(b"not wkb".as_slice()),
+            ]))],
+        )
+        .unwrap();
+
+        let batches = append_area_column(vec![batch], AreaCrs::Crs84, None).unwrap();
+        let areas = batches[0]
+            .column_by_name(AREA_COLUMN)
+            .unwrap()
+            .as_any()
+            .downcast_ref::<Float64Array>()
+            .unwrap();
+        assert_eq!(
+            areas.iter().collect::<Vec<_>>(),
+            vec![Some(1.0), None, None]
+        );
+        // The geometry is written unchanged
+        assert_eq!(
+            batches[0]
+                .column(0)
+                .as_any()
+                .downcast_ref::<BinaryArray>()
+                .unwrap()
+                .value(0),
+            square.as_slice()
+        );
+    }
+}
@@ -0,0 +1,148 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Attribution of the Overture data the zones are generated from
+//!
+//! `--include-attribution` writes [`ATTRIBUTION_FILE_NAME`] into the output
+//! directory and records its SHA-256 in the zone manifest, so `verify` can
+//! tell when the notice was stripped from a published dataset.
+//! `--attribution-column` also adds a constant [`ATTRIBUTION_COLUMN`].
+
+use anyhow::Result;
+use log::debug;
+use sha2::{Digest, Sha256};
+use std::path::Path;
+
+use super::datasource::{source_provenance, OVERTURE_RELEASE_DATE};
+use super::theme::ThemeInput;
+
+/// Name of the attribution notice in the output directory
+pub const ATTRIBUTION_FILE_NAME: &str = "ATTRIBUTION.txt";
+
+/// Column of the [`source_attribution`] added by `--attribution-column`
+pub const ATTRIBUTION_COLUMN: &str = "z_attribution";
+
+const PUBLISHER: &str = "Overture Maps Foundation";
+
+/// The attribution of the zones read from `themes`, e.g. `Overture Maps
+/// Foundation 2025-08-20.1`. The release of inputs read from other
+/// locations is unknown and left out.
+pub fn source_attribution(themes: &[ThemeInput]) -> String {
+    match themes.iter().all(|input| input.location.is_none()) {
+        true => format!("{PUBLISHER} {OVERTURE_RELEASE_DATE}"),
+        false => PUBLISHER.to_string(),
+    }
+}
+
+/// The text of the [`ATTRIBUTION_FILE_NAME`] notice for zones read from
+/// `themes`
+pub fn attribution_text(themes: &[ThemeInput]) -> String {
+    let mut text = format!(
+        "The zone table of this dataset was generated by spatialbench-cli from\n\
+         the divisions theme of Overture Maps.\n\
+         \n\
+         Source: {PUBLISHER}, https://overturemaps.org\n\
+         Input: {}\n\
+         License: ODbL 1.0, https://opendatacommons.org/licenses/odbl/1-0/\n\
+         Attribution: © OpenStreetMap contributors, {PUBLISHER}\n\
+         \n\
+         Redistributions of the zone table must keep this attribution.\n",
+        source_provenance(themes)
+    );
+    if themes.iter().any(|input| input.location.is_some()) {
+        text.push_str(
+            "Inputs read from local paths or URLs are assumed to be Overture data; their\n\
+             publisher's attribution requirements apply.\n",
+        );
+    }
+    text
+}
+
+/// Lowercase hex SHA-256 of an attribution notice
+pub fn attribution_sha256(text: &str) -> String {
+    Sha256::digest(text.as_bytes())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Writes the attribution notice of `themes` into `output_dir`
+pub fn write_attribution(output_dir: &Path, themes: &[ThemeInput]) -> Result<()> {
+    let path = output_dir.join(ATTRIBUTION_FILE_NAME);
+    let temp_path = path.with_extension("inprogress");
+    std::fs::write(&temp_path, attribution_text(themes))?;
+    std::fs::rename(&temp_path, &path)?;
+    debug!("Wrote {}", path.display());
+    Ok(())
+}
+
+/// Checks the attribution notice in `data_dir` against the `recorded`
+/// hash, returning what is wrong with it
+pub fn check_attribution(data_dir: &Path, recorded: &str) -> Result<Option<String>> {
+    let path = data_dir.join(ATTRIBUTION_FILE_NAME);
+    if !path.is_file() {
+        return Ok(Some(format!("{ATTRIBUTION_FILE_NAME} is missing")));
+    }
+    let text = std::fs::read_to_string(&path)?;
+    Ok((attribution_sha256(&text) != recorded)
+        .then(|| format!("{ATTRIBUTION_FILE_NAME} doesn't match its recorded hash")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zone::theme::Theme;
+
+    #[test]
+    fn test_attribution_names_the_release() {
+        let built_in = vec![ThemeInput::built_in(Theme::DivisionArea)];
+        assert_eq!(
+            source_attribution(&built_in),
+            format!("Overture Maps Foundation {OVERTURE_RELEASE_DATE}")
+        );
+        let text = attribution_text(&built_in);
+        assert!(text.contains(&format!("division_area=overture:{OVERTURE_RELEASE_DATE}")));
+        assert!(!text.contains("assumed to be Overture data"));
+
+        let local = vec![ThemeInput {
+            theme: Theme::DivisionArea,
+            location: Some("/data/division_area".to_string()),
+        }];
+        assert_eq!(source_attribution(&local), "Overture Maps Foundation");
+        assert!(attribution_text(&local).contains("assumed to be Overture data"));
+    }
+
+    #[test]
+    fn test_check_attribution() {
+        let dir = tempfile::tempdir().unwrap();
+        let themes = vec![ThemeInput::built_in(Theme::DivisionArea)];
+        let recorded = attribution_sha256(&attribution_text(&themes));
+        assert_eq!(
+            check_attribution(dir.path(), &recorded).unwrap().unwrap(),
+            "ATTRIBUTION.txt is missing"
+        );
+
+        write_attribution(dir.path(), &themes).unwrap();
+        assert_eq!(check_attribution(dir.path(), &recorded).unwrap(), None);
+
+        std::fs::write(dir.path().join(ATTRIBUTION_FILE_NAME), "edited").unwrap();
+        assert!(check_attribution(dir.path(), &recorded)
+            .unwrap()
+            .unwrap()
+            .contains("doesn't match"));
+    }
+}
@@ -0,0 +1,507 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Adding derived columns to the files of a generated zone dataset
+//!
+//! The bounds, area and extra columns are computed from `z_boundary`
+//! alone, so `augment` appends them to the existing files instead of
+//! reading the source again. Every file is rewritten one row group at a
+//! time into a temporary file that replaces it, keeping its rows, row
+//! groups, compression and footer metadata; the footer also lists the
+//! added columns under [`AUGMENTED_KEY`]. Files that already hold the
+//! columns are skipped, so an interrupted run is resumed by running it
+//! again.
+
+use anyhow::{anyhow, Result};
+use arrow_array::RecordBatch;
+use arrow_schema::{DataType, Schema};
+use clap::ValueEnum;
+use log::{debug, info};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::metadata::KeyValue;
+use parquet::file::properties::WriterProperties;
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::dataset::DatasetManifest;
+
+use super::area::{append_area_column, AreaCrs, AREA_COLUMN};
+use super::bbox::{append_bbox_column, geo_metadata, BboxLayout, GEO_METADATA_KEY};
+use super::extra_columns::{append_extra_columns, ExtraColumn};
+use super::hash::{hash_parquet_file, read_footer_hash, ContentHasher, CONTENT_SHA256_KEY};
+use super::manifest::{ManifestColumn, ZoneManifest};
+use super::schema::{SchemaVersion, SCHEMA_VERSION_KEY};
+use super::writer::parquet_footer_bytes;
+
+/// Footer key listing the columns added by `augment`, comma separated
+pub const AUGMENTED_KEY: &str = "spatialbench.augmented";
+
+/// Key under which the Arrow schema is stored in the footer, written anew
+/// by the Parquet writer
+const ARROW_SCHEMA_KEY: &str = "ARROW:schema";
+
+/// A derived column `augment` can add
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum AugmentColumn {
+    /// The bounds of `--with-bbox-covering`, in the `--bbox-layout`
+    Bbox,
+    /// The `z_area` of `--with-area`, in the `--area-crs`
+    Area,
+    /// `z_vertexcount` of `--extra-columns vertexcount`
+    #[value(name = "vertexcount")]
+    VertexCount,
+    /// `z_geomtype` of `--extra-columns geomtype`
+    #[value(name = "geomtype")]
+    GeomType,
+}
+
+/// The columns to add and how to compute them
+#[derive(Clone, Debug, PartialEq)]
+pub struct AugmentOptions {
+    pub columns: Vec<AugmentColumn>,
+    pub bbox_layout: BboxLayout,
+    pub area_crs: AreaCrs,
+    /// Number of files rewritten in parallel
+    pub jobs: usize,
+}
+
+impl AugmentOptions {
+    /// Names of the columns `column` adds
+    fn names(&self, column: AugmentColumn) -> Vec<&'static str> {
+        match column {
+            AugmentColumn::Bbox => self.bbox_layout.columns().to_vec(),
+            AugmentColumn::Area => vec![AREA_COLUMN],
+            AugmentColumn::VertexCount => vec![ExtraColumn::VertexCount.name()],
+            AugmentColumn::GeomType => vec![ExtraColumn::GeomType.name()],
+        }
+    }
+
+    /// The requested columns missing from `schema`, in the order they are
+    /// appended. A column only partly present is an error.
+    fn missing(&self, path: &str, schema: &Schema) -> Result<Vec<AugmentColumn>> {
+        let mut columns = self.columns.clone();
+        columns.sort();
+        columns.dedup();
+        let mut missing = vec![];
+        for column in columns {
+            let names = self.names(column);
+            let present = names
+                .iter()
+                .filter(|name| schema.field_with_name(name).is_ok())
+                .count();
+            match present {
+                0 => missing.push(column),
+                n if n == names.len() => {}
+                _ => {
+                    return Err(anyhow!(
+                        "{path} holds some but not all of the columns {}",
+                        names.join(", ")
+                    ))
+                }
+            }
+        }
+        Ok(missing)
+    }
+
+    /// `batch` with `columns` appended
+    fn append(&self, batch: RecordBatch, columns: &[AugmentColumn]) -> Result<RecordBatch> {
+        let mut batches = vec![batch];
+        for column in columns {
+            batches = match column {
+                AugmentColumn::Bbox => append_bbox_column(batches, self.bbox_layout, 0.0, None)?,
+                AugmentColumn::Area => append_area_column(batches, self.area_crs, None)?,
+                AugmentColumn::VertexCount => {
+                    append_extra_columns(batches, &[ExtraColumn::VertexCount], None)?
+                }
+                AugmentColumn::GeomType => {
+                    append_extra_columns(batches, &[ExtraColumn::GeomType], None)?
+                }
+            };
+        }
+        batches
+            .pop()
+            .ok_or_else(|| anyhow!("No batch left after appending columns"))
+    }
+}
+
+/// What `augment` did to one file
+#[derive(Clone, Debug, PartialEq)]
+pub struct AugmentedFile {
+    /// Path relative to the dataset directory
+    pub path: String,
+    /// Names of the columns added, empty when the file already held them
+    pub added: Vec<&'static str>,
+    pub content_sha256: String,
+}
+
+/// Adds the columns of `options` to every file of the zone dataset in
+/// `data_dir`, including the merged copy, and records the new content
+/// hashes and the `custom` schema version in the manifest and in
+/// `dataset.json`
+pub fn augment_zone(data_dir: &Path, options: &AugmentOptions) -> Result<Vec<AugmentedFile>> {
+    if options.columns.is_empty() {
+        return Err(anyhow!("No columns to add"));
+    }
+    let mut manifest = ZoneManifest::read(data_dir)?
+        .ok_or_else(|| anyhow!("No zone manifest found in {}", data_dir.display()))?;
+    let mut paths: Vec<String> = manifest.files.iter().map(|f| f.path.clone()).collect();
+    paths.extend(manifest.merged.iter().map(|m| m.path.clone()));
+    if let Some(path) = paths.iter().find(|path| !path.ends_with(".parquet")) {
+        return Err(anyhow!(
+            "{path} is not a Parquet file; augment only rewrites Parquet zone files"
+        ));
+    }
+
+    let augment = |path: &String| augment_file(data_dir, path, options);
+    let augmented = match options.jobs {
+        jobs if jobs > 1 && paths.len() > 1 => {
+            let pool = ThreadPoolBuilder::new().num_threads(jobs).build()?;
+            pool.install(|| paths.par_iter().map(augment).collect::<Result<Vec<_>>>())?
+        }
+        _ => paths.iter().map(augment).collect::<Result<Vec<_>>>()?,
+    };
+
+    for file in &augmented {
+        let footer_bytes = Some(parquet_footer_bytes(&data_dir.join(&file.path))?);
+        if let Some(part) = manifest.files.iter_mut().find(|f| f.path == file.path) {
+            part.content_sha256 = file.content_sha256.clone();
+            part.footer_bytes = footer_bytes;
+        }
+        if let Some(merged) = manifest.merged.as_mut().filter(|m| m.path == file.path) {
+            merged.content_sha256 = file.content_sha256.clone();
+            merged.footer_bytes = footer_bytes;
+        }
+    }
+    manifest.schema_version = Some(SchemaVersion::Custom.to_string());
+    if let (Some(_), Some(path)) = (&manifest.columns, paths.first()) {
+        let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(data_dir.join(path))?)?;
+        manifest.columns = Some(ManifestColumn::of_schema(builder.schema()));
+    }
+    manifest.write(data_dir)?;
+
+    if let Some(mut dataset) = DatasetManifest::read(data_dir)? {
+        dataset.refresh_zone_table(data_dir, &manifest)?;
+        dataset.write(data_dir)?;
+    }
+    info!(
+        "Augmented {}: {} of {} file(s) rewritten",
+        data_dir.display(),
+        augmented.iter().filter(|f| !f.added.is_empty()).count(),
+        augmented.len()
+    );
+    Ok(augmented)
+}
+
+/// Appends the columns of `options` missing from the file `path` of
+/// `data_dir`, rewriting it through a temporary file
+fn augment_file(data_dir: &Path, path: &str, options: &AugmentOptions) -> Result<AugmentedFile> {
+    let file_path = data_dir.join(path);
+    let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(&file_path)?)?;
+    let schema = Arc::new(Schema::new(builder.schema().fields().clone()));
+    let missing = options.missing(path, &schema)?;
+    if missing.is_empty() {
+        // A previous run may have rewritten the file without getting to
+        // record its hash
+        let content_sha256 = match read_footer_hash(&file_path)? {
+            Some(hash) => hash,
+            None => hash_parquet_file(&file_path)?,
+        };
+        debug!("{path}: already holds the columns, skipping");
+        return Ok(AugmentedFile {
+            path: path.to_string(),
+            added: vec![],
+            content_sha256,
+        });
+    }
+    match schema.field_with_name("z_boundary").map(|f| f.data_type()) {
+        Ok(DataType::Binary | DataType::LargeBinary | DataType::BinaryView) => {}
+        Ok(_) => {
+            return Err(anyhow!(
+                "Column z_boundary of {path} is not WKB; the derived columns are computed \
+                 from WKB boundaries"
+            ))
+        }
+        Err(_) => return Err(anyhow!("Column z_boundary not found in {path}")),
+    }
+
+    let metadata = Arc::clone(builder.metadata());
+    let row_groups = metadata.row_groups();
+    let compression = row_groups
+        .first()
+        .and_then(|group| group.columns().first())
+        .map_or(Compression::UNCOMPRESSED, |column| column.compression());
+    let props = WriterProperties::builder()
+        .set_compression(compression)
+        // Every row group is ended explicitly where the original one ends
+        .set_max_row_group_size(usize::MAX)
+        .build();
+    let added: Vec<&'static str> = missing.iter().flat_map(|c| options.names(*c)).collect();
+    let output_schema = options
+        .append(RecordBatch::new_empty(Arc::clone(&schema)), &missing)?
+        .schema();
+
+    let temp_path = file_path.with_extension("inprogress");
+    let mut writer = ArrowWriter::try_new(
+        File::create(&temp_path)?,
+        Arc::clone(&output_schema),
+        Some(props),
+    )?;
+    let mut hasher = ContentHasher::try_new(&output_schema)?;
+    let mut bounds = vec![];
+    for (index, group) in row_groups.iter().enumerate() {
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&file_path)?)?
+            .with_row_groups(vec![index])
+            .with_batch_size((group.num_rows() as usize).max(1))
+            .build()?;
+        for batch in reader {
+            let batch = options.append(batch?.with_schema(Arc::clone(&schema))?, &missing)?;
+            hasher.update(&batch)?;
+            if missing.contains(&AugmentColumn::Bbox) {
+                let indices = options
+                    .bbox_layout
+                    .columns()
+                    .iter()
+                    .map(|name| output_schema.index_of(name))
+                    .collect::<Result<Vec<_>, _>>()?;
+                bounds.push(batch.project(&indices)?);
+            }
+            writer.write(&batch)?;
+        }
+        writer.flush()?;
+    }
+
+    let content_sha256 = hasher.finish();
+    for (key, value) in footer_metadata(&metadata, &added, &bounds, options.bbox_layout)? {
+        writer.append_key_value_metadata(KeyValue::new(key, value));
+    }
+    writer.append_key_value_metadata(KeyValue::new(
+        CONTENT_SHA256_KEY.to_string(),
+        content_sha256.clone(),
+    ));
+    writer.close()?;
+    std::fs::rename(&temp_path, &file_path)?;
+    debug!("{path}: added {}", added.join(", "));
+    Ok(AugmentedFile {
+        path: path.to_string(),
+        added,
+        content_sha256,
+    })
+}
+
+/// The footer metadata of the rewritten file: that of the original with
+/// the `custom` schema version, the `added` columns listed and, when the
+/// bounds were added, the `geo` metadata of their `bounds`. The content
+/// hash is left to the caller.
+fn footer_metadata(
+    metadata: &parquet::file::metadata::ParquetMetaData,
+    added: &[&str],
+    bounds: &[RecordBatch],
+    layout: BboxLayout,
+) -> Result<Vec<(String, String)>> {
+    let mut entries: Vec<(String, String)> = metadata
+        .file_metadata()
+        .key_value_metadata()
+        .into_iter()
+        .flatten()
+        .filter(|kv| ![ARROW_SCHEMA_KEY, CONTENT_SHA256_KEY].contains(&kv.key.as_str()))
+        .map(|kv| (kv.key.clone(), kv.value.clone().unwrap_or_default()))
+        .collect();
+    let mut set = |key: &str, value: String| match entries.iter_mut().find(|(k, _)| k == key) {
+        Some(entry) => entry.1 = value,
+        None => entries.push((key.to_string(), value)),
+    };
+    set(SCHEMA_VERSION_KEY, SchemaVersion::Custom.to_string());
+    if !bounds.is_empty() {
+        set(GEO_METADATA_KEY, geo_metadata(bounds, layout)?);
+    }
+    let previous = metadata
+        .file_metadata()
+        .key_value_metadata()
+        .into_iter()
+        .flatten()
+        .find(|kv| kv.key == AUGMENTED_KEY)
+        .and_then(|kv| kv.value.clone());
+    let augmented = previous
+        .iter()
+        .map(String::as_str)
+        .chain(added.iter().copied())
+        .collect::<Vec<_>>()
+        .join(",");
+    set(AUGMENTED_KEY, augmented);
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zone::config::ZoneDfArgs;
+    use crate::zone::fixtures::{source_batch, wkb_polygon, write_parquet, SourceRow};
+    use crate::zone::hash::read_footer_value;
+    use crate::zone::theme::{Theme, ThemeInput};
+    use crate::zone::{generate_zone_parquet_multi, verify};
+    use arrow_array::{BinaryArray, Int64Array};
+    use arrow_schema::Field;
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    fn options(columns: Vec<AugmentColumn>) -> AugmentOptions {
+        AugmentOptions {
+            columns,
+            bbox_layout: BboxLayout::Struct,
+            area_crs: AreaCrs::Epsg6933,
+            jobs: 2,
+        }
+    }
+
+    fn row_group_rows(path: &Path) -> Vec<i64> {
+        SerializedFileReader::new(File::open(path).unwrap())
+            .unwrap()
+            .metadata()
+            .row_groups()
+            .iter()
+            .map(|group| group.num_rows())
+            .collect()
+    }
+
+    #[test]
+    fn test_augment_file_keeps_row_groups_and_footer() {
+        let dir = tempfile::tempdir().unwrap();
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("z_zonekey", DataType::Int64, false),
+            Field::new("z_boundary", DataType::Binary, true),
+        ]));
+        let square = wkb_polygon(&[(0.0, 0.0), (2.0, 0.0), (2.0, 1.0), (0.0, 1.0), (0.0, 0.0)]);
+        let batch = RecordBatch::try_new(
+            Arc::clone(&schema),
+            vec![
+                Arc::new(Int64Array::from(vec![1, 2, 3, 4, 5])),
+                Arc::new(BinaryArray::from(vec![
+                    Some(square.as_slice()),
+                    None,
+                    Some(square.as_slice()),
+                    Some(square.as_slice()),
+                    Some(square.as_slice()),
+                ])),
+            ],
+        )
+        .unwrap();
+        let path = dir.path().join("zone.1.parquet");
+        let props = WriterProperties::builder()
+            .set_compression(Compression::ZSTD(Default::default()))
+            .set_max_row_group_size(2)
+            .build();
+        let mut writer =
+            ArrowWriter::try_new(File::create(&path).unwrap(), schema, Some(props)).unwrap();
+        writer.write(&batch).unwrap();
+        writer
+            .append_key_value_metadata(KeyValue::new("custom.key".to_string(), "kept".to_string()));
+        writer.close().unwrap();
+
+        let options = options(vec![AugmentColumn::Area, AugmentColumn::Bbox]);
+        let file = augment_file(dir.path(), "zone.1.parquet", &options).unwrap();
+        assert_eq!(file.added, vec!["z_bbox", "z_area"]);
+        assert_eq!(row_group_rows(&path), vec![2, 2, 1]);
+        assert_eq!(hash_parquet_file(&path).unwrap(), file.content_sha256);
+        assert_eq!(read_footer_hash(&path).unwrap(), Some(file.content_sha256));
+        let footer = |key| read_footer_value(&path, key).unwrap();
+        assert_eq!(footer("custom.key").as_deref(), Some("kept"));
+        assert_eq!(footer(AUGMENTED_KEY).as_deref(), Some("z_bbox,z_area"));
+        assert_eq!(footer(SCHEMA_VERSION_KEY).as_deref(), Some("custom"));
+        assert!(footer(GEO_METADATA_KEY)
+            .unwrap()
+            .contains("\"bbox\":[0.0,0.0,2.0,1.0]"));
+        let compression = SerializedFileReader::new(File::open(&path).unwrap())
+            .unwrap()
+            .metadata()
+            .row_group(0)
+            .column(2)
+            .compression();
+        assert!(matches!(compression, Compression::ZSTD(_)));
+
+        // The bounds are there now, so only the vertex count is added
+        let options = AugmentOptions {
+            columns: vec![AugmentColumn::Bbox, AugmentColumn::VertexCount],
+            ..options
+        };
+        let file = augment_file(dir.path(), "zone.1.parquet", &options).unwrap();
+        assert_eq!(file.added, vec!["z_vertexcount"]);
+        assert_eq!(
+            footer(AUGMENTED_KEY).as_deref(),
+            Some("z_bbox,z_area,z_vertexcount")
+        );
+        assert_eq!(row_group_rows(&path), vec![2, 2, 1]);
+    }
+
+    #[tokio::test]
+    async fn test_augment_matches_fresh_generation() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("division_area.parquet");
+        let rows: Vec<_> = ["g1", "g2", "g3", "g4", "g5"]
+            .iter()
+            .map(|id| SourceRow::new(id, "county"))
+            .collect();
+        write_parquet(&source, &source_batch(&rows, true));
+        let theme = ThemeInput {
+            theme: Theme::DivisionArea,
+            location: Some(source.to_string_lossy().into_owned()),
+        };
+        let args = |output_dir: &Path| {
+            ZoneDfArgs::new(
+                1.0,
+                output_dir.to_path_buf(),
+                Some(2),
+                None,
+                None,
+                1024 * 1024,
+                Compression::SNAPPY,
+            )
+            .with_themes(vec![theme.clone()])
+        };
+        let augmented = dir.path().join("augmented");
+        let fresh = dir.path().join("fresh");
+        generate_zone_parquet_multi(args(&augmented)).await.unwrap();
+        let mut fresh_args = args(&fresh);
+        fresh_args.transform.bbox_covering = true;
+        fresh_args.transform.area = Some(AreaCrs::Epsg6933);
+        generate_zone_parquet_multi(fresh_args).await.unwrap();
+
+        let options = options(vec![AugmentColumn::Bbox, AugmentColumn::Area]);
+        let files = augment_zone(&augmented, &options).unwrap();
+        assert_eq!(files.len(), 2);
+        assert!(files.iter().all(|f| f.added == vec!["z_bbox", "z_area"]));
+
+        let report = verify::compare_dirs(&augmented, &fresh).unwrap();
+        assert!(report.is_identical(), "{report:?}");
+        assert!(verify::verify_dir(&augmented).unwrap().is_empty());
+        let manifest = ZoneManifest::read(&augmented).unwrap().unwrap();
+        assert_eq!(manifest.schema_version.as_deref(), Some("custom"));
+        let fresh_manifest = ZoneManifest::read(&fresh).unwrap().unwrap();
+        assert_eq!(manifest.columns, fresh_manifest.columns);
+        for (file, part) in files.iter().zip(&manifest.files) {
+            assert_eq!(file.content_sha256, part.content_sha256);
+        }
+
+        // A second run finds the columns in place and rewrites nothing
+        let again = augment_zone(&augmented, &options).unwrap();
+        assert!(again.iter().all(|f| f.added.is_empty()));
+        assert_eq!(ZoneManifest::read(&augmented).unwrap().unwrap(), manifest);
+    }
+}
@@ -0,0 +1,176 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Helpers for rewriting columns of already-collected zone record batches
+
+use anyhow::{anyhow, Result};
+use arrow::compute::cast;
+use arrow_array::{Array, ArrayRef, BinaryArray, RecordBatch, StringArray};
+use arrow_schema::DataType;
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+use std::sync::Arc;
+
+/// Returns a copy of `batch` where the string column `name` is rewritten by `f`.
+///
+/// `f` receives each non-null value and returns `Some(replacement)` to change it
+/// or `None` to keep it. The column keeps its original Arrow type (`Utf8`,
+/// `LargeUtf8` or `Utf8View`) so the batch schema is unchanged.
+pub fn map_string_column<F>(batch: &RecordBatch, name: &str, mut f: F) -> Result<RecordBatch>
+where
+    F: FnMut(&str) -> Option<String>,
+{
+    map_string_column_by_row(batch, name, |_, value| f(value))
+}
+
+/// [`map_string_column`], passing `f` the row index of every value as well
+pub fn map_string_column_by_row<F>(batch: &RecordBatch, name: &str, mut f: F) -> Result<RecordBatch>
+where
+    F: FnMut(usize, &str) -> Option<String>,
+{
+    let index = batch
+        .schema()
+        .index_of(name)
+        .map_err(|_| anyhow!("Column {name} not found in zone batch"))?;
+    let data_type = batch.column(index).data_type().clone();
+    let values = string_column(batch, name)?;
+
+    let mapped: StringArray = values
+        .iter()
+        .enumerate()
+        .map(|(row, v)| v.map(|s| f(row, s).unwrap_or_else(|| s.to_string())))
+        .collect();
+
+    let mapped: ArrayRef = if data_type == DataType::Utf8 {
+        Arc::new(mapped)
+    } else {
+        cast(&mapped, &data_type)?
+    };
+
+    let mut columns = batch.columns().to_vec();
+    columns[index] = mapped;
+    Ok(RecordBatch::try_new(batch.schema(), columns)?)
+}
+
+/// The string column `name` of `batch` as `Utf8`, whatever its string type
+pub fn string_column(batch: &RecordBatch, name: &str) -> Result<StringArray> {
+    let column = batch
+        .column_by_name(name)
+        .ok_or_else(|| anyhow!("Column {name} not found in zone batch"))?;
+    let utf8 = cast(column, &DataType::Utf8)?;
+    utf8.as_any()
+        .downcast_ref::<StringArray>()
+        .cloned()
+        .ok_or_else(|| anyhow!("Column {name} is not a string column"))
+}
+
+/// Returns a copy of `batch` where the binary column `name` is rewritten by `f`.
+///
+/// `f` receives each non-null value and returns `Some(replacement)` to change
+/// it or `None` to keep it. As with [`map_string_column`] the column keeps its
+/// original Arrow type.
+pub fn map_binary_column<F>(batch: &RecordBatch, name: &str, mut f: F) -> Result<RecordBatch>
+where
+    F: FnMut(&[u8]) -> Result<Option<Vec<u8>>>,
+{
+    let index = batch
+        .schema()
+        .index_of(name)
+        .map_err(|_| anyhow!("Column {name} not found in zone batch"))?;
+    let column = batch.column(index);
+    let data_type = column.data_type().clone();
+
+    let binary = cast(column, &DataType::Binary)?;
+    let values = binary
+        .as_any()
+        .downcast_ref::<BinaryArray>()
+        .ok_or_else(|| anyhow!("Column {name} is not a binary column"))?;
+
+    let mapped: BinaryArray = values
+        .iter()
+        .map(|v| {
+            v.map(|bytes| Ok(f(bytes)?.unwrap_or_else(|| bytes.to_vec())))
+                .transpose()
+        })
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .collect();
+
+    let mapped: ArrayRef = if data_type == DataType::Binary {
+        Arc::new(mapped)
+    } else {
+        cast(&mapped, &data_type)?
+    };
+
+    let mut columns = batch.columns().to_vec();
+    columns[index] = mapped;
+    Ok(RecordBatch::try_new(batch.schema(), columns)?)
+}
+
+/// Maps every batch with `f`, which also receives the index of the batch,
+/// spread over `threads` threads when more than one is given. The results
+/// are in the order of `batches` either way.
+pub fn map_batches<T, F>(batches: &[RecordBatch], threads: Option<usize>, f: F) -> Result<Vec<T>>
+where
+    T: Send,
+    F: Fn(usize, &RecordBatch) -> Result<T> + Send + Sync,
+{
+    let f = |(index, batch)| f(index, batch);
+    match threads {
+        Some(threads) if threads > 1 && batches.len() > 1 => {
+            let pool = ThreadPoolBuilder::new().num_threads(threads).build()?;
+            pool.install(|| batches.par_iter().enumerate().map(f).collect())
+        }
+        _ => batches.iter().enumerate().map(f).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_array::StringViewArray;
+    use arrow_schema::{Field, Schema};
+
+    #[test]
+    fn test_map_string_column_preserves_view_type() {
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "z_country",
+            DataType::Utf8View,
+            true,
+        )]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(StringViewArray::from(vec![
+                Some("a"),
+                None,
+                Some("b"),
+            ]))],
+        )
+        .unwrap();
+
+        let mapped =
+            map_string_column(&batch, "z_country", |s| (s == "a").then(|| "A".into())).unwrap();
+
+        assert_eq!(mapped.schema(), schema);
+        let values = cast(mapped.column(0), &DataType::Utf8).unwrap();
+        let values = values.as_any().downcast_ref::<StringArray>().unwrap();
+        assert_eq!(
+            values.iter().collect::<Vec<_>>(),
+            vec![Some("A"), None, Some("b")]
+        );
+    }
+}
@@ -0,0 +1,369 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! GeoParquet 1.1 bounding box covering of `z_boundary`
+//!
+//! Every row gets a `z_bbox` struct of `xmin`, `ymin`, `xmax` and `ymax`,
+//! declared in the `geo` metadata as the covering of `z_boundary`. The
+//! Parquet writer keeps min/max statistics of each struct field per row
+//! group, so those statistics are the bbox of every row group and readers
+//! can skip row groups outside a query window. Bounds are planar, so a
+//! zone crossing the antimeridian spans the longitudes in between and
+//! stays inside the statistics of its row group.
+//!
+//! With [`BboxLayout::Flat`] the bounds are four plain double columns
+//! instead, which engines without struct support read as they are. They
+//! have the same row group statistics, but GeoParquet only declares struct
+//! coverings, so the `geo` metadata then carries the file bbox alone.
+
+use anyhow::{anyhow, Result};
+use arrow::buffer::NullBuffer;
+use arrow::compute::cast;
+use arrow_array::{Array, ArrayRef, BinaryArray, Float64Array, RecordBatch, StructArray};
+use arrow_schema::{DataType, Field, Fields, Schema, SchemaRef};
+use clap::ValueEnum;
+use log::info;
+use std::sync::Arc;
+
+use super::batch::map_batches;
+use super::wkb::NormalizedWkb;
+
+pub const BBOX_COLUMN: &str = "z_bbox";
+
+/// Key of the GeoParquet metadata in the Parquet footer
+pub const GEO_METADATA_KEY: &str = "geo";
+
+const BBOX_FIELDS: [&str; 4] = ["xmin", "ymin", "xmax", "ymax"];
+
+/// Columns of the [`BboxLayout::Flat`] bounds
+pub const FLAT_BBOX_COLUMNS: [&str; 4] = ["z_xmin", "z_ymin", "z_xmax", "z_ymax"];
+
+/// Columns the bounds of every zone are written in
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum BboxLayout {
+    /// A `z_bbox` struct of `xmin`, `ymin`, `xmax` and `ymax`, declared as
+    /// the GeoParquet covering of `z_boundary`
+    #[default]
+    Struct,
+    /// The `z_xmin`, `z_ymin`, `z_xmax` and `z_ymax` double columns
+    Flat,
+}
+
+impl BboxLayout {
+    /// Names of the columns holding the bounds
+    pub fn columns(self) -> &'static [&'static str] {
+        match self {
+            BboxLayout::Struct => &[BBOX_COLUMN],
+            BboxLayout::Flat => &FLAT_BBOX_COLUMNS,
+        }
+    }
+}
+
+fn bbox_fields() -> Fields {
+    BBOX_FIELDS
+        .iter()
+        .map(|name| Field::new(*name, DataType::Float64, false))
+        .collect()
+}
+
+/// `schema` with the bounds columns of `layout` appended
+pub fn output_schema(schema: SchemaRef, layout: BboxLayout) -> SchemaRef {
+    let mut fields: Vec<Field> = schema.fields().iter().map(|f| f.as_ref().clone()).collect();
+    match layout {
+        BboxLayout::Struct => fields.push(Field::new(
+            BBOX_COLUMN,
+            DataType::Struct(bbox_fields()),
+            true,
+        )),
+        BboxLayout::Flat => fields.extend(
+            FLAT_BBOX_COLUMNS
+                .iter()
+                .map(|name| Field::new(*name, DataType::Float64, true)),
+        ),
+    }
+    Arc::new(Schema::new_with_metadata(fields, schema.metadata().clone()))
+}
+
+/// Appends the bounds of `z_boundary` in the columns of `layout`, null
+/// where the geometry is null, empty or not valid WKB. Every bbox is
+/// expanded by `inflate` on all sides. Batches are processed on up to
+/// `threads` threads.
+pub fn append_bbox_column(
+    batches: Vec<RecordBatch>,
+    layout: BboxLayout,
+    inflate: f64,
+    threads: Option<usize>,
+) -> Result<Vec<RecordBatch>> {
+    map_batches(&batches, threads, |_, batch| {
+        let geometries = batch
+            .column_by_name("z_boundary")
+            .ok_or_else(|| anyhow!("Column z_boundary not found in zone batch"))?;
+        let geometries = cast(geometries, &DataType::Binary)?;
+        let geometries = geometries
+            .as_any()
+            .downcast_ref::<BinaryArray>()
+            .ok_or_else(|| anyhow!("Column z_boundary is not a binary column"))?;
+
+        let bounds: Vec<Option<[f64; 4]>> = geometries
+            .iter()
+            .map(|wkb| wkb.and_then(wkb_bounds))
+            .map(|b| {
+                b.map(|b| {
+                    [
+                        b[0] - inflate,
+                        b[1] - inflate,
+                        b[2] + inflate,
+                        b[3] + inflate,
+                    ]
+                })
+            })
+            .collect();
+        let schema = output_schema(batch.schema(), layout);
+        let mut columns = batch.columns().to_vec();
+        match layout {
+            BboxLayout::Struct => {
+                let column = |i: usize| -> ArrayRef {
+                    Arc::new(Float64Array::from_iter_values(
+                        bounds.iter().map(|b| b.map_or(0.0, |b| b[i])),
+                    ))
+                };
+                let nulls = NullBuffer::from_iter(bounds.iter().map(Option::is_some));
+                columns.push(Arc::new(StructArray::try_new(
+                    bbox_fields(),
+                    (0..BBOX_FIELDS.len()).map(column).collect(),
+                    Some(nulls),
+                )?));
+            }
+            BboxLayout::Flat => columns.extend((0..FLAT_BBOX_COLUMNS.len()).map(|i| {
+                Arc::new(
+                    bounds
+                        .iter()
+                        .map(|b| b.map(|b| b[i]))
+                        .collect::<Float64Array>(),
+                ) as ArrayRef
+            })),
+        }
+        Ok(RecordBatch::try_new(schema, columns)?)
+    })
+}
+
+/// Planar bounds of `wkb` as `[xmin, ymin, xmax, ymax]`
+pub(super) fn wkb_bounds(wkb: &[u8]) -> Option<[f64; 4]> {
+    let geometry = NormalizedWkb::parse(wkb).ok()?;
+    let mut bounds: Option<[f64; 4]> = None;
+    for point in geometry.coords.chunks_exact(geometry.dims) {
+        let (x, y) = (point[0], point[1]);
+        if x.is_nan() || y.is_nan() {
+            continue;
+        }
+        let b = bounds.get_or_insert([x, y, x, y]);
+        *b = [b[0].min(x), b[1].min(y), b[2].max(x), b[3].max(y)];
+    }
+    bounds
+}
+
+/// The GeoParquet 1.1 `geo` metadata of a file holding `batches`, with the
+/// file bbox combined from their bounds columns in `layout`
+pub fn geo_metadata(batches: &[RecordBatch], layout: BboxLayout) -> Result<String> {
+    let mut file_bounds: Option<[f64; 4]> = None;
+    for batch in batches {
+        let (bounds, nulls) = match layout {
+            BboxLayout::Struct => {
+                let bbox = batch
+                    .column_by_name(BBOX_COLUMN)
+                    .and_then(|c| c.as_any().downcast_ref::<StructArray>())
+                    .ok_or_else(|| anyhow!("Column {BBOX_COLUMN} not found in zone batch"))?;
+                (bbox.columns().to_vec(), bbox.nulls().cloned())
+            }
+            BboxLayout::Flat => {
+                let columns = FLAT_BBOX_COLUMNS
+                    .iter()
+                    .map(|name| {
+                        batch
+                            .column_by_name(name)
+                            .cloned()
+                            .ok_or_else(|| anyhow!("Column {name} not found in zone batch"))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                let nulls = columns[0].nulls().cloned();
+                (columns, nulls)
+            }
+        };
+        let fields: Vec<&Float64Array> = bounds
+            .iter()
+            .map(|c| c.as_any().downcast_ref::<Float64Array>())
+            .collect::<Option<_>>()
+            .ok_or_else(|| anyhow!("Zone bounds columns have unexpected types"))?;
+        let rows = batch.num_rows();
+        for row in (0..rows).filter(|&row| nulls.as_ref().is_none_or(|n| n.is_valid(row))) {
+            let row_bounds = [0, 1, 2, 3].map(|i| fields[i].value(row));
+            let b = file_bounds.get_or_insert(row_bounds);
+            *b = [
+                b[0].min(row_bounds[0]),
+                b[1].min(row_bounds[1]),
+                b[2].max(row_bounds[2]),
+                b[3].max(row_bounds[3]),
+            ];
+        }
+    }
+
+    let covering: serde_json::Map<String, serde_json::Value> = BBOX_FIELDS
+        .iter()
+        .map(|name| (name.to_string(), serde_json::json!([BBOX_COLUMN, name])))
+        .collect();
+    let mut column = serde_json::json!({
+        "encoding": "WKB",
+        "geometry_types": [],
+    });
+    if layout == BboxLayout::Struct {
+        column["covering"] = serde_json::json!({ "bbox": covering });
+    }
+    if let Some(bounds) = file_bounds {
+        column["bbox"] = serde_json::json!(bounds);
+    }
+    info!("Zone bbox covering spans {file_bounds:?}");
+    Ok(serde_json::json!({
+        "version": "1.1.0",
+        "primary_column": "z_boundary",
+        "columns": { "z_boundary": column },
+    })
+    .to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zone::fixtures::{wkb_point, wkb_polygon};
+
+    fn boundaries(geometries: Vec<Option<&[u8]>>) -> RecordBatch {
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "z_boundary",
+            DataType::Binary,
+            true,
+        )]));
+        RecordBatch::try_new(schema, vec![Arc::new(BinaryArray::from(geometries))]).unwrap()
+    }
+
+    fn test_batches() -> Vec<RecordBatch> {
+        let square = wkb_polygon(&[(1.0, 2.0), (3.0, 2.0), (3.0, 5.0), (1.0, 2.0)]);
+        let point = wkb_point(-4.0, 0.5);
+        vec![
+            boundaries(vec![Some(&square), None]),
+            boundaries(vec![Some(b"not wkb"), Some(&point)]),
+        ]
+    }
+
+    #[test]
+    fn test_bbox_column_and_geo_metadata() {
+        let batches = append_bbox_column(test_batches(), BboxLayout::Struct, 0.0, None).unwrap();
+        let bbox = |batch: usize, row: usize| {
+            let bbox = batches[batch]
+                .column_by_name(BBOX_COLUMN)
+                .unwrap()
+                .as_any()
+                .downcast_ref::<StructArray>()
+                .unwrap();
+            bbox.is_valid(row).then(|| {
+                [0, 1, 2, 3].map(|i| {
+                    bbox.column(i)
+                        .as_any()
+                        .downcast_ref::<Float64Array>()
+                        .unwrap()
+                        .value(row)
+                })
+            })
+        };
+        assert_eq!(bbox(0, 0), Some([1.0, 2.0, 3.0, 5.0]));
+        assert_eq!(bbox(0, 1), None);
+        assert_eq!(bbox(1, 0), None);
+        assert_eq!(bbox(1, 1), Some([-4.0, 0.5, -4.0, 0.5]));
+
+        let geo: serde_json::Value =
+            serde_json::from_str(&geo_metadata(&batches, BboxLayout::Struct).unwrap()).unwrap();
+        let column = &geo["columns"]["z_boundary"];
+        assert_eq!(column["bbox"], serde_json::json!([-4.0, 0.5, 3.0, 5.0]));
+        assert_eq!(
+            column["covering"]["bbox"]["ymax"],
+            serde_json::json!(["z_bbox", "ymax"])
+        );
+        assert_eq!(geo["version"], "1.1.0");
+    }
+
+    #[test]
+    fn test_flat_bbox_columns() {
+        let batches = append_bbox_column(test_batches(), BboxLayout::Flat, 0.0, None).unwrap();
+        let schema = batches[0].schema();
+        let names: Vec<_> = schema.fields().iter().map(|f| f.name().as_str()).collect();
+        assert_eq!(
+            names,
+            vec!["z_boundary", "z_xmin", "z_ymin", "z_xmax", "z_ymax"]
+        );
+        assert_eq!(
+            schema,
+            output_schema(boundaries(vec![]).schema(), BboxLayout::Flat)
+        );
+
+        let column = |batch: usize, name: &str| -> Vec<Option<f64>> {
+            batches[batch]
+                .column_by_name(name)
+                .unwrap()
+                .as_any()
+                .downcast_ref::<Float64Array>()
+                .unwrap()
+                .iter()
+                .collect()
+        };
+        assert_eq!(column(0, "z_xmin"), vec![Some(1.0), None]);
+        assert_eq!(column(0, "z_ymax"), vec![Some(5.0), None]);
+        assert_eq!(column(1, "z_ymin"), vec![None, Some(0.5)]);
+        assert_eq!(column(1, "z_xmax"), vec![None, Some(-4.0)]);
+
+        // The file bbox without a covering, which GeoParquet only declares
+        // for structs
+        let geo: serde_json::Value =
+            serde_json::from_str(&geo_metadata(&batches, BboxLayout::Flat).unwrap()).unwrap();
+        let column = &geo["columns"]["z_boundary"];
+        assert_eq!(column["bbox"], serde_json::json!([-4.0, 0.5, 3.0, 5.0]));
+        assert!(column.get("covering").is_none());
+    }
+
+    #[test]
+    fn test_inflated_bbox() {
+        let square = wkb_polygon(&[(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0), (0.0, 0.0)]);
+        let batches = append_bbox_column(
+            vec![boundaries(vec![Some(&square), None])],
+            BboxLayout::Flat,
+            0.25,
+            None,
+        )
+        .unwrap();
+        let column = |name: &str| -> Vec<Option<f64>> {
+            batches[0]
+                .column_by_name(name)
+                .unwrap()
+                .as_any()
+                .downcast_ref::<Float64Array>()
+                .unwrap()
+                .iter()
+                .collect()
+        };
+        assert_eq!(column("z_xmin"), vec![Some(-0.25), None]);
+        assert_eq!(column("z_ymin"), vec![Some(-0.25), None]);
+        assert_eq!(column("z_xmax"), vec![Some(1.25), None]);
+        assert_eq!(column("z_ymax"), vec![Some(1.25), None]);
+    }
+}
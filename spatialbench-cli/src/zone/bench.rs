@@ -0,0 +1,231 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Generation throughput of the zone pipeline without writing files
+//!
+//! The benchmark runs the same functions as multi-part generation and
+//! encodes every part into a discarding sink, so the numbers include
+//! Parquet encoding but no disk I/O.
+
+use anyhow::{anyhow, Result};
+use log::info;
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+use super::config::ZoneDfArgs;
+use super::stats::ZoneTableStats;
+use super::writer::ParquetWriter;
+use super::{generate_zone_batches_timed, part_partition, partition_table, zone_session_context};
+
+/// Wall time spent in each stage of the pipeline
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StageTimes {
+    /// Reading the source and running the SQL transform. DataFusion runs
+    /// both in the same streaming plan, so they can't be told apart.
+    pub scan_transform: Duration,
+    /// Transforms applied to the collected batches
+    pub batch_transforms: Duration,
+    /// Ordering and slicing the rows into parts
+    pub partition: Duration,
+    /// Parquet encoding of every part
+    pub encode: Duration,
+}
+
+impl StageTimes {
+    fn total(&self) -> Duration {
+        self.scan_transform + self.batch_transforms + self.partition + self.encode
+    }
+}
+
+/// Stage times in milliseconds
+#[derive(Clone, Debug, Serialize)]
+pub struct StageMillis {
+    pub scan_transform: f64,
+    pub batch_transforms: f64,
+    pub partition: f64,
+    pub encode: f64,
+    pub total: f64,
+}
+
+impl From<StageTimes> for StageMillis {
+    fn from(times: StageTimes) -> Self {
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        Self {
+            scan_transform: ms(times.scan_transform),
+            batch_transforms: ms(times.batch_transforms),
+            partition: ms(times.partition),
+            encode: ms(times.encode),
+            total: ms(times.total()),
+        }
+    }
+}
+
+/// Throughput of one iteration
+#[derive(Clone, Debug, Serialize)]
+pub struct BenchIteration {
+    pub stages_ms: StageMillis,
+    pub rows_per_sec: f64,
+    /// In-memory size of the generated Arrow batches per second, in MiB
+    pub mb_per_sec: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BenchReport {
+    pub scale_factor: f64,
+    pub parts: i32,
+    pub rows: u64,
+    /// In-memory size of the generated Arrow batches
+    pub arrow_bytes: u64,
+    pub iterations: Vec<BenchIteration>,
+    /// The fastest iteration
+    pub min: BenchIteration,
+    /// The iteration with the median total time, the faster of the two
+    /// middle ones for an even count
+    pub median: BenchIteration,
+    /// Resident set size high water mark of the process, where the
+    /// platform reports it
+    pub peak_rss_bytes: Option<u64>,
+}
+
+/// Generates the zone table `iterations` times, discarding the encoded
+/// parts, and reports the throughput
+pub async fn bench_zone(args: &ZoneDfArgs, iterations: usize) -> Result<BenchReport> {
+    if iterations == 0 {
+        return Err(anyhow!("Invalid --iterations=0"));
+    }
+    args.validate()?;
+    let parts = args.parts.unwrap_or(1);
+    let stats = ZoneTableStats::new(args.scale_factor, args.parts);
+
+    let mut runs = Vec::with_capacity(iterations);
+    let (mut rows, mut arrow_bytes) = (0, 0);
+    for iteration in 1..=iterations {
+        let mut times = StageTimes::default();
+        let ctx = zone_session_context().await?;
+        let (schema, batches) = generate_zone_batches_timed(&ctx, args, &mut times).await?;
+        rows = batches.iter().map(|b| b.num_rows() as u64).sum();
+        arrow_bytes = batches
+            .iter()
+            .map(|b| b.get_array_memory_size() as u64)
+            .sum();
+
+        let start = Instant::now();
+        let table = partition_table(args, batches, parts)?;
+        let part_batches = (1..=parts)
+            .map(|part| {
+                part_partition(rows as i64, parts, part, &table.boundaries)
+                    .apply_to_batches(&table.batches)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        times.partition = start.elapsed();
+
+        let start = Instant::now();
+        for (part, batches) in (1..).zip(&part_batches) {
+            let part_args = ZoneDfArgs {
+                part: Some(part),
+                ..args.clone()
+            };
+            ParquetWriter::new(&part_args, &stats, schema.clone())
+                .encode(std::io::sink(), batches)?;
+        }
+        times.encode = start.elapsed();
+
+        info!("Bench iteration {iteration}/{iterations}: {times:?}");
+        runs.push(times);
+    }
+
+    let iteration = |times: StageTimes| {
+        let secs = times.total().as_secs_f64();
+        BenchIteration {
+            stages_ms: times.into(),
+            rows_per_sec: rows as f64 / secs,
+            mb_per_sec: arrow_bytes as f64 / (1024.0 * 1024.0) / secs,
+        }
+    };
+    let mut sorted = runs.clone();
+    sorted.sort_by_key(StageTimes::total);
+    Ok(BenchReport {
+        scale_factor: args.scale_factor,
+        parts,
+        rows,
+        arrow_bytes,
+        min: iteration(sorted[0]),
+        median: iteration(sorted[(sorted.len() - 1) / 2]),
+        iterations: runs.into_iter().map(iteration).collect(),
+        peak_rss_bytes: peak_rss_bytes(),
+    })
+}
+
+/// `VmHWM` of the process on Linux, `None` elsewhere
+fn peak_rss_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kb: u64 = line
+        .trim_start_matches("VmHWM:")
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse()
+        .ok()?;
+    Some(kb * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zone::fixtures::{source_batch, write_parquet, SourceRow};
+    use crate::zone::theme::{Theme, ThemeInput};
+    use parquet::basic::Compression;
+
+    #[tokio::test]
+    async fn test_bench_reports_without_writing() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("division_area.parquet");
+        let rows: Vec<_> = ["g1", "g2", "g3"]
+            .iter()
+            .map(|id| SourceRow::new(id, "county"))
+            .collect();
+        write_parquet(&source, &source_batch(&rows, true));
+        let output_dir = dir.path().join("out");
+        let args = ZoneDfArgs::new(
+            1.0,
+            output_dir.clone(),
+            Some(2),
+            None,
+            None,
+            1024 * 1024,
+            Compression::SNAPPY,
+        )
+        .with_themes(vec![ThemeInput {
+            theme: Theme::DivisionArea,
+            location: Some(source.to_string_lossy().into_owned()),
+        }]);
+
+        let report = bench_zone(&args, 3).await.unwrap();
+        assert_eq!(report.rows, 3);
+        assert!(report.arrow_bytes > 0);
+        assert_eq!(report.iterations.len(), 3);
+        assert!(report.min.stages_ms.total <= report.median.stages_ms.total);
+        assert!(report.median.rows_per_sec > 0.0);
+        if cfg!(target_os = "linux") {
+            assert!(report.peak_rss_bytes.unwrap() > 0);
+        }
+        assert!(!output_dir.exists());
+
+        assert!(bench_zone(&args, 0).await.is_err());
+    }
+}
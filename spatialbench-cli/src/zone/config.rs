@@ -16,18 +16,493 @@
 // under the License.
 
 use anyhow::{anyhow, Result};
+use arrow_schema::{Schema, SchemaRef};
+use clap::ValueEnum;
 use parquet::basic::Compression as ParquetCompression;
 use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::avro::AvroCodec;
+use crate::generation_params::GenerationParams;
+use crate::interrupt::{CancellationFlag, OnInterrupt};
+
+use super::area::AreaCrs;
+use super::attribution::source_attribution;
+use super::bbox::BboxLayout;
+use super::datasource::source_provenance;
+use super::explain::Explain;
+use super::extra_columns::ExtraColumn;
+use super::filename::FilenameTemplate;
+use super::geojson::GeometryEncoding;
+use super::manifest::MERGED_FILE_NAME;
+use super::max_length::{MaxStringLength, OnOverlong};
+use super::orc::OrcWriteOptions;
+use super::partition::{PartBoundary, PartExtent};
+use super::region::RegionMap;
+use super::schema::SchemaVersion;
+use super::simplify::SimplifyAlgorithm;
+use super::sink::Fsync;
+use super::subdivision::RegionNormalization;
+use super::synthetic::SyntheticColumn;
+use super::theme::{Theme, ThemeInput};
+use super::write_options::ParquetWriteOptions;
+
+/// Ring orientation convention for `z_boundary` polygons.
+///
+/// Both conventions put exterior rings counterclockwise and holes clockwise;
+/// the names exist so callers can state which specification they follow.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum WindingOrder {
+    /// OGC Simple Features
+    Ogc,
+    /// RFC 7946 GeoJSON right-hand rule
+    Geojson,
+}
+
+/// What to do with `z_boundary` values that are not valid WKB
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum OnBadGeometry {
+    /// Stop generation with an error
+    #[default]
+    Fail,
+    /// Drop the row
+    Skip,
+    /// Write the value unchanged
+    Keep,
+}
+
+/// Shape of the names written by `--pseudonymize-names`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum PseudonymStyle {
+    /// `Zone-` followed by hex digits, e.g. `Zone-7f3a9c`
+    #[default]
+    Hex,
+    /// Letters from a Markov chain over consonants and vowels, e.g.
+    /// `Oranel Tisu`, keeping the word lengths of the name
+    Pronounceable,
+}
+
+/// How `--rows` picks the sampled zones
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Sampling {
+    /// The rows with the smallest hash of their id
+    #[default]
+    Uniform,
+    /// At least `--min-per-country` rows of every country, the rest in
+    /// proportion to the rows of each country, by a hash of the seed and id
+    StratifiedCountry,
+}
+
+/// Geometry types zone rows can be filtered to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum GeometryType {
+    Point,
+    Linestring,
+    Polygon,
+    Multipoint,
+    Multilinestring,
+    Multipolygon,
+    Geometrycollection,
+}
+
+impl GeometryType {
+    /// The type of a WKB base type code, 1 to 7
+    pub fn from_wkb_code(code: u32) -> Option<Self> {
+        match code {
+            1 => Some(Self::Point),
+            2 => Some(Self::Linestring),
+            3 => Some(Self::Polygon),
+            4 => Some(Self::Multipoint),
+            5 => Some(Self::Multilinestring),
+            6 => Some(Self::Multipolygon),
+            7 => Some(Self::Geometrycollection),
+            _ => None,
+        }
+    }
+
+    /// The name as given to `--keep-geometry-type`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Point => "point",
+            Self::Linestring => "linestring",
+            Self::Polygon => "polygon",
+            Self::Multipoint => "multipoint",
+            Self::Multilinestring => "multilinestring",
+            Self::Multipolygon => "multipolygon",
+            Self::Geometrycollection => "geometrycollection",
+        }
+    }
+}
+
+/// Order of the columns selected with `--columns`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ColumnOrder {
+    /// The order of the full zone schema, whatever order they were listed in
+    #[default]
+    Schema,
+    /// The order they were listed in
+    AsSpecified,
+}
+
+/// The columns written out of the full zone schema
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ColumnSelection {
+    /// Selected column names, as listed
+    pub names: Vec<String>,
+    pub order: ColumnOrder,
+}
+
+impl ColumnSelection {
+    pub fn new(names: Vec<String>, order: ColumnOrder) -> Self {
+        Self { names, order }
+    }
+
+    /// The selected fields of `schema`, in the selection's order
+    pub fn project(&self, schema: &Schema) -> Result<SchemaRef> {
+        let mut indices = Vec::with_capacity(self.names.len());
+        for name in &self.names {
+            let index = schema.index_of(name).map_err(|_| {
+                let names: Vec<&str> = schema.fields().iter().map(|f| f.name().as_str()).collect();
+                anyhow!(
+                    "--columns names {name}, which is not a zone column; the columns are {}",
+                    names.join(", ")
+                )
+            })?;
+            if indices.contains(&index) {
+                return Err(anyhow!("--columns names {name} more than once"));
+            }
+            indices.push(index);
+        }
+        if self.order == ColumnOrder::Schema {
+            indices.sort_unstable();
+        }
+        Ok(Arc::new(schema.project(&indices)?))
+    }
+}
+
+/// Column giving each of its values its own `z_zonekey` space
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum KeyPartition {
+    /// Keys restart at 1 in every country
+    #[value(name = "z_country")]
+    Country,
+}
+
+impl KeyPartition {
+    /// The zone column the keys are partitioned by
+    pub fn column(self) -> &'static str {
+        match self {
+            Self::Country => "z_country",
+        }
+    }
+}
+
+/// Options controlling the post-SQL batch transforms applied to zone rows
+#[derive(Clone, Debug, Default)]
+pub struct ZoneTransformOptions {
+    /// Map `z_country` values to canonical uppercase ISO 3166-1 alpha-2 codes
+    pub normalize_country: bool,
+    /// Replacements applied to `z_region`
+    pub region_map: Option<RegionMap>,
+    /// Form `z_region` values are rewritten to, after `region_map`
+    pub normalize_region: RegionNormalization,
+    /// Reorder polygon rings of `z_boundary` to this convention
+    pub normalize_winding: Option<WindingOrder>,
+    /// Simplify `z_boundary` with `simplify_algorithm` at this tolerance
+    pub simplify_tolerance: Option<f64>,
+    /// How `simplify_tolerance` drops vertices and what it measures
+    pub simplify_algorithm: SimplifyAlgorithm,
+    /// Leave `z_boundary` WKB as read from the source instead of rewriting
+    /// it to little-endian ISO WKB
+    pub skip_wkb_normalize: bool,
+    /// Keep Z and M ordinates when normalizing WKB
+    pub keep_zm: bool,
+    /// Handling of malformed WKB found while normalizing
+    pub on_bad_geometry: OnBadGeometry,
+    /// Threads the `z_boundary` rewrites run on, one batch per task. Rows
+    /// keep their order; `None` rewrites on the calling thread.
+    pub geometry_threads: Option<usize>,
+    /// Append `z_parent_zonekey` and `z_admin_level`
+    pub include_hierarchy: bool,
+    /// Number `z_zonekey` from 1 in every value of this column, in id
+    /// order, rather than over the whole table
+    pub key_partition: Option<KeyPartition>,
+    /// Append `z_compositekey`, the `key_partition` value and the key
+    /// joined by `-`
+    pub composite_key: bool,
+    /// Append the `z_bbox` bounds of `z_boundary` and declare them as its
+    /// GeoParquet 1.1 covering
+    pub bbox_covering: bool,
+    /// Columns the `--with-bbox-covering` bounds are written in
+    pub bbox_layout: BboxLayout,
+    /// Distance every `--with-bbox-covering` bbox is expanded by on all
+    /// sides, in the units of `z_boundary`
+    pub bbox_inflate: f64,
+    /// Append the `z_area` of `z_boundary` measured in this CRS
+    pub area: Option<AreaCrs>,
+    /// Append the `z_lon` and `z_lat` of Point boundaries, null for other
+    /// geometry types
+    pub point_as_lonlat: bool,
+    /// Append `z_source` naming the input the zones were read from
+    pub include_provenance: bool,
+    /// Append the constant `z_attribution` of the source publisher and
+    /// release
+    pub attribution_column: bool,
+    /// Append the source `names.common` map as `z_name_common`
+    pub names_common: bool,
+    /// Languages appended as `z_name_<language>` from `names.common`
+    pub names_languages: Vec<String>,
+    /// Replace every name with a pseudonym of this style derived from
+    /// `seed` and `z_gersid`
+    pub pseudonymize_names: Option<PseudonymStyle>,
+    /// Numeric columns appended to every row
+    pub synthetic_columns: Vec<SyntheticColumn>,
+    /// Helper columns read from the `z_boundary` WKB headers
+    pub extra_columns: Vec<ExtraColumn>,
+    /// How `z_boundary` is written; the transforms read it as WKB either way
+    pub geometry_encoding: GeometryEncoding,
+    /// Leave `z_boundary` out of the SELECT, writing the attributes alone
+    pub no_geometry: bool,
+    /// Seed for the synthetic column values
+    pub seed: u64,
+    /// Columns written, after every other transform; all when `None`
+    pub columns: Option<ColumnSelection>,
+}
+
+impl ZoneTransformOptions {
+    /// Whether the transform reads the source `names.common` map
+    pub fn reads_common_names(&self) -> bool {
+        self.names_common || !self.names_languages.is_empty()
+    }
+}
+
+/// What `--parts` splits evenly
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Balance {
+    /// The same number of rows per part
+    #[default]
+    Rows,
+    /// Roughly the same number of geometry vertices per part
+    Vertices,
+}
+
+/// How zone rows are assigned to parts
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum PartitionScheme {
+    /// Contiguous runs of rows, split according to [`Balance`]
+    #[default]
+    Rows,
+    /// Equal-width latitude bands, assigned by centroid
+    LatBands,
+    /// Equal-width longitude bands, assigned by centroid
+    LonBands,
+    /// Latitude bands covering equal surface areas, assigned by centroid.
+    /// The bands narrow towards the poles, evenly spaced in sine latitude
+    EqualArea,
+    /// Cells of a KD-tree over the centroids: recursive bisection at the
+    /// median, alternating between longitude and latitude, so that every
+    /// part is a contiguous box with about the same number of rows
+    Kdtree,
+}
+
+/// Directory layout and naming of the zone part files
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ZoneLayout {
+    /// `zone.parquet`, or `zone/zone.N.parquet` when generating parts
+    #[default]
+    Spatialbench,
+    /// `zone/part-NNNNN-<uuid>.<codec>.parquet` plus a `_SUCCESS` marker,
+    /// or `.<codec>.orc` and `.avro` for ORC and Avro files
+    Spark,
+}
+
+/// File format of the zone files
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum ZoneFileFormat {
+    #[default]
+    Parquet,
+    Orc(OrcWriteOptions),
+    /// Avro object container files with blocks compressed by the codec
+    Avro(AvroCodec),
+    /// CSV with a header, `z_boundary` as WKT unless encoded as GeoJSON
+    Csv,
+    /// `|` terminated fields without a header, `z_boundary` as WKT
+    Tbl,
+}
+
+impl ZoneFileFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ZoneFileFormat::Parquet => "parquet",
+            ZoneFileFormat::Orc(_) => "orc",
+            ZoneFileFormat::Avro(_) => "avro",
+            ZoneFileFormat::Csv => "csv",
+            ZoneFileFormat::Tbl => "tbl",
+        }
+    }
+}
+
+/// Scale factors above this need `--yes-i-know`, unless `--max-scale-factor`
+/// raises it
+pub const DEFAULT_MAX_SCALE_FACTOR: f64 = 100_000.0;
+
+/// `--parts` above this are generated with a warning
+pub const PARTS_WARNING_THRESHOLD: i32 = 100_000;
+
+/// Errors unless `scale_factor` is a finite number above 0, explaining what
+/// a scale factor below 1 does to the zone table
+pub fn check_scale_factor(scale_factor: f64) -> Result<f64> {
+    if !scale_factor.is_finite() || scale_factor <= 0.0 {
+        return Err(anyhow!(
+            "Invalid --scale-factor {scale_factor}, expected a finite number above 0. Below 1 \
+             the synthetic tables shrink proportionally, while the zone table is generated at \
+             1 unless --allow-fractional-scale samples that fraction of its zones"
+        ));
+    }
+    Ok(scale_factor)
+}
+
+/// Errors when `scale_factor` is above the `max` sanity ceiling, which
+/// `--yes-i-know` lifts by passing `None`
+pub fn check_scale_factor_ceiling(scale_factor: f64, max: Option<f64>) -> Result<()> {
+    match max {
+        Some(max) if scale_factor > max => Err(anyhow!(
+            "--scale-factor {scale_factor} is above the sanity ceiling of {max}; pass \
+             --yes-i-know to generate it anyway, or raise the ceiling with --max-scale-factor"
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Errors unless `parts` is at least 1
+pub fn check_parts(parts: i32) -> Result<i32> {
+    if parts < 1 {
+        return Err(anyhow!("Invalid --parts={parts}, expected at least 1"));
+    }
+    Ok(parts)
+}
+
+/// The scale factor zones are generated at for `scale_factor`.
+///
+/// The zone source has the same zones at every scale factor up to 10, so
+/// one below 1 is raised to 1 unless `allow_fractional`, which keeps it to
+/// sample that fraction of the zones instead. Errors unless `scale_factor`
+/// is a positive number, see [`check_scale_factor`].
+pub fn zone_scale_factor(scale_factor: f64, allow_fractional: bool) -> Result<f64> {
+    check_scale_factor(scale_factor)?;
+    Ok(match allow_fractional {
+        true => scale_factor,
+        false => scale_factor.max(1.0),
+    })
+}
 
 #[derive(Clone)]
 pub struct ZoneDfArgs {
+    /// Scale factor of the zones; below 1 the source rows selected at 1 are
+    /// sampled down to that fraction, see [`zone_scale_factor`]
     pub scale_factor: f64,
+    /// Keep a scale factor below 1 rather than raise it to 1
+    pub allow_fractional_scale: bool,
+    /// Sanity ceiling of the scale factor, `None` after `--yes-i-know`
+    pub max_scale_factor: Option<f64>,
     pub output_dir: PathBuf,
     pub parts: Option<i32>,
     pub part: Option<i32>,
     pub output_file_size_mb: Option<f32>,
-    pub parquet_row_group_bytes: i64,
-    pub parquet_compression: ParquetCompression,
+    pub parquet: ParquetWriteOptions,
+    /// Format of the files, whose names end in its extension rather than
+    /// `.parquet`
+    pub file_format: ZoneFileFormat,
+    /// Write the Avro schema of the zone records to `zone.avsc` in the
+    /// output directory
+    pub emit_avro_schema: bool,
+    /// Rows per file; a part with more rows is split over several files, see
+    /// [`Self::split_filename`]
+    pub max_rows_per_file: Option<usize>,
+    /// Train a ZSTD dictionary on the generated values. Parquet can't use
+    /// one, so generation stops with the estimated gain
+    pub zstd_train_dict: bool,
+    pub transform: ZoneTransformOptions,
+    /// Overture themes unioned into the zone source
+    pub themes: Vec<ThemeInput>,
+    /// Flat source column read as the zone name instead of `names.primary`
+    pub name_column: Option<String>,
+    pub layout: ZoneLayout,
+    /// Part file names, written in the `zone` directory, instead of the
+    /// names of the layout
+    pub filename_template: Option<FilenameTemplate>,
+    /// Embed the scale factor in the part file names, see
+    /// [`FilenameTemplate::with_scale_factor`]
+    pub name_with_sf: bool,
+    pub balance: Balance,
+    pub partition_scheme: PartitionScheme,
+    /// Treat zones crossing the 180° meridian as narrow when computing the
+    /// centroids of the band partition strategies
+    pub antimeridian_aware: bool,
+    /// Part row ranges from a plan file, reused instead of balancing again
+    pub part_boundaries: Option<Vec<PartBoundary>>,
+    /// Band of every part, set when partitioning into bands
+    pub part_extents: Option<Vec<PartExtent>>,
+    /// Rows to sample from the source instead of all rows selected for the
+    /// scale factor
+    pub rows: Option<u64>,
+    /// How the `rows` sample is picked
+    pub sampling: Sampling,
+    /// Rows every country keeps in a [`Sampling::StratifiedCountry`] sample,
+    /// when it has that many
+    pub min_per_country: u64,
+    /// Geometry types of the source rows kept, sorted; all rows when empty
+    pub keep_geometry_types: Vec<GeometryType>,
+    /// Keep only the first `limit` rows in key order, before they are cut
+    /// into parts
+    pub limit: Option<u64>,
+    /// Remove parts written by a multi-part run when a later part fails
+    pub cleanup_on_failure: bool,
+    /// Also write the rows of every part into a single `zone.parquet`
+    pub also_merge: bool,
+    /// Spill the collected table of a multi-part run to a temporary
+    /// Parquet file and read it back one part at a time
+    pub spill_collect: bool,
+    /// Simplification tolerances of the companion datasets written next to
+    /// the full-resolution one, see [`Self::simplified_levels`]
+    pub simplification_levels: Vec<f64>,
+    /// Check that the `z_zonekey` values of every part ascend before
+    /// writing it
+    pub verify_key_order: bool,
+    /// Check after the transforms that `z_zonekey` is `1..=count` over the
+    /// whole table, or the key range of the part, without gaps or
+    /// duplicates
+    pub assert_contiguous_keys: bool,
+    /// Byte limits of string columns, applied to every written part
+    pub max_string_lengths: Vec<MaxStringLength>,
+    /// Whether a part with a string over its limit is truncated or fails
+    pub on_overlong: OnOverlong,
+    /// Size of the buffer in front of every written file; `None` leaves
+    /// the buffering to the encoders
+    pub write_buffer_bytes: Option<usize>,
+    /// When written files are synced to disk
+    pub fsync: Fsync,
+    /// Fail unless the options write this numbered schema version
+    pub require_schema_version: Option<u32>,
+    /// Print the plans of the generation query instead of writing files
+    pub explain: Option<Explain>,
+    /// Sort the source rows and pin the writer settings so identical runs
+    /// write byte-identical files
+    pub deterministic: bool,
+    /// Write the `ATTRIBUTION.txt` notice of the source and record its hash
+    /// in the manifest
+    pub include_attribution: bool,
+    /// Parameters recorded in the footer of every file, with
+    /// `--embed-params`; the input defaults to [`Self::source_provenance`]
+    pub generation_params: Option<GenerationParams>,
+    /// Filtered source row count to partition against instead of running a
+    /// count or relying on the built-in estimate
+    pub total_rows: Option<i64>,
+    /// Identifier shared by all parts written by one invocation
+    pub job_id: String,
+    /// Set when the run is interrupted; checked between parts and batches
+    pub cancellation: CancellationFlag,
+    pub on_interrupt: OnInterrupt,
 }
 
 impl ZoneDfArgs {
@@ -42,22 +517,559 @@ impl ZoneDfArgs {
     ) -> Self {
         Self {
             scale_factor,
+            allow_fractional_scale: false,
+            max_scale_factor: Some(DEFAULT_MAX_SCALE_FACTOR),
             output_dir,
             parts,
             part,
             output_file_size_mb,
-            parquet_row_group_bytes,
-            parquet_compression,
+            parquet: ParquetWriteOptions::new(parquet_compression, parquet_row_group_bytes),
+            file_format: ZoneFileFormat::default(),
+            emit_avro_schema: false,
+            max_rows_per_file: None,
+            zstd_train_dict: false,
+            transform: ZoneTransformOptions::default(),
+            themes: vec![ThemeInput::built_in(Theme::DivisionArea)],
+            name_column: None,
+            layout: ZoneLayout::default(),
+            filename_template: None,
+            name_with_sf: false,
+            balance: Balance::default(),
+            partition_scheme: PartitionScheme::default(),
+            antimeridian_aware: true,
+            part_boundaries: None,
+            part_extents: None,
+            rows: None,
+            sampling: Sampling::default(),
+            min_per_country: 1,
+            keep_geometry_types: vec![],
+            limit: None,
+            cleanup_on_failure: false,
+            also_merge: false,
+            spill_collect: false,
+            simplification_levels: vec![],
+            verify_key_order: false,
+            assert_contiguous_keys: false,
+            max_string_lengths: vec![],
+            on_overlong: OnOverlong::Truncate,
+            write_buffer_bytes: None,
+            fsync: Fsync::default(),
+            require_schema_version: None,
+            explain: None,
+            deterministic: false,
+            include_attribution: false,
+            generation_params: None,
+            total_rows: None,
+            job_id: uuid::Uuid::new_v4().to_string(),
+            cancellation: CancellationFlag::default(),
+            on_interrupt: OnInterrupt::default(),
+        }
+    }
+
+    pub fn with_allow_fractional_scale(mut self, allow_fractional_scale: bool) -> Self {
+        self.allow_fractional_scale = allow_fractional_scale;
+        self
+    }
+
+    pub fn with_max_scale_factor(mut self, max_scale_factor: Option<f64>) -> Self {
+        self.max_scale_factor = max_scale_factor;
+        self
+    }
+
+    pub fn with_transform(mut self, transform: ZoneTransformOptions) -> Self {
+        self.transform = transform;
+        self
+    }
+
+    /// Keeps the pinned writer defaults of [`Self::with_deterministic`]
+    pub fn with_parquet(mut self, parquet: ParquetWriteOptions) -> Self {
+        self.parquet = parquet.with_pinned_defaults(self.deterministic);
+        self
+    }
+
+    pub fn with_file_format(mut self, file_format: ZoneFileFormat) -> Self {
+        self.file_format = file_format;
+        self
+    }
+
+    pub fn with_emit_avro_schema(mut self, emit_avro_schema: bool) -> Self {
+        self.emit_avro_schema = emit_avro_schema;
+        self
+    }
+
+    /// Extension of the zone files, `parquet`, `orc` or `avro`
+    pub fn file_extension(&self) -> &'static str {
+        self.file_format.extension()
+    }
+
+    /// Path of the Avro schema written with [`Self::emit_avro_schema`]
+    pub fn avro_schema_filename(&self) -> PathBuf {
+        self.output_dir.join("zone.avsc")
+    }
+
+    /// Path of the merged copy of all parts written by `--also-merge`
+    pub fn merged_filename(&self) -> PathBuf {
+        self.output_dir
+            .join(MERGED_FILE_NAME)
+            .with_extension(self.file_extension())
+    }
+
+    pub fn with_zstd_train_dict(mut self, zstd_train_dict: bool) -> Self {
+        self.zstd_train_dict = zstd_train_dict;
+        self
+    }
+
+    /// The ZSTD level to train a dictionary for, when requested
+    pub fn zstd_dictionary_level(&self) -> Option<i32> {
+        match self.parquet.compression {
+            ParquetCompression::ZSTD(level) if self.zstd_train_dict => {
+                Some(level.compression_level())
+            }
+            _ => None,
+        }
+    }
+
+    pub fn with_themes(mut self, themes: Vec<ThemeInput>) -> Self {
+        self.themes = themes;
+        self
+    }
+
+    pub fn with_name_column(mut self, name_column: Option<String>) -> Self {
+        self.name_column = name_column;
+        self
+    }
+
+    /// The input description written to `z_source` and the manifest
+    pub fn source_provenance(&self) -> String {
+        source_provenance(&self.themes)
+    }
+
+    /// The attribution written to `z_attribution`
+    pub fn source_attribution(&self) -> String {
+        source_attribution(&self.themes)
+    }
+
+    pub fn with_include_attribution(mut self, include_attribution: bool) -> Self {
+        self.include_attribution = include_attribution;
+        self
+    }
+
+    pub fn with_layout(mut self, layout: ZoneLayout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    pub fn with_filename_template(mut self, filename_template: Option<FilenameTemplate>) -> Self {
+        self.filename_template = filename_template;
+        self
+    }
+
+    pub fn with_name_with_sf(mut self, name_with_sf: bool) -> Self {
+        self.name_with_sf = name_with_sf;
+        self
+    }
+
+    pub fn with_balance(
+        mut self,
+        balance: Balance,
+        part_boundaries: Option<Vec<PartBoundary>>,
+    ) -> Self {
+        self.balance = balance;
+        self.part_boundaries = part_boundaries;
+        self
+    }
+
+    pub fn with_partition_scheme(mut self, partition_scheme: PartitionScheme) -> Self {
+        self.partition_scheme = partition_scheme;
+        self
+    }
+
+    pub fn with_antimeridian_aware(mut self, antimeridian_aware: bool) -> Self {
+        self.antimeridian_aware = antimeridian_aware;
+        self
+    }
+
+    pub fn with_rows(mut self, rows: Option<u64>) -> Self {
+        self.rows = rows;
+        self
+    }
+
+    pub fn with_sampling(mut self, sampling: Sampling, min_per_country: u64) -> Self {
+        self.sampling = sampling;
+        self.min_per_country = min_per_country;
+        self
+    }
+
+    /// The sampling recorded in the manifest, `None` for the uniform
+    /// sampling of earlier manifests
+    pub fn sampling_description(&self) -> Option<String> {
+        match self.sampling {
+            Sampling::Uniform => None,
+            Sampling::StratifiedCountry => Some(format!(
+                "stratified-country, at least {} per country",
+                self.min_per_country
+            )),
         }
     }
 
+    pub fn with_keep_geometry_types(mut self, mut keep_geometry_types: Vec<GeometryType>) -> Self {
+        keep_geometry_types.sort();
+        keep_geometry_types.dedup();
+        self.keep_geometry_types = keep_geometry_types;
+        self
+    }
+
+    /// The names of [`Self::keep_geometry_types`] as recorded in the
+    /// manifest, `None` when every type is kept
+    pub fn keep_geometry_type_names(&self) -> Option<Vec<String>> {
+        (!self.keep_geometry_types.is_empty()).then(|| {
+            self.keep_geometry_types
+                .iter()
+                .map(|t| t.name().to_string())
+                .collect()
+        })
+    }
+
+    pub fn with_limit(mut self, limit: Option<u64>) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// `total_rows` capped by [`Self::limit`], the rows the parts are cut
+    /// from
+    pub fn expected_total_rows(&self) -> Option<i64> {
+        let total_rows = self.total_rows?;
+        Some(
+            self.limit
+                .map_or(total_rows, |limit| total_rows.min(limit as i64)),
+        )
+    }
+
+    pub fn with_cleanup_on_failure(mut self, cleanup_on_failure: bool) -> Self {
+        self.cleanup_on_failure = cleanup_on_failure;
+        self
+    }
+
+    pub fn with_also_merge(mut self, also_merge: bool) -> Self {
+        self.also_merge = also_merge;
+        self
+    }
+
+    pub fn with_spill_collect(mut self, spill_collect: bool) -> Self {
+        self.spill_collect = spill_collect;
+        self
+    }
+
+    pub fn with_simplification_levels(mut self, simplification_levels: Vec<f64>) -> Self {
+        self.simplification_levels = simplification_levels;
+        self
+    }
+
+    /// The tolerances of `simplification_levels` above 0, ascending and
+    /// without repeats. Level 0 is the full-resolution dataset itself.
+    pub fn simplified_levels(&self) -> Vec<f64> {
+        let mut levels: Vec<f64> = self
+            .simplification_levels
+            .iter()
+            .copied()
+            .filter(|tolerance| *tolerance > 0.0)
+            .collect();
+        levels.sort_by(f64::total_cmp);
+        levels.dedup();
+        levels
+    }
+
+    pub fn with_max_rows_per_file(mut self, max_rows_per_file: Option<usize>) -> Self {
+        self.max_rows_per_file = max_rows_per_file;
+        self
+    }
+
+    pub fn with_verify_key_order(mut self, verify_key_order: bool) -> Self {
+        self.verify_key_order = verify_key_order;
+        self
+    }
+
+    pub fn with_assert_contiguous_keys(mut self, assert_contiguous_keys: bool) -> Self {
+        self.assert_contiguous_keys = assert_contiguous_keys;
+        self
+    }
+
+    pub fn with_max_string_lengths(
+        mut self,
+        max_string_lengths: Vec<MaxStringLength>,
+        on_overlong: OnOverlong,
+    ) -> Self {
+        self.max_string_lengths = max_string_lengths;
+        self.on_overlong = on_overlong;
+        self
+    }
+
+    /// Path of the report of the values `--max-string-length` truncated in
+    /// this part
+    pub fn truncation_report_filename(&self) -> PathBuf {
+        self.output_dir
+            .join(format!("zone.{}.truncated.csv", self.part.unwrap_or(1)))
+    }
+
+    pub fn with_write_io(mut self, write_buffer_bytes: Option<usize>, fsync: Fsync) -> Self {
+        self.write_buffer_bytes = write_buffer_bytes;
+        self.fsync = fsync;
+        self
+    }
+
+    pub fn with_require_schema_version(mut self, require_schema_version: Option<u32>) -> Self {
+        self.require_schema_version = require_schema_version;
+        self
+    }
+
+    pub fn with_explain(mut self, explain: Option<Explain>) -> Self {
+        self.explain = explain;
+        self
+    }
+
+    /// Also replaces the random job id with the nil uuid, so Spark layout
+    /// file names repeat across runs
+    pub fn with_deterministic(mut self, deterministic: bool) -> Self {
+        self.deterministic = deterministic;
+        self.parquet.pinned_defaults = deterministic;
+        if deterministic {
+            self.job_id = uuid::Uuid::nil().to_string();
+        }
+        self
+    }
+
+    pub fn with_generation_params(mut self, generation_params: Option<GenerationParams>) -> Self {
+        self.generation_params = generation_params;
+        self
+    }
+
+    pub fn with_total_rows(mut self, total_rows: Option<i64>) -> Self {
+        self.total_rows = total_rows;
+        self
+    }
+
+    pub fn with_interrupt(
+        mut self,
+        cancellation: CancellationFlag,
+        on_interrupt: OnInterrupt,
+    ) -> Self {
+        self.cancellation = cancellation;
+        self.on_interrupt = on_interrupt;
+        self
+    }
+
     pub fn validate(&self) -> Result<()> {
+        zone_scale_factor(self.scale_factor, true)?;
+        check_scale_factor_ceiling(self.scale_factor, self.max_scale_factor)?;
+        if let Some(parts) = self.parts {
+            check_parts(parts)?;
+        }
         if let (Some(part), Some(parts)) = (self.part, self.parts) {
             if part < 1 || part > parts {
                 return Err(anyhow!("Invalid --part={} for --parts={}", part, parts));
             }
         }
 
+        if let Some(version) = self.require_schema_version {
+            SchemaVersion::require(&self.transform, version)?;
+        }
+        if self.limit == Some(0) {
+            return Err(anyhow!("Invalid --limit=0, expected at least one row"));
+        }
+        if let Some(total_rows) = self.total_rows {
+            if total_rows < 0 {
+                return Err(anyhow!("Invalid --total-rows={}", total_rows));
+            }
+        }
+
+        let inflate = self.transform.bbox_inflate;
+        if !inflate.is_finite() || inflate < 0.0 {
+            return Err(anyhow!(
+                "Invalid --bbox-inflate={inflate}, expected a non-negative distance"
+            ));
+        }
+        self.parquet.validate()?;
+        if self.write_buffer_bytes == Some(0) {
+            return Err(anyhow!("Invalid --write-buffer-bytes=0"));
+        }
+        if self.file_format != ZoneFileFormat::Parquet && self.zstd_train_dict {
+            return Err(anyhow!(
+                "--parquet-zstd-train-dict can't be combined with --format {}",
+                self.file_extension()
+            ));
+        }
+        if self.zstd_train_dict && !matches!(self.parquet.compression, ParquetCompression::ZSTD(_))
+        {
+            return Err(anyhow!(
+                "--parquet-zstd-train-dict requires ZSTD compression, not {}",
+                self.parquet.compression
+            ));
+        }
+        if self.emit_avro_schema && !matches!(self.file_format, ZoneFileFormat::Avro(_)) {
+            return Err(anyhow!("--emit-avro-schema requires --format avro"));
+        }
+
+        if let (Some(boundaries), Some(parts)) = (&self.part_boundaries, self.parts) {
+            if boundaries.len() != parts as usize {
+                return Err(anyhow!(
+                    "Plan has boundaries for {} part(s) but --parts={}",
+                    boundaries.len(),
+                    parts
+                ));
+            }
+        }
+
+        if self.sampling != Sampling::Uniform && self.rows.is_none() {
+            return Err(anyhow!(
+                "--sampling stratified-country needs the sample size from --rows zone=COUNT"
+            ));
+        }
+
+        if self.name_with_sf && self.filename_template.is_some() {
+            return Err(anyhow!(
+                "--name-with-sf can't be combined with --filename-template, use {{sf}} in the \
+                 template instead"
+            ));
+        }
+        if self.name_with_sf && self.layout == ZoneLayout::Spark {
+            return Err(anyhow!(
+                "--name-with-sf can't be combined with --layout spark"
+            ));
+        }
+        if let Some(template) = &self.filename_template {
+            if self.layout == ZoneLayout::Spark {
+                return Err(anyhow!(
+                    "--filename-template can't be combined with --layout spark"
+                ));
+            }
+            if self.parts.unwrap_or(1) > 1 && !template.has_part() {
+                return Err(anyhow!(
+                    "--filename-template {template} needs a {{part}} placeholder with --parts={}",
+                    self.parts.unwrap_or(1)
+                ));
+            }
+        }
+
+        if self.also_merge && self.part.is_some() {
+            return Err(anyhow!(
+                "--also-merge needs every part written by one run, not a single --part"
+            ));
+        }
+
+        if self.partition_scheme != PartitionScheme::Rows && self.balance != Balance::Rows {
+            return Err(anyhow!(
+                "Band partition strategies can't be combined with --balance vertices"
+            ));
+        }
+
+        if let Some(key_partition) = self.transform.key_partition {
+            for (option, set) in [
+                ("--include-hierarchy", self.transform.include_hierarchy),
+                ("--assert-contiguous-keys", self.assert_contiguous_keys),
+                ("--verify-key-order", self.verify_key_order),
+            ] {
+                if set {
+                    return Err(anyhow!(
+                        "--key-partition-by {} repeats z_zonekey values, so it can't be \
+                         combined with {option}",
+                        key_partition.column()
+                    ));
+                }
+            }
+        } else if self.transform.composite_key {
+            return Err(anyhow!("--composite-key needs --key-partition-by"));
+        }
+
+        if self.transform.geometry_encoding == GeometryEncoding::Geojson
+            && self.transform.bbox_covering
+        {
+            return Err(anyhow!(
+                "--with-bbox-covering declares z_boundary as GeoParquet WKB, so it can't be \
+                 combined with --geometry-encoding geojson"
+            ));
+        }
+
+        if let Some(tolerance) = self.transform.simplify_tolerance {
+            if !(tolerance >= 0.0 && tolerance.is_finite()) {
+                return Err(anyhow!(
+                    "Invalid --simplify-tolerance={tolerance}, expected a finite value of at \
+                     least 0"
+                ));
+            }
+            if self.transform.keep_zm {
+                return Err(anyhow!(
+                    "--simplify-tolerance writes 2D geometries, so it can't be combined with \
+                     --keep-zm"
+                ));
+            }
+        }
+
+        if !self.simplification_levels.is_empty() {
+            if let Some(tolerance) = self
+                .simplification_levels
+                .iter()
+                .find(|tolerance| !(**tolerance >= 0.0 && tolerance.is_finite()))
+            {
+                return Err(anyhow!(
+                    "Invalid --simplification-levels value {tolerance}, expected finite values \
+                     of at least 0"
+                ));
+            }
+            if self.transform.keep_zm {
+                return Err(anyhow!(
+                    "--simplification-levels writes 2D geometries, so it can't be combined \
+                     with --keep-zm"
+                ));
+            }
+            if matches!(self.file_format, ZoneFileFormat::Csv | ZoneFileFormat::Tbl) {
+                return Err(anyhow!(
+                    "--simplification-levels writes the levels from the collected rows, which \
+                     {} parts are streamed past",
+                    self.file_extension()
+                ));
+            }
+        }
+
+        if self.transform.no_geometry {
+            for (option, set) in [
+                ("--with-bbox-covering", self.transform.bbox_covering),
+                ("--with-area", self.transform.area.is_some()),
+                ("--point-as-lonlat", self.transform.point_as_lonlat),
+                ("--extra-columns", !self.transform.extra_columns.is_empty()),
+                (
+                    "--normalize-winding",
+                    self.transform.normalize_winding.is_some(),
+                ),
+                (
+                    "--simplify-tolerance",
+                    self.transform.simplify_tolerance.is_some(),
+                ),
+                (
+                    "--simplification-levels",
+                    !self.simplification_levels.is_empty(),
+                ),
+                (
+                    "--geometry-encoding geojson",
+                    self.transform.geometry_encoding != GeometryEncoding::Wkb,
+                ),
+                ("--balance vertices", self.balance != Balance::Rows),
+                (
+                    "--partition-strategy",
+                    self.partition_scheme != PartitionScheme::Rows,
+                ),
+            ] {
+                if set {
+                    return Err(anyhow!(
+                        "--no-geometry leaves out z_boundary, which {option} reads"
+                    ));
+                }
+            }
+        }
+
+        if self.max_rows_per_file == Some(0) {
+            return Err(anyhow!("--max-rows-per-file must be at least 1"));
+        }
+
         if self.output_file_size_mb.is_some() && (self.parts.is_some() || self.part.is_some()) {
             return Err(anyhow!(
                 "Cannot specify --parts/--part with --max-file-size-mb"
@@ -68,13 +1080,488 @@ impl ZoneDfArgs {
     }
 
     pub fn output_filename(&self) -> PathBuf {
+        if self.layout == ZoneLayout::Spark {
+            return self.output_dir.join("zone").join(format!(
+                "{}-{}{}",
+                Self::spark_part_prefix(self.part.unwrap_or(1)),
+                self.job_id,
+                self.spark_suffix()
+            ));
+        }
+
+        let template = match self.name_with_sf {
+            true => Some(FilenameTemplate::with_scale_factor(self.parts.unwrap_or(1))),
+            false => self.filename_template.clone(),
+        };
+        if let Some(template) = template {
+            let path = self.output_dir.join("zone").join(template.render(
+                "zone",
+                self.part.unwrap_or(1),
+                self.parts.unwrap_or(1),
+                self.scale_factor,
+            ));
+            // A template given on the command line names the whole file
+            return match self.name_with_sf {
+                true => path.with_extension(self.file_extension()),
+                false => path,
+            };
+        }
+
         if self.parts.unwrap_or(1) > 1 {
             // Create zone subdirectory and write parts within it
-            self.output_dir
-                .join("zone")
-                .join(format!("zone.{}.parquet", self.part.unwrap_or(1)))
+            self.output_dir.join("zone").join(format!(
+                "zone.{}.{}",
+                self.part.unwrap_or(1),
+                self.file_extension()
+            ))
         } else {
-            self.output_dir.join("zone.parquet")
+            self.merged_filename()
+        }
+    }
+
+    /// Path of file `index` of a part split by `--max-rows-per-file`: the
+    /// part file name with `-{index}` before its extension, so part 3 is
+    /// written to `zone.3-0.parquet`, `zone.3-1.parquet`, ... A single part
+    /// is split in the `zone` directory as part 1, and Spark layout files
+    /// take Spark's `-c000` file counter.
+    pub fn split_filename(&self, index: usize) -> PathBuf {
+        let part = self.part.unwrap_or(1);
+        if self.layout == ZoneLayout::Spark {
+            return self.output_dir.join("zone").join(format!(
+                "{}-{}-c{index:03}{}",
+                Self::spark_part_prefix(part),
+                self.job_id,
+                self.spark_suffix()
+            ));
         }
+
+        let mut path = self.output_filename();
+        if path == self.merged_filename() {
+            path = self
+                .output_dir
+                .join("zone")
+                .join(format!("zone.{part}.{}", self.file_extension()));
+        }
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let name = match path.extension() {
+            Some(extension) => format!("{stem}-{index}.{}", extension.to_string_lossy()),
+            None => format!("{stem}-{index}"),
+        };
+        path.with_file_name(name)
+    }
+
+    /// Codec and extension ending Spark layout file names, such as
+    /// `.snappy.parquet`, or `.orc` for uncompressed ORC as Spark names them.
+    /// Spark names Avro files `.avro` whatever their codec.
+    fn spark_suffix(&self) -> String {
+        match &self.file_format {
+            ZoneFileFormat::Parquet => {
+                format!(".{}.parquet", spark_codec_name(self.parquet.compression))
+            }
+            ZoneFileFormat::Orc(orc) => match orc.compression.spark_name() {
+                Some(codec) => format!(".{codec}.orc"),
+                None => ".orc".to_string(),
+            },
+            ZoneFileFormat::Avro(_) => ".avro".to_string(),
+            ZoneFileFormat::Csv => ".csv".to_string(),
+            ZoneFileFormat::Tbl => ".tbl".to_string(),
+        }
+    }
+
+    /// Spark numbers part files from zero, so part N is `part-{N-1:05}`
+    pub fn spark_part_prefix(part: i32) -> String {
+        format!("part-{:05}", part - 1)
+    }
+}
+
+/// Codec name Spark puts in part file names
+fn spark_codec_name(compression: ParquetCompression) -> &'static str {
+    match compression {
+        ParquetCompression::UNCOMPRESSED => "uncompressed",
+        ParquetCompression::SNAPPY => "snappy",
+        ParquetCompression::GZIP(_) => "gz",
+        ParquetCompression::LZO => "lzo",
+        ParquetCompression::BROTLI(_) => "br",
+        ParquetCompression::LZ4 | ParquetCompression::LZ4_RAW => "lz4",
+        ParquetCompression::ZSTD(_) => "zstd",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zone::OrcCompression;
+
+    #[test]
+    fn test_spark_layout_filenames() {
+        let args = ZoneDfArgs::new(
+            1.0,
+            PathBuf::from("out"),
+            Some(4),
+            Some(3),
+            None,
+            0,
+            ParquetCompression::SNAPPY,
+        )
+        .with_layout(ZoneLayout::Spark);
+
+        assert_eq!(
+            args.output_filename(),
+            PathBuf::from("out/zone").join(format!("part-00002-{}.snappy.parquet", args.job_id))
+        );
+
+        let args = ZoneDfArgs {
+            layout: ZoneLayout::Spatialbench,
+            ..args
+        };
+        assert_eq!(
+            args.output_filename(),
+            PathBuf::from("out/zone/zone.3.parquet")
+        );
+    }
+
+    #[test]
+    fn test_split_filenames() {
+        let args = ZoneDfArgs::new(
+            1.0,
+            PathBuf::from("out"),
+            Some(4),
+            Some(3),
+            None,
+            0,
+            ParquetCompression::SNAPPY,
+        );
+        assert_eq!(
+            args.split_filename(1),
+            PathBuf::from("out/zone/zone.3-1.parquet")
+        );
+
+        let single = ZoneDfArgs {
+            parts: None,
+            part: None,
+            ..args.clone()
+        };
+        assert_eq!(
+            single.split_filename(0),
+            PathBuf::from("out/zone/zone.1-0.parquet")
+        );
+
+        let spark = args.with_layout(ZoneLayout::Spark);
+        assert_eq!(
+            spark.split_filename(12),
+            PathBuf::from("out/zone")
+                .join(format!("part-00002-{}-c012.snappy.parquet", spark.job_id))
+        );
+    }
+
+    #[test]
+    fn test_orc_filenames() {
+        let args = ZoneDfArgs::new(
+            10.0,
+            PathBuf::from("out"),
+            None,
+            None,
+            None,
+            0,
+            ParquetCompression::SNAPPY,
+        )
+        .with_file_format(ZoneFileFormat::Orc(OrcWriteOptions::default()));
+        assert_eq!(args.output_filename(), PathBuf::from("out/zone.orc"));
+        assert_eq!(args.merged_filename(), PathBuf::from("out/zone.orc"));
+        assert_eq!(
+            args.split_filename(1),
+            PathBuf::from("out/zone/zone.1-1.orc")
+        );
+
+        let parts = ZoneDfArgs {
+            parts: Some(4),
+            part: Some(3),
+            ..args.clone()
+        };
+        assert_eq!(
+            parts.output_filename(),
+            PathBuf::from("out/zone/zone.3.orc")
+        );
+        assert_eq!(
+            parts.clone().with_name_with_sf(true).output_filename(),
+            PathBuf::from("out/zone/zone.sf10.3.orc")
+        );
+
+        let spark = parts.with_layout(ZoneLayout::Spark);
+        assert_eq!(
+            spark.output_filename(),
+            PathBuf::from("out/zone").join(format!("part-00002-{}.zlib.orc", spark.job_id))
+        );
+        let uncompressed = spark.with_file_format(ZoneFileFormat::Orc(OrcWriteOptions::new(
+            OrcCompression::None,
+            0,
+        )));
+        assert_eq!(
+            uncompressed.split_filename(0),
+            PathBuf::from("out/zone").join(format!("part-00002-{}-c000.orc", uncompressed.job_id))
+        );
+    }
+
+    #[test]
+    fn test_zone_scale_factor() {
+        for invalid in [-1.0, 0.0, f64::NAN, f64::INFINITY] {
+            let err = zone_scale_factor(invalid, true).unwrap_err();
+            assert!(
+                err.to_string().contains("expected a finite number above 0"),
+                "{err}"
+            );
+        }
+        assert_eq!(zone_scale_factor(0.1, false).unwrap(), 1.0);
+        assert_eq!(zone_scale_factor(0.1, true).unwrap(), 0.1);
+        for large in [1000.0, 1e6] {
+            assert_eq!(zone_scale_factor(large, false).unwrap(), large);
+            assert_eq!(zone_scale_factor(large, true).unwrap(), large);
+        }
+    }
+
+    #[test]
+    fn test_column_selection_order() {
+        use arrow_schema::{DataType, Field};
+        let schema = Schema::new(
+            ["z_zonekey", "z_gersid", "z_name", "z_boundary"]
+                .map(|name| Field::new(name, DataType::Utf8, true))
+                .to_vec(),
+        );
+        let names = |schema: SchemaRef| -> Vec<String> {
+            schema.fields().iter().map(|f| f.name().clone()).collect()
+        };
+        let selected = vec![
+            "z_boundary".to_string(),
+            "z_zonekey".to_string(),
+            "z_name".to_string(),
+        ];
+
+        let schema_order = ColumnSelection::new(selected.clone(), ColumnOrder::Schema);
+        assert_eq!(
+            names(schema_order.project(&schema).unwrap()),
+            ["z_zonekey", "z_name", "z_boundary"]
+        );
+        let as_specified = ColumnSelection::new(selected, ColumnOrder::AsSpecified);
+        assert_eq!(
+            names(as_specified.project(&schema).unwrap()),
+            ["z_boundary", "z_zonekey", "z_name"]
+        );
+
+        let unknown = ColumnSelection::new(vec!["z_area".to_string()], ColumnOrder::Schema);
+        let error = unknown.project(&schema).unwrap_err().to_string();
+        assert!(
+            error.contains("z_area, which is not a zone column"),
+            "{error}"
+        );
+        let repeated = ColumnSelection::new(
+            vec!["z_name".to_string(), "z_name".to_string()],
+            ColumnOrder::Schema,
+        );
+        assert!(repeated.project(&schema).is_err());
+    }
+
+    #[test]
+    fn test_avro_filenames() {
+        let args = ZoneDfArgs::new(
+            1.0,
+            PathBuf::from("out"),
+            Some(4),
+            Some(3),
+            None,
+            0,
+            ParquetCompression::SNAPPY,
+        )
+        .with_file_format(ZoneFileFormat::Avro(AvroCodec::Snappy));
+        assert_eq!(
+            args.output_filename(),
+            PathBuf::from("out/zone/zone.3.avro")
+        );
+        assert_eq!(
+            args.split_filename(2),
+            PathBuf::from("out/zone/zone.3-2.avro")
+        );
+        assert_eq!(args.merged_filename(), PathBuf::from("out/zone.avro"));
+
+        let spark = args.clone().with_layout(ZoneLayout::Spark);
+        assert_eq!(
+            spark.output_filename(),
+            PathBuf::from("out/zone").join(format!("part-00002-{}.avro", spark.job_id))
+        );
+
+        assert!(args.clone().with_emit_avro_schema(true).validate().is_ok());
+        let parquet = args.with_file_format(ZoneFileFormat::Parquet);
+        let error = parquet.with_emit_avro_schema(true).validate().unwrap_err();
+        assert!(
+            error.to_string().contains("requires --format avro"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn test_filename_template() {
+        let template = |t: &str| Some(t.parse::<FilenameTemplate>().unwrap());
+        let args = ZoneDfArgs::new(
+            1.0,
+            PathBuf::from("out"),
+            Some(16),
+            Some(1),
+            None,
+            0,
+            ParquetCompression::SNAPPY,
+        )
+        .with_filename_template(template("{table}-part-{part:04}-of-{parts:04}.parquet"));
+        args.validate().unwrap();
+        assert_eq!(
+            args.output_filename(),
+            PathBuf::from("out/zone/zone-part-0001-of-0016.parquet")
+        );
+
+        // A single part still goes in the zone directory
+        let single = ZoneDfArgs {
+            parts: Some(1),
+            part: None,
+            ..args.clone()
+        }
+        .with_filename_template(template("nyc_{table}.parquet"));
+        single.validate().unwrap();
+        assert_eq!(
+            single.output_filename(),
+            PathBuf::from("out/zone/nyc_zone.parquet")
+        );
+
+        let error = args
+            .clone()
+            .with_filename_template(template("{table}-of-{parts}.parquet"))
+            .validate()
+            .unwrap_err();
+        assert!(error.to_string().contains("needs a {part} placeholder"));
+        let error = args.with_layout(ZoneLayout::Spark).validate().unwrap_err();
+        assert!(error.to_string().contains("--layout spark"));
+    }
+
+    #[test]
+    fn test_simplification_levels_validated() {
+        let args = ZoneDfArgs::new(
+            1.0,
+            PathBuf::from("out"),
+            None,
+            None,
+            None,
+            0,
+            ParquetCompression::SNAPPY,
+        );
+        args.clone()
+            .with_simplification_levels(vec![0.0, 0.001])
+            .validate()
+            .unwrap();
+        let error = args
+            .clone()
+            .with_simplification_levels(vec![0.001, -1.0])
+            .validate()
+            .unwrap_err();
+        assert!(error.to_string().contains("value -1"), "{error}");
+        let error = args
+            .clone()
+            .with_simplification_levels(vec![0.001])
+            .with_file_format(ZoneFileFormat::Csv)
+            .validate()
+            .unwrap_err();
+        assert!(error.to_string().contains("csv parts"), "{error}");
+    }
+
+    #[test]
+    fn test_scale_factor_ceiling_and_parts_validated() {
+        let args = |scale_factor: f64, parts: Option<i32>| {
+            ZoneDfArgs::new(
+                scale_factor,
+                PathBuf::from("out"),
+                parts,
+                None,
+                None,
+                0,
+                ParquetCompression::SNAPPY,
+            )
+        };
+        args(DEFAULT_MAX_SCALE_FACTOR, Some(1)).validate().unwrap();
+        let error = args(1e12, None).validate().unwrap_err();
+        assert!(error.to_string().contains("--yes-i-know"), "{error}");
+        args(1e12, None)
+            .with_max_scale_factor(None)
+            .validate()
+            .unwrap();
+        args(1e12, None)
+            .with_max_scale_factor(Some(1e13))
+            .validate()
+            .unwrap();
+
+        for parts in [0, -4] {
+            let error = args(1.0, Some(parts)).validate().unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                format!("Invalid --parts={parts}, expected at least 1")
+            );
+        }
+        assert_eq!(check_parts(PARTS_WARNING_THRESHOLD + 1).unwrap(), 100_001);
+    }
+
+    #[test]
+    fn test_simplify_tolerance_validated() {
+        let args = |tolerance: f64, keep_zm: bool| {
+            ZoneDfArgs::new(
+                1.0,
+                PathBuf::from("out"),
+                None,
+                None,
+                None,
+                0,
+                ParquetCompression::SNAPPY,
+            )
+            .with_transform(ZoneTransformOptions {
+                simplify_tolerance: Some(tolerance),
+                simplify_algorithm: SimplifyAlgorithm::VisvalingamWhyatt,
+                keep_zm,
+                ..Default::default()
+            })
+        };
+        args(0.001, false).validate().unwrap();
+        let error = args(-1.0, false).validate().unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("Invalid --simplify-tolerance=-1"));
+        let error = args(0.001, true).validate().unwrap_err();
+        assert!(error.to_string().contains("--keep-zm"));
+    }
+
+    #[test]
+    fn test_name_with_sf() {
+        let args = |scale_factor: f64, parts: i32, part: Option<i32>| {
+            ZoneDfArgs::new(
+                scale_factor,
+                PathBuf::from("out"),
+                Some(parts),
+                part,
+                None,
+                0,
+                ParquetCompression::SNAPPY,
+            )
+            .with_name_with_sf(true)
+        };
+        assert_eq!(
+            args(10.0, 4, Some(2)).output_filename(),
+            PathBuf::from("out/zone/zone.sf10.2.parquet")
+        );
+        assert_eq!(
+            args(0.1, 1, None).output_filename(),
+            PathBuf::from("out/zone/zone.sf0_1.parquet")
+        );
+
+        let template = "{table}.{part}.parquet"
+            .parse::<FilenameTemplate>()
+            .unwrap();
+        let error = args(1.0, 2, None)
+            .with_filename_template(Some(template))
+            .validate()
+            .unwrap_err();
+        assert!(error.to_string().contains("use {sf} in the template"));
     }
 }
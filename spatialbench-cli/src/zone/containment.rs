@@ -0,0 +1,345 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Trip to zone containment join
+//!
+//! Materializes the zone containing the pickup and the dropoff point of
+//! every trip as `trip_zone.parquet`. Zone boundaries are indexed in an
+//! R-tree of their bounding boxes, and trips are streamed from their
+//! Parquet files a few batches at a time, so only the index is held in
+//! memory. A point on the boundary of a zone counts as inside it. Where
+//! zones overlap the one with the smallest area wins, ties going to the
+//! smallest `z_zonekey`; a point in no zone gets a null key.
+
+use anyhow::{anyhow, Result};
+use arrow::compute::cast;
+use arrow_array::{Array, ArrayRef, BinaryArray, Int64Array, RecordBatch};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use geo::{Area, BoundingRect, Geometry, Intersects, Point};
+use geozero::wkb::Wkb;
+use geozero::ToGeo;
+use log::{info, warn};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::arrow::{ArrowWriter, ProjectionMask};
+use rstar::primitives::{GeomWithData, Rectangle};
+use rstar::{RTree, AABB};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use super::batch::map_batches;
+use crate::load_scripts::TableFiles;
+
+/// File written into the data directory
+pub const OUTPUT_FILE: &str = "trip_zone.parquet";
+
+struct Zone {
+    zonekey: i64,
+    area: f64,
+    geometry: Geometry,
+}
+
+/// Zone boundaries indexed by their bounding boxes
+pub struct ZoneIndex {
+    zones: Vec<Zone>,
+    tree: RTree<GeomWithData<Rectangle<[f64; 2]>, usize>>,
+}
+
+impl ZoneIndex {
+    /// Indexes the `z_zonekey` and `z_boundary` columns of `batches`.
+    /// Zones whose boundary is null or not valid WKB are skipped.
+    pub fn new<'a>(batches: impl IntoIterator<Item = &'a RecordBatch>) -> Result<Self> {
+        let mut zones = Vec::new();
+        let mut skipped = 0;
+        for batch in batches {
+            let keys = int64_column(batch, "z_zonekey")?;
+            let boundaries = binary_column(batch, "z_boundary")?;
+            let boundaries = boundaries.as_any().downcast_ref::<BinaryArray>().unwrap();
+            for row in 0..batch.num_rows() {
+                let geometry = match boundaries.is_valid(row) {
+                    true => Wkb(boundaries.value(row)).to_geo().ok(),
+                    false => None,
+                };
+                match (keys.is_valid(row), geometry) {
+                    (true, Some(geometry)) => zones.push(Zone {
+                        zonekey: keys.value(row),
+                        area: geometry.unsigned_area(),
+                        geometry,
+                    }),
+                    _ => skipped += 1,
+                }
+            }
+        }
+        if skipped > 0 {
+            warn!("Skipped {skipped} zone(s) without a key or a readable boundary");
+        }
+
+        let entries = zones
+            .iter()
+            .enumerate()
+            .filter_map(|(i, zone)| {
+                let rect = zone.geometry.bounding_rect()?;
+                let (min, max) = (rect.min(), rect.max());
+                Some(GeomWithData::new(
+                    Rectangle::from_corners([min.x, min.y], [max.x, max.y]),
+                    i,
+                ))
+            })
+            .collect();
+        Ok(Self {
+            zones,
+            tree: RTree::bulk_load(entries),
+        })
+    }
+
+    /// Reads the zone columns of `files`, one batch at a time
+    pub fn read(files: &[PathBuf]) -> Result<Self> {
+        let mut batches = Vec::new();
+        for path in files {
+            let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(path)?)?;
+            let mask =
+                ProjectionMask::columns(builder.parquet_schema(), ["z_zonekey", "z_boundary"]);
+            for batch in builder.with_projection(mask).build()? {
+                batches.push(batch?);
+            }
+        }
+        Self::new(&batches)
+    }
+
+    pub fn len(&self) -> usize {
+        self.zones.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.zones.is_empty()
+    }
+
+    /// Key of the zone containing `(x, y)`, see the module documentation
+    pub fn zone_of(&self, x: f64, y: f64) -> Option<i64> {
+        let point = Point::new(x, y);
+        self.tree
+            .locate_in_envelope_intersecting(&AABB::from_point([x, y]))
+            .map(|entry| &self.zones[entry.data])
+            .filter(|zone| zone.geometry.intersects(&point))
+            .min_by(|a, b| a.area.total_cmp(&b.area).then(a.zonekey.cmp(&b.zonekey)))
+            .map(|zone| zone.zonekey)
+    }
+
+    /// Zone keys of the pickup and dropoff point of every trip of `batch`
+    pub fn join_trips(&self, batch: &RecordBatch) -> Result<RecordBatch> {
+        let tripkeys = int64_column(batch, "t_tripkey")?;
+        let zones_of = |name: &str| -> Result<ArrayRef> {
+            let points = binary_column(batch, name)?;
+            let points = points.as_any().downcast_ref::<BinaryArray>().unwrap();
+            let keys: Int64Array = points
+                .iter()
+                .map(|wkb| match Wkb(wkb?).to_geo().ok()? {
+                    Geometry::Point(point) => self.zone_of(point.x(), point.y()),
+                    _ => None,
+                })
+                .collect();
+            Ok(Arc::new(keys))
+        };
+        Ok(RecordBatch::try_new(
+            output_schema(),
+            vec![
+                Arc::new(tripkeys),
+                zones_of("t_pickuploc")?,
+                zones_of("t_dropoffloc")?,
+            ],
+        )?)
+    }
+}
+
+/// Schema of `trip_zone.parquet`
+pub fn output_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("t_tripkey", DataType::Int64, false),
+        Field::new("pickup_zonekey", DataType::Int64, true),
+        Field::new("dropoff_zonekey", DataType::Int64, true),
+    ]))
+}
+
+pub(super) fn int64_column(batch: &RecordBatch, name: &str) -> Result<Int64Array> {
+    let column = batch
+        .column_by_name(name)
+        .ok_or_else(|| anyhow!("Column {name} not found"))?;
+    Ok(cast(column, &DataType::Int64)?
+        .as_any()
+        .downcast_ref::<Int64Array>()
+        .ok_or_else(|| anyhow!("Column {name} is not an integer column"))?
+        .clone())
+}
+
+pub(super) fn binary_column(batch: &RecordBatch, name: &str) -> Result<ArrayRef> {
+    let column = batch
+        .column_by_name(name)
+        .ok_or_else(|| anyhow!("Column {name} not found"))?;
+    Ok(cast(column, &DataType::Binary)?)
+}
+
+/// Rows written and points that fell in a zone
+#[derive(Debug, Default, PartialEq)]
+pub struct ContainmentSummary {
+    pub trips: u64,
+    pub pickups_in_zone: u64,
+    pub dropoffs_in_zone: u64,
+}
+
+/// Joins the trips of `data_dir` with its zones into `trip_zone.parquet`,
+/// processing up to `jobs` trip batches at a time in parallel
+pub fn materialize_zone_containment(data_dir: &Path, jobs: usize) -> Result<ContainmentSummary> {
+    let zones = TableFiles::find(data_dir, "zone")?;
+    let index = ZoneIndex::read(&zones.files)?;
+    info!(
+        "Indexed {} zone(s) from {}",
+        index.len(),
+        zones.path.display()
+    );
+    if index.is_empty() {
+        warn!(
+            "No readable zones in {}, every zone key will be null",
+            zones.path.display()
+        );
+    }
+
+    let trips = TableFiles::find(data_dir, "trip")?;
+    let output_path = data_dir.join(OUTPUT_FILE);
+    let temp_path = output_path.with_extension("inprogress");
+    let mut writer = ArrowWriter::try_new(File::create(&temp_path)?, output_schema(), None)?;
+
+    let jobs = jobs.max(1);
+    let mut summary = ContainmentSummary::default();
+    let mut write = |batches: &mut Vec<RecordBatch>| -> Result<()> {
+        for joined in map_batches(batches, Some(jobs), |_, batch| index.join_trips(batch))? {
+            summary.trips += joined.num_rows() as u64;
+            summary.pickups_in_zone += (joined.num_rows() - joined.column(1).null_count()) as u64;
+            summary.dropoffs_in_zone += (joined.num_rows() - joined.column(2).null_count()) as u64;
+            writer.write(&joined)?;
+        }
+        batches.clear();
+        Ok(())
+    };
+    for path in &trips.files {
+        let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(path)?)?;
+        let mask = ProjectionMask::columns(
+            builder.parquet_schema(),
+            ["t_tripkey", "t_pickuploc", "t_dropoffloc"],
+        );
+        let mut pending = Vec::with_capacity(jobs);
+        for batch in builder.with_projection(mask).build()? {
+            pending.push(batch?);
+            if pending.len() == jobs {
+                write(&mut pending)?;
+            }
+        }
+        write(&mut pending)?;
+    }
+    writer.close()?;
+    std::fs::rename(&temp_path, &output_path)?;
+
+    info!(
+        "Wrote {} trip(s) to {}",
+        summary.trips,
+        output_path.display()
+    );
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zone::fixtures::{wkb_point, wkb_polygon};
+
+    fn square(x: f64, y: f64, size: f64) -> Vec<u8> {
+        wkb_polygon(&[
+            (x, y),
+            (x + size, y),
+            (x + size, y + size),
+            (x, y + size),
+            (x, y),
+        ])
+    }
+
+    fn zones(zones: &[(i64, Option<Vec<u8>>)]) -> RecordBatch {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("z_zonekey", DataType::Int64, false),
+            Field::new("z_boundary", DataType::Binary, true),
+        ]));
+        let keys = Int64Array::from_iter_values(zones.iter().map(|(key, _)| *key));
+        let boundaries = BinaryArray::from_iter(zones.iter().map(|(_, wkb)| wkb.as_deref()));
+        RecordBatch::try_new(schema, vec![Arc::new(keys), Arc::new(boundaries)]).unwrap()
+    }
+
+    #[test]
+    fn test_zone_of_smallest_area_wins() {
+        let index = ZoneIndex::new(&[zones(&[
+            (1, Some(square(0.0, 0.0, 10.0))),
+            (2, Some(square(2.0, 2.0, 2.0))),
+            // Same area as zone 2, the smaller key wins where they overlap
+            (4, Some(square(3.0, 3.0, 2.0))),
+            (3, Some(square(3.0, 3.0, 2.0))),
+            (5, None),
+            (6, Some(b"not wkb".to_vec())),
+        ])])
+        .unwrap();
+        assert_eq!(index.len(), 4);
+
+        assert_eq!(index.zone_of(1.0, 1.0), Some(1));
+        assert_eq!(index.zone_of(2.5, 2.5), Some(2));
+        assert_eq!(index.zone_of(3.5, 3.5), Some(2));
+        assert_eq!(index.zone_of(4.5, 4.5), Some(3));
+        // Boundary points count as inside
+        assert_eq!(index.zone_of(10.0, 5.0), Some(1));
+        assert_eq!(index.zone_of(11.0, 5.0), None);
+    }
+
+    #[test]
+    fn test_join_trips() {
+        let index = ZoneIndex::new(&[zones(&[(7, Some(square(0.0, 0.0, 1.0)))])]).unwrap();
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("t_tripkey", DataType::Int64, false),
+            Field::new("t_pickuploc", DataType::Binary, false),
+            Field::new("t_dropoffloc", DataType::Binary, false),
+        ]));
+        let inside = wkb_point(0.5, 0.5);
+        let outside = wkb_point(5.0, 5.0);
+        let trips = RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(Int64Array::from(vec![1, 2])),
+                Arc::new(BinaryArray::from(vec![&inside[..], &outside[..]])),
+                Arc::new(BinaryArray::from(vec![&outside[..], b"not wkb"])),
+            ],
+        )
+        .unwrap();
+
+        let joined = index.join_trips(&trips).unwrap();
+        let column = |i: usize| {
+            joined
+                .column(i)
+                .as_any()
+                .downcast_ref::<Int64Array>()
+                .unwrap()
+                .iter()
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(column(0), vec![Some(1), Some(2)]);
+        assert_eq!(column(1), vec![Some(7), None]);
+        assert_eq!(column(2), vec![None, None]);
+    }
+}
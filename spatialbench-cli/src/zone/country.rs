@@ -0,0 +1,469 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Normalization of `z_country` values to ISO 3166-1 alpha-2 codes
+
+use anyhow::Result;
+use arrow_array::RecordBatch;
+use log::{info, warn};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::OnceLock;
+
+use super::batch::map_string_column;
+
+/// ISO 3166-1 entries as (alpha-2, alpha-3, English short name)
+const COUNTRIES: &[(&str, &str, &str)] = &[
+    ("AD", "AND", "Andorra"),
+    ("AE", "ARE", "United Arab Emirates"),
+    ("AF", "AFG", "Afghanistan"),
+    ("AG", "ATG", "Antigua and Barbuda"),
+    ("AI", "AIA", "Anguilla"),
+    ("AL", "ALB", "Albania"),
+    ("AM", "ARM", "Armenia"),
+    ("AO", "AGO", "Angola"),
+    ("AQ", "ATA", "Antarctica"),
+    ("AR", "ARG", "Argentina"),
+    ("AS", "ASM", "American Samoa"),
+    ("AT", "AUT", "Austria"),
+    ("AU", "AUS", "Australia"),
+    ("AW", "ABW", "Aruba"),
+    ("AX", "ALA", "Aland Islands"),
+    ("AZ", "AZE", "Azerbaijan"),
+    ("BA", "BIH", "Bosnia and Herzegovina"),
+    ("BB", "BRB", "Barbados"),
+    ("BD", "BGD", "Bangladesh"),
+    ("BE", "BEL", "Belgium"),
+    ("BF", "BFA", "Burkina Faso"),
+    ("BG", "BGR", "Bulgaria"),
+    ("BH", "BHR", "Bahrain"),
+    ("BI", "BDI", "Burundi"),
+    ("BJ", "BEN", "Benin"),
+    ("BL", "BLM", "Saint Barthelemy"),
+    ("BM", "BMU", "Bermuda"),
+    ("BN", "BRN", "Brunei Darussalam"),
+    ("BO", "BOL", "Bolivia"),
+    ("BQ", "BES", "Bonaire, Sint Eustatius and Saba"),
+    ("BR", "BRA", "Brazil"),
+    ("BS", "BHS", "Bahamas"),
+    ("BT", "BTN", "Bhutan"),
+    ("BV", "BVT", "Bouvet Island"),
+    ("BW", "BWA", "Botswana"),
+    ("BY", "BLR", "Belarus"),
+    ("BZ", "BLZ", "Belize"),
+    ("CA", "CAN", "Canada"),
+    ("CC", "CCK", "Cocos (Keeling) Islands"),
+    ("CD", "COD", "Democratic Republic of the Congo"),
+    ("CF", "CAF", "Central African Republic"),
+    ("CG", "COG", "Congo"),
+    ("CH", "CHE", "Switzerland"),
+    ("CI", "CIV", "Cote d'Ivoire"),
+    ("CK", "COK", "Cook Islands"),
+    ("CL", "CHL", "Chile"),
+    ("CM", "CMR", "Cameroon"),
+    ("CN", "CHN", "China"),
+    ("CO", "COL", "Colombia"),
+    ("CR", "CRI", "Costa Rica"),
+    ("CU", "CUB", "Cuba"),
+    ("CV", "CPV", "Cabo Verde"),
+    ("CW", "CUW", "Curacao"),
+    ("CX", "CXR", "Christmas Island"),
+    ("CY", "CYP", "Cyprus"),
+    ("CZ", "CZE", "Czechia"),
+    ("DE", "DEU", "Germany"),
+    ("DJ", "DJI", "Djibouti"),
+    ("DK", "DNK", "Denmark"),
+    ("DM", "DMA", "Dominica"),
+    ("DO", "DOM", "Dominican Republic"),
+    ("DZ", "DZA", "Algeria"),
+    ("EC", "ECU", "Ecuador"),
+    ("EE", "EST", "Estonia"),
+    ("EG", "EGY", "Egypt"),
+    ("EH", "ESH", "Western Sahara"),
+    ("ER", "ERI", "Eritrea"),
+    ("ES", "ESP", "Spain"),
+    ("ET", "ETH", "Ethiopia"),
+    ("FI", "FIN", "Finland"),
+    ("FJ", "FJI", "Fiji"),
+    ("FK", "FLK", "Falkland Islands"),
+    ("FM", "FSM", "Micronesia"),
+    ("FO", "FRO", "Faroe Islands"),
+    ("FR", "FRA", "France"),
+    ("GA", "GAB", "Gabon"),
+    ("GB", "GBR", "United Kingdom"),
+    ("GD", "GRD", "Grenada"),
+    ("GE", "GEO", "Georgia"),
+    ("GF", "GUF", "French Guiana"),
+    ("GG", "GGY", "Guernsey"),
+    ("GH", "GHA", "Ghana"),
+    ("GI", "GIB", "Gibraltar"),
+    ("GL", "GRL", "Greenland"),
+    ("GM", "GMB", "Gambia"),
+    ("GN", "GIN", "Guinea"),
+    ("GP", "GLP", "Guadeloupe"),
+    ("GQ", "GNQ", "Equatorial Guinea"),
+    ("GR", "GRC", "Greece"),
+    ("GS", "SGS", "South Georgia and the South Sandwich Islands"),
+    ("GT", "GTM", "Guatemala"),
+    ("GU", "GUM", "Guam"),
+    ("GW", "GNB", "Guinea-Bissau"),
+    ("GY", "GUY", "Guyana"),
+    ("HK", "HKG", "Hong Kong"),
+    ("HM", "HMD", "Heard Island and McDonald Islands"),
+    ("HN", "HND", "Honduras"),
+    ("HR", "HRV", "Croatia"),
+    ("HT", "HTI", "Haiti"),
+    ("HU", "HUN", "Hungary"),
+    ("ID", "IDN", "Indonesia"),
+    ("IE", "IRL", "Ireland"),
+    ("IL", "ISR", "Israel"),
+    ("IM", "IMN", "Isle of Man"),
+    ("IN", "IND", "India"),
+    ("IO", "IOT", "British Indian Ocean Territory"),
+    ("IQ", "IRQ", "Iraq"),
+    ("IR", "IRN", "Iran"),
+    ("IS", "ISL", "Iceland"),
+    ("IT", "ITA", "Italy"),
+    ("JE", "JEY", "Jersey"),
+    ("JM", "JAM", "Jamaica"),
+    ("JO", "JOR", "Jordan"),
+    ("JP", "JPN", "Japan"),
+    ("KE", "KEN", "Kenya"),
+    ("KG", "KGZ", "Kyrgyzstan"),
+    ("KH", "KHM", "Cambodia"),
+    ("KI", "KIR", "Kiribati"),
+    ("KM", "COM", "Comoros"),
+    ("KN", "KNA", "Saint Kitts and Nevis"),
+    ("KP", "PRK", "North Korea"),
+    ("KR", "KOR", "South Korea"),
+    ("KW", "KWT", "Kuwait"),
+    ("KY", "CYM", "Cayman Islands"),
+    ("KZ", "KAZ", "Kazakhstan"),
+    ("LA", "LAO", "Laos"),
+    ("LB", "LBN", "Lebanon"),
+    ("LC", "LCA", "Saint Lucia"),
+    ("LI", "LIE", "Liechtenstein"),
+    ("LK", "LKA", "Sri Lanka"),
+    ("LR", "LBR", "Liberia"),
+    ("LS", "LSO", "Lesotho"),
+    ("LT", "LTU", "Lithuania"),
+    ("LU", "LUX", "Luxembourg"),
+    ("LV", "LVA", "Latvia"),
+    ("LY", "LBY", "Libya"),
+    ("MA", "MAR", "Morocco"),
+    ("MC", "MCO", "Monaco"),
+    ("MD", "MDA", "Moldova"),
+    ("ME", "MNE", "Montenegro"),
+    ("MF", "MAF", "Saint Martin"),
+    ("MG", "MDG", "Madagascar"),
+    ("MH", "MHL", "Marshall Islands"),
+    ("MK", "MKD", "North Macedonia"),
+    ("ML", "MLI", "Mali"),
+    ("MM", "MMR", "Myanmar"),
+    ("MN", "MNG", "Mongolia"),
+    ("MO", "MAC", "Macao"),
+    ("MP", "MNP", "Northern Mariana Islands"),
+    ("MQ", "MTQ", "Martinique"),
+    ("MR", "MRT", "Mauritania"),
+    ("MS", "MSR", "Montserrat"),
+    ("MT", "MLT", "Malta"),
+    ("MU", "MUS", "Mauritius"),
+    ("MV", "MDV", "Maldives"),
+    ("MW", "MWI", "Malawi"),
+    ("MX", "MEX", "Mexico"),
+    ("MY", "MYS", "Malaysia"),
+    ("MZ", "MOZ", "Mozambique"),
+    ("NA", "NAM", "Namibia"),
+    ("NC", "NCL", "New Caledonia"),
+    ("NE", "NER", "Niger"),
+    ("NF", "NFK", "Norfolk Island"),
+    ("NG", "NGA", "Nigeria"),
+    ("NI", "NIC", "Nicaragua"),
+    ("NL", "NLD", "Netherlands"),
+    ("NO", "NOR", "Norway"),
+    ("NP", "NPL", "Nepal"),
+    ("NR", "NRU", "Nauru"),
+    ("NU", "NIU", "Niue"),
+    ("NZ", "NZL", "New Zealand"),
+    ("OM", "OMN", "Oman"),
+    ("PA", "PAN", "Panama"),
+    ("PE", "PER", "Peru"),
+    ("PF", "PYF", "French Polynesia"),
+    ("PG", "PNG", "Papua New Guinea"),
+    ("PH", "PHL", "Philippines"),
+    ("PK", "PAK", "Pakistan"),
+    ("PL", "POL", "Poland"),
+    ("PM", "SPM", "Saint Pierre and Miquelon"),
+    ("PN", "PCN", "Pitcairn"),
+    ("PR", "PRI", "Puerto Rico"),
+    ("PS", "PSE", "Palestine"),
+    ("PT", "PRT", "Portugal"),
+    ("PW", "PLW", "Palau"),
+    ("PY", "PRY", "Paraguay"),
+    ("QA", "QAT", "Qatar"),
+    ("RE", "REU", "Reunion"),
+    ("RO", "ROU", "Romania"),
+    ("RS", "SRB", "Serbia"),
+    ("RU", "RUS", "Russia"),
+    ("RW", "RWA", "Rwanda"),
+    ("SA", "SAU", "Saudi Arabia"),
+    ("SB", "SLB", "Solomon Islands"),
+    ("SC", "SYC", "Seychelles"),
+    ("SD", "SDN", "Sudan"),
+    ("SE", "SWE", "Sweden"),
+    ("SG", "SGP", "Singapore"),
+    ("SH", "SHN", "Saint Helena, Ascension and Tristan da Cunha"),
+    ("SI", "SVN", "Slovenia"),
+    ("SJ", "SJM", "Svalbard and Jan Mayen"),
+    ("SK", "SVK", "Slovakia"),
+    ("SL", "SLE", "Sierra Leone"),
+    ("SM", "SMR", "San Marino"),
+    ("SN", "SEN", "Senegal"),
+    ("SO", "SOM", "Somalia"),
+    ("SR", "SUR", "Suriname"),
+    ("SS", "SSD", "South Sudan"),
+    ("ST", "STP", "Sao Tome and Principe"),
+    ("SV", "SLV", "El Salvador"),
+    ("SX", "SXM", "Sint Maarten"),
+    ("SY", "SYR", "Syria"),
+    ("SZ", "SWZ", "Eswatini"),
+    ("TC", "TCA", "Turks and Caicos Islands"),
+    ("TD", "TCD", "Chad"),
+    ("TF", "ATF", "French Southern Territories"),
+    ("TG", "TGO", "Togo"),
+    ("TH", "THA", "Thailand"),
+    ("TJ", "TJK", "Tajikistan"),
+    ("TK", "TKL", "Tokelau"),
+    ("TL", "TLS", "Timor-Leste"),
+    ("TM", "TKM", "Turkmenistan"),
+    ("TN", "TUN", "Tunisia"),
+    ("TO", "TON", "Tonga"),
+    ("TR", "TUR", "Turkey"),
+    ("TT", "TTO", "Trinidad and Tobago"),
+    ("TV", "TUV", "Tuvalu"),
+    ("TW", "TWN", "Taiwan"),
+    ("TZ", "TZA", "Tanzania"),
+    ("UA", "UKR", "Ukraine"),
+    ("UG", "UGA", "Uganda"),
+    ("UM", "UMI", "United States Minor Outlying Islands"),
+    ("US", "USA", "United States"),
+    ("UY", "URY", "Uruguay"),
+    ("UZ", "UZB", "Uzbekistan"),
+    ("VA", "VAT", "Holy See"),
+    ("VC", "VCT", "Saint Vincent and the Grenadines"),
+    ("VE", "VEN", "Venezuela"),
+    ("VG", "VGB", "British Virgin Islands"),
+    ("VI", "VIR", "United States Virgin Islands"),
+    ("VN", "VNM", "Vietnam"),
+    ("VU", "VUT", "Vanuatu"),
+    ("WF", "WLF", "Wallis and Futuna"),
+    ("WS", "WSM", "Samoa"),
+    ("XK", "XKX", "Kosovo"),
+    ("YE", "YEM", "Yemen"),
+    ("YT", "MYT", "Mayotte"),
+    ("ZA", "ZAF", "South Africa"),
+    ("ZM", "ZMB", "Zambia"),
+    ("ZW", "ZWE", "Zimbabwe"),
+];
+
+/// Alternate spellings that are not the ISO short name, as (alias, alpha-2)
+const ALIASES: &[(&str, &str)] = &[
+    ("united states of america", "US"),
+    ("u.s.", "US"),
+    ("u.s.a.", "US"),
+    ("great britain", "GB"),
+    ("uk", "GB"),
+    ("england", "GB"),
+    ("russian federation", "RU"),
+    ("republic of korea", "KR"),
+    ("korea, republic of", "KR"),
+    ("korea", "KR"),
+    ("democratic people's republic of korea", "KP"),
+    ("iran, islamic republic of", "IR"),
+    ("viet nam", "VN"),
+    ("bolivia, plurinational state of", "BO"),
+    ("venezuela, bolivarian republic of", "VE"),
+    ("tanzania, united republic of", "TZ"),
+    ("syrian arab republic", "SY"),
+    ("lao people's democratic republic", "LA"),
+    ("moldova, republic of", "MD"),
+    ("czech republic", "CZ"),
+    ("ivory coast", "CI"),
+    ("cape verde", "CV"),
+    ("swaziland", "SZ"),
+    ("burma", "MM"),
+    ("macedonia", "MK"),
+    ("vatican", "VA"),
+    ("vatican city", "VA"),
+    ("east timor", "TL"),
+    ("turkiye", "TR"),
+    ("republic of the congo", "CG"),
+    ("congo, the democratic republic of the", "CD"),
+    ("drc", "CD"),
+    ("taiwan, province of china", "TW"),
+    ("palestine, state of", "PS"),
+    ("micronesia, federated states of", "FM"),
+    ("brunei", "BN"),
+    ("the netherlands", "NL"),
+    ("holland", "NL"),
+    ("the bahamas", "BS"),
+    ("the gambia", "GM"),
+];
+
+/// Counts of values seen while normalizing `z_country`
+#[derive(Debug, Default)]
+pub struct CountryNormalizationReport {
+    /// Rows whose value was rewritten to a different string
+    pub normalized: usize,
+    /// Unrecognized values and how many rows carried them
+    pub unrecognized: BTreeMap<String, usize>,
+}
+
+fn lookup() -> &'static HashMap<String, &'static str> {
+    static LOOKUP: OnceLock<HashMap<String, &'static str>> = OnceLock::new();
+    LOOKUP.get_or_init(|| {
+        let mut map = HashMap::new();
+        for (alpha2, alpha3, name) in COUNTRIES {
+            map.insert(alpha2.to_lowercase(), *alpha2);
+            map.insert(alpha3.to_lowercase(), *alpha2);
+            map.insert(name.to_lowercase(), *alpha2);
+        }
+        for (alias, alpha2) in ALIASES {
+            map.insert(alias.to_string(), *alpha2);
+        }
+        map
+    })
+}
+
+/// Returns the canonical ISO 3166-1 alpha-2 code for `value`, if recognized.
+///
+/// Matching is case-insensitive, ignores surrounding and repeated whitespace
+/// and accepts alpha-2 codes, alpha-3 codes, English names and common aliases.
+pub fn normalize_country_code(value: &str) -> Option<&'static str> {
+    let key = value
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase();
+    lookup().get(&key).copied()
+}
+
+/// Rewrites `z_country` in every batch to its ISO alpha-2 code.
+///
+/// Empty strings (missing countries) are left alone. Unrecognized values pass
+/// through unchanged and are logged once each with their row count.
+pub fn normalize_country_batches(
+    batches: Vec<RecordBatch>,
+) -> Result<(Vec<RecordBatch>, CountryNormalizationReport)> {
+    let mut report = CountryNormalizationReport::default();
+
+    let batches = batches
+        .iter()
+        .map(|batch| {
+            map_string_column(batch, "z_country", |value| {
+                if value.is_empty() {
+                    return None;
+                }
+                match normalize_country_code(value) {
+                    Some(code) if code != value => {
+                        report.normalized += 1;
+                        Some(code.to_string())
+                    }
+                    Some(_) => None,
+                    None => {
+                        *report.unrecognized.entry(value.to_string()).or_default() += 1;
+                        None
+                    }
+                }
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    for (value, rows) in &report.unrecognized {
+        warn!("Unrecognized z_country value {value:?} in {rows} row(s), leaving as-is");
+    }
+    info!(
+        "Normalized z_country for {} row(s), {} unrecognized value(s)",
+        report.normalized,
+        report.unrecognized.len()
+    );
+
+    Ok((batches, report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_array::{Array, StringArray};
+    use arrow_schema::{DataType, Field, Schema};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_normalize_country_code_variants() {
+        assert_eq!(normalize_country_code("united states"), Some("US"));
+        assert_eq!(normalize_country_code("  United   States "), Some("US"));
+        assert_eq!(normalize_country_code("us"), Some("US"));
+        assert_eq!(normalize_country_code("USA"), Some("US"));
+        assert_eq!(normalize_country_code("Côte"), None);
+        assert_eq!(normalize_country_code("uk"), Some("GB"));
+    }
+
+    #[test]
+    fn test_country_table_is_consistent() {
+        for (alpha2, alpha3, name) in COUNTRIES {
+            assert_eq!(alpha2.len(), 2, "{name}");
+            assert_eq!(alpha3.len(), 3, "{name}");
+            assert_eq!(normalize_country_code(name), Some(*alpha2));
+        }
+        for (alias, alpha2) in ALIASES {
+            assert!(COUNTRIES.iter().any(|(a, _, _)| a == alpha2), "{alias}");
+        }
+    }
+
+    #[test]
+    fn test_normalize_country_batches_passes_unknown_through() {
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "z_country",
+            DataType::Utf8,
+            false,
+        )]));
+        let batch = RecordBatch::try_new(
+            schema,
+            vec![Arc::new(StringArray::from(vec![
+                "united states",
+                "Atlantis",
+                "FR",
+                "",
+            ]))],
+        )
+        .unwrap();
+
+        let (batches, report) = normalize_country_batches(vec![batch]).unwrap();
+        let values = batches[0]
+            .column(0)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+
+        assert_eq!(values.value(0), "US");
+        assert_eq!(values.value(1), "Atlantis");
+        assert_eq!(values.value(2), "FR");
+        assert_eq!(values.value(3), "");
+        assert_eq!(values.len(), 4);
+        assert_eq!(report.normalized, 1);
+        assert_eq!(report.unrecognized.get("Atlantis"), Some(&1));
+    }
+}
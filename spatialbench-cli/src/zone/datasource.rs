@@ -15,40 +15,155 @@
 // specific language governing permissions and limitations
 // under the License.
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use datafusion::arrow::datatypes::DataType;
+use datafusion::functions::core::expr_fn::{get_field, named_struct};
 use datafusion::{
     common::config::ConfigOptions,
+    execution::object_store::ObjectStoreUrl,
     execution::runtime_env::{RuntimeEnv, RuntimeEnvBuilder},
+    execution::SessionStateBuilder,
     prelude::*,
 };
 use log::{debug, info};
+use object_store::aws::AmazonS3Builder;
 use object_store::http::HttpBuilder;
+use object_store::ObjectStore;
 use std::sync::Arc;
-use url::Url;
+use url::{Position, Url};
 
+use super::config::{GeometryType, Sampling};
+use super::geoarrow::{geometry_as_wkb, source_encoding};
+use super::geometry_type::keep_geometry_types;
+use super::sampling::stratified_country_sample;
 use super::stats::ZoneTableStats;
+use super::theme::{Theme, ThemeInput, FLAT_NAME_COLUMN, ZONE_SOURCE_COLUMNS};
 
-const OVERTURE_RELEASE_DATE: &str = "2025-08-20.1";
+pub const OVERTURE_RELEASE_DATE: &str = "2025-08-20.1";
 const HUGGINGFACE_URL: &str = "https://huggingface.co";
 const COMMIT_HASH: &str = "67822daa2fbc0039681922f0d7fea4157f41d13f";
 const PARQUET_PART_COUNT: usize = 4;
 const PARQUET_UUID: &str = "c998b093-fa14-440c-98f0-bbdb2126ed22";
 
+/// URL schemes of the sources read through a remote object store
+pub const REMOTE_SCHEMES: &[&str] = &["http", "https", "s3"];
+
+/// Describes where `themes` are read from, in the `--input-theme` syntax.
+///
+/// Built-in sources are named by their Overture release, e.g.
+/// `division_area=overture:2025-08-20.1`.
+pub fn source_provenance(themes: &[ThemeInput]) -> String {
+    themes
+        .iter()
+        .map(|input| match &input.location {
+            Some(location) => format!("{}={location}", input.theme),
+            None => format!("{}=overture:{OVERTURE_RELEASE_DATE}", input.theme),
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Source columns a zone scan may read: the [`ZONE_SOURCE_COLUMNS`] every
+/// theme is projected to, the `is_land` filter and the flat column names
+/// are read from when an input has no `names.primary`
+pub fn expected_scan_columns(name_column: Option<&str>) -> Vec<String> {
+    ZONE_SOURCE_COLUMNS
+        .iter()
+        .copied()
+        .chain(["is_land", name_column.unwrap_or(FLAT_NAME_COLUMN)])
+        .map(str::to_string)
+        .collect()
+}
+
 pub struct ZoneDataSource {
     runtime: Arc<RuntimeEnv>,
+    themes: Vec<ThemeInput>,
+    sort_by_id: bool,
+    rows: Option<u64>,
+    limit: Option<u64>,
+    geometry_types: Vec<GeometryType>,
+    sampling: Sampling,
+    min_per_country: u64,
+    seed: u64,
+    name_column: Option<String>,
+    common_names: bool,
 }
 
 impl ZoneDataSource {
     pub async fn new() -> Result<Self> {
         let rt = Arc::new(RuntimeEnvBuilder::new().build()?);
 
-        let hf_store = HttpBuilder::new().with_url(HUGGINGFACE_URL).build()?;
-        let hf_url = Url::parse(HUGGINGFACE_URL)?;
-        rt.register_object_store(&hf_url, Arc::new(hf_store));
+        Ok(Self {
+            runtime: rt,
+            themes: vec![ThemeInput::built_in(Theme::DivisionArea)],
+            sort_by_id: false,
+            rows: None,
+            limit: None,
+            geometry_types: vec![],
+            sampling: Sampling::default(),
+            min_per_country: 1,
+            seed: 0,
+            name_column: None,
+            common_names: false,
+        })
+    }
+
+    /// Reads and unions the given themes instead of the built-in division areas
+    pub fn with_themes(mut self, themes: Vec<ThemeInput>) -> Self {
+        self.themes = themes;
+        self
+    }
+
+    /// Sorts the unioned source rows by `id`, so their order no longer
+    /// depends on how the source files are laid out
+    pub fn with_sort_by_id(mut self, sort_by_id: bool) -> Self {
+        self.sort_by_id = sort_by_id;
+        self
+    }
+
+    /// Keeps only `rows` of the selected source rows, those with the
+    /// smallest hash of their `id`. The sample is the same in every run and
+    /// independent of the source file layout, and every row of a smaller
+    /// sample is also in a larger one.
+    pub fn with_rows(mut self, rows: Option<u64>) -> Self {
+        self.rows = rows;
+        self
+    }
+
+    /// Reads the zone names from the flat column `name_column` of every
+    /// theme instead of `names.primary`
+    pub fn with_name_column(mut self, name_column: Option<String>) -> Self {
+        self.name_column = name_column;
+        self
+    }
+
+    /// Keeps `names.common` in the `names` struct, which otherwise holds
+    /// only `names.primary` once read
+    pub fn with_common_names(mut self, common_names: bool) -> Self {
+        self.common_names = common_names;
+        self
+    }
 
-        debug!("Registered HTTPS object store for huggingface.co");
+    /// Keeps only the `limit` selected source rows with the smallest `id`,
+    /// which are the first rows in zone key order
+    pub fn with_limit(mut self, limit: Option<u64>) -> Self {
+        self.limit = limit;
+        self
+    }
 
-        Ok(Self { runtime: rt })
+    /// Picks the `rows` sample with `sampling` instead of by id hash alone;
+    /// `seed` orders the rows of a stratified sample
+    pub fn with_sampling(mut self, sampling: Sampling, min_per_country: u64, seed: u64) -> Self {
+        self.sampling = sampling;
+        self.min_per_country = min_per_country;
+        self.seed = seed;
+        self
+    }
+
+    /// Keeps only source rows of these geometry types, all when empty
+    pub fn with_geometry_types(mut self, geometry_types: Vec<GeometryType>) -> Self {
+        self.geometry_types = geometry_types;
+        self
     }
 
     pub fn create_context(&self) -> Result<SessionContext> {
@@ -64,49 +179,622 @@ impl ZoneDataSource {
         Ok(ctx)
     }
 
+    /// Derives the session to generate in from an embedder's `ctx`.
+    ///
+    /// The derived session shares the runtime (object stores, memory pool,
+    /// disk manager), catalogs, functions and extensions of `ctx`, with
+    /// `target_partitions` set to 1 to keep the source order.
+    pub fn context_from(ctx: &SessionContext) -> SessionContext {
+        let config = ctx.copied_config().with_target_partitions(1);
+        let state = SessionStateBuilder::new_from_existing(ctx.state())
+            .with_config(config)
+            .build();
+        SessionContext::new_with_state(state)
+    }
+
     pub async fn load_zone_data(
         &self,
         ctx: &SessionContext,
         scale_factor: f64,
     ) -> Result<DataFrame> {
-        let parquet_urls = self.generate_parquet_urls();
+        let mut df: Option<DataFrame> = None;
+        let mut first_theme = None;
+        for input in &self.themes {
+            let theme_df = self.load_theme(ctx, input, scale_factor).await?;
+            df = match (df, first_theme) {
+                (Some(df), Some(first_theme)) => {
+                    check_union_compatible(&df, first_theme, &theme_df, input.theme)?;
+                    Some(df.union(theme_df)?)
+                }
+                _ => {
+                    first_theme = Some(input.theme);
+                    Some(theme_df)
+                }
+            };
+        }
+        let df = df.ok_or_else(|| anyhow!("No input themes configured"))?;
+        // Filter before sampling and numbering, so --rows counts kept rows
+        // and the zone keys stay contiguous
+        let df = match self.geometry_types.as_slice() {
+            [] => df,
+            types => keep_geometry_types(df, types).await?,
+        };
+
+        // Below scale factor 1 the sample is that fraction of the rows
+        // selected at 1, picked as a --rows sample is
+        let rows = match self.rows {
+            None if scale_factor < 1.0 => {
+                let total = df.clone().count().await?;
+                let rows = (total as f64 * scale_factor).ceil() as u64;
+                info!("Scale factor {scale_factor} keeps {rows} of {total} source rows");
+                Some(rows)
+            }
+            rows => rows,
+        };
+        let df = match rows {
+            Some(rows) if self.sampling == Sampling::StratifiedCountry => {
+                stratified_country_sample(df, rows, self.min_per_country, self.seed).await?
+            }
+            Some(rows) => {
+                info!("Sampling {rows} source rows by id hash");
+                df.sort(vec![
+                    md5(col("id")).sort(true, false),
+                    col("id").sort(true, false),
+                ])?
+                .limit(0, Some(rows as usize))?
+            }
+            None => df,
+        };
+
+        if let Some(limit) = self.limit {
+            info!("Limiting to the first {limit} source rows by id");
+            return Ok(df
+                .sort(vec![col("id").sort(true, false)])?
+                .limit(0, Some(limit as usize))?);
+        }
+        if self.sort_by_id {
+            info!("Sorting source rows by id for deterministic ordering");
+            return Ok(df.sort(vec![col("id").sort(true, false)])?);
+        }
+        Ok(df)
+    }
+
+    /// Reads one theme, applies its filters and projects it to the columns
+    /// shared by all themes
+    async fn load_theme(
+        &self,
+        ctx: &SessionContext,
+        input: &ThemeInput,
+        scale_factor: f64,
+    ) -> Result<DataFrame> {
+        let paths = theme_locations(input)?;
+        for path in &paths {
+            register_object_store(ctx, path)?;
+        }
         info!(
-            "Reading {} Parquet parts from Hugging Face...",
-            parquet_urls.len()
+            "Reading {} Parquet source(s) for theme {}...",
+            paths.len(),
+            input.theme
         );
 
         let df = ctx
-            .read_parquet(parquet_urls, ParquetReadOptions::default())
+            .read_parquet(paths.clone(), ParquetReadOptions::default())
             .await?;
 
-        let stats = ZoneTableStats::new(scale_factor, Some(1));
-        let subtypes = stats.subtypes();
+        let filter = ThemeFilter::new(input.theme, scale_factor);
+        let df = match input.theme {
+            Theme::DivisionArea => {
+                info!(
+                    "Selected subtypes for SF {}: {:?}",
+                    scale_factor, filter.subtypes
+                );
+
+                let mut pred = col("subtype").eq(lit("__never__"));
+                for s in filter.subtypes {
+                    pred = pred.or(col("subtype").eq(lit(s)));
+                }
+
+                let df = df.filter(pred.and(col("is_land").eq(lit(true))))?;
+                info!("Applied subtype and is_land filters");
+                df
+            }
+            Theme::Locality => {
+                let df = df.filter(col("subtype").eq(lit(filter.subtypes[0])))?;
+                info!("Applied locality subtype filter");
+                df
+            }
+        };
+
+        let df = nest_names(df, input.theme, self.name_column.as_deref())?;
+        for column in ZONE_SOURCE_COLUMNS {
+            if df.schema().field_with_unqualified_name(column).is_err() {
+                return Err(anyhow!(
+                    "Input theme {} is missing required column {column}",
+                    input.theme
+                ));
+            }
+        }
+        let df = df.select_columns(ZONE_SOURCE_COLUMNS)?;
+        // The built-in Overture sources are WKB
+        let df = match input.location {
+            Some(_) => geometry_as_wkb(df, source_encoding(&paths).await?)?,
+            None => df,
+        };
+        match self.common_names {
+            true => Ok(df),
+            false => primary_names_only(df),
+        }
+    }
+}
 
-        info!("Selected subtypes for SF {}: {:?}", scale_factor, subtypes);
+/// `df` with a `names` struct of only its `primary` field.
+///
+/// The Parquet scan reads the whole `names` column, as it projects
+/// top-level columns only, but the other names are dropped right above it
+/// rather than carried through the filters, the sample and the union.
+fn primary_names_only(df: DataFrame) -> Result<DataFrame> {
+    let only_primary = df.schema().field_with_unqualified_name("names").is_ok_and(
+        |field| matches!(field.data_type(), DataType::Struct(fields) if fields.len() == 1),
+    );
+    if only_primary {
+        return Ok(df);
+    }
+    let names = named_struct(vec![lit("primary"), get_field(col("names"), "primary")]);
+    Ok(df.with_column("names", names)?)
+}
 
-        let mut pred = col("subtype").eq(lit("__never__"));
-        for s in subtypes {
-            pred = pred.or(col("subtype").eq(lit(s)));
+/// `df` with its zone names in a `names` struct with a `primary` field, as
+/// Overture writes them.
+///
+/// The struct is built from the flat `name_column` when given, and else
+/// from a top-level `name` column when the input has no `names.primary`,
+/// as extracts flattening the names do.
+fn nest_names(df: DataFrame, theme: Theme, name_column: Option<&str>) -> Result<DataFrame> {
+    let nested = df
+        .schema()
+        .field_with_unqualified_name("names")
+        .is_ok_and(|field| match field.data_type() {
+            DataType::Struct(fields) => fields.find("primary").is_some(),
+            _ => false,
+        });
+    let column = match name_column {
+        Some(column) => column,
+        None if nested => return Ok(df),
+        None if df
+            .schema()
+            .field_with_unqualified_name(FLAT_NAME_COLUMN)
+            .is_ok() =>
+        {
+            info!(
+                "Input theme {theme} has no names.primary, reading names from {FLAT_NAME_COLUMN}"
+            );
+            FLAT_NAME_COLUMN
         }
+        None => {
+            return Err(anyhow!(
+                "Input theme {theme} has no names.primary column; expected names.primary or \
+                 --name-column naming a flat name column"
+            ))
+        }
+    };
+    if df.schema().field_with_unqualified_name(column).is_err() {
+        return Err(anyhow!(
+            "Input theme {theme} has no column {column} given by --name-column"
+        ));
+    }
+    let names = named_struct(vec![lit("primary"), cast(ident(column), DataType::Utf8)]);
+    Ok(df.with_column("names", names)?)
+}
 
-        let df = df.filter(pred.and(col("is_land").eq(lit(true))))?;
-        info!("Applied subtype and is_land filters");
+/// Source rows kept from a theme
+pub struct ThemeFilter {
+    /// Values of `subtype` to keep
+    pub subtypes: Vec<&'static str>,
+    /// Keep only rows with `is_land = true`
+    pub land_only: bool,
+}
 
-        // Sort by 'id' to ensure deterministic ordering regardless of parallelism
-        // let df = df.sort(vec![col("id").sort(true, false)])?;
-        // info!("Sorted by id for deterministic ordering");
+impl ThemeFilter {
+    pub fn new(theme: Theme, scale_factor: f64) -> Self {
+        match theme {
+            Theme::DivisionArea => Self {
+                subtypes: ZoneTableStats::new(scale_factor, Some(1)).subtypes(),
+                land_only: true,
+            },
+            Theme::Locality => Self {
+                subtypes: vec!["locality"],
+                land_only: false,
+            },
+        }
+    }
+}
 
-        Ok(df)
+/// Parquet paths or URLs `input` is read from
+pub fn theme_locations(input: &ThemeInput) -> Result<Vec<String>> {
+    match (&input.location, input.theme) {
+        (Some(location), _) => Ok(vec![location.clone()]),
+        (None, Theme::DivisionArea) => Ok(generate_parquet_urls()),
+        (None, theme) => Err(anyhow!(
+            "Input theme {theme} has no built-in source, pass {theme}=<path or URL>"
+        )),
+    }
+}
+
+fn generate_parquet_urls() -> Vec<String> {
+    (0..PARQUET_PART_COUNT)
+        .map(|i| {
+            format!(
+                "{HUGGINGFACE_URL}/datasets/apache-sedona/spatialbench/resolve/{}/omf-division-area-{}/part-{:05}-{}-c000.zstd.parquet",
+                COMMIT_HASH, OVERTURE_RELEASE_DATE, i, PARQUET_UUID
+            )
+        })
+        .collect()
+}
+
+/// The base URL of `url` and an object store serving it, if it is remote.
+///
+/// HTTP(S) sources are read anonymously. S3 sources take their credentials,
+/// region and endpoint from the `AWS_*` environment variables, or from the
+/// instance metadata; set `AWS_SKIP_SIGNATURE=true` for public buckets.
+pub fn remote_store(url: &Url) -> Result<Option<(Url, Arc<dyn ObjectStore>)>> {
+    let base = Url::parse(&url[..Position::BeforePath])?;
+    let store: Arc<dyn ObjectStore> = match url.scheme() {
+        "http" | "https" => Arc::new(HttpBuilder::new().with_url(base.as_str()).build()?),
+        "s3" => {
+            let bucket = url
+                .host_str()
+                .ok_or_else(|| anyhow!("S3 URL {url} names no bucket"))?;
+            Arc::new(
+                AmazonS3Builder::from_env()
+                    .with_bucket_name(bucket)
+                    .build()?,
+            )
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some((base, store)))
+}
+
+/// Registers an object store for `location` if it is a remote URL whose
+/// origin has none in `ctx` yet
+fn register_object_store(ctx: &SessionContext, location: &str) -> Result<()> {
+    let Ok(url) = Url::parse(location) else {
+        return Ok(());
+    };
+    if !REMOTE_SCHEMES.contains(&url.scheme()) {
+        return Ok(());
+    }
+
+    let runtime = ctx.runtime_env();
+    if runtime
+        .object_store(ObjectStoreUrl::parse(&url[..Position::BeforePath])?)
+        .is_ok()
+    {
+        return Ok(());
+    }
+    if let Some((base, store)) = remote_store(&url)? {
+        runtime.register_object_store(&base, store);
+        debug!("Registered {} object store for {base}", url.scheme());
+    }
+    Ok(())
+}
+
+/// Errors if `right` cannot be unioned with `left` column by column
+fn check_union_compatible(
+    left: &DataFrame,
+    left_theme: Theme,
+    right: &DataFrame,
+    right_theme: Theme,
+) -> Result<()> {
+    for (l, r) in left.schema().fields().iter().zip(right.schema().fields()) {
+        if l.data_type() != r.data_type() {
+            return Err(anyhow!(
+                "Input themes have conflicting schemas: column {} is {} in {} but {} in {}",
+                l.name(),
+                l.data_type(),
+                left_theme,
+                r.data_type(),
+                right_theme
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zone::fixtures::{source_batch, write_parquet, SourceRow};
+    use crate::zone::transform::ZoneTransformer;
+    use arrow::compute::cast;
+    use arrow_array::{Array, StringArray};
+    use arrow_schema::DataType;
+
+    #[tokio::test]
+    async fn test_union_of_two_themes() {
+        let dir = tempfile::tempdir().unwrap();
+        let areas = dir.path().join("division_area.parquet");
+        let localities = dir.path().join("division.parquet");
+        write_parquet(
+            &areas,
+            &source_batch(
+                &[
+                    SourceRow::new("a1", "county"),
+                    SourceRow::new("a2", "region"),
+                ],
+                true,
+            ),
+        );
+        write_parquet(
+            &localities,
+            &source_batch(
+                &[
+                    SourceRow::new("l1", "locality"),
+                    SourceRow::new("l2", "county"),
+                ],
+                false,
+            ),
+        );
+
+        let datasource = ZoneDataSource::new().await.unwrap().with_themes(vec![
+            ThemeInput {
+                theme: Theme::DivisionArea,
+                location: Some(areas.to_string_lossy().into_owned()),
+            },
+            ThemeInput {
+                theme: Theme::Locality,
+                location: Some(localities.to_string_lossy().into_owned()),
+            },
+        ]);
+        let ctx = datasource.create_context().unwrap();
+        let df = datasource.load_zone_data(&ctx, 1.0).await.unwrap();
+        let df = ZoneTransformer::new(0)
+            .transform(&ctx, &Default::default(), df)
+            .await
+            .unwrap();
+        let batches = df.collect().await.unwrap();
+
+        let mut ids = Vec::new();
+        for batch in &batches {
+            let column = cast(batch.column_by_name("z_gersid").unwrap(), &DataType::Utf8).unwrap();
+            let column = column.as_any().downcast_ref::<StringArray>().unwrap();
+            ids.extend((0..column.len()).map(|i| column.value(i).to_string()));
+        }
+        ids.sort();
+
+        // The SF 1 subtype filter drops the region, the locality filter the county
+        assert_eq!(ids, vec!["a1", "l1"]);
+    }
+
+    /// `rows` with the `names` struct flattened into a `column` of their
+    /// primary names
+    fn flat_names_batch(rows: &[SourceRow], column: &str) -> arrow_array::RecordBatch {
+        let batch = source_batch(rows, true);
+        let index = batch.schema().index_of("names").unwrap();
+        let mut fields: Vec<_> = batch.schema().fields().iter().cloned().collect();
+        let mut columns = batch.columns().to_vec();
+        fields[index] = std::sync::Arc::new(arrow_schema::Field::new(column, DataType::Utf8, true));
+        columns[index] = std::sync::Arc::new(StringArray::from_iter_values(
+            rows.iter().map(|row| row.name),
+        ));
+        arrow_array::RecordBatch::try_new(
+            std::sync::Arc::new(arrow_schema::Schema::new(fields)),
+            columns,
+        )
+        .unwrap()
     }
 
-    fn generate_parquet_urls(&self) -> Vec<String> {
-        (0..PARQUET_PART_COUNT)
-            .map(|i| {
-                format!(
-                    "https://huggingface.co/datasets/apache-sedona/spatialbench/resolve/{}/omf-division-area-{}/part-{:05}-{}-c000.zstd.parquet",
-                    COMMIT_HASH, OVERTURE_RELEASE_DATE, i, PARQUET_UUID
-                )
-            })
-            .collect()
+    /// `z_name` of every zone generated from the single input `path`
+    async fn zone_names(
+        path: &std::path::Path,
+        name_column: Option<&str>,
+        options: &crate::zone::ZoneTransformOptions,
+    ) -> Result<Vec<String>> {
+        let datasource = ZoneDataSource::new()
+            .await?
+            .with_themes(vec![ThemeInput {
+                theme: Theme::DivisionArea,
+                location: Some(path.to_string_lossy().into_owned()),
+            }])
+            .with_name_column(name_column.map(str::to_string))
+            .with_common_names(options.reads_common_names());
+        let ctx = datasource.create_context()?;
+        let df = datasource.load_zone_data(&ctx, 1.0).await?;
+        let df = ZoneTransformer::new(0).transform(&ctx, options, df).await?;
+        let mut names = Vec::new();
+        for batch in df.collect().await? {
+            let column = cast(batch.column_by_name("z_name").unwrap(), &DataType::Utf8)?;
+            let column = column.as_any().downcast_ref::<StringArray>().unwrap();
+            names.extend(column.iter().map(|name| name.unwrap().to_string()));
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    #[tokio::test]
+    async fn test_nested_and_flat_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let rows = [
+            SourceRow {
+                name: "King County",
+                ..SourceRow::new("a1", "county")
+            },
+            SourceRow {
+                name: "Pierce County",
+                ..SourceRow::new("a2", "county")
+            },
+        ];
+        let expected = vec!["King County", "Pierce County"];
+        let defaults = Default::default();
+
+        let nested = dir.path().join("nested.parquet");
+        write_parquet(&nested, &source_batch(&rows, true));
+        assert_eq!(
+            zone_names(&nested, None, &defaults).await.unwrap(),
+            expected
+        );
+
+        // A top-level `name` column is picked up without an override
+        let flat = dir.path().join("flat.parquet");
+        write_parquet(&flat, &flat_names_batch(&rows, "name"));
+        assert_eq!(zone_names(&flat, None, &defaults).await.unwrap(), expected);
+
+        // Other flat columns need --name-column
+        let label = dir.path().join("label.parquet");
+        write_parquet(&label, &flat_names_batch(&rows, "label"));
+        let error = zone_names(&label, None, &defaults).await.unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("expected names.primary or --name-column"),
+            "{error}"
+        );
+        assert_eq!(
+            zone_names(&label, Some("label"), &defaults).await.unwrap(),
+            expected
+        );
+        let error = zone_names(&label, Some("title"), &defaults)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("no column title"), "{error}");
+
+        // Flat names have no names.common to read languages from
+        let languages = crate::zone::ZoneTransformOptions {
+            names_languages: vec!["fr".to_string()],
+            ..Default::default()
+        };
+        let error = zone_names(&flat, None, &languages).await.unwrap_err();
+        assert!(error.to_string().contains("names.common"), "{error}");
+        assert!(zone_names(&nested, None, &languages).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_geoarrow_source_geometry_read_as_wkb() {
+        let dir = tempfile::tempdir().unwrap();
+        let square = [(0.0, 0.0), (2.0, 0.0), (2.0, 2.0), (0.0, 2.0), (0.0, 0.0)];
+        let triangle = [(5.0, 5.0), (6.0, 5.0), (5.0, 6.0), (5.0, 5.0)];
+        let batch = source_batch(
+            &[SourceRow::new("a", "county"), SourceRow::new("b", "county")],
+            true,
+        );
+        let index = batch.schema().index_of("geometry").unwrap();
+        let geometry = crate::zone::fixtures::geoarrow_polygons(&[&square, &triangle]);
+        let mut fields: Vec<_> = batch.schema().fields().iter().cloned().collect();
+        let mut columns = batch.columns().to_vec();
+        fields[index] = std::sync::Arc::new(arrow_schema::Field::new(
+            "geometry",
+            geometry.data_type().clone(),
+            true,
+        ));
+        columns[index] = geometry;
+        let batch = arrow_array::RecordBatch::try_new(
+            std::sync::Arc::new(arrow_schema::Schema::new(fields)),
+            columns,
+        )
+        .unwrap();
+
+        let path = dir.path().join("geoarrow.parquet");
+        let file = std::fs::File::create(&path).unwrap();
+        let mut writer = parquet::arrow::ArrowWriter::try_new(file, batch.schema(), None).unwrap();
+        writer.append_key_value_metadata(parquet::file::metadata::KeyValue::new(
+            "geo".to_string(),
+            r#"{"version":"1.1.0","primary_column":"geometry","columns":{"geometry":{"encoding":"polygon","geometry_types":["Polygon"]}}}"#.to_string(),
+        ));
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+
+        let datasource = ZoneDataSource::new()
+            .await
+            .unwrap()
+            .with_themes(vec![ThemeInput {
+                theme: Theme::DivisionArea,
+                location: Some(path.to_string_lossy().into_owned()),
+            }])
+            .with_sort_by_id(true);
+        let ctx = datasource.create_context().unwrap();
+        let df = datasource.load_zone_data(&ctx, 1.0).await.unwrap();
+        let df = ZoneTransformer::new(0)
+            .transform(&ctx, &Default::default(), df)
+            .await
+            .unwrap();
+        let mut boundaries = Vec::new();
+        for batch in df.collect().await.unwrap() {
+            let column = cast(
+                batch.column_by_name("z_boundary").unwrap(),
+                &DataType::Binary,
+            )
+            .unwrap();
+            let column = column
+                .as_any()
+                .downcast_ref::<arrow_array::BinaryArray>()
+                .unwrap();
+            boundaries.extend(column.iter().map(|wkb| wkb.unwrap().to_vec()));
+        }
+        assert_eq!(
+            boundaries,
+            vec![
+                crate::zone::fixtures::wkb_polygon(&square),
+                crate::zone::fixtures::wkb_polygon(&triangle),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_source_provenance() {
+        let themes = vec![
+            ThemeInput::built_in(Theme::DivisionArea),
+            ThemeInput {
+                theme: Theme::Locality,
+                location: Some("/data/division".to_string()),
+            },
+        ];
+        assert_eq!(
+            source_provenance(&themes),
+            format!("division_area=overture:{OVERTURE_RELEASE_DATE},locality=/data/division")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_conflicting_theme_schemas_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let areas = dir.path().join("division_area.parquet");
+        let localities = dir.path().join("division.parquet");
+        write_parquet(
+            &areas,
+            &source_batch(&[SourceRow::new("a1", "county")], true),
+        );
+
+        // Geometry stored as a string instead of WKB bytes
+        let batch = source_batch(&[SourceRow::new("l1", "locality")], false);
+        let mut columns = batch.columns().to_vec();
+        let index = batch.schema().index_of("geometry").unwrap();
+        columns[index] = std::sync::Arc::new(StringArray::from(vec!["POINT (0 0)"]));
+        let mut fields: Vec<_> = batch.schema().fields().iter().cloned().collect();
+        fields[index] =
+            std::sync::Arc::new(arrow_schema::Field::new("geometry", DataType::Utf8, true));
+        let batch = arrow_array::RecordBatch::try_new(
+            std::sync::Arc::new(arrow_schema::Schema::new(fields)),
+            columns,
+        )
+        .unwrap();
+        write_parquet(&localities, &batch);
+
+        let datasource = ZoneDataSource::new().await.unwrap().with_themes(vec![
+            ThemeInput {
+                theme: Theme::DivisionArea,
+                location: Some(areas.to_string_lossy().into_owned()),
+            },
+            ThemeInput {
+                theme: Theme::Locality,
+                location: Some(localities.to_string_lossy().into_owned()),
+            },
+        ]);
+        let ctx = datasource.create_context().unwrap();
+        let err = datasource.load_zone_data(&ctx, 1.0).await.unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("conflicting schemas: column geometry"),
+            "{err}"
+        );
     }
 }
@@ -0,0 +1,350 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Row level diff of two generated zone datasets keyed by `z_gersid`
+
+use anyhow::{anyhow, Result};
+use arrow::compute::cast;
+use arrow::util::display::{ArrayFormatter, FormatOptions};
+use arrow_array::{Array, BinaryArray, RecordBatch, StringArray};
+use arrow_schema::DataType;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::path::Path;
+
+use super::verify::discover_files;
+use super::wkb::NormalizedWkb;
+
+const KEY_COLUMN: &str = "z_gersid";
+const GEOMETRY_COLUMN: &str = "z_boundary";
+
+/// `z_zonekey` is a row number, so a single added or removed row would
+/// otherwise show up as a change to every following row
+const IGNORED_COLUMNS: &[&str] = &["z_zonekey"];
+
+/// Non-key values of one row
+#[derive(Debug, Default)]
+struct DiffRow {
+    values: BTreeMap<String, Option<String>>,
+    geometry: Option<Vec<u8>>,
+}
+
+/// Changes between two datasets
+#[derive(Debug, Default, Serialize)]
+pub struct DiffReport {
+    pub left_rows: usize,
+    pub right_rows: usize,
+    /// Keys only present in the right dataset
+    pub added: Vec<String>,
+    /// Keys only present in the left dataset
+    pub removed: Vec<String>,
+    pub changed: Vec<ChangedRow>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ChangedRow {
+    pub z_gersid: String,
+    pub columns: Vec<ColumnChange>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ColumnChange {
+    pub column: String,
+    pub left: Option<String>,
+    pub right: Option<String>,
+    /// For geometries of the same shape, the largest ordinate difference
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_coordinate_delta: Option<f64>,
+}
+
+impl DiffReport {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    /// Number of changed rows per column
+    pub fn changes_per_column(&self) -> BTreeMap<&str, usize> {
+        let mut counts = BTreeMap::new();
+        for row in &self.changed {
+            for change in &row.columns {
+                *counts.entry(change.column.as_str()).or_default() += 1;
+            }
+        }
+        counts
+    }
+}
+
+/// Diffs two datasets row by row.
+///
+/// Geometries are compared after WKB normalization, so byte order and type
+/// code flavour don't count as changes; ordinates within `tolerance` of each
+/// other are considered equal. Both datasets are held in memory.
+pub fn diff_dirs(left: &Path, right: &Path, tolerance: f64) -> Result<DiffReport> {
+    let left_rows = read_rows(left)?;
+    let right_rows = read_rows(right)?;
+
+    let mut report = DiffReport {
+        left_rows: left_rows.len(),
+        right_rows: right_rows.len(),
+        ..Default::default()
+    };
+    for (key, l) in &left_rows {
+        match right_rows.get(key) {
+            Some(r) => {
+                let columns = diff_row(l, r, tolerance);
+                if !columns.is_empty() {
+                    report.changed.push(ChangedRow {
+                        z_gersid: key.clone(),
+                        columns,
+                    });
+                }
+            }
+            None => report.removed.push(key.clone()),
+        }
+    }
+    report.added = right_rows
+        .keys()
+        .filter(|key| !left_rows.contains_key(*key))
+        .cloned()
+        .collect();
+    Ok(report)
+}
+
+fn diff_row(left: &DiffRow, right: &DiffRow, tolerance: f64) -> Vec<ColumnChange> {
+    let names: BTreeSet<&String> = left.values.keys().chain(right.values.keys()).collect();
+    let mut changes: Vec<ColumnChange> = names
+        .into_iter()
+        .filter_map(|name| {
+            let l = left.values.get(name).cloned().flatten();
+            let r = right.values.get(name).cloned().flatten();
+            (l != r).then(|| ColumnChange {
+                column: name.clone(),
+                left: l,
+                right: r,
+                max_coordinate_delta: None,
+            })
+        })
+        .collect();
+
+    if let Some(change) = diff_geometry(&left.geometry, &right.geometry, tolerance) {
+        changes.push(change);
+    }
+    changes
+}
+
+fn diff_geometry(
+    left: &Option<Vec<u8>>,
+    right: &Option<Vec<u8>>,
+    tolerance: f64,
+) -> Option<ColumnChange> {
+    let (l, r) = match (left, right) {
+        (None, None) => return None,
+        (Some(l), Some(r)) => (l, r),
+        _ => {
+            return Some(ColumnChange {
+                column: GEOMETRY_COLUMN.to_string(),
+                left: left.as_deref().map(describe_geometry),
+                right: right.as_deref().map(describe_geometry),
+                max_coordinate_delta: None,
+            })
+        }
+    };
+    if l == r {
+        return None;
+    }
+
+    let max_coordinate_delta = match (NormalizedWkb::parse(l), NormalizedWkb::parse(r)) {
+        (Ok(l), Ok(r)) => l.max_coordinate_delta(&r),
+        // Not valid WKB on one side; the raw bytes already differ
+        _ => None,
+    };
+    if max_coordinate_delta.is_some_and(|delta| delta <= tolerance) {
+        return None;
+    }
+    Some(ColumnChange {
+        column: GEOMETRY_COLUMN.to_string(),
+        left: Some(describe_geometry(l)),
+        right: Some(describe_geometry(r)),
+        max_coordinate_delta,
+    })
+}
+
+fn describe_geometry(wkb: &[u8]) -> String {
+    match NormalizedWkb::parse(wkb) {
+        Ok(geometry) => format!("{} point(s)", geometry.num_points()),
+        Err(e) => format!("invalid WKB: {e}"),
+    }
+}
+
+fn read_rows(data_dir: &Path) -> Result<BTreeMap<String, DiffRow>> {
+    let files = discover_files(data_dir)?;
+    if files.is_empty() {
+        return Err(anyhow!("No zone files found in {}", data_dir.display()));
+    }
+
+    let mut rows = BTreeMap::new();
+    for file in files.values() {
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&file.path)?)?.build()?;
+        for batch in reader {
+            add_batch_rows(&batch?, &mut rows)
+                .map_err(|e| anyhow!("{}: {e}", file.path.display()))?;
+        }
+    }
+    Ok(rows)
+}
+
+fn add_batch_rows(batch: &RecordBatch, rows: &mut BTreeMap<String, DiffRow>) -> Result<()> {
+    let schema = batch.schema();
+    let keys = batch
+        .column_by_name(KEY_COLUMN)
+        .ok_or_else(|| anyhow!("missing {KEY_COLUMN} column"))?;
+    let keys = cast(keys, &DataType::Utf8)?;
+    let keys = keys.as_any().downcast_ref::<StringArray>().unwrap();
+
+    let geometries = batch
+        .column_by_name(GEOMETRY_COLUMN)
+        .map(|c| cast(c, &DataType::Binary))
+        .transpose()?;
+    let geometries = geometries
+        .as_ref()
+        .map(|c| c.as_any().downcast_ref::<BinaryArray>().unwrap());
+
+    let options = FormatOptions::default();
+    let values = schema
+        .fields()
+        .iter()
+        .zip(batch.columns())
+        .filter(|(field, _)| {
+            let name = field.name().as_str();
+            name != KEY_COLUMN && name != GEOMETRY_COLUMN && !IGNORED_COLUMNS.contains(&name)
+        })
+        .map(|(field, column)| {
+            Ok((
+                field.name().clone(),
+                column.clone(),
+                ArrayFormatter::try_new(column.as_ref(), &options)?,
+            ))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    for i in 0..batch.num_rows() {
+        if keys.is_null(i) {
+            return Err(anyhow!("null {KEY_COLUMN} in row {i}"));
+        }
+        let row = DiffRow {
+            values: values
+                .iter()
+                .map(|(name, column, formatter)| {
+                    let value = (!column.is_null(i)).then(|| formatter.value(i).to_string());
+                    (name.clone(), value)
+                })
+                .collect(),
+            geometry: geometries
+                .filter(|g| !g.is_null(i))
+                .map(|g| g.value(i).to_vec()),
+        };
+        let key = keys.value(i);
+        if rows.insert(key.to_string(), row).is_some() {
+            return Err(anyhow!("duplicate {KEY_COLUMN} {key:?}"));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zone::fixtures::{wkb_polygon, write_parquet};
+    use arrow_array::{Int64Array, RecordBatch};
+    use arrow_schema::{Field, Schema};
+    use std::sync::Arc;
+
+    fn dataset(dir: &Path, rows: &[(&str, &str, f64)]) {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("z_zonekey", DataType::Int64, false),
+            Field::new("z_gersid", DataType::Utf8, false),
+            Field::new("z_name", DataType::Utf8, true),
+            Field::new("z_boundary", DataType::Binary, true),
+        ]));
+        let geometries: Vec<Vec<u8>> = rows
+            .iter()
+            .map(|(_, _, x)| wkb_polygon(&[(*x, 0.0), (1.0, 0.0), (1.0, 1.0), (*x, 0.0)]))
+            .collect();
+        let batch = RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(Int64Array::from_iter_values(0..rows.len() as i64)),
+                Arc::new(StringArray::from_iter_values(rows.iter().map(|r| r.0))),
+                Arc::new(StringArray::from_iter_values(rows.iter().map(|r| r.1))),
+                Arc::new(BinaryArray::from_iter_values(
+                    geometries.iter().map(Vec::as_slice),
+                )),
+            ],
+        )
+        .unwrap();
+        write_parquet(&dir.join("zone.parquet"), &batch);
+    }
+
+    #[test]
+    fn test_diff_reports_one_altered_row() {
+        let a = tempfile::tempdir().unwrap();
+        let b = tempfile::tempdir().unwrap();
+        dataset(a.path(), &[("g1", "Seattle", 0.0), ("g2", "Tacoma", 0.0)]);
+        dataset(b.path(), &[("g1", "Seattle", 0.0), ("g2", "Olympia", 0.0)]);
+
+        assert!(diff_dirs(a.path(), a.path(), 0.0).unwrap().is_empty());
+
+        let report = diff_dirs(a.path(), b.path(), 0.0).unwrap();
+        assert!(report.added.is_empty());
+        assert!(report.removed.is_empty());
+        assert_eq!(
+            report.changed,
+            vec![ChangedRow {
+                z_gersid: "g2".to_string(),
+                columns: vec![ColumnChange {
+                    column: "z_name".to_string(),
+                    left: Some("Tacoma".to_string()),
+                    right: Some("Olympia".to_string()),
+                    max_coordinate_delta: None,
+                }],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_diff_added_removed_and_geometry_tolerance() {
+        let a = tempfile::tempdir().unwrap();
+        let b = tempfile::tempdir().unwrap();
+        dataset(a.path(), &[("g1", "Seattle", 0.0), ("g2", "Tacoma", 0.0)]);
+        dataset(b.path(), &[("g1", "Seattle", 1e-9), ("g3", "Olympia", 0.0)]);
+
+        let report = diff_dirs(a.path(), b.path(), 0.0).unwrap();
+        assert_eq!(report.added, vec!["g3"]);
+        assert_eq!(report.removed, vec!["g2"]);
+        assert_eq!(report.changed.len(), 1);
+        assert_eq!(report.changed[0].columns[0].column, "z_boundary");
+        assert_eq!(
+            report.changed[0].columns[0].max_coordinate_delta,
+            Some(1e-9)
+        );
+
+        let report = diff_dirs(a.path(), b.path(), 1e-6).unwrap();
+        assert!(report.changed.is_empty());
+    }
+}
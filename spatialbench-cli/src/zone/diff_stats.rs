@@ -0,0 +1,531 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Aggregate statistics diff of two zone datasets, such as the outputs of
+//! two Overture releases
+//!
+//! Both datasets are streamed batch by batch. Row counts and bounds are
+//! aggregated per country and subtype as they go by; the `z_gersid` values
+//! are spilled to sorted runs on disk and the added and removed ids come
+//! from a merge of the two sorted streams, so memory stays bounded by the
+//! run size whatever the dataset size. Ids that already arrive in order,
+//! as they do from deterministic runs, extend a single run without being
+//! sorted.
+
+use anyhow::{anyhow, Context, Result};
+use arrow::compute::cast;
+use arrow_array::cast::AsArray;
+use arrow_array::{Array, RecordBatch};
+use arrow_schema::DataType;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Lines, Write};
+use std::path::{Path, PathBuf};
+
+use super::bbox::wkb_bounds;
+use super::verify::discover_files;
+
+/// Ids held in memory before they are sorted and spilled as a run
+const RUN_IDS: usize = 1 << 20;
+
+/// Group of rows without a country or subtype
+const NONE_GROUP: &str = "(none)";
+
+#[derive(Debug, Serialize)]
+pub struct DiffStatsReport {
+    pub old_rows: u64,
+    pub new_rows: u64,
+    pub countries: BTreeMap<String, CountDelta>,
+    pub subtypes: BTreeMap<String, CountDelta>,
+    /// `z_gersid` values only in the new dataset
+    pub added: IdDelta,
+    /// `z_gersid` values only in the old dataset
+    pub removed: IdDelta,
+    /// Change of the bounds of each country present in both datasets
+    pub bbox_drift: BTreeMap<String, BboxDrift>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct CountDelta {
+    pub old: u64,
+    pub new: u64,
+    pub delta: i64,
+    /// Delta relative to the old count; 100 for a group that is new
+    pub delta_pct: f64,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct IdDelta {
+    pub count: u64,
+    /// The smallest ids, in order
+    pub samples: Vec<String>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct BboxDrift {
+    /// `[xmin, ymin, xmax, ymax]` of the old dataset
+    pub old: [f64; 4],
+    pub new: [f64; 4],
+    /// Largest move of any of the four edges, in degrees
+    pub max_shift: f64,
+}
+
+impl CountDelta {
+    fn new(old: u64, new: u64) -> Self {
+        let delta = new as i64 - old as i64;
+        let delta_pct = match old {
+            0 if new == 0 => 0.0,
+            0 => 100.0,
+            old => delta as f64 / old as f64 * 100.0,
+        };
+        Self {
+            old,
+            new,
+            delta,
+            delta_pct,
+        }
+    }
+}
+
+impl DiffStatsReport {
+    /// Largest relative difference: of any country or subtype count, or of
+    /// the added and removed ids together against the old row count
+    pub fn max_delta_pct(&self) -> f64 {
+        let ids = CountDelta::new(
+            self.old_rows,
+            self.old_rows + self.added.count + self.removed.count,
+        );
+        self.countries
+            .values()
+            .chain(self.subtypes.values())
+            .chain([&ids])
+            .map(|delta| delta.delta_pct.abs())
+            .fold(0.0, f64::max)
+    }
+}
+
+/// Diffs the statistics of the `old` and `new` datasets, keeping `samples`
+/// of the added and removed ids
+pub fn diff_stats(old: &Path, new: &Path, samples: usize) -> Result<DiffStatsReport> {
+    diff_stats_with_runs(old, new, samples, RUN_IDS)
+}
+
+fn diff_stats_with_runs(
+    old: &Path,
+    new: &Path,
+    samples: usize,
+    run_ids: usize,
+) -> Result<DiffStatsReport> {
+    let spill = tempfile::tempdir()?;
+    let old = DatasetStats::read(old, &spill.path().join("old"), run_ids)?;
+    let new = DatasetStats::read(new, &spill.path().join("new"), run_ids)?;
+
+    let (mut added, mut removed) = (IdDelta::default(), IdDelta::default());
+    let mut old_ids = old.ids.merge()?;
+    let mut new_ids = new.ids.merge()?;
+    let mut old_id = old_ids.next().transpose()?;
+    let mut new_id = new_ids.next().transpose()?;
+    loop {
+        match (&old_id, &new_id) {
+            (None, None) => break,
+            (Some(o), Some(n)) if o == n => {
+                old_id = old_ids.next().transpose()?;
+                new_id = new_ids.next().transpose()?;
+            }
+            (Some(o), n) if n.as_ref().is_none_or(|n| o < n) => {
+                removed.push(old_id.take().unwrap(), samples);
+                old_id = old_ids.next().transpose()?;
+            }
+            _ => {
+                added.push(new_id.take().unwrap(), samples);
+                new_id = new_ids.next().transpose()?;
+            }
+        }
+    }
+
+    let bbox_drift = old
+        .bounds
+        .iter()
+        .filter_map(|(country, old)| {
+            let new = *new.bounds.get(country)?;
+            let max_shift = (0..4).map(|i| (new[i] - old[i]).abs()).fold(0.0, f64::max);
+            Some((
+                country.clone(),
+                BboxDrift {
+                    old: *old,
+                    new,
+                    max_shift,
+                },
+            ))
+        })
+        .collect();
+    Ok(DiffStatsReport {
+        old_rows: old.rows,
+        new_rows: new.rows,
+        countries: count_deltas(&old.countries, &new.countries),
+        subtypes: count_deltas(&old.subtypes, &new.subtypes),
+        added,
+        removed,
+        bbox_drift,
+    })
+}
+
+fn count_deltas(
+    old: &BTreeMap<String, u64>,
+    new: &BTreeMap<String, u64>,
+) -> BTreeMap<String, CountDelta> {
+    old.keys()
+        .chain(new.keys())
+        .map(|group| {
+            let count = |counts: &BTreeMap<String, u64>| counts.get(group).copied().unwrap_or(0);
+            (group.clone(), CountDelta::new(count(old), count(new)))
+        })
+        .collect()
+}
+
+impl IdDelta {
+    fn push(&mut self, id: String, samples: usize) {
+        self.count += 1;
+        if self.samples.len() < samples {
+            self.samples.push(id);
+        }
+    }
+}
+
+/// Aggregates of one dataset
+struct DatasetStats {
+    rows: u64,
+    countries: BTreeMap<String, u64>,
+    subtypes: BTreeMap<String, u64>,
+    bounds: BTreeMap<String, [f64; 4]>,
+    ids: SortedRuns,
+}
+
+impl DatasetStats {
+    fn read(data_dir: &Path, spill_dir: &Path, run_ids: usize) -> Result<Self> {
+        let files = discover_files(data_dir)?;
+        if files.is_empty() {
+            return Err(anyhow!("No zone files found in {}", data_dir.display()));
+        }
+        std::fs::create_dir_all(spill_dir)?;
+        let mut stats = Self {
+            rows: 0,
+            countries: BTreeMap::new(),
+            subtypes: BTreeMap::new(),
+            bounds: BTreeMap::new(),
+            ids: SortedRuns::new(spill_dir, run_ids),
+        };
+        for file in files.values() {
+            let reader =
+                ParquetRecordBatchReaderBuilder::try_new(File::open(&file.path)?)?.build()?;
+            for batch in reader {
+                stats.update(&batch?)?;
+            }
+        }
+        stats.ids.flush()?;
+        Ok(stats)
+    }
+
+    fn update(&mut self, batch: &RecordBatch) -> Result<()> {
+        let column = |name: &str, data_type: &DataType| {
+            let column = batch
+                .column_by_name(name)
+                .with_context(|| format!("Zone file has no {name} column"))?;
+            Ok::<_, anyhow::Error>(cast(column, data_type)?)
+        };
+        let ids = column("z_gersid", &DataType::Utf8)?;
+        let ids = ids.as_string::<i32>();
+        let countries = column("z_country", &DataType::Utf8)?;
+        let countries = countries.as_string::<i32>();
+        let subtypes = column("z_subtype", &DataType::Utf8)?;
+        let subtypes = subtypes.as_string::<i32>();
+        let geometries = column("z_boundary", &DataType::Binary)?;
+        let geometries = geometries.as_binary::<i32>();
+
+        self.rows += batch.num_rows() as u64;
+        for row in 0..batch.num_rows() {
+            let country = match countries.is_null(row) {
+                true => NONE_GROUP,
+                false => countries.value(row),
+            };
+            let subtype = match subtypes.is_null(row) {
+                true => NONE_GROUP,
+                false => subtypes.value(row),
+            };
+            *self.countries.entry(country.to_string()).or_default() += 1;
+            *self.subtypes.entry(subtype.to_string()).or_default() += 1;
+
+            let bounds = (!geometries.is_null(row))
+                .then(|| wkb_bounds(geometries.value(row)))
+                .flatten();
+            if let Some(b) = bounds {
+                let c = self
+                    .bounds
+                    .entry(country.to_string())
+                    .or_insert([b[0], b[1], b[2], b[3]]);
+                *c = [
+                    c[0].min(b[0]),
+                    c[1].min(b[1]),
+                    c[2].max(b[2]),
+                    c[3].max(b[3]),
+                ];
+            }
+            if !ids.is_null(row) {
+                self.ids.push(ids.value(row))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Ids spilled to sorted runs of one id per line
+struct SortedRuns {
+    dir: PathBuf,
+    run_ids: usize,
+    runs: Vec<PathBuf>,
+    buffer: Vec<String>,
+    /// Largest id of the last run, which buffered ids above it extend
+    last: Option<String>,
+}
+
+impl SortedRuns {
+    fn new(dir: &Path, run_ids: usize) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            run_ids,
+            runs: Vec::new(),
+            buffer: Vec::new(),
+            last: None,
+        }
+    }
+
+    fn push(&mut self, id: &str) -> Result<()> {
+        self.buffer.push(id.to_string());
+        if self.buffer.len() >= self.run_ids {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the buffered ids as a run, appending them to the last run
+    /// when they arrived in order and follow it
+    fn flush(&mut self) -> Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let ordered = self.buffer.is_sorted();
+        if !ordered {
+            self.buffer.sort_unstable();
+        }
+        let extends = ordered
+            && self
+                .last
+                .as_ref()
+                .is_some_and(|last| *last <= self.buffer[0]);
+        let path = match (extends, self.runs.last()) {
+            (true, Some(path)) => path.clone(),
+            _ => {
+                let path = self.dir.join(format!("run-{}", self.runs.len()));
+                self.runs.push(path.clone());
+                path
+            }
+        };
+        let file = File::options().create(true).append(true).open(&path)?;
+        let mut out = BufWriter::new(file);
+        for id in &self.buffer {
+            writeln!(out, "{id}")?;
+        }
+        out.flush()?;
+        self.last = self.buffer.pop();
+        self.buffer.clear();
+        Ok(())
+    }
+
+    /// Every id of every run in order
+    fn merge(&self) -> Result<MergedRuns> {
+        let mut runs = Vec::with_capacity(self.runs.len());
+        let mut heap = BinaryHeap::new();
+        for (index, path) in self.runs.iter().enumerate() {
+            let mut lines = BufReader::new(File::open(path)?).lines();
+            if let Some(id) = lines.next().transpose()? {
+                heap.push(Reverse((id, index)));
+            }
+            runs.push(lines);
+        }
+        Ok(MergedRuns { runs, heap })
+    }
+}
+
+/// K-way merge of sorted runs
+struct MergedRuns {
+    runs: Vec<Lines<BufReader<File>>>,
+    heap: BinaryHeap<Reverse<(String, usize)>>,
+}
+
+impl Iterator for MergedRuns {
+    type Item = Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Reverse((id, index)) = self.heap.pop()?;
+        match self.runs[index].next().transpose() {
+            Ok(Some(next)) => self.heap.push(Reverse((next, index))),
+            Ok(None) => {}
+            Err(e) => return Some(Err(e.into())),
+        }
+        Some(Ok(id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zone::fixtures::{wkb_polygon, write_parquet};
+    use arrow_array::{BinaryArray, StringArray};
+    use arrow_schema::{Field, Schema};
+    use std::sync::Arc;
+
+    /// Writes zones of `(gersid, country, subtype, lon)`, each a unit square
+    /// at `lon`, 0
+    fn write_zones(path: &Path, rows: &[(&str, &str, &str, f64)]) {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("z_gersid", DataType::Utf8, false),
+            Field::new("z_country", DataType::Utf8, true),
+            Field::new("z_subtype", DataType::Utf8, true),
+            Field::new("z_boundary", DataType::Binary, true),
+        ]));
+        let squares: Vec<Vec<u8>> = rows
+            .iter()
+            .map(|row| {
+                let x = row.3;
+                wkb_polygon(&[(x, 0.0), (x + 1.0, 0.0), (x + 1.0, 1.0), (x, 1.0), (x, 0.0)])
+            })
+            .collect();
+        let batch = RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(StringArray::from_iter_values(rows.iter().map(|r| r.0))),
+                Arc::new(StringArray::from_iter_values(rows.iter().map(|r| r.1))),
+                Arc::new(StringArray::from_iter_values(rows.iter().map(|r| r.2))),
+                Arc::new(BinaryArray::from_iter_values(squares.iter())),
+            ],
+        )
+        .unwrap();
+        write_parquet(path, &batch);
+    }
+
+    #[test]
+    fn test_diff_stats() {
+        let old = tempfile::tempdir().unwrap();
+        let new = tempfile::tempdir().unwrap();
+        write_zones(
+            &old.path().join("zone.parquet"),
+            &[
+                ("a", "US", "county", 0.0),
+                ("b", "US", "county", 1.0),
+                ("c", "CA", "locality", 5.0),
+                ("d", "CA", "county", 6.0),
+            ],
+        );
+        write_zones(
+            &new.path().join("zone.parquet"),
+            &[
+                ("a", "US", "county", 0.0),
+                ("b", "US", "county", 2.5),
+                ("c", "CA", "locality", 5.0),
+                ("e", "MX", "county", 9.0),
+                ("f", "MX", "county", 10.0),
+            ],
+        );
+
+        let report = diff_stats(old.path(), new.path(), 1).unwrap();
+        assert_eq!((report.old_rows, report.new_rows), (4, 5));
+        assert_eq!(report.countries["US"], CountDelta::new(2, 2));
+        assert_eq!(report.countries["CA"].delta, -1);
+        assert_eq!(report.countries["CA"].delta_pct, -50.0);
+        assert_eq!(report.countries["MX"].delta_pct, 100.0);
+        assert_eq!(report.subtypes["county"], CountDelta::new(3, 4));
+        assert_eq!(
+            report.added,
+            IdDelta {
+                count: 2,
+                samples: vec!["e".to_string()]
+            }
+        );
+        assert_eq!(report.removed.count, 1);
+        assert_eq!(report.removed.samples, vec!["d".to_string()]);
+
+        assert_eq!(report.bbox_drift["US"].old, [0.0, 0.0, 2.0, 1.0]);
+        assert_eq!(report.bbox_drift["US"].max_shift, 1.5);
+        assert_eq!(report.bbox_drift["CA"].max_shift, 1.0);
+        assert!(!report.bbox_drift.contains_key("MX"));
+        assert_eq!(report.max_delta_pct(), 100.0);
+    }
+
+    #[test]
+    fn test_identical_datasets() {
+        let dir = tempfile::tempdir().unwrap();
+        write_zones(
+            &dir.path().join("zone.parquet"),
+            &[("a", "US", "county", 0.0), ("b", "US", "county", 1.0)],
+        );
+        let report = diff_stats(dir.path(), dir.path(), 10).unwrap();
+        assert_eq!(report.added, IdDelta::default());
+        assert_eq!(report.removed, IdDelta::default());
+        assert_eq!(report.max_delta_pct(), 0.0);
+    }
+
+    #[test]
+    fn test_ids_merge_across_runs() {
+        let old = tempfile::tempdir().unwrap();
+        let new = tempfile::tempdir().unwrap();
+        // Unordered ids across two parts, spilled two at a time
+        let ids = ["m", "c", "x", "a", "q", "f", "z"];
+        for (part, ids) in ids.chunks(4).enumerate() {
+            let rows: Vec<_> = ids.iter().map(|id| (*id, "US", "county", 0.0)).collect();
+            write_zones(&old.path().join(format!("zone/zone_{part}.parquet")), &rows);
+        }
+        let rows: Vec<_> = ["b", "c", "f", "m", "q", "x", "y"]
+            .iter()
+            .map(|id| (*id, "US", "county", 0.0))
+            .collect();
+        write_zones(&new.path().join("zone.parquet"), &rows);
+
+        let report = diff_stats_with_runs(old.path(), new.path(), 10, 2).unwrap();
+        assert_eq!(report.removed.samples, vec!["a", "z"]);
+        assert_eq!(report.added.samples, vec!["b", "y"]);
+    }
+
+    #[test]
+    fn test_ordered_ids_extend_one_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut runs = SortedRuns::new(dir.path(), 2);
+        for id in ["a", "b", "c", "d", "e"] {
+            runs.push(id).unwrap();
+        }
+        runs.flush().unwrap();
+        assert_eq!(runs.runs.len(), 1);
+
+        runs.push("b").unwrap();
+        runs.flush().unwrap();
+        assert_eq!(runs.runs.len(), 2);
+        let ids: Vec<String> = runs.merge().unwrap().map(Result::unwrap).collect();
+        assert_eq!(ids, vec!["a", "b", "b", "c", "d", "e"]);
+    }
+}
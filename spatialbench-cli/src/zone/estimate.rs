@@ -0,0 +1,472 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Source download estimates from Parquet footers
+//!
+//! Only the footer of every source file is fetched. A row group is pruned
+//! when its `subtype` or `is_land` statistics rule out every row the theme
+//! keeps, and the compressed bytes of the source columns in the remaining
+//! row groups are what a scan downloads. The estimate is exact to the row
+//! group: a kept row group is read whole even if few of its rows match.
+//!
+//! Kept bytes are split by country using the `country` statistics of each
+//! row group. Row groups holding more than one country are counted under
+//! [`MIXED_COUNTRIES`].
+//!
+//! The footers also count the kept rows when the statistics of every row
+//! group show that all or none of its rows are kept, see
+//! [`metadata_row_count`].
+
+use anyhow::{anyhow, Context, Result};
+use futures::TryStreamExt;
+use log::info;
+use object_store::ObjectStore;
+use parquet::arrow::async_reader::ParquetObjectReader;
+use parquet::file::metadata::{ParquetMetaData, ParquetMetaDataReader, RowGroupMetaData};
+use parquet::file::statistics::Statistics;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use url::Url;
+
+use super::datasource::{remote_store, theme_locations, ThemeFilter};
+use super::theme::{ThemeInput, ZONE_SOURCE_COLUMNS};
+
+/// Country of row groups whose rows span several countries
+pub const MIXED_COUNTRIES: &str = "mixed";
+
+/// Footer bytes fetched from the end of a remote file in the first request
+const FOOTER_PREFETCH: usize = 64 * 1024;
+
+/// What a scan of the zone source would read
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct IoEstimate {
+    /// Source files whose footers were read
+    pub files: usize,
+    /// Row groups that may hold kept rows
+    pub row_groups: usize,
+    /// Row groups in all source files
+    pub row_groups_total: usize,
+    /// Rows in the kept row groups
+    pub rows: u64,
+    /// Compressed bytes of the source columns in the kept row groups
+    pub compressed_bytes: u64,
+    /// Compressed bytes of the source columns in all row groups
+    pub compressed_bytes_total: u64,
+    /// Kept compressed bytes by `country`
+    pub countries: BTreeMap<String, u64>,
+}
+
+impl IoEstimate {
+    fn add_file(&mut self, metadata: &ParquetMetaData, filter: &ThemeFilter) {
+        self.files += 1;
+        for row_group in metadata.row_groups() {
+            let bytes = source_bytes(row_group);
+            self.row_groups_total += 1;
+            self.compressed_bytes_total += bytes;
+            if !may_match(row_group, filter) {
+                continue;
+            }
+            self.row_groups += 1;
+            self.rows += row_group.num_rows() as u64;
+            self.compressed_bytes += bytes;
+            let country = single_country(row_group).unwrap_or(MIXED_COUNTRIES);
+            *self.countries.entry(country.to_string()).or_default() += bytes;
+        }
+    }
+}
+
+/// Estimates the source reads of generating the zone table from `themes`
+/// at `scale_factor`, reading only file footers
+pub async fn estimate_source_io(themes: &[ThemeInput], scale_factor: f64) -> Result<IoEstimate> {
+    let mut estimate = IoEstimate::default();
+    for input in themes {
+        let filter = ThemeFilter::new(input.theme, scale_factor);
+        for location in theme_locations(input)? {
+            for metadata in read_footers(&location).await? {
+                estimate.add_file(&metadata, &filter);
+            }
+        }
+    }
+    info!(
+        "Source scan would read {} of {} row groups, {} of {} compressed bytes",
+        estimate.row_groups,
+        estimate.row_groups_total,
+        estimate.compressed_bytes,
+        estimate.compressed_bytes_total
+    );
+    Ok(estimate)
+}
+
+/// Compressed bytes of every leaf column of the `themes` sources over all
+/// row groups, keyed by its dotted path, e.g. `names.primary`. Only the
+/// footers are read.
+pub async fn source_column_bytes(themes: &[ThemeInput]) -> Result<BTreeMap<String, u64>> {
+    let mut bytes = BTreeMap::new();
+    for input in themes {
+        for location in theme_locations(input)? {
+            for metadata in read_footers(&location).await? {
+                for row_group in metadata.row_groups() {
+                    for column in row_group.columns() {
+                        *bytes.entry(column.column_path().string()).or_default() +=
+                            column.compressed_size() as u64;
+                    }
+                }
+            }
+        }
+    }
+    Ok(bytes)
+}
+
+/// The rows a scan of `themes` at `scale_factor` selects, summed from the
+/// row counts in the Parquet footers, or `None` when the statistics of a
+/// row group leave open which of its rows are kept and only a scan can
+/// count them
+pub async fn metadata_row_count(themes: &[ThemeInput], scale_factor: f64) -> Result<Option<u64>> {
+    let mut rows = 0;
+    for input in themes {
+        let filter = ThemeFilter::new(input.theme, scale_factor);
+        for location in theme_locations(input)? {
+            for metadata in read_footers(&location).await? {
+                for row_group in metadata.row_groups() {
+                    match kept_rows(row_group, &filter) {
+                        Some(kept) => rows += kept,
+                        None => return Ok(None),
+                    }
+                }
+            }
+        }
+    }
+    // Below scale factor 1 the scan samples that fraction of the rows
+    if scale_factor < 1.0 {
+        rows = (rows as f64 * scale_factor).ceil() as u64;
+    }
+    Ok(Some(rows))
+}
+
+/// The rows of `row_group` that `filter` keeps, when its statistics show
+/// that it keeps all or none of them
+fn kept_rows(row_group: &RowGroupMetaData, filter: &ThemeFilter) -> Option<u64> {
+    let rows = row_group.num_rows() as u64;
+    if rows == 0 || !may_match(row_group, filter) {
+        return Some(0);
+    }
+    // One non-null value throughout, as null never equals a kept value
+    let subtype = match statistics(row_group, "subtype")? {
+        Statistics::ByteArray(s) if s.null_count_opt() == Some(0) && s.min_opt() == s.max_opt() => {
+            s.min_opt()?.as_utf8().ok()?
+        }
+        _ => return None,
+    };
+    let land = match statistics(row_group, "is_land") {
+        _ if !filter.land_only => true,
+        Some(Statistics::Boolean(s)) if s.null_count_opt() == Some(0) => {
+            match (s.min_opt(), s.max_opt()) {
+                (Some(true), Some(true)) => true,
+                (Some(false), Some(false)) => false,
+                _ => return None,
+            }
+        }
+        _ => return None,
+    };
+    Some(match land && filter.subtypes.contains(&subtype) {
+        true => rows,
+        false => 0,
+    })
+}
+
+/// Footers of the Parquet file at `location`, or of the `.parquet` files
+/// below it if it is a local directory or a remote URL ending in `/`
+pub(super) async fn read_footers(location: &str) -> Result<Vec<ParquetMetaData>> {
+    let remote = match Url::parse(location) {
+        Ok(url) => remote_store(&url)?.map(|(_, store)| (url, store)),
+        Err(_) => None,
+    };
+    if let Some((url, store)) = remote {
+        let path = object_store::path::Path::from_url_path(url.path())?;
+        let files = match url.path().ends_with('/') {
+            true => remote_parquet_files(store.as_ref(), &path).await?,
+            false => vec![(path.clone(), store.head(&path).await?.size)],
+        };
+        let mut footers = Vec::with_capacity(files.len());
+        for (path, size) in files {
+            let mut reader =
+                ParquetObjectReader::new(Arc::clone(&store), path.clone()).with_file_size(size);
+            footers.push(
+                ParquetMetaDataReader::new()
+                    .with_prefetch_hint(Some(FOOTER_PREFETCH))
+                    .load_and_finish(&mut reader, size)
+                    .await
+                    .with_context(|| format!("Failed to read the footer of {location} {path}"))?,
+            );
+        }
+        return Ok(footers);
+    }
+    local_parquet_files(Path::new(location))?
+        .iter()
+        .map(|path| {
+            let file = std::fs::File::open(path)
+                .with_context(|| format!("Failed to open {}", path.display()))?;
+            ParquetMetaDataReader::new()
+                .parse_and_finish(&file)
+                .with_context(|| format!("Failed to read the footer of {}", path.display()))
+        })
+        .collect()
+}
+
+/// The `.parquet` objects below `prefix` in `store` and their sizes, in
+/// path order
+async fn remote_parquet_files(
+    store: &dyn ObjectStore,
+    prefix: &object_store::path::Path,
+) -> Result<Vec<(object_store::path::Path, u64)>> {
+    let mut files: Vec<_> = store
+        .list(Some(prefix))
+        .try_collect::<Vec<_>>()
+        .await?
+        .into_iter()
+        .filter(|meta| meta.location.extension() == Some("parquet"))
+        .map(|meta| (meta.location, meta.size))
+        .collect();
+    if files.is_empty() {
+        return Err(anyhow!("No .parquet files found below {prefix}"));
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// `path` itself, or the `.parquet` files below it in path order
+fn local_parquet_files(path: &Path) -> Result<Vec<PathBuf>> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut files = Vec::new();
+    let mut dirs = vec![path.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if path.extension().is_some_and(|e| e == "parquet") {
+                files.push(path);
+            }
+        }
+    }
+    if files.is_empty() {
+        return Err(anyhow!("No .parquet files found in {}", path.display()));
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Compressed bytes of the columns a scan reads, the source columns and
+/// `is_land`
+fn source_bytes(row_group: &RowGroupMetaData) -> u64 {
+    row_group
+        .columns()
+        .iter()
+        .filter(|column| {
+            let name = &column.column_path().parts()[0];
+            name == "is_land" || ZONE_SOURCE_COLUMNS.contains(&name.as_str())
+        })
+        .map(|column| column.compressed_size() as u64)
+        .sum()
+}
+
+fn statistics<'a>(row_group: &'a RowGroupMetaData, column: &str) -> Option<&'a Statistics> {
+    row_group
+        .columns()
+        .iter()
+        .find(|c| c.column_path().string() == column)?
+        .statistics()
+}
+
+/// Whether the statistics of `row_group` allow rows that `filter` keeps.
+/// Row groups without statistics may hold any row.
+fn may_match(row_group: &RowGroupMetaData, filter: &ThemeFilter) -> bool {
+    let rows = row_group.num_rows() as u64;
+    let subtypes = match statistics(row_group, "subtype") {
+        Some(Statistics::ByteArray(s)) if s.null_count_opt() == Some(rows) => false,
+        Some(Statistics::ByteArray(s)) => match (s.min_opt(), s.max_opt()) {
+            (Some(min), Some(max)) => filter.subtypes.iter().any(|subtype| {
+                let subtype = subtype.as_bytes();
+                min.data() <= subtype && subtype <= max.data()
+            }),
+            _ => true,
+        },
+        _ => true,
+    };
+    let land = match statistics(row_group, "is_land") {
+        _ if !filter.land_only => true,
+        Some(Statistics::Boolean(s)) if s.null_count_opt() == Some(rows) => false,
+        Some(Statistics::Boolean(s)) => s.max_opt() != Some(&false),
+        _ => true,
+    };
+    subtypes && land
+}
+
+/// The country of every row of `row_group`, if its statistics show one
+fn single_country(row_group: &RowGroupMetaData) -> Option<&str> {
+    match statistics(row_group, "country")? {
+        Statistics::ByteArray(s) if s.min_opt() == s.max_opt() => s.min_opt()?.as_utf8().ok(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zone::fixtures::{source_batch, SourceRow};
+    use crate::zone::theme::Theme;
+    use arrow_array::{BooleanArray, RecordBatch};
+    use parquet::arrow::ArrowWriter;
+    use parquet::file::properties::WriterProperties;
+
+    fn row(id: &'static str, subtype: &'static str, country: &'static str) -> SourceRow {
+        SourceRow {
+            country,
+            ..SourceRow::new(id, subtype)
+        }
+    }
+
+    /// Writes `rows` in row groups of two rows, with `is_land` per row
+    fn write_source(path: &Path, rows: &[SourceRow], is_land: &[bool]) {
+        let batch = source_batch(rows, true);
+        let mut columns = batch.columns().to_vec();
+        *columns.last_mut().unwrap() = Arc::new(BooleanArray::from(is_land.to_vec()));
+        let batch = RecordBatch::try_new(batch.schema(), columns).unwrap();
+
+        let props = WriterProperties::builder()
+            .set_max_row_group_size(2)
+            .build();
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let file = std::fs::File::create(path).unwrap();
+        let mut writer = ArrowWriter::try_new(file, batch.schema(), Some(props)).unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_estimate_prunes_row_groups_by_statistics() {
+        let dir = tempfile::tempdir().unwrap();
+        write_source(
+            &dir.path().join("part-0.parquet"),
+            &[
+                // Kept, all US
+                row("a", "county", "US"),
+                row("b", "county", "US"),
+                // Countries are not kept below SF 1000
+                row("c", "country", "FR"),
+                row("d", "country", "FR"),
+                // Kept, spans two countries
+                row("e", "county", "FR"),
+                row("f", "microhood", "JP"),
+                // Counties, but no land
+                row("g", "county", "DE"),
+                row("h", "county", "DE"),
+            ],
+            &[true, true, true, true, true, true, false, false],
+        );
+        write_source(
+            &dir.path().join("nested/part-1.parquet"),
+            &[row("i", "macrohood", "JP"), row("j", "region", "JP")],
+            &[true, true],
+        );
+        std::fs::write(dir.path().join("_SUCCESS"), "").unwrap();
+
+        let themes = vec![ThemeInput {
+            theme: Theme::DivisionArea,
+            location: Some(dir.path().to_str().unwrap().to_string()),
+        }];
+        let estimate = estimate_source_io(&themes, 1.0).await.unwrap();
+        assert_eq!(estimate.files, 2);
+        assert_eq!(estimate.row_groups_total, 5);
+        assert_eq!(estimate.row_groups, 3);
+        assert_eq!(estimate.rows, 6);
+        assert_eq!(
+            estimate.countries.keys().collect::<Vec<_>>(),
+            vec!["JP", "US", MIXED_COUNTRIES]
+        );
+        assert_eq!(
+            estimate.countries.values().sum::<u64>(),
+            estimate.compressed_bytes
+        );
+        assert!(estimate.compressed_bytes < estimate.compressed_bytes_total);
+
+        // Countries are kept from SF 1000
+        let estimate = estimate_source_io(&themes, 1000.0).await.unwrap();
+        assert_eq!(estimate.row_groups, 4);
+        assert!(estimate.countries.contains_key("FR"));
+
+        // No localities, but "locality" sorts between county and microhood,
+        // so that row group can't be pruned
+        let localities = vec![ThemeInput {
+            theme: Theme::Locality,
+            ..themes[0].clone()
+        }];
+        let estimate = estimate_source_io(&localities, 1.0).await.unwrap();
+        assert_eq!(estimate.row_groups, 1);
+        assert_eq!(
+            estimate.countries.keys().collect::<Vec<_>>(),
+            vec![MIXED_COUNTRIES]
+        );
+
+        // The subtypes of the county and microhood row group leave its
+        // count to a scan
+        assert_eq!(metadata_row_count(&themes, 1.0).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_metadata_row_count_matches_scan() {
+        let dir = tempfile::tempdir().unwrap();
+        write_source(
+            &dir.path().join("part-0.parquet"),
+            &[
+                row("a", "county", "US"),
+                row("b", "county", "US"),
+                row("c", "microhood", "FR"),
+                row("d", "microhood", "FR"),
+                row("e", "country", "FR"),
+                row("f", "country", "FR"),
+                row("g", "county", "DE"),
+            ],
+            &[true, true, true, true, true, true, true],
+        );
+        write_source(
+            &dir.path().join("part-1.parquet"),
+            &[row("h", "county", "DE"), row("i", "county", "DE")],
+            &[false, false],
+        );
+        let themes = vec![ThemeInput {
+            theme: Theme::DivisionArea,
+            location: Some(dir.path().to_str().unwrap().to_string()),
+        }];
+
+        for (scale_factor, rows) in [(1.0, 5), (1000.0, 7), (0.5, 3)] {
+            let exact = crate::zone::count_zone_rows(scale_factor, None, themes.clone())
+                .await
+                .unwrap();
+            assert_eq!(exact, rows, "scale factor {scale_factor}");
+            assert_eq!(
+                metadata_row_count(&themes, scale_factor).await.unwrap(),
+                Some(rows as u64),
+                "scale factor {scale_factor}"
+            );
+        }
+    }
+}
@@ -0,0 +1,296 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Plans of the zone generation query and whether the source scan receives
+//! its filters and projection
+//!
+//! The pushdown is read off the indented physical plan: a Parquet scan
+//! lists the `projection` and `predicate` it was given, and a `FilterExec`
+//! over a scan without a predicate filters rows the scan could have pruned.
+
+use anyhow::Result;
+use std::collections::{BTreeMap, BTreeSet};
+
+use datafusion::physical_plan::display::DisplayableExecutionPlan;
+use datafusion::physical_plan::{collect, displayable};
+use datafusion::prelude::DataFrame;
+
+/// What `--explain` and `--explain-analyze` print
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Explain {
+    /// The plans, without running the query
+    Plan,
+    /// The plans, then the physical plan with the metrics of a run over the
+    /// first `rows` rows
+    Analyze { rows: usize },
+}
+
+/// A Parquet scan of the physical plan
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScanPushdown {
+    /// The scan as displayed in the plan
+    pub plan: String,
+    /// Columns read, when the projection reached the scan
+    pub projection: Option<String>,
+    /// Predicate the scan prunes with, when a filter reached it
+    pub predicate: Option<String>,
+}
+
+impl ScanPushdown {
+    /// Names of the columns read, when the projection reached the scan
+    pub fn columns(&self) -> Option<Vec<String>> {
+        let projection = self.projection.as_deref()?;
+        let list = projection.strip_prefix('[')?.strip_suffix(']')?;
+        Some(
+            list.split(", ")
+                .filter(|column| !column.is_empty())
+                .map(|column| match column.split_once('@') {
+                    Some((name, _)) => name.to_string(),
+                    None => column.to_string(),
+                })
+                .collect(),
+        )
+    }
+}
+
+/// Compressed source bytes of the columns the scans read, from the source
+/// footers
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScanBytes {
+    /// Bytes of the columns read
+    pub scanned: u64,
+    /// Bytes of every source column
+    pub total: u64,
+    /// Bytes of the `names` struct, which a scan reads whole
+    pub names: u64,
+    /// Bytes of `names.primary`, the only name read unless the transform
+    /// reads `names.common`
+    pub names_primary: u64,
+}
+
+impl ScanBytes {
+    /// The bytes of `columns` among the leaf column bytes `column_bytes`,
+    /// keyed by dotted path
+    pub fn new(column_bytes: &BTreeMap<String, u64>, columns: &BTreeSet<String>) -> Self {
+        let root = |path: &str| path.split('.').next().unwrap_or(path).to_string();
+        let sum = |keep: &dyn Fn(&str) -> bool| {
+            column_bytes
+                .iter()
+                .filter(|(path, _)| keep(path))
+                .map(|(_, bytes)| bytes)
+                .sum()
+        };
+        Self {
+            scanned: sum(&|path| columns.contains(&root(path))),
+            total: sum(&|_| true),
+            names: sum(&|path| root(path) == "names"),
+            names_primary: sum(&|path| path == "names.primary"),
+        }
+    }
+
+    /// Bytes the projection leaves unread
+    pub fn saved(&self) -> u64 {
+        self.total - self.scanned
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ZoneExplain {
+    pub logical_plan: String,
+    pub physical_plan: String,
+    /// Physical plan with metrics, for [`Explain::Analyze`]
+    pub analyzed_plan: Option<String>,
+    pub scans: Vec<ScanPushdown>,
+    /// Filters evaluated above a scan that received no predicate
+    pub filters_above_scan: Vec<String>,
+    /// Columns a scan reads beyond those the zone query needs
+    pub unexpected_columns: Vec<String>,
+    /// What the scanned columns weigh in the source, when measured
+    pub scan_bytes: Option<ScanBytes>,
+}
+
+impl ZoneExplain {
+    /// The columns read by every scan with a projection
+    pub fn scanned_columns(&self) -> BTreeSet<String> {
+        self.scans
+            .iter()
+            .filter_map(ScanPushdown::columns)
+            .flatten()
+            .collect()
+    }
+
+    /// Records the scanned columns that are not `expected`
+    pub fn with_expected_columns(mut self, expected: &[String]) -> Self {
+        self.unexpected_columns = self
+            .scanned_columns()
+            .into_iter()
+            .filter(|column| !expected.contains(column))
+            .collect();
+        self
+    }
+
+    /// Measures the scanned columns against the leaf column bytes of the
+    /// source, see [`ScanBytes::new`]
+    pub fn with_column_bytes(mut self, column_bytes: &BTreeMap<String, u64>) -> Self {
+        self.scan_bytes = Some(ScanBytes::new(column_bytes, &self.scanned_columns()));
+        self
+    }
+}
+
+/// Plans `df`, and runs it as `mode` asks
+pub async fn explain(df: DataFrame, mode: Explain) -> Result<ZoneExplain> {
+    let logical_plan = df
+        .clone()
+        .into_optimized_plan()?
+        .display_indent()
+        .to_string();
+    let plan = df.clone().create_physical_plan().await?;
+    let physical_plan = displayable(plan.as_ref()).indent(true).to_string();
+
+    let analyzed_plan = match mode {
+        Explain::Plan => None,
+        Explain::Analyze { rows } => {
+            let df = df.limit(0, Some(rows))?;
+            let task_ctx = df.task_ctx();
+            let plan = df.create_physical_plan().await?;
+            collect(plan.clone(), task_ctx.into()).await?;
+            let analyzed = DisplayableExecutionPlan::with_metrics(plan.as_ref())
+                .indent(true)
+                .to_string();
+            Some(analyzed)
+        }
+    };
+
+    let (scans, filters_above_scan) = analyze_pushdown(&physical_plan);
+    Ok(ZoneExplain {
+        logical_plan,
+        physical_plan,
+        analyzed_plan,
+        scans,
+        filters_above_scan,
+        unexpected_columns: vec![],
+        scan_bytes: None,
+    })
+}
+
+/// The Parquet scans of an indented physical plan and the filters above
+/// scans without a predicate
+fn analyze_pushdown(physical_plan: &str) -> (Vec<ScanPushdown>, Vec<String>) {
+    let nodes: Vec<(usize, &str)> = physical_plan
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let node = line.trim_start();
+            (line.len() - node.len(), node)
+        })
+        .collect();
+
+    let scans = nodes
+        .iter()
+        .filter(|(_, node)| is_parquet_scan(node))
+        .map(|(_, node)| ScanPushdown {
+            plan: node.to_string(),
+            projection: plan_field(node, "projection="),
+            predicate: plan_field(node, "predicate="),
+        })
+        .collect();
+
+    let mut filters_above_scan = Vec::new();
+    for (i, (depth, node)) in nodes.iter().enumerate() {
+        if !node.starts_with("FilterExec") {
+            continue;
+        }
+        let unpruned_scan_below = nodes[i + 1..]
+            .iter()
+            .take_while(|(child_depth, _)| child_depth > depth)
+            .any(|(_, child)| is_parquet_scan(child) && plan_field(child, "predicate=").is_none());
+        if unpruned_scan_below {
+            filters_above_scan.push(node.to_string());
+        }
+    }
+    (scans, filters_above_scan)
+}
+
+fn is_parquet_scan(node: &str) -> bool {
+    node.contains("file_type=parquet") || node.starts_with("ParquetExec")
+}
+
+/// The value of `key` in a displayed plan node, up to the next top level
+/// `, ` separator
+fn plan_field(node: &str, key: &str) -> Option<String> {
+    let start = node.find(key)? + key.len();
+    let value = &node[start..];
+    let mut depth = 0i32;
+    for (i, c) in value.char_indices() {
+        match c {
+            '[' | '(' | '{' => depth += 1,
+            ']' | ')' | '}' => depth -= 1,
+            ',' if depth == 0 => return Some(value[..i].to_string()),
+            _ => {}
+        }
+    }
+    Some(value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_analyze_pushdown() {
+        let plan = "\
+SortExec: expr=[z_zonekey@0 ASC], preserve_partitioning=[false]
+  FilterExec: subtype@4 = county
+    DataSourceExec: file_groups={1 group: [[a.parquet]]}, projection=[id, subtype], file_type=parquet
+  FilterExec: is_land@2
+    DataSourceExec: file_groups={1 group: [[b.parquet]]}, projection=[id, is_land], file_type=parquet, predicate=is_land@2, pruning_predicate=is_land_min@0
+";
+        let (scans, filters) = analyze_pushdown(plan);
+        assert_eq!(scans.len(), 2);
+        assert_eq!(scans[0].projection.as_deref(), Some("[id, subtype]"));
+        assert_eq!(scans[0].predicate, None);
+        assert_eq!(scans[1].predicate.as_deref(), Some("is_land@2"));
+        assert_eq!(filters, vec!["FilterExec: subtype@4 = county"]);
+        assert_eq!(
+            scans[1].columns(),
+            Some(vec!["id".to_string(), "is_land".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_scan_bytes() {
+        let column_bytes = BTreeMap::from([
+            ("id".to_string(), 10),
+            ("names.primary".to_string(), 20),
+            ("names.common.key_value.key".to_string(), 30),
+            ("names.common.key_value.value".to_string(), 40),
+            ("sources.list.element.dataset".to_string(), 100),
+        ]);
+        let columns = BTreeSet::from(["id".to_string(), "names".to_string()]);
+        let bytes = ScanBytes::new(&column_bytes, &columns);
+        assert_eq!(
+            bytes,
+            ScanBytes {
+                scanned: 100,
+                total: 200,
+                names: 90,
+                names_primary: 20,
+            }
+        );
+        assert_eq!(bytes.saved(), 100);
+    }
+}
@@ -0,0 +1,593 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Re-cutting a generated zone dataset into another number of parts
+//!
+//! Parts split by row count hold consecutive runs of the table in key
+//! order, so the parts of any other count are slices of the existing files.
+//! `extend` plans the new parts over the row count in the manifest, reads
+//! each from the files holding its rows and writes it the way a fresh run
+//! with the new `--parts` would. No source is read: new parts with rows in
+//! a missing or damaged file are left to be regenerated with `--part`.
+
+use anyhow::{anyhow, Result};
+use arrow_array::RecordBatch;
+use arrow_schema::{Schema, SchemaRef};
+use log::{debug, info};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::basic::Compression as ParquetCompression;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::generation_params::{GenerationParams, GENERATION_PARAMS_KEY};
+
+use super::bbox::{BboxLayout, BBOX_COLUMN, GEO_METADATA_KEY};
+use super::config::ZoneDfArgs;
+use super::hash::{hash_parquet_file, read_footer_value};
+use super::manifest::{ManifestPart, ZoneManifest, SUCCESS_FILE_NAME};
+use super::partition::PartitionPlan;
+use super::schema::SCHEMA_VERSION_KEY;
+use super::stats::ZoneTableStats;
+use super::writer::ParquetWriter;
+
+/// Directory of `data_dir` the new parts are written into before they
+/// replace the existing ones
+const STAGING_DIR_NAME: &str = "zone.extend.inprogress";
+
+/// How the parts of a new part count are produced from a dataset
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExtendPlan {
+    pub parts: i32,
+    pub total_rows: u64,
+    /// New parts cut from the existing files
+    pub local: Vec<LocalPart>,
+    /// New parts holding rows of an existing file that is missing or no
+    /// longer matches its content hash
+    pub regenerate: Vec<i32>,
+}
+
+/// A new part and the slices of the existing files holding its rows, in
+/// row order
+#[derive(Clone, Debug, PartialEq)]
+pub struct LocalPart {
+    pub part: i32,
+    pub slices: Vec<FileSlice>,
+}
+
+/// Rows `offset..offset + rows` of an existing file
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileSlice {
+    /// Path relative to the dataset directory
+    pub path: String,
+    pub offset: u64,
+    pub rows: u64,
+}
+
+/// Plans `new_parts` parts over the rows of the dataset in `data_dir`
+/// described by `manifest`.
+///
+/// Every part must be recorded, the recorded row counts must add up to the
+/// planned total and every part must hold the rows a split by row count
+/// gives it. The files are re-hashed; new parts overlapping one that is
+/// missing or doesn't match are planned for regeneration.
+pub fn plan_extend(manifest: &ZoneManifest, data_dir: &Path, new_parts: i32) -> Result<ExtendPlan> {
+    if new_parts < 1 {
+        return Err(anyhow!(
+            "Invalid --new-parts={new_parts}, expected at least 1"
+        ));
+    }
+    if new_parts == manifest.parts {
+        return Err(anyhow!(
+            "{} already has {new_parts} part(s)",
+            data_dir.display()
+        ));
+    }
+    if manifest.extents.is_some() {
+        return Err(anyhow!(
+            "The parts of {} are latitude or longitude bands, which can't be re-cut into \
+             {new_parts} part(s); regenerate the dataset with --parts {new_parts}",
+            data_dir.display()
+        ));
+    }
+    if manifest.boundaries.is_some() {
+        return Err(anyhow!(
+            "The parts of {} were balanced by vertex count or a plan file, which can't be \
+             re-cut into {new_parts} part(s); regenerate the dataset with --parts {new_parts}",
+            data_dir.display()
+        ));
+    }
+    let missing = manifest.missing_parts();
+    if !missing.is_empty() {
+        return Err(anyhow!(
+            "Manifest of {} is incomplete: {} of {} part(s) missing: {:?}",
+            data_dir.display(),
+            missing.len(),
+            manifest.parts,
+            missing
+        ));
+    }
+
+    let mut files = manifest.files.clone();
+    files.sort_by_key(|f| (f.part, f.file_index));
+    let total_rows: u64 = files.iter().map(|f| f.rows).sum();
+    if let Some(planned) = manifest.total_rows {
+        if planned != total_rows {
+            return Err(anyhow!(
+                "Manifest of {} plans {planned} row(s) but its files hold {total_rows}",
+                data_dir.display()
+            ));
+        }
+    }
+    let old_plan = PartitionPlan::new(total_rows as i64, manifest.parts);
+    for spec in old_plan.iter() {
+        let rows: u64 = files
+            .iter()
+            .filter(|f| f.part == spec.part)
+            .map(|f| f.rows)
+            .sum();
+        if rows != spec.limit as u64 {
+            return Err(anyhow!(
+                "Part {} of {} holds {rows} row(s), not the {} of a split by row count",
+                spec.part,
+                data_dir.display(),
+                spec.limit
+            ));
+        }
+    }
+
+    // Row range of every file in the table and whether its rows are intact
+    let mut ranges = Vec::with_capacity(files.len());
+    let mut start = 0;
+    for file in &files {
+        let intact = file_is_intact(data_dir, file)?;
+        ranges.push((file, start, intact));
+        start += file.rows;
+    }
+
+    let mut plan = ExtendPlan {
+        parts: new_parts,
+        total_rows,
+        local: vec![],
+        regenerate: vec![],
+    };
+    for spec in PartitionPlan::new(total_rows as i64, new_parts).iter() {
+        let (offset, end) = (spec.offset as u64, (spec.offset + spec.limit) as u64);
+        let overlapping: Vec<_> = ranges
+            .iter()
+            .filter(|(file, start, _)| *start < end && start + file.rows > offset)
+            .collect();
+        if overlapping.iter().any(|(_, _, intact)| !intact) {
+            plan.regenerate.push(spec.part);
+            continue;
+        }
+        let slices = overlapping
+            .iter()
+            .map(|(file, start, _)| {
+                let from = offset.max(*start);
+                let to = end.min(start + file.rows);
+                FileSlice {
+                    path: file.path.clone(),
+                    offset: from - start,
+                    rows: to - from,
+                }
+            })
+            .collect();
+        plan.local.push(LocalPart {
+            part: spec.part,
+            slices,
+        });
+    }
+    Ok(plan)
+}
+
+/// Whether `file` exists and its rows still match the recorded count and
+/// content hash
+fn file_is_intact(data_dir: &Path, file: &ManifestPart) -> Result<bool> {
+    let path = data_dir.join(&file.path);
+    if !path.is_file() {
+        info!("{}: missing", file.path);
+        return Ok(false);
+    }
+    let rows = ParquetRecordBatchReaderBuilder::try_new(File::open(&path)?)?
+        .metadata()
+        .file_metadata()
+        .num_rows();
+    if rows as u64 != file.rows {
+        info!("{}: {rows} row(s), {} recorded", file.path, file.rows);
+        return Ok(false);
+    }
+    if hash_parquet_file(&path)? != file.content_sha256 {
+        info!("{}: content hash doesn't match the manifest", file.path);
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+/// Re-cuts the dataset in `data_dir` into `new_parts` parts.
+///
+/// The parts cut from the existing files are written into a staging
+/// directory with `compression` and `row_group_bytes`, then replace every
+/// existing part file and the manifest is rewritten for the new part
+/// count. Returns the plan, whose `regenerate` parts are left for
+/// `--parts {new_parts} --part N` runs into the same directory.
+pub fn extend_zone(
+    data_dir: &Path,
+    new_parts: i32,
+    compression: ParquetCompression,
+    row_group_bytes: i64,
+) -> Result<ExtendPlan> {
+    let manifest = ZoneManifest::read(data_dir)?
+        .ok_or_else(|| anyhow!("No zone manifest found in {}", data_dir.display()))?;
+    let plan = plan_extend(&manifest, data_dir, new_parts)?;
+    if plan.local.is_empty() {
+        return Err(anyhow!(
+            "No part of {} can be cut from its files; regenerate the dataset with --parts \
+             {new_parts}",
+            data_dir.display()
+        ));
+    }
+
+    // Any intact file describes the schema and the footer options of the run
+    let template = plan
+        .local
+        .iter()
+        .flat_map(|local| &local.slices)
+        .next()
+        .map(|slice| data_dir.join(&slice.path))
+        .ok_or_else(|| anyhow!("{} has no rows to cut", data_dir.display()))?;
+    let schema = file_schema(&template)?;
+    let footer = FooterOptions::read(&manifest, &template, &schema)?;
+
+    let staging = data_dir.join(STAGING_DIR_NAME);
+    if staging.exists() {
+        std::fs::remove_dir_all(&staging)?;
+    }
+    let stats = ZoneTableStats::new(manifest.scale_factor, Some(new_parts));
+    for local in &plan.local {
+        let batches = read_slices(data_dir, &local.slices, &schema)?;
+        let mut args = ZoneDfArgs::new(
+            manifest.scale_factor,
+            staging.clone(),
+            Some(new_parts),
+            Some(local.part),
+            None,
+            row_group_bytes,
+            compression,
+        );
+        args.total_rows = Some(plan.total_rows as i64);
+        footer.apply(&manifest, &mut args);
+        let mut writer = ParquetWriter::new(&args, &stats, Arc::clone(&schema));
+        if let Some(version) = &footer.schema_version {
+            writer = writer.with_schema_version(version.clone());
+        }
+        writer.write(&batches)?;
+        debug!(
+            "Cut part {} of {new_parts} from {:?}",
+            local.part, local.slices
+        );
+    }
+
+    let staged = ZoneManifest::read(&staging)?
+        .ok_or_else(|| anyhow!("No zone manifest found in {}", staging.display()))?;
+    let had_success_marker = had_success_marker(&manifest, data_dir);
+    promote(data_dir, &manifest, &staged, &staging)?;
+    let extended = ZoneManifest {
+        parts: new_parts,
+        total_rows: Some(plan.total_rows),
+        files: staged.files,
+        ..manifest.clone()
+    };
+    extended.write(data_dir)?;
+    std::fs::remove_dir_all(&staging)?;
+
+    if had_success_marker && plan.regenerate.is_empty() {
+        extended.finalize(data_dir)?;
+    }
+    info!(
+        "Re-cut {} into {new_parts} part(s): {} cut locally, {} to regenerate",
+        data_dir.display(),
+        plan.local.len(),
+        plan.regenerate.len()
+    );
+    Ok(plan)
+}
+
+/// The schema of the Parquet file `path`, without the footer metadata the
+/// reader merges into it
+fn file_schema(path: &Path) -> Result<SchemaRef> {
+    let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(path)?)?;
+    Ok(Arc::new(Schema::new(builder.schema().fields().clone())))
+}
+
+/// Reads `slices` of the files in `data_dir`, which must all have `schema`
+fn read_slices(
+    data_dir: &Path,
+    slices: &[FileSlice],
+    schema: &SchemaRef,
+) -> Result<Vec<RecordBatch>> {
+    let mut batches = vec![];
+    for slice in slices {
+        let path = data_dir.join(&slice.path);
+        if &file_schema(&path)? != schema {
+            return Err(anyhow!(
+                "{} has a different schema than the other zone files",
+                slice.path
+            ));
+        }
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&path)?)?
+            .with_offset(slice.offset as usize)
+            .with_limit(slice.rows as usize)
+            .build()?;
+        for batch in reader {
+            batches.push(batch?.with_schema(Arc::clone(schema))?);
+        }
+    }
+    Ok(batches)
+}
+
+/// The footer metadata the run recorded in a manifest chose by options the
+/// manifest doesn't record, read back from one of its files
+struct FooterOptions {
+    /// `--with-bbox-covering` layout, when the files declare a covering
+    bbox_layout: Option<BboxLayout>,
+    /// Manifests written before the schema version was recorded leave it
+    /// to the footers
+    schema_version: Option<String>,
+    /// `--embed-params` parameters of the run, kept in the new parts
+    generation_params: Option<GenerationParams>,
+}
+
+impl FooterOptions {
+    fn read(manifest: &ZoneManifest, path: &Path, schema: &SchemaRef) -> Result<Self> {
+        let bbox_layout = read_footer_value(path, GEO_METADATA_KEY)?.map(|_| {
+            match schema.field_with_name(BBOX_COLUMN) {
+                Ok(_) => BboxLayout::Struct,
+                Err(_) => BboxLayout::Flat,
+            }
+        });
+        let schema_version = match &manifest.schema_version {
+            Some(version) => Some(version.clone()),
+            None => read_footer_value(path, SCHEMA_VERSION_KEY)?,
+        };
+        let generation_params = read_footer_value(path, GENERATION_PARAMS_KEY)?
+            .map(|json| GenerationParams::from_json(&json))
+            .transpose()?;
+        Ok(Self {
+            bbox_layout,
+            schema_version,
+            generation_params,
+        })
+    }
+
+    /// Sets the options of `args` that shape the footers and the manifest
+    /// entries of the run recorded in `manifest`
+    fn apply(&self, manifest: &ZoneManifest, args: &mut ZoneDfArgs) {
+        args.rows = manifest.rows;
+        args.limit = manifest.limit;
+        if let Some(seed) = manifest.seed {
+            args.transform.seed = seed;
+        }
+        // The first file of a part split by --max-rows-per-file is a full one
+        args.max_rows_per_file = manifest
+            .files
+            .iter()
+            .find(|f| f.file_index == Some(0))
+            .map(|f| f.rows as usize);
+        if let Some(layout) = self.bbox_layout {
+            args.transform.bbox_covering = true;
+            args.transform.bbox_layout = layout;
+        }
+        args.generation_params = self.generation_params.clone();
+    }
+}
+
+/// Whether the parts of `manifest` were marked complete with `_SUCCESS`
+fn had_success_marker(manifest: &ZoneManifest, data_dir: &Path) -> bool {
+    manifest
+        .files
+        .first()
+        .and_then(|f| data_dir.join(&f.path).parent().map(Path::to_path_buf))
+        .is_some_and(|dir| dir.join(SUCCESS_FILE_NAME).is_file())
+}
+
+/// Replaces the part files of `manifest` and their `_SUCCESS` marker with
+/// the files staged in `staging`
+fn promote(
+    data_dir: &Path,
+    manifest: &ZoneManifest,
+    staged: &ZoneManifest,
+    staging: &Path,
+) -> Result<()> {
+    let mut removed: Vec<PathBuf> = vec![];
+    for file in &manifest.files {
+        let path = data_dir.join(&file.path);
+        if let Some(parent) = path.parent() {
+            removed.push(parent.join(SUCCESS_FILE_NAME));
+        }
+        removed.push(path);
+    }
+    for path in removed {
+        match std::fs::remove_file(&path) {
+            Ok(()) => debug!("Removed {}", path.display()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(anyhow!("Failed removing {}: {e}", path.display())),
+        }
+    }
+    for file in &staged.files {
+        let to = data_dir.join(&file.path);
+        if let Some(parent) = to.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::rename(staging.join(&file.path), &to)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zone::fixtures::{source_batch, write_parquet, SourceRow};
+    use crate::zone::theme::{Theme, ThemeInput};
+    use crate::zone::{generate_zone_parquet_multi, generate_zone_parquet_single, verify};
+
+    fn source_file(dir: &Path, ids: &[&'static str]) -> ThemeInput {
+        let path = dir.join("division_area.parquet");
+        let rows: Vec<_> = ids.iter().map(|id| SourceRow::new(id, "county")).collect();
+        write_parquet(&path, &source_batch(&rows, true));
+        ThemeInput {
+            theme: Theme::DivisionArea,
+            location: Some(path.to_string_lossy().into_owned()),
+        }
+    }
+
+    fn zone_args(
+        theme: &ThemeInput,
+        output_dir: &Path,
+        parts: i32,
+        part: Option<i32>,
+    ) -> ZoneDfArgs {
+        ZoneDfArgs::new(
+            1.0,
+            output_dir.to_path_buf(),
+            Some(parts),
+            part,
+            None,
+            1024 * 1024,
+            ParquetCompression::SNAPPY,
+        )
+        .with_themes(vec![theme.clone()])
+    }
+
+    #[tokio::test]
+    async fn test_extend_matches_fresh_generation() {
+        let dir = tempfile::tempdir().unwrap();
+        let theme = source_file(dir.path(), &["g1", "g2", "g3", "g4", "g5", "g6", "g7"]);
+
+        for max_rows_per_file in [None, Some(2)] {
+            let args = |output_dir: &Path, parts: i32| {
+                let mut args = zone_args(&theme, output_dir, parts, None)
+                    .with_max_rows_per_file(max_rows_per_file);
+                args.transform.bbox_covering = max_rows_per_file.is_some();
+                args
+            };
+            let name = format!("{max_rows_per_file:?}");
+            let extended = dir.path().join(format!("extended-{name}"));
+            let fresh = dir.path().join(format!("fresh-{name}"));
+            generate_zone_parquet_multi(args(&extended, 2))
+                .await
+                .unwrap();
+            generate_zone_parquet_multi(args(&fresh, 3)).await.unwrap();
+
+            let plan = extend_zone(&extended, 3, ParquetCompression::SNAPPY, 1024 * 1024).unwrap();
+            assert_eq!(plan.local.len(), 3);
+            assert!(plan.regenerate.is_empty());
+
+            let report = verify::compare_dirs(&extended, &fresh).unwrap();
+            assert!(report.is_identical(), "{report:?}");
+            assert!(!report.identical.is_empty());
+            let manifest = ZoneManifest::read(&extended).unwrap().unwrap();
+            let expected = ZoneManifest::read(&fresh).unwrap().unwrap();
+            assert_eq!(manifest.parts, 3);
+            assert_eq!(manifest.files, expected.files);
+            for file in &manifest.files {
+                let footer = |dir: &Path| {
+                    read_footer_value(&dir.join(&file.path), GEO_METADATA_KEY).unwrap()
+                };
+                assert_eq!(footer(&extended), footer(&fresh));
+            }
+            assert!(!extended.join(STAGING_DIR_NAME).exists());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_extend_leaves_damaged_rows_to_regenerate() {
+        let dir = tempfile::tempdir().unwrap();
+        let theme = source_file(dir.path(), &["g1", "g2", "g3", "g4", "g5", "g6", "g7"]);
+        let extended = dir.path().join("extended");
+        let fresh = dir.path().join("fresh");
+        generate_zone_parquet_multi(zone_args(&theme, &extended, 2, None))
+            .await
+            .unwrap();
+        generate_zone_parquet_multi(zone_args(&theme, &fresh, 3, None))
+            .await
+            .unwrap();
+        let manifest = ZoneManifest::read(&extended).unwrap().unwrap();
+
+        // Rows 0..4 and 4..7 are re-cut into 0..3, 3..5 and 5..7
+        let plan = plan_extend(&manifest, &extended, 3).unwrap();
+        let slices: Vec<Vec<_>> = plan
+            .local
+            .iter()
+            .map(|local| {
+                local
+                    .slices
+                    .iter()
+                    .map(|s| (s.path.as_str(), s.offset, s.rows))
+                    .collect()
+            })
+            .collect();
+        assert_eq!(
+            slices,
+            vec![
+                vec![("zone/zone.1.parquet", 0, 3)],
+                vec![("zone/zone.1.parquet", 3, 1), ("zone/zone.2.parquet", 0, 1)],
+                vec![("zone/zone.2.parquet", 1, 2)],
+            ]
+        );
+        assert!(plan_extend(&manifest, &extended, 2).is_err());
+
+        std::fs::copy(
+            extended.join("zone/zone.1.parquet"),
+            extended.join("zone/zone.2.parquet"),
+        )
+        .unwrap();
+        let plan = extend_zone(&extended, 3, ParquetCompression::SNAPPY, 1024 * 1024).unwrap();
+        assert_eq!(plan.regenerate, vec![2, 3]);
+        let manifest = ZoneManifest::read(&extended).unwrap().unwrap();
+        assert_eq!(manifest.parts, 3);
+        assert_eq!(manifest.missing_parts(), vec![2, 3]);
+
+        for part in plan.regenerate {
+            generate_zone_parquet_single(zone_args(&theme, &extended, 3, Some(part)))
+                .await
+                .unwrap();
+        }
+        assert!(verify::compare_dirs(&extended, &fresh)
+            .unwrap()
+            .is_identical());
+    }
+
+    #[tokio::test]
+    async fn test_extend_refuses_balanced_parts() {
+        let dir = tempfile::tempdir().unwrap();
+        let theme = source_file(dir.path(), &["g1", "g2", "g3", "g4"]);
+        let output = dir.path().join("balanced");
+        generate_zone_parquet_multi(
+            zone_args(&theme, &output, 2, None).with_balance(crate::zone::Balance::Vertices, None),
+        )
+        .await
+        .unwrap();
+
+        let err = extend_zone(&output, 4, ParquetCompression::SNAPPY, 1024 * 1024).unwrap_err();
+        assert!(
+            err.to_string().contains("balanced by vertex count"),
+            "{err}"
+        );
+        assert_eq!(ZoneManifest::read(&output).unwrap().unwrap().parts, 2);
+    }
+}
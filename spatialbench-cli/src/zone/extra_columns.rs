@@ -0,0 +1,205 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Helper columns describing `z_boundary`, read from its WKB headers
+//!
+//! The vertex count and geometry type come from the WKB type codes and
+//! point counts alone, skipping over the ordinates, so no geometry is
+//! decoded. `profile` reads them the same way.
+
+use anyhow::{anyhow, Result};
+use arrow::compute::cast;
+use arrow_array::{Array, ArrayRef, BinaryArray, Int32Array, RecordBatch, StringArray};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use clap::ValueEnum;
+use std::sync::Arc;
+
+use super::batch::map_batches;
+use super::wkb;
+
+/// A column appended with `--extra-columns`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ExtraColumn {
+    /// `z_vertexcount`: the vertices of every ring and part
+    #[value(name = "vertexcount")]
+    VertexCount,
+    /// `z_geomtype`: the WKB geometry type, e.g. `MultiPolygon`
+    #[value(name = "geomtype")]
+    GeomType,
+}
+
+impl ExtraColumn {
+    /// Every extra column, in the order they are appended
+    pub const ALL: [Self; 2] = [Self::VertexCount, Self::GeomType];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::VertexCount => "z_vertexcount",
+            Self::GeomType => "z_geomtype",
+        }
+    }
+
+    fn field(self) -> Field {
+        match self {
+            Self::VertexCount => Field::new(self.name(), DataType::Int32, true),
+            Self::GeomType => Field::new(self.name(), DataType::Utf8, true),
+        }
+    }
+
+    fn values(self, geometries: &BinaryArray) -> ArrayRef {
+        match self {
+            Self::VertexCount => Arc::new(
+                geometries
+                    .iter()
+                    .map(|wkb| wkb.and_then(vertex_count))
+                    .collect::<Int32Array>(),
+            ),
+            Self::GeomType => Arc::new(
+                geometries
+                    .iter()
+                    .map(|wkb| wkb.and_then(geometry_type_name))
+                    .collect::<StringArray>(),
+            ),
+        }
+    }
+}
+
+/// The vertices of every ring and part of `wkb`, `None` for WKB that
+/// doesn't parse or has more than `i32::MAX`
+pub fn vertex_count(wkb: &[u8]) -> Option<i32> {
+    wkb::count_points(wkb).ok()?.try_into().ok()
+}
+
+/// The OGC name of the geometry type of `wkb`, e.g. `MultiPolygon`, from its
+/// header alone
+pub fn geometry_type_name(wkb: &[u8]) -> Option<&'static str> {
+    Some(match wkb::geometry_type(wkb).ok()? {
+        1 => "Point",
+        2 => "LineString",
+        3 => "Polygon",
+        4 => "MultiPoint",
+        5 => "MultiLineString",
+        6 => "MultiPolygon",
+        7 => "GeometryCollection",
+        _ => return None,
+    })
+}
+
+/// `columns` in the order they are appended
+fn ordered(columns: &[ExtraColumn]) -> impl Iterator<Item = ExtraColumn> + '_ {
+    ExtraColumn::ALL
+        .into_iter()
+        .filter(|column| columns.contains(column))
+}
+
+/// `schema` with the `columns` appended
+pub fn output_schema(schema: SchemaRef, columns: &[ExtraColumn]) -> SchemaRef {
+    let mut fields: Vec<Field> = schema.fields().iter().map(|f| f.as_ref().clone()).collect();
+    fields.extend(ordered(columns).map(ExtraColumn::field));
+    Arc::new(Schema::new_with_metadata(fields, schema.metadata().clone()))
+}
+
+/// Appends the `columns` of every row's `z_boundary`, null where the
+/// geometry is null or not valid WKB
+pub fn append_extra_columns(
+    batches: Vec<RecordBatch>,
+    columns: &[ExtraColumn],
+    threads: Option<usize>,
+) -> Result<Vec<RecordBatch>> {
+    if columns.is_empty() {
+        return Ok(batches);
+    }
+    map_batches(&batches, threads, |_, batch| {
+        let geometries = batch
+            .column_by_name("z_boundary")
+            .ok_or_else(|| anyhow!("Column z_boundary not found in zone batch"))?;
+        let geometries = cast(geometries, &DataType::Binary)?;
+        let geometries = geometries
+            .as_any()
+            .downcast_ref::<BinaryArray>()
+            .ok_or_else(|| anyhow!("Column z_boundary is not a binary column"))?;
+
+        let mut values = batch.columns().to_vec();
+        values.extend(ordered(columns).map(|column| column.values(geometries)));
+        Ok(RecordBatch::try_new(
+            output_schema(batch.schema(), columns),
+            values,
+        )?)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zone::fixtures::{wkb_donut, wkb_point, wkb_polygon};
+
+    #[test]
+    fn test_extra_columns() {
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "z_boundary",
+            DataType::Binary,
+            true,
+        )]));
+        let square = wkb_polygon(&[(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0), (0.0, 0.0)]);
+        let donut = wkb_donut();
+        let point = wkb_point(1.0, 2.0);
+        let batch = RecordBatch::try_new(
+            schema,
+            vec![Arc::new(BinaryArray::from(vec![
+                Some(square.as_slice()),
+                Some(donut.as_slice()),
+                Some(point.as_slice()),
+                None,
+                Some(b"not wkb".as_slice()),
+            ]))],
+        )
+        .unwrap();
+
+        // Listed in either order, the columns are appended in one
+        let columns = [ExtraColumn::GeomType, ExtraColumn::VertexCount];
+        let batches = append_extra_columns(vec![batch], &columns, None).unwrap();
+        let schema = batches[0].schema();
+        let names: Vec<_> = schema.fields().iter().map(|f| f.name().as_str()).collect();
+        assert_eq!(names, ["z_boundary", "z_vertexcount", "z_geomtype"]);
+
+        let counts = batches[0]
+            .column(1)
+            .as_any()
+            .downcast_ref::<Int32Array>()
+            .unwrap();
+        // The donut's two rings and its second part, five points each
+        assert_eq!(
+            counts.iter().collect::<Vec<_>>(),
+            vec![Some(5), Some(15), Some(1), None, None]
+        );
+        let types = batches[0]
+            .column(2)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(
+            types.iter().collect::<Vec<_>>(),
+            vec![
+                Some("Polygon"),
+                Some("MultiPolygon"),
+                Some("Point"),
+                None,
+                None
+            ]
+        );
+    }
+}
@@ -0,0 +1,273 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Templates for zone part file names
+//!
+//! Names can embed the scale factor as `sf<token>`, where the token is the
+//! shortest decimal form of the scale factor with `_` for the decimal point
+//! (`sf10`, `sf0_1`). Reading subcommands use it to refuse directories that
+//! mix files of different scale factors.
+
+use anyhow::{anyhow, Result};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Placeholder {
+    Table,
+    Part,
+    Parts,
+    ScaleFactor,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    /// A placeholder zero padded to `width` digits
+    Placeholder(Placeholder, usize),
+}
+
+/// A part file name such as `zone-part-{part:04}-of-{parts:04}.parquet`
+///
+/// `{table}`, `{part}`, `{parts}` and `{sf}` are replaced by the table name,
+/// the part number, the part count and the [`scale_factor_token`].
+/// `{part:04}` pads the number with zeros to four digits. `{{` and `}}` are
+/// literal braces.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FilenameTemplate {
+    template: String,
+    segments: Vec<Segment>,
+}
+
+impl FilenameTemplate {
+    /// `zone.sf<token>.parquet`, or `zone.sf<token>.<part>.parquet` when
+    /// generating more than one part
+    pub fn with_scale_factor(parts: i32) -> Self {
+        let template = match parts {
+            ..=1 => "{table}.sf{sf}.parquet",
+            _ => "{table}.sf{sf}.{part}.parquet",
+        };
+        template.parse().expect("built-in template is valid")
+    }
+
+    /// Whether the name differs from part to part
+    pub fn has_part(&self) -> bool {
+        self.segments
+            .iter()
+            .any(|s| matches!(s, Segment::Placeholder(Placeholder::Part, _)))
+    }
+
+    /// File name of `part` of `parts` parts of `table` at `scale_factor`
+    pub fn render(&self, table: &str, part: i32, parts: i32, scale_factor: f64) -> String {
+        self.segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Literal(text) => text.clone(),
+                Segment::Placeholder(Placeholder::Table, _) => table.to_string(),
+                Segment::Placeholder(Placeholder::Part, width) => format!("{part:0width$}"),
+                Segment::Placeholder(Placeholder::Parts, width) => format!("{parts:0width$}"),
+                Segment::Placeholder(Placeholder::ScaleFactor, _) => {
+                    scale_factor_token(scale_factor)
+                }
+            })
+            .collect()
+    }
+}
+
+impl FromStr for FilenameTemplate {
+    type Err = anyhow::Error;
+
+    fn from_str(template: &str) -> Result<Self> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let (spec, rest) = chars
+                        .as_str()
+                        .split_once('}')
+                        .ok_or_else(|| anyhow!("Unclosed {{ in filename template {template:?}"))?;
+                    chars = rest.chars();
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(parse_placeholder(spec, template)?);
+                }
+                '}' => return Err(anyhow!("Unmatched }} in filename template {template:?}")),
+                '/' | '\\' => {
+                    return Err(anyhow!(
+                        "Filename template {template:?} must be a file name, not a path"
+                    ))
+                }
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+        if segments.is_empty() {
+            return Err(anyhow!("Filename template is empty"));
+        }
+        Ok(Self {
+            template: template.to_string(),
+            segments,
+        })
+    }
+}
+
+fn parse_placeholder(spec: &str, template: &str) -> Result<Segment> {
+    let (name, format) = match spec.split_once(':') {
+        Some((name, format)) => (name, Some(format)),
+        None => (spec, None),
+    };
+    let placeholder = match name {
+        "table" => Placeholder::Table,
+        "part" => Placeholder::Part,
+        "parts" => Placeholder::Parts,
+        "sf" => Placeholder::ScaleFactor,
+        _ => {
+            return Err(anyhow!(
+                "Unknown placeholder {{{name}}} in filename template {template:?}, expected \
+                 {{table}}, {{part}}, {{parts}} or {{sf}}"
+            ))
+        }
+    };
+    let width = match format {
+        None => 0,
+        Some(format) if matches!(placeholder, Placeholder::Part | Placeholder::Parts) => format
+            .strip_prefix('0')
+            .and_then(|width| width.parse().ok())
+            .ok_or_else(|| {
+                anyhow!(
+                    "Invalid padding {{{spec}}} in filename template {template:?}, expected \
+                     e.g. {{{name}:04}}"
+                )
+            })?,
+        Some(_) => {
+            return Err(anyhow!(
+                "{{{name}}} takes no padding in filename template {template:?}"
+            ))
+        }
+    };
+    Ok(Segment::Placeholder(placeholder, width))
+}
+
+/// `{sf}` text of `scale_factor`, e.g. `10` or `0_1`
+pub fn scale_factor_token(scale_factor: f64) -> String {
+    format!("{scale_factor}").replace('.', "_")
+}
+
+/// Scale factor embedded in `file_name` as an `sf<token>` component
+/// between `.` or `-` separators
+pub fn embedded_scale_factor(file_name: &str) -> Option<f64> {
+    file_name.split(['.', '-']).find_map(|component| {
+        let token = component.strip_prefix("sf")?;
+        let digits = token.chars().all(|c| c.is_ascii_digit() || c == '_');
+        match digits && token.starts_with(|c: char| c.is_ascii_digit()) {
+            true => token.replacen('_', ".", 1).parse().ok(),
+            false => None,
+        }
+    })
+}
+
+impl fmt::Display for FilenameTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.template)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(template: &str, part: i32, parts: i32) -> String {
+        template
+            .parse::<FilenameTemplate>()
+            .unwrap()
+            .render("zone", part, parts, 10.0)
+    }
+
+    #[test]
+    fn test_render_padding_and_prefixes() {
+        assert_eq!(
+            render("{table}-part-{part:04}-of-{parts:04}.parquet", 1, 16),
+            "zone-part-0001-of-0016.parquet"
+        );
+        assert_eq!(render("{table}_{part}.parquet", 12, 16), "zone_12.parquet");
+        // Numbers wider than the padding are kept whole
+        assert_eq!(render("p{part:02}.parquet", 123, 200), "p123.parquet");
+        assert_eq!(render("{{{part}}}.parquet", 3, 4), "{3}.parquet");
+        assert_eq!(render("all.parquet", 1, 1), "all.parquet");
+        assert_eq!(
+            render("{table}-sf{sf}-{part}.parquet", 2, 4),
+            "zone-sf10-2.parquet"
+        );
+    }
+
+    #[test]
+    fn test_scale_factor_names() {
+        for (scale_factor, token) in [(10.0, "10"), (0.1, "0_1"), (1.5, "1_5"), (0.001, "0_001")] {
+            assert_eq!(scale_factor_token(scale_factor), token);
+            let name = FilenameTemplate::with_scale_factor(3).render("zone", 2, 3, scale_factor);
+            assert_eq!(name, format!("zone.sf{token}.2.parquet"));
+            assert_eq!(embedded_scale_factor(&name), Some(scale_factor));
+        }
+        assert_eq!(
+            FilenameTemplate::with_scale_factor(1).render("zone", 1, 1, 1.0),
+            "zone.sf1.parquet"
+        );
+        assert_eq!(embedded_scale_factor("zone-sf0_1-0001.parquet"), Some(0.1));
+        assert_eq!(embedded_scale_factor("zone.1.parquet"), None);
+        assert_eq!(embedded_scale_factor("zone.sfx.parquet"), None);
+        assert_eq!(embedded_scale_factor("zone.sf_1.parquet"), None);
+    }
+
+    #[test]
+    fn test_invalid_templates() {
+        let error = |template: &str| {
+            template
+                .parse::<FilenameTemplate>()
+                .unwrap_err()
+                .to_string()
+        };
+        assert!(error("{shard}.parquet").contains("Unknown placeholder {shard}"));
+        assert!(error("{part:4}.parquet").contains("Invalid padding {part:4}"));
+        assert!(error("{part:0x}.parquet").contains("Invalid padding"));
+        assert!(error("{table:04}.parquet").contains("takes no padding"));
+        assert!(error("{sf:02}.parquet").contains("{sf} takes no padding"));
+        assert!(error("{part.parquet").contains("Unclosed {"));
+        assert!(error("part}.parquet").contains("Unmatched }"));
+        assert!(error("zone/{part}.parquet").contains("not a path"));
+        assert!(error("").contains("empty"));
+    }
+
+    #[test]
+    fn test_has_part() {
+        let template = |t: &str| t.parse::<FilenameTemplate>().unwrap();
+        assert!(template("{table}.{part:03}.parquet").has_part());
+        assert!(!template("{table}-of-{parts}.parquet").has_part());
+    }
+}
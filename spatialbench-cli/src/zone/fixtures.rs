@@ -0,0 +1,215 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Small Overture-shaped inputs for zone unit tests
+
+use arrow_array::builder::{
+    Float64Builder, ListBuilder, MapBuilder, MapFieldNames, StringBuilder, StructBuilder,
+};
+use arrow_array::{
+    Array, ArrayRef, BinaryArray, BooleanArray, RecordBatch, StringArray, StructArray,
+};
+use arrow_schema::{DataType, Field, Fields, Schema};
+use parquet::arrow::ArrowWriter;
+use std::path::Path;
+use std::sync::Arc;
+
+/// One Overture division row
+pub struct SourceRow {
+    pub id: &'static str,
+    pub country: &'static str,
+    pub region: &'static str,
+    pub name: &'static str,
+    /// `names.common` entries as (language, name)
+    pub common_names: Vec<(&'static str, &'static str)>,
+    pub subtype: &'static str,
+    pub geometry: Vec<u8>,
+}
+
+impl SourceRow {
+    pub fn new(id: &'static str, subtype: &'static str) -> Self {
+        Self {
+            id,
+            country: "US",
+            region: "US-WA",
+            name: id,
+            common_names: Vec::new(),
+            subtype,
+            geometry: wkb_polygon(&[(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0), (0.0, 0.0)]),
+        }
+    }
+}
+
+/// Little-endian WKB for a point
+pub fn wkb_point(x: f64, y: f64) -> Vec<u8> {
+    let mut wkb = vec![1u8];
+    wkb.extend_from_slice(&1u32.to_le_bytes());
+    wkb.extend_from_slice(&x.to_le_bytes());
+    wkb.extend_from_slice(&y.to_le_bytes());
+    wkb
+}
+
+/// Little-endian WKB for a single ring polygon
+pub fn wkb_polygon(ring: &[(f64, f64)]) -> Vec<u8> {
+    let mut wkb = vec![1u8];
+    wkb.extend_from_slice(&3u32.to_le_bytes());
+    wkb.extend_from_slice(&1u32.to_le_bytes());
+    wkb.extend_from_slice(&(ring.len() as u32).to_le_bytes());
+    for (x, y) in ring {
+        wkb.extend_from_slice(&x.to_le_bytes());
+        wkb.extend_from_slice(&y.to_le_bytes());
+    }
+    wkb
+}
+
+/// Little-endian WKB for a multipolygon, each polygon given as its exterior
+/// ring followed by its holes
+pub fn wkb_multipolygon(polygons: &[&[&[(f64, f64)]]]) -> Vec<u8> {
+    let mut wkb = vec![1u8];
+    wkb.extend_from_slice(&6u32.to_le_bytes());
+    wkb.extend_from_slice(&(polygons.len() as u32).to_le_bytes());
+    for rings in polygons {
+        wkb.push(1);
+        wkb.extend_from_slice(&3u32.to_le_bytes());
+        wkb.extend_from_slice(&(rings.len() as u32).to_le_bytes());
+        for ring in rings.iter() {
+            wkb.extend_from_slice(&(ring.len() as u32).to_le_bytes());
+            for (x, y) in ring.iter() {
+                wkb.extend_from_slice(&x.to_le_bytes());
+                wkb.extend_from_slice(&y.to_le_bytes());
+            }
+        }
+    }
+    wkb
+}
+
+/// A 4×4 square with a 2×2 hole, and a separate 2×2 square
+pub fn wkb_donut() -> Vec<u8> {
+    wkb_multipolygon(&[
+        &[
+            &[(0.0, 0.0), (4.0, 0.0), (4.0, 4.0), (0.0, 4.0), (0.0, 0.0)],
+            &[(1.0, 1.0), (1.0, 3.0), (3.0, 3.0), (3.0, 1.0), (1.0, 1.0)],
+        ],
+        &[&[
+            (10.0, 0.0),
+            (12.0, 0.0),
+            (12.0, 2.0),
+            (10.0, 2.0),
+            (10.0, 0.0),
+        ]],
+    ])
+}
+
+/// Builds a batch shaped like Overture `division_area` (`with_is_land`) or
+/// `division` rows
+pub fn source_batch(rows: &[SourceRow], with_is_land: bool) -> RecordBatch {
+    let mut common = MapBuilder::new(
+        Some(MapFieldNames {
+            entry: "key_value".to_string(),
+            key: "key".to_string(),
+            value: "value".to_string(),
+        }),
+        StringBuilder::new(),
+        StringBuilder::new(),
+    );
+    for row in rows {
+        for (language, name) in &row.common_names {
+            common.keys().append_value(language);
+            common.values().append_value(name);
+        }
+        common.append(!row.common_names.is_empty()).unwrap();
+    }
+    let common = common.finish();
+
+    let names_fields = Fields::from(vec![
+        Field::new("primary", DataType::Utf8, true),
+        Field::new("common", common.data_type().clone(), true),
+    ]);
+    let names = StructArray::new(
+        names_fields.clone(),
+        vec![
+            Arc::new(StringArray::from_iter_values(rows.iter().map(|r| r.name))) as ArrayRef,
+            Arc::new(common),
+        ],
+        None,
+    );
+
+    let mut fields = vec![
+        Field::new("id", DataType::Utf8, true),
+        Field::new("country", DataType::Utf8, true),
+        Field::new("region", DataType::Utf8, true),
+        Field::new("names", DataType::Struct(names_fields), true),
+        Field::new("subtype", DataType::Utf8, true),
+        Field::new("geometry", DataType::Binary, true),
+    ];
+    let mut columns: Vec<ArrayRef> = vec![
+        Arc::new(StringArray::from_iter_values(rows.iter().map(|r| r.id))),
+        Arc::new(StringArray::from_iter_values(
+            rows.iter().map(|r| r.country),
+        )),
+        Arc::new(StringArray::from_iter_values(rows.iter().map(|r| r.region))),
+        Arc::new(names),
+        Arc::new(StringArray::from_iter_values(
+            rows.iter().map(|r| r.subtype),
+        )),
+        Arc::new(BinaryArray::from_iter_values(
+            rows.iter().map(|r| r.geometry.as_slice()),
+        )),
+    ];
+    if with_is_land {
+        fields.push(Field::new("is_land", DataType::Boolean, true));
+        columns.push(Arc::new(BooleanArray::from(vec![true; rows.len()])));
+    }
+
+    RecordBatch::try_new(Arc::new(Schema::new(fields)), columns).unwrap()
+}
+
+/// Separated GeoArrow polygons of `rings`, each the single ring of a polygon
+pub fn geoarrow_polygons(rings: &[&[(f64, f64)]]) -> ArrayRef {
+    let fields = Fields::from(vec![
+        Field::new("x", DataType::Float64, false),
+        Field::new("y", DataType::Float64, false),
+    ]);
+    let mut polygons = ListBuilder::new(ListBuilder::new(StructBuilder::from_fields(fields, 0)));
+    for ring in rings {
+        let coords = polygons.values().values();
+        for (x, y) in ring.iter() {
+            coords
+                .field_builder::<Float64Builder>(0)
+                .unwrap()
+                .append_value(*x);
+            coords
+                .field_builder::<Float64Builder>(1)
+                .unwrap()
+                .append_value(*y);
+            coords.append(true);
+        }
+        polygons.values().append(true);
+        polygons.append(true);
+    }
+    Arc::new(polygons.finish())
+}
+
+pub fn write_parquet(path: &Path, batch: &RecordBatch) {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).unwrap();
+    }
+    let file = std::fs::File::create(path).unwrap();
+    let mut writer = ArrowWriter::try_new(file, batch.schema(), None).unwrap();
+    writer.write(batch).unwrap();
+    writer.close().unwrap();
+}
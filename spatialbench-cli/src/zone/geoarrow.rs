@@ -0,0 +1,478 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Reading of source geometry in the GeoArrow native encodings as WKB
+
+use anyhow::{anyhow, Context, Result};
+use arrow_array::builder::BinaryBuilder;
+use arrow_array::{
+    Array, ArrayRef, FixedSizeListArray, Float64Array, LargeListArray, ListArray, StructArray,
+};
+use arrow_schema::DataType;
+use datafusion::logical_expr::{create_udf, ColumnarValue, ScalarUDF, Volatility};
+use datafusion::prelude::*;
+use log::info;
+use parquet::file::metadata::ParquetMetaData;
+use std::ops::Range;
+use std::sync::Arc;
+
+use super::bbox::GEO_METADATA_KEY;
+use super::estimate::read_footers;
+
+const GEOMETRY_COLUMN: &str = "geometry";
+
+/// Encoding of a GeoParquet geometry column, as its `encoding` in the `geo`
+/// metadata names it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SourceEncoding {
+    Wkb,
+    Point,
+    LineString,
+    Polygon,
+    MultiPoint,
+    MultiLineString,
+    MultiPolygon,
+}
+
+impl SourceEncoding {
+    fn parse(encoding: &str) -> Result<Self> {
+        Ok(match encoding.to_ascii_lowercase().as_str() {
+            "wkb" => Self::Wkb,
+            "point" => Self::Point,
+            "linestring" => Self::LineString,
+            "polygon" => Self::Polygon,
+            "multipoint" => Self::MultiPoint,
+            "multilinestring" => Self::MultiLineString,
+            "multipolygon" => Self::MultiPolygon,
+            other => return Err(anyhow!("unsupported GeoParquet geometry encoding {other}")),
+        })
+    }
+
+    /// The encoding of the `geometry` column the `geo` metadata of `footer`
+    /// declares, WKB when the footer has no such metadata
+    pub fn from_footer(footer: &ParquetMetaData) -> Result<Self> {
+        let geo = footer
+            .file_metadata()
+            .key_value_metadata()
+            .into_iter()
+            .flatten()
+            .find(|kv| kv.key == GEO_METADATA_KEY)
+            .and_then(|kv| kv.value.as_deref());
+        let Some(geo) = geo else {
+            return Ok(Self::Wkb);
+        };
+        let geo: serde_json::Value =
+            serde_json::from_str(geo).context("Invalid GeoParquet geo metadata")?;
+        match geo["columns"][GEOMETRY_COLUMN]["encoding"].as_str() {
+            Some(encoding) => Self::parse(encoding),
+            None => Ok(Self::Wkb),
+        }
+    }
+
+    /// WKB type code of the geometries in this encoding
+    fn wkb_type(self) -> u32 {
+        match self {
+            Self::Wkb => 0,
+            Self::Point => 1,
+            Self::LineString => 2,
+            Self::Polygon => 3,
+            Self::MultiPoint => 4,
+            Self::MultiLineString => 5,
+            Self::MultiPolygon => 6,
+        }
+    }
+}
+
+/// The encoding of the `geometry` of the Parquet sources at `locations`,
+/// read from the `geo` metadata of the first footer
+pub async fn source_encoding(locations: &[String]) -> Result<SourceEncoding> {
+    for location in locations {
+        if let Some(footer) = read_footers(location).await?.first() {
+            return SourceEncoding::from_footer(footer);
+        }
+    }
+    Ok(SourceEncoding::Wkb)
+}
+
+/// `df` with a `geometry` in `encoding` replaced by its WKB, as the
+/// transforms read it
+pub fn geometry_as_wkb(df: DataFrame, encoding: SourceEncoding) -> Result<DataFrame> {
+    if encoding == SourceEncoding::Wkb {
+        return Ok(df);
+    }
+    let field = df.schema().field_with_unqualified_name(GEOMETRY_COLUMN)?;
+    info!("Converting the {encoding:?} GeoArrow geometry of the source to WKB");
+    let udf = geoarrow_to_wkb_udf(encoding, field.data_type().clone());
+    Ok(df.with_column(GEOMETRY_COLUMN, udf.call(vec![col(GEOMETRY_COLUMN)]))?)
+}
+
+/// `geoarrow_to_wkb(geometry)`: the little-endian ISO WKB of a geometry in
+/// the native `encoding`, null when the geometry is null
+fn geoarrow_to_wkb_udf(encoding: SourceEncoding, input: DataType) -> ScalarUDF {
+    create_udf(
+        "geoarrow_to_wkb",
+        vec![input],
+        DataType::Binary,
+        Volatility::Immutable,
+        Arc::new(move |args: &[ColumnarValue]| {
+            let arrays = ColumnarValue::values_to_arrays(args)?;
+            let wkb = to_wkb(&arrays[0], encoding)
+                .map_err(|e| datafusion::error::DataFusionError::Execution(e.to_string()))?;
+            Ok(ColumnarValue::Array(Arc::new(wkb)))
+        }),
+    )
+}
+
+/// The WKB of every geometry of `array`, in the native `encoding`
+fn to_wkb(array: &ArrayRef, encoding: SourceEncoding) -> Result<arrow_array::BinaryArray> {
+    let mut builder = BinaryBuilder::new();
+    let coords = Coords::of(coords_array(array, encoding)?)?;
+    let mut wkb = Vec::new();
+    for row in 0..array.len() {
+        if array.is_null(row) {
+            builder.append_null();
+            continue;
+        }
+        wkb.clear();
+        write_geometry(&mut wkb, array, &coords, row, encoding)?;
+        builder.append_value(&wkb);
+    }
+    Ok(builder.finish())
+}
+
+fn write_geometry(
+    out: &mut Vec<u8>,
+    array: &ArrayRef,
+    coords: &Coords,
+    row: usize,
+    encoding: SourceEncoding,
+) -> Result<()> {
+    use SourceEncoding::*;
+    write_header(out, encoding.wkb_type(), coords.dims_code);
+    match encoding {
+        Point => {
+            coords.write(out, row);
+            Ok(())
+        }
+        LineString => write_points(out, coords, list_range(array, row)?),
+        Polygon => write_rings(out, coords, array, row),
+        MultiPoint => {
+            let points = list_range(array, row)?;
+            out.extend_from_slice(&(points.len() as u32).to_le_bytes());
+            for point in points {
+                write_header(out, Point.wkb_type(), coords.dims_code);
+                coords.write(out, point);
+            }
+            Ok(())
+        }
+        MultiLineString => {
+            let lines = list_range(array, row)?;
+            let child = list_values(array)?;
+            out.extend_from_slice(&(lines.len() as u32).to_le_bytes());
+            for line in lines {
+                write_header(out, LineString.wkb_type(), coords.dims_code);
+                write_points(out, coords, list_range(child, line)?)?;
+            }
+            Ok(())
+        }
+        MultiPolygon => {
+            let polygons = list_range(array, row)?;
+            let child = list_values(array)?;
+            out.extend_from_slice(&(polygons.len() as u32).to_le_bytes());
+            for polygon in polygons {
+                write_header(out, Polygon.wkb_type(), coords.dims_code);
+                write_rings(out, coords, child, polygon)?;
+            }
+            Ok(())
+        }
+        Wkb => Err(anyhow!("WKB geometry needs no conversion")),
+    }
+}
+
+fn write_header(out: &mut Vec<u8>, wkb_type: u32, dims_code: u32) {
+    out.push(1);
+    out.extend_from_slice(&(wkb_type + dims_code).to_le_bytes());
+}
+
+fn write_points(out: &mut Vec<u8>, coords: &Coords, points: Range<usize>) -> Result<()> {
+    out.extend_from_slice(&(points.len() as u32).to_le_bytes());
+    for point in points {
+        coords.write(out, point);
+    }
+    Ok(())
+}
+
+fn write_rings(out: &mut Vec<u8>, coords: &Coords, array: &ArrayRef, row: usize) -> Result<()> {
+    let rings = list_range(array, row)?;
+    let child = list_values(array)?;
+    out.extend_from_slice(&(rings.len() as u32).to_le_bytes());
+    for ring in rings {
+        write_points(out, coords, list_range(child, ring)?)?;
+    }
+    Ok(())
+}
+
+/// The array of the coordinates below the list nesting of `encoding`
+fn coords_array(array: &ArrayRef, encoding: SourceEncoding) -> Result<&ArrayRef> {
+    use SourceEncoding::*;
+    let depth = match encoding {
+        Wkb | Point => 0,
+        LineString | MultiPoint => 1,
+        Polygon | MultiLineString => 2,
+        MultiPolygon => 3,
+    };
+    let mut coords = array;
+    for _ in 0..depth {
+        coords = list_values(coords)?;
+    }
+    Ok(coords)
+}
+
+fn list_values(array: &ArrayRef) -> Result<&ArrayRef> {
+    if let Some(list) = array.as_any().downcast_ref::<ListArray>() {
+        return Ok(list.values());
+    }
+    if let Some(list) = array.as_any().downcast_ref::<LargeListArray>() {
+        return Ok(list.values());
+    }
+    Err(anyhow!(
+        "expected a list in GeoArrow geometry, found {}",
+        array.data_type()
+    ))
+}
+
+/// The child indices of the list at `row` of `array`
+fn list_range(array: &ArrayRef, row: usize) -> Result<Range<usize>> {
+    if let Some(list) = array.as_any().downcast_ref::<ListArray>() {
+        let offsets = list.value_offsets();
+        return Ok(offsets[row] as usize..offsets[row + 1] as usize);
+    }
+    if let Some(list) = array.as_any().downcast_ref::<LargeListArray>() {
+        let offsets = list.value_offsets();
+        return Ok(offsets[row] as usize..offsets[row + 1] as usize);
+    }
+    Err(anyhow!(
+        "expected a list in GeoArrow geometry, found {}",
+        array.data_type()
+    ))
+}
+
+/// GeoArrow coordinates, separated into a struct of one array per dimension
+/// or interleaved in fixed size lists
+struct Coords<'a> {
+    /// One array per ordinate, or the single interleaved array
+    ordinates: Vec<&'a Float64Array>,
+    /// Ordinates per coordinate
+    dims: usize,
+    interleaved: bool,
+    /// Added to the WKB type code: 1000 for Z, 2000 for M, 3000 for ZM
+    dims_code: u32,
+}
+
+impl<'a> Coords<'a> {
+    fn of(array: &'a ArrayRef) -> Result<Self> {
+        let float = |array: &'a ArrayRef| {
+            array
+                .as_any()
+                .downcast_ref::<Float64Array>()
+                .ok_or_else(|| {
+                    anyhow!(
+                        "expected Float64 GeoArrow ordinates, found {}",
+                        array.data_type()
+                    )
+                })
+        };
+        if let Some(coords) = array.as_any().downcast_ref::<StructArray>() {
+            let names: String = coords.fields().iter().map(|f| f.name().as_str()).collect();
+            return Ok(Self {
+                ordinates: coords.columns().iter().map(float).collect::<Result<_>>()?,
+                dims: coords.num_columns(),
+                interleaved: false,
+                dims_code: dims_code(&names, coords.num_columns()),
+            });
+        }
+        if let Some(coords) = array.as_any().downcast_ref::<FixedSizeListArray>() {
+            let DataType::FixedSizeList(field, _) = coords.data_type() else {
+                unreachable!()
+            };
+            let dims = coords.value_length() as usize;
+            return Ok(Self {
+                ordinates: vec![float(coords.values())?],
+                dims,
+                interleaved: true,
+                dims_code: dims_code(field.name(), dims),
+            });
+        }
+        Err(anyhow!(
+            "expected GeoArrow coordinates, found {}",
+            array.data_type()
+        ))
+    }
+
+    fn write(&self, out: &mut Vec<u8>, point: usize) {
+        for dim in 0..self.dims {
+            let value = match self.interleaved {
+                true => self.ordinates[0].value(point * self.dims + dim),
+                false => self.ordinates[dim].value(point),
+            };
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+}
+
+/// The WKB type code offset of coordinates with dimension names `names`,
+/// e.g. `xym`, falling back to XYZ for three unnamed dimensions
+fn dims_code(names: &str, dims: usize) -> u32 {
+    let names = names.to_ascii_lowercase();
+    match dims {
+        4 => 3000,
+        3 if names.ends_with('m') => 2000,
+        3 => 1000,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zone::fixtures::{wkb_multipolygon, wkb_point, wkb_polygon};
+    use arrow_array::builder::{Float64Builder, ListBuilder, StructBuilder};
+    use arrow_array::BinaryArray;
+    use arrow_schema::{Field, Fields};
+
+    fn xy_fields() -> Fields {
+        Fields::from(vec![
+            Field::new("x", DataType::Float64, false),
+            Field::new("y", DataType::Float64, false),
+        ])
+    }
+
+    fn append_xy(coords: &mut StructBuilder, (x, y): (f64, f64)) {
+        coords
+            .field_builder::<Float64Builder>(0)
+            .unwrap()
+            .append_value(x);
+        coords
+            .field_builder::<Float64Builder>(1)
+            .unwrap()
+            .append_value(y);
+        coords.append(true);
+    }
+
+    /// Polygons of rings of points
+    type MultiPolygon<'a> = &'a [&'a [&'a [(f64, f64)]]];
+
+    /// Separated GeoArrow multipolygons
+    fn multipolygons(rows: &[Option<MultiPolygon>]) -> ArrayRef {
+        let coords = StructBuilder::from_fields(xy_fields(), 0);
+        let mut builder = ListBuilder::new(ListBuilder::new(ListBuilder::new(coords)));
+        for row in rows {
+            let Some(polygons) = row else {
+                builder.append(false);
+                continue;
+            };
+            for rings in polygons.iter() {
+                for ring in rings.iter() {
+                    for point in ring.iter() {
+                        append_xy(builder.values().values().values(), *point);
+                    }
+                    builder.values().values().append(true);
+                }
+                builder.values().append(true);
+            }
+            builder.append(true);
+        }
+        Arc::new(builder.finish())
+    }
+
+    #[test]
+    fn test_separated_multipolygons_to_wkb() {
+        let square: &[(f64, f64)] = &[(0.0, 0.0), (2.0, 0.0), (2.0, 2.0), (0.0, 2.0), (0.0, 0.0)];
+        let hole: &[(f64, f64)] = &[(0.5, 0.5), (0.5, 1.0), (1.0, 1.0), (0.5, 0.5)];
+        let other: &[(f64, f64)] = &[(5.0, 5.0), (6.0, 5.0), (6.0, 6.0), (5.0, 5.0)];
+        let rows: &[Option<MultiPolygon>] = &[Some(&[&[square, hole], &[other]]), None];
+        let wkb = to_wkb(&multipolygons(rows), SourceEncoding::MultiPolygon).unwrap();
+        assert_eq!(wkb.value(0), wkb_multipolygon(&[&[square, hole], &[other]]));
+        assert!(wkb.is_null(1));
+    }
+
+    #[test]
+    fn test_interleaved_points_and_polygons_to_wkb() {
+        let mut points = arrow_array::builder::FixedSizeListBuilder::new(Float64Builder::new(), 2)
+            .with_field(Arc::new(Field::new("xy", DataType::Float64, false)));
+        for (x, y) in [(1.5, -2.0), (3.0, 4.0)] {
+            points.values().append_value(x);
+            points.values().append_value(y);
+            points.append(true);
+        }
+        let points: ArrayRef = Arc::new(points.finish());
+        let wkb = to_wkb(&points, SourceEncoding::Point).unwrap();
+        let expected = BinaryArray::from_iter_values([wkb_point(1.5, -2.0), wkb_point(3.0, 4.0)]);
+        assert_eq!(wkb, expected);
+
+        let ring = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 0.0)];
+        let mut polygons = ListBuilder::new(ListBuilder::new(
+            arrow_array::builder::FixedSizeListBuilder::new(Float64Builder::new(), 2),
+        ));
+        for (x, y) in ring {
+            polygons.values().values().values().append_value(x);
+            polygons.values().values().values().append_value(y);
+            polygons.values().values().append(true);
+        }
+        polygons.values().append(true);
+        polygons.append(true);
+        let polygons: ArrayRef = Arc::new(polygons.finish());
+        let wkb = to_wkb(&polygons, SourceEncoding::Polygon).unwrap();
+        assert_eq!(wkb.value(0), wkb_polygon(&ring));
+    }
+
+    #[test]
+    fn test_xyz_coordinates_write_iso_z_codes() {
+        let fields = Fields::from(vec![
+            Field::new("x", DataType::Float64, false),
+            Field::new("y", DataType::Float64, false),
+            Field::new("z", DataType::Float64, false),
+        ]);
+        let mut coords = StructBuilder::from_fields(fields, 1);
+        for value in [1.0, 2.0, 3.0] {
+            let index = value as usize - 1;
+            coords
+                .field_builder::<Float64Builder>(index)
+                .unwrap()
+                .append_value(value);
+        }
+        coords.append(true);
+        let points: ArrayRef = Arc::new(coords.finish());
+        let wkb = to_wkb(&points, SourceEncoding::Point).unwrap();
+        let parsed = crate::zone::wkb::NormalizedWkb::parse(wkb.value(0)).unwrap();
+        assert_eq!(parsed.structure, vec![1001]);
+        assert_eq!(parsed.coords, vec![1.0, 2.0, 3.0]);
+    }
+
+    #[test]
+    fn test_encoding_from_geo_metadata() {
+        assert_eq!(SourceEncoding::parse("WKB").unwrap(), SourceEncoding::Wkb);
+        assert_eq!(
+            SourceEncoding::parse("multipolygon").unwrap(),
+            SourceEncoding::MultiPolygon
+        );
+        assert!(SourceEncoding::parse("geoarrow.box")
+            .unwrap_err()
+            .to_string()
+            .contains("unsupported"));
+    }
+}
@@ -0,0 +1,112 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Encoding of the written `z_boundary` column
+//!
+//! The batch transforms, partitioning and bounds all read `z_boundary` as
+//! WKB; a GeoJSON column is only encoded as the files are written.
+
+use anyhow::{anyhow, Result};
+use arrow::compute::cast;
+use arrow_array::{Array, ArrayRef, BinaryArray, StringArray};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use clap::ValueEnum;
+use geozero::wkb::Wkb;
+use geozero::ToJson;
+use std::sync::Arc;
+
+const GEOMETRY_COLUMN: &str = "z_boundary";
+
+/// How `z_boundary` is written
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum GeometryEncoding {
+    /// ISO WKB bytes
+    #[default]
+    Wkb,
+    /// GeoJSON geometry objects as UTF-8 strings, e.g.
+    /// `{"type": "Polygon", "coordinates": [...]}`
+    Geojson,
+}
+
+impl GeometryEncoding {
+    /// `schema` with `z_boundary` of the type this encoding writes
+    pub fn output_schema(self, schema: SchemaRef) -> SchemaRef {
+        if self == Self::Wkb {
+            return schema;
+        }
+        let fields: Vec<Field> = schema
+            .fields()
+            .iter()
+            .map(|field| match field.name() == GEOMETRY_COLUMN {
+                true => field.as_ref().clone().with_data_type(DataType::Utf8),
+                false => field.as_ref().clone(),
+            })
+            .collect();
+        Arc::new(Schema::new_with_metadata(fields, schema.metadata().clone()))
+    }
+}
+
+/// `wkb`, a binary `z_boundary` column, as GeoJSON geometry strings. Nulls
+/// stay null and WKB that doesn't parse is an error.
+pub fn encode_geojson(wkb: &dyn Array) -> Result<ArrayRef> {
+    let wkb = cast(wkb, &DataType::Binary)?;
+    let wkb = wkb
+        .as_any()
+        .downcast_ref::<BinaryArray>()
+        .ok_or_else(|| anyhow!("Column {GEOMETRY_COLUMN} is not a binary column"))?;
+    let geojson = wkb
+        .iter()
+        .enumerate()
+        .map(|(row, value)| {
+            value
+                .map(|value| {
+                    Wkb(value)
+                        .to_json()
+                        .map_err(|e| anyhow!("Invalid {GEOMETRY_COLUMN} WKB in row {row}: {e}"))
+                })
+                .transpose()
+        })
+        .collect::<Result<StringArray>>()?;
+    Ok(Arc::new(geojson))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zone::fixtures::wkb_polygon;
+
+    #[test]
+    fn test_polygon_as_geojson() {
+        let square = wkb_polygon(&[(0.0, 0.0), (1.5, 0.0), (1.5, 1.0), (0.0, 1.0), (0.0, 0.0)]);
+        let wkb = BinaryArray::from(vec![Some(square.as_slice()), None]);
+        let encoded = encode_geojson(&wkb).unwrap();
+        let encoded = encoded.as_any().downcast_ref::<StringArray>().unwrap();
+
+        let geometry: serde_json::Value = serde_json::from_str(encoded.value(0)).unwrap();
+        assert_eq!(
+            geometry,
+            serde_json::json!({
+                "type": "Polygon",
+                "coordinates": [[[0, 0], [1.5, 0], [1.5, 1], [0, 1], [0, 0]]],
+            })
+        );
+        assert!(encoded.is_null(1));
+
+        let invalid = BinaryArray::from(vec![b"not wkb".as_slice()]);
+        assert!(encode_geojson(&invalid).is_err());
+    }
+}
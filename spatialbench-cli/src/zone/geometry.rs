@@ -0,0 +1,238 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! ISO WKB normalization of `z_boundary` geometries
+
+use anyhow::{anyhow, Result};
+use arrow::compute::{cast, filter_record_batch};
+use arrow_array::{Array, BooleanArray, RecordBatch, StringArray};
+use arrow_schema::DataType;
+use log::{debug, info, warn};
+
+use super::batch::{map_batches, map_binary_column};
+use super::config::OnBadGeometry;
+use super::wkb::to_iso_wkb;
+
+#[derive(Debug, Default, PartialEq)]
+pub struct WkbReport {
+    /// Values whose bytes changed
+    pub rewritten: usize,
+    /// Values that are not valid WKB
+    pub malformed: usize,
+}
+
+/// Rewrites `z_boundary` to little-endian ISO WKB without SRID.
+///
+/// Malformed values fail the run, drop their row or are written unchanged
+/// according to `on_bad_geometry`. Null geometries are left alone. Batches
+/// are rewritten on up to `threads` threads.
+pub fn normalize_wkb_batches(
+    batches: Vec<RecordBatch>,
+    keep_zm: bool,
+    on_bad_geometry: OnBadGeometry,
+    threads: Option<usize>,
+) -> Result<(Vec<RecordBatch>, WkbReport)> {
+    let mut report = WkbReport::default();
+
+    let batches = map_batches(&batches, threads, |index, batch| {
+        let mut batch_report = WkbReport::default();
+        // Rows of the non-null values, in the order they are mapped
+        let mut rows = batch
+            .column_by_name("z_boundary")
+            .map(|geometries| (0..geometries.len()).filter(|&i| geometries.is_valid(i)))
+            .into_iter()
+            .flatten();
+        // Validity of each non-null value in row order
+        let mut valid = Vec::new();
+        let mapped = map_binary_column(batch, "z_boundary", |wkb| {
+            let row = rows.next().unwrap_or_default();
+            match to_iso_wkb(wkb, keep_zm) {
+                Ok(iso) => {
+                    valid.push(true);
+                    if iso == wkb {
+                        return Ok(None);
+                    }
+                    batch_report.rewritten += 1;
+                    Ok(Some(iso))
+                }
+                Err(e) if on_bad_geometry == OnBadGeometry::Fail => Err(anyhow!(
+                    "Malformed z_boundary WKB at {}: {e}",
+                    describe_row(batch, index, row)
+                )),
+                Err(e) => {
+                    debug!(
+                        "Malformed z_boundary WKB at {}: {e}",
+                        describe_row(batch, index, row)
+                    );
+                    valid.push(false);
+                    batch_report.malformed += 1;
+                    Ok(None)
+                }
+            }
+        })?;
+
+        if on_bad_geometry != OnBadGeometry::Skip || valid.iter().all(|v| *v) {
+            return Ok((mapped, batch_report));
+        }
+        let geometries = mapped.column_by_name("z_boundary").unwrap();
+        let mut valid = valid.into_iter();
+        let keep: BooleanArray = (0..geometries.len())
+            .map(|i| Some(geometries.is_null(i) || valid.next().unwrap()))
+            .collect();
+        Ok((filter_record_batch(&mapped, &keep)?, batch_report))
+    })?
+    .into_iter()
+    .map(|(batch, batch_report)| {
+        report.rewritten += batch_report.rewritten;
+        report.malformed += batch_report.malformed;
+        batch
+    })
+    .collect();
+
+    if report.malformed > 0 {
+        match on_bad_geometry {
+            OnBadGeometry::Skip => warn!(
+                "Dropped {} row(s) with malformed z_boundary WKB",
+                report.malformed
+            ),
+            _ => warn!(
+                "Wrote {} malformed z_boundary value(s) unchanged",
+                report.malformed
+            ),
+        }
+    }
+    info!(
+        "Rewrote {} z_boundary value(s) as ISO WKB",
+        report.rewritten
+    );
+
+    Ok((batches, report))
+}
+
+/// Names row `row` of batch `index` by its `z_gersid` when the batch has
+/// one, and by its position
+fn describe_row(batch: &RecordBatch, index: usize, row: usize) -> String {
+    let gersid = batch
+        .column_by_name("z_gersid")
+        .and_then(|ids| cast(ids, &DataType::Utf8).ok())
+        .and_then(|ids| {
+            let ids = ids.as_any().downcast_ref::<StringArray>()?;
+            ids.is_valid(row).then(|| ids.value(row).to_string())
+        });
+    match gersid {
+        Some(gersid) => format!("z_gersid {gersid} (batch {index}, row {row})"),
+        None => format!("batch {index}, row {row}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zone::fixtures::wkb_polygon;
+    use arrow_array::BinaryArray;
+    use arrow_schema::{DataType, Field, Schema};
+    use std::sync::Arc;
+
+    fn boundaries(values: Vec<Option<&[u8]>>) -> RecordBatch {
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "z_boundary",
+            DataType::Binary,
+            true,
+        )]));
+        RecordBatch::try_new(schema, vec![Arc::new(BinaryArray::from(values))]).unwrap()
+    }
+
+    #[test]
+    fn test_malformed_wkb_policies() {
+        let square = wkb_polygon(&[(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 0.0)]);
+        let batch = || {
+            boundaries(vec![
+                Some(square.as_slice()),
+                None,
+                Some(&[1, 3, 0]),
+                Some(square.as_slice()),
+            ])
+        };
+
+        assert!(normalize_wkb_batches(vec![batch()], false, OnBadGeometry::Fail, None).is_err());
+
+        let (batches, report) =
+            normalize_wkb_batches(vec![batch()], false, OnBadGeometry::Skip, None).unwrap();
+        assert_eq!(
+            report,
+            WkbReport {
+                rewritten: 0,
+                malformed: 1
+            }
+        );
+        assert_eq!(batches[0].num_rows(), 3);
+        assert!(batches[0].column(0).is_null(1));
+
+        let (batches, _) =
+            normalize_wkb_batches(vec![batch()], false, OnBadGeometry::Keep, None).unwrap();
+        assert_eq!(batches[0], batch());
+    }
+
+    #[test]
+    fn test_malformed_wkb_reported_with_row() {
+        let square = wkb_polygon(&[(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 0.0)]);
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("z_gersid", DataType::Utf8, false),
+            Field::new("z_boundary", DataType::Binary, true),
+        ]));
+        let batch = |ids: Vec<&str>, values: Vec<Option<&[u8]>>| {
+            RecordBatch::try_new(
+                Arc::clone(&schema),
+                vec![
+                    Arc::new(StringArray::from(ids)),
+                    Arc::new(BinaryArray::from(values)),
+                ],
+            )
+            .unwrap()
+        };
+        let batches = || {
+            vec![
+                batch(vec!["g1", "g2"], vec![Some(&square), Some(&square)]),
+                batch(
+                    vec!["g3", "g4", "g5"],
+                    vec![None, Some(b"\x01\x03\x00\x00"), Some(&square)],
+                ),
+            ]
+        };
+
+        let err = normalize_wkb_batches(batches(), false, OnBadGeometry::Fail, None).unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("Malformed z_boundary WKB at z_gersid g4 (batch 1, row 1): "),
+            "{err}"
+        );
+
+        let (skipped, report) =
+            normalize_wkb_batches(batches(), false, OnBadGeometry::Skip, Some(2)).unwrap();
+        assert_eq!(report.malformed, 1);
+        let ids: Vec<_> = skipped
+            .iter()
+            .flat_map(|b| {
+                let ids = b.column(0).as_any().downcast_ref::<StringArray>().unwrap();
+                ids.iter()
+                    .map(|id| id.unwrap().to_string())
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        assert_eq!(ids, vec!["g1", "g2", "g3", "g5"]);
+    }
+}
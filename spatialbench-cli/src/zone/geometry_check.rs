@@ -0,0 +1,363 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Read-only ring checks of the `z_boundary` geometries of a dataset
+//!
+//! `validate-geometries` counts what `--normalize-winding` and the WKB
+//! normalization would have to deal with, without rewriting anything: ring
+//! orientations, holes wound the wrong way, self-intersecting, unclosed
+//! and degenerate rings, and repeated consecutive points. Files are read
+//! one batch of `z_boundary` values at a time.
+
+use anyhow::{anyhow, Result};
+use arrow::compute::cast;
+use arrow_array::{Array, BinaryArray};
+use arrow_schema::DataType;
+use geo::line_intersection::LineIntersection;
+use geo::sweep::{Cross, Intersections};
+use geo::{Coord, Line};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::arrow::ProjectionMask;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::path::Path;
+
+use super::verify::discover_files;
+use super::wkb::{polygon_rings, Ring};
+
+const GEOMETRY_COLUMN: &str = "z_boundary";
+
+/// Ring counts of the geometries of one file or a whole dataset
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct GeometryCheck {
+    pub geometries: u64,
+    /// Geometries that are null or not valid WKB
+    pub unreadable: u64,
+    pub ccw_exteriors: u64,
+    pub cw_exteriors: u64,
+    pub holes: u64,
+    /// Holes wound counterclockwise, the same way as their exterior
+    pub misoriented_holes: u64,
+    /// Rings with fewer than three distinct points or no area, which have
+    /// no orientation
+    pub degenerate_rings: u64,
+    /// Rings crossing or touching themselves away from consecutive
+    /// segments, or doubling back along a segment
+    pub self_intersecting_rings: u64,
+    /// Points equal to the point before them
+    pub duplicate_points: u64,
+    /// Rings whose last point differs from their first
+    pub unclosed_rings: u64,
+}
+
+impl GeometryCheck {
+    fn add(&mut self, other: &Self) {
+        self.geometries += other.geometries;
+        self.unreadable += other.unreadable;
+        self.ccw_exteriors += other.ccw_exteriors;
+        self.cw_exteriors += other.cw_exteriors;
+        self.holes += other.holes;
+        self.misoriented_holes += other.misoriented_holes;
+        self.degenerate_rings += other.degenerate_rings;
+        self.self_intersecting_rings += other.self_intersecting_rings;
+        self.duplicate_points += other.duplicate_points;
+        self.unclosed_rings += other.unclosed_rings;
+    }
+
+    /// Counts the rings of one `z_boundary` value
+    fn update(&mut self, wkb: Option<&[u8]>) {
+        self.geometries += 1;
+        let Some(rings) = wkb.and_then(|wkb| polygon_rings(wkb).ok()) else {
+            self.unreadable += 1;
+            return;
+        };
+        for ring in &rings {
+            self.check_ring(ring);
+        }
+    }
+
+    fn check_ring(&mut self, ring: &Ring) {
+        if !ring.exterior {
+            self.holes += 1;
+        }
+        if !ring.is_closed() {
+            self.unclosed_rings += 1;
+        }
+        let points = distinct_points(&ring.points);
+        self.duplicate_points += (ring.points.len() - points.len()) as u64;
+
+        // The closing point repeats the first
+        let distinct = match points.first() == points.last() {
+            true => points.len().saturating_sub(1),
+            false => points.len(),
+        };
+        if distinct >= 3 && self_intersects(&points) {
+            self.self_intersecting_rings += 1;
+        }
+
+        let area = ring.signed_area();
+        if distinct < 3 || area == 0.0 || !area.is_finite() {
+            self.degenerate_rings += 1;
+            return;
+        }
+        match (ring.exterior, area > 0.0) {
+            (true, true) => self.ccw_exteriors += 1,
+            (true, false) => self.cw_exteriors += 1,
+            (false, true) => self.misoriented_holes += 1,
+            (false, false) => {}
+        }
+    }
+}
+
+/// The checks of every zone part file and of the whole dataset
+#[derive(Debug, Serialize)]
+pub struct GeometryCheckReport {
+    /// Checks by file path relative to the data directory
+    pub files: BTreeMap<String, GeometryCheck>,
+    pub total: GeometryCheck,
+}
+
+/// Checks the `z_boundary` rings of every zone file in `data_dir`
+pub fn check_geometries_dir(data_dir: &Path) -> Result<GeometryCheckReport> {
+    let files = discover_files(data_dir)?;
+    if files.is_empty() {
+        return Err(anyhow!("No zone files found in {}", data_dir.display()));
+    }
+
+    let mut report = GeometryCheckReport {
+        files: BTreeMap::new(),
+        total: GeometryCheck::default(),
+    };
+    for (name, file) in files {
+        let check = check_file(&file.path)?;
+        report.total.add(&check);
+        report.files.insert(name, check);
+    }
+    Ok(report)
+}
+
+/// Checks the `z_boundary` rings of one Parquet file, reading no other
+/// column
+fn check_file(path: &Path) -> Result<GeometryCheck> {
+    let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(path)?)?;
+    let index = builder
+        .schema()
+        .index_of(GEOMETRY_COLUMN)
+        .map_err(|_| anyhow!("{} has no {GEOMETRY_COLUMN} column", path.display()))?;
+    let mask = ProjectionMask::roots(builder.parquet_schema(), [index]);
+    let reader = builder.with_projection(mask).build()?;
+
+    let mut check = GeometryCheck::default();
+    for batch in reader {
+        let geometries = cast(batch?.column(0), &DataType::Binary)?;
+        let geometries = geometries
+            .as_any()
+            .downcast_ref::<BinaryArray>()
+            .ok_or_else(|| anyhow!("Column {GEOMETRY_COLUMN} is not a binary column"))?;
+        for wkb in geometries.iter() {
+            check.update(wkb);
+        }
+    }
+    Ok(check)
+}
+
+/// `points` without the points equal to the one before them
+fn distinct_points(points: &[(f64, f64)]) -> Vec<(f64, f64)> {
+    let mut distinct: Vec<(f64, f64)> = Vec::with_capacity(points.len());
+    for &point in points {
+        if distinct.last() != Some(&point) {
+            distinct.push(point);
+        }
+    }
+    distinct
+}
+
+/// A ring segment and its position along the ring
+#[derive(Clone, Debug)]
+struct Segment {
+    index: usize,
+    line: Line<f64>,
+}
+
+impl Cross for Segment {
+    type Scalar = f64;
+
+    fn line(&self) -> Line<f64> {
+        self.line
+    }
+}
+
+/// Whether the ring through `points`, free of repeated consecutive points,
+/// intersects itself anywhere but at the points shared by consecutive
+/// segments
+fn self_intersects(points: &[(f64, f64)]) -> bool {
+    if points.iter().any(|(x, y)| !x.is_finite() || !y.is_finite()) {
+        return false;
+    }
+    let coord = |&(x, y): &(f64, f64)| Coord { x, y };
+    let mut segments: Vec<Segment> = points
+        .windows(2)
+        .enumerate()
+        .map(|(index, w)| Segment {
+            index,
+            line: Line::new(coord(&w[0]), coord(&w[1])),
+        })
+        .collect();
+    // The segment closing an unclosed ring
+    if points.first() != points.last() {
+        segments.push(Segment {
+            index: segments.len(),
+            line: Line::new(coord(&points[points.len() - 1]), coord(&points[0])),
+        });
+    }
+    let last = segments.len() - 1;
+    let consecutive = |a: usize, b: usize| a.abs_diff(b) == 1 || a.abs_diff(b) == last;
+
+    Intersections::from_iter(segments).any(|(a, b, intersection)| match intersection {
+        LineIntersection::SinglePoint { .. } => !consecutive(a.index, b.index),
+        LineIntersection::Collinear { .. } => true,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zone::fixtures::{wkb_multipolygon, wkb_point, wkb_polygon, write_parquet};
+    use arrow_array::RecordBatch;
+    use arrow_schema::{Field, Schema};
+    use std::sync::Arc;
+
+    const CCW: &[(f64, f64)] = &[(0.0, 0.0), (4.0, 0.0), (4.0, 4.0), (0.0, 4.0), (0.0, 0.0)];
+
+    fn reversed(ring: &[(f64, f64)]) -> Vec<(f64, f64)> {
+        ring.iter().rev().copied().collect()
+    }
+
+    #[test]
+    fn test_ring_checks() {
+        let hole_ccw = [(1.0, 1.0), (2.0, 1.0), (2.0, 2.0), (1.0, 2.0), (1.0, 1.0)];
+        let bowtie = [(0.0, 0.0), (2.0, 2.0), (2.0, 0.0), (0.0, 2.0), (0.0, 0.0)];
+        let repeated = [
+            (0.0, 0.0),
+            (4.0, 0.0),
+            (4.0, 0.0),
+            (4.0, 4.0),
+            (0.0, 4.0),
+            (0.0, 0.0),
+        ];
+        let unclosed = [(0.0, 0.0), (4.0, 0.0), (4.0, 4.0), (0.0, 4.0)];
+
+        let mut check = GeometryCheck::default();
+        for wkb in [
+            Some(wkb_polygon(CCW)),
+            Some(wkb_polygon(&reversed(CCW))),
+            Some(wkb_multipolygon(&[
+                &[CCW, &hole_ccw],
+                &[CCW, &reversed(&hole_ccw)],
+            ])),
+            Some(wkb_polygon(&bowtie)),
+            Some(wkb_polygon(&repeated)),
+            Some(wkb_polygon(&unclosed)),
+            Some(wkb_point(1.0, 2.0)),
+            Some(b"not wkb".to_vec()),
+            None,
+        ] {
+            check.update(wkb.as_deref());
+        }
+
+        assert_eq!(
+            check,
+            GeometryCheck {
+                geometries: 9,
+                unreadable: 2,
+                ccw_exteriors: 5,
+                cw_exteriors: 1,
+                holes: 2,
+                misoriented_holes: 1,
+                // The bowtie's lobes cancel out to no area
+                degenerate_rings: 1,
+                self_intersecting_rings: 1,
+                duplicate_points: 1,
+                unclosed_rings: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_self_intersections() {
+        assert!(!self_intersects(CCW));
+        // Unclosed rings are checked with their closing segment
+        assert!(!self_intersects(&CCW[..4]));
+        // A figure eight with lobes of different sizes still has an area
+        let eight = [(0.0, 0.0), (4.0, 4.0), (4.0, 0.0), (0.0, 2.0), (0.0, 0.0)];
+        assert!(self_intersects(&eight));
+        // A spike doubling back along its last segment
+        let spike = [(0.0, 0.0), (4.0, 0.0), (4.0, 4.0), (4.0, 2.0), (0.0, 0.0)];
+        assert!(self_intersects(&spike));
+        // A ring touching itself at a vertex
+        let touching = [
+            (0.0, 0.0),
+            (4.0, 0.0),
+            (2.0, 2.0),
+            (4.0, 4.0),
+            (0.0, 4.0),
+            (2.0, 2.0),
+            (0.0, 0.0),
+        ];
+        assert!(self_intersects(&touching));
+    }
+
+    #[test]
+    fn test_check_dir_by_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("z_zonekey", DataType::Int64, false),
+            Field::new(GEOMETRY_COLUMN, DataType::Binary, true),
+        ]));
+        for (part, ring) in [(1, CCW.to_vec()), (2, reversed(CCW))] {
+            let batch = RecordBatch::try_new(
+                schema.clone(),
+                vec![
+                    Arc::new(arrow_array::Int64Array::from(vec![part, part + 10])),
+                    Arc::new(BinaryArray::from(vec![
+                        Some(wkb_polygon(&ring).as_slice()),
+                        None,
+                    ])),
+                ],
+            )
+            .unwrap();
+            write_parquet(
+                &dir.path().join(format!("zone/zone.{part}.parquet")),
+                &batch,
+            );
+        }
+        let before = std::fs::read(dir.path().join("zone/zone.1.parquet")).unwrap();
+
+        let report = check_geometries_dir(dir.path()).unwrap();
+        assert_eq!(report.files.len(), 2);
+        assert_eq!(report.files["zone/zone.1.parquet"].ccw_exteriors, 1);
+        assert_eq!(report.files["zone/zone.2.parquet"].cw_exteriors, 1);
+        assert_eq!(report.total.geometries, 4);
+        assert_eq!(report.total.unreadable, 2);
+        // Nothing is rewritten
+        assert_eq!(
+            std::fs::read(dir.path().join("zone/zone.1.parquet")).unwrap(),
+            before
+        );
+    }
+}
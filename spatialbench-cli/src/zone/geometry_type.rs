@@ -0,0 +1,160 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Filtering of source zones by the geometry type of their WKB
+
+use anyhow::{anyhow, Result};
+use arrow_array::{Array, BinaryArray, Int64Array, StringArray};
+use arrow_schema::DataType;
+use datafusion::functions_aggregate::expr_fn::count;
+use datafusion::logical_expr::{create_udf, ColumnarValue, ScalarUDF, Volatility};
+use datafusion::prelude::*;
+use log::info;
+use std::sync::Arc;
+
+use super::config::GeometryType;
+use super::wkb;
+
+const TYPE_COLUMN: &str = "geometry_type";
+
+/// `wkb_geometry_type(geometry)`: the [`GeometryType::name`] of a WKB
+/// value, null when the value is null or not valid WKB
+fn wkb_geometry_type_udf() -> ScalarUDF {
+    create_udf(
+        "wkb_geometry_type",
+        vec![DataType::Binary],
+        DataType::Utf8,
+        Volatility::Immutable,
+        Arc::new(|args: &[ColumnarValue]| {
+            let arrays = ColumnarValue::values_to_arrays(args)?;
+            let values = arrays[0]
+                .as_any()
+                .downcast_ref::<BinaryArray>()
+                .expect("wkb_geometry_type takes a Binary argument");
+            let names: StringArray = values
+                .iter()
+                .map(|wkb| {
+                    let code = wkb::geometry_type(wkb?).ok()?;
+                    GeometryType::from_wkb_code(code).map(|t| t.name())
+                })
+                .collect();
+            Ok(ColumnarValue::Array(Arc::new(names)))
+        }),
+    )
+}
+
+/// Keeps the rows of `df` whose `geometry` is one of `types`, logging the
+/// number of rows dropped for each other type. Null and malformed
+/// geometries are dropped too.
+pub async fn keep_geometry_types(df: DataFrame, types: &[GeometryType]) -> Result<DataFrame> {
+    let geometry_type = wkb_geometry_type_udf().call(vec![cast(col("geometry"), DataType::Binary)]);
+    let kept = geometry_type
+        .clone()
+        .in_list(types.iter().map(|t| lit(t.name())).collect(), false);
+
+    let dropped = df
+        .clone()
+        .filter(kept.clone().is_not_true())?
+        .aggregate(
+            vec![geometry_type.alias(TYPE_COLUMN)],
+            vec![count(lit(1)).alias("rows")],
+        )?
+        .sort(vec![col(TYPE_COLUMN).sort(true, false)])?
+        .collect()
+        .await?;
+    let mut total = 0;
+    for batch in &dropped {
+        let names = arrow::compute::cast(batch.column(0), &DataType::Utf8)?;
+        let names = names
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .ok_or_else(|| anyhow!("Unexpected {TYPE_COLUMN} column type"))?;
+        let rows = batch
+            .column(1)
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .ok_or_else(|| anyhow!("Unexpected row count column type"))?;
+        for i in 0..batch.num_rows() {
+            let name = match names.is_null(i) {
+                true => "null or malformed",
+                false => names.value(i),
+            };
+            info!(
+                "Dropped {} source row(s) with {name} geometry",
+                rows.value(i)
+            );
+            total += rows.value(i);
+        }
+    }
+    let kept_names: Vec<_> = types.iter().map(|t| t.name()).collect();
+    info!(
+        "Kept only {} geometries, dropping {total} source row(s)",
+        kept_names.join(", ")
+    );
+
+    Ok(df.filter(kept)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zone::fixtures::{wkb_point, wkb_polygon};
+    use arrow_array::RecordBatch;
+    use arrow_schema::{Field, Schema};
+
+    #[tokio::test]
+    async fn test_keep_geometry_types() {
+        let square = wkb_polygon(&[(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 0.0)]);
+        let point = wkb_point(1.0, 2.0);
+        let geometries: Vec<Option<&[u8]>> = vec![
+            Some(&square),
+            Some(&point),
+            None,
+            Some(b"not wkb"),
+            Some(&square),
+        ];
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Utf8, false),
+            Field::new("geometry", DataType::Binary, true),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(StringArray::from(vec!["a", "b", "c", "d", "e"])),
+                Arc::new(BinaryArray::from(geometries)),
+            ],
+        )
+        .unwrap();
+
+        let ctx = SessionContext::new();
+        let df = ctx.read_batch(batch).unwrap();
+        let df = keep_geometry_types(df, &[GeometryType::Polygon, GeometryType::Multipolygon])
+            .await
+            .unwrap();
+        let batches = df.select_columns(&["id"]).unwrap().collect().await.unwrap();
+        let ids: Vec<String> = batches
+            .iter()
+            .flat_map(|b| {
+                let ids = b.column(0).as_any().downcast_ref::<StringArray>().unwrap();
+                ids.iter()
+                    .map(|id| id.unwrap().to_string())
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        assert_eq!(ids, vec!["a", "e"]);
+    }
+}
@@ -0,0 +1,162 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Canonical content hashing of zone record batches
+
+use anyhow::{anyhow, Result};
+use arrow::row::{RowConverter, SortField};
+use arrow_array::RecordBatch;
+use arrow_schema::SchemaRef;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::file::reader::FileReader;
+use parquet::file::serialized_reader::SerializedFileReader;
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::path::Path;
+
+/// Footer key under which the content hash of a part is stored
+pub const CONTENT_SHA256_KEY: &str = "spatialbench.content_sha256";
+
+/// Incremental SHA-256 over the logical content of a stream of batches.
+///
+/// Each row is converted to the Arrow row format before hashing, so the
+/// digest depends only on column names and values: it is the same for
+/// `Utf8` and `Utf8View` strings, for any batch or row group boundaries,
+/// and for any Parquet compression.
+pub struct ContentHasher {
+    converter: RowConverter,
+    digest: Sha256,
+}
+
+impl ContentHasher {
+    pub fn try_new(schema: &SchemaRef) -> Result<Self> {
+        let fields = schema
+            .fields()
+            .iter()
+            .map(|f| SortField::new(f.data_type().clone()))
+            .collect();
+
+        let mut digest = Sha256::new();
+        for field in schema.fields() {
+            digest.update((field.name().len() as u64).to_le_bytes());
+            digest.update(field.name().as_bytes());
+        }
+
+        Ok(Self {
+            converter: RowConverter::new(fields)?,
+            digest,
+        })
+    }
+
+    pub fn update(&mut self, batch: &RecordBatch) -> Result<()> {
+        let rows = self.converter.convert_columns(batch.columns())?;
+        for row in rows.iter() {
+            let bytes = row.as_ref();
+            self.digest.update((bytes.len() as u64).to_le_bytes());
+            self.digest.update(bytes);
+        }
+        Ok(())
+    }
+
+    /// Returns the lowercase hex digest
+    pub fn finish(self) -> String {
+        self.digest
+            .finalize()
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect()
+    }
+}
+
+/// Computes the content hash of a Parquet file by streaming its batches
+pub fn hash_parquet_file(path: &Path) -> Result<String> {
+    let file = File::open(path)?;
+    let builder = ParquetRecordBatchReaderBuilder::try_new(file)?;
+    let mut hasher = ContentHasher::try_new(builder.schema())?;
+    for batch in builder.build()? {
+        hasher.update(&batch?)?;
+    }
+    Ok(hasher.finish())
+}
+
+/// Returns the content hash recorded in a Parquet footer, if any
+pub fn read_footer_hash(path: &Path) -> Result<Option<String>> {
+    read_footer_value(path, CONTENT_SHA256_KEY)
+}
+
+/// Returns the value of `key` in the key-value metadata of a Parquet
+/// footer, if any
+pub fn read_footer_value(path: &Path, key: &str) -> Result<Option<String>> {
+    let file = File::open(path)?;
+    let reader = SerializedFileReader::new(file)
+        .map_err(|e| anyhow!("Failed to read footer of {}: {e}", path.display()))?;
+    let value = reader
+        .metadata()
+        .file_metadata()
+        .key_value_metadata()
+        .and_then(|kvs| kvs.iter().find(|kv| kv.key == key))
+        .and_then(|kv| kv.value.clone());
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_array::{ArrayRef, Int64Array, StringArray, StringViewArray};
+    use arrow_schema::{DataType, Field, Schema};
+    use std::sync::Arc;
+
+    fn batch(keys: Vec<i64>, names: ArrayRef) -> RecordBatch {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("z_zonekey", DataType::Int64, false),
+            Field::new("z_name", names.data_type().clone(), false),
+        ]));
+        RecordBatch::try_new(schema, vec![Arc::new(Int64Array::from(keys)), names]).unwrap()
+    }
+
+    fn hash(batches: &[RecordBatch]) -> String {
+        let mut hasher = ContentHasher::try_new(&batches[0].schema()).unwrap();
+        for b in batches {
+            hasher.update(b).unwrap();
+        }
+        hasher.finish()
+    }
+
+    #[test]
+    fn test_hash_independent_of_batching_and_string_type() {
+        let whole = batch(
+            vec![1, 2, 3],
+            Arc::new(StringArray::from(vec!["a", "bb", "ccc"])),
+        );
+        let split = vec![
+            batch(vec![1], Arc::new(StringViewArray::from(vec!["a"]))),
+            batch(
+                vec![2, 3],
+                Arc::new(StringViewArray::from(vec!["bb", "ccc"])),
+            ),
+        ];
+
+        assert_eq!(hash(&[whole]), hash(&split));
+    }
+
+    #[test]
+    fn test_hash_detects_value_change() {
+        let a = batch(vec![1, 2], Arc::new(StringArray::from(vec!["a", "b"])));
+        let b = batch(vec![1, 2], Arc::new(StringArray::from(vec!["a", "c"])));
+        assert_ne!(hash(&[a]), hash(&[b]));
+    }
+}
@@ -0,0 +1,241 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Parent zone keys and administrative levels for the zone hierarchy
+//!
+//! Parents are resolved through the `z_country` and `z_region` codes: a
+//! region's parent is the country zone with its `z_country`, any lower level
+//! zone's parent is the region zone with its `z_region`, falling back to the
+//! country zone. Parents must be rows of the same generated table, so this
+//! runs on the complete table before it is split into parts.
+
+use anyhow::{anyhow, Result};
+use arrow::compute::cast;
+use arrow_array::{Array, Int32Array, Int64Array, RecordBatch, StringArray};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use log::info;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+pub const PARENT_ZONEKEY_COLUMN: &str = "z_parent_zonekey";
+pub const ADMIN_LEVEL_COLUMN: &str = "z_admin_level";
+
+/// Administrative level of an Overture division subtype, `0` for countries
+pub fn admin_level(subtype: &str) -> Option<i32> {
+    let level = match subtype {
+        "country" => 0,
+        "dependency" | "macroregion" => 1,
+        "region" => 2,
+        "macrocounty" => 3,
+        "county" => 4,
+        "localadmin" => 5,
+        "locality" => 6,
+        "borough" => 7,
+        "macrohood" => 8,
+        "neighborhood" => 9,
+        "microhood" => 10,
+        _ => return None,
+    };
+    Some(level)
+}
+
+/// Appends the hierarchy fields to `schema`
+pub fn output_schema(schema: SchemaRef) -> SchemaRef {
+    let mut fields: Vec<Field> = schema.fields().iter().map(|f| f.as_ref().clone()).collect();
+    fields.push(Field::new(PARENT_ZONEKEY_COLUMN, DataType::Int64, true));
+    fields.push(Field::new(ADMIN_LEVEL_COLUMN, DataType::Int32, true));
+    Arc::new(Schema::new_with_metadata(fields, schema.metadata().clone()))
+}
+
+/// Columns of one batch needed to resolve parents
+struct HierarchyColumns {
+    keys: Int64Array,
+    countries: StringArray,
+    regions: StringArray,
+    subtypes: StringArray,
+}
+
+impl HierarchyColumns {
+    fn try_new(batch: &RecordBatch) -> Result<Self> {
+        let column = |name: &str, data_type: &DataType| {
+            let column = batch
+                .column_by_name(name)
+                .ok_or_else(|| anyhow!("Column {name} not found in zone batch"))?;
+            Ok::<_, anyhow::Error>(cast(column, data_type)?)
+        };
+        let strings = |name: &str| {
+            Ok::<_, anyhow::Error>(
+                column(name, &DataType::Utf8)?
+                    .as_any()
+                    .downcast_ref::<StringArray>()
+                    .unwrap()
+                    .clone(),
+            )
+        };
+        Ok(Self {
+            keys: column("z_zonekey", &DataType::Int64)?
+                .as_any()
+                .downcast_ref::<Int64Array>()
+                .unwrap()
+                .clone(),
+            countries: strings("z_country")?,
+            regions: strings("z_region")?,
+            subtypes: strings("z_subtype")?,
+        })
+    }
+}
+
+/// Appends `z_parent_zonekey` and `z_admin_level` to every batch.
+///
+/// When several zones share a country or region code the one with the
+/// smallest `z_zonekey` is the parent, so the result doesn't depend on row
+/// order.
+pub fn add_hierarchy_columns(batches: Vec<RecordBatch>) -> Result<Vec<RecordBatch>> {
+    let columns = batches
+        .iter()
+        .map(HierarchyColumns::try_new)
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut countries: HashMap<String, i64> = HashMap::new();
+    let mut regions: HashMap<String, i64> = HashMap::new();
+    for c in &columns {
+        for i in 0..c.keys.len() {
+            let (lookup, code) = match c.subtypes.value(i) {
+                "country" => (&mut countries, c.countries.value(i)),
+                "region" => (&mut regions, c.regions.value(i)),
+                _ => continue,
+            };
+            if code.is_empty() {
+                continue;
+            }
+            let key = c.keys.value(i);
+            lookup
+                .entry(code.to_string())
+                .and_modify(|k| *k = (*k).min(key))
+                .or_insert(key);
+        }
+    }
+
+    let mut resolved = 0;
+    let batches = batches
+        .iter()
+        .zip(&columns)
+        .map(|(batch, c)| {
+            let parents: Int64Array = (0..c.keys.len())
+                .map(|i| {
+                    let country = countries.get(c.countries.value(i)).copied();
+                    let parent = match c.subtypes.value(i) {
+                        "country" => None,
+                        "dependency" | "macroregion" | "region" => country,
+                        _ => regions.get(c.regions.value(i)).copied().or(country),
+                    };
+                    resolved += parent.is_some() as usize;
+                    parent
+                })
+                .collect();
+            let levels: Int32Array = c.subtypes.iter().map(|s| s.and_then(admin_level)).collect();
+
+            let mut arrays = batch.columns().to_vec();
+            arrays.push(Arc::new(parents));
+            arrays.push(Arc::new(levels));
+            Ok(RecordBatch::try_new(output_schema(batch.schema()), arrays)?)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    info!(
+        "Resolved parents for {resolved} zone(s) from {} country and {} region zone(s)",
+        countries.len(),
+        regions.len()
+    );
+    Ok(batches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn zones(rows: &[(i64, &str, &str, &str)]) -> RecordBatch {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("z_zonekey", DataType::Int64, false),
+            Field::new("z_country", DataType::Utf8, false),
+            Field::new("z_region", DataType::Utf8, false),
+            Field::new("z_subtype", DataType::Utf8, false),
+        ]));
+        RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(Int64Array::from_iter_values(rows.iter().map(|r| r.0))),
+                Arc::new(StringArray::from_iter_values(rows.iter().map(|r| r.1))),
+                Arc::new(StringArray::from_iter_values(rows.iter().map(|r| r.2))),
+                Arc::new(StringArray::from_iter_values(rows.iter().map(|r| r.3))),
+            ],
+        )
+        .unwrap()
+    }
+
+    fn parents_and_levels(batch: &RecordBatch) -> Vec<(Option<i64>, Option<i32>)> {
+        let parents = batch
+            .column_by_name(PARENT_ZONEKEY_COLUMN)
+            .unwrap()
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .unwrap();
+        let levels = batch
+            .column_by_name(ADMIN_LEVEL_COLUMN)
+            .unwrap()
+            .as_any()
+            .downcast_ref::<Int32Array>()
+            .unwrap();
+        parents.iter().zip(levels.iter()).collect()
+    }
+
+    #[test]
+    fn test_country_region_locality_chain() {
+        // Rows in separate batches and out of key order
+        let batches = add_hierarchy_columns(vec![
+            zones(&[
+                (3, "US", "US-WA", "locality"),
+                (4, "CA", "CA-BC", "locality"),
+            ]),
+            zones(&[(2, "US", "US-WA", "region"), (1, "US", "", "country")]),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            parents_and_levels(&batches[0]),
+            vec![(Some(2), Some(6)), (None, Some(6))]
+        );
+        assert_eq!(
+            parents_and_levels(&batches[1]),
+            vec![(Some(1), Some(2)), (None, Some(0))]
+        );
+    }
+
+    #[test]
+    fn test_falls_back_to_country_without_region() {
+        let batches = add_hierarchy_columns(vec![zones(&[
+            (1, "US", "", "country"),
+            (2, "US", "US-OR", "county"),
+            (3, "US", "US-OR", "unknown"),
+        ])])
+        .unwrap();
+        assert_eq!(
+            parents_and_levels(&batches[0]),
+            vec![(None, Some(0)), (Some(1), Some(4)), (Some(1), None)]
+        );
+    }
+}
@@ -0,0 +1,284 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Simplified companion datasets of `--simplification-levels`
+//!
+//! Every tolerance above 0 gets a `zone_simplified_<tolerance>` directory
+//! next to the full-resolution files, holding the same parts, rows and keys
+//! with `z_boundary` simplified from the full-resolution geometry. A
+//! geometry simplified to nothing, or to a polygon without area, keeps the
+//! geometry of the level below instead. The bounds of `--with-bbox-covering`
+//! are recomputed for each level; the other columns keep the values of the
+//! full-resolution rows.
+
+use anyhow::{anyhow, Result};
+use arrow::compute::cast;
+use arrow_array::{Array, BinaryArray, RecordBatch};
+use arrow_schema::DataType;
+use geo::{Area, CoordsIter, Geometry};
+use geozero::wkb::Wkb;
+use geozero::{CoordDimensions, ToGeo, ToWkb};
+
+use super::batch::map_batches;
+use super::bbox::{append_bbox_column, BboxLayout};
+use super::simplify::SimplifyAlgorithm;
+use super::wkb::count_points;
+
+/// Name of the directory of the level simplified at `tolerance`, e.g.
+/// `zone_simplified_0_001` for 0.001
+pub fn level_dir_name(tolerance: f64) -> String {
+    format!(
+        "zone_simplified_{}",
+        tolerance.to_string().replace('.', "_")
+    )
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct LevelReport {
+    /// Vertices of the simplified geometries before and after
+    pub points_before: usize,
+    pub points_after: usize,
+    /// Geometries simplified to nothing that kept those of the level below
+    pub fallbacks: usize,
+}
+
+/// Whether simplification left nothing of `geometry` worth keeping
+fn is_collapsed(geometry: &Geometry) -> bool {
+    match geometry {
+        Geometry::Polygon(_) | Geometry::MultiPolygon(_) => geometry.unsigned_area() == 0.0,
+        _ => geometry.coords_count() == 0,
+    }
+}
+
+/// `batches` with `z_boundary` simplified by `algorithm` at `tolerance`,
+/// as little-endian 2D ISO WKB. `below` holds the same rows at the level
+/// below, whose geometry replaces any that collapsed. Points and unreadable
+/// values are left as-is. With `bbox`, its layout and inflation, the bounds
+/// columns are recomputed from the simplified geometries.
+pub fn simplify_level(
+    batches: &[RecordBatch],
+    below: &[RecordBatch],
+    algorithm: SimplifyAlgorithm,
+    tolerance: f64,
+    bbox: Option<(BboxLayout, f64)>,
+    threads: Option<usize>,
+) -> Result<(Vec<RecordBatch>, LevelReport)> {
+    if batches.len() != below.len() {
+        return Err(anyhow!(
+            "Level below has {} batches, expected {}",
+            below.len(),
+            batches.len()
+        ));
+    }
+    let mut report = LevelReport::default();
+    let batches = map_batches(batches, threads, |index, batch| {
+        let mut batch_report = LevelReport::default();
+        let column_index = batch
+            .schema()
+            .index_of("z_boundary")
+            .map_err(|_| anyhow!("Column z_boundary not found in zone batch"))?;
+        let data_type = batch.column(column_index).data_type().clone();
+        let geometries = cast(batch.column(column_index), &DataType::Binary)?;
+        let geometries = geometries
+            .as_any()
+            .downcast_ref::<BinaryArray>()
+            .ok_or_else(|| anyhow!("Column z_boundary is not a binary column"))?;
+        let fallback = cast(
+            below[index]
+                .column_by_name("z_boundary")
+                .ok_or_else(|| anyhow!("Column z_boundary not found in zone batch"))?,
+            &DataType::Binary,
+        )?;
+        let fallback = fallback
+            .as_any()
+            .downcast_ref::<BinaryArray>()
+            .ok_or_else(|| anyhow!("Column z_boundary is not a binary column"))?;
+
+        let simplified: BinaryArray = geometries
+            .iter()
+            .enumerate()
+            .map(|(row, wkb)| {
+                let Some(wkb) = wkb else {
+                    return Ok(None);
+                };
+                let Some(simplified) = Wkb(wkb)
+                    .to_geo()
+                    .ok()
+                    .and_then(|geometry| algorithm.simplify(&geometry, tolerance))
+                else {
+                    return Ok(Some(wkb.to_vec()));
+                };
+                batch_report.points_before += count_points(wkb)?;
+                if is_collapsed(&simplified) {
+                    batch_report.fallbacks += 1;
+                    let below = fallback.value(row);
+                    batch_report.points_after += count_points(below)?;
+                    return Ok(Some(below.to_vec()));
+                }
+                let simplified = simplified.to_wkb(CoordDimensions::xy())?;
+                batch_report.points_after += count_points(&simplified)?;
+                Ok(Some(simplified))
+            })
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .collect();
+
+        let mut columns = batch.columns().to_vec();
+        columns[column_index] = cast(&simplified, &data_type)?;
+        let mut level = RecordBatch::try_new(batch.schema(), columns)?;
+        if let Some((layout, inflate)) = bbox {
+            level = recompute_bbox(&level, layout, inflate)?;
+        }
+        Ok((level, batch_report))
+    })?
+    .into_iter()
+    .map(|(batch, batch_report)| {
+        report.points_before += batch_report.points_before;
+        report.points_after += batch_report.points_after;
+        report.fallbacks += batch_report.fallbacks;
+        batch
+    })
+    .collect();
+    Ok((batches, report))
+}
+
+/// `batch` with its bounds columns of `layout` computed anew from
+/// `z_boundary`, in their places
+fn recompute_bbox(batch: &RecordBatch, layout: BboxLayout, inflate: f64) -> Result<RecordBatch> {
+    let schema = batch.schema();
+    let kept: Vec<usize> = (0..schema.fields().len())
+        .filter(|i| !layout.columns().contains(&schema.field(*i).name().as_str()))
+        .collect();
+    let appended = append_bbox_column(vec![batch.project(&kept)?], layout, inflate, None)?;
+    let columns = schema
+        .fields()
+        .iter()
+        .map(|field| {
+            appended[0]
+                .column_by_name(field.name())
+                .cloned()
+                .ok_or_else(|| anyhow!("Column {} not found in zone batch", field.name()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(RecordBatch::try_new(schema, columns)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zone::bbox::BBOX_COLUMN;
+    use crate::zone::fixtures::{wkb_point, wkb_polygon};
+    use arrow_array::{Float64Array, StructArray};
+    use arrow_schema::{Field, Schema};
+    use std::sync::Arc;
+
+    fn boundaries(geometries: &[Vec<u8>]) -> RecordBatch {
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "z_boundary",
+            DataType::Binary,
+            true,
+        )]));
+        let values: BinaryArray = geometries.iter().map(|g| Some(g.as_slice())).collect();
+        RecordBatch::try_new(schema, vec![Arc::new(values)]).unwrap()
+    }
+
+    fn values(batches: &[RecordBatch]) -> Vec<Vec<u8>> {
+        let column = batches[0].column_by_name("z_boundary").unwrap();
+        let column = column.as_any().downcast_ref::<BinaryArray>().unwrap();
+        column.iter().map(|v| v.unwrap().to_vec()).collect()
+    }
+
+    #[test]
+    fn test_level_dir_name() {
+        assert_eq!(level_dir_name(0.001), "zone_simplified_0_001");
+        assert_eq!(level_dir_name(2.0), "zone_simplified_2");
+    }
+
+    #[test]
+    fn test_collapsed_geometries_fall_back_to_the_level_below() {
+        // A ring along a line, which simplifies to a polygon without area
+        let flat = wkb_polygon(&[(0.0, 0.0), (4.0, 0.0), (2.0, 0.0), (1.0, 0.0), (0.0, 0.0)]);
+        let square = wkb_polygon(&[
+            (0.0, 0.0),
+            (10.0, 0.0),
+            (10.0, 10.0),
+            (0.0, 10.0),
+            (0.0, 0.0),
+        ]);
+        let triangle = wkb_polygon(&[(0.0, 0.0), (4.0, 0.0), (2.0, 1.0), (0.0, 0.0)]);
+        let full = vec![boundaries(&[flat, square.clone(), wkb_point(1.0, 2.0)])];
+        let below = vec![boundaries(&[
+            triangle.clone(),
+            square.clone(),
+            wkb_point(1.0, 2.0),
+        ])];
+
+        let (level, report) = simplify_level(
+            &full,
+            &below,
+            SimplifyAlgorithm::DouglasPeucker,
+            1.0,
+            None,
+            None,
+        )
+        .unwrap();
+        let simplified = values(&level);
+        assert_eq!(report.fallbacks, 1, "{report:?}");
+        assert_eq!(simplified[0], triangle);
+        assert_eq!(simplified[1], square);
+        assert_eq!(simplified[2], wkb_point(1.0, 2.0));
+        assert_eq!(report.points_before, 10);
+        assert_eq!(report.points_after, 9);
+    }
+
+    #[test]
+    fn test_bbox_follows_the_simplified_geometry() {
+        let spike = wkb_polygon(&[
+            (0.0, 0.0),
+            (10.0, 0.0),
+            (10.0, 10.0),
+            (5.0, 10.0),
+            (5.0, 30.0),
+            (4.99, 10.0),
+            (0.0, 10.0),
+            (0.0, 0.0),
+        ]);
+        let full =
+            append_bbox_column(vec![boundaries(&[spike])], BboxLayout::Struct, 0.0, None).unwrap();
+        let (level, _) = simplify_level(
+            &full,
+            &full,
+            SimplifyAlgorithm::VisvalingamWhyatt,
+            1.0,
+            Some((BboxLayout::Struct, 0.0)),
+            None,
+        )
+        .unwrap();
+        assert_eq!(level[0].schema(), full[0].schema());
+        let ymax = |batches: &[RecordBatch]| {
+            let bbox = batches[0].column_by_name(BBOX_COLUMN).unwrap();
+            let bbox = bbox.as_any().downcast_ref::<StructArray>().unwrap();
+            let ymax = bbox.column_by_name("ymax").unwrap();
+            ymax.as_any()
+                .downcast_ref::<Float64Array>()
+                .unwrap()
+                .value(0)
+        };
+        assert_eq!(ymax(&full), 30.0);
+        assert_eq!(ymax(&level), 10.0);
+    }
+}
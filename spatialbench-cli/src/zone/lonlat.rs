@@ -0,0 +1,128 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! `z_lon` and `z_lat` of Point boundaries
+//!
+//! `--point-as-lonlat` appends the coordinates of every Point `z_boundary`
+//! as plain doubles next to the WKB, for CSV consumers without spatial
+//! types. Rows of any other geometry type, and empty points, get nulls.
+
+use anyhow::{anyhow, Result};
+use arrow::compute::cast;
+use arrow_array::{Array, BinaryArray, Float64Array, RecordBatch};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use geo::Geometry;
+use geozero::wkb::Wkb;
+use geozero::ToGeo;
+use std::sync::Arc;
+
+use super::batch::map_batches;
+use super::wkb::geometry_type;
+
+pub const LON_COLUMN: &str = "z_lon";
+pub const LAT_COLUMN: &str = "z_lat";
+
+/// WKB geometry type code of a Point
+const WKB_POINT: u32 = 1;
+
+/// The `(lon, lat)` of a Point `wkb`, `None` for other geometry types,
+/// empty points and WKB that doesn't parse
+pub fn point_lonlat(wkb: &[u8]) -> Option<(f64, f64)> {
+    if geometry_type(wkb).ok()? != WKB_POINT {
+        return None;
+    }
+    match Wkb(wkb).to_geo().ok()? {
+        Geometry::Point(point) if !point.x().is_nan() && !point.y().is_nan() => {
+            Some((point.x(), point.y()))
+        }
+        _ => None,
+    }
+}
+
+/// `schema` with the `z_lon` and `z_lat` columns appended
+pub fn output_schema(schema: SchemaRef) -> SchemaRef {
+    let mut fields: Vec<Field> = schema.fields().iter().map(|f| f.as_ref().clone()).collect();
+    fields.push(Field::new(LON_COLUMN, DataType::Float64, true));
+    fields.push(Field::new(LAT_COLUMN, DataType::Float64, true));
+    Arc::new(Schema::new_with_metadata(fields, schema.metadata().clone()))
+}
+
+/// Appends the `z_lon` and `z_lat` of every row whose `z_boundary` is a
+/// Point, null for the other rows
+pub fn append_lonlat_columns(
+    batches: Vec<RecordBatch>,
+    threads: Option<usize>,
+) -> Result<Vec<RecordBatch>> {
+    map_batches(&batches, threads, |_, batch| {
+        let geometries = batch
+            .column_by_name("z_boundary")
+            .ok_or_else(|| anyhow!("Column z_boundary not found in zone batch"))?;
+        let geometries = cast(geometries, &DataType::Binary)?;
+        let geometries = geometries
+            .as_any()
+            .downcast_ref::<BinaryArray>()
+            .ok_or_else(|| anyhow!("Column z_boundary is not a binary column"))?;
+
+        let points: Vec<Option<(f64, f64)>> = geometries
+            .iter()
+            .map(|wkb| wkb.and_then(point_lonlat))
+            .collect();
+        let lons: Float64Array = points.iter().map(|p| p.map(|(lon, _)| lon)).collect();
+        let lats: Float64Array = points.iter().map(|p| p.map(|(_, lat)| lat)).collect();
+        let mut columns = batch.columns().to_vec();
+        columns.push(Arc::new(lons));
+        columns.push(Arc::new(lats));
+        Ok(RecordBatch::try_new(
+            output_schema(batch.schema()),
+            columns,
+        )?)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zone::fixtures::{wkb_point, wkb_polygon};
+
+    #[test]
+    fn test_points_get_lonlat_and_polygons_nulls() {
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "z_boundary",
+            DataType::Binary,
+            true,
+        )]));
+        let square = wkb_polygon(&[(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 0.0)]);
+        let point = wkb_point(13.4, 52.5);
+        let boundaries =
+            BinaryArray::from(vec![Some(point.as_slice()), Some(square.as_slice()), None]);
+        let batch = RecordBatch::try_new(schema, vec![Arc::new(boundaries)]).unwrap();
+
+        let batches = append_lonlat_columns(vec![batch], Some(2)).unwrap();
+        let column = |name: &str| {
+            batches[0]
+                .column_by_name(name)
+                .unwrap()
+                .as_any()
+                .downcast_ref::<Float64Array>()
+                .unwrap()
+                .iter()
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(column(LON_COLUMN), vec![Some(13.4), None, None]);
+        assert_eq!(column(LAT_COLUMN), vec![Some(52.5), None, None]);
+    }
+}
@@ -15,66 +15,880 @@
 // specific language governing permissions and limitations
 // under the License.
 
-use log::info;
-use parquet::basic::Compression as ParquetCompression;
+use log::{info, warn};
+use parquet::basic::Compression;
 use std::io;
-use std::path::PathBuf;
+use std::path::Path;
 
-use super::config::ZoneDfArgs;
+use crate::output_dir::ExistingOutputs;
+
+use super::adjacency;
+use super::augment::{self, AugmentOptions};
+use super::config::{zone_scale_factor, ZoneDfArgs};
+use super::containment;
+use super::diff;
+use super::diff_stats::{self, CountDelta};
+use super::extend;
+use super::geometry_check::{self, GeometryCheck};
+use super::manifest::ZoneManifest;
+use super::package;
+use super::plan_diff::{diff_plan, ColumnChange};
+use super::profile;
+use super::queries;
+use super::theme::ThemeInput;
+use super::tiles;
+use super::verify;
+
+/// The scale factor zones are generated at for `--scale-factor`, see
+/// [`zone_scale_factor`], warning when one below 1 is raised to 1
+pub fn resolve_scale_factor(scale_factor: f64, allow_fractional: bool) -> io::Result<f64> {
+    let resolved = zone_scale_factor(scale_factor, allow_fractional)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+    if resolved != scale_factor {
+        eprintln!(
+            "Warning: zones have no scale factor below 1, generating them at scale factor \
+             {resolved} for --scale-factor {scale_factor}; pass --allow-fractional-scale to \
+             sample that fraction of them instead"
+        );
+    }
+    Ok(resolved)
+}
 
 /// Generates zone table in the requested format
-#[allow(clippy::too_many_arguments)]
-pub async fn generate_zone(
-    format: OutputFormat,
-    scale_factor: f64,
-    output_dir: PathBuf,
-    parts: Option<i32>,
-    part: Option<i32>,
-    max_file_size_mb: Option<f32>,
-    parquet_row_group_bytes: i64,
-    parquet_compression: ParquetCompression,
-) -> io::Result<()> {
+///
+/// `args` carries the CLI options; when `args.part` is set only that part is
+/// generated, otherwise all `args.parts` parts are.
+pub async fn generate_zone(format: OutputFormat, args: ZoneDfArgs) -> io::Result<()> {
     match format {
-        OutputFormat::Parquet => {
-            let parts = parts.unwrap_or(1);
+        // The rows stream into the delimited text files
+        OutputFormat::Csv | OutputFormat::Tbl => {
+            let args = ZoneDfArgs {
+                scale_factor: resolve_scale_factor(args.scale_factor, args.allow_fractional_scale)?,
+                parts: Some(args.parts.unwrap_or(1)),
+                ..args
+            };
+            info!(
+                "Streaming the zone table into {} files",
+                args.file_extension()
+            );
+            super::generate_zone_text(args).await.map_err(into_io_error)
+        }
+        // `args.file_format` picks the file format
+        OutputFormat::Parquet | OutputFormat::Orc | OutputFormat::Avro => {
+            let parts = args.parts.unwrap_or(1);
+            let args = ZoneDfArgs {
+                scale_factor: resolve_scale_factor(args.scale_factor, args.allow_fractional_scale)?,
+                parts: Option::from(parts),
+                ..args
+            };
+            if let Some(rows) = args.rows {
+                info!(
+                    "--rows zone={rows} overrides scale factor {}: sampling {rows} of the \
+                     source rows it selects",
+                    args.scale_factor
+                );
+            }
 
-            if let Some(part_num) = part {
+            if let Some(part_num) = args.part {
                 // Single part mode - use LIMIT/OFFSET
                 info!("Generating part {} of {} for zone table", part_num, parts);
-                let args = ZoneDfArgs::new(
-                    1.0f64.max(scale_factor),
-                    output_dir,
-                    Option::from(parts),
-                    Option::from(part_num),
-                    max_file_size_mb,
-                    parquet_row_group_bytes,
-                    parquet_compression,
-                );
                 super::generate_zone_parquet_single(args)
                     .await
-                    .map_err(io::Error::other)
+                    .map_err(into_io_error)
             } else {
                 // Multi-part mode - collect once and partition in memory
                 info!("Generating all {} part(s) for zone table", parts);
-                let args = ZoneDfArgs::new(
-                    1.0f64.max(scale_factor),
-                    output_dir,
-                    Option::from(parts),
-                    None,
-                    max_file_size_mb,
-                    parquet_row_group_bytes,
-                    parquet_compression,
-                );
                 super::generate_zone_parquet_multi(args)
                     .await
-                    .map_err(io::Error::other)
+                    .map_err(into_io_error)
+            }
+        }
+    }
+}
+
+/// Prints the plans of the zone generation query and the projection and
+/// predicate its source scans received, warning about filters left above
+/// a scan
+pub async fn explain_zone(args: ZoneDfArgs) -> io::Result<()> {
+    let args = ZoneDfArgs {
+        scale_factor: resolve_scale_factor(args.scale_factor, args.allow_fractional_scale)?,
+        ..args
+    };
+    let ctx = super::zone_session_context().await.map_err(into_io_error)?;
+    let explain = super::explain_zone_query(&ctx, &args)
+        .await
+        .map_err(into_io_error)?;
+
+    println!("== Logical plan ==\n{}\n", explain.logical_plan);
+    println!("== Physical plan ==\n{}", explain.physical_plan);
+    if let Some(plan) = &explain.analyzed_plan {
+        println!("== Analyzed plan ==\n{plan}");
+    }
+    println!("== Source scan pushdown ==");
+    if explain.scans.is_empty() {
+        println!("No Parquet scan in the plan");
+    }
+    for (i, scan) in explain.scans.iter().enumerate() {
+        println!(
+            "Scan {}: projection {}, predicate {}",
+            i + 1,
+            scan.projection.as_deref().unwrap_or("not pushed down"),
+            scan.predicate.as_deref().unwrap_or("not pushed down")
+        );
+    }
+    for filter in &explain.filters_above_scan {
+        warn!("Filter evaluated above a source scan that received no predicate: {filter}");
+    }
+    let columns = explain.scanned_columns();
+    if !columns.is_empty() {
+        println!(
+            "Scanned columns: {}",
+            columns.into_iter().collect::<Vec<_>>().join(", ")
+        );
+    }
+    if let Some(bytes) = &explain.scan_bytes {
+        println!(
+            "Scanned bytes: {} of {} compressed source bytes, {} saved by the projection",
+            bytes.scanned,
+            bytes.total,
+            bytes.saved()
+        );
+        if bytes.names > bytes.names_primary && !args.transform.reads_common_names() {
+            println!(
+                "names: read whole, {} bytes, for names.primary of {} bytes; scans read \
+                 top-level columns only",
+                bytes.names, bytes.names_primary
+            );
+        }
+    }
+    for column in &explain.unexpected_columns {
+        warn!("Source scan reads column {column}, which the zone query doesn't need");
+    }
+    Ok(())
+}
+
+/// Exit code of `--diff-against` when the plan differs from the manifest
+pub const PLAN_DIFFERS_EXIT_CODE: i32 = 3;
+
+/// The error of a `--diff-against` plan that differs from the manifest,
+/// which the CLI exits with [`PLAN_DIFFERS_EXIT_CODE`] for
+#[derive(Debug)]
+pub struct PlanDiffers(String);
+
+impl std::fmt::Display for PlanDiffers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The zone plan differs from {}", self.0)
+    }
+}
+
+impl std::error::Error for PlanDiffers {}
+
+/// Whether `e` is the [`PlanDiffers`] error of [`diff_zone_plan`]
+pub fn is_plan_differs(e: &io::Error) -> bool {
+    e.get_ref().is_some_and(|e| e.is::<PlanDiffers>())
+}
+
+/// Plans the zone table of `args` without generating it and prints how the
+/// plan differs from the manifest at `manifest_path`: the recorded options,
+/// the rows of every part and the columns. Fails with [`PlanDiffers`] if
+/// anything differs.
+pub async fn diff_zone_plan(args: ZoneDfArgs, manifest_path: &Path) -> io::Result<()> {
+    let args = ZoneDfArgs {
+        scale_factor: resolve_scale_factor(args.scale_factor, args.allow_fractional_scale)?,
+        ..args
+    };
+    let old = ZoneManifest::read_file(manifest_path).map_err(into_io_error)?;
+    let ctx = super::zone_session_context().await.map_err(into_io_error)?;
+    let plan = super::plan_zone(&ctx, &args).await.map_err(into_io_error)?;
+    let diff = diff_plan(&old, &plan);
+
+    if diff.is_empty() {
+        println!("No differences from {}", manifest_path.display());
+        return Ok(());
+    }
+    println!("Differences from {}:", manifest_path.display());
+    if !diff.options.is_empty() {
+        println!("Options:");
+        for option in &diff.options {
+            println!("  {option}");
+        }
+    }
+    let (old_rows, new_rows) = diff.total_rows;
+    if old_rows != new_rows || !diff.parts.is_empty() {
+        println!(
+            "Rows: {old_rows} -> {new_rows} ({})",
+            delta(old_rows, new_rows)
+        );
+        let describe = |rows: Option<u64>| rows.map_or("-".to_string(), |r| r.to_string());
+        for part in &diff.parts {
+            println!(
+                "  part {}: {} -> {}{}",
+                part.part,
+                describe(part.old),
+                describe(part.new),
+                match (part.old, part.new) {
+                    (Some(old), Some(new)) => format!(" ({})", delta(old, new)),
+                    _ => String::new(),
+                }
+            );
+        }
+    }
+    if plan.part_rows.is_none() {
+        println!("Part rows: not planned, the parts are cut from the collected table");
+    }
+    if let Some((old_fingerprint, new_fingerprint)) = &diff.fingerprints {
+        match old_fingerprint {
+            Some(old_fingerprint) => {
+                println!("Schema: fingerprint {old_fingerprint} -> {new_fingerprint}")
             }
+            None => println!(
+                "Schema: no columns recorded in the manifest, fingerprint {new_fingerprint} planned"
+            ),
         }
-        _ => Err(io::Error::new(
-            io::ErrorKind::InvalidInput,
-            "Zone table is only supported in --format=parquet.",
+        for change in &diff.columns {
+            match change {
+                ColumnChange::Added(column) => {
+                    println!("  + {}: {}", column.name, column.data_type)
+                }
+                ColumnChange::Removed(column) => {
+                    println!("  - {}: {}", column.name, column.data_type)
+                }
+                ColumnChange::Changed { old, new } => println!(
+                    "  ~ {}: {}{} -> {}{}",
+                    new.name,
+                    old.data_type,
+                    if old.nullable { "" } else { " not null" },
+                    new.data_type,
+                    if new.nullable { "" } else { " not null" }
+                ),
+            }
+        }
+        if diff.columns.is_empty() && old_fingerprint.is_some() {
+            println!("  column order changed");
+        }
+    }
+    Err(io::Error::other(PlanDiffers(
+        manifest_path.display().to_string(),
+    )))
+}
+
+/// A signed row count change, e.g. `+12`
+fn delta(old: u64, new: u64) -> String {
+    if new >= old {
+        format!("+{}", new - old)
+    } else {
+        format!("-{}", old - new)
+    }
+}
+
+/// Keeps I/O errors such as an interrupt intact so the CLI can tell them apart
+fn into_io_error(e: anyhow::Error) -> io::Error {
+    match e.downcast::<io::Error>() {
+        Ok(e) => e,
+        Err(e) => io::Error::other(e),
+    }
+}
+
+/// Refuses `data_dir` when its zone file names embed different scale
+/// factors, unless `force` is set
+fn check_scale_factors(data_dir: &Path, force: bool) -> io::Result<()> {
+    if force {
+        return Ok(());
+    }
+    verify::check_single_scale_factor(data_dir)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+}
+
+/// Prints the number of source rows selected for the zone table as JSON.
+///
+/// With `from_metadata` the rows are counted from the source footers when
+/// their statistics allow it, and `method` tells whether they were.
+pub async fn count_zone(
+    scale_factor: f64,
+    rows: Option<u64>,
+    themes: Vec<ThemeInput>,
+    from_metadata: bool,
+) -> io::Result<()> {
+    let scale_factor = resolve_scale_factor(scale_factor, false)?;
+    let metadata_rows = match from_metadata {
+        true => super::metadata_row_count(&themes, scale_factor)
+            .await
+            .map_err(io::Error::other)?,
+        false => None,
+    };
+    let total_rows = match metadata_rows {
+        // --rows samples that many of the selected rows, if there are more
+        Some(total_rows) => rows.map_or(total_rows, |rows| rows.min(total_rows)) as i64,
+        None => {
+            if from_metadata {
+                info!("Source statistics can't tell the kept rows apart, counting with a scan");
+            }
+            super::count_zone_rows(scale_factor, rows, themes)
+                .await
+                .map_err(io::Error::other)?
+        }
+    };
+    let mut json = serde_json::json!({
+        "table": "zone",
+        "scale_factor": scale_factor,
+        "total_rows": total_rows,
+    });
+    if let Some(rows) = rows {
+        json["rows"] = rows.into();
+    }
+    if from_metadata {
+        json["method"] = match metadata_rows {
+            Some(_) => "metadata",
+            None => "scan",
+        }
+        .into();
+    }
+    println!("{json}");
+    Ok(())
+}
+
+/// Prints an estimate of the source reads of the zone table as JSON, see
+/// [`super::estimate`]
+pub async fn estimate_zone_io(scale_factor: f64, themes: Vec<ThemeInput>) -> io::Result<()> {
+    let scale_factor = resolve_scale_factor(scale_factor, false)?;
+    let estimate = super::estimate_source_io(&themes, scale_factor)
+        .await
+        .map_err(io::Error::other)?;
+    let mut json = serde_json::to_value(estimate).map_err(io::Error::other)?;
+    json["table"] = "zone".into();
+    json["scale_factor"] = scale_factor.into();
+    println!("{json}");
+    Ok(())
+}
+
+/// Benchmarks zone generation without writing files and prints the report
+/// as JSON
+pub async fn bench_zone(args: ZoneDfArgs, iterations: usize) -> io::Result<()> {
+    let args = ZoneDfArgs {
+        scale_factor: resolve_scale_factor(args.scale_factor, args.allow_fractional_scale)?,
+        ..args
+    };
+    let report = super::bench_zone(&args, iterations)
+        .await
+        .map_err(into_io_error)?;
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&report).map_err(io::Error::other)?
+    );
+    Ok(())
+}
+
+/// Reads `total_rows` from a plan file (a zone manifest or `count` output)
+/// made at the zone `scale_factor`, see [`resolve_scale_factor`]
+pub fn read_plan_total_rows(path: &Path, scale_factor: f64) -> io::Result<i64> {
+    ZoneManifest::read_plan_total_rows(path, scale_factor)
+        .map(|rows| rows as i64)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))
+}
+
+/// Compares the zone manifest in `output_dir` with the run about to write
+/// into it and handles a mismatch as `existing` says.
+///
+/// The scale factor is always compared, as the manifest is the only record
+/// of the scale factor of files in the directory; the seed, source,
+/// `--rows`, `--sampling` and `--keep-geometry-type` only when `zone` is
+/// generated.
+pub fn check_existing_outputs(
+    output_dir: &Path,
+    scale_factor: f64,
+    zone: Option<&ZoneDfArgs>,
+    existing: ExistingOutputs,
+) -> io::Result<()> {
+    let Some(manifest) = ZoneManifest::read(output_dir).map_err(io::Error::other)? else {
+        return Ok(());
+    };
+    let scale_factor = match zone {
+        Some(args) => args.scale_factor,
+        None => 1.0f64.max(scale_factor),
+    };
+    let requested = ZoneManifest::new(scale_factor, manifest.parts);
+    let requested = match zone {
+        Some(args) => requested
+            .with_seed(Some(args.transform.seed))
+            .with_source(args.source_provenance())
+            .with_rows(args.rows)
+            .with_limit(args.limit)
+            .with_keep_geometry_types(args.keep_geometry_type_names())
+            .with_sampling(args.sampling_description()),
+        None => requested
+            .with_rows(manifest.rows)
+            .with_limit(manifest.limit)
+            .with_keep_geometry_types(manifest.keep_geometry_types.clone())
+            .with_sampling(manifest.sampling.clone()),
+    };
+    let differences = manifest.differences(&requested);
+    if differences.is_empty() {
+        return Ok(());
+    }
+
+    match existing {
+        ExistingOutputs::Refuse => Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!(
+                "Output directory {} holds outputs of a different run:\n  {}\n\
+                 Rerun with --overwrite to delete them first, or with \
+                 --allow-mixed-outputs to keep them next to the new files",
+                output_dir.display(),
+                differences.join("\n  ")
+            ),
         )),
+        ExistingOutputs::Overwrite => {
+            info!(
+                "Deleting {} zone file(s) of a different run from {}",
+                manifest.files.len(),
+                output_dir.display()
+            );
+            manifest
+                .remove_outputs(output_dir)
+                .map_err(io::Error::other)
+        }
+        ExistingOutputs::AllowMixed => {
+            warn!(
+                "Output directory {} holds outputs of a different run ({}), keeping them",
+                output_dir.display(),
+                differences.join("; ")
+            );
+            Ok(())
+        }
+    }
+}
+
+/// Reads the part boundaries recorded in a plan file made at the zone
+/// `scale_factor`, if any
+pub fn read_plan_boundaries(
+    path: &Path,
+    scale_factor: f64,
+) -> io::Result<Option<Vec<super::PartBoundary>>> {
+    ZoneManifest::read_plan_boundaries(path, scale_factor)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))
+}
+
+/// Writes `_SUCCESS` for a dataset generated by separate single-part runs
+/// once its manifest shows every part is complete
+pub fn finalize_zone(data_dir: &Path) -> io::Result<()> {
+    let manifest = ZoneManifest::read(data_dir)
+        .map_err(io::Error::other)?
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("No zone manifest found in {}", data_dir.display()),
+            )
+        })?;
+    let marker = manifest.finalize(data_dir).map_err(io::Error::other)?;
+    println!("Wrote {}", marker.display());
+    Ok(())
+}
+
+/// Re-cuts the dataset in `data_dir` into `new_parts` parts from its files,
+/// printing the parts that must be regenerated from the source
+pub fn extend_zone(
+    data_dir: &Path,
+    new_parts: i32,
+    compression: Compression,
+    row_group_bytes: i64,
+) -> io::Result<()> {
+    let plan = extend::extend_zone(data_dir, new_parts, compression, row_group_bytes)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+    println!(
+        "{}: cut {} of {new_parts} part(s) from the existing files",
+        data_dir.display(),
+        plan.local.len()
+    );
+    if plan.regenerate.is_empty() {
+        return Ok(());
+    }
+    for part in &plan.regenerate {
+        println!("REGENERATE part {part}: --parts {new_parts} --part {part}");
+    }
+    Err(io::Error::other(format!(
+        "{} part(s) of {} hold rows of missing or damaged files; regenerate them with \
+         --parts {new_parts} --part N, then run finalize",
+        plan.regenerate.len(),
+        data_dir.display()
+    )))
+}
+
+/// Adds the columns of `options` to the zone files of `data_dir`, printing
+/// what was done to every file
+pub fn augment_zone(data_dir: &Path, options: &AugmentOptions) -> io::Result<()> {
+    let files = augment::augment_zone(data_dir, options)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+    for file in &files {
+        match file.added.is_empty() {
+            true => println!("SKIPPED {}: already holds the columns", file.path),
+            false => println!("ADDED {}: {}", file.path, file.added.join(", ")),
+        }
+    }
+    println!(
+        "{}: {} of {} file(s) rewritten",
+        data_dir.display(),
+        files.iter().filter(|f| !f.added.is_empty()).count(),
+        files.len()
+    );
+    Ok(())
+}
+
+/// Writes `per_kind` queries of every kind for `scale_factor` into `dir`
+pub fn write_zone_queries(
+    dir: &Path,
+    scale_factor: f64,
+    seed: u64,
+    per_kind: usize,
+) -> io::Result<()> {
+    let written =
+        queries::write_queries(dir, scale_factor, seed, per_kind).map_err(io::Error::other)?;
+    println!("Wrote {} queries to {}", written.len(), dir.display());
+    Ok(())
+}
+
+/// Writes the zone of every trip pickup and dropoff point into
+/// `trip_zone.parquet` of `data_dir`
+pub fn materialize_zone_containment(data_dir: &Path, jobs: usize, force: bool) -> io::Result<()> {
+    check_scale_factors(data_dir, force)?;
+    let summary =
+        containment::materialize_zone_containment(data_dir, jobs).map_err(io::Error::other)?;
+    println!(
+        "Wrote {}: {} trip(s), {} pickup(s) and {} dropoff(s) in a zone",
+        data_dir.join(containment::OUTPUT_FILE).display(),
+        summary.trips,
+        summary.pickups_in_zone,
+        summary.dropoffs_in_zone
+    );
+    Ok(())
+}
+
+/// Writes the pairs of adjacent zones into `zone_adjacency.parquet` of
+/// `data_dir`
+pub fn materialize_zone_adjacency(
+    data_dir: &Path,
+    jobs: usize,
+    same_subtype_only: bool,
+    force: bool,
+) -> io::Result<()> {
+    check_scale_factors(data_dir, force)?;
+    let summary = adjacency::materialize_zone_adjacency(data_dir, jobs, same_subtype_only)
+        .map_err(io::Error::other)?;
+    println!(
+        "Wrote {}: {} adjacent pair(s) among {} zone(s)",
+        data_dir.join(adjacency::OUTPUT_FILE).display(),
+        summary.pairs,
+        summary.zones
+    );
+    Ok(())
+}
+
+/// Packages the dataset of `data_dir` into the `.tar.zst` archive `out`
+pub fn package_dataset(
+    data_dir: &Path,
+    out: &Path,
+    check_hashes: bool,
+    force: bool,
+) -> io::Result<()> {
+    check_scale_factors(data_dir, force)?;
+    let summary =
+        package::package_dataset(data_dir, out, check_hashes).map_err(io::Error::other)?;
+    println!(
+        "Wrote {}: {} file(s), {} bytes",
+        out.display(),
+        summary.files,
+        summary.bytes
+    );
+    Ok(())
+}
+
+/// Extracts a dataset archive into `output_dir` and verifies the content
+/// hashes of the extracted zone files
+pub fn unpack_dataset(archive: &Path, output_dir: &Path) -> io::Result<()> {
+    let summary = package::unpack_dataset(archive, output_dir).map_err(io::Error::other)?;
+    println!(
+        "Extracted {} file(s), {} bytes, into {}",
+        summary.files,
+        summary.bytes,
+        output_dir.display()
+    );
+    if summary.mismatched.is_empty() {
+        println!("{}: all content hashes match", output_dir.display());
+        return Ok(());
+    }
+    for name in &summary.mismatched {
+        println!("MISMATCH {name}");
+    }
+    Err(io::Error::other(format!(
+        "{} part(s) in {} do not match their recorded content hash",
+        summary.mismatched.len(),
+        output_dir.display()
+    )))
+}
+
+/// Verifies the content hashes of one dataset against its manifest and footers
+pub fn verify_zone(data_dir: &Path, force: bool) -> io::Result<()> {
+    check_scale_factors(data_dir, force)?;
+    let truncated = verify::truncated_files(data_dir).map_err(io::Error::other)?;
+    for name in &truncated {
+        println!("TRUNCATED {name}");
+    }
+    if !truncated.is_empty() {
+        warn!(
+            "{} zone file(s) in {} were cut short by --limit and are NOT benchmark data",
+            truncated.len(),
+            data_dir.display()
+        );
+    }
+    let mismatched = verify::verify_dir(data_dir).map_err(io::Error::other)?;
+    let attribution = verify::attribution_problem(data_dir).map_err(io::Error::other)?;
+    if let Some(problem) = &attribution {
+        println!("ATTRIBUTION {problem}");
+    }
+    if mismatched.is_empty() {
+        return match attribution {
+            None => {
+                println!("{}: all content hashes match", data_dir.display());
+                Ok(())
+            }
+            Some(problem) => Err(io::Error::other(format!(
+                "{}: {problem}; the dataset was generated with --include-attribution",
+                data_dir.display()
+            ))),
+        };
+    }
+    for name in &mismatched {
+        println!("MISMATCH {name}");
+    }
+    Err(io::Error::other(format!(
+        "{} part(s) in {} do not match their recorded content hash",
+        mismatched.len(),
+        data_dir.display()
+    )))
+}
+
+/// Compares two datasets part by part and reports the parts that differ
+pub fn compare_zone(left: &Path, right: &Path, force: bool) -> io::Result<()> {
+    check_scale_factors(left, force)?;
+    check_scale_factors(right, force)?;
+    let report = verify::compare_dirs(left, right).map_err(io::Error::other)?;
+    for name in &report.identical {
+        println!("SAME      {name}");
+    }
+    for name in &report.different {
+        println!("DIFFERENT {name}");
+    }
+    for name in &report.only_in_left {
+        println!("ONLY IN {} {name}", left.display());
+    }
+    for name in &report.only_in_right {
+        println!("ONLY IN {} {name}", right.display());
+    }
+    if report.is_identical() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!(
+            "Datasets differ: {} different, {} missing",
+            report.different.len(),
+            report.only_in_left.len() + report.only_in_right.len()
+        )))
+    }
+}
+
+/// Diffs two datasets row by row, printing a summary and optionally writing
+/// every change as JSON
+pub fn diff_zone(
+    left: &Path,
+    right: &Path,
+    tolerance: f64,
+    json_path: Option<&Path>,
+    force: bool,
+) -> io::Result<()> {
+    check_scale_factors(left, force)?;
+    check_scale_factors(right, force)?;
+    let report = diff::diff_dirs(left, right, tolerance).map_err(io::Error::other)?;
+
+    println!("{}: {} row(s)", left.display(), report.left_rows);
+    println!("{}: {} row(s)", right.display(), report.right_rows);
+    println!("Added:   {}", report.added.len());
+    println!("Removed: {}", report.removed.len());
+    println!("Changed: {}", report.changed.len());
+    for (column, count) in report.changes_per_column() {
+        println!("  {column}: {count}");
+    }
+
+    if let Some(path) = json_path {
+        let json = serde_json::to_string_pretty(&report).map_err(io::Error::other)?;
+        std::fs::write(path, json)?;
+        info!("Wrote diff details to {}", path.display());
+    }
+
+    if report.is_empty() {
+        Ok(())
+    } else {
+        Err(io::Error::other("Datasets differ"))
+    }
+}
+
+/// Prints row count deltas per country and subtype, the added and removed
+/// ids and the bbox drift of two datasets, failing when the largest
+/// relative difference exceeds `tolerance_pct`
+pub fn diff_zone_stats(
+    old: &Path,
+    new: &Path,
+    tolerance_pct: f64,
+    samples: usize,
+    json_path: Option<&Path>,
+    force: bool,
+) -> io::Result<()> {
+    check_scale_factors(old, force)?;
+    check_scale_factors(new, force)?;
+    let report = diff_stats::diff_stats(old, new, samples).map_err(into_io_error)?;
+
+    println!("{}: {} row(s)", old.display(), report.old_rows);
+    println!("{}: {} row(s)", new.display(), report.new_rows);
+    let print_deltas = |title: &str, deltas: &std::collections::BTreeMap<String, CountDelta>| {
+        println!(
+            "{title:<16} {:>10} {:>10} {:>10} {:>9}",
+            "old", "new", "delta", "delta%"
+        );
+        for (group, delta) in deltas {
+            println!(
+                "{group:<16} {:>10} {:>10} {:>+10} {:>+8.2}%",
+                delta.old, delta.new, delta.delta, delta.delta_pct
+            );
+        }
+    };
+    print_deltas("Country", &report.countries);
+    print_deltas("Subtype", &report.subtypes);
+    for (label, ids) in [("Added", &report.added), ("Removed", &report.removed)] {
+        println!("{label} z_gersid: {}", ids.count);
+        for id in &ids.samples {
+            println!("  {id}");
+        }
+    }
+    println!("Bbox drift (degrees):");
+    for (country, drift) in &report.bbox_drift {
+        println!("  {country:<14} {:.6}", drift.max_shift);
+    }
+
+    if let Some(path) = json_path {
+        let json = serde_json::to_string_pretty(&report).map_err(io::Error::other)?;
+        std::fs::write(path, json)?;
+        info!("Wrote statistics diff to {}", path.display());
+    }
+
+    let max_delta_pct = report.max_delta_pct();
+    if max_delta_pct > tolerance_pct {
+        return Err(io::Error::other(format!(
+            "Datasets differ by {max_delta_pct:.2}%, more than --tolerance-pct {tolerance_pct}"
+        )));
+    }
+    Ok(())
+}
+
+/// Prints distinct counts, top values, the geometry vertex histogram and the
+/// geometry types of a dataset, optionally writing the full profile as JSON
+pub fn profile_zone(
+    data_dir: &Path,
+    top: usize,
+    json_path: Option<&Path>,
+    force: bool,
+) -> io::Result<()> {
+    check_scale_factors(data_dir, force)?;
+    let report = profile::profile_dir(data_dir, top).map_err(io::Error::other)?;
+
+    println!("{}: {} row(s)", data_dir.display(), report.rows);
+    for (name, column) in &report.columns {
+        println!(
+            "{name}: {} distinct, {} null(s)",
+            column.distinct, column.nulls
+        );
+        for value in &column.top {
+            println!("  {:>10}  {}", value.count, value.value);
+        }
+    }
+    println!("Geometry vertex counts:");
+    for bucket in &report.vertex_histogram {
+        println!("  {:>6}..{:<6} {}", bucket.min, bucket.max, bucket.count);
+    }
+    if report.unreadable_geometries > 0 {
+        println!("  null or invalid: {}", report.unreadable_geometries);
+    }
+    println!("Geometry types:");
+    for (name, count) in &report.geometry_types {
+        println!("  {name:<18} {count}");
+    }
+
+    if let Some(path) = json_path {
+        let json = serde_json::to_string_pretty(&report).map_err(io::Error::other)?;
+        std::fs::write(path, json)?;
+        info!("Wrote profile to {}", path.display());
+    }
+    Ok(())
+}
+
+/// Prints the ring checks of every zone file and of the whole dataset,
+/// changing nothing
+pub fn validate_zone_geometries(
+    data_dir: &Path,
+    json_path: Option<&Path>,
+    force: bool,
+) -> io::Result<()> {
+    check_scale_factors(data_dir, force)?;
+    let report = geometry_check::check_geometries_dir(data_dir).map_err(io::Error::other)?;
+
+    let print = |name: &str, check: &GeometryCheck| {
+        println!(
+            "{name}: {} geometries, {} null or invalid",
+            check.geometries, check.unreadable
+        );
+        println!(
+            "  exteriors: {} counterclockwise, {} clockwise",
+            check.ccw_exteriors, check.cw_exteriors
+        );
+        println!(
+            "  holes: {}, {} wound counterclockwise",
+            check.holes, check.misoriented_holes
+        );
+        println!(
+            "  rings: {} self-intersecting, {} unclosed, {} degenerate",
+            check.self_intersecting_rings, check.unclosed_rings, check.degenerate_rings
+        );
+        println!("  duplicate consecutive points: {}", check.duplicate_points);
+    };
+    for (name, check) in &report.files {
+        print(name, check);
+    }
+    print("Overall", &report.total);
+
+    if let Some(path) = json_path {
+        let json = serde_json::to_string_pretty(&report).map_err(io::Error::other)?;
+        std::fs::write(path, json)?;
+        info!("Wrote geometry checks to {}", path.display());
+    }
+    Ok(())
+}
+
+pub fn export_zone_tiles(
+    data_dir: &Path,
+    output: &Path,
+    min_zoom: u8,
+    max_zoom: u8,
+    force: bool,
+) -> io::Result<()> {
+    check_scale_factors(data_dir, force)?;
+    let report =
+        tiles::export_tiles(data_dir, output, min_zoom, max_zoom).map_err(into_io_error)?;
+    for (zoom, tiles) in &report.tiles {
+        info!("Zoom {zoom}: {tiles} tile(s)");
     }
+    info!(
+        "Wrote {} zone(s) to {} ({} bytes)",
+        report.features,
+        output.display(),
+        report.bytes
+    );
+    Ok(())
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -82,4 +896,6 @@ pub enum OutputFormat {
     Tbl,
     Csv,
     Parquet,
+    Orc,
+    Avro,
 }
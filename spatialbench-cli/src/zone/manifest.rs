@@ -0,0 +1,800 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! JSON manifest describing the zone part files in an output directory
+
+use anyhow::{anyhow, Result};
+use arrow_schema::Schema;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::{File, OpenOptions};
+use std::path::{Path, PathBuf};
+
+use crate::output_lock::is_current;
+
+use super::partition::{PartBoundary, PartExtent};
+use super::sink::{sync_closed, sync_dir, Fsync};
+
+pub const MANIFEST_FILE_NAME: &str = "zone.manifest.json";
+
+/// Marker written next to the part files once every part is complete
+pub const SUCCESS_FILE_NAME: &str = "_SUCCESS";
+
+/// Lock file held while the manifest is read, updated and replaced
+const MANIFEST_LOCK_FILE_NAME: &str = "zone.manifest.lock";
+
+/// Single file holding every part, written with `--also-merge`; `zone.orc`
+/// for ORC output
+pub const MERGED_FILE_NAME: &str = "zone.parquet";
+
+/// One written part file
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ManifestPart {
+    pub part: i32,
+    /// Path of the part file relative to the output directory
+    pub path: String,
+    pub rows: u64,
+    pub content_sha256: String,
+    /// Index of the file among those the part was split into by
+    /// `--max-rows-per-file`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_index: Option<usize>,
+    /// Offset of the first row of a split file within its part
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub row_offset: Option<u64>,
+    /// Size of the Parquet footer, which remote readers fetch before any
+    /// data
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub footer_bytes: Option<u64>,
+}
+
+/// One column of the written zone files
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ManifestColumn {
+    pub name: String,
+    /// Arrow type of the column, as displayed by arrow
+    pub data_type: String,
+    pub nullable: bool,
+}
+
+impl ManifestColumn {
+    /// The columns of `schema`, in order
+    pub fn of_schema(schema: &Schema) -> Vec<Self> {
+        schema
+            .fields()
+            .iter()
+            .map(|field| Self {
+                name: field.name().clone(),
+                data_type: field.data_type().to_string(),
+                nullable: field.is_nullable(),
+            })
+            .collect()
+    }
+}
+
+/// The single file holding the rows of every part in part order
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MergedFile {
+    /// Path relative to the output directory
+    pub path: String,
+    pub rows: u64,
+    pub content_sha256: String,
+    /// Size of the Parquet footer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub footer_bytes: Option<u64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ZoneManifest {
+    pub table: String,
+    pub scale_factor: f64,
+    pub parts: i32,
+    /// Filtered source row count the parts were planned against, when known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_rows: Option<u64>,
+    /// Row range of every part when parts were balanced by something other
+    /// than row count
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub boundaries: Option<Vec<PartBoundary>>,
+    /// Latitude and longitude band of every part when zones were assigned
+    /// to parts by centroid
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extents: Option<Vec<PartExtent>>,
+    /// Input themes and where they were read from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// `--seed` of the generated values that are not fixed by the benchmark
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    /// `--rows` count sampled from the source instead of all the rows
+    /// selected for the scale factor
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rows: Option<u64>,
+    /// `--keep-geometry-type` names the source rows were filtered to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keep_geometry_types: Option<Vec<String>>,
+    /// How the `rows` sample was picked, when not by id hash alone
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sampling: Option<String>,
+    /// `--limit` on the rows kept, the first in zone key order
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u64>,
+    /// Whether `limit` cut the table short, so the files are not benchmark
+    /// data
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+    /// Numbered zone schema version of the files, or `custom`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema_version: Option<String>,
+    /// SHA-256 of the `ATTRIBUTION.txt` written by `--include-attribution`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attribution_sha256: Option<String>,
+    /// Columns of the files, compared by `--diff-against`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub columns: Option<Vec<ManifestColumn>>,
+    pub files: Vec<ManifestPart>,
+    /// Merged copy of the parts, not counted as a part itself
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub merged: Option<MergedFile>,
+}
+
+impl ZoneManifest {
+    pub fn new(scale_factor: f64, parts: i32) -> Self {
+        Self {
+            table: "zone".to_string(),
+            scale_factor,
+            parts,
+            total_rows: None,
+            boundaries: None,
+            extents: None,
+            source: None,
+            seed: None,
+            rows: None,
+            keep_geometry_types: None,
+            sampling: None,
+            limit: None,
+            truncated: false,
+            schema_version: None,
+            attribution_sha256: None,
+            columns: None,
+            files: Vec::new(),
+            merged: None,
+        }
+    }
+
+    pub fn with_total_rows(mut self, total_rows: Option<u64>) -> Self {
+        self.total_rows = total_rows;
+        self
+    }
+
+    pub fn with_boundaries(mut self, boundaries: Option<Vec<PartBoundary>>) -> Self {
+        self.boundaries = boundaries;
+        self
+    }
+
+    pub fn with_extents(mut self, extents: Option<Vec<PartExtent>>) -> Self {
+        self.extents = extents;
+        self
+    }
+
+    pub fn with_source(mut self, source: String) -> Self {
+        self.source = Some(source);
+        self
+    }
+
+    pub fn with_seed(mut self, seed: Option<u64>) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn with_rows(mut self, rows: Option<u64>) -> Self {
+        self.rows = rows;
+        self
+    }
+
+    pub fn with_keep_geometry_types(mut self, keep_geometry_types: Option<Vec<String>>) -> Self {
+        self.keep_geometry_types = keep_geometry_types;
+        self
+    }
+
+    pub fn with_sampling(mut self, sampling: Option<String>) -> Self {
+        self.sampling = sampling;
+        self
+    }
+
+    pub fn with_limit(mut self, limit: Option<u64>) -> Self {
+        self.limit = limit;
+        self.truncated = limit.is_some();
+        self
+    }
+
+    pub fn with_schema_version(mut self, schema_version: Option<String>) -> Self {
+        self.schema_version = schema_version;
+        self
+    }
+
+    pub fn with_attribution_sha256(mut self, attribution_sha256: Option<String>) -> Self {
+        self.attribution_sha256 = attribution_sha256;
+        self
+    }
+
+    pub fn with_columns(mut self, columns: Option<Vec<ManifestColumn>>) -> Self {
+        self.columns = columns;
+        self
+    }
+
+    /// SHA-256 of the recorded columns, `None` when they were not recorded
+    pub fn schema_fingerprint(&self) -> Option<String> {
+        let columns = self.columns.as_ref()?;
+        let json = serde_json::to_vec(columns).expect("columns serialize to JSON");
+        Some(format!("{:x}", Sha256::digest(json)))
+    }
+
+    pub fn path(output_dir: &Path) -> PathBuf {
+        output_dir.join(MANIFEST_FILE_NAME)
+    }
+
+    /// Reads the manifest in `output_dir`, returning `None` if there is none
+    pub fn read(output_dir: &Path) -> Result<Option<Self>> {
+        let path = Self::path(output_dir);
+        if !path.exists() {
+            return Ok(None);
+        }
+        Self::read_file(&path).map(Some)
+    }
+
+    /// Reads the manifest file at `path`
+    pub fn read_file(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("Failed reading {}: {e}", path.display()))?;
+        serde_json::from_str(&text).map_err(|e| anyhow!("Failed parsing {}: {e}", path.display()))
+    }
+
+    /// Atomically writes the manifest into `output_dir`
+    pub fn write(&self, output_dir: &Path) -> Result<()> {
+        let path = Self::path(output_dir);
+        let temp_path = path.with_extension("inprogress");
+        std::fs::write(&temp_path, serde_json::to_string_pretty(self)?)?;
+        std::fs::rename(&temp_path, &path)?;
+        debug!("Wrote manifest {}", path.display());
+        Ok(())
+    }
+
+    /// Replaces every file of `part` with `files`, keeping entries sorted.
+    /// A part split by `--max-rows-per-file` has several files.
+    pub fn replace_part(&mut self, part: i32, files: Vec<ManifestPart>) {
+        self.files.retain(|f| f.part != part);
+        self.files.extend(files);
+        self.files.sort_by_key(|f| (f.part, f.file_index));
+    }
+
+    /// Records the files of a written part in the manifest of `output_dir`,
+    /// using `self` as the description of the run that wrote it.
+    ///
+    /// Entries from other invocations writing into the same directory (for
+    /// example distributed single-part workers) are preserved as long as the
+    /// scale factor and part count agree; otherwise the manifest is replaced.
+    pub fn record_part(self, output_dir: &Path, part: i32, files: Vec<ManifestPart>) -> Result<()> {
+        let _lock = ManifestLock::acquire(output_dir)?;
+        let mut manifest = match Self::read(output_dir)? {
+            Some(m) if m.scale_factor == self.scale_factor && m.parts == self.parts => m,
+            _ => self.clone(),
+        };
+        if self.total_rows.is_some() {
+            manifest.total_rows = self.total_rows;
+        }
+        if self.boundaries.is_some() {
+            manifest.boundaries = self.boundaries;
+        }
+        if self.extents.is_some() {
+            manifest.extents = self.extents;
+        }
+        if self.source.is_some() {
+            manifest.source = self.source;
+        }
+        if self.seed.is_some() {
+            manifest.seed = self.seed;
+        }
+        if self.rows.is_some() {
+            manifest.rows = self.rows;
+        }
+        if self.keep_geometry_types.is_some() {
+            manifest.keep_geometry_types = self.keep_geometry_types;
+        }
+        if self.sampling.is_some() {
+            manifest.sampling = self.sampling;
+        }
+        if self.limit.is_some() {
+            manifest.limit = self.limit;
+            manifest.truncated = self.truncated;
+        }
+        if self.schema_version.is_some() {
+            manifest.schema_version = self.schema_version;
+        }
+        if self.attribution_sha256.is_some() {
+            manifest.attribution_sha256 = self.attribution_sha256;
+        }
+        if self.columns.is_some() {
+            manifest.columns = self.columns;
+        }
+        manifest.replace_part(part, files);
+        manifest.write(output_dir)
+    }
+
+    /// Records the merged copy of the parts in the manifest of `output_dir`,
+    /// which the parts were recorded in first
+    pub fn record_merged(output_dir: &Path, merged: MergedFile) -> Result<()> {
+        let _lock = ManifestLock::acquire(output_dir)?;
+        let mut manifest = Self::read(output_dir)?.ok_or_else(|| {
+            anyhow!(
+                "No zone manifest in {} to record {} in",
+                output_dir.display(),
+                merged.path
+            )
+        })?;
+        manifest.merged = Some(merged);
+        manifest.write(output_dir)
+    }
+
+    /// Drops the entries for `parts` from the manifest of `output_dir`,
+    /// deleting the manifest once it lists no files
+    pub fn remove_parts(output_dir: &Path, parts: &[i32]) -> Result<()> {
+        let _lock = ManifestLock::acquire(output_dir)?;
+        let Some(mut manifest) = Self::read(output_dir)? else {
+            return Ok(());
+        };
+        manifest.files.retain(|f| !parts.contains(&f.part));
+        if manifest.files.is_empty() {
+            std::fs::remove_file(Self::path(output_dir))?;
+            return Ok(());
+        }
+        manifest.write(output_dir)
+    }
+
+    /// Describes how the run recorded in `self` differs from `requested`
+    /// in what it generates. Fields missing from either side, as in
+    /// manifests written before they were recorded, are not compared.
+    pub fn differences(&self, requested: &ZoneManifest) -> Vec<String> {
+        let mut differences = Vec::new();
+        if self.scale_factor != requested.scale_factor {
+            differences.push(format!(
+                "scale factor: {} in the existing outputs, {} requested",
+                self.scale_factor, requested.scale_factor
+            ));
+        }
+        if let (Some(existing), Some(seed)) = (self.seed, requested.seed) {
+            if existing != seed {
+                differences.push(format!(
+                    "seed: {existing} in the existing outputs, {seed} requested"
+                ));
+            }
+        }
+        if let (Some(existing), Some(source)) = (&self.source, &requested.source) {
+            if existing != source {
+                differences.push(format!(
+                    "source: {existing} in the existing outputs, {source} requested"
+                ));
+            }
+        }
+        if self.rows != requested.rows {
+            let describe = |rows: Option<u64>| match rows {
+                Some(rows) => format!("--rows zone={rows}"),
+                None => "all rows".to_string(),
+            };
+            differences.push(format!(
+                "rows: {} in the existing outputs, {} requested",
+                describe(self.rows),
+                describe(requested.rows)
+            ));
+        }
+        if self.rows.is_some() && self.sampling != requested.sampling {
+            let describe = |sampling: &Option<String>| match sampling {
+                Some(sampling) => sampling.clone(),
+                None => "uniform".to_string(),
+            };
+            differences.push(format!(
+                "sampling: {} in the existing outputs, {} requested",
+                describe(&self.sampling),
+                describe(&requested.sampling)
+            ));
+        }
+        if self.limit != requested.limit {
+            let describe = |limit: Option<u64>| match limit {
+                Some(limit) => format!("--limit {limit}"),
+                None => "no limit".to_string(),
+            };
+            differences.push(format!(
+                "limit: {} in the existing outputs, {} requested",
+                describe(self.limit),
+                describe(requested.limit)
+            ));
+        }
+        if self.keep_geometry_types != requested.keep_geometry_types {
+            let describe = |types: &Option<Vec<String>>| match types {
+                Some(types) => format!("--keep-geometry-type {}", types.join(",")),
+                None => "all types".to_string(),
+            };
+            differences.push(format!(
+                "geometry types: {} in the existing outputs, {} requested",
+                describe(&self.keep_geometry_types),
+                describe(&requested.keep_geometry_types)
+            ));
+        }
+        differences
+    }
+
+    /// Deletes the part files listed in the manifest, their `_SUCCESS`
+    /// marker and the manifest itself from `output_dir`
+    pub fn remove_outputs(&self, output_dir: &Path) -> Result<()> {
+        let mut paths: Vec<PathBuf> = Vec::new();
+        for file in &self.files {
+            let path = output_dir.join(&file.path);
+            if let Some(parent) = path.parent() {
+                paths.push(parent.join(SUCCESS_FILE_NAME));
+            }
+            paths.push(path);
+        }
+        if let Some(merged) = &self.merged {
+            paths.push(output_dir.join(&merged.path));
+        }
+        paths.push(Self::path(output_dir));
+        for path in paths {
+            match std::fs::remove_file(&path) {
+                Ok(()) => debug!("Removed {}", path.display()),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(anyhow!("Failed removing {}: {e}", path.display())),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn find(&self, path: &str) -> Option<&ManifestPart> {
+        self.files.iter().find(|f| f.path == path)
+    }
+
+    /// Reads the planned total row count from a plan file.
+    ///
+    /// Any JSON document with `scale_factor` and `total_rows` fields is
+    /// accepted, which covers both zone manifests and the output of the
+    /// `count` subcommand.
+    pub fn read_plan_total_rows(path: &Path, scale_factor: f64) -> Result<u64> {
+        read_plan(path, scale_factor)?
+            .total_rows
+            .ok_or_else(|| anyhow!("Plan file {} has no total_rows", path.display()))
+    }
+
+    /// Reads the part boundaries from a plan file, if it records any
+    pub fn read_plan_boundaries(
+        path: &Path,
+        scale_factor: f64,
+    ) -> Result<Option<Vec<PartBoundary>>> {
+        Ok(read_plan(path, scale_factor)?.boundaries)
+    }
+
+    /// Returns the parts in `1..=parts` that have no entry
+    pub fn missing_parts(&self) -> Vec<i32> {
+        (1..=self.parts)
+            .filter(|part| !self.files.iter().any(|f| f.part == *part))
+            .collect()
+    }
+
+    /// Checks that every part is recorded and present on disk, then writes
+    /// the `_SUCCESS` marker next to the part files, synced to disk
+    pub fn finalize(&self, output_dir: &Path) -> Result<PathBuf> {
+        self.check_complete(output_dir)?;
+        let parts_dir = self
+            .files
+            .first()
+            .and_then(|f| output_dir.join(&f.path).parent().map(Path::to_path_buf))
+            .unwrap_or_else(|| output_dir.to_path_buf());
+        write_success_marker(&parts_dir, Fsync::PerPart)
+    }
+
+    /// Checks that every part is recorded and its files are present in
+    /// `output_dir`
+    pub fn check_complete(&self, output_dir: &Path) -> Result<()> {
+        let missing = self.missing_parts();
+        if !missing.is_empty() {
+            return Err(anyhow!(
+                "Manifest is incomplete: {} of {} part(s) missing: {:?}",
+                missing.len(),
+                self.parts,
+                missing
+            ));
+        }
+
+        for file in &self.files {
+            let path = output_dir.join(&file.path);
+            if !path.is_file() {
+                return Err(anyhow!(
+                    "Part {} is recorded in the manifest but {} does not exist",
+                    file.part,
+                    path.display()
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The fields of a plan file used to plan parts
+#[derive(Deserialize)]
+struct Plan {
+    scale_factor: f64,
+    total_rows: Option<u64>,
+    #[serde(default)]
+    boundaries: Option<Vec<PartBoundary>>,
+}
+
+fn read_plan(path: &Path, scale_factor: f64) -> Result<Plan> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("Failed reading plan file {}: {e}", path.display()))?;
+    let plan: Plan = serde_json::from_str(&text)
+        .map_err(|e| anyhow!("Failed parsing plan file {}: {e}", path.display()))?;
+    if plan.scale_factor != scale_factor {
+        return Err(anyhow!(
+            "Plan file {} is for scale factor {}, not {}",
+            path.display(),
+            plan.scale_factor,
+            scale_factor
+        ));
+    }
+    Ok(plan)
+}
+
+/// Exclusive lock on the manifest of an output directory, released when
+/// dropped.
+///
+/// `--part` workers share the lock on the output directory and record
+/// their parts in the one manifest, so each holds this lock from reading
+/// the manifest to replacing it. The holder removes the lock file before
+/// releasing it, as [`crate::output_lock::OutputLock`] does.
+struct ManifestLock {
+    file: File,
+    path: PathBuf,
+}
+
+impl ManifestLock {
+    fn acquire(output_dir: &Path) -> Result<Self> {
+        let path = output_dir.join(MANIFEST_LOCK_FILE_NAME);
+        loop {
+            let file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(&path)
+                .map_err(|e| anyhow!("Failed opening {}: {e}", path.display()))?;
+            file.lock()?;
+            // The previous holder may have removed the file in between
+            if is_current(&file, &path)? {
+                return Ok(Self { file, path });
+            }
+        }
+    }
+}
+
+impl Drop for ManifestLock {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            warn!(
+                "Failed to remove the lock file {}: {e}",
+                self.path.display()
+            );
+        }
+        let _ = self.file.unlock();
+    }
+}
+
+/// Writes an empty `_SUCCESS` file into `dir`, syncing it and its
+/// directory entry unless `fsync` is [`Fsync::Never`]
+pub fn write_success_marker(dir: &Path, fsync: Fsync) -> Result<PathBuf> {
+    let path = dir.join(SUCCESS_FILE_NAME);
+    std::fs::write(&path, b"")?;
+    sync_closed(&path, fsync)?;
+    sync_dir(&path, fsync)?;
+    debug!("Wrote {}", path.display());
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(part: i32) -> ManifestPart {
+        ManifestPart {
+            part,
+            path: format!("zone/zone.{part}.parquet"),
+            rows: 10,
+            content_sha256: format!("{part:064}"),
+            file_index: None,
+            row_offset: None,
+            footer_bytes: None,
+        }
+    }
+
+    #[test]
+    fn test_record_part_merges_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let run = ZoneManifest::new(1.0, 2);
+        run.clone()
+            .with_total_rows(Some(20))
+            .record_part(dir.path(), 2, vec![entry(2)])
+            .unwrap();
+        run.record_part(dir.path(), 1, vec![entry(1)]).unwrap();
+
+        let manifest = ZoneManifest::read(dir.path()).unwrap().unwrap();
+        assert_eq!(manifest.files, vec![entry(1), entry(2)]);
+        assert_eq!(manifest.total_rows, Some(20));
+
+        // A different configuration starts a fresh manifest
+        ZoneManifest::new(10.0, 2)
+            .record_part(dir.path(), 1, vec![entry(1)])
+            .unwrap();
+        let manifest = ZoneManifest::read(dir.path()).unwrap().unwrap();
+        assert_eq!(manifest.files, vec![entry(1)]);
+        assert_eq!(manifest.total_rows, None);
+    }
+
+    #[test]
+    fn test_concurrent_workers_record_every_part() {
+        use crate::output_lock::{LockHolder, LockMode, OutputLock};
+        use std::sync::{Arc, Barrier};
+
+        let dir = tempfile::tempdir().unwrap();
+        let workers = 2;
+        let parts_per_worker = 25;
+        let parts = workers * parts_per_worker;
+        let barrier = Arc::new(Barrier::new(workers as usize));
+        let handles: Vec<_> = (0..workers)
+            .map(|worker| {
+                let output_dir = dir.path().to_path_buf();
+                let barrier = Arc::clone(&barrier);
+                std::thread::spawn(move || {
+                    let holder = LockHolder::current("run", LockMode::Shared);
+                    let _lock = OutputLock::acquire(&output_dir, &holder, false).unwrap();
+                    barrier.wait();
+                    for part in (1..=parts).skip(worker as usize).step_by(workers as usize) {
+                        ZoneManifest::new(1.0, parts)
+                            .record_part(&output_dir, part, vec![entry(part)])
+                            .unwrap();
+                    }
+                    barrier.wait();
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let manifest = ZoneManifest::read(dir.path()).unwrap().unwrap();
+        assert_eq!(manifest.files, (1..=parts).map(entry).collect::<Vec<_>>());
+        assert!(!dir.path().join(MANIFEST_LOCK_FILE_NAME).exists());
+    }
+
+    #[test]
+    fn test_replace_part_drops_files_of_earlier_split() {
+        let split = |index: usize| ManifestPart {
+            path: format!("zone/zone.2-{index}.parquet"),
+            file_index: Some(index),
+            row_offset: Some(index as u64 * 10),
+            ..entry(2)
+        };
+        let mut manifest = ZoneManifest::new(1.0, 2);
+        manifest.replace_part(2, vec![split(1), split(0), split(2)]);
+        manifest.replace_part(1, vec![entry(1)]);
+        assert_eq!(manifest.files, vec![entry(1), split(0), split(1), split(2)]);
+
+        manifest.replace_part(2, vec![split(0)]);
+        assert_eq!(manifest.files, vec![entry(1), split(0)]);
+        manifest.replace_part(2, vec![entry(2)]);
+        assert_eq!(manifest.files, vec![entry(1), entry(2)]);
+    }
+
+    #[test]
+    fn test_differences() {
+        let existing = ZoneManifest::new(1.0, 2)
+            .with_seed(Some(7))
+            .with_source("division_area=a.parquet".to_string());
+        assert!(existing.differences(&existing.clone()).is_empty());
+
+        let requested = ZoneManifest::new(10.0, 4)
+            .with_seed(Some(8))
+            .with_source("division_area=b.parquet".to_string())
+            .with_rows(Some(1000))
+            .with_limit(Some(10))
+            .with_keep_geometry_types(Some(vec![
+                "polygon".to_string(),
+                "multipolygon".to_string(),
+            ]));
+        assert_eq!(
+            existing.differences(&requested),
+            vec![
+                "scale factor: 1 in the existing outputs, 10 requested",
+                "seed: 7 in the existing outputs, 8 requested",
+                "source: division_area=a.parquet in the existing outputs, \
+                 division_area=b.parquet requested",
+                "rows: all rows in the existing outputs, --rows zone=1000 requested",
+                "limit: no limit in the existing outputs, --limit 10 requested",
+                "geometry types: all types in the existing outputs, \
+                 --keep-geometry-type polygon,multipolygon requested",
+            ]
+        );
+
+        // Part counts may differ, and fields an older manifest lacks are
+        // not compared
+        assert!(ZoneManifest::new(1.0, 1).differences(&existing).is_empty());
+    }
+
+    #[test]
+    fn test_remove_outputs() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("zone")).unwrap();
+        let mut manifest = ZoneManifest::new(1.0, 2);
+        manifest.replace_part(1, vec![entry(1)]);
+        manifest.replace_part(2, vec![entry(2)]);
+        manifest.write(dir.path()).unwrap();
+        std::fs::write(dir.path().join(&entry(1).path), b"").unwrap();
+        write_success_marker(&dir.path().join("zone"), Fsync::Never).unwrap();
+        std::fs::write(dir.path().join("zone").join("other.parquet"), b"").unwrap();
+
+        manifest.remove_outputs(dir.path()).unwrap();
+        assert!(ZoneManifest::read(dir.path()).unwrap().is_none());
+        assert!(!dir.path().join(&entry(1).path).exists());
+        assert!(!dir.path().join("zone").join(SUCCESS_FILE_NAME).exists());
+        assert!(dir.path().join("zone").join("other.parquet").exists());
+    }
+
+    #[test]
+    fn test_read_plan_total_rows() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("count.json");
+        std::fs::write(
+            &path,
+            r#"{"table": "zone", "scale_factor": 10.0, "total_rows": 454710}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            ZoneManifest::read_plan_total_rows(&path, 10.0).unwrap(),
+            454710
+        );
+        assert!(ZoneManifest::read_plan_total_rows(&path, 1.0).is_err());
+    }
+
+    #[test]
+    fn test_finalize_requires_complete_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("zone")).unwrap();
+        let mut manifest = ZoneManifest::new(1.0, 2);
+        manifest.replace_part(1, vec![entry(1)]);
+        std::fs::write(dir.path().join(&entry(1).path), b"").unwrap();
+
+        assert_eq!(manifest.missing_parts(), vec![2]);
+        assert!(manifest.finalize(dir.path()).is_err());
+        assert!(!dir.path().join("zone").join(SUCCESS_FILE_NAME).exists());
+
+        manifest.replace_part(2, vec![entry(2)]);
+        assert!(manifest.finalize(dir.path()).is_err());
+
+        std::fs::write(dir.path().join(&entry(2).path), b"").unwrap();
+        let marker = manifest.finalize(dir.path()).unwrap();
+        assert_eq!(marker, dir.path().join("zone").join(SUCCESS_FILE_NAME));
+        assert!(marker.exists());
+    }
+}
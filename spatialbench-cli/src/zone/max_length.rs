@@ -0,0 +1,413 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! `--max-string-length` limits of zone string columns
+//!
+//! Limits count UTF-8 bytes, so a value within a limit of `n` also fits a
+//! `VARCHAR(n)` counting characters. Overlong values are cut at the last
+//! character boundary within the limit, never inside a code point.
+
+use anyhow::{anyhow, Result};
+use arrow::compute::cast;
+use arrow_array::{Array, ArrayRef, RecordBatch, StringArray};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use clap::ValueEnum;
+use log::{info, warn};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Field metadata key recording the byte limit of a written string column
+pub const MAX_LENGTH_KEY: &str = "spatialbench.max_length";
+
+/// Byte limit of one string column, `column=bytes`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MaxStringLength {
+    pub column: String,
+    pub bytes: usize,
+}
+
+impl MaxStringLength {
+    /// Parses a comma separated list such as `z_name=255,z_region=100`
+    pub fn parse_list(spec: &str) -> Result<Vec<Self>> {
+        let limits = spec
+            .split(',')
+            .filter(|s| !s.trim().is_empty())
+            .map(Self::from_str)
+            .collect::<Result<Vec<_>>>()?;
+        for (i, limit) in limits.iter().enumerate() {
+            if limits[..i].iter().any(|l| l.column == limit.column) {
+                return Err(anyhow!(
+                    "--max-string-length limits {} more than once",
+                    limit.column
+                ));
+            }
+        }
+        Ok(limits)
+    }
+}
+
+impl FromStr for MaxStringLength {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (column, bytes) = s
+            .trim()
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Invalid --max-string-length {s:?}, expected column=bytes"))?;
+        let bytes = bytes
+            .trim()
+            .parse::<usize>()
+            .ok()
+            .filter(|bytes| *bytes > 0)
+            .ok_or_else(|| {
+                anyhow!("Invalid --max-string-length {s:?}, expected a positive byte length")
+            })?;
+        Ok(Self {
+            column: column.trim().to_string(),
+            bytes,
+        })
+    }
+}
+
+/// What to do with a string longer than its `--max-string-length`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum OnOverlong {
+    /// Cut the value at a character boundary and report its id
+    #[default]
+    Truncate,
+    /// Fail before writing the part
+    Fail,
+}
+
+/// The longest prefix of `value` of at most `bytes` bytes that ends on a
+/// character boundary
+pub fn truncate(value: &str, bytes: usize) -> &str {
+    if value.len() <= bytes {
+        return value;
+    }
+    let end = (0..=bytes)
+        .rev()
+        .find(|&end| value.is_char_boundary(end))
+        .unwrap_or(0);
+    &value[..end]
+}
+
+/// `schema` with the limit of every limited column recorded under
+/// [`MAX_LENGTH_KEY`] in its field metadata
+pub fn with_max_lengths(schema: SchemaRef, limits: &[MaxStringLength]) -> SchemaRef {
+    if limits.is_empty() {
+        return schema;
+    }
+    let fields: Vec<Field> = schema
+        .fields()
+        .iter()
+        .map(
+            |field| match limits.iter().find(|l| &l.column == field.name()) {
+                Some(limit) => {
+                    let mut metadata = field.metadata().clone();
+                    metadata.insert(MAX_LENGTH_KEY.to_string(), limit.bytes.to_string());
+                    field.as_ref().clone().with_metadata(metadata)
+                }
+                None => field.as_ref().clone(),
+            },
+        )
+        .collect();
+    Arc::new(Schema::new_with_metadata(fields, schema.metadata().clone()))
+}
+
+/// A value cut to its limit
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TruncatedValue {
+    pub gersid: String,
+    pub column: String,
+    /// Length of the value before it was cut
+    pub bytes: usize,
+    pub max_bytes: usize,
+}
+
+/// The values [`limit_string_lengths`] cut
+#[derive(Debug, Default)]
+pub struct TruncationReport {
+    /// Number of values cut in every limited column
+    pub truncated: BTreeMap<String, u64>,
+    pub values: Vec<TruncatedValue>,
+}
+
+impl TruncationReport {
+    /// Adds the values cut in a later batch
+    pub fn extend(&mut self, other: TruncationReport) {
+        for (column, values) in other.truncated {
+            *self.truncated.entry(column).or_default() += values;
+        }
+        self.values.extend(other.values);
+    }
+
+    /// Writes the cut values as CSV with a header, one row per value
+    pub fn write_csv(&self, path: &Path) -> Result<()> {
+        let mut csv = String::from("z_gersid,column,bytes,max_bytes\n");
+        for value in &self.values {
+            let _ = writeln!(
+                csv,
+                "{},{},{},{}",
+                csv_field(&value.gersid),
+                csv_field(&value.column),
+                value.bytes,
+                value.max_bytes
+            );
+        }
+        std::fs::write(path, csv)?;
+        Ok(())
+    }
+}
+
+/// `value` quoted when it holds a separator, quote or line break
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Cuts the values of every limited column of `batches` to its limit, or
+/// fails on the first overlong value with [`OnOverlong::Fail`]. The columns
+/// keep their Arrow types.
+pub fn limit_string_lengths(
+    batches: &[RecordBatch],
+    limits: &[MaxStringLength],
+    on_overlong: OnOverlong,
+) -> Result<(Vec<RecordBatch>, TruncationReport)> {
+    let mut report = TruncationReport::default();
+    let batches = batches
+        .iter()
+        .map(|batch| limit_batch(batch, limits, on_overlong, &mut report))
+        .collect::<Result<Vec<_>>>()?;
+
+    for (column, values) in &report.truncated {
+        warn!("Truncated {values} {column} value(s) to --max-string-length");
+    }
+    if report.values.is_empty() && !limits.is_empty() {
+        info!("No string exceeds --max-string-length");
+    }
+    Ok((batches, report))
+}
+
+fn limit_batch(
+    batch: &RecordBatch,
+    limits: &[MaxStringLength],
+    on_overlong: OnOverlong,
+    report: &mut TruncationReport,
+) -> Result<RecordBatch> {
+    let schema = batch.schema();
+    let gersids = string_column(batch, "z_gersid")?;
+    let mut columns = batch.columns().to_vec();
+    for limit in limits {
+        let index = schema.index_of(&limit.column).map_err(|_| {
+            anyhow!(
+                "--max-string-length limits {}, which is not a zone column",
+                limit.column
+            )
+        })?;
+        let data_type = columns[index].data_type().clone();
+        if !matches!(
+            data_type,
+            DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View
+        ) {
+            return Err(anyhow!(
+                "--max-string-length limits {}, which is not a string column",
+                limit.column
+            ));
+        }
+        let values = string_column(batch, &limit.column)?;
+        if values.iter().flatten().all(|v| v.len() <= limit.bytes) {
+            continue;
+        }
+
+        let mut limited = Vec::with_capacity(values.len());
+        for (row, value) in values.iter().enumerate() {
+            let Some(value) = value.filter(|v| v.len() > limit.bytes) else {
+                limited.push(value);
+                continue;
+            };
+            let gersid = gersids.value(row);
+            if on_overlong == OnOverlong::Fail {
+                return Err(anyhow!(
+                    "{} of {gersid} is {} bytes, over --max-string-length {}={}",
+                    limit.column,
+                    value.len(),
+                    limit.column,
+                    limit.bytes
+                ));
+            }
+            *report.truncated.entry(limit.column.clone()).or_default() += 1;
+            report.values.push(TruncatedValue {
+                gersid: gersid.to_string(),
+                column: limit.column.clone(),
+                bytes: value.len(),
+                max_bytes: limit.bytes,
+            });
+            limited.push(Some(truncate(value, limit.bytes)));
+        }
+        let limited: ArrayRef = Arc::new(StringArray::from(limited));
+        columns[index] = cast(&limited, &data_type)?;
+    }
+    Ok(RecordBatch::try_new(schema, columns)?)
+}
+
+/// The string column `name` of `batch` as `Utf8`
+fn string_column(batch: &RecordBatch, name: &str) -> Result<StringArray> {
+    let column = batch
+        .column_by_name(name)
+        .ok_or_else(|| anyhow!("Column {name} not found in zone batch"))?;
+    let utf8 = cast(column, &DataType::Utf8)?;
+    Ok(utf8
+        .as_any()
+        .downcast_ref::<StringArray>()
+        .ok_or_else(|| anyhow!("Column {name} is not a string column"))?
+        .clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_array::StringViewArray;
+
+    fn batch(names: Vec<Option<&str>>) -> RecordBatch {
+        let ids: Vec<String> = (1..=names.len()).map(|i| format!("g{i}")).collect();
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("z_gersid", DataType::Utf8View, false),
+            Field::new("z_name", DataType::Utf8View, true),
+        ]));
+        RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(StringViewArray::from_iter_values(ids)),
+                Arc::new(StringViewArray::from(names)),
+            ],
+        )
+        .unwrap()
+    }
+
+    fn names(batch: &RecordBatch) -> Vec<Option<String>> {
+        string_column(batch, "z_name")
+            .unwrap()
+            .iter()
+            .map(|v| v.map(str::to_string))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_limits() {
+        assert_eq!(
+            MaxStringLength::parse_list("z_name=255, z_region = 100").unwrap(),
+            vec![
+                MaxStringLength {
+                    column: "z_name".to_string(),
+                    bytes: 255
+                },
+                MaxStringLength {
+                    column: "z_region".to_string(),
+                    bytes: 100
+                },
+            ]
+        );
+        assert!(MaxStringLength::parse_list("z_name").is_err());
+        assert!(MaxStringLength::parse_list("z_name=0").is_err());
+        assert!(MaxStringLength::parse_list("z_name=-1").is_err());
+        assert!(MaxStringLength::parse_list("z_name=3,z_name=4").is_err());
+    }
+
+    #[test]
+    fn test_truncate_at_character_boundary() {
+        assert_eq!(truncate("Zürich", 10), "Zürich");
+        // ü is 2 bytes at 1..3, so 2 bytes would cut it in half
+        assert_eq!(truncate("Zürich", 2), "Z");
+        assert_eq!(truncate("Zürich", 3), "Zü");
+        // 東京都 is three 3 byte characters
+        assert_eq!(truncate("東京都", 8), "東京");
+        assert_eq!(truncate("東京都", 2), "");
+        // 🗺 is 4 bytes
+        assert_eq!(truncate("a🗺b", 4), "a");
+        assert_eq!(truncate("a🗺b", 5), "a🗺");
+    }
+
+    #[test]
+    fn test_limit_reports_truncated_ids() {
+        let limits = MaxStringLength::parse_list("z_name=5").unwrap();
+        let batch = batch(vec![Some("Köln"), Some("Straße"), None, Some("東京都")]);
+
+        let (batches, report) =
+            limit_string_lengths(std::slice::from_ref(&batch), &limits, OnOverlong::Truncate)
+                .unwrap();
+        assert_eq!(batches[0].schema(), batch.schema());
+        assert_eq!(
+            names(&batches[0]),
+            vec![
+                Some("Köln".to_string()),
+                Some("Stra".to_string()),
+                None,
+                Some("東".to_string()),
+            ]
+        );
+        assert_eq!(
+            report.truncated,
+            BTreeMap::from([("z_name".to_string(), 2)])
+        );
+        assert_eq!(
+            report.values,
+            vec![
+                TruncatedValue {
+                    gersid: "g2".to_string(),
+                    column: "z_name".to_string(),
+                    bytes: 7,
+                    max_bytes: 5
+                },
+                TruncatedValue {
+                    gersid: "g4".to_string(),
+                    column: "z_name".to_string(),
+                    bytes: 9,
+                    max_bytes: 5
+                },
+            ]
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("report.csv");
+        report.write_csv(&path).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "z_gersid,column,bytes,max_bytes\ng2,z_name,7,5\ng4,z_name,9,5\n"
+        );
+
+        let err = limit_string_lengths(&[batch], &limits, OnOverlong::Fail).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "z_name of g2 is 7 bytes, over --max-string-length z_name=5"
+        );
+    }
+
+    #[test]
+    fn test_limit_rejects_unknown_columns() {
+        let limits = MaxStringLength::parse_list("z_zonekey=5").unwrap();
+        let err = limit_string_lengths(&[batch(vec![Some("a")])], &limits, OnOverlong::Truncate)
+            .unwrap_err();
+        assert!(err.to_string().contains("not a zone column"), "{err}");
+    }
+}
@@ -17,97 +17,3318 @@
 
 //! Zone table generation module using DataFusion and remote Parquet files
 
+mod adjacency;
+mod antimeridian;
+mod area;
+mod attribution;
+mod augment;
+mod batch;
+mod bbox;
+mod bench;
 mod config;
+mod containment;
+mod country;
 mod datasource;
+mod diff;
+mod diff_stats;
+mod estimate;
+mod explain;
+mod extend;
+mod extra_columns;
+mod filename;
+#[cfg(test)]
+mod fixtures;
+mod geoarrow;
+mod geojson;
+mod geometry;
+mod geometry_check;
+mod geometry_type;
+mod hash;
+mod hierarchy;
+mod levels;
+mod lonlat;
+mod manifest;
+mod max_length;
+mod names;
+mod orc;
+mod package;
 mod partition;
+mod plan_diff;
+mod profile;
+mod protobuf;
+mod pseudonym;
+mod queries;
+mod region;
+mod sampling;
+mod schema;
+mod simplify;
+mod sink;
+mod spill;
 mod stats;
+mod subdivision;
+mod synthetic;
+mod tar;
+mod text;
+mod theme;
+mod tiles;
 mod transform;
+mod verify;
+mod winding;
+mod wkb;
+mod write_options;
 mod writer;
+mod zstd_dict;
 
 pub mod main;
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use arrow_array::RecordBatch;
+use arrow_schema::SchemaRef;
+use datafusion::datasource::MemTable;
+use datafusion::execution::SendableRecordBatchStream;
+use datafusion::prelude::{col, DataFrame, SessionContext};
+use futures::StreamExt;
+use log::{info, warn};
+use std::collections::VecDeque;
 use std::sync::Arc;
+use std::time::Instant;
+use tracing::{field, info_span, instrument, Instrument};
 
-pub use config::ZoneDfArgs;
+use crate::interrupt::interrupted_error;
+
+pub use area::AreaCrs;
+pub use attribution::{ATTRIBUTION_COLUMN, ATTRIBUTION_FILE_NAME};
+pub use augment::{AugmentColumn, AugmentOptions, AugmentedFile, AUGMENTED_KEY};
+pub use bbox::BboxLayout;
+use bench::StageTimes;
+pub use bench::{bench_zone, BenchReport};
+pub use config::{
+    check_parts, check_scale_factor, check_scale_factor_ceiling, zone_scale_factor, Balance,
+    ColumnOrder, ColumnSelection, GeometryType, KeyPartition, OnBadGeometry, PartitionScheme,
+    PseudonymStyle, Sampling, WindingOrder, ZoneDfArgs, ZoneFileFormat, ZoneLayout,
+    ZoneTransformOptions, DEFAULT_MAX_SCALE_FACTOR, PARTS_WARNING_THRESHOLD,
+};
+pub use datasource::remote_store;
 use datasource::ZoneDataSource;
-use partition::PartitionStrategy;
+pub use diff_stats::{BboxDrift, CountDelta, DiffStatsReport, IdDelta};
+pub use estimate::{estimate_source_io, metadata_row_count, IoEstimate};
+pub use explain::{Explain, ScanBytes, ScanPushdown, ZoneExplain};
+pub use extend::{ExtendPlan, FileSlice, LocalPart};
+pub use extra_columns::ExtraColumn;
+pub use filename::FilenameTemplate;
+pub use geojson::GeometryEncoding;
+use manifest::write_success_marker;
+pub use manifest::{ZoneManifest, MANIFEST_FILE_NAME, SUCCESS_FILE_NAME};
+pub use max_length::{MaxStringLength, OnOverlong, MAX_LENGTH_KEY};
+pub use orc::{OrcCompression, OrcWriteOptions, DEFAULT_ORC_STRIPE_BYTES};
+pub use package::{PackageMetadata, PackagedFile, METADATA_FILE_NAME};
+pub use partition::{partition_plan, PartBoundary, PartSpec, PartitionPlan};
+use partition::{PartExtent, PartitionStrategy};
+use plan_diff::ZonePlan;
+pub use queries::{generate_queries, write_queries, Query, QueryKind};
+pub use region::RegionMap;
+use schema::SchemaVersion;
+pub use simplify::SimplifyAlgorithm;
+pub use sink::Fsync;
+use spill::SpilledTable;
 use stats::ZoneTableStats;
+pub use subdivision::RegionNormalization;
+pub use synthetic::SyntheticColumn;
+pub use theme::{Theme, ThemeInput};
+pub use tiles::{TilesReport, DEFAULT_MAX_ZOOM, DEFAULT_MIN_ZOOM};
 use transform::ZoneTransformer;
-use writer::ParquetWriter;
+pub use write_options::{
+    check_row_group_bytes, ParquetWriteOptions, DEFAULT_NO_DICTIONARY_COLUMNS, MIN_ROW_GROUP_BYTES,
+};
+pub use writer::TRUNCATED_KEY;
+use writer::{check_contiguous_keys, ParquetWriter};
+
+/// Orders the transformed rows by `z_zonekey`, so the key statistics of
+/// every row group are a narrow range for key range queries. The keys are
+/// numbered in id order, so the window already sorted them and the sort
+/// only pins that order down. Keys partitioned by a column are ordered
+/// within its values.
+fn sort_by_zonekey(df: DataFrame, options: &ZoneTransformOptions) -> Result<DataFrame> {
+    let mut order = Vec::new();
+    if let Some(key_partition) = options.key_partition {
+        order.push(col(key_partition.column()).sort(true, false));
+    }
+    order.push(col("z_zonekey").sort(true, false));
+    Ok(df.sort(order)?)
+}
+
+/// The session context zone generation creates when none is given
+pub async fn zone_session_context() -> Result<SessionContext> {
+    ZoneDataSource::new().await?.create_context()
+}
 
 /// Generate a single part using LIMIT/OFFSET on the dataframe
 pub async fn generate_zone_parquet_single(args: ZoneDfArgs) -> Result<()> {
+    generate_zone_parquet_single_with_ctx(&zone_session_context().await?, args).await
+}
+
+/// [`generate_zone_parquet_single`] in a session derived from an embedder's
+/// `ctx`.
+///
+/// The generator uses the runtime of `ctx`, so its object stores, memory
+/// pool and disk manager, and its catalogs, functions and extensions.
+/// `target_partitions` is set to 1 for generation, as part boundaries
+/// depend on the source order. The generator expects and makes these
+/// registrations:
+///
+/// * an object store for every non-local input theme location. Stores for
+///   `http(s)://` origins, including `huggingface.co` for the built-in
+///   source, are registered when `ctx` has none; any other scheme, such as
+///   `s3://`, must be registered by the caller.
+/// * a view named `zone_filtered` in the default schema, registered by the
+///   generator and replacing any table of that name.
+///
+/// # Example
+///
+/// ```
+/// # use std::sync::Arc;
+/// # use datafusion::execution::memory_pool::FairSpillPool;
+/// # use datafusion::execution::runtime_env::RuntimeEnvBuilder;
+/// # use datafusion::prelude::{SessionConfig, SessionContext};
+/// # use parquet::basic::Compression;
+/// # use spatialbench_cli::zone::{generate_zone_parquet_single_with_ctx, ThemeInput, ZoneDfArgs};
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() -> anyhow::Result<()> {
+/// // An embedder's context with its own memory pool
+/// let runtime = RuntimeEnvBuilder::new()
+///     .with_memory_pool(Arc::new(FairSpillPool::new(256 * 1024 * 1024)))
+///     .build_arc()?;
+/// let ctx = SessionContext::new_with_config_rt(SessionConfig::new(), runtime);
+///
+/// // A one zone Overture-shaped source, written with the same context
+/// let dir = tempfile::tempdir()?;
+/// let source = dir.path().join("division_area.parquet");
+/// ctx.sql(&format!(
+///     "COPY (SELECT 'g1' AS id, 'US' AS country, 'US-WA' AS region, \
+///      named_struct('primary', 'King County') AS names, 'county' AS subtype, \
+///      X'0101000000000000000000F03F000000000000F03F' AS geometry, true AS is_land) \
+///      TO '{}' STORED AS PARQUET",
+///     source.display()
+/// ))
+/// .await?
+/// .collect()
+/// .await?;
+///
+/// let output_dir = dir.path().join("out");
+/// let args = ZoneDfArgs::new(
+///     1.0,
+///     output_dir.clone(),
+///     Some(1),
+///     Some(1),
+///     None,
+///     64 * 1024 * 1024,
+///     Compression::SNAPPY,
+/// )
+/// .with_themes(ThemeInput::parse_list(&format!(
+///     "division_area={}",
+///     source.display()
+/// ))?);
+/// generate_zone_parquet_single_with_ctx(&ctx, args).await?;
+/// assert!(output_dir.join("zone.parquet").is_file());
+/// # Ok(())
+/// # }
+/// ```
+pub async fn generate_zone_parquet_single_with_ctx(
+    ctx: &SessionContext,
+    args: ZoneDfArgs,
+) -> Result<()> {
     args.validate()?;
+    let needs_table = parts_need_table(&args);
+    if args.transform.include_hierarchy || args.transform.key_partition.is_some() || needs_table {
+        return generate_zone_parquet_part_from_table(ctx, args).await;
+    }
 
     let stats = ZoneTableStats::new(args.scale_factor, args.parts);
-    let datasource = ZoneDataSource::new().await?;
-    let ctx = datasource.create_context()?;
+    let (ctx, df) = scan_source(ctx, &args).await?;
+    let total_rows = total_source_rows(&args, &stats, &df).await?;
+    let partition = match &args.part_boundaries {
+        Some(boundaries) => {
+            PartitionStrategy::from_boundary(&boundaries[args.part.unwrap_or(1) as usize - 1])
+        }
+        None => PartitionStrategy::calculate(total_rows, args.parts, args.part),
+    };
+
+    let df = partition.apply_to_dataframe(df)?;
+    let (transformer, df) = transform_source(&ctx, &args, df, partition.offset()).await?;
+
+    // Get schema before collecting (which moves df)
+    let schema = transformer.output_schema(&args.transform, &df)?;
+    let batches = df.collect().instrument(info_span!("zone.collect")).await?;
+    let batches = transformer.apply_batch_transforms(&args.transform, batches)?;
+    if args.assert_contiguous_keys {
+        check_contiguous_keys(&batches, partition.offset() + 1)?;
+    }
+    if let Some(level) = args.zstd_dictionary_level() {
+        return Err(zstd_dict::unsupported_error(&batches, level));
+    }
+
+    let writer = ParquetWriter::new(&args, &stats, schema.clone());
+    let written = writer.write(&batches)?;
+    write_simplified_levels(&args, &stats, &schema, &batches)?;
+    if let (Some(written), Some(_)) = (written, args.total_rows) {
+        if written as i64 != partition.limit() {
+            return Err(anyhow!(
+                "Part {:?} wrote {} rows but {} were planned from --total-rows={}; \
+                 the source row count has drifted",
+                args.part,
+                written,
+                partition.limit(),
+                total_rows
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// The number of rows the scan `df` of the source selects, without
+/// collecting it.
+///
+/// Trust a provided count over the built-in estimate; the row count check
+/// after writing catches a source that has drifted from it. The estimate
+/// only describes the unfiltered built-in division_area source, so
+/// anything else is counted.
+async fn total_source_rows(
+    args: &ZoneDfArgs,
+    stats: &ZoneTableStats,
+    df: &DataFrame,
+) -> Result<i64> {
+    let span = info_span!("zone.count", rows = field::Empty);
+    let rows = match args.expected_total_rows() {
+        Some(total_rows) => total_rows,
+        None if args.themes == [ThemeInput::built_in(Theme::DivisionArea)]
+            && args.keep_geometry_types.is_empty()
+            && args.sampling == Sampling::Uniform =>
+        {
+            let estimate = stats.estimated_total_rows();
+            [args.rows, args.limit]
+                .into_iter()
+                .flatten()
+                .fold(estimate, |estimate, rows| estimate.min(rows as i64))
+        }
+        None => df.clone().count().instrument(span.clone()).await? as i64,
+    };
+    span.record("rows", rows);
+    Ok(rows)
+}
 
+/// Scans the source rows of `args` in a session derived from `ctx`,
+/// returning the session the later stages run in and the scan
+#[instrument(name = "zone.scan", skip_all)]
+async fn scan_source(
+    ctx: &SessionContext,
+    args: &ZoneDfArgs,
+) -> Result<(SessionContext, DataFrame)> {
+    let datasource = ZoneDataSource::new()
+        .await?
+        .with_themes(args.themes.clone())
+        .with_name_column(args.name_column.clone())
+        .with_common_names(args.transform.reads_common_names())
+        .with_sort_by_id(args.deterministic)
+        .with_rows(args.rows)
+        .with_limit(args.limit)
+        .with_geometry_types(args.keep_geometry_types.clone())
+        .with_sampling(args.sampling, args.min_per_country, args.transform.seed);
+    let ctx = ZoneDataSource::context_from(ctx);
     let df = datasource.load_zone_data(&ctx, args.scale_factor).await?;
+    Ok((ctx, df))
+}
 
-    let partition =
-        PartitionStrategy::calculate(stats.estimated_total_rows(), args.parts, args.part);
+/// Transforms the scanned rows in `df`, numbering their keys from `offset`,
+/// and orders them by key. The result is the query whose rows are
+/// collected for the batch transforms and the writer.
+async fn transform_source(
+    ctx: &SessionContext,
+    args: &ZoneDfArgs,
+    df: DataFrame,
+    offset: i64,
+) -> Result<(ZoneTransformer, DataFrame)> {
+    let transformer = ZoneTransformer::new(offset)
+        .with_source(args.source_provenance())
+        .with_attribution(args.source_attribution());
+    let df = transformer.transform(ctx, &args.transform, df).await?;
+    Ok((transformer, sort_by_zonekey(df, &args.transform)?))
+}
 
-    let df = partition.apply_to_dataframe(df)?;
+/// Plans the query generating the whole zone table, the source scan and
+/// the transforms, without writing anything. With [`Explain::Analyze`] the
+/// query also runs over its first rows.
+pub async fn explain_zone_query(ctx: &SessionContext, args: &ZoneDfArgs) -> Result<ZoneExplain> {
+    args.validate()?;
+    let (ctx, df) = scan_source(ctx, args).await?;
+    let (_, df) = transform_source(&ctx, args, df, 0).await?;
+    let explain = explain::explain(df, args.explain.unwrap_or(Explain::Plan)).await?;
+    let column_bytes = estimate::source_column_bytes(&args.themes).await?;
+    Ok(explain
+        .with_expected_columns(&datasource::expected_scan_columns(
+            args.name_column.as_deref(),
+        ))
+        .with_column_bytes(&column_bytes))
+}
 
-    let transformer = ZoneTransformer::new(partition.offset());
-    let df = transformer.transform(&ctx, df).await?;
+/// Plans the zone table of `args` without writing it: the manifest the run
+/// would record, with the columns of the schema its writer would be given,
+/// and the rows of every part. The source rows are counted unless
+/// `--total-rows` or a plan file gives their number.
+pub async fn plan_zone(ctx: &SessionContext, args: &ZoneDfArgs) -> Result<ZonePlan> {
+    args.validate()?;
+    let (ctx, df) = scan_source(ctx, args).await?;
+    let total_rows = match args.expected_total_rows() {
+        Some(total_rows) => total_rows,
+        None => df.clone().count().await? as i64,
+    };
+    let (transformer, df) = transform_source(&ctx, args, df, 0).await?;
+    let schema = transformer.output_schema(&args.transform, &df)?;
 
-    // Get schema before collecting (which moves df)
-    let schema = Arc::new(transformer.arrow_schema(&df)?);
-    let batches = df.collect().await?;
+    let parts = args.parts.unwrap_or(1);
+    let part_rows = (!parts_need_table(args)).then(|| {
+        (1..=parts)
+            .map(|part| {
+                part_partition(total_rows, parts, part, &args.part_boundaries).limit() as u64
+            })
+            .collect()
+    });
+    let schema_version = SchemaVersion::of(&args.transform).to_string();
+    Ok(ZonePlan {
+        manifest: writer::run_manifest(args, &schema_version, &schema),
+        total_rows: total_rows as u64,
+        part_rows,
+    })
+}
+
+/// Whether the part boundaries depend on every row, as for
+/// `Balance::Vertices` without a plan and for bands
+fn parts_need_table(args: &ZoneDfArgs) -> bool {
+    (args.balance == Balance::Vertices && args.part_boundaries.is_none())
+        || args.partition_scheme != PartitionScheme::Rows
+}
 
-    let writer = ParquetWriter::new(&args, &stats, schema);
+/// Generate a single part cut from the whole collected table.
+///
+/// Used when the batch transforms need rows outside the part, as parent
+/// zones do for `include_hierarchy`, when the keys are numbered within
+/// `key_partition` values across parts, or when the part boundaries depend on
+/// every row, as for `Balance::Vertices` without a plan and for bands.
+async fn generate_zone_parquet_part_from_table(
+    ctx: &SessionContext,
+    args: ZoneDfArgs,
+) -> Result<()> {
+    let stats = ZoneTableStats::new(args.scale_factor, args.parts);
+    let (schema, batches) = generate_zone_batches(ctx, &args).await?;
+    if args.assert_contiguous_keys {
+        check_contiguous_keys(&batches, 1)?;
+    }
+    if let Some(level) = args.zstd_dictionary_level() {
+        return Err(zstd_dict::unsupported_error(&batches, level));
+    }
+
+    let total_rows = batches.iter().map(|b| b.num_rows() as i64).sum();
+    if let Some(expected) = args.expected_total_rows() {
+        if expected != total_rows {
+            return Err(anyhow!(
+                "Source has {} rows but --total-rows={} was given",
+                total_rows,
+                expected
+            ));
+        }
+    }
+    let parts = args.parts.unwrap_or(1);
+    let table = partition_table(&args, batches, parts)?;
+    let partition = part_partition(total_rows, parts, args.part.unwrap_or(1), &table.boundaries);
+    let batches = partition.apply_to_batches(&table.batches)?;
+
+    let args = ZoneDfArgs {
+        part_boundaries: table.boundaries,
+        part_extents: table.extents,
+        ..args
+    };
+    let writer = ParquetWriter::new(&args, &stats, schema.clone());
     writer.write(&batches)?;
+    write_simplified_levels(&args, &stats, &schema, &batches)
+}
 
+/// Writes the `--simplification-levels` companions of the part of `args`
+/// holding `batches`, each level into its own output directory with its
+/// own manifest. Every level is simplified from the full-resolution rows and
+/// falls back to the level below for geometries that collapse.
+fn write_simplified_levels(
+    args: &ZoneDfArgs,
+    stats: &ZoneTableStats,
+    schema: &SchemaRef,
+    batches: &[RecordBatch],
+) -> Result<()> {
+    let options = &args.transform;
+    let bbox = options
+        .bbox_covering
+        .then_some((options.bbox_layout, options.bbox_inflate));
+    let mut below = batches.to_vec();
+    for tolerance in args.simplified_levels() {
+        let (level, report) = levels::simplify_level(
+            batches,
+            &below,
+            options.simplify_algorithm,
+            tolerance,
+            bbox,
+            options.geometry_threads,
+        )?;
+        let level_args = ZoneDfArgs {
+            output_dir: args.output_dir.join(levels::level_dir_name(tolerance)),
+            also_merge: false,
+            ..args.clone()
+        };
+        if report.fallbacks > 0 {
+            warn!(
+                "{} z_boundary value(s) of part {} collapsed at tolerance {tolerance} and \
+                 kept the geometry of the level below",
+                report.fallbacks,
+                args.part.unwrap_or(1)
+            );
+        }
+        info!(
+            "Simplified part {} at tolerance {tolerance}: {} of {} vertices kept, {} fallback(s)",
+            args.part.unwrap_or(1),
+            report.points_after,
+            report.points_before,
+            report.fallbacks
+        );
+        ParquetWriter::new(&level_args, stats, schema.clone()).write(&level)?;
+        below = level;
+    }
     Ok(())
 }
 
-/// Generate all parts by collecting once and partitioning in memory
-pub async fn generate_zone_parquet_multi(args: ZoneDfArgs) -> Result<()> {
-    let stats = ZoneTableStats::new(args.scale_factor, args.parts);
-    let datasource = ZoneDataSource::new().await?;
-    let ctx = datasource.create_context()?;
+/// Runs the whole pipeline and collects every zone row in memory
+async fn generate_zone_batches(
+    ctx: &SessionContext,
+    args: &ZoneDfArgs,
+) -> Result<(SchemaRef, Vec<RecordBatch>)> {
+    generate_zone_batches_timed(ctx, args, &mut StageTimes::default()).await
+}
 
-    let df = datasource.load_zone_data(&ctx, args.scale_factor).await?;
+/// [`generate_zone_batches`], adding the time spent in each stage to `times`
+async fn generate_zone_batches_timed(
+    ctx: &SessionContext,
+    args: &ZoneDfArgs,
+    times: &mut StageTimes,
+) -> Result<(SchemaRef, Vec<RecordBatch>)> {
+    let start = Instant::now();
+    let (ctx, df) = scan_source(ctx, args).await?;
 
     // Transform without offset (we'll adjust per-part later)
-    let transformer = ZoneTransformer::new(0);
-    let df = transformer.transform(&ctx, df).await?;
+    let (transformer, df) = transform_source(&ctx, args, df, 0).await?;
 
     // Collect once
-    let schema = Arc::new(transformer.arrow_schema(&df)?);
-    let batches = df.collect().await?;
+    let schema = transformer.output_schema(&args.transform, &df)?;
+    let batches = df.collect().instrument(info_span!("zone.collect")).await?;
+    times.scan_transform += start.elapsed();
+
+    let start = Instant::now();
+    let batches = transformer.apply_batch_transforms(&args.transform, batches)?;
+    times.batch_transforms += start.elapsed();
+    Ok((schema, batches))
+}
+
+/// Generate all parts by collecting once and partitioning in memory
+pub async fn generate_zone_parquet_multi(args: ZoneDfArgs) -> Result<()> {
+    generate_zone_parquet_multi_with_ctx(&zone_session_context().await?, args).await
+}
+
+/// [`generate_zone_parquet_multi`] in a session derived from an embedder's
+/// `ctx`, see [`generate_zone_parquet_single_with_ctx`]
+pub async fn generate_zone_parquet_multi_with_ctx(
+    ctx: &SessionContext,
+    args: ZoneDfArgs,
+) -> Result<()> {
+    args.validate()?;
+    let stats = ZoneTableStats::new(args.scale_factor, args.parts);
+    let (schema, batches) = generate_zone_batches(ctx, &args).await?;
+    if args.assert_contiguous_keys {
+        check_contiguous_keys(&batches, 1)?;
+    }
+    if let Some(level) = args.zstd_dictionary_level() {
+        return Err(zstd_dict::unsupported_error(&batches, level));
+    }
 
     // Calculate total rows
     let total_rows: i64 = batches.iter().map(|b| b.num_rows() as i64).sum();
+    if let Some(expected) = args.expected_total_rows() {
+        if expected != total_rows {
+            return Err(anyhow!(
+                "Source has {} rows but --total-rows={} was given",
+                total_rows,
+                expected
+            ));
+        }
+    }
 
     // Determine number of parts
     let mut parts = args.parts.unwrap_or(1);
     if let Some(max_size) = args.output_file_size_mb {
-        parts = PartitionStrategy::calculate_parts_from_max_size(args.scale_factor, max_size);
+        parts = PartitionStrategy::calculate_parts_from_max_size(
+            args.scale_factor,
+            args.rows,
+            max_size,
+        );
     }
 
-    // Write each part
+    let PartitionedTable {
+        batches,
+        boundaries,
+        extents,
+    } = partition_table(&args, batches, parts)?;
+
+    let spilled = match (args.spill_collect, batches.first()) {
+        (true, Some(first)) => Some(SpilledTable::write(
+            &args.output_dir,
+            first.schema(),
+            &batches,
+        )?),
+        _ => None,
+    };
+    let batches = match spilled {
+        Some(_) => Vec::new(),
+        None => batches,
+    };
+    let part_batches = |part: i32| {
+        let partition = part_partition(total_rows, parts, part, &boundaries);
+        match &spilled {
+            Some(spilled) => spilled.read(&partition),
+            None => partition.apply_to_batches(&batches),
+        }
+    };
+
+    // Write each part, remembering the ones this run wrote
+    let mut written = Vec::new();
     for part in 1..=parts {
-        let partition =
-            PartitionStrategy::calculate(total_rows, Option::from(parts), Option::from(part));
-        let partitioned_batches = partition.apply_to_batches(&batches)?;
+        if args.cancellation.is_cancelled() {
+            info!("Interrupted before part {part} of {parts}");
+            return Err(interrupted_error().into());
+        }
 
-        let part_args = ZoneDfArgs::new(
+        let part_args = ZoneDfArgs {
+            parts: Option::from(parts),
+            part: Option::from(part),
+            total_rows: Some(total_rows),
+            part_boundaries: boundaries.clone(),
+            part_extents: extents.clone(),
+            ..args.clone()
+        };
+        let result = part_batches(part).and_then(|partitioned_batches| {
+            let written = ParquetWriter::new(&part_args, &stats, schema.clone())
+                .write(&partitioned_batches)?;
+            write_simplified_levels(&part_args, &stats, &schema, &partitioned_batches)?;
+            Ok(written)
+        });
+
+        match result {
+            Ok(Some(_)) => written.push(part_args),
+            Ok(None) => {}
+            Err(e) if args.cleanup_on_failure && !is_interrupted(&e) => {
+                remove_written_parts(&args, &written, &part_args)?;
+                return Err(e);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    // All requested parts are done. Single-part workers leave this to `finalize`
+    if args.layout == ZoneLayout::Spark {
+        write_success_marker(&args.output_dir.join("zone"), args.fsync)?;
+        for tolerance in args.simplified_levels() {
+            let level_dir = args.output_dir.join(levels::level_dir_name(tolerance));
+            write_success_marker(&level_dir.join("zone"), args.fsync)?;
+        }
+    }
+
+    if args.also_merge {
+        let merge_args = ZoneDfArgs {
+            parts: Some(parts),
+            total_rows: Some(total_rows),
+            ..args.clone()
+        };
+        if parts == 1 && merge_args.output_filename() == args.merged_filename() {
+            info!(
+                "The single part is already {}, nothing to merge",
+                args.merged_filename().display()
+            );
+        } else {
+            let parts = (1..=parts).map(part_batches).collect::<Result<Vec<_>>>()?;
+            ParquetWriter::new(&merge_args, &stats, schema).write_merged(&parts)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The collected rows in part order with the row range and band of every
+/// part
+struct PartitionedTable {
+    batches: Vec<RecordBatch>,
+    boundaries: Option<Vec<PartBoundary>>,
+    extents: Option<Vec<PartExtent>>,
+}
+
+/// Reorders the collected rows into bands for the band partition
+/// strategies and plans the parts
+#[instrument(
+    name = "zone.partition",
+    skip_all,
+    fields(parts = parts, scheme = ?args.partition_scheme)
+)]
+fn partition_table(
+    args: &ZoneDfArgs,
+    batches: Vec<RecordBatch>,
+    parts: i32,
+) -> Result<PartitionedTable> {
+    match partition::order_by_band(
+        &batches,
+        args.partition_scheme,
+        parts,
+        args.antimeridian_aware,
+    )? {
+        Some(bands) => Ok(PartitionedTable {
+            boundaries: part_boundaries(args, &bands.batches, parts, Some(bands.boundaries))?,
+            batches: bands.batches,
+            extents: Some(bands.extents),
+        }),
+        None => Ok(PartitionedTable {
+            boundaries: part_boundaries(args, &batches, parts, None)?,
+            batches,
+            extents: None,
+        }),
+    }
+}
+
+/// Row ranges of the parts of the collected table: the plan's, the bands',
+/// balanced by vertex count, or `None` to split by row count
+fn part_boundaries(
+    args: &ZoneDfArgs,
+    batches: &[RecordBatch],
+    parts: i32,
+    band_boundaries: Option<Vec<PartBoundary>>,
+) -> Result<Option<Vec<PartBoundary>>> {
+    let total_rows: i64 = batches.iter().map(|b| b.num_rows() as i64).sum();
+    if let Some(boundaries) = &args.part_boundaries {
+        let planned_rows = boundaries.last().map_or(0, |b| b.offset + b.limit);
+        if boundaries.len() != parts as usize || planned_rows != total_rows {
+            return Err(anyhow!(
+                "Plan boundaries cover {} part(s) and {} row(s), but the table has {} part(s) \
+                 and {} row(s)",
+                boundaries.len(),
+                planned_rows,
+                parts,
+                total_rows
+            ));
+        }
+        return Ok(Some(boundaries.clone()));
+    }
+    if band_boundaries.is_some() {
+        return Ok(band_boundaries);
+    }
+
+    match args.balance {
+        Balance::Rows => Ok(None),
+        Balance::Vertices => {
+            let weights = partition::vertex_weights(batches)?;
+            let boundaries = PartitionStrategy::balanced_boundaries(&weights, parts);
+            info!("Balanced {parts} part(s) by vertex count: {boundaries:?}");
+            Ok(Some(boundaries))
+        }
+    }
+}
+
+fn part_partition(
+    total_rows: i64,
+    parts: i32,
+    part: i32,
+    boundaries: &Option<Vec<PartBoundary>>,
+) -> PartitionStrategy {
+    match boundaries {
+        Some(boundaries) => PartitionStrategy::from_boundary(&boundaries[part as usize - 1]),
+        None => PartitionStrategy::calculate(total_rows, Some(parts), Some(part)),
+    }
+}
+
+fn is_interrupted(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<std::io::Error>()
+        .is_some_and(|e| e.kind() == std::io::ErrorKind::Interrupted)
+}
+
+/// Generate the CSV or TBL zone files of [`ZoneDfArgs::file_format`],
+/// every part or only `args.part`, streaming the rows into them
+pub async fn generate_zone_text(args: ZoneDfArgs) -> Result<()> {
+    generate_zone_text_with_ctx(&zone_session_context().await?, args).await
+}
+
+/// [`generate_zone_text`] in a session derived from an embedder's `ctx`,
+/// see [`generate_zone_parquet_single_with_ctx`].
+///
+/// Unlike the other formats the rows are not collected: the batches of the
+/// query run through the batch transforms and into the part files as they
+/// arrive, a part ending once it has its rows. Only a batch is held at a
+/// time, besides what the key order sort keeps or spills, so options
+/// needing the whole table are rejected.
+pub async fn generate_zone_text_with_ctx(ctx: &SessionContext, args: ZoneDfArgs) -> Result<()> {
+    args.validate()?;
+    for (option, set) in [
+        ("--include-hierarchy", args.transform.include_hierarchy),
+        ("--key-partition-by", args.transform.key_partition.is_some()),
+        (
+            "--balance vertices",
+            args.balance == Balance::Vertices && args.part_boundaries.is_none(),
+        ),
+        (
+            "--partition-strategy",
+            args.partition_scheme != PartitionScheme::Rows,
+        ),
+        ("--also-merge", args.also_merge),
+        ("--max-rows-per-file", args.max_rows_per_file.is_some()),
+        ("--verify-key-order", args.verify_key_order),
+    ] {
+        if set {
+            return Err(anyhow!(
+                "--format {} streams the zone rows, so it can't be combined with {option}",
+                args.file_extension()
+            ));
+        }
+    }
+
+    let stats = ZoneTableStats::new(args.scale_factor, args.parts);
+    let (ctx, df) = scan_source(ctx, &args).await?;
+    let total_rows = total_source_rows(&args, &stats, &df).await?;
+    let mut parts = args.parts.unwrap_or(1);
+    if let (Some(max_size), None) = (args.output_file_size_mb, args.part) {
+        parts = PartitionStrategy::calculate_parts_from_max_size(
             args.scale_factor,
-            args.output_dir.clone(),
-            Option::from(parts),
-            Option::from(part),
-            args.output_file_size_mb,
-            args.parquet_row_group_bytes,
-            args.parquet_compression,
+            args.rows,
+            max_size,
         );
+    }
+    let written_parts: Vec<i32> = match args.part {
+        Some(part) => vec![part],
+        None => (1..=parts).collect(),
+    };
+    let partitions: Vec<_> = written_parts
+        .iter()
+        .map(|&part| part_partition(total_rows, parts, part, &args.part_boundaries))
+        .collect();
+
+    // The parts are consecutive, so one query streams the rows of all of them
+    let offset = partitions[0].offset();
+    let rows: i64 = partitions.iter().map(PartitionStrategy::limit).sum();
+    let df = df.limit(offset as usize, Some(rows as usize))?;
+    let (transformer, df) = transform_source(&ctx, &args, df, offset).await?;
+    let schema = transformer.output_schema(&args.transform, &df)?;
+    let mut rows = StreamedRows {
+        stream: df.execute_stream().await?,
+        pending: VecDeque::new(),
+        transformer,
+        next_key: offset + 1,
+    };
 
+    let mut written = Vec::new();
+    for (part, partition) in written_parts.into_iter().zip(partitions) {
+        let part_args = ZoneDfArgs {
+            parts: Some(parts),
+            part: Some(part),
+            total_rows: Some(total_rows),
+            ..args.clone()
+        };
         let writer = ParquetWriter::new(&part_args, &stats, schema.clone());
-        writer.write(&partitioned_batches)?;
+        let result = match writer.start_stream() {
+            Ok(Some(mut file)) => {
+                let span = file.span().clone();
+                let result = rows
+                    .write_part(&part_args, partition.limit(), |batch| {
+                        writer.write_stream(&mut file, batch)
+                    })
+                    .instrument(span)
+                    .await;
+                match result {
+                    Ok(()) => writer.finish_stream(file).map(Some),
+                    Err(e) => writer.discard_stream(file).and(Err(e)),
+                }
+            }
+            // The rows of a part already written are skipped
+            Ok(None) => rows
+                .write_part(&part_args, partition.limit(), |_| Ok(()))
+                .await
+                .map(|()| None),
+            Err(e) => Err(e),
+        };
+
+        match result {
+            Ok(Some(_)) => written.push(part_args),
+            Ok(None) => {}
+            Err(e) if args.cleanup_on_failure && !is_interrupted(&e) => {
+                remove_written_parts(&args, &written, &part_args)?;
+                return Err(e);
+            }
+            Err(e) => return Err(e),
+        }
     }
 
+    if args.layout == ZoneLayout::Spark && args.part.is_none() {
+        write_success_marker(&args.output_dir.join("zone"), args.fsync)?;
+    }
     Ok(())
 }
+
+/// The transformed rows of a zone query, taken part by part as the query
+/// streams them
+struct StreamedRows {
+    stream: SendableRecordBatchStream,
+    /// Transformed rows not yet taken, past the end of the previous part
+    pending: VecDeque<RecordBatch>,
+    transformer: ZoneTransformer,
+    /// `z_zonekey` of the next row, for `--assert-contiguous-keys`
+    next_key: i64,
+}
+
+impl StreamedRows {
+    /// Passes the next `rows` rows to `write` a batch at a time
+    async fn write_part(
+        &mut self,
+        args: &ZoneDfArgs,
+        rows: i64,
+        mut write: impl FnMut(&RecordBatch) -> Result<()>,
+    ) -> Result<()> {
+        let mut remaining = rows as usize;
+        while remaining > 0 {
+            if args.cancellation.is_cancelled() {
+                info!("Interrupted while writing part {:?}", args.part);
+                return Err(interrupted_error().into());
+            }
+            let batch = match self.pending.pop_front() {
+                Some(batch) => batch,
+                None => match self.stream.next().await {
+                    Some(batch) => {
+                        let batches = self
+                            .transformer
+                            .apply_batch_transforms(&args.transform, vec![batch?])?;
+                        self.pending.extend(batches);
+                        continue;
+                    }
+                    None => {
+                        return Err(anyhow!(
+                            "The zone rows ended {remaining} row(s) short of part {:?}; \
+                             the source row count has drifted",
+                            args.part
+                        ))
+                    }
+                },
+            };
+
+            let batch = if batch.num_rows() > remaining {
+                self.pending
+                    .push_front(batch.slice(remaining, batch.num_rows() - remaining));
+                batch.slice(0, remaining)
+            } else {
+                batch
+            };
+            if args.assert_contiguous_keys {
+                check_contiguous_keys(std::slice::from_ref(&batch), self.next_key)?;
+            }
+            self.next_key += batch.num_rows() as i64;
+            remaining -= batch.num_rows();
+            write(&batch)?;
+        }
+        Ok(())
+    }
+}
+
+/// Deletes the `written` part files, their manifest entries and the
+/// temporary file left by the `failed` part
+fn remove_written_parts(
+    args: &ZoneDfArgs,
+    written: &[ZoneDfArgs],
+    failed: &ZoneDfArgs,
+) -> Result<()> {
+    let temp_path = failed.output_filename().with_extension("inprogress");
+    if temp_path.is_file() {
+        std::fs::remove_file(&temp_path)?;
+    }
+    let parts: Vec<i32> = written.iter().filter_map(|a| a.part).collect();
+    // A part split by --max-rows-per-file has several files, all recorded
+    if let Some(manifest) = ZoneManifest::read(&args.output_dir)? {
+        for file in manifest.files.iter().filter(|f| parts.contains(&f.part)) {
+            std::fs::remove_file(args.output_dir.join(&file.path))?;
+        }
+    }
+    ZoneManifest::remove_parts(&args.output_dir, &parts)?;
+    info!(
+        "Removed {} zone part(s) written before part {:?} failed",
+        written.len(),
+        failed.part
+    );
+    Ok(())
+}
+
+/// Generates the zone table and registers it as `table_name` in `ctx`
+/// without writing any files.
+///
+/// Generation runs in a session derived from `ctx`, with the registrations
+/// described in [`generate_zone_parquet_single_with_ctx`]. When `args.part`
+/// is set only the rows of that part are registered. The rows are held in
+/// a [`MemTable`]. Returns the registered row count and schema.
+pub async fn register_generated(
+    ctx: &SessionContext,
+    args: &ZoneDfArgs,
+    table_name: &str,
+) -> Result<(usize, SchemaRef)> {
+    args.validate()?;
+    let (schema, batches) = generate_zone_batches(ctx, args).await?;
+
+    let batches = match args.part {
+        Some(part) => {
+            let total_rows = batches.iter().map(|b| b.num_rows() as i64).sum();
+            let parts = args.parts.unwrap_or(1);
+            let table = partition_table(args, batches, parts)?;
+            part_partition(total_rows, parts, part, &table.boundaries)
+                .apply_to_batches(&table.batches)?
+        }
+        None => batches,
+    };
+    let rows = batches.iter().map(|b| b.num_rows()).sum();
+
+    let table = MemTable::try_new(schema.clone(), vec![batches])?;
+    ctx.register_table(table_name, Arc::new(table))?;
+    info!("Registered {rows} zone row(s) as {table_name}");
+    Ok((rows, schema))
+}
+
+/// Counts the source rows selected for the zone table at `scale_factor`,
+/// at most `rows` when given
+pub async fn count_zone_rows(
+    scale_factor: f64,
+    rows: Option<u64>,
+    themes: Vec<ThemeInput>,
+) -> Result<i64> {
+    let datasource = ZoneDataSource::new()
+        .await?
+        .with_themes(themes)
+        .with_rows(rows);
+    let ctx = datasource.create_context()?;
+    let df = datasource.load_zone_data(&ctx, scale_factor).await?;
+    Ok(df.count().await? as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::compute::cast;
+    use arrow_array::{Array, BinaryArray, Float64Array, Int64Array, StringArray, StructArray};
+    use arrow_schema::{DataType, Field, Schema};
+    use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+    use fixtures::{source_batch, write_parquet, SourceRow};
+    use hash::CONTENT_SHA256_KEY;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use parquet::basic::Compression;
+    use std::collections::{BTreeMap, BTreeSet};
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn source_file(dir: &Path, ids: &[&'static str]) -> ThemeInput {
+        let path = dir.join("division_area.parquet");
+        let rows: Vec<_> = ids.iter().map(|id| SourceRow::new(id, "county")).collect();
+        write_parquet(&path, &source_batch(&rows, true));
+        ThemeInput {
+            theme: Theme::DivisionArea,
+            location: Some(path.to_string_lossy().into_owned()),
+        }
+    }
+
+    fn zone_args(output_dir: &Path, parts: Option<i32>, part: Option<i32>) -> ZoneDfArgs {
+        ZoneDfArgs::new(
+            1.0,
+            output_dir.to_path_buf(),
+            parts,
+            part,
+            None,
+            1024 * 1024,
+            Compression::SNAPPY,
+        )
+    }
+
+    /// Reads `column` of every written row as text keyed by `z_gersid`
+    fn values_by_gersid(output_dir: &Path, column: &str) -> BTreeMap<String, String> {
+        let mut values = BTreeMap::new();
+        for file in verify::discover_files(output_dir).unwrap().values() {
+            let reader = std::fs::File::open(&file.path).unwrap();
+            for batch in ParquetRecordBatchReaderBuilder::try_new(reader)
+                .unwrap()
+                .build()
+                .unwrap()
+            {
+                let batch = batch.unwrap();
+                let ids = cast(batch.column_by_name("z_gersid").unwrap(), &DataType::Utf8).unwrap();
+                let ids = ids.as_any().downcast_ref::<StringArray>().unwrap();
+                let column = cast(batch.column_by_name(column).unwrap(), &DataType::Utf8).unwrap();
+                let column = column.as_any().downcast_ref::<StringArray>().unwrap();
+                for i in 0..batch.num_rows() {
+                    values.insert(ids.value(i).to_string(), column.value(i).to_string());
+                }
+            }
+        }
+        values
+    }
+
+    #[tokio::test]
+    async fn test_synthetic_columns_independent_of_parts() {
+        let dir = tempfile::tempdir().unwrap();
+        let theme = source_file(
+            dir.path(),
+            &["g1", "g2", "g3", "g4", "g5", "g6", "g7", "g8", "g9"],
+        );
+        let transform = ZoneTransformOptions {
+            synthetic_columns: SyntheticColumn::parse_list(
+                "zipf:z_pop:1.1,uniform:z_rand:0..1000,normal:z_score:50:10",
+            )
+            .unwrap(),
+            seed: 42,
+            ..Default::default()
+        };
+        let with_options = |args: ZoneDfArgs| {
+            args.with_themes(vec![theme.clone()])
+                .with_transform(transform.clone())
+        };
+
+        let whole = dir.path().join("whole");
+        generate_zone_parquet_multi(with_options(zone_args(&whole, Some(1), None)))
+            .await
+            .unwrap();
+        let multi = dir.path().join("multi");
+        generate_zone_parquet_multi(with_options(zone_args(&multi, Some(3), None)))
+            .await
+            .unwrap();
+        let single = dir.path().join("single");
+        for part in 1..=3 {
+            generate_zone_parquet_single(with_options(zone_args(&single, Some(3), Some(part))))
+                .await
+                .unwrap();
+        }
+
+        for column in ["z_pop", "z_rand", "z_score"] {
+            let expected = values_by_gersid(&whole, column);
+            assert_eq!(expected.len(), 9);
+            assert_eq!(values_by_gersid(&multi, column), expected, "{column}");
+            assert_eq!(values_by_gersid(&single, column), expected, "{column}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rows_sample_independent_of_parts() {
+        let dir = tempfile::tempdir().unwrap();
+        let theme = source_file(
+            dir.path(),
+            &["g1", "g2", "g3", "g4", "g5", "g6", "g7", "g8", "g9"],
+        );
+        let with_rows =
+            |args: ZoneDfArgs, rows| args.with_themes(vec![theme.clone()]).with_rows(Some(rows));
+
+        let whole = dir.path().join("whole");
+        generate_zone_parquet_multi(with_rows(zone_args(&whole, Some(1), None), 4))
+            .await
+            .unwrap();
+        let multi = dir.path().join("multi");
+        generate_zone_parquet_multi(with_rows(zone_args(&multi, Some(3), None), 4))
+            .await
+            .unwrap();
+        let single = dir.path().join("single");
+        for part in 1..=3 {
+            generate_zone_parquet_single(with_rows(zone_args(&single, Some(3), Some(part)), 4))
+                .await
+                .unwrap();
+        }
+        let larger = dir.path().join("larger");
+        generate_zone_parquet_multi(with_rows(zone_args(&larger, Some(1), None), 6))
+            .await
+            .unwrap();
+
+        let sample: Vec<_> = values_by_gersid(&whole, "z_gersid").into_keys().collect();
+        assert_eq!(sample.len(), 4);
+        for dir in [&multi, &single] {
+            let ids: Vec<_> = values_by_gersid(dir, "z_gersid").into_keys().collect();
+            assert_eq!(ids, sample);
+        }
+        let larger_sample = values_by_gersid(&larger, "z_gersid");
+        assert_eq!(larger_sample.len(), 6);
+        assert!(sample.iter().all(|id| larger_sample.contains_key(id)));
+
+        let manifest = ZoneManifest::read(&multi).unwrap().unwrap();
+        assert_eq!(manifest.scale_factor, 1.0);
+        assert_eq!(manifest.rows, Some(4));
+
+        let count = count_zone_rows(1.0, Some(4), vec![theme.clone()]);
+        assert_eq!(count.await.unwrap(), 4);
+        let count = count_zone_rows(1.0, Some(20), vec![theme.clone()]);
+        assert_eq!(count.await.unwrap(), 9);
+    }
+
+    #[tokio::test]
+    async fn test_limit_keeps_first_rows_in_key_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let ids = [
+            "g09", "g14", "g02", "g11", "g05", "g01", "g13", "g07", "g15", "g03", "g10", "g06",
+            "g12", "g04", "g08",
+        ];
+        let theme = source_file(dir.path(), &ids);
+        let with_limit =
+            |args: ZoneDfArgs| args.with_themes(vec![theme.clone()]).with_limit(Some(10));
+
+        let multi = dir.path().join("multi");
+        generate_zone_parquet_multi(with_limit(zone_args(&multi, Some(3), None)))
+            .await
+            .unwrap();
+        let single = dir.path().join("single");
+        for part in 1..=3 {
+            generate_zone_parquet_single(with_limit(zone_args(&single, Some(3), Some(part))))
+                .await
+                .unwrap();
+        }
+
+        let mut expected: Vec<_> = ids.to_vec();
+        expected.sort();
+        let expected: BTreeMap<String, String> = expected[..10]
+            .iter()
+            .zip(1..)
+            .map(|(id, key)| (id.to_string(), key.to_string()))
+            .collect();
+        for dir in [&multi, &single] {
+            assert_eq!(values_by_gersid(dir, "z_zonekey"), expected);
+        }
+        let manifest = ZoneManifest::read(&multi).unwrap().unwrap();
+        assert_eq!(manifest.limit, Some(10));
+        assert!(manifest.truncated);
+        assert_eq!(manifest.files.iter().map(|f| f.rows).sum::<u64>(), 10);
+
+        // Every file is marked, so verify can tell it from benchmark data
+        for dir in [&multi, &single] {
+            assert_eq!(verify::truncated_files(dir).unwrap().len(), 3);
+            for part in 1..=3 {
+                let path = dir.join(format!("zone/zone.{part}.parquet"));
+                assert_eq!(
+                    hash::read_footer_value(&path, TRUNCATED_KEY)
+                        .unwrap()
+                        .as_deref(),
+                    Some("true")
+                );
+            }
+        }
+        let whole = dir.path().join("whole");
+        generate_zone_parquet_multi(zone_args(&whole, Some(3), None).with_themes(vec![theme]))
+            .await
+            .unwrap();
+        assert!(!ZoneManifest::read(&whole).unwrap().unwrap().truncated);
+        assert!(verify::truncated_files(&whole).unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_max_rows_per_file_splits_parts_without_changing_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let theme = source_file(dir.path(), &["g1", "g2", "g3", "g4", "g5", "g6", "g7"]);
+
+        let whole = dir.path().join("whole");
+        generate_zone_parquet_multi(
+            zone_args(&whole, Some(2), None).with_themes(vec![theme.clone()]),
+        )
+        .await
+        .unwrap();
+        let split_args = |output_dir: &Path, part: Option<i32>| {
+            zone_args(output_dir, Some(2), part)
+                .with_themes(vec![theme.clone()])
+                .with_max_rows_per_file(Some(3))
+        };
+        let multi = dir.path().join("multi");
+        generate_zone_parquet_multi(split_args(&multi, None))
+            .await
+            .unwrap();
+        let single = dir.path().join("single");
+        for part in 1..=2 {
+            generate_zone_parquet_single(split_args(&single, Some(part)))
+                .await
+                .unwrap();
+        }
+
+        let expected = values_by_gersid(&whole, "z_zonekey");
+        let whole_manifest = ZoneManifest::read(&whole).unwrap().unwrap();
+        let manifest = ZoneManifest::read(&multi).unwrap().unwrap();
+        let files: Vec<_> = manifest
+            .files
+            .iter()
+            .map(|f| (f.part, f.path.as_str(), f.rows, f.file_index, f.row_offset))
+            .collect();
+        assert_eq!(
+            files,
+            vec![
+                (1, "zone/zone.1-0.parquet", 3, Some(0), Some(0)),
+                (1, "zone/zone.1-1.parquet", 1, Some(1), Some(3)),
+                (2, "zone/zone.2.parquet", 3, None, None),
+            ]
+        );
+        assert_eq!(whole_manifest.files[0].rows, 4);
+        for output_dir in [&multi, &single] {
+            assert_eq!(values_by_gersid(output_dir, "z_zonekey"), expected);
+            assert!(verify::verify_dir(output_dir).unwrap().is_empty());
+        }
+        assert_eq!(
+            ZoneManifest::read(&single).unwrap().unwrap().files,
+            manifest.files
+        );
+
+        // A rerun finds the split part already written
+        generate_zone_parquet_single(split_args(&single, Some(1)))
+            .await
+            .unwrap();
+        assert!(!single.join("zone/zone.1.parquet").exists());
+    }
+
+    #[tokio::test]
+    async fn test_orc_output_matches_parquet_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let theme = source_file(dir.path(), &["g1", "g2", "g3", "g4", "g5", "g6", "g7"]);
+
+        let parquet = dir.path().join("parquet");
+        generate_zone_parquet_multi(
+            zone_args(&parquet, Some(2), None).with_themes(vec![theme.clone()]),
+        )
+        .await
+        .unwrap();
+        let orc = dir.path().join("orc");
+        for compression in [OrcCompression::Zlib, OrcCompression::Zstd] {
+            std::fs::remove_dir_all(&orc).ok();
+            generate_zone_parquet_multi(
+                zone_args(&orc, Some(2), None)
+                    .with_themes(vec![theme.clone()])
+                    .with_file_format(ZoneFileFormat::Orc(OrcWriteOptions::new(compression, 0))),
+            )
+            .await
+            .unwrap();
+
+            let parquet_manifest = ZoneManifest::read(&parquet).unwrap().unwrap();
+            let orc_manifest = ZoneManifest::read(&orc).unwrap().unwrap();
+            let paths: Vec<_> = orc_manifest.files.iter().map(|f| f.path.as_str()).collect();
+            assert_eq!(paths, vec!["zone/zone.1.orc", "zone/zone.2.orc"]);
+            for (orc_file, parquet_file) in orc_manifest.files.iter().zip(&parquet_manifest.files) {
+                // The content hash covers the rows, whatever the file format
+                assert_eq!(orc_file.rows, parquet_file.rows);
+                assert_eq!(orc_file.content_sha256, parquet_file.content_sha256);
+
+                let file =
+                    orc::reader::read(&std::fs::read(orc.join(&orc_file.path)).unwrap()).unwrap();
+                let reader = std::fs::File::open(parquet.join(&parquet_file.path)).unwrap();
+                let parquet_batches: Vec<RecordBatch> =
+                    ParquetRecordBatchReaderBuilder::try_new(reader)
+                        .unwrap()
+                        .build()
+                        .unwrap()
+                        .map(|batch| batch.unwrap())
+                        .collect();
+                let names = |schema: SchemaRef| -> Vec<String> {
+                    schema.fields().iter().map(|f| f.name().clone()).collect()
+                };
+                assert_eq!(
+                    names(file.batches[0].schema()),
+                    names(parquet_batches[0].schema())
+                );
+                assert_eq!(
+                    arrow::util::pretty::pretty_format_batches(&file.batches)
+                        .unwrap()
+                        .to_string(),
+                    arrow::util::pretty::pretty_format_batches(&parquet_batches)
+                        .unwrap()
+                        .to_string()
+                );
+                assert!(file.metadata.contains(&(
+                    CONTENT_SHA256_KEY.to_string(),
+                    orc_file.content_sha256.clone()
+                )));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_text_output_matches_parquet_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let theme = source_file(dir.path(), &["g1", "g2", "g3", "g4", "g5"]);
+
+        let parquet = dir.path().join("parquet");
+        generate_zone_parquet_multi(
+            zone_args(&parquet, Some(2), None).with_themes(vec![theme.clone()]),
+        )
+        .await
+        .unwrap();
+        let parquet_manifest = ZoneManifest::read(&parquet).unwrap().unwrap();
+
+        for format in [ZoneFileFormat::Csv, ZoneFileFormat::Tbl] {
+            let extension = format.extension();
+            let multi = dir.path().join(format!("{extension}-multi"));
+            generate_zone_text(
+                zone_args(&multi, Some(2), None)
+                    .with_themes(vec![theme.clone()])
+                    .with_file_format(format.clone()),
+            )
+            .await
+            .unwrap();
+            let single = dir.path().join(format!("{extension}-single"));
+            for part in 1..=2 {
+                generate_zone_text(
+                    zone_args(&single, Some(2), Some(part))
+                        .with_themes(vec![theme.clone()])
+                        .with_file_format(format.clone()),
+                )
+                .await
+                .unwrap();
+            }
+
+            let manifest = ZoneManifest::read(&multi).unwrap().unwrap();
+            for (file, parquet_file) in manifest.files.iter().zip(&parquet_manifest.files) {
+                assert_eq!(file.path, format!("zone/zone.{}.{extension}", file.part));
+                // The content hash covers the rows, whatever the file format
+                assert_eq!(file.rows, parquet_file.rows);
+                assert_eq!(file.content_sha256, parquet_file.content_sha256);
+                // Parts streamed alone hold the same rows
+                assert_eq!(
+                    std::fs::read(multi.join(&file.path)).unwrap(),
+                    std::fs::read(single.join(&file.path)).unwrap()
+                );
+            }
+
+            let first = std::fs::read_to_string(multi.join(&manifest.files[0].path)).unwrap();
+            let lines: Vec<_> = first.lines().collect();
+            match format {
+                ZoneFileFormat::Csv => {
+                    assert!(lines[0].starts_with("z_zonekey,z_gersid,"), "{}", lines[0]);
+                    assert_eq!(lines.len(), 4);
+                    assert!(lines[1].starts_with("1,g1,"), "{}", lines[1]);
+                    assert!(lines[1].contains(",\"POLYGON((0 0,1 0,1 1,0 1,0 0))\""));
+                }
+                _ => {
+                    assert_eq!(lines.len(), 3);
+                    assert!(lines[0].starts_with("1|g1|"), "{}", lines[0]);
+                    assert!(lines[0].contains("|POLYGON((0 0,1 0,1 1,0 1,0 0))|"));
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_text_output_rejects_whole_table_options() {
+        let dir = tempfile::tempdir().unwrap();
+        let err = generate_zone_text(
+            zone_args(dir.path(), Some(2), None)
+                .with_file_format(ZoneFileFormat::Csv)
+                .with_also_merge(true),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "--format csv streams the zone rows, so it can't be combined with --also-merge"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_streamed_rows_are_written_before_more_are_read() {
+        const BATCHES: usize = 200;
+        const BATCH_ROWS: usize = 1000;
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("z_zonekey", DataType::Int64, false),
+            Field::new("z_gersid", DataType::Utf8, false),
+            Field::new("z_boundary", DataType::Binary, false),
+        ]));
+
+        // Rows read from the query but not yet written, when the next batch
+        // is read
+        let written = Arc::new(AtomicUsize::new(0));
+        let max_held = Arc::new(AtomicUsize::new(0));
+        let source = {
+            let (schema, written, max_held) = (schema.clone(), written.clone(), max_held.clone());
+            futures::stream::iter(0..BATCHES).map(move |i| {
+                let held = i * BATCH_ROWS - written.load(Ordering::SeqCst);
+                max_held.fetch_max(held, Ordering::SeqCst);
+                let first = (i * BATCH_ROWS) as i64 + 1;
+                let point = fixtures::wkb_point(1.0, 2.0);
+                Ok(RecordBatch::try_new(
+                    schema.clone(),
+                    vec![
+                        Arc::new(Int64Array::from_iter_values(
+                            first..first + BATCH_ROWS as i64,
+                        )),
+                        Arc::new(StringArray::from_iter_values(
+                            (0..BATCH_ROWS).map(|j| format!("g{}", i * BATCH_ROWS + j)),
+                        )),
+                        Arc::new(BinaryArray::from_iter_values(std::iter::repeat_n(
+                            point.as_slice(),
+                            BATCH_ROWS,
+                        ))),
+                    ],
+                )?)
+            })
+        };
+        let mut rows = StreamedRows {
+            stream: Box::pin(RecordBatchStreamAdapter::new(schema.clone(), source)),
+            pending: VecDeque::new(),
+            transformer: ZoneTransformer::new(0),
+            next_key: 1,
+        };
+
+        // Three parts, whose ends fall inside batches
+        let dir = tempfile::tempdir().unwrap();
+        let total_rows = (BATCHES * BATCH_ROWS) as i64;
+        let stats = ZoneTableStats::new(1.0, Some(3));
+        for part in 1..=3 {
+            let args = zone_args(dir.path(), Some(3), Some(part))
+                .with_file_format(ZoneFileFormat::Csv)
+                .with_assert_contiguous_keys(true)
+                .with_total_rows(Some(total_rows));
+            let writer = ParquetWriter::new(&args, &stats, schema.clone());
+            let mut file = writer.start_stream().unwrap().unwrap();
+            let limit = PartitionStrategy::calculate(total_rows, Some(3), Some(part)).limit();
+            rows.write_part(&args, limit, |batch| {
+                written.fetch_add(batch.num_rows(), Ordering::SeqCst);
+                writer.write_stream(&mut file, batch)
+            })
+            .await
+            .unwrap();
+            assert_eq!(writer.finish_stream(file).unwrap(), limit as usize);
+        }
+
+        assert_eq!(written.load(Ordering::SeqCst), BATCHES * BATCH_ROWS);
+        assert!(max_held.load(Ordering::SeqCst) <= BATCH_ROWS);
+        let lines: usize = (1..=3)
+            .map(|part| {
+                let path = dir.path().join(format!("zone/zone.{part}.csv"));
+                std::fs::read_to_string(path).unwrap().lines().count() - 1
+            })
+            .sum();
+        assert_eq!(lines, BATCHES * BATCH_ROWS);
+    }
+
+    #[tokio::test]
+    async fn test_avro_output_matches_parquet_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let theme = source_file(dir.path(), &["g1", "g2", "g3", "g4", "g5"]);
+
+        let parquet = dir.path().join("parquet");
+        generate_zone_parquet_multi(
+            zone_args(&parquet, Some(2), None).with_themes(vec![theme.clone()]),
+        )
+        .await
+        .unwrap();
+        let avro = dir.path().join("avro");
+        generate_zone_parquet_multi(
+            zone_args(&avro, Some(2), None)
+                .with_themes(vec![theme])
+                .with_file_format(ZoneFileFormat::Avro(crate::avro::AvroCodec::Snappy))
+                .with_emit_avro_schema(true),
+        )
+        .await
+        .unwrap();
+
+        let parquet_manifest = ZoneManifest::read(&parquet).unwrap().unwrap();
+        let avro_manifest = ZoneManifest::read(&avro).unwrap().unwrap();
+        let paths: Vec<_> = avro_manifest
+            .files
+            .iter()
+            .map(|f| f.path.as_str())
+            .collect();
+        assert_eq!(paths, vec!["zone/zone.1.avro", "zone/zone.2.avro"]);
+        let avsc: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(avro.join("zone.avsc")).unwrap()).unwrap();
+        for (avro_file, parquet_file) in avro_manifest.files.iter().zip(&parquet_manifest.files) {
+            assert_eq!(avro_file.content_sha256, parquet_file.content_sha256);
+
+            let file =
+                crate::avro::reader::read(&std::fs::read(avro.join(&avro_file.path)).unwrap());
+            assert_eq!(file.schema, avsc);
+            assert_eq!(file.metadata[CONTENT_SHA256_KEY], avro_file.content_sha256);
+            let reader = std::fs::File::open(parquet.join(&parquet_file.path)).unwrap();
+            let parquet_batches: Vec<RecordBatch> =
+                ParquetRecordBatchReaderBuilder::try_new(reader)
+                    .unwrap()
+                    .build()
+                    .unwrap()
+                    .map(|batch| batch.unwrap())
+                    .collect();
+            assert_eq!(
+                arrow::util::pretty::pretty_format_batches(&[file.batch])
+                    .unwrap()
+                    .to_string(),
+                arrow::util::pretty::pretty_format_batches(&parquet_batches)
+                    .unwrap()
+                    .to_string()
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_explain_zone_query_reports_scan_pushdown() {
+        let dir = tempfile::tempdir().unwrap();
+        let theme = source_file(dir.path(), &["g1", "g2", "g3"]);
+        let args = zone_args(dir.path(), None, None)
+            .with_themes(vec![theme])
+            .with_explain(Some(Explain::Analyze { rows: 2 }));
+        let ctx = zone_session_context().await.unwrap();
+        let explain = explain_zone_query(&ctx, &args).await.unwrap();
+
+        assert!(explain.logical_plan.contains("TableScan"));
+        assert_eq!(explain.scans.len(), 1);
+        let scan = &explain.scans[0];
+        assert!(scan.projection.as_ref().unwrap().contains("subtype"));
+        assert!(scan.predicate.as_ref().unwrap().contains("subtype"));
+        assert!(explain.filters_above_scan.is_empty());
+        assert!(explain.analyzed_plan.unwrap().contains("output_rows=2"));
+        assert!(!dir.path().join("zone.parquet").exists());
+    }
+
+    #[tokio::test]
+    async fn test_source_scan_reads_only_needed_columns() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("division_area.parquet");
+        let rows: Vec<_> = ["g1", "g2", "g3"]
+            .into_iter()
+            .map(|id| SourceRow {
+                common_names: vec![("fr", "Zone"), ("de", "Zone")],
+                ..SourceRow::new(id, "county")
+            })
+            .collect();
+        // An Overture column the zone query never reads
+        let batch = source_batch(&rows, true);
+        let mut fields = batch.schema().fields().to_vec();
+        fields.push(Arc::new(Field::new("sources", DataType::Utf8, false)));
+        let mut columns = batch.columns().to_vec();
+        columns.push(Arc::new(StringArray::from(vec!["x".repeat(1000); 3])));
+        let batch = RecordBatch::try_new(Arc::new(Schema::new(fields)), columns).unwrap();
+        write_parquet(&path, &batch);
+        let theme = ThemeInput {
+            theme: Theme::DivisionArea,
+            location: Some(path.to_string_lossy().into_owned()),
+        };
+        let args = zone_args(dir.path(), None, None).with_themes(vec![theme]);
+        let ctx = zone_session_context().await.unwrap();
+
+        let explain = explain_zone_query(&ctx, &args).await.unwrap();
+        let expected: BTreeSet<String> = datasource::expected_scan_columns(None)
+            .into_iter()
+            .filter(|column| column != "name")
+            .collect();
+        assert_eq!(explain.scanned_columns(), expected);
+        assert!(explain.unexpected_columns.is_empty());
+        let bytes = explain.scan_bytes.unwrap();
+        assert!(bytes.saved() > 0, "{bytes:?}");
+        assert!(bytes.names_primary < bytes.names, "{bytes:?}");
+
+        // Only names.primary is carried above the scan unless names.common
+        // is read
+        let languages = args.clone().with_transform(ZoneTransformOptions {
+            names_languages: vec!["fr".to_string()],
+            ..Default::default()
+        });
+        for (args, expected) in [
+            (args, vec!["primary"]),
+            (languages, vec!["primary", "common"]),
+        ] {
+            let (_, df) = scan_source(&ctx, &args).await.unwrap();
+            let names = df.schema().field_with_unqualified_name("names").unwrap();
+            match names.data_type() {
+                DataType::Struct(fields) => {
+                    let names: Vec<_> = fields.iter().map(|f| f.name().as_str()).collect();
+                    assert_eq!(names, expected);
+                }
+                other => panic!("unexpected names type {other}"),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_keep_geometry_type_numbers_kept_rows() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("division_area.parquet");
+        let rows: Vec<_> = ["g1", "g2", "g3", "g4", "g5", "g6"]
+            .into_iter()
+            .enumerate()
+            .map(|(i, id)| SourceRow {
+                geometry: if i % 2 == 0 {
+                    fixtures::wkb_point(2.0, 3.0)
+                } else {
+                    SourceRow::new(id, "county").geometry
+                },
+                ..SourceRow::new(id, "county")
+            })
+            .collect();
+        write_parquet(&path, &source_batch(&rows, true));
+        let theme = ThemeInput {
+            theme: Theme::DivisionArea,
+            location: Some(path.to_string_lossy().into_owned()),
+        };
+        let with_types = |args: ZoneDfArgs| {
+            args.with_themes(vec![theme.clone()])
+                .with_keep_geometry_types(vec![GeometryType::Multipolygon, GeometryType::Polygon])
+        };
+
+        let multi = dir.path().join("multi");
+        generate_zone_parquet_multi(with_types(zone_args(&multi, Some(2), None)))
+            .await
+            .unwrap();
+        let single = dir.path().join("single");
+        for part in 1..=2 {
+            generate_zone_parquet_single(with_types(zone_args(&single, Some(2), Some(part))))
+                .await
+                .unwrap();
+        }
+
+        let expected: BTreeMap<String, String> = [("g2", "1"), ("g4", "2"), ("g6", "3")]
+            .into_iter()
+            .map(|(id, key)| (id.to_string(), key.to_string()))
+            .collect();
+        assert_eq!(values_by_gersid(&multi, "z_zonekey"), expected);
+        assert_eq!(values_by_gersid(&single, "z_zonekey"), expected);
+
+        let manifest = ZoneManifest::read(&single).unwrap().unwrap();
+        assert_eq!(
+            manifest.keep_geometry_types,
+            Some(vec!["polygon".to_string(), "multipolygon".to_string()])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_key_partition_restarts_keys_per_country() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("division_area.parquet");
+        let rows: Vec<_> = [
+            ("g01", "US"),
+            ("g02", "FR"),
+            ("g03", "US"),
+            ("g04", "JP"),
+            ("g05", "FR"),
+            ("g06", "US"),
+            ("g07", "FR"),
+        ]
+        .into_iter()
+        .map(|(id, country)| SourceRow {
+            country,
+            ..SourceRow::new(id, "county")
+        })
+        .collect();
+        write_parquet(&path, &source_batch(&rows, true));
+        let theme = ThemeInput {
+            theme: Theme::DivisionArea,
+            location: Some(path.to_string_lossy().into_owned()),
+        };
+        let per_country = |args: ZoneDfArgs| {
+            args.with_themes(vec![theme.clone()])
+                .with_transform(ZoneTransformOptions {
+                    key_partition: Some(KeyPartition::Country),
+                    composite_key: true,
+                    ..Default::default()
+                })
+        };
+
+        let multi = dir.path().join("multi");
+        generate_zone_parquet_multi(per_country(zone_args(&multi, Some(2), None)))
+            .await
+            .unwrap();
+        // Parts cut from the whole table, not numbered from their offset
+        let single = dir.path().join("single");
+        for part in 1..=2 {
+            generate_zone_parquet_single(per_country(zone_args(&single, Some(2), Some(part))))
+                .await
+                .unwrap();
+        }
+
+        let expected = |pairs: &[(&str, &str)]| -> BTreeMap<String, String> {
+            pairs
+                .iter()
+                .map(|(id, value)| (id.to_string(), value.to_string()))
+                .collect()
+        };
+        for dir in [&multi, &single] {
+            assert_eq!(
+                values_by_gersid(dir, "z_zonekey"),
+                expected(&[
+                    ("g01", "1"),
+                    ("g02", "1"),
+                    ("g03", "2"),
+                    ("g04", "1"),
+                    ("g05", "2"),
+                    ("g06", "3"),
+                    ("g07", "3"),
+                ])
+            );
+            assert_eq!(
+                values_by_gersid(dir, transform::COMPOSITE_KEY_COLUMN),
+                expected(&[
+                    ("g01", "US-1"),
+                    ("g02", "FR-1"),
+                    ("g03", "US-2"),
+                    ("g04", "JP-1"),
+                    ("g05", "FR-2"),
+                    ("g06", "US-3"),
+                    ("g07", "FR-3"),
+                ])
+            );
+        }
+        // The parts run through the countries in order, then the keys
+        let mut order = Vec::new();
+        for part in 1..=2 {
+            let file =
+                std::fs::File::open(multi.join(format!("zone/zone.{part}.parquet"))).unwrap();
+            for batch in ParquetRecordBatchReaderBuilder::try_new(file)
+                .unwrap()
+                .build()
+                .unwrap()
+            {
+                let batch = batch.unwrap();
+                let ids = cast(batch.column_by_name("z_gersid").unwrap(), &DataType::Utf8).unwrap();
+                let ids = ids.as_any().downcast_ref::<StringArray>().unwrap();
+                order.extend(ids.iter().map(|id| id.unwrap().to_string()));
+            }
+        }
+        assert_eq!(order, ["g02", "g05", "g07", "g04", "g01", "g03", "g06"]);
+
+        let hierarchy =
+            per_country(zone_args(&multi, Some(2), None)).with_transform(ZoneTransformOptions {
+                key_partition: Some(KeyPartition::Country),
+                include_hierarchy: true,
+                ..Default::default()
+            });
+        assert!(hierarchy.validate().is_err());
+        let composite_only = zone_args(&multi, None, None).with_transform(ZoneTransformOptions {
+            composite_key: true,
+            ..Default::default()
+        });
+        assert!(composite_only.validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_stratified_sample_independent_of_parts() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("division_area.parquet");
+        const IDS: [&str; 12] = [
+            "g01", "g02", "g03", "g04", "g05", "g06", "g07", "g08", "g09", "g10", "g11", "g12",
+        ];
+        let rows: Vec<_> = IDS
+            .into_iter()
+            .enumerate()
+            .map(|(i, id)| SourceRow {
+                country: match i {
+                    0..=7 => "US",
+                    8..=10 => "FR",
+                    _ => "JP",
+                },
+                ..SourceRow::new(id, "county")
+            })
+            .collect();
+        write_parquet(&path, &source_batch(&rows, true));
+        let theme = ThemeInput {
+            theme: Theme::DivisionArea,
+            location: Some(path.to_string_lossy().into_owned()),
+        };
+        let sampled = |args: ZoneDfArgs| {
+            args.with_themes(vec![theme.clone()])
+                .with_rows(Some(6))
+                .with_sampling(Sampling::StratifiedCountry, 1)
+        };
+
+        let whole = dir.path().join("whole");
+        generate_zone_parquet_multi(sampled(zone_args(&whole, Some(1), None)))
+            .await
+            .unwrap();
+        let multi = dir.path().join("multi");
+        generate_zone_parquet_multi(sampled(zone_args(&multi, Some(3), None)))
+            .await
+            .unwrap();
+        let single = dir.path().join("single");
+        for part in 1..=3 {
+            generate_zone_parquet_single(sampled(zone_args(&single, Some(3), Some(part))))
+                .await
+                .unwrap();
+        }
+
+        let countries = values_by_gersid(&whole, "z_country");
+        let mut per_country = BTreeMap::new();
+        for country in countries.values() {
+            *per_country.entry(country.as_str()).or_insert(0) += 1;
+        }
+        assert_eq!(
+            per_country,
+            BTreeMap::from([("FR", 2), ("JP", 1), ("US", 3)])
+        );
+        assert_eq!(values_by_gersid(&multi, "z_country"), countries);
+        assert_eq!(values_by_gersid(&single, "z_country"), countries);
+
+        let manifest = ZoneManifest::read(&single).unwrap().unwrap();
+        assert_eq!(
+            manifest.sampling.as_deref(),
+            Some("stratified-country, at least 1 per country")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_assert_contiguous_keys_passes_for_all_and_single_parts() {
+        let dir = tempfile::tempdir().unwrap();
+        let theme = source_file(dir.path(), &["g1", "g2", "g3", "g4", "g5", "g6", "g7"]);
+
+        let output = dir.path().join("multi");
+        generate_zone_parquet_multi(
+            zone_args(&output, Some(3), None)
+                .with_themes(vec![theme.clone()])
+                .with_assert_contiguous_keys(true),
+        )
+        .await
+        .unwrap();
+        let keys: BTreeSet<i64> = values_by_gersid(&output, "z_zonekey")
+            .values()
+            .map(|key| key.parse().unwrap())
+            .collect();
+        assert_eq!(keys, (1..=7).collect());
+
+        for part in 1..=3 {
+            generate_zone_parquet_single(
+                zone_args(&dir.path().join("single"), Some(3), Some(part))
+                    .with_themes(vec![theme.clone()])
+                    .with_assert_contiguous_keys(true),
+            )
+            .await
+            .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_columns_written_in_column_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let theme = source_file(dir.path(), &["g1", "g2", "g3"]);
+        let columns = vec![
+            "z_name".to_string(),
+            "z_zonekey".to_string(),
+            "z_gersid".to_string(),
+        ];
+
+        for (order, expected) in [
+            (ColumnOrder::Schema, ["z_zonekey", "z_gersid", "z_name"]),
+            (
+                ColumnOrder::AsSpecified,
+                ["z_name", "z_zonekey", "z_gersid"],
+            ),
+        ] {
+            let output = dir.path().join(format!("{order:?}"));
+            generate_zone_parquet_multi(
+                zone_args(&output, Some(2), None)
+                    .with_themes(vec![theme.clone()])
+                    .with_transform(ZoneTransformOptions {
+                        columns: Some(ColumnSelection::new(columns.clone(), order)),
+                        ..Default::default()
+                    }),
+            )
+            .await
+            .unwrap();
+
+            let files = verify::discover_files(&output).unwrap();
+            assert_eq!(files.len(), 2);
+            for file in files.values() {
+                let file = std::fs::File::open(&file.path).unwrap();
+                let reader = ParquetRecordBatchReaderBuilder::try_new(file).unwrap();
+                let names: Vec<_> = reader
+                    .schema()
+                    .fields()
+                    .iter()
+                    .map(|f| f.name().clone())
+                    .collect();
+                assert_eq!(names, expected, "{order:?}");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_max_string_lengths_truncate_and_report_per_part() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("division_area.parquet");
+        let rows: Vec<_> = [
+            ("g1", "Zürich"),
+            ("g2", "Straße"),
+            ("g3", "東京都"),
+            ("g4", "Köln"),
+        ]
+        .into_iter()
+        .map(|(id, name)| SourceRow {
+            name,
+            ..SourceRow::new(id, "county")
+        })
+        .collect();
+        write_parquet(&path, &source_batch(&rows, true));
+        let theme = ThemeInput {
+            theme: Theme::DivisionArea,
+            location: Some(path.to_string_lossy().into_owned()),
+        };
+        let limits = MaxStringLength::parse_list("z_name=5").unwrap();
+
+        let output = dir.path().join("out");
+        generate_zone_parquet_multi(
+            zone_args(&output, Some(2), None)
+                .with_themes(vec![theme.clone()])
+                .with_max_string_lengths(limits.clone(), OnOverlong::Truncate),
+        )
+        .await
+        .unwrap();
+
+        // Cut at the last character boundary within 5 bytes
+        assert_eq!(
+            values_by_gersid(&output, "z_name"),
+            BTreeMap::from([
+                ("g1".to_string(), "Züri".to_string()),
+                ("g2".to_string(), "Stra".to_string()),
+                ("g3".to_string(), "東".to_string()),
+                ("g4".to_string(), "Köln".to_string()),
+            ])
+        );
+        assert_eq!(
+            std::fs::read_to_string(output.join("zone.1.truncated.csv")).unwrap(),
+            "z_gersid,column,bytes,max_bytes\ng1,z_name,7,5\ng2,z_name,7,5\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(output.join("zone.2.truncated.csv")).unwrap(),
+            "z_gersid,column,bytes,max_bytes\ng3,z_name,9,5\n"
+        );
+        for file in verify::discover_files(&output).unwrap().values() {
+            let reader = std::fs::File::open(&file.path).unwrap();
+            let schema = ParquetRecordBatchReaderBuilder::try_new(reader)
+                .unwrap()
+                .schema()
+                .clone();
+            let name = schema.field_with_name("z_name").unwrap();
+            assert_eq!(
+                name.metadata().get(MAX_LENGTH_KEY).map(String::as_str),
+                Some("5")
+            );
+        }
+
+        let failed = dir.path().join("failed");
+        let err = generate_zone_parquet_multi(
+            zone_args(&failed, Some(2), None)
+                .with_themes(vec![theme])
+                .with_max_string_lengths(limits, OnOverlong::Fail),
+        )
+        .await
+        .unwrap_err();
+        assert!(
+            err.to_string()
+                .ends_with("z_name of g1 is 7 bytes, over --max-string-length z_name=5"),
+            "{err}"
+        );
+        assert!(verify::discover_files(&failed)
+            .map(|files| files.is_empty())
+            .unwrap_or(true));
+    }
+
+    #[tokio::test]
+    async fn test_zonekey_ranges_of_row_groups_are_disjoint() {
+        // 3000 zones listed in reverse id order, in row groups of 1000 rows
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("division_area.parquet");
+        let rows: Vec<_> = (0..3000)
+            .rev()
+            .map(|i| {
+                let id: &'static str = Box::leak(format!("g{i:04}").into_boxed_str());
+                SourceRow::new(id, "county")
+            })
+            .collect();
+        write_parquet(&path, &source_batch(&rows, true));
+        let theme = ThemeInput {
+            theme: Theme::DivisionArea,
+            location: Some(path.to_string_lossy().into_owned()),
+        };
+
+        let output = dir.path().join("out");
+        generate_zone_parquet_multi(
+            zone_args(&output, Some(1), None)
+                .with_themes(vec![theme])
+                .with_verify_key_order(true),
+        )
+        .await
+        .unwrap();
+
+        let file = std::fs::File::open(output.join("zone.parquet")).unwrap();
+        let reader = ParquetRecordBatchReaderBuilder::try_new(file).unwrap();
+        let metadata = reader.metadata();
+        let schema = metadata.file_metadata().schema_descr();
+        let column = (0..schema.num_columns())
+            .find(|&i| schema.column(i).name() == "z_zonekey")
+            .unwrap();
+        let ranges: Vec<(i64, i64)> = metadata
+            .row_groups()
+            .iter()
+            .map(|row_group| match row_group.column(column).statistics() {
+                Some(parquet::file::statistics::Statistics::Int64(s)) => {
+                    (*s.min_opt().unwrap(), *s.max_opt().unwrap())
+                }
+                other => panic!("unexpected statistics {other:?}"),
+            })
+            .collect();
+        assert_eq!(ranges, vec![(1, 1000), (1001, 2000), (2001, 3000)]);
+    }
+
+    #[tokio::test]
+    async fn test_extra_columns_have_statistics() {
+        let dir = tempfile::tempdir().unwrap();
+        let theme = source_file(dir.path(), &["g1", "g2"]);
+        let output = dir.path().join("out");
+        generate_zone_parquet_multi(
+            zone_args(&output, Some(1), None)
+                .with_themes(vec![theme])
+                .with_transform(ZoneTransformOptions {
+                    extra_columns: vec![ExtraColumn::VertexCount, ExtraColumn::GeomType],
+                    ..Default::default()
+                }),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(values_by_gersid(&output, "z_vertexcount")["g1"], "5");
+        assert_eq!(values_by_gersid(&output, "z_geomtype")["g2"], "Polygon");
+        let file = std::fs::File::open(output.join("zone.parquet")).unwrap();
+        let reader = ParquetRecordBatchReaderBuilder::try_new(file).unwrap();
+        let metadata = reader.metadata();
+        let schema = metadata.file_metadata().schema_descr();
+        let column = |name: &str| {
+            (0..schema.num_columns())
+                .find(|&i| schema.column(i).name() == name)
+                .unwrap()
+        };
+        match metadata
+            .row_group(0)
+            .column(column("z_vertexcount"))
+            .statistics()
+        {
+            Some(parquet::file::statistics::Statistics::Int32(s)) => {
+                assert_eq!((s.min_opt(), s.max_opt()), (Some(&5), Some(&5)));
+            }
+            other => panic!("unexpected statistics {other:?}"),
+        }
+        assert!(metadata
+            .row_group(0)
+            .column(column("z_geomtype"))
+            .statistics()
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn test_geojson_geometry_encoding() {
+        let dir = tempfile::tempdir().unwrap();
+        let theme = source_file(dir.path(), &["g1", "g2"]);
+        let transform = ZoneTransformOptions {
+            geometry_encoding: GeometryEncoding::Geojson,
+            ..Default::default()
+        };
+        let square = serde_json::json!({
+            "type": "Polygon",
+            "coordinates": [[[0, 0], [1, 0], [1, 1], [0, 1], [0, 0]]],
+        });
+
+        let parquet = dir.path().join("parquet");
+        generate_zone_parquet_multi(
+            zone_args(&parquet, Some(1), None)
+                .with_themes(vec![theme.clone()])
+                .with_transform(transform.clone()),
+        )
+        .await
+        .unwrap();
+        let file = std::fs::File::open(parquet.join("zone.parquet")).unwrap();
+        let reader = ParquetRecordBatchReaderBuilder::try_new(file).unwrap();
+        let field = reader
+            .schema()
+            .field_with_name("z_boundary")
+            .unwrap()
+            .clone();
+        assert_eq!(field.data_type(), &DataType::Utf8);
+        let geometry = &values_by_gersid(&parquet, "z_boundary")["g1"];
+        let geometry: serde_json::Value = serde_json::from_str(geometry).unwrap();
+        assert_eq!(geometry, square);
+
+        let csv = dir.path().join("csv");
+        generate_zone_text(
+            zone_args(&csv, Some(1), None)
+                .with_themes(vec![theme])
+                .with_file_format(ZoneFileFormat::Csv)
+                .with_transform(transform),
+        )
+        .await
+        .unwrap();
+        let text = std::fs::read_to_string(csv.join("zone.csv")).unwrap();
+        let row = text.lines().nth(1).unwrap();
+        let quoted = &row[row.find("\"{").unwrap() + 1..row.rfind("}\"").unwrap() + 1];
+        let geometry: serde_json::Value =
+            serde_json::from_str(&quoted.replace("\"\"", "\"")).unwrap();
+        assert_eq!(geometry, square);
+
+        let err = zone_args(dir.path(), Some(1), None)
+            .with_transform(ZoneTransformOptions {
+                geometry_encoding: GeometryEncoding::Geojson,
+                bbox_covering: true,
+                ..Default::default()
+            })
+            .validate()
+            .unwrap_err();
+        assert!(err.to_string().contains("--with-bbox-covering"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn test_fractional_scale_factor_samples_source() {
+        let dir = tempfile::tempdir().unwrap();
+        let ids = [
+            "g01", "g02", "g03", "g04", "g05", "g06", "g07", "g08", "g09", "g10",
+        ];
+        let theme = source_file(dir.path(), &ids);
+        let args = |output: &Path, scale_factor: f64, parts: i32, part: Option<i32>| ZoneDfArgs {
+            scale_factor,
+            ..zone_args(output, Some(parts), part).with_themes(vec![theme.clone()])
+        };
+        let gersids = |output: &Path| -> BTreeSet<String> {
+            values_by_gersid(output, "z_gersid").into_keys().collect()
+        };
+
+        let half = dir.path().join("half");
+        generate_zone_parquet_multi(args(&half, 0.5, 2, None))
+            .await
+            .unwrap();
+        let half_ids = gersids(&half);
+        assert_eq!(half_ids.len(), 5);
+        assert_eq!(
+            ZoneManifest::read(&half).unwrap().unwrap().scale_factor,
+            0.5
+        );
+
+        // Parts generated alone hold the same sample
+        let parts = dir.path().join("parts");
+        for part in 1..=2 {
+            generate_zone_parquet_single(args(&parts, 0.5, 2, Some(part)))
+                .await
+                .unwrap();
+        }
+        assert_eq!(gersids(&parts), half_ids);
+
+        // A smaller fraction keeps a subset, rounded up
+        let fifth = dir.path().join("fifth");
+        generate_zone_parquet_multi(args(&fifth, 0.15, 1, None))
+            .await
+            .unwrap();
+        let fifth_ids = gersids(&fifth);
+        assert_eq!(fifth_ids.len(), 2);
+        assert!(fifth_ids.is_subset(&half_ids));
+
+        // Scale factors above 1 select every county
+        let large = dir.path().join("large");
+        generate_zone_parquet_multi(args(&large, 1000.0, 1, None))
+            .await
+            .unwrap();
+        assert_eq!(gersids(&large).len(), ids.len());
+
+        let err = args(dir.path(), -1.0, 1, None).validate().unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("Invalid --scale-factor -1, expected a finite number above 0"));
+    }
+
+    /// Row groups of `metadata` whose `z_bbox` statistics overlap the
+    /// window `[xmin, ymin, xmax, ymax]`, as a GeoParquet 1.1 reader
+    /// selects them
+    fn row_groups_in_window(
+        metadata: &parquet::file::metadata::ParquetMetaData,
+        window: [f64; 4],
+    ) -> Vec<usize> {
+        let schema = metadata.file_metadata().schema_descr();
+        let stats = |row_group: usize, field: &str| {
+            let column = (0..schema.num_columns())
+                .find(|&i| schema.column(i).path().string() == format!("z_bbox.{field}"))
+                .unwrap();
+            match metadata.row_group(row_group).column(column).statistics() {
+                Some(parquet::file::statistics::Statistics::Double(s)) => {
+                    (*s.min_opt().unwrap(), *s.max_opt().unwrap())
+                }
+                other => panic!("unexpected statistics {other:?}"),
+            }
+        };
+        (0..metadata.num_row_groups())
+            .filter(|&rg| {
+                stats(rg, "xmin").0 <= window[2]
+                    && stats(rg, "xmax").1 >= window[0]
+                    && stats(rg, "ymin").0 <= window[3]
+                    && stats(rg, "ymax").1 >= window[1]
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_bbox_layouts_written() {
+        let dir = tempfile::tempdir().unwrap();
+        let theme = source_file(dir.path(), &["g1", "g2"]);
+
+        for layout in [BboxLayout::Struct, BboxLayout::Flat] {
+            let transform = ZoneTransformOptions {
+                bbox_covering: true,
+                bbox_layout: layout,
+                ..Default::default()
+            };
+            let output = dir.path().join(format!("{layout:?}"));
+            generate_zone_parquet_multi(
+                zone_args(&output, Some(1), None)
+                    .with_themes(vec![theme.clone()])
+                    .with_transform(transform.clone()),
+            )
+            .await
+            .unwrap();
+
+            let reader = std::fs::File::open(output.join("zone.parquet")).unwrap();
+            let reader = ParquetRecordBatchReaderBuilder::try_new(reader).unwrap();
+            let schema = reader.schema().clone();
+            let names: Vec<_> = schema.fields().iter().map(|f| f.name().as_str()).collect();
+            let geo = reader
+                .metadata()
+                .file_metadata()
+                .key_value_metadata()
+                .unwrap()
+                .iter()
+                .find(|kv| kv.key == "geo")
+                .and_then(|kv| kv.value.clone())
+                .unwrap();
+            let geo: serde_json::Value = serde_json::from_str(&geo).unwrap();
+            let column = &geo["columns"]["z_boundary"];
+            assert_eq!(column["bbox"], serde_json::json!([0.0, 0.0, 1.0, 1.0]));
+
+            let batch = reader.build().unwrap().next().unwrap().unwrap();
+            let bounds: Vec<f64> = match layout {
+                BboxLayout::Struct => {
+                    assert!(names.contains(&"z_bbox"), "{names:?}");
+                    assert!(!names.contains(&"z_xmin"), "{names:?}");
+                    assert!(column.get("covering").is_some());
+                    let bbox = batch.column_by_name("z_bbox").unwrap();
+                    let bbox = bbox.as_any().downcast_ref::<StructArray>().unwrap();
+                    (0..4)
+                        .map(|i| {
+                            let field = bbox.column(i).as_any();
+                            field.downcast_ref::<Float64Array>().unwrap().value(1)
+                        })
+                        .collect()
+                }
+                BboxLayout::Flat => {
+                    assert_eq!(
+                        &names[names.len() - 4..],
+                        ["z_xmin", "z_ymin", "z_xmax", "z_ymax"]
+                    );
+                    assert!(!names.contains(&"z_bbox"), "{names:?}");
+                    assert!(column.get("covering").is_none());
+                    ["z_xmin", "z_ymin", "z_xmax", "z_ymax"]
+                        .iter()
+                        .map(|name| {
+                            let field = batch.column_by_name(name).unwrap();
+                            field
+                                .as_any()
+                                .downcast_ref::<Float64Array>()
+                                .unwrap()
+                                .value(1)
+                        })
+                        .collect()
+                }
+            };
+            assert_eq!(bounds, vec![0.0, 0.0, 1.0, 1.0]);
+
+            // The streamed CSV writer takes the same columns
+            let csv = dir.path().join(format!("{layout:?}-csv"));
+            generate_zone_text(
+                zone_args(&csv, Some(1), None)
+                    .with_themes(vec![theme.clone()])
+                    .with_transform(transform)
+                    .with_file_format(ZoneFileFormat::Csv),
+            )
+            .await
+            .unwrap();
+            let text = std::fs::read_to_string(csv.join("zone.csv")).unwrap();
+            let header = text.lines().next().unwrap();
+            assert_eq!(header.split(',').collect::<Vec<_>>(), names);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_bbox_covering_prunes_row_groups() {
+        // 3000 zones in id order, each a unit square 0.1 further east, so
+        // the row groups of 1000 rows start at x = 0, 100 and 200
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("division_area.parquet");
+        let rows: Vec<_> = (0..3000)
+            .map(|i| {
+                let id: &'static str = Box::leak(format!("g{i:04}").into_boxed_str());
+                let x = i as f64 / 10.0;
+                SourceRow {
+                    geometry: fixtures::wkb_polygon(&[
+                        (x, 0.0),
+                        (x + 1.0, 0.0),
+                        (x + 1.0, 1.0),
+                        (x, 0.0),
+                    ]),
+                    ..SourceRow::new(id, "county")
+                }
+            })
+            .collect();
+        write_parquet(&path, &source_batch(&rows, true));
+        let theme = ThemeInput {
+            theme: Theme::DivisionArea,
+            location: Some(path.to_string_lossy().into_owned()),
+        };
+
+        let output = dir.path().join("out");
+        let transform = ZoneTransformOptions {
+            bbox_covering: true,
+            ..Default::default()
+        };
+        generate_zone_parquet_multi(
+            zone_args(&output, Some(1), None)
+                .with_themes(vec![theme])
+                .with_transform(transform)
+                .with_deterministic(true),
+        )
+        .await
+        .unwrap();
+
+        let file = output.join("zone.parquet");
+        let reader =
+            ParquetRecordBatchReaderBuilder::try_new(std::fs::File::open(&file).unwrap()).unwrap();
+        let metadata = reader.metadata();
+        assert_eq!(metadata.num_row_groups(), 3);
+        let geo = metadata
+            .file_metadata()
+            .key_value_metadata()
+            .unwrap()
+            .iter()
+            .find(|kv| kv.key == "geo")
+            .and_then(|kv| kv.value.clone())
+            .unwrap();
+        let geo: serde_json::Value = serde_json::from_str(&geo).unwrap();
+        assert_eq!(
+            geo["columns"]["z_boundary"]["bbox"],
+            serde_json::json!([0.0, 0.0, 300.9, 1.0])
+        );
+
+        // A window east of x = 250 overlaps the last row group only, which
+        // holds every zone in the window
+        let window = [250.0, 0.0, 400.0, 1.0];
+        let row_groups = row_groups_in_window(metadata, window);
+        assert_eq!(row_groups, vec![2]);
+        let in_window: usize = reader
+            .with_row_groups(row_groups)
+            .build()
+            .unwrap()
+            .map(|batch| {
+                let batch = batch.unwrap();
+                let bbox = batch.column_by_name("z_bbox").unwrap();
+                let bbox = bbox.as_any().downcast_ref::<StructArray>().unwrap();
+                let xmax = bbox.column_by_name("xmax").unwrap();
+                let xmax = xmax.as_any().downcast_ref::<Float64Array>().unwrap();
+                xmax.iter().filter(|x| x.unwrap() >= window[0]).count()
+            })
+            .sum();
+        assert_eq!(in_window, 510);
+    }
+
+    #[tokio::test]
+    async fn test_hierarchy_parents_across_parts() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("division_area.parquet");
+        let mut rows = vec![
+            SourceRow::new("g1", "country"),
+            SourceRow::new("g2", "region"),
+            SourceRow::new("g3", "locality"),
+        ];
+        rows[0].region = "";
+        write_parquet(&source, &source_batch(&rows, true));
+        let theme = ThemeInput {
+            theme: Theme::DivisionArea,
+            location: Some(source.to_string_lossy().into_owned()),
+        };
+
+        // The country subtype is only selected from SF 1000
+        let with_options = |dir: &Path, part: Option<i32>| {
+            let mut args = zone_args(dir, Some(3), part)
+                .with_themes(vec![theme.clone()])
+                .with_transform(ZoneTransformOptions {
+                    include_hierarchy: true,
+                    ..Default::default()
+                });
+            args.scale_factor = 1000.0;
+            args
+        };
+
+        let multi = dir.path().join("multi");
+        generate_zone_parquet_multi(with_options(&multi, None))
+            .await
+            .unwrap();
+        let single = dir.path().join("single");
+        for part in 1..=3 {
+            generate_zone_parquet_single(with_options(&single, Some(part)))
+                .await
+                .unwrap();
+        }
+
+        for output in [&multi, &single] {
+            let keys = values_by_gersid(output, "z_zonekey");
+            let parents = values_by_gersid(output, "z_parent_zonekey");
+            assert_eq!(parents["g1"], "");
+            assert_eq!(parents["g2"], keys["g1"]);
+            assert_eq!(parents["g3"], keys["g2"]);
+
+            let levels = values_by_gersid(output, "z_admin_level");
+            assert_eq!(
+                (&*levels["g1"], &*levels["g2"], &*levels["g3"]),
+                ("0", "2", "6")
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_names_languages_from_common() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("division_area.parquet");
+        let mut rows = vec![
+            SourceRow::new("g1", "county"),
+            SourceRow::new("g2", "county"),
+        ];
+        rows[0].name = "Geneva";
+        rows[0].common_names = vec![("fr", "Genève"), ("de", "Genf")];
+        write_parquet(&source, &source_batch(&rows, true));
+
+        let output = dir.path().join("out");
+        let args = zone_args(&output, Some(1), None)
+            .with_themes(vec![ThemeInput {
+                theme: Theme::DivisionArea,
+                location: Some(source.to_string_lossy().into_owned()),
+            }])
+            .with_transform(ZoneTransformOptions {
+                names_common: true,
+                names_languages: vec!["fr".to_string(), "zh-Hant".to_string()],
+                ..Default::default()
+            });
+        generate_zone_parquet_multi(args).await.unwrap();
+
+        let french = values_by_gersid(&output, "z_name_fr");
+        assert_eq!((&*french["g1"], &*french["g2"]), ("Genève", ""));
+        let chinese = values_by_gersid(&output, "z_name_zh_hant");
+        assert_eq!((&*chinese["g1"], &*chinese["g2"]), ("", ""));
+        assert_eq!(values_by_gersid(&output, "z_name")["g1"], "Geneva");
+
+        let common = values_by_gersid(&output, "z_name_common");
+        assert_eq!(common["g1"], r#"{"de":"Genf","fr":"Genève"}"#);
+        assert_eq!(common["g2"], "");
+    }
+
+    #[tokio::test]
+    async fn test_pseudonymized_names_match_across_parts() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("division_area.parquet");
+        let mut rows = vec![
+            SourceRow::new("g1", "county"),
+            SourceRow::new("g2", "county"),
+            SourceRow::new("g3", "county"),
+        ];
+        rows[0].name = "Geneva";
+        rows[0].common_names = vec![("fr", "Genève")];
+        rows[1].name = "";
+        write_parquet(&source, &source_batch(&rows, true));
+
+        let generate = |output: &Path, parts: i32| {
+            zone_args(output, Some(parts), None)
+                .with_themes(vec![ThemeInput {
+                    theme: Theme::DivisionArea,
+                    location: Some(source.to_string_lossy().into_owned()),
+                }])
+                .with_transform(ZoneTransformOptions {
+                    names_common: true,
+                    names_languages: vec!["fr".to_string()],
+                    pseudonymize_names: Some(PseudonymStyle::Pronounceable),
+                    seed: 3,
+                    ..Default::default()
+                })
+        };
+        let (single, split) = (dir.path().join("single"), dir.path().join("split"));
+        generate_zone_parquet_multi(generate(&single, 1))
+            .await
+            .unwrap();
+        generate_zone_parquet_multi(generate(&split, 2))
+            .await
+            .unwrap();
+
+        for column in ["z_name", "z_name_fr", "z_name_common"] {
+            assert_eq!(
+                values_by_gersid(&single, column),
+                values_by_gersid(&split, column),
+                "{column}"
+            );
+        }
+        let names = values_by_gersid(&single, "z_name");
+        assert_ne!(names["g1"], "Geneva");
+        assert_eq!(names["g1"].len(), "Geneva".len());
+        assert_eq!(names["g2"], "");
+        let french = values_by_gersid(&single, "z_name_fr");
+        assert_eq!(
+            values_by_gersid(&single, "z_name_common")["g1"],
+            serde_json::json!({ "fr": french["g1"] }).to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_provenance_matches_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        let theme = source_file(dir.path(), &["g1", "g2", "g3", "g4"]);
+        let expected = format!("division_area={}", theme.location.as_deref().unwrap());
+
+        let output = dir.path().join("out");
+        let args = zone_args(&output, Some(2), None)
+            .with_themes(vec![theme])
+            .with_transform(ZoneTransformOptions {
+                include_provenance: true,
+                ..Default::default()
+            });
+        generate_zone_parquet_multi(args).await.unwrap();
+
+        let sources = values_by_gersid(&output, "z_source");
+        assert_eq!(sources.len(), 4);
+        assert!(sources.values().all(|s| *s == expected), "{sources:?}");
+        let manifest = ZoneManifest::read(&output).unwrap().unwrap();
+        assert_eq!(manifest.source, Some(expected));
+    }
+
+    #[tokio::test]
+    async fn test_attribution_notice_and_column() {
+        let dir = tempfile::tempdir().unwrap();
+        let theme = source_file(dir.path(), &["g1", "g2", "g3"]);
+        let output = dir.path().join("out");
+        let args = zone_args(&output, Some(2), None)
+            .with_themes(vec![theme])
+            .with_include_attribution(true)
+            .with_transform(ZoneTransformOptions {
+                attribution_column: true,
+                ..Default::default()
+            });
+        generate_zone_parquet_multi(args).await.unwrap();
+
+        let attributions = values_by_gersid(&output, ATTRIBUTION_COLUMN);
+        assert_eq!(attributions.len(), 3);
+        assert!(attributions
+            .values()
+            .all(|a| a == "Overture Maps Foundation"));
+        let text = std::fs::read_to_string(output.join(ATTRIBUTION_FILE_NAME)).unwrap();
+        let manifest = ZoneManifest::read(&output).unwrap().unwrap();
+        assert_eq!(
+            manifest.attribution_sha256,
+            Some(attribution::attribution_sha256(&text))
+        );
+        assert_eq!(verify::attribution_problem(&output).unwrap(), None);
+
+        std::fs::remove_file(output.join(ATTRIBUTION_FILE_NAME)).unwrap();
+        assert_eq!(
+            verify::attribution_problem(&output).unwrap().unwrap(),
+            "ATTRIBUTION.txt is missing"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_no_geometry_leaves_out_the_boundary() {
+        let dir = tempfile::tempdir().unwrap();
+        let theme = source_file(dir.path(), &["g1", "g2", "g3"]);
+        let generate = |name: &str, no_geometry: bool| {
+            let output = dir.path().join(name);
+            let args = zone_args(&output, Some(1), None)
+                .with_themes(vec![theme.clone()])
+                .with_transform(ZoneTransformOptions {
+                    no_geometry,
+                    ..Default::default()
+                });
+            (output, args)
+        };
+        let (full, args) = generate("full", false);
+        generate_zone_parquet_multi(args).await.unwrap();
+        let (attributes, args) = generate("attributes", true);
+        generate_zone_parquet_multi(args).await.unwrap();
+
+        let schema = |output: &Path| {
+            let reader = std::fs::File::open(output.join("zone.parquet")).unwrap();
+            let reader = ParquetRecordBatchReaderBuilder::try_new(reader).unwrap();
+            let names: Vec<String> = reader
+                .schema()
+                .fields()
+                .iter()
+                .map(|f| f.name().clone())
+                .collect();
+            (names, reader.schema().metadata().clone())
+        };
+        let (full_names, _) = schema(&full);
+        let (names, metadata) = schema(&attributes);
+        let expected: Vec<_> = full_names
+            .iter()
+            .filter(|name| *name != "z_boundary")
+            .cloned()
+            .collect();
+        assert_eq!(names, expected);
+        assert!(!metadata.contains_key("geo"));
+        assert_eq!(metadata.get(schema::SCHEMA_VERSION_KEY).unwrap(), "custom");
+        for column in ["z_zonekey", "z_name", "z_country", "z_subtype"] {
+            assert_eq!(
+                values_by_gersid(&attributes, column),
+                values_by_gersid(&full, column)
+            );
+        }
+
+        let (_, mut args) = generate("bbox", true);
+        args.transform.bbox_covering = true;
+        assert!(args
+            .validate()
+            .unwrap_err()
+            .to_string()
+            .contains("--no-geometry leaves out z_boundary, which --with-bbox-covering reads"));
+    }
+
+    /// SHA-256 of the raw bytes of every file under `dir`, by relative path
+    fn file_hashes(dir: &Path) -> BTreeMap<String, String> {
+        use sha2::{Digest, Sha256};
+
+        let mut hashes = BTreeMap::new();
+        let mut dirs = vec![dir.to_path_buf()];
+        while let Some(current) = dirs.pop() {
+            for entry in std::fs::read_dir(current).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    dirs.push(path);
+                    continue;
+                }
+                let digest = Sha256::digest(std::fs::read(&path).unwrap());
+                let relative = path.strip_prefix(dir).unwrap().to_string_lossy();
+                hashes.insert(relative.into_owned(), format!("{digest:x}"));
+            }
+        }
+        hashes
+    }
+
+    #[tokio::test]
+    async fn test_deterministic_runs_write_identical_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let theme = source_file(dir.path(), &["g5", "g2", "g4", "g1", "g3"]);
+
+        let run = |output: PathBuf, part: Option<i32>| {
+            zone_args(&output, Some(2), part)
+                .with_themes(vec![theme.clone()])
+                .with_layout(ZoneLayout::Spark)
+                .with_deterministic(true)
+        };
+        let first = dir.path().join("first");
+        let second = dir.path().join("second");
+        generate_zone_parquet_multi(run(first.clone(), None))
+            .await
+            .unwrap();
+        generate_zone_parquet_multi(run(second.clone(), None))
+            .await
+            .unwrap();
+
+        let hashes = file_hashes(&first);
+        assert!(hashes.keys().any(|name| name.ends_with(".parquet")));
+        assert_eq!(hashes, file_hashes(&second));
+
+        // Separately generated parts match the parts of the multi-part run
+        let single = dir.path().join("single");
+        for part in 1..=2 {
+            generate_zone_parquet_single(run(single.clone(), Some(part)))
+                .await
+                .unwrap();
+        }
+        let parts = |hashes: BTreeMap<String, String>| {
+            hashes
+                .into_iter()
+                .filter(|(name, _)| name.ends_with(".parquet"))
+                .collect::<BTreeMap<_, _>>()
+        };
+        assert_eq!(parts(file_hashes(&single)), parts(hashes));
+    }
+
+    #[tokio::test]
+    async fn test_write_buffer_and_fsync_leave_files_unchanged() {
+        let dir = tempfile::tempdir().unwrap();
+        let theme = source_file(dir.path(), &["g1", "g2", "g3", "g4", "g5"]);
+
+        let run = |output: PathBuf, part: Option<i32>| {
+            zone_args(&output, Some(2), part)
+                .with_themes(vec![theme.clone()])
+                .with_deterministic(true)
+        };
+        let default = dir.path().join("default");
+        generate_zone_parquet_multi(run(default.clone(), None))
+            .await
+            .unwrap();
+        let tuned = dir.path().join("tuned");
+        generate_zone_parquet_multi(
+            run(tuned.clone(), None).with_write_io(Some(3), Fsync::PerRowgroup),
+        )
+        .await
+        .unwrap();
+        let single = dir.path().join("single");
+        for part in 1..=2 {
+            generate_zone_parquet_single(
+                run(single.clone(), Some(part)).with_write_io(Some(1 << 20), Fsync::PerPart),
+            )
+            .await
+            .unwrap();
+        }
+        let parts = |dir: &Path| {
+            file_hashes(dir)
+                .into_iter()
+                .filter(|(name, _)| name.ends_with(".parquet"))
+                .collect::<BTreeMap<_, _>>()
+        };
+        assert_eq!(parts(&default).len(), 2);
+        assert_eq!(parts(&tuned), parts(&default));
+        assert_eq!(parts(&single), parts(&default));
+
+        let csv = |output: PathBuf, write_buffer_bytes, fsync| {
+            generate_zone_text(
+                run(output, None)
+                    .with_file_format(ZoneFileFormat::Csv)
+                    .with_write_io(write_buffer_bytes, fsync),
+            )
+        };
+        let streamed = dir.path().join("csv");
+        csv(streamed.clone(), None, Fsync::Never).await.unwrap();
+        let tuned = dir.path().join("csv-tuned");
+        csv(tuned.clone(), Some(1), Fsync::PerRowgroup)
+            .await
+            .unwrap();
+        for part in 1..=2 {
+            let path = format!("zone/zone.{part}.csv");
+            assert_eq!(
+                std::fs::read(tuned.join(&path)).unwrap(),
+                std::fs::read(streamed.join(&path)).unwrap()
+            );
+        }
+
+        let err = generate_zone_parquet_multi(
+            run(dir.path().join("zero"), None).with_write_io(Some(0), Fsync::Never),
+        )
+        .await
+        .unwrap_err();
+        assert!(err.to_string().contains("--write-buffer-bytes=0"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn test_fsync_leaves_a_complete_spark_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let theme = source_file(dir.path(), &["g1", "g2", "g3"]);
+        let output = dir.path().join("out");
+        generate_zone_parquet_multi(
+            zone_args(&output, Some(3), None)
+                .with_themes(vec![theme])
+                .with_layout(ZoneLayout::Spark)
+                .with_also_merge(true)
+                .with_write_io(None, Fsync::PerPart),
+        )
+        .await
+        .unwrap();
+
+        let names = file_hashes(&output).into_keys().collect::<Vec<_>>();
+        assert!(
+            names.iter().all(|name| !name.ends_with(".inprogress")),
+            "{names:?}"
+        );
+        assert!(output.join("zone/_SUCCESS").exists());
+        assert!(verify::verify_dir(&output).unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_simplification_levels_keep_rows_and_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("division_area.parquet");
+        let circle: Vec<(f64, f64)> = (0..=40)
+            .map(|i| {
+                let angle = (i % 40) as f64 / 40.0 * std::f64::consts::TAU;
+                (angle.cos(), angle.sin())
+            })
+            .collect();
+        let rows: Vec<_> = ["g1", "g2", "g3", "g4"]
+            .iter()
+            .map(|id| SourceRow {
+                geometry: fixtures::wkb_polygon(&circle),
+                ..SourceRow::new(id, "county")
+            })
+            .collect();
+        write_parquet(&source, &source_batch(&rows, true));
+        let theme = ThemeInput {
+            theme: Theme::DivisionArea,
+            location: Some(source.to_string_lossy().into_owned()),
+        };
+        let output = dir.path().join("out");
+        let mut args = zone_args(&output, Some(2), None)
+            .with_themes(vec![theme])
+            .with_layout(ZoneLayout::Spark)
+            .with_simplification_levels(vec![0.0, 0.5, 0.05, 0.05]);
+        args.transform.bbox_covering = true;
+        assert_eq!(args.simplified_levels(), vec![0.05, 0.5]);
+        generate_zone_parquet_multi(args).await.unwrap();
+
+        let vertices = |dir: &Path| {
+            let mut vertices = 0;
+            for file in verify::discover_files(dir).unwrap().values() {
+                let builder = ParquetRecordBatchReaderBuilder::try_new(
+                    std::fs::File::open(&file.path).unwrap(),
+                )
+                .unwrap();
+                let geo = builder
+                    .metadata()
+                    .file_metadata()
+                    .key_value_metadata()
+                    .unwrap();
+                assert!(geo.iter().any(|kv| kv.key == bbox::GEO_METADATA_KEY));
+                for batch in builder.build().unwrap() {
+                    let batch = batch.unwrap();
+                    let column = cast(
+                        batch.column_by_name("z_boundary").unwrap(),
+                        &DataType::Binary,
+                    )
+                    .unwrap();
+                    let column = column.as_any().downcast_ref::<BinaryArray>().unwrap();
+                    for wkb in column.iter().flatten() {
+                        vertices += wkb::count_points(wkb).unwrap();
+                    }
+                }
+            }
+            vertices
+        };
+        let keys = values_by_gersid(&output, "z_zonekey");
+        let mut previous = vertices(&output);
+        for name in ["zone_simplified_0_05", "zone_simplified_0_5"] {
+            let level = output.join(name);
+            assert!(verify::verify_dir(&level).unwrap().is_empty(), "{name}");
+            assert!(level.join("zone/_SUCCESS").exists(), "{name}");
+            assert_eq!(values_by_gersid(&level, "z_zonekey"), keys, "{name}");
+            let level_vertices = vertices(&level);
+            assert!(
+                level_vertices < previous,
+                "{name}: {level_vertices} of {previous}"
+            );
+            previous = level_vertices;
+        }
+        assert!(!output.join("zone_simplified_0").exists());
+    }
+
+    #[tokio::test]
+    async fn test_spill_collect_matches_in_memory_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let theme = source_file(dir.path(), &["g1", "g2", "g3", "g4", "g5", "g6", "g7"]);
+        let generate = |output: PathBuf, spill_collect: bool| {
+            generate_zone_parquet_multi(
+                zone_args(&output, Some(3), None)
+                    .with_themes(vec![theme.clone()])
+                    .with_also_merge(true)
+                    .with_spill_collect(spill_collect),
+            )
+        };
+        let in_memory = dir.path().join("in_memory");
+        generate(in_memory.clone(), false).await.unwrap();
+        let spilled = dir.path().join("spilled");
+        generate(spilled.clone(), true).await.unwrap();
+
+        let expected = ZoneManifest::read(&in_memory).unwrap().unwrap();
+        let actual = ZoneManifest::read(&spilled).unwrap().unwrap();
+        assert_eq!(actual.files, expected.files);
+        assert_eq!(actual.merged, expected.merged);
+        for file in expected
+            .files
+            .iter()
+            .map(|f| &f.path)
+            .chain(expected.merged.as_ref().map(|m| &m.path))
+        {
+            assert_eq!(
+                std::fs::read(spilled.join(file)).unwrap(),
+                std::fs::read(in_memory.join(file)).unwrap(),
+                "{file}"
+            );
+        }
+        // The spill file is gone
+        let names: Vec<String> = std::fs::read_dir(&spilled)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        assert!(
+            !names.iter().any(|name| name.starts_with(".zone-spill")),
+            "{names:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_also_merge_writes_parts_and_merged_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let theme = source_file(dir.path(), &["g1", "g2", "g3", "g4", "g5", "g6", "g7"]);
+        let output = dir.path().join("out");
+        generate_zone_parquet_multi(
+            zone_args(&output, Some(3), None)
+                .with_themes(vec![theme])
+                .with_also_merge(true),
+        )
+        .await
+        .unwrap();
+
+        let zonekeys = |path: &Path| -> Vec<String> {
+            let reader = std::fs::File::open(path).unwrap();
+            ParquetRecordBatchReaderBuilder::try_new(reader)
+                .unwrap()
+                .build()
+                .unwrap()
+                .flat_map(|batch| {
+                    let batch = batch.unwrap();
+                    let keys =
+                        cast(batch.column_by_name("z_zonekey").unwrap(), &DataType::Utf8).unwrap();
+                    let keys = keys.as_any().downcast_ref::<StringArray>().unwrap();
+                    keys.iter()
+                        .map(|k| k.unwrap().to_string())
+                        .collect::<Vec<_>>()
+                })
+                .collect()
+        };
+        // The merged copy is left out of the parts
+        let parts = verify::discover_files(&output).unwrap();
+        assert_eq!(parts.len(), 3);
+        let part_keys: Vec<String> = parts.values().flat_map(|f| zonekeys(&f.path)).collect();
+        assert_eq!(part_keys.len(), 7);
+        assert_eq!(zonekeys(&output.join("zone.parquet")), part_keys);
+
+        let manifest = ZoneManifest::read(&output).unwrap().unwrap();
+        assert_eq!(manifest.files.len(), 3);
+        assert_eq!(manifest.merged.as_ref().unwrap().rows, 7);
+        assert!(verify::verify_dir(&output).unwrap().is_empty());
+
+        let error = zone_args(&output, Some(3), Some(1))
+            .with_also_merge(true)
+            .validate()
+            .unwrap_err();
+        assert!(error.to_string().contains("--also-merge"));
+    }
+
+    #[tokio::test]
+    async fn test_row_groups_end_at_part_boundaries() {
+        let dir = tempfile::tempdir().unwrap();
+        let theme = source_file(dir.path(), &["g1", "g2", "g3", "g4", "g5", "g6", "g7"]);
+        let output = dir.path().join("out");
+        generate_zone_parquet_multi(
+            zone_args(&output, Some(3), None)
+                .with_themes(vec![theme])
+                .with_also_merge(true),
+        )
+        .await
+        .unwrap();
+
+        // The z_zonekey range of every row group of a file
+        let key_ranges = |path: &Path| -> Vec<(i64, i64)> {
+            let file = std::fs::File::open(path).unwrap();
+            let reader = ParquetRecordBatchReaderBuilder::try_new(file).unwrap();
+            let metadata = reader.metadata();
+            let schema = metadata.file_metadata().schema_descr();
+            let column = (0..schema.num_columns())
+                .find(|&i| schema.column(i).name() == "z_zonekey")
+                .unwrap();
+            metadata
+                .row_groups()
+                .iter()
+                .map(|row_group| match row_group.column(column).statistics() {
+                    Some(parquet::file::statistics::Statistics::Int64(s)) => {
+                        (*s.min_opt().unwrap(), *s.max_opt().unwrap())
+                    }
+                    other => panic!("unexpected statistics {other:?}"),
+                })
+                .collect()
+        };
+        let parts = vec![(1, 3), (4, 5), (6, 7)];
+        for (part, range) in parts.iter().enumerate() {
+            let path = output.join(format!("zone/zone.{}.parquet", part + 1));
+            assert_eq!(key_ranges(&path), vec![*range]);
+        }
+        // A row group of the merged copy holds the rows of a single part
+        assert_eq!(key_ranges(&output.join("zone.parquet")), parts);
+        assert!(verify::verify_dir(&output).unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_balance_vertices_moves_split_points_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("division_area.parquet");
+        let mut rows: Vec<_> = ["g1", "g2", "g3", "g4", "g5", "g6"]
+            .iter()
+            .map(|id| SourceRow::new(id, "county"))
+            .collect();
+        // A 201 vertex ring outweighs the five other squares together
+        let mut ring: Vec<(f64, f64)> = (0..200)
+            .map(|i| {
+                let angle = i as f64 * std::f64::consts::TAU / 200.0;
+                (angle.cos(), angle.sin())
+            })
+            .collect();
+        ring.push(ring[0]);
+        rows[0].geometry = fixtures::wkb_polygon(&ring);
+        write_parquet(&source, &source_batch(&rows, true));
+        let theme = ThemeInput {
+            theme: Theme::DivisionArea,
+            location: Some(source.to_string_lossy().into_owned()),
+        };
+        let args = |output_dir: &Path, part: Option<i32>, balance: Balance| {
+            zone_args(output_dir, Some(2), part)
+                .with_themes(vec![theme.clone()])
+                .with_balance(balance, None)
+        };
+
+        let by_rows = dir.path().join("rows");
+        generate_zone_parquet_multi(args(&by_rows, None, Balance::Rows))
+            .await
+            .unwrap();
+        let by_vertices = dir.path().join("vertices");
+        generate_zone_parquet_multi(args(&by_vertices, None, Balance::Vertices))
+            .await
+            .unwrap();
+
+        let manifest = ZoneManifest::read(&by_vertices).unwrap().unwrap();
+        let limits: Vec<i64> = manifest
+            .boundaries
+            .unwrap()
+            .iter()
+            .map(|b| b.limit)
+            .collect();
+        assert_eq!(limits, vec![1, 5]);
+        let rows: Vec<u64> = manifest.files.iter().map(|f| f.rows).collect();
+        assert_eq!(rows, vec![1, 5]);
+        assert_eq!(
+            values_by_gersid(&by_vertices, "z_zonekey"),
+            values_by_gersid(&by_rows, "z_zonekey")
+        );
+
+        // Single-part workers reproduce the parts with or without the plan
+        let plan = ZoneManifest::path(&by_vertices);
+        for with_plan in [true, false] {
+            let single = dir.path().join(format!("single-{with_plan}"));
+            for part in 1..=2 {
+                let boundaries = with_plan.then(|| {
+                    ZoneManifest::read_plan_boundaries(&plan, 1.0)
+                        .unwrap()
+                        .unwrap()
+                });
+                let args = args(&single, Some(part), Balance::Vertices)
+                    .with_balance(Balance::Vertices, boundaries);
+                generate_zone_parquet_single(args).await.unwrap();
+            }
+            let single_manifest = ZoneManifest::read(&single).unwrap().unwrap();
+            assert_eq!(
+                single_manifest.files, manifest.files,
+                "with_plan={with_plan}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lat_bands_split_equator_and_pole() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("division_area.parquet");
+        let square = |lon: f64, lat: f64| {
+            fixtures::wkb_polygon(&[
+                (lon, lat),
+                (lon + 1.0, lat),
+                (lon + 1.0, lat + 1.0),
+                (lon, lat + 1.0),
+                (lon, lat),
+            ])
+        };
+        let mut rows = vec![
+            SourceRow::new("g1", "county"),
+            SourceRow::new("g2", "county"),
+        ];
+        rows[0].geometry = square(10.0, 0.0);
+        rows[1].geometry = square(10.0, 85.0);
+        write_parquet(&source, &source_batch(&rows, true));
+        let theme = ThemeInput {
+            theme: Theme::DivisionArea,
+            location: Some(source.to_string_lossy().into_owned()),
+        };
+        let args = |output_dir: &Path, part: Option<i32>| {
+            zone_args(output_dir, Some(4), part)
+                .with_themes(vec![theme.clone()])
+                .with_partition_scheme(PartitionScheme::LatBands)
+        };
+
+        let multi = dir.path().join("multi");
+        generate_zone_parquet_multi(args(&multi, None))
+            .await
+            .unwrap();
+
+        let part_of = |gersid: &str| {
+            let files = verify::discover_files(&multi).unwrap();
+            let (path, _) = files
+                .iter()
+                .find(|(_, file)| {
+                    let reader = std::fs::File::open(&file.path).unwrap();
+                    ParquetRecordBatchReaderBuilder::try_new(reader)
+                        .unwrap()
+                        .build()
+                        .unwrap()
+                        .any(|batch| {
+                            let batch = batch.unwrap();
+                            let ids = batch.column_by_name("z_gersid").unwrap();
+                            let ids = cast(ids, &DataType::Utf8).unwrap();
+                            let ids = ids.as_any().downcast_ref::<StringArray>().unwrap();
+                            ids.iter().any(|id| id == Some(gersid))
+                        })
+                })
+                .unwrap();
+            path.clone()
+        };
+        assert_eq!(part_of("g1"), "zone/zone.3.parquet");
+        assert_eq!(part_of("g2"), "zone/zone.4.parquet");
+
+        let manifest = ZoneManifest::read(&multi).unwrap().unwrap();
+        let bands: Vec<(f64, f64)> = manifest
+            .extents
+            .unwrap()
+            .iter()
+            .map(|e| (e.min_lat, e.max_lat))
+            .collect();
+        assert_eq!(
+            bands,
+            vec![(-90.0, -45.0), (-45.0, 0.0), (0.0, 45.0), (45.0, 90.0)]
+        );
+        let rows: Vec<u64> = manifest.files.iter().map(|f| f.rows).collect();
+        assert_eq!(rows, vec![0, 0, 1, 1]);
+
+        // Single-part workers assign the same zones
+        let single = dir.path().join("single");
+        for part in 1..=4 {
+            generate_zone_parquet_single(args(&single, Some(part)))
+                .await
+                .unwrap();
+        }
+        let single_manifest = ZoneManifest::read(&single).unwrap().unwrap();
+        assert_eq!(single_manifest.files, manifest.files);
+    }
+
+    /// Multi-part run over nine rows whose second part can't be written
+    async fn generate_with_failing_part(args: ZoneDfArgs) -> Result<()> {
+        let failing = ZoneDfArgs {
+            part: Some(2),
+            ..args.clone()
+        };
+        // A directory in place of the temporary file makes creating it fail
+        std::fs::create_dir_all(failing.output_filename().with_extension("inprogress")).unwrap();
+        generate_zone_parquet_multi(args).await
+    }
+
+    #[tokio::test]
+    async fn test_cleanup_on_failure_removes_written_parts() {
+        let dir = tempfile::tempdir().unwrap();
+        let theme = source_file(
+            dir.path(),
+            &["g1", "g2", "g3", "g4", "g5", "g6", "g7", "g8", "g9"],
+        );
+
+        let kept = dir.path().join("kept");
+        let args = zone_args(&kept, Some(3), None)
+            .with_themes(vec![theme.clone()])
+            .with_layout(ZoneLayout::Spark);
+        let first_part = ZoneDfArgs {
+            part: Some(1),
+            ..args.clone()
+        }
+        .output_filename();
+        assert!(generate_with_failing_part(args).await.is_err());
+        assert!(first_part.exists());
+        assert!(!kept.join("zone/_SUCCESS").exists());
+        assert_eq!(ZoneManifest::read(&kept).unwrap().unwrap().files.len(), 1);
+
+        let cleaned = dir.path().join("cleaned");
+        let args = zone_args(&cleaned, Some(3), None)
+            .with_themes(vec![theme])
+            .with_cleanup_on_failure(true);
+        assert!(generate_with_failing_part(args).await.is_err());
+        let remaining: Vec<_> = std::fs::read_dir(cleaned.join("zone"))
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        // Only the directory planted in place of the temporary file is left
+        assert_eq!(remaining, vec!["zone.2.inprogress"]);
+        assert!(ZoneManifest::read(&cleaned).unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_zstd_train_dict_fails_without_writing() {
+        let dir = tempfile::tempdir().unwrap();
+        let theme = source_file(dir.path(), &["g1", "g2", "g3"]);
+        let output = dir.path().join("out");
+        let args = ZoneDfArgs {
+            parquet: ParquetWriteOptions::new(Compression::ZSTD(Default::default()), 1024 * 1024),
+            ..zone_args(&output, Some(1), None)
+        }
+        .with_themes(vec![theme.clone()])
+        .with_zstd_train_dict(true);
+        let err = generate_zone_parquet_multi(args).await.unwrap_err();
+        assert!(
+            err.to_string().contains("--parquet-zstd-train-dict"),
+            "{err}"
+        );
+        assert!(!output.exists());
+
+        let args = zone_args(&output, Some(1), None)
+            .with_themes(vec![theme])
+            .with_zstd_train_dict(true);
+        // Refused by the argument checks, before the source is scanned
+        let err = args.validate().unwrap_err();
+        assert!(err.to_string().contains("requires ZSTD"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn test_register_generated_and_query() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("division_area.parquet");
+        let mut rows = vec![
+            SourceRow::new("g1", "county"),
+            SourceRow::new("g2", "county"),
+            SourceRow::new("g3", "microhood"),
+        ];
+        rows[1].country = "CA";
+        write_parquet(&source, &source_batch(&rows, true));
+
+        let args = ZoneDfArgs::new(
+            1.0,
+            dir.path().join("unused"),
+            None,
+            None,
+            None,
+            1024 * 1024,
+            Compression::SNAPPY,
+        )
+        .with_themes(vec![ThemeInput {
+            theme: Theme::DivisionArea,
+            location: Some(source.to_string_lossy().into_owned()),
+        }]);
+
+        let ctx = SessionContext::new();
+        let (rows, schema) = register_generated(&ctx, &args, "zone").await.unwrap();
+        assert_eq!(rows, 3);
+        assert!(schema.field_with_name("z_boundary").is_ok());
+
+        let batches = ctx
+            .sql("SELECT z_gersid FROM zone WHERE z_country = 'US' ORDER BY z_gersid")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        let ids = cast(batches[0].column(0), &DataType::Utf8).unwrap();
+        let ids = ids.as_any().downcast_ref::<StringArray>().unwrap();
+        assert_eq!(ids.len(), 2);
+        assert_eq!((ids.value(0), ids.value(1)), ("g1", "g3"));
+
+        // Nothing was written
+        assert!(!dir.path().join("unused").exists());
+    }
+}
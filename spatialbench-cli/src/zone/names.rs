@@ -0,0 +1,158 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Name columns taken from the Overture `names.common` map
+//!
+//! The map itself is written as a JSON object string rather than a Parquet
+//! map, which keeps every zone column flat for readers without nested type
+//! support.
+
+use anyhow::{anyhow, Result};
+use arrow::compute::cast;
+use arrow_array::{Array, MapArray, RecordBatch, StringArray};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// The source `names.common` map of language to name, as a JSON object
+pub const NAME_COMMON_COLUMN: &str = "z_name_common";
+
+/// Column holding the `names.common` entry for `language`, e.g. `z_name_fr`
+/// for `fr` and `z_name_zh_hant` for `zh-Hant`
+pub fn name_language_column(language: &str) -> Result<String> {
+    let valid = !language.is_empty()
+        && language
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        return Err(anyhow!(
+            "Invalid name language {language:?}, expected a tag such as en or zh-Hant"
+        ));
+    }
+    Ok(format!(
+        "z_name_{}",
+        language.to_ascii_lowercase().replace('-', "_")
+    ))
+}
+
+/// Replaces the type of [`NAME_COMMON_COLUMN`] in `schema` with a string
+pub fn output_schema(schema: SchemaRef) -> SchemaRef {
+    let fields: Vec<Field> = schema
+        .fields()
+        .iter()
+        .map(|f| match f.name().as_str() {
+            NAME_COMMON_COLUMN => Field::new(NAME_COMMON_COLUMN, DataType::Utf8, false),
+            _ => f.as_ref().clone(),
+        })
+        .collect();
+    Arc::new(Schema::new_with_metadata(fields, schema.metadata().clone()))
+}
+
+/// Rewrites the [`NAME_COMMON_COLUMN`] map as JSON objects with sorted keys.
+///
+/// Zones without common names get an empty string, like the other name
+/// columns.
+pub fn common_names_to_json(batches: Vec<RecordBatch>) -> Result<Vec<RecordBatch>> {
+    batches
+        .into_iter()
+        .map(|batch| {
+            let Ok(index) = batch.schema().index_of(NAME_COMMON_COLUMN) else {
+                return Ok(batch);
+            };
+            let column = batch.column(index);
+            let map = column.as_any().downcast_ref::<MapArray>().ok_or_else(|| {
+                anyhow!(
+                    "Expected names.common to be a map, found {}",
+                    column.data_type()
+                )
+            })?;
+
+            let keys = cast(map.keys(), &DataType::Utf8)?;
+            let keys = keys.as_any().downcast_ref::<StringArray>().unwrap();
+            let values = cast(map.values(), &DataType::Utf8)?;
+            let values = values.as_any().downcast_ref::<StringArray>().unwrap();
+            let offsets = map.value_offsets();
+
+            let json: StringArray = (0..map.len())
+                .map(|i| {
+                    let entries: BTreeMap<&str, &str> = (offsets[i] as usize
+                        ..offsets[i + 1] as usize)
+                        .filter(|&j| map.is_valid(i) && values.is_valid(j))
+                        .map(|j| (keys.value(j), values.value(j)))
+                        .collect();
+                    if entries.is_empty() {
+                        Ok(Some(String::new()))
+                    } else {
+                        Ok(Some(serde_json::to_string(&entries)?))
+                    }
+                })
+                .collect::<Result<_>>()?;
+
+            let mut columns = batch.columns().to_vec();
+            columns[index] = Arc::new(json);
+            Ok(RecordBatch::try_new(
+                output_schema(batch.schema()),
+                columns,
+            )?)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_array::builder::{MapBuilder, StringBuilder};
+
+    #[test]
+    fn test_name_language_column() {
+        assert_eq!(name_language_column("fr").unwrap(), "z_name_fr");
+        assert_eq!(name_language_column("zh-Hant").unwrap(), "z_name_zh_hant");
+        assert!(name_language_column("").is_err());
+        assert!(name_language_column("fr'] AS x, id AS z_gersid --").is_err());
+    }
+
+    #[test]
+    fn test_common_names_to_json() {
+        let mut builder = MapBuilder::new(None, StringBuilder::new(), StringBuilder::new());
+        builder.keys().append_value("fr");
+        builder.values().append_value("Genève");
+        builder.keys().append_value("de");
+        builder.values().append_value("Genf");
+        builder.append(true).unwrap();
+        builder.append(false).unwrap();
+        builder.append(true).unwrap();
+        let map = builder.finish();
+
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            NAME_COMMON_COLUMN,
+            map.data_type().clone(),
+            true,
+        )]));
+        let batch = RecordBatch::try_new(schema, vec![Arc::new(map)]).unwrap();
+        let batches = common_names_to_json(vec![batch]).unwrap();
+
+        let json = batches[0]
+            .column(0)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(
+            json.iter().collect::<Vec<_>>(),
+            vec![Some(r#"{"de":"Genf","fr":"Genève"}"#), Some(""), Some("")]
+        );
+    }
+}
@@ -0,0 +1,1208 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! ORC encoding of the zone files
+//!
+//! Writes the column types the zone table can have: booleans, integers,
+//! floats, strings, binary and structs of those, named as in the Arrow
+//! schema. Every column uses the `DIRECT` encodings of ORC 0.11, with run
+//! length encoding v1 for integers, lengths and presence bits, and files
+//! have no row index. Streams, stripe footers and the file footer are
+//! compressed in blocks as the ORC specification lays out, and statistics
+//! are kept per stripe and per file so readers can skip stripes.
+
+use anyhow::{anyhow, Result};
+use arrow::compute::{cast, concat_batches, filter};
+use arrow_array::cast::AsArray;
+use arrow_array::types::{Float32Type, Float64Type, Int64Type};
+use arrow_array::{Array, ArrayRef, BooleanArray, RecordBatch, StructArray};
+use arrow_schema::{DataType, SchemaRef};
+use clap::ValueEnum;
+use std::io::Write;
+use std::sync::Arc;
+
+use super::protobuf::{put_varint, zigzag, Message};
+
+/// Default target stripe size, that of the ORC Java writer
+pub const DEFAULT_ORC_STRIPE_BYTES: i64 = 64 * 1024 * 1024;
+
+/// Uncompressed bytes per compression block, the ORC default
+const COMPRESSION_BLOCK_BYTES: usize = 256 * 1024;
+
+const ZSTD_LEVEL: i32 = 3;
+
+const MAGIC: &[u8] = b"ORC";
+
+/// Writer version of ORC-135, the latest fix the written types depend on
+const WRITER_VERSION: u64 = 6;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum OrcCompression {
+    None,
+    #[default]
+    Zlib,
+    Zstd,
+}
+
+impl OrcCompression {
+    /// `CompressionKind` of the ORC PostScript
+    fn kind(self) -> u64 {
+        match self {
+            OrcCompression::None => 0,
+            OrcCompression::Zlib => 1,
+            OrcCompression::Zstd => 5,
+        }
+    }
+
+    /// Codec name Spark puts in ORC file names, none when uncompressed
+    pub fn spark_name(self) -> Option<&'static str> {
+        match self {
+            OrcCompression::None => None,
+            OrcCompression::Zlib => Some("zlib"),
+            OrcCompression::Zstd => Some("zstd"),
+        }
+    }
+
+    /// Splits `data` into blocks, each compressed unless that doesn't make
+    /// it smaller, behind a 3 byte header of its length and whether it is
+    /// stored as is
+    fn compress(self, data: &[u8]) -> Result<Vec<u8>> {
+        if self == OrcCompression::None {
+            return Ok(data.to_vec());
+        }
+        let mut compressed = Vec::with_capacity(data.len() / 2);
+        for block in data.chunks(COMPRESSION_BLOCK_BYTES) {
+            let encoded = match self {
+                OrcCompression::Zlib => {
+                    let mut encoder = flate2::write::DeflateEncoder::new(
+                        Vec::new(),
+                        flate2::Compression::default(),
+                    );
+                    encoder.write_all(block)?;
+                    encoder.finish()?
+                }
+                _ => zstd::bulk::compress(block, ZSTD_LEVEL)?,
+            };
+            let (body, original) = match encoded.len() < block.len() {
+                true => (encoded.as_slice(), false),
+                false => (block, true),
+            };
+            let header = (body.len() << 1) | original as usize;
+            compressed.extend_from_slice(&header.to_le_bytes()[..3]);
+            compressed.extend_from_slice(body);
+        }
+        Ok(compressed)
+    }
+}
+
+/// The options of the zone ORC writer
+#[derive(Clone, Debug, PartialEq)]
+pub struct OrcWriteOptions {
+    pub compression: OrcCompression,
+    /// Target stripe size; the rows per stripe are derived from it
+    pub stripe_bytes: i64,
+}
+
+impl OrcWriteOptions {
+    pub fn new(compression: OrcCompression, stripe_bytes: i64) -> Self {
+        Self {
+            compression,
+            stripe_bytes,
+        }
+    }
+}
+
+impl Default for OrcWriteOptions {
+    fn default() -> Self {
+        Self::new(OrcCompression::default(), DEFAULT_ORC_STRIPE_BYTES)
+    }
+}
+
+/// `Type.Kind` of the ORC footer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum TypeKind {
+    Boolean = 0,
+    Short = 2,
+    Int = 3,
+    Long = 4,
+    Float = 5,
+    Double = 6,
+    String = 7,
+    Binary = 8,
+    Struct = 12,
+}
+
+/// `Stream.Kind` of a stripe footer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum StreamKind {
+    Present = 0,
+    Data = 1,
+    Length = 2,
+}
+
+/// A column of the ORC type tree, numbered in pre-order with the root
+/// struct of the rows as column 0
+struct Column {
+    kind: TypeKind,
+    children: Vec<usize>,
+    names: Vec<String>,
+}
+
+/// Writes record batches to an ORC file, a stripe every `rows_per_stripe`
+/// rows
+pub struct OrcWriter<W: Write> {
+    sink: W,
+    schema: SchemaRef,
+    columns: Vec<Column>,
+    compression: OrcCompression,
+    rows_per_stripe: usize,
+    buffered: Vec<RecordBatch>,
+    buffered_rows: usize,
+    position: u64,
+    rows: u64,
+    /// `StripeInformation` of every stripe written
+    stripes: Vec<Message>,
+    stripe_statistics: Vec<Vec<ColumnStatistics>>,
+    metadata: Vec<(String, String)>,
+}
+
+impl<W: Write> OrcWriter<W> {
+    pub fn try_new(
+        mut sink: W,
+        schema: SchemaRef,
+        compression: OrcCompression,
+        rows_per_stripe: usize,
+    ) -> Result<Self> {
+        let mut columns = Vec::new();
+        add_column(&mut columns, "", &DataType::Struct(schema.fields().clone()))?;
+        sink.write_all(MAGIC)?;
+        Ok(Self {
+            sink,
+            schema,
+            columns,
+            compression,
+            rows_per_stripe: rows_per_stripe.max(1),
+            buffered: Vec::new(),
+            buffered_rows: 0,
+            position: MAGIC.len() as u64,
+            rows: 0,
+            stripes: Vec::new(),
+            stripe_statistics: Vec::new(),
+            metadata: Vec::new(),
+        })
+    }
+
+    pub fn write(&mut self, batch: &RecordBatch) -> Result<()> {
+        let mut offset = 0;
+        while offset < batch.num_rows() {
+            let length = (self.rows_per_stripe - self.buffered_rows).min(batch.num_rows() - offset);
+            self.buffered.push(batch.slice(offset, length));
+            self.buffered_rows += length;
+            offset += length;
+            if self.buffered_rows == self.rows_per_stripe {
+                self.write_stripe()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the buffered rows as a stripe, so the next rows start a new
+    /// one
+    pub fn flush(&mut self) -> Result<()> {
+        self.write_stripe()
+    }
+
+    /// Number of stripes written
+    pub fn stripes_written(&self) -> usize {
+        self.stripes.len()
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.sink
+    }
+
+    /// Adds a user metadata item to the file footer
+    pub fn append_key_value_metadata(&mut self, key: String, value: String) {
+        self.metadata.push((key, value));
+    }
+
+    /// Writes the buffered rows and the file tail
+    pub fn close(mut self) -> Result<()> {
+        self.write_stripe()?;
+        let content_length = self.position;
+
+        let mut metadata = Message::default();
+        for statistics in &self.stripe_statistics {
+            metadata = metadata.message(1, self.statistics_message(statistics));
+        }
+        let metadata = self.compression.compress(&metadata.0)?;
+        self.write_bytes(&metadata)?;
+
+        let mut file_statistics = vec![ColumnStatistics::default(); self.columns.len()];
+        for statistics in &self.stripe_statistics {
+            for (file, stripe) in file_statistics.iter_mut().zip(statistics) {
+                file.merge(stripe);
+            }
+        }
+        let mut footer = Message::default()
+            .uint(1, MAGIC.len() as u64)
+            .uint(2, content_length);
+        for stripe in std::mem::take(&mut self.stripes) {
+            footer = footer.message(3, stripe);
+        }
+        for column in &self.columns {
+            let mut message = Message::default()
+                .uint(1, column.kind as u64)
+                .packed(2, column.children.iter().map(|&c| c as u64));
+            for name in &column.names {
+                message = message.bytes(3, name.as_bytes());
+            }
+            footer = footer.message(4, message);
+        }
+        for (key, value) in &self.metadata {
+            footer = footer.message(
+                5,
+                Message::default()
+                    .bytes(1, key.as_bytes())
+                    .bytes(2, value.as_bytes()),
+            );
+        }
+        footer = footer.uint(6, self.rows);
+        for statistics in &file_statistics {
+            footer = footer.message(7, statistics.to_message());
+        }
+        // No row index
+        footer = footer.uint(8, 0);
+        let footer = self.compression.compress(&footer.0)?;
+        self.write_bytes(&footer)?;
+
+        let postscript = Message::default()
+            .uint(1, footer.len() as u64)
+            .uint(2, self.compression.kind())
+            .uint(3, COMPRESSION_BLOCK_BYTES as u64)
+            .packed(4, [0, 12])
+            .uint(5, metadata.len() as u64)
+            .uint(6, WRITER_VERSION)
+            .bytes(8000, MAGIC);
+        self.write_bytes(&postscript.0)?;
+        self.write_bytes(&[postscript.0.len() as u8])?;
+        self.sink.flush()?;
+        Ok(())
+    }
+
+    /// The `ColumnStatistics` of every column, as repeated field 1 of a
+    /// message
+    fn statistics_message(&self, statistics: &[ColumnStatistics]) -> Message {
+        statistics
+            .iter()
+            .fold(Message::default(), |message, column| {
+                message.message(1, column.to_message())
+            })
+    }
+
+    fn write_stripe(&mut self) -> Result<()> {
+        if self.buffered_rows == 0 {
+            return Ok(());
+        }
+        let batch = concat_batches(&self.schema, &self.buffered)?;
+        self.buffered.clear();
+        self.buffered_rows = 0;
+
+        let mut streams = Vec::new();
+        let mut statistics = vec![ColumnStatistics::default(); self.columns.len()];
+        let root: ArrayRef = Arc::new(StructArray::from(batch.clone()));
+        self.encode_column(0, &root, &mut streams, &mut statistics)?;
+
+        let offset = self.position;
+        let mut footer = Message::default();
+        let mut data_length = 0;
+        for (kind, column, data) in streams {
+            let data = self.compression.compress(&data)?;
+            footer = footer.message(
+                1,
+                Message::default()
+                    .uint(1, kind as u64)
+                    .uint(2, column as u64)
+                    .uint(3, data.len() as u64),
+            );
+            data_length += data.len() as u64;
+            self.write_bytes(&data)?;
+        }
+        for _ in &self.columns {
+            // DIRECT
+            footer = footer.message(2, Message::default().uint(1, 0));
+        }
+        let footer = self.compression.compress(&footer.0)?;
+        self.write_bytes(&footer)?;
+
+        self.stripes.push(
+            Message::default()
+                .uint(1, offset)
+                .uint(2, 0)
+                .uint(3, data_length)
+                .uint(4, footer.len() as u64)
+                .uint(5, batch.num_rows() as u64),
+        );
+        self.stripe_statistics.push(statistics);
+        self.rows += batch.num_rows() as u64;
+        Ok(())
+    }
+
+    /// Appends the streams of column `id`, whose values are `array`, and of
+    /// its children
+    fn encode_column(
+        &self,
+        id: usize,
+        array: &ArrayRef,
+        streams: &mut Vec<(StreamKind, usize, Vec<u8>)>,
+        statistics: &mut [ColumnStatistics],
+    ) -> Result<()> {
+        let column = &self.columns[id];
+        let nulls = array.nulls().filter(|nulls| nulls.null_count() > 0);
+        statistics[id].values += (array.len() - array.null_count()) as u64;
+        statistics[id].has_null |= nulls.is_some();
+        if let Some(nulls) = nulls {
+            let mut present = Vec::new();
+            encode_booleans(nulls.iter(), &mut present);
+            streams.push((StreamKind::Present, id, present));
+        }
+
+        let mut data = Vec::new();
+        match column.kind {
+            TypeKind::Struct => {
+                // Children hold values only for the rows where the struct
+                // is present
+                for (child, &child_id) in array.as_struct().columns().iter().zip(&column.children) {
+                    let child = match nulls {
+                        Some(nulls) => {
+                            filter(child, &BooleanArray::new(nulls.inner().clone(), None))?
+                        }
+                        None => Arc::clone(child),
+                    };
+                    self.encode_column(child_id, &child, streams, statistics)?;
+                }
+                return Ok(());
+            }
+            TypeKind::Boolean => {
+                encode_booleans(array.as_boolean().iter().flatten(), &mut data);
+            }
+            TypeKind::Short | TypeKind::Int | TypeKind::Long => {
+                let values = cast(array, &DataType::Int64)?;
+                let values: Vec<i64> = values
+                    .as_primitive::<Int64Type>()
+                    .iter()
+                    .flatten()
+                    .collect();
+                for &value in &values {
+                    statistics[id].update_int(value);
+                }
+                encode_ints(&values, true, &mut data);
+            }
+            TypeKind::Float => {
+                for value in array.as_primitive::<Float32Type>().iter().flatten() {
+                    statistics[id].update_double(value as f64);
+                    data.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+            TypeKind::Double => {
+                for value in array.as_primitive::<Float64Type>().iter().flatten() {
+                    statistics[id].update_double(value);
+                    data.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+            TypeKind::String => {
+                let values = cast(array, &DataType::Utf8)?;
+                let mut lengths = Vec::new();
+                for value in values.as_string::<i32>().iter().flatten() {
+                    statistics[id].update_string(value);
+                    data.extend_from_slice(value.as_bytes());
+                    lengths.push(value.len() as i64);
+                }
+                streams.push((StreamKind::Data, id, data));
+                let mut length = Vec::new();
+                encode_ints(&lengths, false, &mut length);
+                streams.push((StreamKind::Length, id, length));
+                return Ok(());
+            }
+            TypeKind::Binary => {
+                let values = cast(array, &DataType::Binary)?;
+                let mut lengths = Vec::new();
+                for value in values.as_binary::<i32>().iter().flatten() {
+                    data.extend_from_slice(value);
+                    lengths.push(value.len() as i64);
+                }
+                streams.push((StreamKind::Data, id, data));
+                let mut length = Vec::new();
+                encode_ints(&lengths, false, &mut length);
+                streams.push((StreamKind::Length, id, length));
+                return Ok(());
+            }
+        }
+        streams.push((StreamKind::Data, id, data));
+        Ok(())
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<()> {
+        self.sink.write_all(bytes)?;
+        self.position += bytes.len() as u64;
+        Ok(())
+    }
+}
+
+/// Adds the column of `data_type` and its children to `columns`
+fn add_column(columns: &mut Vec<Column>, name: &str, data_type: &DataType) -> Result<usize> {
+    let kind = match data_type {
+        DataType::Boolean => TypeKind::Boolean,
+        DataType::Int16 => TypeKind::Short,
+        DataType::Int32 => TypeKind::Int,
+        DataType::Int64 => TypeKind::Long,
+        DataType::Float32 => TypeKind::Float,
+        DataType::Float64 => TypeKind::Double,
+        DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View => TypeKind::String,
+        DataType::Binary | DataType::LargeBinary | DataType::BinaryView => TypeKind::Binary,
+        DataType::Struct(_) => TypeKind::Struct,
+        other => {
+            return Err(anyhow!(
+                "Column {name} of type {other} can't be written as ORC"
+            ))
+        }
+    };
+    let id = columns.len();
+    columns.push(Column {
+        kind,
+        children: vec![],
+        names: vec![],
+    });
+    if let DataType::Struct(fields) = data_type {
+        for field in fields {
+            let child = add_column(columns, field.name(), field.data_type())?;
+            columns[id].children.push(child);
+            columns[id].names.push(field.name().clone());
+        }
+    }
+    Ok(id)
+}
+
+/// Statistics of the values of a column in a stripe or the file
+#[derive(Clone, Debug, Default)]
+struct ColumnStatistics {
+    values: u64,
+    has_null: bool,
+    range: Option<Range>,
+}
+
+/// Smallest and largest value of a column
+#[derive(Clone, Debug)]
+enum Range {
+    Int(i64, i64),
+    Double(f64, f64),
+    String(String, String),
+}
+
+impl ColumnStatistics {
+    fn update_int(&mut self, value: i64) {
+        self.range = Some(match self.range.take() {
+            Some(Range::Int(min, max)) => Range::Int(min.min(value), max.max(value)),
+            _ => Range::Int(value, value),
+        });
+    }
+
+    fn update_double(&mut self, value: f64) {
+        if value.is_nan() {
+            return;
+        }
+        self.range = Some(match self.range.take() {
+            Some(Range::Double(min, max)) => Range::Double(min.min(value), max.max(value)),
+            _ => Range::Double(value, value),
+        });
+    }
+
+    fn update_string(&mut self, value: &str) {
+        match &mut self.range {
+            Some(Range::String(min, max)) => {
+                if value < min.as_str() {
+                    *min = value.to_string();
+                } else if value > max.as_str() {
+                    *max = value.to_string();
+                }
+            }
+            _ => self.range = Some(Range::String(value.to_string(), value.to_string())),
+        }
+    }
+
+    fn merge(&mut self, other: &Self) {
+        self.values += other.values;
+        self.has_null |= other.has_null;
+        match &other.range {
+            Some(Range::Int(min, max)) => {
+                self.update_int(*min);
+                self.update_int(*max);
+            }
+            Some(Range::Double(min, max)) => {
+                self.update_double(*min);
+                self.update_double(*max);
+            }
+            Some(Range::String(min, max)) => {
+                self.update_string(min);
+                self.update_string(max);
+            }
+            None => {}
+        }
+    }
+
+    fn to_message(&self) -> Message {
+        let mut message = Message::default().uint(1, self.values);
+        message = match &self.range {
+            Some(Range::Int(min, max)) => {
+                message.message(2, Message::default().sint(1, *min).sint(2, *max))
+            }
+            Some(Range::Double(min, max)) => {
+                message.message(3, Message::default().double(1, *min).double(2, *max))
+            }
+            Some(Range::String(min, max)) => message.message(
+                4,
+                Message::default()
+                    .bytes(1, min.as_bytes())
+                    .bytes(2, max.as_bytes()),
+            ),
+            None => message,
+        };
+        message.uint(10, self.has_null as u64)
+    }
+}
+
+const MIN_RUN: usize = 3;
+const MAX_RUN: usize = 130;
+const MAX_LITERALS: usize = 128;
+
+/// Run length encoding v1 of integers: runs of 3 to 130 values with a
+/// constant delta that fits a byte, and up to 128 literals otherwise.
+/// Values are zigzag encoded when `signed`.
+fn encode_ints(values: &[i64], signed: bool, out: &mut Vec<u8>) {
+    let put = |out: &mut Vec<u8>, value: i64| match signed {
+        true => put_varint(out, zigzag(value)),
+        false => put_varint(out, value as u64),
+    };
+    let put_literals = |out: &mut Vec<u8>, literals: &[i64]| {
+        for chunk in literals.chunks(MAX_LITERALS) {
+            out.push((chunk.len() as u8).wrapping_neg());
+            for &value in chunk {
+                put(out, value);
+            }
+        }
+    };
+
+    let (mut start, mut i) = (0, 0);
+    while i < values.len() {
+        let (length, delta) = int_run(&values[i..]);
+        if length < MIN_RUN {
+            i += 1;
+            continue;
+        }
+        put_literals(out, &values[start..i]);
+        out.push((length - MIN_RUN) as u8);
+        out.push(delta as u8);
+        put(out, values[i]);
+        i += length;
+        start = i;
+    }
+    put_literals(out, &values[start..]);
+}
+
+/// Length and delta of the run `values` start with
+fn int_run(values: &[i64]) -> (usize, i8) {
+    let delta = match values {
+        [first, second, ..] => second
+            .checked_sub(*first)
+            .and_then(|d| i8::try_from(d).ok()),
+        _ => None,
+    };
+    let Some(delta) = delta else {
+        return (1, 0);
+    };
+    let length = 2 + values
+        .windows(2)
+        .skip(1)
+        .take(MAX_RUN - 2)
+        .take_while(|pair| pair[1].checked_sub(pair[0]) == Some(delta as i64))
+        .count();
+    (length, delta)
+}
+
+/// Byte run length encoding: runs of 3 to 130 equal bytes, and up to 128
+/// literal bytes otherwise
+fn encode_bytes(values: &[u8], out: &mut Vec<u8>) {
+    let put_literals = |out: &mut Vec<u8>, literals: &[u8]| {
+        for chunk in literals.chunks(MAX_LITERALS) {
+            out.push((chunk.len() as u8).wrapping_neg());
+            out.extend_from_slice(chunk);
+        }
+    };
+
+    let (mut start, mut i) = (0, 0);
+    while i < values.len() {
+        let length = values[i..]
+            .iter()
+            .take(MAX_RUN)
+            .take_while(|&&value| value == values[i])
+            .count();
+        if length < MIN_RUN {
+            i += 1;
+            continue;
+        }
+        put_literals(out, &values[start..i]);
+        out.push((length - MIN_RUN) as u8);
+        out.push(values[i]);
+        i += length;
+        start = i;
+    }
+    put_literals(out, &values[start..]);
+}
+
+/// Booleans packed eight to a byte, most significant bit first, then byte
+/// run length encoded
+fn encode_booleans(values: impl IntoIterator<Item = bool>, out: &mut Vec<u8>) {
+    let mut bytes = Vec::new();
+    for (i, value) in values.into_iter().enumerate() {
+        if i % 8 == 0 {
+            bytes.push(0);
+        }
+        if value {
+            *bytes.last_mut().unwrap() |= 0x80 >> (i % 8);
+        }
+    }
+    encode_bytes(&bytes, out);
+}
+
+/// Reads the ORC files [`OrcWriter`] writes back into record batches, for
+/// tests
+#[cfg(test)]
+pub(super) mod reader {
+    use super::super::protobuf::decode::{fields, repeated, uint, varint};
+    use super::*;
+    use arrow::buffer::NullBuffer;
+    use arrow::compute::take;
+    use arrow_array::{
+        BinaryArray, Float32Array, Float64Array, Int16Array, Int32Array, Int64Array, StringArray,
+        UInt32Array,
+    };
+    use arrow_schema::{Field, Fields, Schema};
+    use std::collections::HashMap;
+    use std::io::Read;
+
+    /// The contents of an ORC file
+    pub struct OrcFile {
+        /// One batch per stripe
+        pub batches: Vec<RecordBatch>,
+        pub metadata: Vec<(String, String)>,
+        pub compression: u64,
+        /// Number of rows in the footer
+        pub rows: u64,
+    }
+
+    fn decompress(compression: u64, data: &[u8]) -> Result<Vec<u8>> {
+        if compression == 0 {
+            return Ok(data.to_vec());
+        }
+        let mut out = Vec::new();
+        let mut rest = data;
+        while !rest.is_empty() {
+            let header = rest[0] as usize | (rest[1] as usize) << 8 | (rest[2] as usize) << 16;
+            let (block, next) = rest[3..].split_at(header >> 1);
+            rest = next;
+            match (header & 1 == 1, compression) {
+                (true, _) => out.extend_from_slice(block),
+                (false, 1) => {
+                    flate2::read::DeflateDecoder::new(block).read_to_end(&mut out)?;
+                }
+                (false, 5) => {
+                    out.extend(zstd::bulk::decompress(block, COMPRESSION_BLOCK_BYTES)?);
+                }
+                (false, other) => return Err(anyhow!("Unexpected compression {other}")),
+            }
+        }
+        Ok(out)
+    }
+
+    pub fn decode_ints(mut bytes: &[u8], signed: bool) -> Result<Vec<i64>> {
+        let value = |v: u64| match signed {
+            true => (v >> 1) as i64 ^ -((v & 1) as i64),
+            false => v as i64,
+        };
+        let mut values = Vec::new();
+        while let Some((&control, rest)) = bytes.split_first() {
+            bytes = rest;
+            if control < 0x80 {
+                let delta = bytes[0] as i8 as i64;
+                bytes = &bytes[1..];
+                let base = value(varint(&mut bytes)?);
+                values.extend((0..control as i64 + 3).map(|i| base + i * delta));
+            } else {
+                for _ in 0..control.wrapping_neg() {
+                    values.push(value(varint(&mut bytes)?));
+                }
+            }
+        }
+        Ok(values)
+    }
+
+    fn decode_bytes(mut bytes: &[u8]) -> Vec<u8> {
+        let mut values = Vec::new();
+        while let Some((&control, rest)) = bytes.split_first() {
+            if control < 0x80 {
+                values.extend(std::iter::repeat_n(rest[0], control as usize + 3));
+                bytes = &rest[1..];
+            } else {
+                let (literals, next) = rest.split_at(control.wrapping_neg() as usize);
+                values.extend_from_slice(literals);
+                bytes = next;
+            }
+        }
+        values
+    }
+
+    fn decode_booleans(bytes: &[u8], count: usize) -> Vec<bool> {
+        let bytes = decode_bytes(bytes);
+        (0..count)
+            .map(|i| bytes[i / 8] & (0x80 >> (i % 8)) != 0)
+            .collect()
+    }
+
+    struct Type {
+        kind: u64,
+        children: Vec<usize>,
+        names: Vec<String>,
+    }
+
+    pub fn read(bytes: &[u8]) -> Result<OrcFile> {
+        assert_eq!(&bytes[..3], MAGIC);
+        let postscript_length = *bytes.last().unwrap() as usize;
+        let postscript_start = bytes.len() - 1 - postscript_length;
+        let postscript = fields(&bytes[postscript_start..bytes.len() - 1])?;
+        assert_eq!(repeated(&postscript, 8000), vec![MAGIC]);
+        let compression = uint(&postscript, 2);
+        let footer_length = uint(&postscript, 1) as usize;
+        let footer = decompress(
+            compression,
+            &bytes[postscript_start - footer_length..postscript_start],
+        )?;
+        let footer = fields(&footer)?;
+
+        let types = repeated(&footer, 4)
+            .into_iter()
+            .map(|message| {
+                let message = fields(message)?;
+                let children = repeated(&message, 2)
+                    .into_iter()
+                    .flat_map(|mut packed| {
+                        std::iter::from_fn(move || {
+                            (!packed.is_empty()).then(|| varint(&mut packed).unwrap() as usize)
+                        })
+                    })
+                    .collect();
+                Ok(Type {
+                    kind: uint(&message, 1),
+                    children,
+                    names: repeated(&message, 3)
+                        .into_iter()
+                        .map(|name| String::from_utf8(name.to_vec()).unwrap())
+                        .collect(),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let fields_of = |id: usize| -> Fields {
+            types[id]
+                .children
+                .iter()
+                .zip(&types[id].names)
+                .map(|(&child, name)| Field::new(name, data_type(&types, child), true))
+                .collect()
+        };
+        let schema = Arc::new(Schema::new(fields_of(0)));
+
+        let mut batches = Vec::new();
+        for stripe in repeated(&footer, 3) {
+            let stripe = fields(stripe)?;
+            let offset = uint(&stripe, 1) as usize + uint(&stripe, 2) as usize;
+            let footer_start = offset + uint(&stripe, 3) as usize;
+            let stripe_footer = decompress(
+                compression,
+                &bytes[footer_start..footer_start + uint(&stripe, 4) as usize],
+            )?;
+            let mut streams = HashMap::new();
+            let mut position = offset;
+            for stream in repeated(&fields(&stripe_footer)?, 1) {
+                let stream = fields(stream)?;
+                let length = uint(&stream, 3) as usize;
+                let data = decompress(compression, &bytes[position..position + length])?;
+                streams.insert((uint(&stream, 1), uint(&stream, 2) as usize), data);
+                position += length;
+            }
+            let rows = uint(&stripe, 5) as usize;
+            let root = read_column(&types, &streams, 0, rows)?;
+            batches.push(RecordBatch::try_new(
+                Arc::clone(&schema),
+                root.as_struct().columns().to_vec(),
+            )?);
+        }
+
+        let metadata = repeated(&footer, 5)
+            .into_iter()
+            .map(|item| {
+                let item = fields(item).unwrap();
+                let text =
+                    |field: u32| String::from_utf8(repeated(&item, field)[0].to_vec()).unwrap();
+                (text(1), text(2))
+            })
+            .collect();
+        Ok(OrcFile {
+            batches,
+            metadata,
+            compression,
+            rows: uint(&footer, 6),
+        })
+    }
+
+    fn data_type(types: &[Type], id: usize) -> DataType {
+        match types[id].kind {
+            0 => DataType::Boolean,
+            2 => DataType::Int16,
+            3 => DataType::Int32,
+            4 => DataType::Int64,
+            5 => DataType::Float32,
+            6 => DataType::Float64,
+            7 => DataType::Utf8,
+            8 => DataType::Binary,
+            12 => DataType::Struct(
+                types[id]
+                    .children
+                    .iter()
+                    .zip(&types[id].names)
+                    .map(|(&child, name)| Field::new(name, data_type(types, child), true))
+                    .collect(),
+            ),
+            other => panic!("Unexpected type kind {other}"),
+        }
+    }
+
+    /// The column `id` of a stripe, with `count` entries
+    fn read_column(
+        types: &[Type],
+        streams: &HashMap<(u64, usize), Vec<u8>>,
+        id: usize,
+        count: usize,
+    ) -> Result<ArrayRef> {
+        let stream = |kind: StreamKind| streams.get(&(kind as u64, id));
+        let present = stream(StreamKind::Present).map(|bytes| decode_booleans(bytes, count));
+        let values = present
+            .as_ref()
+            .map_or(count, |present| present.iter().filter(|p| **p).count());
+        let data = || stream(StreamKind::Data).map_or(&[][..], Vec::as_slice);
+        let lengths = || decode_ints(stream(StreamKind::Length).unwrap(), false);
+
+        let dense: ArrayRef = match types[id].kind {
+            0 => Arc::new(BooleanArray::from(decode_booleans(data(), values))),
+            2 => Arc::new(Int16Array::from_iter_values(
+                decode_ints(data(), true)?.into_iter().map(|v| v as i16),
+            )),
+            3 => Arc::new(Int32Array::from_iter_values(
+                decode_ints(data(), true)?.into_iter().map(|v| v as i32),
+            )),
+            4 => Arc::new(Int64Array::from(decode_ints(data(), true)?)),
+            5 => Arc::new(Float32Array::from_iter_values(
+                data()
+                    .chunks(4)
+                    .map(|b| f32::from_le_bytes(b.try_into().unwrap())),
+            )),
+            6 => Arc::new(Float64Array::from_iter_values(
+                data()
+                    .chunks(8)
+                    .map(|b| f64::from_le_bytes(b.try_into().unwrap())),
+            )),
+            7 | 8 => {
+                let mut offset = 0;
+                let slices: Vec<&[u8]> = lengths()?
+                    .into_iter()
+                    .map(|length| {
+                        let slice = &data()[offset..offset + length as usize];
+                        offset += length as usize;
+                        slice
+                    })
+                    .collect();
+                match types[id].kind {
+                    7 => Arc::new(StringArray::from_iter_values(
+                        slices.iter().map(|s| std::str::from_utf8(s).unwrap()),
+                    )),
+                    _ => Arc::new(BinaryArray::from_iter_values(slices)),
+                }
+            }
+            12 => {
+                let DataType::Struct(fields) = data_type(types, id) else {
+                    unreachable!()
+                };
+                let children = types[id]
+                    .children
+                    .iter()
+                    .map(|&child| read_column(types, streams, child, values))
+                    .collect::<Result<Vec<_>>>()?;
+                Arc::new(StructArray::try_new(fields, children, None)?)
+            }
+            other => return Err(anyhow!("Unexpected type kind {other}")),
+        };
+        assert_eq!(dense.len(), values, "values of column {id}");
+
+        // Spread the values over the present entries
+        let Some(present) = present else {
+            return Ok(dense);
+        };
+        let mut next = 0;
+        let indices: UInt32Array = present
+            .iter()
+            .map(|&p| {
+                p.then(|| {
+                    next += 1;
+                    next - 1
+                })
+            })
+            .collect();
+        let spread = take(&dense, &indices, None)?;
+        Ok(match types[id].kind {
+            // take doesn't carry the struct nulls over from the indices
+            12 => {
+                let spread = spread.as_struct();
+                Arc::new(StructArray::try_new(
+                    spread.fields().clone(),
+                    spread.columns().to_vec(),
+                    Some(NullBuffer::from(present)),
+                )?)
+            }
+            _ => spread,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::buffer::NullBuffer;
+    use arrow::util::pretty::pretty_format_batches;
+    use arrow_array::{
+        BinaryViewArray, Float64Array, Int32Array, Int64Array, StringArray, StringViewArray,
+    };
+    use arrow_schema::{Field, Fields, Schema};
+
+    fn ints(values: &[i64], signed: bool) -> Vec<u8> {
+        let mut out = Vec::new();
+        encode_ints(values, signed, &mut out);
+        out
+    }
+
+    fn bytes(values: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        encode_bytes(values, &mut out);
+        out
+    }
+
+    #[test]
+    fn test_run_length_encodings_match_specification_examples() {
+        assert_eq!(ints(&[7; 100], false), vec![0x61, 0x00, 0x07]);
+        assert_eq!(
+            ints(&[2, 3, 6, 7, 11], false),
+            vec![0xfb, 0x02, 0x03, 0x06, 0x07, 0x0b]
+        );
+        assert_eq!(bytes(&[0; 100]), vec![0x61, 0x00]);
+        assert_eq!(bytes(&[0x44, 0x45]), vec![0xfe, 0x44, 0x45]);
+
+        // Literals around a run, a descending run, and a run cut at 130
+        assert_eq!(
+            ints(&[-1, 5, 4, 3, 2, 9], true),
+            vec![0xff, 0x01, 0x01, 0xff, 0x0a, 0xff, 0x12]
+        );
+        let keys: Vec<i64> = (1..=200).collect();
+        assert_eq!(
+            ints(&keys, true),
+            vec![0x7f, 0x01, 0x02, 0x43, 0x01, 0x86, 0x02]
+        );
+        for values in [keys, vec![i64::MIN, i64::MAX, 0, 0, 0], vec![]] {
+            assert_eq!(
+                reader::decode_ints(&ints(&values, true), true).unwrap(),
+                values
+            );
+        }
+    }
+
+    fn batch() -> RecordBatch {
+        let bbox_fields = Fields::from(vec![
+            Field::new("xmin", DataType::Float64, false),
+            Field::new("ymax", DataType::Float64, false),
+        ]);
+        let bbox = StructArray::try_new(
+            bbox_fields.clone(),
+            vec![
+                Arc::new(Float64Array::from(vec![1.5, 0.0, -3.25, 4.0, 5.0])),
+                Arc::new(Float64Array::from(vec![2.5, 0.0, -1.0, 8.0, 9.0])),
+            ],
+            Some(NullBuffer::from(vec![true, false, true, true, false])),
+        )
+        .unwrap();
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("z_zonekey", DataType::Int64, false),
+            Field::new("z_name", DataType::Utf8, true),
+            Field::new("z_country", DataType::Utf8View, false),
+            Field::new("z_boundary", DataType::BinaryView, true),
+            Field::new("z_level", DataType::Int32, true),
+            Field::new("z_bbox", DataType::Struct(bbox_fields), true),
+        ]));
+        RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(Int64Array::from(vec![1, 2, 3, 4, 5])),
+                Arc::new(StringArray::from(vec![
+                    Some("Zürich"),
+                    None,
+                    Some(""),
+                    Some("Bern"),
+                    None,
+                ])),
+                Arc::new(StringViewArray::from(vec!["CH", "CH", "DE", "FR", "FR"])),
+                Arc::new(BinaryViewArray::from(vec![
+                    Some(&[1u8, 3, 0, 0, 0][..]),
+                    Some(&[][..]),
+                    None,
+                    Some(&[0u8; 40][..]),
+                    Some(&[7u8][..]),
+                ])),
+                Arc::new(Int32Array::from(vec![
+                    None,
+                    Some(-2),
+                    Some(300),
+                    None,
+                    None,
+                ])),
+                Arc::new(bbox),
+            ],
+        )
+        .unwrap()
+    }
+
+    fn write(batches: &[RecordBatch], compression: OrcCompression, rows: usize) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut writer =
+            OrcWriter::try_new(&mut bytes, batches[0].schema(), compression, rows).unwrap();
+        for batch in batches {
+            writer.write(batch).unwrap();
+        }
+        writer.append_key_value_metadata("key".to_string(), "value".to_string());
+        writer.close().unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let batches = [batch(), batch().slice(1, 3)];
+        let expected = pretty_format_batches(&batches).unwrap().to_string();
+        for compression in OrcCompression::value_variants() {
+            for rows_per_stripe in [1, 3, 100] {
+                let file = reader::read(&write(&batches, *compression, rows_per_stripe)).unwrap();
+                assert_eq!(file.compression, compression.kind());
+                assert_eq!(file.rows, 8);
+                assert_eq!(file.batches.len(), 8usize.div_ceil(rows_per_stripe));
+                assert_eq!(
+                    pretty_format_batches(&file.batches).unwrap().to_string(),
+                    expected,
+                    "{compression:?}, {rows_per_stripe} rows per stripe"
+                );
+                assert_eq!(
+                    file.metadata,
+                    vec![("key".to_string(), "value".to_string())]
+                );
+                let names: Vec<_> = file.batches[0]
+                    .schema()
+                    .fields()
+                    .iter()
+                    .map(|f| f.name().clone())
+                    .collect();
+                assert_eq!(
+                    names,
+                    vec![
+                        "z_zonekey",
+                        "z_name",
+                        "z_country",
+                        "z_boundary",
+                        "z_level",
+                        "z_bbox"
+                    ]
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_flush_ends_the_stripe() {
+        let mut bytes = Vec::new();
+        let mut writer =
+            OrcWriter::try_new(&mut bytes, batch().schema(), OrcCompression::None, 100).unwrap();
+        writer.write(&batch().slice(0, 3)).unwrap();
+        writer.flush().unwrap();
+        // Nothing buffered, no empty stripe
+        writer.flush().unwrap();
+        writer.write(&batch().slice(3, 2)).unwrap();
+        writer.close().unwrap();
+
+        let file = reader::read(&bytes).unwrap();
+        let rows: Vec<_> = file.batches.iter().map(|b| b.num_rows()).collect();
+        assert_eq!(rows, vec![3, 2]);
+    }
+
+    #[test]
+    fn test_streams_larger_than_a_compression_block() {
+        let value = (0..COMPRESSION_BLOCK_BYTES * 3)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "z_boundary",
+            DataType::Binary,
+            true,
+        )]));
+        let batch = RecordBatch::try_new(
+            schema,
+            vec![Arc::new(arrow_array::BinaryArray::from_iter_values([
+                value.as_slice(),
+                &value[..10],
+            ]))],
+        )
+        .unwrap();
+        for compression in OrcCompression::value_variants() {
+            let bytes = write(std::slice::from_ref(&batch), *compression, 10);
+            let file = reader::read(&bytes).unwrap();
+            assert_eq!(file.batches, vec![batch.clone()], "{compression:?}");
+        }
+    }
+
+    #[test]
+    fn test_unsupported_type_fails() {
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "z_area",
+            DataType::Decimal128(10, 2),
+            false,
+        )]));
+        let error = OrcWriter::try_new(Vec::new(), schema, OrcCompression::Zlib, 10)
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("z_area"), "{error}");
+    }
+}
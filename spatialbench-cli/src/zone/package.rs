@@ -0,0 +1,451 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Distributable archives of generated datasets
+//!
+//! `package` streams every file of a dataset directory into a zstd
+//! compressed tar archive, one file at a time. The archive opens with
+//! `spatialbench.package.json`, describing the dataset and listing its
+//! files, followed by the zone manifest, so both are read by decompressing
+//! only the start of the archive. `unpack` extracts an archive the same
+//! way and re-verifies the zone content hashes of what it wrote.
+
+use anyhow::{anyhow, Result};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter};
+use std::path::{Component, Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::manifest::{ZoneManifest, MANIFEST_FILE_NAME};
+use super::tar::{TarHeader, TarReader, TarWriter};
+use super::verify;
+
+/// First entry of every archive
+pub const METADATA_FILE_NAME: &str = "spatialbench.package.json";
+
+/// What `spatialbench.package.json` records about the packaged dataset
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PackageMetadata {
+    /// Version of spatialbench-cli that wrote the archive
+    pub spatialbench_version: String,
+    pub scale_factor: f64,
+    pub parts: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema_version: Option<String>,
+    /// Every file of the dataset, the manifest included
+    pub files: Vec<PackagedFile>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PackagedFile {
+    /// `/` separated path relative to the dataset directory
+    pub path: String,
+    pub bytes: u64,
+}
+
+impl PackageMetadata {
+    /// Reads the metadata at the start of `archive` without decompressing
+    /// the rest of it
+    pub fn read(archive: &Path) -> Result<Self> {
+        let mut reader = archive_reader(archive)?;
+        read_metadata(&mut reader, archive)
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        self.files.iter().map(|f| f.bytes).sum()
+    }
+}
+
+/// Files and bytes written or extracted
+#[derive(Debug, Default, PartialEq)]
+pub struct PackageSummary {
+    pub files: usize,
+    pub bytes: u64,
+    /// Zone files whose content hash no longer matches, after `unpack`
+    pub mismatched: Vec<String>,
+}
+
+/// Packages the dataset of `data_dir` into the `.tar.zst` archive `out`,
+/// after checking that its manifest lists every part and, with
+/// `check_hashes`, that the zone files and the attribution notice still
+/// match their content hashes
+pub fn package_dataset(data_dir: &Path, out: &Path, check_hashes: bool) -> Result<PackageSummary> {
+    let manifest = ZoneManifest::read(data_dir)?
+        .ok_or_else(|| anyhow!("No zone manifest found in {}", data_dir.display()))?;
+    manifest.check_complete(data_dir)?;
+    if check_hashes {
+        let mismatched = verify::verify_dir(data_dir)?;
+        if !mismatched.is_empty() {
+            return Err(anyhow!(
+                "Not packaging {}: {} file(s) do not match their recorded content hash: {}",
+                data_dir.display(),
+                mismatched.len(),
+                mismatched.join(", ")
+            ));
+        }
+        if let Some(problem) = verify::attribution_problem(data_dir)? {
+            return Err(anyhow!("Not packaging {}: {problem}", data_dir.display()));
+        }
+    }
+
+    let temp_path = out.with_extension("inprogress");
+    let skipped = [absolute(out)?, absolute(&temp_path)?];
+    let mut files = BTreeMap::new();
+    collect_files(data_dir, data_dir, &skipped, &mut files)?;
+    let manifest_path = files
+        .remove(MANIFEST_FILE_NAME)
+        .ok_or_else(|| anyhow!("No zone manifest found in {}", data_dir.display()))?;
+
+    // The manifest follows the metadata, then the files in path order
+    let entries: Vec<(String, PathBuf)> = [(MANIFEST_FILE_NAME.to_string(), manifest_path)]
+        .into_iter()
+        .chain(files)
+        .collect();
+    let metadata = PackageMetadata {
+        spatialbench_version: env!("CARGO_PKG_VERSION").to_string(),
+        scale_factor: manifest.scale_factor,
+        parts: manifest.parts,
+        schema_version: manifest.schema_version.clone(),
+        files: entries
+            .iter()
+            .map(|(name, path)| {
+                Ok(PackagedFile {
+                    path: name.clone(),
+                    bytes: std::fs::metadata(path)?.len(),
+                })
+            })
+            .collect::<Result<_>>()?,
+    };
+
+    let result = write_archive(&temp_path, &metadata, &entries);
+    if let Err(e) = result {
+        let _ = std::fs::remove_file(&temp_path);
+        return Err(e);
+    }
+    std::fs::rename(&temp_path, out)?;
+    info!(
+        "Packaged {} file(s), {} bytes, of {} into {}",
+        entries.len(),
+        metadata.total_bytes(),
+        data_dir.display(),
+        out.display()
+    );
+    Ok(PackageSummary {
+        files: entries.len(),
+        bytes: metadata.total_bytes(),
+        mismatched: vec![],
+    })
+}
+
+fn write_archive(
+    path: &Path,
+    metadata: &PackageMetadata,
+    entries: &[(String, PathBuf)],
+) -> Result<()> {
+    let encoder = zstd::stream::Encoder::new(BufWriter::new(File::create(path)?), 0)?;
+    let mut tar = TarWriter::new(encoder);
+
+    let json = serde_json::to_vec_pretty(metadata)?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    tar.append(
+        &TarHeader {
+            path: METADATA_FILE_NAME.to_string(),
+            size: json.len() as u64,
+            mtime: now,
+        },
+        json.as_slice(),
+    )?;
+    for ((name, path), packaged) in entries.iter().zip(&metadata.files) {
+        let file = File::open(path)?;
+        let mtime = file
+            .metadata()?
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let header = TarHeader {
+            path: name.clone(),
+            size: packaged.bytes,
+            mtime,
+        };
+        tar.append(&header, BufReader::new(file))?;
+    }
+    tar.finish()?.finish()?.into_inner()?.sync_all()?;
+    Ok(())
+}
+
+/// Adds the files under `dir` to `files` by their path relative to `root`,
+/// leaving out `skipped` and the temporary files of unfinished writes
+fn collect_files(
+    root: &Path,
+    dir: &Path,
+    skipped: &[PathBuf],
+    files: &mut BTreeMap<String, PathBuf>,
+) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(root, &path, skipped, files)?;
+        } else if path.extension().is_some_and(|e| e == "inprogress")
+            || skipped.contains(&absolute(&path)?)
+        {
+            continue;
+        } else {
+            let relative = path.strip_prefix(root).unwrap_or(&path);
+            let name = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            files.insert(name, path);
+        }
+    }
+    Ok(())
+}
+
+fn absolute(path: &Path) -> Result<PathBuf> {
+    Ok(std::path::absolute(path)?)
+}
+
+fn archive_reader(archive: &Path) -> Result<TarReader<impl std::io::Read>> {
+    let file = File::open(archive)
+        .map_err(|e| anyhow!("Failed opening archive {}: {e}", archive.display()))?;
+    Ok(TarReader::new(zstd::stream::Decoder::new(file)?))
+}
+
+fn read_metadata<R: std::io::Read>(
+    reader: &mut TarReader<R>,
+    archive: &Path,
+) -> Result<PackageMetadata> {
+    match reader.next_entry()? {
+        Some(header) if header.path == METADATA_FILE_NAME => {
+            let mut json = Vec::new();
+            reader.copy_entry(&mut json)?;
+            Ok(serde_json::from_slice(&json)?)
+        }
+        _ => Err(anyhow!(
+            "{} is not a dataset archive: it doesn't start with {METADATA_FILE_NAME}",
+            archive.display()
+        )),
+    }
+}
+
+/// Extracts the dataset archive `archive` into `output_dir`, refusing to
+/// overwrite files, then checks its manifest and re-hashes its zone files
+pub fn unpack_dataset(archive: &Path, output_dir: &Path) -> Result<PackageSummary> {
+    let mut reader = archive_reader(archive)?;
+    let metadata = read_metadata(&mut reader, archive)?;
+    let mut expected: BTreeMap<&str, u64> = metadata
+        .files
+        .iter()
+        .map(|f| (f.path.as_str(), f.bytes))
+        .collect();
+
+    let mut summary = PackageSummary::default();
+    while let Some(header) = reader.next_entry()? {
+        if expected.remove(header.path.as_str()) != Some(header.size) {
+            return Err(anyhow!(
+                "Archive entry {} ({} bytes) is not listed in {METADATA_FILE_NAME}",
+                header.path,
+                header.size
+            ));
+        }
+        let path = output_dir.join(safe_relative_path(&header.path)?);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .map_err(|e| anyhow!("Failed creating {}: {e}", path.display()))?;
+        let mut sink = BufWriter::new(file);
+        summary.bytes += reader.copy_entry(&mut sink)?;
+        sink.into_inner().map_err(|e| e.into_error())?;
+        summary.files += 1;
+    }
+    if !expected.is_empty() {
+        return Err(anyhow!(
+            "Archive {} is missing {} listed file(s): {}",
+            archive.display(),
+            expected.len(),
+            expected.into_keys().collect::<Vec<_>>().join(", ")
+        ));
+    }
+
+    let manifest = ZoneManifest::read(output_dir)?
+        .ok_or_else(|| anyhow!("No zone manifest in {}", archive.display()))?;
+    manifest.check_complete(output_dir)?;
+    summary.mismatched = verify::verify_dir(output_dir)?;
+    info!(
+        "Unpacked {} file(s), {} bytes, into {}",
+        summary.files,
+        summary.bytes,
+        output_dir.display()
+    );
+    Ok(summary)
+}
+
+/// `path` of an archive entry, rejected when it would land outside the
+/// output directory
+fn safe_relative_path(path: &str) -> Result<PathBuf> {
+    let relative = PathBuf::from(path);
+    if path.is_empty()
+        || !relative
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)))
+    {
+        return Err(anyhow!("Refusing to extract archive entry {path:?}"));
+    }
+    Ok(relative)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zone::hash::{ContentHasher, CONTENT_SHA256_KEY};
+    use crate::zone::manifest::ManifestPart;
+    use arrow_array::{Int64Array, RecordBatch};
+    use arrow_schema::{DataType, Field, Schema};
+    use parquet::arrow::ArrowWriter;
+    use parquet::file::metadata::KeyValue;
+    use std::sync::Arc;
+
+    /// Writes a two part zone dataset with its manifest and another table
+    fn dataset(dir: &Path) {
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "z_zonekey",
+            DataType::Int64,
+            false,
+        )]));
+        std::fs::create_dir_all(dir.join("zone")).unwrap();
+        let mut manifest = ZoneManifest::new(1.0, 2);
+        for part in 1..=2 {
+            let batch = RecordBatch::try_new(
+                schema.clone(),
+                vec![Arc::new(Int64Array::from(vec![part as i64 * 10]))],
+            )
+            .unwrap();
+            let mut hasher = ContentHasher::try_new(&schema).unwrap();
+            hasher.update(&batch).unwrap();
+            let content_sha256 = hasher.finish();
+
+            let path = format!("zone/zone.{part}.parquet");
+            let file = File::create(dir.join(&path)).unwrap();
+            let mut writer = ArrowWriter::try_new(file, schema.clone(), None).unwrap();
+            writer.write(&batch).unwrap();
+            writer.append_key_value_metadata(KeyValue::new(
+                CONTENT_SHA256_KEY.to_string(),
+                content_sha256.clone(),
+            ));
+            writer.close().unwrap();
+            manifest.replace_part(
+                part,
+                vec![ManifestPart {
+                    part,
+                    path,
+                    rows: 1,
+                    content_sha256,
+                    file_index: None,
+                    row_offset: None,
+                    footer_bytes: None,
+                }],
+            );
+        }
+        manifest.write(dir).unwrap();
+        std::fs::write(dir.join("trip.parquet"), b"trips").unwrap();
+    }
+
+    #[test]
+    fn test_package_and_unpack_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = dir.path().join("sf1");
+        dataset(&data);
+        // Archives written into the dataset directory are left out of it
+        let archive = data.join("spatialbench-sf1.tar.zst");
+
+        let packaged = package_dataset(&data, &archive, true).unwrap();
+        assert_eq!(packaged.files, 4);
+        assert!(!data.join("spatialbench-sf1.tar.inprogress").exists());
+
+        let metadata = PackageMetadata::read(&archive).unwrap();
+        assert_eq!(metadata.scale_factor, 1.0);
+        assert_eq!(metadata.parts, 2);
+        let paths: Vec<_> = metadata.files.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                MANIFEST_FILE_NAME,
+                "trip.parquet",
+                "zone/zone.1.parquet",
+                "zone/zone.2.parquet"
+            ]
+        );
+        assert_eq!(metadata.total_bytes(), packaged.bytes);
+
+        let out = dir.path().join("unpacked");
+        let unpacked = unpack_dataset(&archive, &out).unwrap();
+        assert_eq!(unpacked, packaged);
+        for path in paths {
+            assert_eq!(
+                std::fs::read(out.join(path)).unwrap(),
+                std::fs::read(data.join(path)).unwrap(),
+                "{path}"
+            );
+        }
+        assert!(!out.join(METADATA_FILE_NAME).exists());
+
+        // Extracting again would overwrite the dataset
+        let err = unpack_dataset(&archive, &out).unwrap_err();
+        assert!(err.to_string().contains("Failed creating"), "{err}");
+    }
+
+    #[test]
+    fn test_package_checks_manifest_and_hashes() {
+        let dir = tempfile::tempdir().unwrap();
+        dataset(dir.path());
+        let archive = dir.path().join("out.tar.zst");
+
+        // A part file rewritten with other rows
+        let part = dir.path().join("zone/zone.2.parquet");
+        std::fs::copy(dir.path().join("zone/zone.1.parquet"), &part).unwrap();
+        let err = package_dataset(dir.path(), &archive, true).unwrap_err();
+        assert!(err.to_string().contains("zone/zone.2.parquet"), "{err}");
+        assert!(!archive.exists());
+        // which only hash checking notices
+        package_dataset(dir.path(), &archive, false).unwrap();
+        let unpacked = unpack_dataset(&archive, &dir.path().join("out")).unwrap();
+        assert_eq!(unpacked.mismatched, vec!["zone/zone.2.parquet"]);
+
+        std::fs::remove_file(&part).unwrap();
+        let err = package_dataset(dir.path(), &archive, false).unwrap_err();
+        assert!(err.to_string().contains("does not exist"), "{err}");
+    }
+
+    #[test]
+    fn test_rejects_paths_outside_output_dir() {
+        for path in ["../escape", "/etc/passwd", "a/../../b", ""] {
+            assert!(safe_relative_path(path).is_err(), "{path}");
+        }
+        assert_eq!(
+            safe_relative_path("zone/zone.1.parquet").unwrap(),
+            PathBuf::from("zone/zone.1.parquet")
+        );
+    }
+}
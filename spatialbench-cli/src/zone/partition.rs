@@ -15,10 +15,231 @@
 // specific language governing permissions and limitations
 // under the License.
 
+use crate::zone::antimeridian;
+use crate::zone::config::{check_parts, PartitionScheme};
 use crate::zone::stats::ZoneTableStats;
-use arrow_array::RecordBatch;
+use crate::zone::wkb::count_points;
+use anyhow::anyhow;
+use arrow::compute::{cast, concat_batches, take_record_batch};
+use arrow_array::{Array, BinaryArray, RecordBatch, UInt32Array};
+use arrow_schema::DataType;
 use datafusion::prelude::*;
+use geozero::wkb::Wkb;
+use geozero::ToGeo;
 use log::{debug, info};
+use serde::{Deserialize, Serialize};
+
+/// Explicit row range of one part, recorded when parts are not split by
+/// row count alone
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PartBoundary {
+    pub part: i32,
+    pub offset: i64,
+    pub limit: i64,
+}
+
+/// Band, or KD-tree cell, of one part. Zones are assigned to it by
+/// centroid, so their boundaries may reach outside it.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PartExtent {
+    pub part: i32,
+    pub min_lon: f64,
+    pub min_lat: f64,
+    pub max_lon: f64,
+    pub max_lat: f64,
+}
+
+/// Rows reordered so that every band is a contiguous run
+pub struct Bands {
+    pub batches: Vec<RecordBatch>,
+    pub boundaries: Vec<PartBoundary>,
+    pub extents: Vec<PartExtent>,
+}
+
+/// One part of a [`PartitionPlan`]: rows `offset..offset + limit`
+pub type PartSpec = PartBoundary;
+
+/// Row ranges of every part of a table with `total_rows` rows, as used to
+/// generate the parts.
+///
+/// Part `i` of a plan starts where part `i - 1` ends, so the parts cover
+/// every row exactly once in part order. Schedulers can build one task per
+/// [`PartSpec`] and pass its `part` as `--part`. A deserialized plan is
+/// checked like one built by [`PartitionPlan::from_parts`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "UncheckedPartitionPlan")]
+pub struct PartitionPlan {
+    total_rows: i64,
+    parts: Vec<PartSpec>,
+}
+
+/// A [`PartitionPlan`] as read, before its parts are checked
+#[derive(Deserialize)]
+struct UncheckedPartitionPlan {
+    total_rows: i64,
+    parts: Vec<PartSpec>,
+}
+
+impl TryFrom<UncheckedPartitionPlan> for PartitionPlan {
+    type Error = anyhow::Error;
+
+    fn try_from(unchecked: UncheckedPartitionPlan) -> anyhow::Result<Self> {
+        let plan = Self::from_parts(unchecked.parts)?;
+        if plan.total_rows != unchecked.total_rows {
+            return Err(anyhow!(
+                "Plan of {} rows has parts covering {} rows",
+                unchecked.total_rows,
+                plan.total_rows
+            ));
+        }
+        Ok(plan)
+    }
+}
+
+impl PartitionPlan {
+    /// Splits `total_rows` into `parts` runs whose lengths differ by at most
+    /// one row, the longer runs first.
+    ///
+    /// # Panics
+    ///
+    /// If `total_rows` is negative or `parts` is less than 1.
+    pub fn new(total_rows: i64, parts: i32) -> Self {
+        assert!(total_rows >= 0, "Invalid total_rows={total_rows}");
+        assert!(parts >= 1, "Invalid parts={parts}");
+        Self {
+            total_rows,
+            parts: (1..=parts)
+                .map(|part| Self::spec(total_rows, parts, part))
+                .collect(),
+        }
+    }
+
+    /// A plan of explicit part ranges, such as the balanced boundaries of a
+    /// zone manifest. The parts must be numbered from 1 and be contiguous
+    /// from row 0.
+    pub fn from_parts(parts: Vec<PartSpec>) -> anyhow::Result<Self> {
+        if parts.is_empty() {
+            return Err(anyhow!("A partition plan needs at least one part"));
+        }
+        let mut end: i64 = 0;
+        for (i, spec) in parts.iter().enumerate() {
+            if spec.part != i as i32 + 1 || spec.offset != end || spec.limit < 0 {
+                return Err(anyhow!(
+                    "Part {} with offset {} and limit {} does not follow the row range \
+                     of the previous part, ending at {end}",
+                    spec.part,
+                    spec.offset,
+                    spec.limit
+                ));
+            }
+            end = end
+                .checked_add(spec.limit)
+                .ok_or_else(|| anyhow!("Part {} ends beyond the last row", spec.part))?;
+        }
+        Ok(Self {
+            total_rows: end,
+            parts,
+        })
+    }
+
+    fn spec(total_rows: i64, parts: i32, part: i32) -> PartSpec {
+        let i = part as i64 - 1;
+        let base = total_rows / parts as i64;
+        let rem = total_rows % parts as i64;
+        PartSpec {
+            part,
+            offset: i * base + i.min(rem),
+            limit: base + if i < rem { 1 } else { 0 },
+        }
+    }
+
+    pub fn total_rows(&self) -> i64 {
+        self.total_rows
+    }
+
+    pub fn part_count(&self) -> i32 {
+        self.parts.len() as i32
+    }
+
+    /// The parts in order
+    pub fn iter(&self) -> impl Iterator<Item = &PartSpec> {
+        self.parts.iter()
+    }
+
+    /// The range of `part`, `None` when the plan has no such part
+    pub fn part(&self, part: i32) -> Option<&PartSpec> {
+        usize::try_from(part - 1)
+            .ok()
+            .and_then(|i| self.parts.get(i))
+    }
+
+    /// The part holding row `row` (0-based), `None` when the table has no
+    /// such row
+    pub fn part_for_row(&self, row: i64) -> Option<i32> {
+        if row < 0 || row >= self.total_rows {
+            return None;
+        }
+        // The first part ending after `row`; empty parts end where the
+        // next part starts, so they are skipped
+        let i = self
+            .parts
+            .partition_point(|spec| spec.offset + spec.limit <= row);
+        self.parts.get(i).map(|spec| spec.part)
+    }
+
+    /// Checks that `other`, computed elsewhere, describes the same parts
+    /// and returns the agreed plan. The error names the first part the
+    /// plans disagree on.
+    pub fn merge(&self, other: &PartitionPlan) -> anyhow::Result<PartitionPlan> {
+        if self.total_rows != other.total_rows {
+            return Err(anyhow!(
+                "Plans disagree on the total row count: {} and {}",
+                self.total_rows,
+                other.total_rows
+            ));
+        }
+        if self.parts.len() != other.parts.len() {
+            return Err(anyhow!(
+                "Plans disagree on the part count: {} and {}",
+                self.parts.len(),
+                other.parts.len()
+            ));
+        }
+        if let Some((a, b)) = self.iter().zip(other.iter()).find(|(a, b)| a != b) {
+            return Err(anyhow!(
+                "Plans disagree on part {}: offset {} and limit {}, offset {} and limit {}",
+                a.part,
+                a.offset,
+                a.limit,
+                b.offset,
+                b.limit
+            ));
+        }
+        Ok(self.clone())
+    }
+}
+
+/// The plan of `parts` parts of a table with `total_rows` rows, for a
+/// driver that dispatches every part's offset and limit to its workers
+/// without generating anything. Errors where [`PartitionPlan::new`] panics.
+pub fn partition_plan(total_rows: i64, parts: i32) -> anyhow::Result<PartitionPlan> {
+    if total_rows < 0 {
+        return Err(anyhow!(
+            "Invalid total_rows={total_rows}, expected at least 0"
+        ));
+    }
+    check_parts(parts)?;
+    Ok(PartitionPlan::new(total_rows, parts))
+}
+
+impl<'a> IntoIterator for &'a PartitionPlan {
+    type Item = &'a PartSpec;
+    type IntoIter = std::slice::Iter<'a, PartSpec>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.parts.iter()
+    }
+}
 
 pub struct PartitionStrategy {
     offset: i64,
@@ -26,28 +247,77 @@ pub struct PartitionStrategy {
 }
 
 impl PartitionStrategy {
+    /// The row range of `part` (default 1) of `parts` (default 1), as in
+    /// [`PartitionPlan::new`]
     pub fn calculate(total_rows: i64, parts: Option<i32>, part: Option<i32>) -> Self {
         let parts = parts.unwrap_or(1);
-        let part = part.unwrap_or(1);
-        let i = part - 1;
+        let spec = PartitionPlan::spec(total_rows, parts, part.unwrap_or(1));
 
-        let base = total_rows / parts as i64;
-        let rem = total_rows % parts as i64;
+        info!(
+            "Partition: total={}, parts={}, part={}, offset={}, limit={}",
+            total_rows, parts, spec.part, spec.offset, spec.limit
+        );
 
-        let limit = base + if (i as i64) < rem { 1 } else { 0 };
-        let offset = i as i64 * base + std::cmp::min(i as i64, rem);
+        Self {
+            offset: spec.offset,
+            limit: spec.limit,
+        }
+    }
 
+    pub fn from_boundary(boundary: &PartBoundary) -> Self {
         info!(
-            "Partition: total={}, parts={}, part={}, offset={}, limit={}",
-            total_rows, parts, part, offset, limit
+            "Partition: part={}, offset={}, limit={} (balanced)",
+            boundary.part, boundary.offset, boundary.limit
         );
+        Self {
+            offset: boundary.offset,
+            limit: boundary.limit,
+        }
+    }
 
-        Self { offset, limit }
+    /// Splits rows into `parts` contiguous runs of roughly equal total
+    /// weight.
+    ///
+    /// A part ends at the first row where the running total reaches its
+    /// share, so a single heavy row can leave a later part empty.
+    pub fn balanced_boundaries(weights: &[u64], parts: i32) -> Vec<PartBoundary> {
+        let total: u128 = weights.iter().map(|w| *w as u128).sum();
+        let mut boundaries = Vec::with_capacity(parts as usize);
+        let mut offset = 0usize;
+        let mut running: u128 = 0;
+        for part in 1..=parts {
+            let target = (total * part as u128).div_ceil(parts as u128);
+            let mut end = offset;
+            if part == parts {
+                end = weights.len();
+            } else {
+                while end < weights.len() && running < target {
+                    running += weights[end] as u128;
+                    end += 1;
+                }
+            }
+            boundaries.push(PartBoundary {
+                part,
+                offset: offset as i64,
+                limit: (end - offset) as i64,
+            });
+            offset = end;
+        }
+        boundaries
     }
 
-    /// Calculates the number of parts needed to approximate the output file size.
-    pub(crate) fn calculate_parts_from_max_size(sf: f64, output_file_size_mb: f32) -> i32 {
-        let (size_gb, _) = ZoneTableStats::base_stats(sf);
+    /// Calculates the number of parts needed to approximate the output file
+    /// size. With `rows`, the size shrinks in proportion to the sample.
+    pub(crate) fn calculate_parts_from_max_size(
+        sf: f64,
+        rows: Option<u64>,
+        output_file_size_mb: f32,
+    ) -> i32 {
+        let (size_gb, total_rows) = ZoneTableStats::base_stats(sf);
+        let size_gb = match rows {
+            Some(rows) => size_gb * (rows as f64 / total_rows as f64).min(1.0),
+            None => size_gb,
+        };
 
         let total_size_bytes = size_gb * 1024.0 * 1024.0 * 1024.0;
         let output_file_size_mb = output_file_size_mb * 1024.0 * 1024.0;
@@ -67,6 +337,10 @@ impl PartitionStrategy {
         self.offset
     }
 
+    pub fn limit(&self) -> i64 {
+        self.limit
+    }
+
     pub fn apply_to_dataframe(&self, df: DataFrame) -> datafusion::common::Result<DataFrame> {
         df.limit(self.offset as usize, Some(self.limit as usize))
     }
@@ -102,9 +376,259 @@ impl PartitionStrategy {
     }
 }
 
+/// Splits the globe into `parts` bands along the axis of `scheme`, or
+/// `None` for [`PartitionScheme::Rows`] and [`PartitionScheme::Kdtree`],
+/// whose cells depend on the rows
+///
+/// [`PartitionScheme::EqualArea`] bands are equal-width in sine latitude:
+/// the area of the sphere north of a latitude is proportional to one minus
+/// its sine, so band `i` of `n` ends at `asin(2i/n - 1)`.
+pub fn band_extents(scheme: PartitionScheme, parts: i32) -> Option<Vec<PartExtent>> {
+    let edge = |min: f64, max: f64, i: i32| min + (max - min) * i as f64 / parts as f64;
+    let equal_area_edge = |i: i32| edge(-1.0, 1.0, i).asin().to_degrees();
+    let lat_band = |part, min_lat, max_lat| PartExtent {
+        part,
+        min_lon: -180.0,
+        min_lat,
+        max_lon: 180.0,
+        max_lat,
+    };
+    let extents = (1..=parts).map(|part| match scheme {
+        PartitionScheme::LatBands => {
+            lat_band(part, edge(-90.0, 90.0, part - 1), edge(-90.0, 90.0, part))
+        }
+        PartitionScheme::EqualArea => {
+            lat_band(part, equal_area_edge(part - 1), equal_area_edge(part))
+        }
+        _ => PartExtent {
+            part,
+            min_lon: edge(-180.0, 180.0, part - 1),
+            min_lat: -90.0,
+            max_lon: edge(-180.0, 180.0, part),
+            max_lat: 90.0,
+        },
+    });
+    match scheme {
+        PartitionScheme::Rows | PartitionScheme::Kdtree => None,
+        _ => Some(extents.collect()),
+    }
+}
+
+/// Splits the globe into `parts` KD-tree cells holding about the same
+/// number of `centroids`, returning the cell of every centroid and the
+/// extent of every cell
+///
+/// Every split divides the rows of a cell in proportion to the parts on
+/// either side at the median coordinate, along longitude first and then
+/// alternating with latitude. Centroids on a split go to either side to
+/// keep the counts balanced; ties are broken by row order.
+fn kd_tree_cells(centroids: &[(f64, f64)], parts: i32) -> (Vec<usize>, Vec<PartExtent>) {
+    fn bisect(
+        points: &mut [(f64, f64, usize)],
+        parts: usize,
+        cell: PartExtent,
+        by_lon: bool,
+        cells: &mut Vec<(PartExtent, Vec<usize>)>,
+    ) {
+        if parts == 1 {
+            cells.push((cell, points.iter().map(|p| p.2).collect()));
+            return;
+        }
+        let key = |p: &(f64, f64, usize)| if by_lon { p.0 } else { p.1 };
+        points.sort_by(|a, b| key(a).total_cmp(&key(b)).then(a.2.cmp(&b.2)));
+
+        let lower_parts = parts / 2;
+        let split = points.len() * lower_parts / parts;
+        let (min, max) = match by_lon {
+            true => (cell.min_lon, cell.max_lon),
+            false => (cell.min_lat, cell.max_lat),
+        };
+        // An empty cell is halved
+        let edge = points
+            .get(split)
+            .map_or((min + max) / 2.0, key)
+            .clamp(min, max);
+        let (mut lower, mut upper) = (cell, cell);
+        match by_lon {
+            true => (lower.max_lon, upper.min_lon) = (edge, edge),
+            false => (lower.max_lat, upper.min_lat) = (edge, edge),
+        }
+        let (lower_points, upper_points) = points.split_at_mut(split);
+        bisect(lower_points, lower_parts, lower, !by_lon, cells);
+        bisect(upper_points, parts - lower_parts, upper, !by_lon, cells);
+    }
+
+    let globe = PartExtent {
+        part: 0,
+        min_lon: -180.0,
+        min_lat: -90.0,
+        max_lon: 180.0,
+        max_lat: 90.0,
+    };
+    let mut points: Vec<_> = centroids
+        .iter()
+        .enumerate()
+        .map(|(i, &(lon, lat))| (lon, lat, i))
+        .collect();
+    let mut cells = Vec::with_capacity(parts as usize);
+    bisect(&mut points, parts as usize, globe, true, &mut cells);
+
+    let mut assignment = vec![0; centroids.len()];
+    let extents = cells
+        .into_iter()
+        .zip(1..)
+        .map(|((extent, rows), part)| {
+            for row in rows {
+                assignment[row] = part as usize - 1;
+            }
+            PartExtent { part, ..extent }
+        })
+        .collect();
+    (assignment, extents)
+}
+
+/// Index of the band containing `(lon, lat)`. Points on an edge between
+/// two bands belong to the upper one, the poles and the antimeridian to
+/// the last band.
+fn band_index(scheme: PartitionScheme, parts: i32, (lon, lat): (f64, f64)) -> usize {
+    let fraction = match scheme {
+        PartitionScheme::LatBands => (lat + 90.0) / 180.0,
+        PartitionScheme::EqualArea => (lat.to_radians().sin() + 1.0) / 2.0,
+        _ => (lon + 180.0) / 360.0,
+    };
+    ((fraction * parts as f64).floor().max(0.0) as usize).min(parts as usize - 1)
+}
+
+/// Centroid of a WKB geometry as `(lon, lat)` and whether the geometry
+/// crosses the antimeridian, `None` when it is empty or not readable
+fn centroid(wkb: &[u8], antimeridian_aware: bool) -> Option<((f64, f64), bool)> {
+    let geometry = Wkb(wkb).to_geo().ok()?;
+    let bounds = antimeridian::bounds(&geometry, antimeridian_aware)?;
+    let centroid = antimeridian::centroid(&geometry, antimeridian_aware)?;
+    Some((centroid, bounds.min_lon > bounds.max_lon))
+}
+
+/// Reorders the rows into the bands, or KD-tree cells, of `scheme` by
+/// `z_boundary` centroid, keeping the order within each band. Returns
+/// `None` for [`PartitionScheme::Rows`].
+///
+/// Rows without a centroid, such as null geometries, go to the first part.
+/// With `antimeridian_aware` zones crossing the 180° meridian are assigned
+/// by their centroid near it rather than the one planar math puts near the
+/// prime meridian.
+pub fn order_by_band(
+    batches: &[RecordBatch],
+    scheme: PartitionScheme,
+    parts: i32,
+    antimeridian_aware: bool,
+) -> anyhow::Result<Option<Bands>> {
+    if scheme == PartitionScheme::Rows {
+        return Ok(None);
+    }
+
+    let mut centroids = Vec::new();
+    let mut crossing = 0;
+    for batch in batches {
+        let geometries = batch
+            .column_by_name("z_boundary")
+            .ok_or_else(|| anyhow!("Column z_boundary not found in zone batch"))?;
+        let geometries = cast(geometries, &DataType::Binary)?;
+        let geometries = geometries.as_any().downcast_ref::<BinaryArray>().unwrap();
+        centroids.extend(geometries.iter().map(|wkb| {
+            let (point, crosses) = wkb.and_then(|wkb| centroid(wkb, antimeridian_aware))?;
+            crossing += crosses as u64;
+            Some(point)
+        }));
+    }
+    if crossing > 0 {
+        info!("{crossing} zone(s) cross the antimeridian");
+    }
+
+    let (bands, extents) = match band_extents(scheme, parts) {
+        Some(extents) => {
+            let bands: Vec<usize> = centroids
+                .iter()
+                .map(|point| point.map_or(0, |point| band_index(scheme, parts, point)))
+                .collect();
+            (bands, extents)
+        }
+        None => {
+            let located: Vec<(usize, (f64, f64))> = centroids
+                .iter()
+                .enumerate()
+                .filter_map(|(row, point)| Some((row, (*point)?)))
+                .collect();
+            let points: Vec<(f64, f64)> = located.iter().map(|(_, point)| *point).collect();
+            let (cells, extents) = kd_tree_cells(&points, parts);
+            let mut bands = vec![0; centroids.len()];
+            for ((row, _), cell) in located.iter().zip(cells) {
+                bands[*row] = cell;
+            }
+            (bands, extents)
+        }
+    };
+
+    let mut counts = vec![0i64; parts as usize];
+    for band in &bands {
+        counts[*band] += 1;
+    }
+    let mut offset = 0;
+    let boundaries = counts
+        .iter()
+        .zip(1..)
+        .map(|(&limit, part)| {
+            let boundary = PartBoundary {
+                part,
+                offset,
+                limit,
+            };
+            offset += limit;
+            boundary
+        })
+        .collect();
+
+    let batches = match batches.first() {
+        Some(first) => {
+            let batch = concat_batches(&first.schema(), batches)?;
+            let mut indices: Vec<u32> = (0..bands.len() as u32).collect();
+            indices.sort_by_key(|&i| bands[i as usize]);
+            vec![take_record_batch(&batch, &UInt32Array::from(indices))?]
+        }
+        None => vec![],
+    };
+    info!("Assigned zones to {parts} {scheme:?} part(s) by centroid: {counts:?} row(s)");
+    Ok(Some(Bands {
+        batches,
+        boundaries,
+        extents,
+    }))
+}
+
+/// Vertex count of every row's `z_boundary`, at least 1 so that rows with
+/// null or unreadable geometries still carry weight
+pub fn vertex_weights(batches: &[RecordBatch]) -> anyhow::Result<Vec<u64>> {
+    let mut weights = Vec::new();
+    for batch in batches {
+        let geometries = batch
+            .column_by_name("z_boundary")
+            .ok_or_else(|| anyhow!("Column z_boundary not found in zone batch"))?;
+        let geometries = cast(geometries, &DataType::Binary)?;
+        let geometries = geometries.as_any().downcast_ref::<BinaryArray>().unwrap();
+        weights.extend(geometries.iter().map(|wkb| {
+            wkb.and_then(|wkb| count_points(wkb).ok())
+                .unwrap_or(0)
+                .max(1) as u64
+        }));
+    }
+    Ok(weights)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use arrow_schema::{Field, Schema};
+    use proptest::prelude::*;
+    use std::sync::Arc;
 
     #[test]
     fn test_partition_distribution() {
@@ -130,6 +654,422 @@ mod tests {
         }
     }
 
+    /// Checks the invariants every plan holds: the parts cover every row
+    /// once, in order, with lengths differing by at most one row, and agree
+    /// with `PartitionStrategy::calculate` and `part_for_row`
+    fn check_plan(total_rows: i64, parts: i32) {
+        let plan = PartitionPlan::new(total_rows, parts);
+        assert_eq!(plan.part_count(), parts);
+        assert_eq!(plan.total_rows(), total_rows);
+
+        let mut end = 0;
+        let (mut min, mut max) = (i64::MAX, 0);
+        for (i, spec) in plan.iter().enumerate() {
+            assert_eq!(spec.part, i as i32 + 1);
+            assert_eq!(spec.offset, end, "{total_rows} rows, {parts} parts");
+            assert!(spec.limit >= 0);
+            end += spec.limit;
+            min = min.min(spec.limit);
+            max = max.max(spec.limit);
+
+            let strategy = PartitionStrategy::calculate(total_rows, Some(parts), Some(spec.part));
+            assert_eq!(
+                (strategy.offset(), strategy.limit()),
+                (spec.offset, spec.limit)
+            );
+
+            for row in [spec.offset, spec.offset + spec.limit - 1] {
+                if spec.limit > 0 {
+                    assert_eq!(plan.part_for_row(row), Some(spec.part));
+                }
+            }
+        }
+        assert_eq!(end, total_rows);
+        assert!(max - min <= 1, "{total_rows} rows, {parts} parts");
+        assert_eq!(plan.part_for_row(-1), None);
+        assert_eq!(plan.part_for_row(total_rows), None);
+    }
+
+    #[test]
+    fn test_partition_plan_small() {
+        for total_rows in 0..=200 {
+            for parts in 1..=40 {
+                check_plan(total_rows, parts);
+            }
+        }
+    }
+
+    #[test]
+    fn test_partition_plan_covers_every_row() {
+        let plan = partition_plan(1_000_003, 16).unwrap();
+        assert_eq!(plan.iter().map(|spec| spec.limit).sum::<i64>(), 1_000_003);
+        let mut end = 0;
+        for spec in &plan {
+            assert_eq!(spec.offset, end);
+            end += spec.limit;
+        }
+        assert_eq!(partition_plan(0, 3).unwrap().total_rows(), 0);
+
+        let err = partition_plan(-1, 3).unwrap_err();
+        assert!(err.to_string().contains("total_rows=-1"), "{err}");
+        let err = partition_plan(10, 0).unwrap_err();
+        assert!(err.to_string().contains("--parts=0"), "{err}");
+    }
+
+    proptest! {
+        #[test]
+        fn test_partition_plan_covers_rows_in_order(
+            total_rows in 0i64..10_000_000_000,
+            parts in 1i32..=2000,
+        ) {
+            check_plan(total_rows, parts);
+        }
+
+        #[test]
+        fn test_partition_plan_part_for_row_is_monotone(
+            total_rows in 1i64..10_000_000_000,
+            parts in 1i32..=2000,
+            rows in proptest::collection::vec(0.0f64..1.0, 1..50),
+        ) {
+            let plan = PartitionPlan::new(total_rows, parts);
+            let mut rows: Vec<i64> = rows
+                .iter()
+                .map(|r| ((r * total_rows as f64) as i64).min(total_rows - 1))
+                .collect();
+            rows.sort_unstable();
+            let found: Vec<i32> = rows
+                .iter()
+                .map(|&row| plan.part_for_row(row).unwrap())
+                .collect();
+            prop_assert!(found.windows(2).all(|w| w[0] <= w[1]));
+            for (row, part) in rows.iter().zip(&found) {
+                let spec = plan.part(*part).unwrap();
+                prop_assert!(spec.offset <= *row && *row < spec.offset + spec.limit);
+            }
+        }
+    }
+
+    #[test]
+    fn test_partition_plan_part_for_row_every_row() {
+        let plan = PartitionPlan::new(23, 5);
+        let parts: Vec<i32> = (0..23).map(|row| plan.part_for_row(row).unwrap()).collect();
+        assert!(parts.windows(2).all(|w| w[0] <= w[1]));
+        for spec in &plan {
+            let rows = parts.iter().filter(|&&p| p == spec.part).count();
+            assert_eq!(rows as i64, spec.limit);
+        }
+
+        // Empty parts hold no rows
+        let plan = PartitionPlan::new(2, 4);
+        assert_eq!(plan.part_for_row(0), Some(1));
+        assert_eq!(plan.part_for_row(1), Some(2));
+    }
+
+    #[test]
+    fn test_partition_plan_serde_and_merge() {
+        let plan = PartitionPlan::new(1000, 7);
+        let json = serde_json::to_string(&plan).unwrap();
+        let parsed: PartitionPlan = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, plan);
+        assert_eq!(plan.merge(&parsed).unwrap(), plan);
+
+        let err = plan.merge(&PartitionPlan::new(1001, 7)).unwrap_err();
+        assert!(err.to_string().contains("total row count"), "{err}");
+        let err = plan.merge(&PartitionPlan::new(1000, 8)).unwrap_err();
+        assert!(err.to_string().contains("part count"), "{err}");
+
+        // Same totals, different boundaries
+        let mut parts: Vec<PartSpec> = plan.iter().copied().collect();
+        parts[2].limit += 1;
+        parts[3].offset += 1;
+        parts[3].limit -= 1;
+        let other = PartitionPlan::from_parts(parts).unwrap();
+        let err = plan.merge(&other).unwrap_err();
+        assert!(err.to_string().contains("part 3"), "{err}");
+    }
+
+    #[test]
+    fn test_partition_plan_deserialization_is_checked() {
+        let parse = |json: &str| serde_json::from_str::<PartitionPlan>(json);
+        let part =
+            |part, offset, limit| format!(r#"{{"part":{part},"offset":{offset},"limit":{limit}}}"#);
+
+        let gap = format!(
+            r#"{{"total_rows":4,"parts":[{},{}]}}"#,
+            part(1, 0, 2),
+            part(2, 3, 1)
+        );
+        let err = parse(&gap).unwrap_err();
+        assert!(err.to_string().contains("ending at 2"), "{err}");
+        let overlap = format!(
+            r#"{{"total_rows":4,"parts":[{},{}]}}"#,
+            part(1, 0, 3),
+            part(2, 2, 2)
+        );
+        assert!(parse(&overlap).is_err());
+        let total = format!(
+            r#"{{"total_rows":9,"parts":[{},{}]}}"#,
+            part(1, 0, 2),
+            part(2, 2, 2)
+        );
+        let err = parse(&total).unwrap_err();
+        assert!(err.to_string().contains("covering 4 rows"), "{err}");
+        let overflow = format!(
+            r#"{{"total_rows":0,"parts":[{},{}]}}"#,
+            part(1, 0, i64::MAX),
+            part(2, i64::MAX, 1)
+        );
+        assert!(parse(&overflow).is_err());
+        assert!(parse(r#"{"total_rows":0,"parts":[]}"#).is_err());
+
+        let plan = parse(&format!(
+            r#"{{"total_rows":4,"parts":[{},{}]}}"#,
+            part(1, 0, 2),
+            part(2, 2, 2)
+        ))
+        .unwrap();
+        assert_eq!(plan.part_for_row(3), Some(2));
+        assert_eq!(plan.part_for_row(4), None);
+    }
+
+    #[test]
+    fn test_partition_plan_from_parts() {
+        let boundaries = PartitionStrategy::balanced_boundaries(&[5, 1, 1, 1, 9], 2);
+        let plan = PartitionPlan::from_parts(boundaries.clone()).unwrap();
+        assert_eq!(plan.total_rows(), 5);
+        assert_eq!(plan.iter().copied().collect::<Vec<_>>(), boundaries);
+
+        assert!(PartitionPlan::from_parts(vec![]).is_err());
+        let gap = vec![
+            PartSpec {
+                part: 1,
+                offset: 0,
+                limit: 2,
+            },
+            PartSpec {
+                part: 2,
+                offset: 3,
+                limit: 2,
+            },
+        ];
+        let err = PartitionPlan::from_parts(gap).unwrap_err();
+        assert!(err.to_string().contains("ending at 2"), "{err}");
+    }
+
+    #[test]
+    fn test_balanced_boundaries() {
+        // One heavy row followed by many light ones
+        let mut weights = vec![1000u64];
+        weights.extend(std::iter::repeat_n(10, 100));
+        let boundaries = PartitionStrategy::balanced_boundaries(&weights, 2);
+        assert_eq!(
+            boundaries,
+            vec![
+                PartBoundary {
+                    part: 1,
+                    offset: 0,
+                    limit: 1
+                },
+                PartBoundary {
+                    part: 2,
+                    offset: 1,
+                    limit: 100
+                },
+            ]
+        );
+
+        // Equal weights split like row counts, covering every row once
+        let boundaries = PartitionStrategy::balanced_boundaries(&[5; 10], 3);
+        let limits: Vec<i64> = boundaries.iter().map(|b| b.limit).collect();
+        assert_eq!(limits, vec![4, 3, 3]);
+        assert_eq!(boundaries[2].offset + boundaries[2].limit, 10);
+
+        // More parts than rows leaves the extra parts empty
+        let boundaries = PartitionStrategy::balanced_boundaries(&[1], 3);
+        let limits: Vec<i64> = boundaries.iter().map(|b| b.limit).collect();
+        assert_eq!(limits, vec![1, 0, 0]);
+    }
+
+    #[test]
+    fn test_band_index() {
+        let lat = PartitionScheme::LatBands;
+        assert_eq!(band_index(lat, 4, (10.0, 0.5)), 2);
+        assert_eq!(band_index(lat, 4, (10.0, 0.0)), 2);
+        assert_eq!(band_index(lat, 4, (10.0, -89.0)), 0);
+        assert_eq!(band_index(lat, 4, (10.0, 90.0)), 3);
+
+        let lon = PartitionScheme::LonBands;
+        assert_eq!(band_index(lon, 3, (-180.0, 0.0)), 0);
+        assert_eq!(band_index(lon, 3, (180.0, 0.0)), 2);
+
+        let extents = band_extents(lat, 4).unwrap();
+        assert_eq!((extents[2].min_lat, extents[2].max_lat), (0.0, 45.0));
+        assert_eq!((extents[2].min_lon, extents[2].max_lon), (-180.0, 180.0));
+        assert!(band_extents(PartitionScheme::Rows, 4).is_none());
+    }
+
+    #[test]
+    fn test_equal_area_bands_follow_sine_spacing() {
+        let equal_area = PartitionScheme::EqualArea;
+        let extents = band_extents(equal_area, 4).unwrap();
+        let edges: Vec<f64> = extents
+            .iter()
+            .map(|e| e.min_lat)
+            .chain([extents[3].max_lat])
+            .collect();
+        for (edge, expected) in edges.iter().zip([-90.0, -30.0, 0.0, 30.0, 90.0]) {
+            assert!((edge - expected).abs() < 1e-9, "{edges:?}");
+        }
+        for extent in &extents {
+            assert_eq!((extent.min_lon, extent.max_lon), (-180.0, 180.0));
+            // Each band covers a quarter of the sphere
+            let area = extent.max_lat.to_radians().sin() - extent.min_lat.to_radians().sin();
+            assert!((area - 0.5).abs() < 1e-12, "{extent:?}");
+        }
+
+        let extents = band_extents(equal_area, 3).unwrap();
+        let expected = (1.0f64 / 3.0).asin().to_degrees();
+        assert!((extents[1].min_lat + expected).abs() < 1e-9);
+        assert!((extents[1].max_lat - expected).abs() < 1e-9);
+
+        assert_eq!(band_index(equal_area, 4, (0.0, 29.0)), 2);
+        assert_eq!(band_index(equal_area, 4, (0.0, 31.0)), 3);
+        assert_eq!(band_index(equal_area, 4, (0.0, -45.0)), 0);
+        assert_eq!(band_index(equal_area, 4, (0.0, 90.0)), 3);
+        assert_eq!(band_index(equal_area, 4, (0.0, -90.0)), 0);
+    }
+
+    #[test]
+    fn test_kdtree_cells_of_clustered_zones_are_contiguous_and_balanced() {
+        // Four clusters of unequal size, the largest spanning two cells
+        let mut centroids = Vec::new();
+        for (count, (lon, lat)) in [
+            (40, (10.0, 45.0)),
+            (20, (-70.0, -30.0)),
+            (25, (120.0, 30.0)),
+        ]
+        .into_iter()
+        .chain([(15, (140.0, -35.0))])
+        {
+            for i in 0..count {
+                centroids.push((lon + (i % 7) as f64 * 0.1, lat + (i / 7) as f64 * 0.1));
+            }
+        }
+        let wkb: Vec<Vec<u8>> = centroids
+            .iter()
+            .map(|&(lon, lat)| {
+                crate::zone::fixtures::wkb_polygon(&[
+                    (lon - 0.01, lat - 0.01),
+                    (lon + 0.01, lat - 0.01),
+                    (lon + 0.01, lat + 0.01),
+                    (lon - 0.01, lat + 0.01),
+                    (lon - 0.01, lat - 0.01),
+                ])
+            })
+            .collect();
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "z_boundary",
+            DataType::Binary,
+            false,
+        )]));
+        let batch =
+            RecordBatch::try_new(schema, vec![Arc::new(BinaryArray::from_iter_values(wkb))])
+                .unwrap();
+
+        let bands = order_by_band(&[batch], PartitionScheme::Kdtree, 4, true)
+            .unwrap()
+            .unwrap();
+        let limits: Vec<i64> = bands.boundaries.iter().map(|b| b.limit).collect();
+        assert_eq!(limits, vec![25, 25, 25, 25]);
+
+        // Every part's zones lie in its cell, and the cells don't overlap
+        let geometries = bands.batches[0]
+            .column(0)
+            .as_any()
+            .downcast_ref::<BinaryArray>()
+            .unwrap();
+        for (boundary, extent) in bands.boundaries.iter().zip(&bands.extents) {
+            assert_eq!(boundary.part, extent.part);
+            for row in boundary.offset..boundary.offset + boundary.limit {
+                let ((lon, lat), _) = centroid(geometries.value(row as usize), true).unwrap();
+                assert!(
+                    (extent.min_lon..=extent.max_lon).contains(&lon)
+                        && (extent.min_lat..=extent.max_lat).contains(&lat),
+                    "({lon}, {lat}) outside {extent:?}"
+                );
+            }
+        }
+        for (i, a) in bands.extents.iter().enumerate() {
+            for b in &bands.extents[i + 1..] {
+                let overlap_lon = a.max_lon.min(b.max_lon) - a.min_lon.max(b.min_lon);
+                let overlap_lat = a.max_lat.min(b.max_lat) - a.min_lat.max(b.min_lat);
+                assert!(
+                    overlap_lon <= 0.0 || overlap_lat <= 0.0,
+                    "{a:?} overlaps {b:?}"
+                );
+            }
+        }
+        let area: f64 = bands
+            .extents
+            .iter()
+            .map(|e| (e.max_lon - e.min_lon) * (e.max_lat - e.min_lat))
+            .sum();
+        assert!((area - 360.0 * 180.0).abs() < 1e-6, "{area}");
+    }
+
+    #[test]
+    fn test_kdtree_cells_split_uneven_part_counts() {
+        let centroids: Vec<(f64, f64)> = (0..10).map(|i| (i as f64, -(i as f64))).collect();
+        let (cells, extents) = kd_tree_cells(&centroids, 3);
+        assert_eq!(cells, vec![0, 0, 0, 2, 2, 2, 2, 1, 1, 1]);
+        // The first split is along longitude, the second along latitude
+        assert_eq!((extents[0].min_lon, extents[0].max_lon), (-180.0, 3.0));
+        assert_eq!((extents[1].min_lat, extents[1].max_lat), (-90.0, -6.0));
+        assert_eq!((extents[2].min_lat, extents[2].max_lat), (-6.0, 90.0));
+
+        // Parts beyond the rows are empty cells
+        let (cells, extents) = kd_tree_cells(&[(5.0, 5.0)], 4);
+        assert_eq!(cells, vec![3]);
+        assert_eq!(extents.len(), 4);
+    }
+
+    #[test]
+    fn test_lon_bands_of_zone_straddling_antimeridian() {
+        let wkb = crate::zone::fixtures::wkb_polygon(&[
+            (178.0, -18.0),
+            (-178.0, -18.0),
+            (-178.0, -16.0),
+            (178.0, -16.0),
+            (178.0, -18.0),
+        ]);
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "z_boundary",
+            DataType::Binary,
+            false,
+        )]));
+        let batch =
+            RecordBatch::try_new(schema, vec![Arc::new(BinaryArray::from_iter_values([wkb]))])
+                .unwrap();
+        let limits = |antimeridian_aware| {
+            order_by_band(
+                std::slice::from_ref(&batch),
+                PartitionScheme::LonBands,
+                3,
+                antimeridian_aware,
+            )
+            .unwrap()
+            .unwrap()
+            .boundaries
+            .iter()
+            .map(|b| b.limit)
+            .collect::<Vec<_>>()
+        };
+
+        assert_eq!(limits(true), vec![0, 0, 1]);
+        // Planar math puts the centroid on the prime meridian
+        assert_eq!(limits(false), vec![0, 1, 0]);
+    }
+
     #[test]
     fn test_calculate_parts_from_max_size() {
         // Test with a scale factor that produces a known size
@@ -139,22 +1079,29 @@ mod tests {
 
         // Test case 1: file size larger than total - should return 1 part
         let output_file_size_mb = (total_size_mb * 2.0) as f32;
-        let parts = PartitionStrategy::calculate_parts_from_max_size(sf, output_file_size_mb);
+        let parts = PartitionStrategy::calculate_parts_from_max_size(sf, None, output_file_size_mb);
         assert_eq!(parts, 1);
 
         // Test case 2: file size exactly half of total - should return 2 parts
         let output_file_size_mb = (total_size_mb / 2.0) as f32;
-        let parts = PartitionStrategy::calculate_parts_from_max_size(sf, output_file_size_mb);
+        let parts = PartitionStrategy::calculate_parts_from_max_size(sf, None, output_file_size_mb);
         assert_eq!(parts, 2);
 
         // Test case 3: file size forces 3+ parts
         let output_file_size_mb = (total_size_mb / 3.5) as f32;
-        let parts = PartitionStrategy::calculate_parts_from_max_size(sf, output_file_size_mb);
+        let parts = PartitionStrategy::calculate_parts_from_max_size(sf, None, output_file_size_mb);
         assert_eq!(parts, 4); // ceil(3.5) = 4
 
         // Test case 5: very small file size
-        let parts = PartitionStrategy::calculate_parts_from_max_size(sf, 1.0);
+        let parts = PartitionStrategy::calculate_parts_from_max_size(sf, None, 1.0);
         assert!(parts > 1);
         assert_eq!(parts, (total_size_mb.round() as i32).max(1));
+
+        // A sample of a quarter of the rows needs a quarter of the parts
+        let (_, total_rows) = ZoneTableStats::base_stats(sf);
+        let output_file_size_mb = (total_size_mb / 8.0) as f32;
+        let rows = Some(total_rows as u64 / 4);
+        let parts = PartitionStrategy::calculate_parts_from_max_size(sf, rows, output_file_size_mb);
+        assert_eq!(parts, 2);
     }
 }
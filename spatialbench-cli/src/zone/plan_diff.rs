@@ -0,0 +1,282 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! A planned zone run compared with the manifest of an earlier one
+//!
+//! `--diff-against` plans the zone table without generating it: the options
+//! the run would record, the row count of every part and the columns of the
+//! files, taken from the schema the writer would be given. The plan is then
+//! compared with an earlier manifest, so a changed configuration can be
+//! checked before regenerating.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::manifest::{ManifestColumn, ZoneManifest};
+
+/// What a zone run would write, without its files
+#[derive(Clone, Debug, PartialEq)]
+pub struct ZonePlan {
+    /// The manifest the run would record, without any files
+    pub manifest: ZoneManifest,
+    pub total_rows: u64,
+    /// Rows of every part, `None` when the parts are cut from the
+    /// collected table, by vertex count or by bands
+    pub part_rows: Option<Vec<u64>>,
+}
+
+/// Row counts of a part that differ between the manifest and the plan
+#[derive(Clone, Debug, PartialEq)]
+pub struct PartRows {
+    pub part: i32,
+    /// `None` for a part only one side has
+    pub old: Option<u64>,
+    pub new: Option<u64>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ColumnChange {
+    Added(ManifestColumn),
+    Removed(ManifestColumn),
+    /// A column of the same name with another type or nullability
+    Changed {
+        old: ManifestColumn,
+        new: ManifestColumn,
+    },
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PlanDiff {
+    /// Recorded options that differ, worded as for the `--overwrite` check
+    pub options: Vec<String>,
+    /// Total rows of the manifest and of the plan
+    pub total_rows: (u64, u64),
+    pub parts: Vec<PartRows>,
+    /// Schema fingerprints of the manifest and of the plan when they
+    /// differ, the first `None` when the manifest has no columns recorded
+    pub fingerprints: Option<(Option<String>, String)>,
+    pub columns: Vec<ColumnChange>,
+}
+
+impl PlanDiff {
+    pub fn is_empty(&self) -> bool {
+        self.options.is_empty()
+            && self.total_rows.0 == self.total_rows.1
+            && self.parts.is_empty()
+            && self.fingerprints.is_none()
+    }
+}
+
+/// Compares `plan` with the manifest `old` of an earlier run
+pub fn diff_plan(old: &ZoneManifest, plan: &ZonePlan) -> PlanDiff {
+    let new = &plan.manifest;
+    let mut options = old.differences(new);
+    if old.parts != new.parts {
+        options.push(format!(
+            "parts: {} in the existing outputs, {} requested",
+            old.parts, new.parts
+        ));
+    }
+    if let (Some(existing), Some(version)) = (&old.schema_version, &new.schema_version) {
+        if existing != version {
+            options.push(format!(
+                "schema version: {existing} in the existing outputs, {version} requested"
+            ));
+        }
+    }
+
+    let mut old_rows: BTreeMap<i32, u64> = BTreeMap::new();
+    for file in &old.files {
+        *old_rows.entry(file.part).or_default() += file.rows;
+    }
+    let parts = match &plan.part_rows {
+        Some(part_rows) => {
+            let new_rows: BTreeMap<i32, u64> = (1..).zip(part_rows.iter().copied()).collect();
+            let part_numbers: BTreeSet<i32> =
+                old_rows.keys().chain(new_rows.keys()).copied().collect();
+            part_numbers
+                .into_iter()
+                .map(|part| PartRows {
+                    part,
+                    old: old_rows.get(&part).copied(),
+                    new: new_rows.get(&part).copied(),
+                })
+                .filter(|rows| rows.old != rows.new)
+                .collect()
+        }
+        None => Vec::new(),
+    };
+
+    let planned = new.schema_fingerprint().unwrap_or_default();
+    let (fingerprints, columns) = match (&old.columns, &new.columns) {
+        (Some(old_columns), Some(new_columns)) if old_columns != new_columns => (
+            Some((old.schema_fingerprint(), planned)),
+            column_changes(old_columns, new_columns),
+        ),
+        (None, _) => (Some((None, planned)), Vec::new()),
+        _ => (None, Vec::new()),
+    };
+
+    PlanDiff {
+        options,
+        total_rows: (old_rows.values().sum(), plan.total_rows),
+        parts,
+        fingerprints,
+        columns,
+    }
+}
+
+/// The columns added, removed and changed from `old` to `new`, in the
+/// order of `new` then of the removed columns in `old`. Only the order
+/// changed when this is empty for differing lists.
+fn column_changes(old: &[ManifestColumn], new: &[ManifestColumn]) -> Vec<ColumnChange> {
+    let find = |columns: &[ManifestColumn], name: &str| {
+        columns.iter().find(|column| column.name == name).cloned()
+    };
+    let mut changes: Vec<ColumnChange> = new
+        .iter()
+        .filter_map(|column| match find(old, &column.name) {
+            None => Some(ColumnChange::Added(column.clone())),
+            Some(existing) if existing != *column => Some(ColumnChange::Changed {
+                old: existing,
+                new: column.clone(),
+            }),
+            Some(_) => None,
+        })
+        .collect();
+    changes.extend(
+        old.iter()
+            .filter(|column| find(new, &column.name).is_none())
+            .map(|column| ColumnChange::Removed(column.clone())),
+    );
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zone::manifest::ManifestPart;
+
+    fn column(name: &str, data_type: &str) -> ManifestColumn {
+        ManifestColumn {
+            name: name.to_string(),
+            data_type: data_type.to_string(),
+            nullable: true,
+        }
+    }
+
+    fn manifest(part_rows: &[u64], columns: Vec<ManifestColumn>) -> ZoneManifest {
+        let mut manifest = ZoneManifest::new(1.0, part_rows.len() as i32)
+            .with_seed(Some(1))
+            .with_schema_version(Some("1".to_string()))
+            .with_columns(Some(columns));
+        for (part, rows) in (1..).zip(part_rows) {
+            manifest.replace_part(
+                part,
+                vec![ManifestPart {
+                    part,
+                    path: format!("zone/zone.{part}.parquet"),
+                    rows: *rows,
+                    content_sha256: "0".repeat(64),
+                    file_index: None,
+                    row_offset: None,
+                    footer_bytes: None,
+                }],
+            );
+        }
+        manifest
+    }
+
+    fn plan(part_rows: &[u64], columns: Vec<ManifestColumn>) -> ZonePlan {
+        let mut manifest = manifest(part_rows, columns);
+        manifest.files.clear();
+        ZonePlan {
+            manifest,
+            total_rows: part_rows.iter().sum(),
+            part_rows: Some(part_rows.to_vec()),
+        }
+    }
+
+    #[test]
+    fn test_same_plan_has_no_differences() {
+        let columns = vec![column("z_zonekey", "Int64"), column("z_name", "Utf8View")];
+        let diff = diff_plan(&manifest(&[3, 2], columns.clone()), &plan(&[3, 2], columns));
+        assert!(diff.is_empty(), "{diff:?}");
+        assert_eq!(diff.total_rows, (5, 5));
+    }
+
+    #[test]
+    fn test_reports_options_rows_and_columns() {
+        let old = manifest(
+            &[3, 2],
+            vec![column("z_zonekey", "Int64"), column("z_name", "Utf8View")],
+        );
+        let mut new = plan(
+            &[2, 2, 2],
+            vec![column("z_zonekey", "Utf8"), column("z_area", "Float64")],
+        );
+        new.manifest.seed = Some(2);
+
+        let diff = diff_plan(&old, &new);
+        assert_eq!(
+            diff.options,
+            vec![
+                "seed: 1 in the existing outputs, 2 requested",
+                "parts: 2 in the existing outputs, 3 requested",
+            ]
+        );
+        assert_eq!(diff.total_rows, (5, 6));
+        assert_eq!(
+            diff.parts,
+            vec![
+                PartRows {
+                    part: 1,
+                    old: Some(3),
+                    new: Some(2)
+                },
+                PartRows {
+                    part: 3,
+                    old: None,
+                    new: Some(2)
+                },
+            ]
+        );
+        let (old_fingerprint, new_fingerprint) = diff.fingerprints.clone().unwrap();
+        assert_ne!(old_fingerprint.unwrap(), new_fingerprint);
+        assert_eq!(
+            diff.columns,
+            vec![
+                ColumnChange::Changed {
+                    old: column("z_zonekey", "Int64"),
+                    new: column("z_zonekey", "Utf8"),
+                },
+                ColumnChange::Added(column("z_area", "Float64")),
+                ColumnChange::Removed(column("z_name", "Utf8View")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unrecorded_columns_differ() {
+        let columns = vec![column("z_zonekey", "Int64")];
+        let mut old = manifest(&[1], columns.clone());
+        old.columns = None;
+        let diff = diff_plan(&old, &plan(&[1], columns));
+        assert!(!diff.is_empty());
+        assert!(diff.columns.is_empty());
+        assert_eq!(diff.fingerprints.unwrap().0, None);
+    }
+}
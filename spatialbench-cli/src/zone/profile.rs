@@ -0,0 +1,260 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Cardinality and distribution profile of a generated zone dataset
+
+use anyhow::{anyhow, Result};
+use arrow::compute::cast;
+use arrow_array::{Array, BinaryArray, RecordBatch, StringArray};
+use arrow_schema::DataType;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::path::Path;
+
+use super::extra_columns::{geometry_type_name, vertex_count};
+use super::verify::discover_files;
+
+/// Text columns whose value distribution is profiled
+pub const PROFILED_COLUMNS: &[&str] = &["z_country", "z_region", "z_subtype"];
+
+const GEOMETRY_COLUMN: &str = "z_boundary";
+
+#[derive(Debug, Serialize)]
+pub struct ProfileReport {
+    pub rows: u64,
+    pub columns: BTreeMap<String, ColumnProfile>,
+    /// Geometry vertex counts in power of two buckets
+    pub vertex_histogram: Vec<HistogramBucket>,
+    /// Geometries by type name, e.g. `MultiPolygon`
+    pub geometry_types: BTreeMap<String, u64>,
+    /// Geometries that are null or not valid WKB
+    pub unreadable_geometries: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ColumnProfile {
+    pub distinct: usize,
+    pub nulls: u64,
+    /// Most frequent values, ties broken by value
+    pub top: Vec<ValueCount>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ValueCount {
+    pub value: String,
+    pub count: u64,
+}
+
+/// Geometries with `min..=max` vertices
+#[derive(Debug, PartialEq, Serialize)]
+pub struct HistogramBucket {
+    pub min: u64,
+    pub max: u64,
+    pub count: u64,
+}
+
+#[derive(Default)]
+struct Profiler {
+    rows: u64,
+    values: BTreeMap<String, HashMap<String, u64>>,
+    nulls: BTreeMap<String, u64>,
+    /// Counts keyed by bucket index, bucket `i` holding `2^(i-1)+1..=2^i`
+    vertex_buckets: BTreeMap<u32, u64>,
+    geometry_types: BTreeMap<String, u64>,
+    unreadable_geometries: u64,
+}
+
+/// Profiles every zone file in `data_dir`, keeping the `top` most frequent
+/// values per column
+pub fn profile_dir(data_dir: &Path, top: usize) -> Result<ProfileReport> {
+    let files = discover_files(data_dir)?;
+    if files.is_empty() {
+        return Err(anyhow!("No zone files found in {}", data_dir.display()));
+    }
+
+    let mut profiler = Profiler::default();
+    for file in files.values() {
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&file.path)?)?.build()?;
+        for batch in reader {
+            profiler.update(&batch?)?;
+        }
+    }
+    Ok(profiler.finish(top))
+}
+
+impl Profiler {
+    fn update(&mut self, batch: &RecordBatch) -> Result<()> {
+        self.rows += batch.num_rows() as u64;
+
+        for name in PROFILED_COLUMNS {
+            let Some(column) = batch.column_by_name(name) else {
+                continue;
+            };
+            let column = cast(column, &DataType::Utf8)?;
+            let column = column.as_any().downcast_ref::<StringArray>().unwrap();
+            let counts = self.values.entry(name.to_string()).or_default();
+            for value in column.iter() {
+                match value {
+                    Some(value) => *counts.entry(value.to_string()).or_default() += 1,
+                    None => *self.nulls.entry(name.to_string()).or_default() += 1,
+                }
+            }
+        }
+
+        if let Some(geometries) = batch.column_by_name(GEOMETRY_COLUMN) {
+            let geometries = cast(geometries, &DataType::Binary)?;
+            let geometries = geometries.as_any().downcast_ref::<BinaryArray>().unwrap();
+            for wkb in geometries.iter() {
+                let header =
+                    wkb.and_then(|wkb| Some((vertex_count(wkb)?, geometry_type_name(wkb)?)));
+                match header {
+                    Some((vertices, geometry_type)) => {
+                        let bucket = (vertices as u64).next_power_of_two().ilog2();
+                        *self.vertex_buckets.entry(bucket).or_default() += 1;
+                        *self
+                            .geometry_types
+                            .entry(geometry_type.to_string())
+                            .or_default() += 1;
+                    }
+                    None => self.unreadable_geometries += 1,
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self, top: usize) -> ProfileReport {
+        let columns = self
+            .values
+            .into_iter()
+            .map(|(name, counts)| {
+                let mut values: Vec<ValueCount> = counts
+                    .into_iter()
+                    .map(|(value, count)| ValueCount { value, count })
+                    .collect();
+                values.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.value.cmp(&b.value)));
+                let profile = ColumnProfile {
+                    distinct: values.len(),
+                    nulls: self.nulls.get(&name).copied().unwrap_or(0),
+                    top: values.into_iter().take(top).collect(),
+                };
+                (name, profile)
+            })
+            .collect();
+
+        let vertex_histogram = self
+            .vertex_buckets
+            .into_iter()
+            .map(|(bucket, count)| HistogramBucket {
+                min: if bucket == 0 {
+                    0
+                } else {
+                    (1u64 << (bucket - 1)) + 1
+                },
+                max: 1u64 << bucket,
+                count,
+            })
+            .collect();
+
+        ProfileReport {
+            rows: self.rows,
+            columns,
+            vertex_histogram,
+            geometry_types: self.geometry_types,
+            unreadable_geometries: self.unreadable_geometries,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zone::fixtures::{wkb_polygon, write_parquet};
+    use arrow_schema::{Field, Schema};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_profile_distinct_counts() {
+        let dir = tempfile::tempdir().unwrap();
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("z_country", DataType::Utf8, true),
+            Field::new("z_region", DataType::Utf8, true),
+            Field::new("z_subtype", DataType::Utf8, true),
+            Field::new("z_boundary", DataType::Binary, true),
+        ]));
+        let square = wkb_polygon(&[(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0), (0.0, 0.0)]);
+        let triangle = wkb_polygon(&[(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 0.0)]);
+        let batch = RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(StringArray::from(vec!["US", "US", "CA", "US"])),
+                Arc::new(StringArray::from(vec![
+                    Some("US-WA"),
+                    Some("US-OR"),
+                    Some("CA-BC"),
+                    None,
+                ])),
+                Arc::new(StringArray::from(vec!["county"; 4])),
+                Arc::new(BinaryArray::from(vec![
+                    Some(square.as_slice()),
+                    Some(square.as_slice()),
+                    Some(triangle.as_slice()),
+                    None,
+                ])),
+            ],
+        )
+        .unwrap();
+        write_parquet(&dir.path().join("zone.parquet"), &batch);
+
+        let report = profile_dir(dir.path(), 1).unwrap();
+        assert_eq!(report.rows, 4);
+        assert_eq!(report.columns["z_country"].distinct, 2);
+        assert_eq!(
+            report.columns["z_country"].top,
+            vec![ValueCount {
+                value: "US".to_string(),
+                count: 3
+            }]
+        );
+        assert_eq!(report.columns["z_region"].distinct, 3);
+        assert_eq!(report.columns["z_region"].nulls, 1);
+        assert_eq!(report.columns["z_subtype"].distinct, 1);
+
+        assert_eq!(
+            report.vertex_histogram,
+            vec![
+                HistogramBucket {
+                    min: 3,
+                    max: 4,
+                    count: 1
+                },
+                HistogramBucket {
+                    min: 5,
+                    max: 8,
+                    count: 2
+                },
+            ]
+        );
+        assert_eq!(
+            report.geometry_types,
+            BTreeMap::from([("Polygon".to_string(), 3)])
+        );
+        assert_eq!(report.unreadable_geometries, 1);
+    }
+}
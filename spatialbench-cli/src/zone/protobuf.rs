@@ -0,0 +1,146 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Protobuf wire format encoding shared by the ORC file tail and the
+//! vector tiles
+
+/// A protobuf message, encoded as its fields are added
+#[derive(Default)]
+pub(super) struct Message(pub(super) Vec<u8>);
+
+impl Message {
+    pub(super) fn key(mut self, field: u32, wire_type: u64) -> Self {
+        put_varint(&mut self.0, ((field as u64) << 3) | wire_type);
+        self
+    }
+
+    pub(super) fn uint(self, field: u32, value: u64) -> Self {
+        let mut message = self.key(field, 0);
+        put_varint(&mut message.0, value);
+        message
+    }
+
+    pub(super) fn sint(self, field: u32, value: i64) -> Self {
+        self.uint(field, zigzag(value))
+    }
+
+    pub(super) fn double(self, field: u32, value: f64) -> Self {
+        let mut message = self.key(field, 1);
+        message.0.extend_from_slice(&value.to_le_bytes());
+        message
+    }
+
+    pub(super) fn bytes(self, field: u32, value: &[u8]) -> Self {
+        let mut message = self.key(field, 2);
+        put_varint(&mut message.0, value.len() as u64);
+        message.0.extend_from_slice(value);
+        message
+    }
+
+    pub(super) fn message(self, field: u32, message: Message) -> Self {
+        self.bytes(field, &message.0)
+    }
+
+    pub(super) fn packed(self, field: u32, values: impl IntoIterator<Item = u64>) -> Self {
+        let mut packed = Vec::new();
+        for value in values {
+            put_varint(&mut packed, value);
+        }
+        self.bytes(field, &packed)
+    }
+}
+
+pub(super) fn put_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+pub(super) fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+/// Decoding of protobuf messages, for tests
+#[cfg(test)]
+pub(super) mod decode {
+    use anyhow::{anyhow, Context, Result};
+
+    pub(crate) enum Value<'a> {
+        Varint(u64),
+        Bytes(&'a [u8]),
+    }
+
+    /// The fields of a protobuf message
+    pub(crate) fn fields(mut buf: &[u8]) -> Result<Vec<(u32, Value<'_>)>> {
+        let mut fields = Vec::new();
+        while !buf.is_empty() {
+            let key = varint(&mut buf)?;
+            let value = match key & 7 {
+                0 => Value::Varint(varint(&mut buf)?),
+                1 => {
+                    let (value, rest) = buf.split_at(8);
+                    buf = rest;
+                    Value::Bytes(value)
+                }
+                2 => {
+                    let length = varint(&mut buf)? as usize;
+                    let (value, rest) = buf.split_at(length);
+                    buf = rest;
+                    Value::Bytes(value)
+                }
+                other => return Err(anyhow!("Unexpected wire type {other}")),
+            };
+            fields.push(((key >> 3) as u32, value));
+        }
+        Ok(fields)
+    }
+
+    pub(crate) fn uint(fields: &[(u32, Value)], field: u32) -> u64 {
+        fields
+            .iter()
+            .find_map(|(f, v)| match (f, v) {
+                (f, Value::Varint(v)) if *f == field => Some(*v),
+                _ => None,
+            })
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn repeated<'a>(fields: &[(u32, Value<'a>)], field: u32) -> Vec<&'a [u8]> {
+        fields
+            .iter()
+            .filter_map(|(f, v)| match v {
+                Value::Bytes(bytes) if *f == field => Some(*bytes),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub(crate) fn varint(buf: &mut &[u8]) -> Result<u64> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let (&byte, rest) = buf.split_first().context("Truncated varint")?;
+            *buf = rest;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte < 0x80 {
+                return Ok(value);
+            }
+        }
+        Err(anyhow!("Varint too long"))
+    }
+}
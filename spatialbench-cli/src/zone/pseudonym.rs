@@ -0,0 +1,331 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Synthetic replacements for the zone name columns
+//!
+//! Every name is replaced by a string derived from the seed, the row's
+//! `z_gersid` and the language of the name, with roughly the length of the
+//! name it replaces. Pseudonyms therefore don't depend on row order or
+//! parts, and `z_name_<lang>` matches the `<lang>` entry of
+//! `z_name_common`. Empty names stay empty.
+
+use anyhow::{anyhow, Result};
+use arrow::compute::cast;
+use arrow_array::{RecordBatch, StringArray};
+use arrow_schema::DataType;
+use std::collections::BTreeMap;
+
+use super::config::PseudonymStyle;
+use super::names::NAME_COMMON_COLUMN;
+use super::synthetic::{fnv1a, splitmix64};
+
+/// Shortest hex suffix of a [`PseudonymStyle::Hex`] name
+const MIN_HEX_DIGITS: usize = 6;
+
+const VOWELS: &[(char, u32)] = &[('a', 8), ('e', 12), ('i', 7), ('o', 7), ('u', 3), ('y', 1)];
+
+const CONSONANTS: &[(char, u32)] = &[
+    ('b', 2),
+    ('c', 3),
+    ('d', 4),
+    ('f', 2),
+    ('g', 2),
+    ('h', 4),
+    ('k', 1),
+    ('l', 4),
+    ('m', 3),
+    ('n', 7),
+    ('p', 2),
+    ('r', 6),
+    ('s', 6),
+    ('t', 8),
+    ('v', 1),
+    ('w', 2),
+    ('z', 1),
+];
+
+/// Replaces `z_name`, the `z_name_<lang>` columns and the values of
+/// `z_name_common` in every batch
+pub fn pseudonymize_names(
+    batches: Vec<RecordBatch>,
+    style: PseudonymStyle,
+    seed: u64,
+) -> Result<Vec<RecordBatch>> {
+    batches
+        .into_iter()
+        .map(|batch| {
+            let ids = batch
+                .column_by_name("z_gersid")
+                .ok_or_else(|| anyhow!("Pseudonymizing names needs a z_gersid column"))?;
+            let ids = cast(ids, &DataType::Utf8)?;
+            let ids = ids.as_any().downcast_ref::<StringArray>().unwrap();
+
+            let mut columns = batch.columns().to_vec();
+            for (index, field) in batch.schema().fields().iter().enumerate() {
+                let name = field.name().as_str();
+                let language = name_language(name);
+                if name != NAME_COMMON_COLUMN && language.is_none() {
+                    continue;
+                }
+                let values = cast(&columns[index], &DataType::Utf8)?;
+                let values = values.as_any().downcast_ref::<StringArray>().unwrap();
+                let replaced: StringArray = values
+                    .iter()
+                    .zip(ids.iter())
+                    .map(|(value, id)| {
+                        let (Some(value), id) = (value, id.unwrap_or("")) else {
+                            return Ok(None);
+                        };
+                        match language {
+                            Some(language) => Ok(Some(pseudonym(value, style, seed, id, language))),
+                            None => pseudonymize_json(value, style, seed, id).map(Some),
+                        }
+                    })
+                    .collect::<Result<_>>()?;
+                columns[index] = cast(&replaced, field.data_type())?;
+            }
+            Ok(RecordBatch::try_new(batch.schema(), columns)?)
+        })
+        .collect()
+}
+
+/// The language key of a name column: empty for `z_name`, the column
+/// suffix for `z_name_<lang>`
+fn name_language(column: &str) -> Option<&str> {
+    match column {
+        "z_name" => Some(""),
+        NAME_COMMON_COLUMN => None,
+        _ => column.strip_prefix("z_name_"),
+    }
+}
+
+/// Rewrites a `names.common` JSON object, keyed by the language tags the
+/// `z_name_<lang>` columns are named after
+fn pseudonymize_json(json: &str, style: PseudonymStyle, seed: u64, id: &str) -> Result<String> {
+    if json.is_empty() {
+        return Ok(String::new());
+    }
+    let names: BTreeMap<String, String> = serde_json::from_str(json)
+        .map_err(|e| anyhow!("Expected {NAME_COMMON_COLUMN} to be a JSON object: {e}"))?;
+    let names: BTreeMap<String, String> = names
+        .into_iter()
+        .map(|(language, name)| {
+            let key = language.to_ascii_lowercase().replace('-', "_");
+            let name = pseudonym(&name, style, seed, id, &key);
+            (language, name)
+        })
+        .collect();
+    Ok(serde_json::to_string(&names)?)
+}
+
+/// The pseudonym of `name`, the name in `language` of the zone `id`
+pub fn pseudonym(name: &str, style: PseudonymStyle, seed: u64, id: &str, language: &str) -> String {
+    if name.is_empty() {
+        return String::new();
+    }
+    let mut state =
+        splitmix64(seed ^ fnv1a(id.as_bytes()) ^ splitmix64(fnv1a(language.as_bytes())));
+    let mut next = || {
+        state = splitmix64(state);
+        state
+    };
+
+    match style {
+        PseudonymStyle::Hex => {
+            let digits = name.chars().count().saturating_sub(5).max(MIN_HEX_DIGITS);
+            let mut hex = String::with_capacity(digits);
+            while hex.len() < digits {
+                hex.push_str(&format!("{:016x}", next()));
+            }
+            hex.truncate(digits);
+            format!("Zone-{hex}")
+        }
+        PseudonymStyle::Pronounceable => name
+            .split(' ')
+            .filter(|word| !word.is_empty())
+            .map(|word| pronounceable_word(word.chars().count(), &mut next))
+            .collect::<Vec<_>>()
+            .join(" "),
+    }
+}
+
+/// A capitalized word of `len` letters. Runs of consonants and of vowels
+/// are at most two letters long, and a second letter of a run is less
+/// likely than a switch.
+fn pronounceable_word(len: usize, next: &mut impl FnMut() -> u64) -> String {
+    let mut word = String::with_capacity(len);
+    // Whether the current run is of vowels, and its length so far
+    let mut vowel = next() % 10 < 3;
+    let mut run = 1;
+    for i in 0..len {
+        if i > 0 {
+            let stay = match (vowel, run) {
+                (_, 2) => false,
+                (true, _) => next().is_multiple_of(4),
+                (false, _) => next().is_multiple_of(10),
+            };
+            if stay {
+                run += 1;
+            } else {
+                vowel = !vowel;
+                run = 1;
+            }
+        }
+        let letter = pick(if vowel { VOWELS } else { CONSONANTS }, next());
+        if i == 0 {
+            word.push(letter.to_ascii_uppercase());
+        } else {
+            word.push(letter);
+        }
+    }
+    word
+}
+
+fn pick(letters: &[(char, u32)], hash: u64) -> char {
+    let total: u32 = letters.iter().map(|(_, weight)| weight).sum();
+    let mut target = (hash % total as u64) as u32;
+    for &(letter, weight) in letters {
+        if target < weight {
+            return letter;
+        }
+        target -= weight;
+    }
+    unreachable!()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_schema::{Field, Schema};
+    use std::sync::Arc;
+
+    fn zones(ids: &[&str], names: &[&str], french: &[&str]) -> RecordBatch {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("z_gersid", DataType::Utf8, false),
+            Field::new("z_name", DataType::Utf8View, false),
+            Field::new("z_name_fr", DataType::Utf8, false),
+            Field::new("z_subtype", DataType::Utf8, false),
+        ]));
+        RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(StringArray::from(ids.to_vec())),
+                cast(&StringArray::from(names.to_vec()), &DataType::Utf8View).unwrap(),
+                Arc::new(StringArray::from(french.to_vec())),
+                Arc::new(StringArray::from(vec!["county"; ids.len()])),
+            ],
+        )
+        .unwrap()
+    }
+
+    fn column(batch: &RecordBatch, name: &str) -> Vec<String> {
+        let values = cast(batch.column_by_name(name).unwrap(), &DataType::Utf8).unwrap();
+        let values = values.as_any().downcast_ref::<StringArray>().unwrap();
+        values.iter().map(|v| v.unwrap().to_string()).collect()
+    }
+
+    #[test]
+    fn test_hex_pseudonyms() {
+        let name = pseudonym("Geneva", PseudonymStyle::Hex, 0, "g1", "");
+        assert_eq!(name.len(), "Zone-".len() + MIN_HEX_DIGITS);
+        assert!(name.starts_with("Zone-"), "{name}");
+        assert!(name[5..].chars().all(|c| c.is_ascii_hexdigit()), "{name}");
+
+        let long = "Census Tract 9801 of King County, Washington";
+        let name = pseudonym(long, PseudonymStyle::Hex, 0, "g1", "");
+        assert_eq!(name.len(), long.len());
+
+        assert_eq!(name, pseudonym(long, PseudonymStyle::Hex, 0, "g1", ""));
+        assert_ne!(name, pseudonym(long, PseudonymStyle::Hex, 1, "g1", ""));
+        assert_ne!(name, pseudonym(long, PseudonymStyle::Hex, 0, "g2", ""));
+        assert_ne!(name, pseudonym(long, PseudonymStyle::Hex, 0, "g1", "fr"));
+        assert_eq!(pseudonym("", PseudonymStyle::Hex, 0, "g1", ""), "");
+    }
+
+    #[test]
+    fn test_pronounceable_pseudonyms_keep_word_lengths() {
+        let name = pseudonym("King  County", PseudonymStyle::Pronounceable, 0, "g1", "");
+        let words: Vec<&str> = name.split(' ').collect();
+        assert_eq!(words.iter().map(|w| w.len()).collect::<Vec<_>>(), [4, 6]);
+        for word in words {
+            assert!(word.starts_with(|c: char| c.is_ascii_uppercase()), "{name}");
+            assert!(word[1..].chars().all(|c| c.is_ascii_lowercase()), "{name}");
+            // No three vowels or consonants in a row
+            let kinds: Vec<bool> = word
+                .to_ascii_lowercase()
+                .chars()
+                .map(|c| VOWELS.iter().any(|(v, _)| *v == c))
+                .collect();
+            assert!(
+                kinds.windows(3).all(|w| w[0] != w[1] || w[1] != w[2]),
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_pseudonymize_batches() {
+        let batch = zones(&["g1", "g2"], &["Geneva", ""], &["Genève", ""]);
+        let pseudonymized =
+            pseudonymize_names(vec![batch.clone()], PseudonymStyle::Hex, 7).unwrap();
+        let pseudonymized = &pseudonymized[0];
+
+        assert_eq!(pseudonymized.schema(), batch.schema());
+        let names = column(pseudonymized, "z_name");
+        assert_eq!(
+            names[0],
+            pseudonym("Geneva", PseudonymStyle::Hex, 7, "g1", "")
+        );
+        assert_eq!(names[1], "");
+        let french = column(pseudonymized, "z_name_fr");
+        assert_eq!(
+            french[0],
+            pseudonym("Genève", PseudonymStyle::Hex, 7, "g1", "fr")
+        );
+        assert_eq!(column(pseudonymized, "z_gersid"), ["g1", "g2"]);
+        assert_eq!(column(pseudonymized, "z_subtype"), ["county", "county"]);
+
+        // The same zone gets the same pseudonym in any batch
+        let single = zones(&["g1"], &["Geneva"], &["Genève"]);
+        let single = pseudonymize_names(vec![single], PseudonymStyle::Hex, 7).unwrap();
+        assert_eq!(column(&single[0], "z_name")[0], names[0]);
+    }
+
+    #[test]
+    fn test_pseudonymize_common_names() {
+        let json = pseudonymize_json(
+            r#"{"fr":"Genève","zh-Hant":"日內瓦"}"#,
+            PseudonymStyle::Hex,
+            0,
+            "g1",
+        )
+        .unwrap();
+        let names: BTreeMap<String, String> = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            names["fr"],
+            pseudonym("Genève", PseudonymStyle::Hex, 0, "g1", "fr")
+        );
+        assert_eq!(
+            names["zh-Hant"],
+            pseudonym("日內瓦", PseudonymStyle::Hex, 0, "g1", "zh_hant")
+        );
+        assert_eq!(
+            pseudonymize_json("", PseudonymStyle::Hex, 0, "g1").unwrap(),
+            ""
+        );
+    }
+}
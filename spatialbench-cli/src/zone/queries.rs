@@ -0,0 +1,311 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Spatial SQL workload over the zone table
+//!
+//! Every query is templated against the `z_*` columns of the numbered zone
+//! schema, reading `z_boundary` as the geometry the load scripts convert it
+//! to. The points and boxes the queries are parameterized with derive from
+//! the seed and the query name alone, so a seed always reproduces the same
+//! workload. The subtypes the intersection queries pick from are those
+//! selected at the scale factor.
+
+use anyhow::Result;
+use log::info;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use super::stats::ZoneTableStats;
+use super::synthetic::{fnv1a, splitmix64};
+
+/// Latitudes points and boxes are drawn from, leaving out the polar regions
+/// that hold hardly any zones
+const LATITUDES: (f64, f64) = (-60.0, 75.0);
+
+/// Side lengths in degrees of the boxes the queries are restricted to
+const BOX_DEGREES: (f64, f64) = (0.5, 5.0);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QueryKind {
+    /// Zones containing a point
+    PointInZone,
+    /// Pairs of intersecting zones of one subtype within a box
+    ZoneIntersection,
+    /// Zone count and area by country within a box
+    CountryAggregation,
+}
+
+impl QueryKind {
+    pub const ALL: [QueryKind; 3] = [
+        QueryKind::PointInZone,
+        QueryKind::ZoneIntersection,
+        QueryKind::CountryAggregation,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            QueryKind::PointInZone => "point_in_zone",
+            QueryKind::ZoneIntersection => "zone_intersection",
+            QueryKind::CountryAggregation => "country_aggregation",
+        }
+    }
+}
+
+impl fmt::Display for QueryKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// One query of the workload
+#[derive(Clone, Debug, PartialEq)]
+pub struct Query {
+    pub kind: QueryKind,
+    /// File stem, such as `point_in_zone_01`
+    pub name: String,
+    pub sql: String,
+}
+
+/// Generates `per_kind` queries of every kind for `scale_factor`
+pub fn generate_queries(scale_factor: f64, seed: u64, per_kind: usize) -> Vec<Query> {
+    let subtypes = ZoneTableStats::new(scale_factor, None).subtypes();
+    let mut queries = Vec::with_capacity(per_kind * QueryKind::ALL.len());
+    for kind in QueryKind::ALL {
+        for number in 1..=per_kind {
+            let name = format!("{kind}_{number:02}");
+            let mut params = Params::new(seed, &name);
+            let body = match kind {
+                QueryKind::PointInZone => point_in_zone(params.point()),
+                QueryKind::ZoneIntersection => {
+                    let subtype = subtypes[params.index(subtypes.len())];
+                    zone_intersection(subtype, &params.bbox())
+                }
+                QueryKind::CountryAggregation => country_aggregation(&params.bbox()),
+            };
+            let sql = format!(
+                "-- {name}: scale factor {scale_factor}, seed {seed}\n{}\n",
+                body.trim()
+            );
+            queries.push(Query { kind, name, sql });
+        }
+    }
+    queries
+}
+
+/// Writes the queries of [`generate_queries`] as `<name>.sql` files into
+/// `dir`, returning their paths
+pub fn write_queries(
+    dir: &Path,
+    scale_factor: f64,
+    seed: u64,
+    per_kind: usize,
+) -> Result<Vec<PathBuf>> {
+    std::fs::create_dir_all(dir)?;
+    let mut written = Vec::new();
+    for query in generate_queries(scale_factor, seed, per_kind) {
+        let path = dir.join(format!("{}.sql", query.name));
+        std::fs::write(&path, &query.sql)?;
+        written.push(path);
+    }
+    info!("Wrote {} queries to {}", written.len(), dir.display());
+    Ok(written)
+}
+
+fn point_in_zone((lon, lat): (f64, f64)) -> String {
+    format!(
+        "
+SELECT z_zonekey, z_name, z_subtype, z_country
+FROM zone
+WHERE ST_Contains(z_boundary, ST_Point({lon}, {lat}))
+ORDER BY z_zonekey;"
+    )
+}
+
+fn zone_intersection(subtype: &str, bbox: &str) -> String {
+    format!(
+        "
+SELECT a.z_zonekey, b.z_zonekey AS other_zonekey, b.z_subtype AS other_subtype
+FROM zone a
+JOIN zone b
+  ON a.z_zonekey <> b.z_zonekey
+  AND ST_Intersects(a.z_boundary, b.z_boundary)
+WHERE a.z_subtype = '{subtype}'
+  AND ST_Intersects(a.z_boundary, ST_GeomFromText('{bbox}'))
+ORDER BY a.z_zonekey, other_zonekey;"
+    )
+}
+
+fn country_aggregation(bbox: &str) -> String {
+    format!(
+        "
+SELECT z_country, COUNT(*) AS zones, SUM(ST_Area(z_boundary)) AS area
+FROM zone
+WHERE ST_Intersects(z_boundary, ST_GeomFromText('{bbox}'))
+GROUP BY z_country
+ORDER BY zones DESC, z_country;"
+    )
+}
+
+/// Random parameters of one query
+struct Params {
+    state: u64,
+}
+
+impl Params {
+    fn new(seed: u64, name: &str) -> Self {
+        Self {
+            state: splitmix64(seed ^ fnv1a(name.as_bytes())),
+        }
+    }
+
+    /// Uniform in `[0, 1)`
+    fn next(&mut self) -> f64 {
+        self.state = splitmix64(self.state);
+        (self.state >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Uniform in `[min, max)`, rounded to micro degrees so the SQL text
+    /// stays short
+    fn between(&mut self, (min, max): (f64, f64)) -> f64 {
+        ((min + self.next() * (max - min)) * 1e6).round() / 1e6
+    }
+
+    fn index(&mut self, len: usize) -> usize {
+        ((self.next() * len as f64) as usize).min(len - 1)
+    }
+
+    fn point(&mut self) -> (f64, f64) {
+        (self.between((-180.0, 180.0)), self.between(LATITUDES))
+    }
+
+    /// A box as WKT, kept within the valid longitudes and latitudes
+    fn bbox(&mut self) -> String {
+        let width = self.between(BOX_DEGREES);
+        let height = self.between(BOX_DEGREES);
+        let west = self.between((-180.0, 180.0 - width));
+        let south = self.between((LATITUDES.0, LATITUDES.1 - height));
+        let east = ((west + width) * 1e6).round() / 1e6;
+        let north = ((south + height) * 1e6).round() / 1e6;
+        format!(
+            "POLYGON(({west} {south}, {east} {south}, {east} {north}, {west} {north}, \
+             {west} {south}))"
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::sql::parser::DFParser;
+    use std::collections::BTreeSet;
+
+    /// Columns of the numbered zone schema
+    fn schema_columns() -> BTreeSet<String> {
+        let schema: serde_json::Value =
+            serde_json::from_str(include_str!("schemas/zone_v1.json")).unwrap();
+        schema
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|field| field["name"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    /// Every `z_*` identifier in `sql`
+    fn referenced_columns(sql: &str) -> BTreeSet<String> {
+        sql.split(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .filter(|word| word.starts_with("z_"))
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn test_queries_reference_schema_columns_and_parse() {
+        let columns = schema_columns();
+        let queries = generate_queries(1.0, 42, 3);
+        assert_eq!(queries.len(), 9);
+        for kind in QueryKind::ALL {
+            assert_eq!(queries.iter().filter(|q| q.kind == kind).count(), 3);
+        }
+        for query in &queries {
+            let referenced = referenced_columns(&query.sql);
+            assert!(!referenced.is_empty(), "{}", query.sql);
+            assert!(
+                referenced.is_subset(&columns),
+                "{} references {:?}, not in the zone schema",
+                query.name,
+                referenced.difference(&columns).collect::<Vec<_>>()
+            );
+            let statements = DFParser::parse_sql(&query.sql)
+                .unwrap_or_else(|e| panic!("{} doesn't parse: {e}\n{}", query.name, query.sql));
+            assert_eq!(statements.len(), 1, "{}", query.name);
+        }
+    }
+
+    #[test]
+    fn test_queries_are_reproducible_from_the_seed() {
+        assert_eq!(generate_queries(1.0, 7, 2), generate_queries(1.0, 7, 2));
+        assert_ne!(generate_queries(1.0, 7, 2), generate_queries(1.0, 8, 2));
+
+        // More queries only append, the first keep their parameters
+        let more = generate_queries(1.0, 7, 4);
+        for query in generate_queries(1.0, 7, 2) {
+            assert!(more.contains(&query), "{}", query.name);
+        }
+    }
+
+    #[test]
+    fn test_intersection_subtypes_follow_scale_factor() {
+        let subtypes_in = |scale_factor: f64| -> BTreeSet<String> {
+            generate_queries(scale_factor, 1, 50)
+                .iter()
+                .filter(|q| q.kind == QueryKind::ZoneIntersection)
+                .map(|q| {
+                    let (_, rest) = q.sql.split_once("a.z_subtype = '").unwrap();
+                    rest.split('\'').next().unwrap().to_string()
+                })
+                .collect()
+        };
+        let allowed: BTreeSet<String> = ZoneTableStats::new(1.0, None)
+            .subtypes()
+            .into_iter()
+            .map(str::to_string)
+            .collect();
+        assert!(subtypes_in(1.0).is_subset(&allowed));
+        assert!(subtypes_in(100.0).contains("locality"));
+    }
+
+    #[test]
+    fn test_write_queries() {
+        let dir = tempfile::tempdir().unwrap();
+        let written = write_queries(&dir.path().join("queries"), 1.0, 0, 1).unwrap();
+        let names: Vec<_> = written
+            .iter()
+            .map(|p| p.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "point_in_zone_01.sql",
+                "zone_intersection_01.sql",
+                "country_aggregation_01.sql"
+            ]
+        );
+        let sql = std::fs::read_to_string(&written[0]).unwrap();
+        assert!(sql.starts_with("-- point_in_zone_01: scale factor 1, seed 0\n"));
+    }
+}
@@ -0,0 +1,187 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Renaming of `z_region` values from a user supplied lookup file
+
+use anyhow::{anyhow, Result};
+use arrow_array::RecordBatch;
+use log::info;
+use std::collections::HashMap;
+use std::path::Path;
+
+use super::batch::map_string_column;
+
+/// `from -> to` replacements for `z_region`
+#[derive(Clone, Debug, Default)]
+pub struct RegionMap {
+    entries: HashMap<String, String>,
+    case_insensitive: bool,
+}
+
+impl RegionMap {
+    /// Loads a two column `from,to` CSV file.
+    ///
+    /// Blank lines are skipped, as is a leading `from,to` header. Values may
+    /// be wrapped in double quotes.
+    pub fn from_csv(path: &Path, case_insensitive: bool) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("Failed reading region map {}: {e}", path.display()))?;
+        Self::parse(&text, case_insensitive)
+            .map_err(|e| anyhow!("Invalid region map {}: {e}", path.display()))
+    }
+
+    pub fn parse(text: &str, case_insensitive: bool) -> Result<Self> {
+        let mut map = Self {
+            entries: HashMap::new(),
+            case_insensitive,
+        };
+
+        for (i, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (from, to) = line
+                .split_once(',')
+                .ok_or_else(|| anyhow!("line {}: expected `from,to`", i + 1))?;
+            let (from, to) = (unquote(from), unquote(to));
+            if i == 0 && from.eq_ignore_ascii_case("from") && to.eq_ignore_ascii_case("to") {
+                continue;
+            }
+            if from.is_empty() {
+                return Err(anyhow!("line {}: empty `from` value", i + 1));
+            }
+
+            let key = map.key(from);
+            if map.entries.insert(key, to.to_string()).is_some() {
+                return Err(anyhow!("line {}: duplicate mapping for {from:?}", i + 1));
+            }
+        }
+
+        Ok(map)
+    }
+
+    fn key(&self, value: &str) -> String {
+        if self.case_insensitive {
+            value.to_lowercase()
+        } else {
+            value.to_string()
+        }
+    }
+
+    pub fn get(&self, value: &str) -> Option<&str> {
+        self.entries.get(&self.key(value)).map(String::as_str)
+    }
+
+    /// Rewrites `z_region` in every batch, returning how many rows changed
+    pub fn apply(&self, batches: Vec<RecordBatch>) -> Result<(Vec<RecordBatch>, usize)> {
+        let mut mapped = 0;
+        let batches = batches
+            .iter()
+            .map(|batch| {
+                map_string_column(batch, "z_region", |value| {
+                    let to = self.get(value)?;
+                    if to == value {
+                        return None;
+                    }
+                    mapped += 1;
+                    Some(to.to_string())
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        info!(
+            "Mapped z_region for {mapped} row(s) using {} region mapping(s)",
+            self.entries.len()
+        );
+        Ok((batches, mapped))
+    }
+}
+
+fn unquote(value: &str) -> &str {
+    let value = value.trim();
+    value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_array::{Array, StringArray};
+    use arrow_schema::{DataType, Field, Schema};
+    use std::sync::Arc;
+
+    fn regions(values: Vec<&str>) -> RecordBatch {
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "z_region",
+            DataType::Utf8,
+            false,
+        )]));
+        RecordBatch::try_new(schema, vec![Arc::new(StringArray::from(values))]).unwrap()
+    }
+
+    fn values(batch: &RecordBatch) -> Vec<String> {
+        let array = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        (0..array.len())
+            .map(|i| array.value(i).to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_region_map_only_changes_listed_regions() {
+        let map =
+            RegionMap::parse("from,to\nWashington,US-WA\n\"Oregon\",\"US-OR\"\n", false).unwrap();
+        let (batches, mapped) = map
+            .apply(vec![regions(vec![
+                "Washington",
+                "washington",
+                "Oregon",
+                "US-CA",
+                "",
+            ])])
+            .unwrap();
+
+        assert_eq!(mapped, 2);
+        assert_eq!(
+            values(&batches[0]),
+            vec!["US-WA", "washington", "US-OR", "US-CA", ""]
+        );
+    }
+
+    #[test]
+    fn test_region_map_case_insensitive() {
+        let map = RegionMap::parse("Washington,US-WA\n", true).unwrap();
+        let (batches, mapped) = map
+            .apply(vec![regions(vec!["WASHINGTON", "washington", "Oregon"])])
+            .unwrap();
+
+        assert_eq!(mapped, 2);
+        assert_eq!(values(&batches[0]), vec!["US-WA", "US-WA", "Oregon"]);
+    }
+
+    #[test]
+    fn test_region_map_rejects_malformed_lines() {
+        assert!(RegionMap::parse("Washington\n", false).is_err());
+        assert!(RegionMap::parse("a,b\na,c\n", false).is_err());
+        assert!(RegionMap::parse("A,b\na,c\n", true).is_err());
+    }
+}
@@ -0,0 +1,183 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Country stratified sampling of the zone source
+//!
+//! Every country first gets up to `min_per_country` rows, and the rest of
+//! the sample is shared among the countries in proportion to the rows they
+//! have left. Within a country the rows with the smallest hash of the seed
+//! and their `id` are taken. Quotas depend only on the row count of each
+//! country and ranks only on the ids, so the sample is the same set of
+//! rows whatever the source order, and parts are cut from it afterwards.
+
+use anyhow::{anyhow, Result};
+use arrow_array::{Array, Int64Array, StringArray};
+use arrow_schema::DataType;
+use datafusion::functions_aggregate::expr_fn::count;
+use datafusion::functions_window::expr_fn::row_number;
+use datafusion::logical_expr::{case, ExprFunctionExt};
+use datafusion::prelude::*;
+use log::info;
+use std::collections::BTreeMap;
+
+const RANK_COLUMN: &str = "__country_rank";
+
+/// Rows to sample from each country, given the rows of each country.
+///
+/// Every country gets `min(rows, min_per_country)`, even when that adds up
+/// to more than `total`. The remaining rows are split in proportion to the
+/// rows each country has beyond its minimum, rounding by largest remainder
+/// with ties going to the first country by code.
+pub fn country_quotas(
+    counts: &BTreeMap<String, u64>,
+    total: u64,
+    min_per_country: u64,
+) -> BTreeMap<String, u64> {
+    let mut quotas: BTreeMap<String, u64> = counts
+        .iter()
+        .map(|(country, &rows)| (country.clone(), rows.min(min_per_country)))
+        .collect();
+    let assigned: u64 = quotas.values().sum();
+    let spare: u64 = counts.values().sum::<u64>() - assigned;
+    let remainder = total.saturating_sub(assigned);
+    if remainder == 0 || spare == 0 {
+        return quotas;
+    }
+    if remainder >= spare {
+        return counts.clone();
+    }
+
+    // Shares of `remainder` as whole rows and the numerators of what is left
+    let mut fractions = Vec::with_capacity(counts.len());
+    let mut given = 0;
+    for (country, &rows) in counts {
+        let capacity = (rows - quotas[country]) as u128;
+        let share = remainder as u128 * capacity;
+        let whole = (share / spare as u128) as u64;
+        *quotas.get_mut(country).unwrap() += whole;
+        given += whole;
+        fractions.push((share % spare as u128, country));
+    }
+    // Stable, so equal fractions keep the country order
+    fractions.sort_by(|a, b| b.0.cmp(&a.0));
+    for (_, country) in fractions.into_iter().take((remainder - given) as usize) {
+        *quotas.get_mut(country).unwrap() += 1;
+    }
+    quotas
+}
+
+/// Samples about `rows` rows of `df` stratified by `country`, see the
+/// module documentation
+pub async fn stratified_country_sample(
+    df: DataFrame,
+    rows: u64,
+    min_per_country: u64,
+    seed: u64,
+) -> Result<DataFrame> {
+    let country = coalesce(vec![col("country"), lit("")]);
+    let counts = df
+        .clone()
+        .aggregate(
+            vec![country.clone().alias("country")],
+            vec![count(lit(1)).alias("rows")],
+        )?
+        .collect()
+        .await?;
+    let mut country_rows = BTreeMap::new();
+    for batch in &counts {
+        let countries = arrow::compute::cast(batch.column(0), &DataType::Utf8)?;
+        let countries = countries
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .ok_or_else(|| anyhow!("Unexpected country column type"))?;
+        let rows = batch
+            .column(1)
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .ok_or_else(|| anyhow!("Unexpected row count column type"))?;
+        for i in 0..batch.num_rows() {
+            country_rows.insert(countries.value(i).to_string(), rows.value(i) as u64);
+        }
+    }
+
+    let quotas = country_quotas(&country_rows, rows, min_per_country);
+    let sampled: u64 = quotas.values().sum();
+    info!(
+        "Sampling {sampled} source rows from {} countries, at least {min_per_country} per \
+         country where available",
+        quotas.len()
+    );
+    if quotas.is_empty() {
+        return Ok(df);
+    }
+
+    let rank = row_number()
+        .partition_by(vec![country.clone()])
+        .order_by(vec![
+            md5(concat(vec![lit(format!("{seed}:")), col("id")])).sort(true, false),
+            col("id").sort(true, false),
+        ])
+        .build()?;
+    let quota = quotas
+        .iter()
+        .fold(case(country), |mut quota, (country, rows)| {
+            quota.when(lit(country.as_str()), lit(*rows))
+        })
+        .otherwise(lit(0u64))?;
+    Ok(df
+        .with_column(RANK_COLUMN, rank)?
+        .filter(col(RANK_COLUMN).lt_eq(quota))?
+        .drop_columns(&[RANK_COLUMN])?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counts(counts: &[(&str, u64)]) -> BTreeMap<String, u64> {
+        counts
+            .iter()
+            .map(|(country, rows)| (country.to_string(), *rows))
+            .collect()
+    }
+
+    #[test]
+    fn test_country_quotas() {
+        let source = counts(&[("FR", 3), ("JP", 1), ("US", 8)]);
+
+        // JP keeps its one row, the other 3 go 7:2 to US and FR
+        assert_eq!(
+            country_quotas(&source, 6, 1),
+            counts(&[("FR", 2), ("JP", 1), ("US", 3)])
+        );
+        // Minimums win over the total
+        assert_eq!(
+            country_quotas(&source, 2, 2),
+            counts(&[("FR", 2), ("JP", 1), ("US", 2)])
+        );
+        // A total above the source takes every row
+        assert_eq!(country_quotas(&source, 100, 1), source);
+        assert_eq!(country_quotas(&source, 12, 0).values().sum::<u64>(), 12);
+
+        // Quotas always add up to the total once minimums are met
+        for total in 3..=12 {
+            let quotas = country_quotas(&source, total, 1);
+            assert_eq!(quotas.values().sum::<u64>(), total);
+            assert!(quotas.iter().all(|(c, q)| *q >= 1 && *q <= source[c]));
+        }
+    }
+}
@@ -0,0 +1,198 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Versions of the zone table schema
+//!
+//! The columns written with the default options, their types and their
+//! nullability make up a numbered schema version. Any change to them bumps
+//! [`SCHEMA_VERSION`] and checks in a new golden schema under `schemas/`,
+//! which a test holds the transform to. Options that add columns write a
+//! `custom` schema instead of a numbered one.
+
+use anyhow::{anyhow, Result};
+use std::fmt;
+
+use super::config::ZoneTransformOptions;
+use super::geojson::GeometryEncoding;
+
+/// Version of the schema the default options write
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Key of the schema version in the Parquet footer
+pub const SCHEMA_VERSION_KEY: &str = "spatialbench.schema_version";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SchemaVersion {
+    Numbered(u32),
+    /// Columns added by options on top of a numbered schema
+    Custom,
+}
+
+impl SchemaVersion {
+    /// The schema version written with `options`
+    pub fn of(options: &ZoneTransformOptions) -> Self {
+        match schema_options(options).is_empty() {
+            true => Self::Numbered(SCHEMA_VERSION),
+            false => Self::Custom,
+        }
+    }
+
+    /// Errors unless `options` write schema version `required`
+    pub fn require(options: &ZoneTransformOptions, required: u32) -> Result<()> {
+        match Self::of(options) {
+            Self::Numbered(version) if version == required => Ok(()),
+            Self::Numbered(version) => Err(anyhow!(
+                "--require-schema-version {required} was given, but this build writes zone \
+                 schema version {version}"
+            )),
+            Self::Custom => Err(anyhow!(
+                "--require-schema-version {required} was given, but {} change the zone schema \
+                 to a custom one",
+                schema_options(options).join(", ")
+            )),
+        }
+    }
+}
+
+impl fmt::Display for SchemaVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Numbered(version) => write!(f, "{version}"),
+            Self::Custom => f.write_str("custom"),
+        }
+    }
+}
+
+/// The flags among `options` that add columns
+fn schema_options(options: &ZoneTransformOptions) -> Vec<&'static str> {
+    [
+        (options.include_hierarchy, "--include-hierarchy"),
+        (options.bbox_covering, "--with-bbox-covering"),
+        (options.area.is_some(), "--with-area"),
+        (options.point_as_lonlat, "--point-as-lonlat"),
+        (options.include_provenance, "--with-provenance"),
+        (options.attribution_column, "--attribution-column"),
+        (options.composite_key, "--composite-key"),
+        (options.names_common, "--with-names-common"),
+        (!options.names_languages.is_empty(), "--names-languages"),
+        (!options.extra_columns.is_empty(), "--extra-columns"),
+        (!options.synthetic_columns.is_empty(), "--synthetic-columns"),
+        (
+            options.geometry_encoding != GeometryEncoding::Wkb,
+            "--geometry-encoding",
+        ),
+        (options.columns.is_some(), "--columns"),
+        (options.no_geometry, "--no-geometry"),
+    ]
+    .into_iter()
+    .filter_map(|(set, flag)| set.then_some(flag))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zone::area::AreaCrs;
+    use crate::zone::datasource::ZoneDataSource;
+    use crate::zone::fixtures::{source_batch, write_parquet, SourceRow};
+    use crate::zone::theme::{Theme, ThemeInput};
+    use crate::zone::transform::ZoneTransformer;
+    use arrow_schema::Schema;
+
+    /// Golden schema of every numbered version, by version
+    const GOLDEN_SCHEMAS: &[(u32, &str)] = &[(1, include_str!("schemas/zone_v1.json"))];
+
+    /// The name, type and nullability of every field of `schema`, the form of
+    /// the golden schema files
+    fn schema_json(schema: &Schema) -> serde_json::Value {
+        let fields: Vec<_> = schema
+            .fields()
+            .iter()
+            .map(|field| {
+                serde_json::json!({
+                    "name": field.name(),
+                    "type": field.data_type().to_string(),
+                    "nullable": field.is_nullable(),
+                })
+            })
+            .collect();
+        serde_json::Value::Array(fields)
+    }
+
+    #[tokio::test]
+    async fn test_default_schema_matches_golden_schema() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("division_area.parquet");
+        write_parquet(
+            &path,
+            &source_batch(&[SourceRow::new("g1", "county")], true),
+        );
+        let datasource = ZoneDataSource::new()
+            .await
+            .unwrap()
+            .with_themes(vec![ThemeInput {
+                theme: Theme::DivisionArea,
+                location: Some(path.to_string_lossy().into_owned()),
+            }]);
+        let ctx = datasource.create_context().unwrap();
+        let df = datasource.load_zone_data(&ctx, 1.0).await.unwrap();
+        let options = ZoneTransformOptions::default();
+        let transformer = ZoneTransformer::new(0);
+        let df = transformer.transform(&ctx, &options, df).await.unwrap();
+        let schema = transformer.output_schema(&options, &df).unwrap();
+
+        let (_, golden) = GOLDEN_SCHEMAS
+            .iter()
+            .find(|(version, _)| *version == SCHEMA_VERSION)
+            .expect("SCHEMA_VERSION has a golden schema");
+        let golden: serde_json::Value = serde_json::from_str(golden).unwrap();
+        assert_eq!(
+            schema_json(&schema),
+            golden,
+            "The default zone schema changed; bump SCHEMA_VERSION and add its golden schema"
+        );
+        assert_eq!(SchemaVersion::of(&options), SchemaVersion::Numbered(1));
+    }
+
+    #[test]
+    fn test_options_adding_columns_are_custom() {
+        let options = ZoneTransformOptions {
+            bbox_covering: true,
+            area: Some(AreaCrs::Epsg6933),
+            names_languages: vec!["fr".to_string()],
+            normalize_country: true,
+            ..Default::default()
+        };
+        assert_eq!(SchemaVersion::of(&options), SchemaVersion::Custom);
+        assert_eq!(SchemaVersion::of(&options).to_string(), "custom");
+        let error = SchemaVersion::require(&options, 1).unwrap_err().to_string();
+        assert!(
+            error.contains(
+                "--with-bbox-covering, --with-area, --names-languages change the zone schema"
+            ),
+            "{error}"
+        );
+
+        let options = ZoneTransformOptions {
+            normalize_country: true,
+            ..Default::default()
+        };
+        assert!(SchemaVersion::require(&options, 1).is_ok());
+        let error = SchemaVersion::require(&options, 2).unwrap_err().to_string();
+        assert!(error.contains("writes zone schema version 1"), "{error}");
+    }
+}
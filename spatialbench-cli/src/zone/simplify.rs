@@ -0,0 +1,237 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Simplification of `z_boundary` geometries
+//!
+//! `--simplify-tolerance` drops vertices with the
+//! [`SimplifyAlgorithm`] of `--simplify-algorithm`. Douglas–Peucker reads
+//! the tolerance as a distance, Visvalingam–Whyatt as the area of the
+//! triangle a vertex forms with its neighbours, both in the units of the
+//! coordinates. Rings keep at least four points, so polygons stay closed.
+
+use anyhow::Result;
+use arrow_array::RecordBatch;
+use clap::ValueEnum;
+use geo::{Geometry, Simplify, SimplifyVwPreserve};
+use geozero::wkb::Wkb;
+use geozero::{CoordDimensions, ToGeo, ToWkb};
+use log::{info, warn};
+
+use super::batch::{map_batches, map_binary_column};
+use super::wkb::count_points;
+
+/// Algorithm `--simplify-tolerance` drops vertices with
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum SimplifyAlgorithm {
+    /// Douglas–Peucker: keeps every vertex farther than the tolerance from
+    /// the simplified line, in degrees
+    #[default]
+    #[value(name = "dp")]
+    DouglasPeucker,
+    /// Visvalingam–Whyatt: drops the vertices whose triangle with their
+    /// neighbours is smaller than the tolerance, in square degrees, without
+    /// letting rings cross
+    #[value(name = "vw")]
+    VisvalingamWhyatt,
+}
+
+impl SimplifyAlgorithm {
+    /// `geometry` simplified with `tolerance`, `None` for geometry types
+    /// without lines to simplify
+    pub(super) fn simplify(self, geometry: &Geometry, tolerance: f64) -> Option<Geometry> {
+        Some(match (self, geometry) {
+            (Self::DouglasPeucker, Geometry::LineString(g)) => g.simplify(tolerance).into(),
+            (Self::DouglasPeucker, Geometry::MultiLineString(g)) => g.simplify(tolerance).into(),
+            (Self::DouglasPeucker, Geometry::Polygon(g)) => g.simplify(tolerance).into(),
+            (Self::DouglasPeucker, Geometry::MultiPolygon(g)) => g.simplify(tolerance).into(),
+            (Self::VisvalingamWhyatt, Geometry::LineString(g)) => {
+                g.simplify_vw_preserve(tolerance).into()
+            }
+            (Self::VisvalingamWhyatt, Geometry::MultiLineString(g)) => {
+                g.simplify_vw_preserve(tolerance).into()
+            }
+            (Self::VisvalingamWhyatt, Geometry::Polygon(g)) => {
+                g.simplify_vw_preserve(tolerance).into()
+            }
+            (Self::VisvalingamWhyatt, Geometry::MultiPolygon(g)) => {
+                g.simplify_vw_preserve(tolerance).into()
+            }
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct SimplifyReport {
+    /// Vertices of the geometries before and after simplification
+    pub points_before: usize,
+    pub points_after: usize,
+    /// Geometries that could not be decoded, left as-is
+    pub unreadable: usize,
+}
+
+/// Rewrites `z_boundary` simplified by `algorithm` with `tolerance` as
+/// little-endian 2D ISO WKB. Points and unreadable values are left as-is.
+/// Batches are rewritten on up to `threads` threads.
+pub fn simplify_batches(
+    batches: Vec<RecordBatch>,
+    algorithm: SimplifyAlgorithm,
+    tolerance: f64,
+    threads: Option<usize>,
+) -> Result<(Vec<RecordBatch>, SimplifyReport)> {
+    let mut report = SimplifyReport::default();
+
+    let batches = map_batches(&batches, threads, |_, batch| {
+        let mut batch_report = SimplifyReport::default();
+        let mapped = map_binary_column(batch, "z_boundary", |wkb| {
+            let simplified = Wkb(wkb)
+                .to_geo()
+                .ok()
+                .and_then(|geometry| algorithm.simplify(&geometry, tolerance))
+                .map(|geometry| geometry.to_wkb(CoordDimensions::xy()))
+                .transpose()?;
+            match simplified {
+                Some(simplified) => {
+                    batch_report.points_before += count_points(wkb)?;
+                    batch_report.points_after += count_points(&simplified)?;
+                    Ok(Some(simplified))
+                }
+                None => {
+                    batch_report.unreadable += Wkb(wkb).to_geo().is_err() as usize;
+                    Ok(None)
+                }
+            }
+        })?;
+        Ok((mapped, batch_report))
+    })?
+    .into_iter()
+    .map(|(batch, batch_report)| {
+        report.points_before += batch_report.points_before;
+        report.points_after += batch_report.points_after;
+        report.unreadable += batch_report.unreadable;
+        batch
+    })
+    .collect();
+
+    if report.unreadable > 0 {
+        warn!(
+            "Could not decode {} z_boundary value(s), leaving them unsimplified",
+            report.unreadable
+        );
+    }
+    info!(
+        "Simplified z_boundary with {algorithm:?} at {tolerance}: {} of {} vertices kept",
+        report.points_after, report.points_before
+    );
+
+    Ok((batches, report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zone::fixtures::{wkb_point, wkb_polygon};
+    use arrow_array::{Array, BinaryArray};
+    use arrow_schema::{DataType, Field, Schema};
+    use geo::{Coord, LineString};
+    use std::sync::Arc;
+
+    fn boundaries(geometries: Vec<Vec<u8>>) -> RecordBatch {
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "z_boundary",
+            DataType::Binary,
+            true,
+        )]));
+        let values: BinaryArray = geometries.iter().map(|g| Some(g.as_slice())).collect();
+        RecordBatch::try_new(schema, vec![Arc::new(values)]).unwrap()
+    }
+
+    fn simplified(algorithm: SimplifyAlgorithm, tolerance: f64, wkb: Vec<u8>) -> Geometry {
+        let (batches, _) =
+            simplify_batches(vec![boundaries(vec![wkb])], algorithm, tolerance, None).unwrap();
+        let column = batches[0].column(0);
+        let column = column.as_any().downcast_ref::<BinaryArray>().unwrap();
+        Wkb(column.value(0)).to_geo().unwrap()
+    }
+
+    /// A circle of `points` vertices with a little noise on every other one
+    fn noisy_circle(points: usize) -> Vec<u8> {
+        let mut ring: Vec<(f64, f64)> = (0..points)
+            .map(|i| {
+                let angle = i as f64 / points as f64 * std::f64::consts::TAU;
+                let radius = 1.0 + 0.001 * (i % 2) as f64;
+                (radius * angle.cos(), radius * angle.sin())
+            })
+            .collect();
+        ring.push(ring[0]);
+        wkb_polygon(&ring)
+    }
+
+    #[test]
+    fn test_each_algorithm_reduces_vertices() {
+        for (algorithm, tolerance) in [
+            (SimplifyAlgorithm::DouglasPeucker, 0.01),
+            (SimplifyAlgorithm::VisvalingamWhyatt, 0.001),
+        ] {
+            let batches = vec![boundaries(vec![noisy_circle(200), wkb_point(1.0, 2.0)])];
+            let (batches, report) =
+                simplify_batches(batches, algorithm, tolerance, Some(2)).unwrap();
+            assert_eq!(report.points_before, 201, "{algorithm:?}");
+            assert!(
+                report.points_after >= 4 && report.points_after < 100,
+                "{algorithm:?}: {report:?}"
+            );
+            let column = batches[0].column(0);
+            let column = column.as_any().downcast_ref::<BinaryArray>().unwrap();
+            assert_eq!(count_points(column.value(0)).unwrap(), report.points_after);
+            assert_eq!(column.value(1), wkb_point(1.0, 2.0));
+        }
+    }
+
+    #[test]
+    fn test_visvalingam_keeps_the_salient_bump() {
+        // A needle spike that encloses almost no area, then a broad low bump
+        let line = LineString::from(vec![
+            (0.0, 0.0),
+            (5.0, 0.0),
+            (5.01, 2.0),
+            (5.02, 0.0),
+            (10.0, 0.0),
+            (15.0, 0.5),
+            (20.0, 0.0),
+        ]);
+        let wkb = Geometry::from(line).to_wkb(CoordDimensions::xy()).unwrap();
+        let points = |geometry: Geometry| match geometry {
+            Geometry::LineString(line) => line.0,
+            other => panic!("unexpected {other:?}"),
+        };
+        let bump = Coord { x: 15.0, y: 0.5 };
+        let spike = Coord { x: 5.01, y: 2.0 };
+
+        let dp = points(simplified(
+            SimplifyAlgorithm::DouglasPeucker,
+            1.0,
+            wkb.clone(),
+        ));
+        let vw = points(simplified(SimplifyAlgorithm::VisvalingamWhyatt, 0.1, wkb));
+        assert!(dp.len() < 7 && vw.len() < 7);
+        // Douglas-Peucker keeps the far but tiny spike and flattens the bump
+        assert!(dp.contains(&spike) && !dp.contains(&bump), "{dp:?}");
+        // Visvalingam-Whyatt weighs vertices by area and keeps the bump
+        assert!(vw.contains(&bump) && !vw.contains(&spike), "{vw:?}");
+    }
+}
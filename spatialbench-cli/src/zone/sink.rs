@@ -0,0 +1,193 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Files the zone writer encodes into
+//!
+//! The formats are encoded into a [`PartFile`], which adds the
+//! `--write-buffer-bytes` buffer in front of the file and syncs it to disk
+//! as `--fsync` asks. Without either option files are written as before:
+//! unbuffered beyond what the encoder buffers itself, and never synced.
+
+use clap::ValueEnum;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+
+/// Buffer of the streamed CSV and TBL parts when `--write-buffer-bytes` is
+/// unset, that of [`BufWriter::new`]
+pub const DEFAULT_STREAM_BUFFER_BYTES: usize = 8 * 1024;
+
+/// When the zone files are synced to disk
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Fsync {
+    /// Leave flushing to the operating system
+    #[default]
+    Never,
+    /// Sync every file once it is written, before it is moved into place,
+    /// and its directory once it is
+    PerPart,
+    /// Also sync after every Parquet row group, ORC stripe or CSV/TBL batch
+    PerRowgroup,
+}
+
+enum Sink {
+    Direct(File),
+    Buffered(BufWriter<File>),
+}
+
+/// A zone file being written, see the module documentation
+pub struct PartFile {
+    sink: Sink,
+    fsync: Fsync,
+    /// Row groups written when the file was last synced
+    synced_groups: usize,
+}
+
+impl PartFile {
+    /// Creates `path`, buffering `buffer_bytes` in front of it when set
+    pub fn create(path: &Path, buffer_bytes: Option<usize>, fsync: Fsync) -> io::Result<Self> {
+        let file = File::create(path)?;
+        let sink = match buffer_bytes {
+            Some(bytes) => Sink::Buffered(BufWriter::with_capacity(bytes, file)),
+            None => Sink::Direct(file),
+        };
+        Ok(Self {
+            sink,
+            fsync,
+            synced_groups: 0,
+        })
+    }
+
+    /// Syncs what was written so far under `--fsync per-rowgroup` once the
+    /// encoder has completed `groups` row groups, stripes or batches
+    pub fn groups_written(&mut self, groups: usize) -> io::Result<()> {
+        if self.fsync != Fsync::PerRowgroup || groups <= self.synced_groups {
+            return Ok(());
+        }
+        self.synced_groups = groups;
+        self.flush()?;
+        match &self.sink {
+            Sink::Direct(file) => file.sync_data(),
+            Sink::Buffered(writer) => writer.get_ref().sync_data(),
+        }
+    }
+}
+
+impl Write for PartFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.sink {
+            Sink::Direct(file) => file.write(buf),
+            Sink::Buffered(writer) => writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.sink {
+            Sink::Direct(file) => file.flush(),
+            Sink::Buffered(writer) => writer.flush(),
+        }
+    }
+}
+
+/// Syncs the closed file at `path` unless `fsync` is [`Fsync::Never`]
+pub fn sync_closed(path: &Path, fsync: Fsync) -> io::Result<()> {
+    match fsync {
+        Fsync::Never => Ok(()),
+        Fsync::PerPart | Fsync::PerRowgroup => File::open(path)?.sync_all(),
+    }
+}
+
+/// Syncs the directory holding `path` unless `fsync` is [`Fsync::Never`],
+/// so a file renamed or created there survives a crash
+pub fn sync_dir(path: &Path, fsync: Fsync) -> io::Result<()> {
+    if fsync == Fsync::Never {
+        return Ok(());
+    }
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => File::open(dir)?.sync_all(),
+        _ => File::open(".")?.sync_all(),
+    }
+}
+
+/// Write throughput of `bytes` written in `elapsed`, e.g. `"12.5 MB/s"`
+pub fn throughput(bytes: u64, elapsed: Duration) -> String {
+    let seconds = elapsed.as_secs_f64();
+    if seconds == 0.0 {
+        return "n/a".to_string();
+    }
+    format!("{:.1} MB/s", bytes as f64 / seconds / 1e6)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buffered_file_writes_everything() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("zone.parquet");
+        for buffer in [None, Some(1), Some(1 << 20)] {
+            let mut file = PartFile::create(&path, buffer, Fsync::Never).unwrap();
+            file.write_all(b"zone rows").unwrap();
+            file.flush().unwrap();
+            drop(file);
+            assert_eq!(std::fs::read(&path).unwrap(), b"zone rows");
+        }
+    }
+
+    #[test]
+    fn test_syncs_only_new_row_groups_per_rowgroup() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("zone.csv");
+
+        let mut file = PartFile::create(&path, Some(1024), Fsync::PerRowgroup).unwrap();
+        file.write_all(b"a\n").unwrap();
+        file.groups_written(0).unwrap();
+        assert_eq!(file.synced_groups, 0);
+        file.groups_written(2).unwrap();
+        assert_eq!(file.synced_groups, 2);
+        // Synced data was flushed out of the buffer
+        assert_eq!(std::fs::read(&path).unwrap(), b"a\n");
+
+        let mut file = PartFile::create(&path, Some(1024), Fsync::PerPart).unwrap();
+        file.groups_written(3).unwrap();
+        assert_eq!(file.synced_groups, 0);
+    }
+
+    #[test]
+    fn test_sync_dir_syncs_the_parent_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("zone.parquet");
+        std::fs::write(&path, b"zone rows").unwrap();
+        for fsync in [Fsync::Never, Fsync::PerPart, Fsync::PerRowgroup] {
+            sync_dir(&path, fsync).unwrap();
+        }
+        // A bare file name lives in the current directory
+        sync_dir(Path::new("zone.parquet"), Fsync::PerPart).unwrap();
+        // Never doesn't touch the directory, even a missing one
+        let missing = dir.path().join("missing/zone.parquet");
+        sync_dir(&missing, Fsync::Never).unwrap();
+        assert!(sync_dir(&missing, Fsync::PerPart).is_err());
+    }
+
+    #[test]
+    fn test_throughput() {
+        assert_eq!(throughput(25_000_000, Duration::from_secs(2)), "12.5 MB/s");
+        assert_eq!(throughput(1, Duration::ZERO), "n/a");
+    }
+}
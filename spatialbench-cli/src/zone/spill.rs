@@ -0,0 +1,87 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! The collected zone table spilled to a temporary Parquet file
+//!
+//! With `--spill-collect` a multi-part run writes the transformed and
+//! partitioned table to disk once and frees it, then reads back the rows of
+//! one part at a time, so writing the parts holds a single part in memory.
+
+use anyhow::Result;
+use arrow_array::RecordBatch;
+use arrow_schema::SchemaRef;
+use log::{debug, info};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use std::path::Path;
+use tempfile::NamedTempFile;
+
+use super::partition::PartitionStrategy;
+
+/// Rows per row group of the spill file, small enough that reading a part
+/// skips most of the rows before it without decoding them
+const SPILL_ROW_GROUP_ROWS: usize = 8192;
+
+/// A temporary Parquet copy of the collected rows, removed when dropped
+pub struct SpilledTable {
+    file: NamedTempFile,
+}
+
+impl SpilledTable {
+    /// Writes `batches` with `schema` to a hidden temporary file in `dir`
+    pub fn write(dir: &Path, schema: SchemaRef, batches: &[RecordBatch]) -> Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        let file = tempfile::Builder::new()
+            .prefix(".zone-spill-")
+            .suffix(".parquet")
+            .tempfile_in(dir)?;
+        let props = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .set_max_row_group_size(SPILL_ROW_GROUP_ROWS)
+            .build();
+        let mut writer = ArrowWriter::try_new(file.reopen()?, schema, Some(props))?;
+        for batch in batches {
+            writer.write(batch)?;
+        }
+        writer.close()?;
+        info!(
+            "Spilled {} row(s) to {} ({} bytes)",
+            batches.iter().map(RecordBatch::num_rows).sum::<usize>(),
+            file.path().display(),
+            file.as_file().metadata()?.len()
+        );
+        Ok(Self { file })
+    }
+
+    /// Reads the rows of `partition` back
+    pub fn read(&self, partition: &PartitionStrategy) -> Result<Vec<RecordBatch>> {
+        let reader = ParquetRecordBatchReaderBuilder::try_new(self.file.reopen()?)?
+            .with_offset(partition.offset() as usize)
+            .with_limit(partition.limit() as usize)
+            .build()?;
+        let batches = reader.collect::<Result<Vec<_>, _>>()?;
+        debug!(
+            "Read rows {}..{} from {}",
+            partition.offset(),
+            partition.offset() + partition.limit(),
+            self.file.path().display()
+        );
+        Ok(batches)
+    }
+}
@@ -0,0 +1,458 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Normalization of `z_region` values to ISO 3166-2 codes or English names
+//!
+//! Overture writes a region as `US-WA` in some themes and releases and as
+//! `Washington` in others. The bundled table covers the first level
+//! subdivisions of the countries benchmarks group by most; values outside it
+//! pass through unchanged and are counted.
+
+use anyhow::Result;
+use arrow_array::{Array, RecordBatch};
+use clap::ValueEnum;
+use log::{info, warn};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::OnceLock;
+
+use super::batch::{map_string_column_by_row, string_column};
+use super::country::normalize_country_code;
+
+/// ISO 3166-2 subdivisions as (code, English name)
+const SUBDIVISIONS: &[(&str, &str)] = &[
+    ("AU-ACT", "Australian Capital Territory"),
+    ("AU-NSW", "New South Wales"),
+    ("AU-NT", "Northern Territory"),
+    ("AU-QLD", "Queensland"),
+    ("AU-SA", "South Australia"),
+    ("AU-TAS", "Tasmania"),
+    ("AU-VIC", "Victoria"),
+    ("AU-WA", "Western Australia"),
+    ("CA-AB", "Alberta"),
+    ("CA-BC", "British Columbia"),
+    ("CA-MB", "Manitoba"),
+    ("CA-NB", "New Brunswick"),
+    ("CA-NL", "Newfoundland and Labrador"),
+    ("CA-NS", "Nova Scotia"),
+    ("CA-NT", "Northwest Territories"),
+    ("CA-NU", "Nunavut"),
+    ("CA-ON", "Ontario"),
+    ("CA-PE", "Prince Edward Island"),
+    ("CA-QC", "Quebec"),
+    ("CA-SK", "Saskatchewan"),
+    ("CA-YT", "Yukon"),
+    ("DE-BB", "Brandenburg"),
+    ("DE-BE", "Berlin"),
+    ("DE-BW", "Baden-Wurttemberg"),
+    ("DE-BY", "Bavaria"),
+    ("DE-HB", "Bremen"),
+    ("DE-HE", "Hesse"),
+    ("DE-HH", "Hamburg"),
+    ("DE-MV", "Mecklenburg-Western Pomerania"),
+    ("DE-NI", "Lower Saxony"),
+    ("DE-NW", "North Rhine-Westphalia"),
+    ("DE-RP", "Rhineland-Palatinate"),
+    ("DE-SH", "Schleswig-Holstein"),
+    ("DE-SL", "Saarland"),
+    ("DE-SN", "Saxony"),
+    ("DE-ST", "Saxony-Anhalt"),
+    ("DE-TH", "Thuringia"),
+    ("FR-20R", "Corsica"),
+    ("FR-ARA", "Auvergne-Rhone-Alpes"),
+    ("FR-BFC", "Bourgogne-Franche-Comte"),
+    ("FR-BRE", "Brittany"),
+    ("FR-CVL", "Centre-Val de Loire"),
+    ("FR-GES", "Grand Est"),
+    ("FR-HDF", "Hauts-de-France"),
+    ("FR-IDF", "Ile-de-France"),
+    ("FR-NAQ", "Nouvelle-Aquitaine"),
+    ("FR-NOR", "Normandy"),
+    ("FR-OCC", "Occitanie"),
+    ("FR-PAC", "Provence-Alpes-Cote d'Azur"),
+    ("FR-PDL", "Pays de la Loire"),
+    ("GB-ENG", "England"),
+    ("GB-NIR", "Northern Ireland"),
+    ("GB-SCT", "Scotland"),
+    ("GB-WLS", "Wales"),
+    ("US-AK", "Alaska"),
+    ("US-AL", "Alabama"),
+    ("US-AR", "Arkansas"),
+    ("US-AS", "American Samoa"),
+    ("US-AZ", "Arizona"),
+    ("US-CA", "California"),
+    ("US-CO", "Colorado"),
+    ("US-CT", "Connecticut"),
+    ("US-DC", "District of Columbia"),
+    ("US-DE", "Delaware"),
+    ("US-FL", "Florida"),
+    ("US-GA", "Georgia"),
+    ("US-GU", "Guam"),
+    ("US-HI", "Hawaii"),
+    ("US-IA", "Iowa"),
+    ("US-ID", "Idaho"),
+    ("US-IL", "Illinois"),
+    ("US-IN", "Indiana"),
+    ("US-KS", "Kansas"),
+    ("US-KY", "Kentucky"),
+    ("US-LA", "Louisiana"),
+    ("US-MA", "Massachusetts"),
+    ("US-MD", "Maryland"),
+    ("US-ME", "Maine"),
+    ("US-MI", "Michigan"),
+    ("US-MN", "Minnesota"),
+    ("US-MO", "Missouri"),
+    ("US-MP", "Northern Mariana Islands"),
+    ("US-MS", "Mississippi"),
+    ("US-MT", "Montana"),
+    ("US-NC", "North Carolina"),
+    ("US-ND", "North Dakota"),
+    ("US-NE", "Nebraska"),
+    ("US-NH", "New Hampshire"),
+    ("US-NJ", "New Jersey"),
+    ("US-NM", "New Mexico"),
+    ("US-NV", "Nevada"),
+    ("US-NY", "New York"),
+    ("US-OH", "Ohio"),
+    ("US-OK", "Oklahoma"),
+    ("US-OR", "Oregon"),
+    ("US-PA", "Pennsylvania"),
+    ("US-PR", "Puerto Rico"),
+    ("US-RI", "Rhode Island"),
+    ("US-SC", "South Carolina"),
+    ("US-SD", "South Dakota"),
+    ("US-TN", "Tennessee"),
+    ("US-TX", "Texas"),
+    ("US-UM", "United States Minor Outlying Islands"),
+    ("US-UT", "Utah"),
+    ("US-VA", "Virginia"),
+    ("US-VI", "United States Virgin Islands"),
+    ("US-VT", "Vermont"),
+    ("US-WA", "Washington"),
+    ("US-WI", "Wisconsin"),
+    ("US-WV", "West Virginia"),
+    ("US-WY", "Wyoming"),
+];
+
+/// Local and alternate spellings that are not the English name, as
+/// (alias, code)
+const ALIASES: &[(&str, &str)] = &[
+    ("baden-württemberg", "DE-BW"),
+    ("bayern", "DE-BY"),
+    ("hessen", "DE-HE"),
+    ("mecklenburg-vorpommern", "DE-MV"),
+    ("niedersachsen", "DE-NI"),
+    ("nordrhein-westfalen", "DE-NW"),
+    ("rheinland-pfalz", "DE-RP"),
+    ("sachsen", "DE-SN"),
+    ("sachsen-anhalt", "DE-ST"),
+    ("thüringen", "DE-TH"),
+    ("québec", "CA-QC"),
+    ("corse", "FR-20R"),
+    ("auvergne-rhône-alpes", "FR-ARA"),
+    ("bourgogne-franche-comté", "FR-BFC"),
+    ("bretagne", "FR-BRE"),
+    ("île-de-france", "FR-IDF"),
+    ("normandie", "FR-NOR"),
+    ("provence-alpes-côte d'azur", "FR-PAC"),
+    ("washington, d.c.", "US-DC"),
+    ("u.s. virgin islands", "US-VI"),
+];
+
+/// What `z_region` values are rewritten to
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum RegionNormalization {
+    /// ISO 3166-2 codes, e.g. `US-WA`
+    Iso,
+    /// English names, e.g. `Washington`
+    Name,
+    /// The source values, unchanged
+    #[default]
+    Raw,
+}
+
+/// Counts of rows seen while normalizing `z_region`
+#[derive(Debug, Default)]
+pub struct RegionNormalizationReport {
+    /// Rows whose value was rewritten to a different string
+    pub normalized: usize,
+    /// Rows whose value was already in the requested form
+    pub unchanged: usize,
+    /// Values missing from the table and how many rows carried them
+    pub unmapped: BTreeMap<String, usize>,
+}
+
+impl RegionNormalizationReport {
+    /// Rows whose value passed through because the table doesn't have it
+    pub fn passed_through(&self) -> usize {
+        self.unmapped.values().sum()
+    }
+}
+
+/// Codes by case folded code, and by country and case folded name or alias
+struct Lookup {
+    codes: HashMap<String, &'static str>,
+    names: HashMap<(&'static str, String), &'static str>,
+    /// Names found in a single country only, for rows whose country isn't
+    /// recognized
+    unique_names: HashMap<String, Option<&'static str>>,
+    english: HashMap<&'static str, &'static str>,
+}
+
+fn lookup() -> &'static Lookup {
+    static LOOKUP: OnceLock<Lookup> = OnceLock::new();
+    LOOKUP.get_or_init(|| {
+        let mut lookup = Lookup {
+            codes: HashMap::new(),
+            names: HashMap::new(),
+            unique_names: HashMap::new(),
+            english: HashMap::new(),
+        };
+        let names = SUBDIVISIONS
+            .iter()
+            .map(|(code, name)| (name.to_lowercase(), *code))
+            .chain(
+                ALIASES
+                    .iter()
+                    .map(|(alias, code)| (alias.to_string(), *code)),
+            );
+        for (name, code) in names {
+            lookup.names.insert((&code[..2], name.clone()), code);
+            lookup
+                .unique_names
+                .entry(name)
+                .and_modify(|found| {
+                    if *found != Some(code) {
+                        *found = None;
+                    }
+                })
+                .or_insert(Some(code));
+        }
+        for (code, name) in SUBDIVISIONS {
+            lookup.codes.insert(code.to_lowercase(), *code);
+            lookup.english.insert(*code, *name);
+        }
+        lookup
+    })
+}
+
+/// Returns the ISO 3166-2 code of the region `value` in `country`, if the
+/// table has it.
+///
+/// `value` may be a code, in any case, a code without its country prefix,
+/// an English name or a local spelling; names are matched ignoring case and
+/// repeated whitespace. `country` is matched as by
+/// [`normalize_country_code`], and an unrecognized or empty country only
+/// matches names found in a single country.
+pub fn subdivision_code(country: &str, value: &str) -> Option<&'static str> {
+    let lookup = lookup();
+    let key = value
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase();
+    if let Some(code) = lookup.codes.get(&key) {
+        return Some(code);
+    }
+    match normalize_country_code(country) {
+        Some(country) => lookup
+            .codes
+            .get(&format!("{}-{key}", country.to_lowercase()))
+            .or_else(|| lookup.names.get(&(country, key)))
+            .copied(),
+        None => lookup.unique_names.get(&key).copied().flatten(),
+    }
+}
+
+/// Returns the English name of the subdivision with ISO 3166-2 `code`
+pub fn subdivision_name(code: &str) -> Option<&'static str> {
+    lookup().english.get(code).copied()
+}
+
+/// Rewrites `z_region` in every batch to its ISO 3166-2 code or English
+/// name, reading the country of every row from `z_country`.
+///
+/// Empty strings (missing regions) are left alone. Values missing from the
+/// table pass through unchanged and are logged with their row count.
+pub fn normalize_region_batches(
+    batches: Vec<RecordBatch>,
+    normalization: RegionNormalization,
+) -> Result<(Vec<RecordBatch>, RegionNormalizationReport)> {
+    let mut report = RegionNormalizationReport::default();
+    if normalization == RegionNormalization::Raw {
+        return Ok((batches, report));
+    }
+
+    let batches = batches
+        .iter()
+        .map(|batch| {
+            let countries = string_column(batch, "z_country")?;
+            map_string_column_by_row(batch, "z_region", |row, value| {
+                if value.is_empty() {
+                    return None;
+                }
+                let country = countries.is_valid(row).then(|| countries.value(row));
+                let normalized =
+                    subdivision_code(country.unwrap_or_default(), value).and_then(|code| {
+                        match normalization {
+                            RegionNormalization::Name => subdivision_name(code),
+                            _ => Some(code),
+                        }
+                    });
+                match normalized {
+                    Some(normalized) if normalized != value => {
+                        report.normalized += 1;
+                        Some(normalized.to_string())
+                    }
+                    Some(_) => {
+                        report.unchanged += 1;
+                        None
+                    }
+                    None => {
+                        *report.unmapped.entry(value.to_string()).or_default() += 1;
+                        None
+                    }
+                }
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    for (value, rows) in &report.unmapped {
+        warn!("Unmapped z_region value {value:?} in {rows} row(s), leaving as-is");
+    }
+    info!(
+        "Normalized z_region for {} row(s), {} already normalized, {} passed through \
+         unmapped",
+        report.normalized,
+        report.unchanged,
+        report.passed_through()
+    );
+
+    Ok((batches, report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_array::StringArray;
+    use arrow_schema::{DataType, Field, Schema};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_subdivision_code_variants() {
+        assert_eq!(subdivision_code("US", "US-WA"), Some("US-WA"));
+        assert_eq!(subdivision_code("US", "us-wa"), Some("US-WA"));
+        assert_eq!(subdivision_code("US", "WA"), Some("US-WA"));
+        assert_eq!(subdivision_code("US", " washington "), Some("US-WA"));
+        assert_eq!(
+            subdivision_code("united states", "Washington"),
+            Some("US-WA")
+        );
+        // The country tells the two apart
+        assert_eq!(subdivision_code("AU", "WA"), Some("AU-WA"));
+        assert_eq!(subdivision_code("AU", "Washington"), None);
+        assert_eq!(subdivision_code("DE", "Bayern"), Some("DE-BY"));
+        // Without a country only names of a single country match
+        assert_eq!(subdivision_code("", "Queensland"), Some("AU-QLD"));
+        assert_eq!(subdivision_code("", "WA"), None);
+        assert_eq!(subdivision_code("US", "Atlantis"), None);
+    }
+
+    #[test]
+    fn test_subdivision_table_is_consistent() {
+        for (code, name) in SUBDIVISIONS {
+            let (country, _) = code.split_once('-').unwrap();
+            assert_eq!(normalize_country_code(country), Some(country), "{code}");
+            assert_eq!(subdivision_code(country, name), Some(*code));
+            assert_eq!(subdivision_name(code), Some(*name));
+        }
+        for (alias, code) in ALIASES {
+            assert!(SUBDIVISIONS.iter().any(|(c, _)| c == code), "{alias}");
+            assert_eq!(alias.to_lowercase(), *alias);
+        }
+        let mut codes: Vec<_> = SUBDIVISIONS.iter().map(|(code, _)| code).collect();
+        codes.sort();
+        codes.dedup();
+        assert_eq!(codes.len(), SUBDIVISIONS.len());
+    }
+
+    fn batch(rows: &[(&str, &str)]) -> RecordBatch {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("z_country", DataType::Utf8, false),
+            Field::new("z_region", DataType::Utf8View, false),
+        ]));
+        let (countries, regions): (Vec<&str>, Vec<&str>) = rows.iter().copied().unzip();
+        RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(StringArray::from(countries)),
+                Arc::new(arrow_array::StringViewArray::from(regions)),
+            ],
+        )
+        .unwrap()
+    }
+
+    fn regions(batch: &RecordBatch) -> Vec<String> {
+        let regions = arrow::compute::cast(batch.column(1), &DataType::Utf8).unwrap();
+        let regions = regions.as_any().downcast_ref::<StringArray>().unwrap();
+        (0..regions.len())
+            .map(|i| regions.value(i).to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_normalize_region_batches() {
+        let rows = [
+            ("US", "Washington"),
+            ("US", "US-OR"),
+            ("AU", "WA"),
+            ("US", "Atlantis"),
+            ("US", "Atlantis"),
+            ("FR", ""),
+        ];
+
+        let (batches, report) =
+            normalize_region_batches(vec![batch(&rows)], RegionNormalization::Iso).unwrap();
+        assert_eq!(
+            regions(&batches[0]),
+            vec!["US-WA", "US-OR", "AU-WA", "Atlantis", "Atlantis", ""]
+        );
+        assert_eq!(batches[0].schema(), batch(&rows).schema());
+        assert_eq!((report.normalized, report.unchanged), (2, 1));
+        assert_eq!(report.unmapped.get("Atlantis"), Some(&2));
+        assert_eq!(report.passed_through(), 2);
+
+        let (batches, report) =
+            normalize_region_batches(vec![batch(&rows)], RegionNormalization::Name).unwrap();
+        assert_eq!(
+            regions(&batches[0]),
+            vec![
+                "Washington",
+                "Oregon",
+                "Western Australia",
+                "Atlantis",
+                "Atlantis",
+                ""
+            ]
+        );
+        assert_eq!((report.normalized, report.unchanged), (2, 1));
+
+        let (batches, report) =
+            normalize_region_batches(vec![batch(&rows)], RegionNormalization::Raw).unwrap();
+        assert_eq!(regions(&batches[0])[0], "Washington");
+        assert_eq!(report.normalized + report.passed_through(), 0);
+    }
+}
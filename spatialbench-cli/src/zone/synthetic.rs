@@ -0,0 +1,412 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Numeric columns drawn from configurable distributions for selectivity
+//! and cardinality experiments.
+//!
+//! Each value is derived from the seed, the column name and the row's
+//! `z_gersid`, so it does not depend on how rows are ordered or split into
+//! parts.
+
+use anyhow::{anyhow, Result};
+use arrow::compute::cast;
+use arrow_array::{ArrayRef, Float64Array, Int64Array, RecordBatch, StringArray};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Number of ranks a zipf column draws from when the spec doesn't say
+const DEFAULT_ZIPF_RANKS: u64 = 1000;
+
+/// Largest supported zipf rank count, bounding the size of the CDF table
+const MAX_ZIPF_RANKS: u64 = 10_000_000;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Distribution {
+    /// Ranks `1..=ranks` with probability proportional to `1 / rank^exponent`
+    Zipf {
+        exponent: f64,
+        ranks: u64,
+    },
+    /// Integers in `low..high`
+    UniformInt {
+        low: i64,
+        high: i64,
+    },
+    /// Floats in `low..high`
+    UniformFloat {
+        low: f64,
+        high: f64,
+    },
+    Normal {
+        mean: f64,
+        std_dev: f64,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SyntheticColumn {
+    pub name: String,
+    pub distribution: Distribution,
+}
+
+impl SyntheticColumn {
+    /// Parses a comma separated list such as
+    /// `zipf:z_popularity:1.1,uniform:z_rand:0..1000,normal:z_score:50:10`
+    pub fn parse_list(spec: &str) -> Result<Vec<Self>> {
+        let columns = spec
+            .split(',')
+            .filter(|s| !s.trim().is_empty())
+            .map(Self::from_str)
+            .collect::<Result<Vec<_>>>()?;
+        for (i, column) in columns.iter().enumerate() {
+            if columns[..i].iter().any(|c| c.name == column.name) {
+                return Err(anyhow!(
+                    "Synthetic column {} is defined more than once",
+                    column.name
+                ));
+            }
+        }
+        Ok(columns)
+    }
+
+    pub fn data_type(&self) -> DataType {
+        match self.distribution {
+            Distribution::Zipf { .. } | Distribution::UniformInt { .. } => DataType::Int64,
+            Distribution::UniformFloat { .. } | Distribution::Normal { .. } => DataType::Float64,
+        }
+    }
+}
+
+impl FromStr for SyntheticColumn {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let parts: Vec<&str> = s.split(':').map(str::trim).collect();
+        let invalid = |reason: String| anyhow!("Invalid synthetic column {s:?}: {reason}");
+        let number = |value: &str, what: &str| {
+            value
+                .parse::<f64>()
+                .ok()
+                .filter(|v| v.is_finite())
+                .ok_or_else(|| invalid(format!("{what} {value:?} is not a number")))
+        };
+
+        if parts.len() < 2 {
+            return Err(invalid("expected `distribution:name:params`".to_string()));
+        }
+        let name = parts[1];
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(invalid(format!(
+                "column name {name:?} must be non-empty ASCII letters, digits or `_`"
+            )));
+        }
+        let params = &parts[2..];
+
+        let distribution = match (parts[0], params) {
+            ("zipf", [exponent]) | ("zipf", [exponent, _]) => {
+                let exponent = number(exponent, "exponent")?;
+                if exponent <= 0.0 {
+                    return Err(invalid("zipf exponent must be positive".to_string()));
+                }
+                let ranks = match params.get(1) {
+                    Some(ranks) => ranks
+                        .parse::<u64>()
+                        .ok()
+                        .filter(|r| (1..=MAX_ZIPF_RANKS).contains(r))
+                        .ok_or_else(|| {
+                            invalid(format!(
+                                "zipf rank count {ranks:?} must be between 1 and {MAX_ZIPF_RANKS}"
+                            ))
+                        })?,
+                    None => DEFAULT_ZIPF_RANKS,
+                };
+                Distribution::Zipf { exponent, ranks }
+            }
+            ("zipf", _) => {
+                return Err(invalid("expected `zipf:name:exponent[:ranks]`".to_string()))
+            }
+            ("uniform", [range]) => {
+                let (low, high) = range
+                    .split_once("..")
+                    .ok_or_else(|| invalid(format!("range {range:?} must be `low..high`")))?;
+                match (low.parse::<i64>(), high.parse::<i64>()) {
+                    (Ok(low), Ok(high)) if low < high => Distribution::UniformInt { low, high },
+                    (Ok(_), Ok(_)) => {
+                        return Err(invalid(format!("range {range:?} is empty")));
+                    }
+                    _ => {
+                        let low = number(low, "range start")?;
+                        let high = number(high, "range end")?;
+                        if low >= high {
+                            return Err(invalid(format!("range {range:?} is empty")));
+                        }
+                        Distribution::UniformFloat { low, high }
+                    }
+                }
+            }
+            ("uniform", _) => return Err(invalid("expected `uniform:name:low..high`".to_string())),
+            ("normal", [mean, std_dev]) => {
+                let mean = number(mean, "mean")?;
+                let std_dev = number(std_dev, "standard deviation")?;
+                if std_dev < 0.0 {
+                    return Err(invalid(
+                        "standard deviation must not be negative".to_string(),
+                    ));
+                }
+                Distribution::Normal { mean, std_dev }
+            }
+            ("normal", _) => return Err(invalid("expected `normal:name:mean:stddev`".to_string())),
+            (other, _) => {
+                return Err(invalid(format!(
+                    "unknown distribution {other:?}, expected zipf, uniform or normal"
+                )))
+            }
+        };
+
+        Ok(Self {
+            name: name.to_string(),
+            distribution,
+        })
+    }
+}
+
+/// Appends the synthetic column fields to `schema`, rejecting names that
+/// are already taken
+pub fn output_schema(schema: SchemaRef, columns: &[SyntheticColumn]) -> Result<SchemaRef> {
+    if columns.is_empty() {
+        return Ok(schema);
+    }
+    let mut fields: Vec<Field> = schema.fields().iter().map(|f| f.as_ref().clone()).collect();
+    for column in columns {
+        if schema.field_with_name(&column.name).is_ok() {
+            return Err(anyhow!(
+                "Synthetic column {} clashes with an existing zone column",
+                column.name
+            ));
+        }
+        fields.push(Field::new(&column.name, column.data_type(), false));
+    }
+    Ok(Arc::new(Schema::new_with_metadata(
+        fields,
+        schema.metadata().clone(),
+    )))
+}
+
+/// Appends the synthetic columns to every batch
+pub fn append_synthetic_columns(
+    batches: Vec<RecordBatch>,
+    columns: &[SyntheticColumn],
+    seed: u64,
+) -> Result<Vec<RecordBatch>> {
+    if columns.is_empty() {
+        return Ok(batches);
+    }
+    let samplers: Vec<Sampler> = columns.iter().map(|c| Sampler::new(c, seed)).collect();
+
+    batches
+        .into_iter()
+        .map(|batch| {
+            let schema = output_schema(batch.schema(), columns)?;
+            let ids = batch
+                .column_by_name("z_gersid")
+                .ok_or_else(|| anyhow!("Synthetic columns need a z_gersid column"))?;
+            let ids = cast(ids, &DataType::Utf8)?;
+            let ids = ids.as_any().downcast_ref::<StringArray>().unwrap();
+
+            let mut arrays = batch.columns().to_vec();
+            for sampler in &samplers {
+                let row_hashes = ids.iter().map(|id| sampler.row_hash(id.unwrap_or("")));
+                arrays.push(sampler.sample(row_hashes));
+            }
+            Ok(RecordBatch::try_new(schema, arrays)?)
+        })
+        .collect()
+}
+
+struct Sampler<'a> {
+    column: &'a SyntheticColumn,
+    column_seed: u64,
+    /// Cumulative zipf probabilities, `cdf[i]` covering ranks `1..=i+1`
+    zipf_cdf: Vec<f64>,
+}
+
+impl<'a> Sampler<'a> {
+    fn new(column: &'a SyntheticColumn, seed: u64) -> Self {
+        let zipf_cdf = match column.distribution {
+            Distribution::Zipf { exponent, ranks } => {
+                let mut total = 0.0;
+                let mut cdf: Vec<f64> = (1..=ranks)
+                    .map(|rank| {
+                        total += 1.0 / (rank as f64).powf(exponent);
+                        total
+                    })
+                    .collect();
+                cdf.iter_mut().for_each(|p| *p /= total);
+                cdf
+            }
+            _ => Vec::new(),
+        };
+        Self {
+            column,
+            column_seed: splitmix64(seed ^ fnv1a(column.name.as_bytes())),
+            zipf_cdf,
+        }
+    }
+
+    fn row_hash(&self, gersid: &str) -> u64 {
+        splitmix64(self.column_seed ^ fnv1a(gersid.as_bytes()))
+    }
+
+    fn sample(&self, row_hashes: impl Iterator<Item = u64>) -> ArrayRef {
+        match self.column.distribution {
+            Distribution::Zipf { .. } => {
+                Arc::new(Int64Array::from_iter_values(row_hashes.map(|h| {
+                    let u = unit(h);
+                    self.zipf_cdf.partition_point(|p| *p < u) as i64 + 1
+                })))
+            }
+            Distribution::UniformInt { low, high } => {
+                let span = high.abs_diff(low);
+                Arc::new(Int64Array::from_iter_values(
+                    row_hashes.map(|h| low.wrapping_add((h % span) as i64)),
+                ))
+            }
+            Distribution::UniformFloat { low, high } => Arc::new(Float64Array::from_iter_values(
+                row_hashes.map(|h| low + unit(h) * (high - low)),
+            )),
+            Distribution::Normal { mean, std_dev } => {
+                Arc::new(Float64Array::from_iter_values(row_hashes.map(|h| {
+                    // Box-Muller on two independent uniforms derived from the row
+                    let u1 = 1.0 - unit(h);
+                    let u2 = unit(splitmix64(h));
+                    let z = (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos();
+                    mean + std_dev * z
+                })))
+            }
+        }
+    }
+}
+
+/// Maps a hash to `[0, 1)` using its top 53 bits
+fn unit(hash: u64) -> f64 {
+    (hash >> 11) as f64 / (1u64 << 53) as f64
+}
+
+pub(super) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, b| {
+        (hash ^ *b as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+pub(super) fn splitmix64(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_array::Array;
+
+    fn ids(values: &[&str]) -> RecordBatch {
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "z_gersid",
+            DataType::Utf8,
+            false,
+        )]));
+        RecordBatch::try_new(schema, vec![Arc::new(StringArray::from(values.to_vec()))]).unwrap()
+    }
+
+    #[test]
+    fn test_parse_synthetic_columns() {
+        let columns = SyntheticColumn::parse_list(
+            "zipf:z_popularity:1.1,uniform:z_rand:0..1000,normal:z_score:50:10,uniform:z_f:0..0.5",
+        )
+        .unwrap();
+        assert_eq!(
+            columns.iter().map(|c| &c.distribution).collect::<Vec<_>>(),
+            vec![
+                &Distribution::Zipf {
+                    exponent: 1.1,
+                    ranks: DEFAULT_ZIPF_RANKS
+                },
+                &Distribution::UniformInt { low: 0, high: 1000 },
+                &Distribution::Normal {
+                    mean: 50.0,
+                    std_dev: 10.0
+                },
+                &Distribution::UniformFloat {
+                    low: 0.0,
+                    high: 0.5
+                },
+            ]
+        );
+
+        for (spec, message) in [
+            ("poisson:z_a:1", "unknown distribution"),
+            ("zipf:z_a", "expected `zipf"),
+            ("zipf:z_a:-1", "must be positive"),
+            ("uniform:z_a:10..10", "is empty"),
+            ("uniform:z_a:1-5", "must be `low..high`"),
+            ("normal:z_a:x:1", "mean \"x\" is not a number"),
+            ("normal:z a:0:1", "column name"),
+            ("zipf:z_a:1,zipf:z_a:2", "more than once"),
+        ] {
+            let err = SyntheticColumn::parse_list(spec).unwrap_err().to_string();
+            assert!(err.contains(message), "{spec}: {err}");
+        }
+    }
+
+    #[test]
+    fn test_values_independent_of_batching_and_order() {
+        let columns =
+            SyntheticColumn::parse_list("zipf:z_pop:1.1,uniform:z_rand:-5..5,normal:z_score:0:1")
+                .unwrap();
+        let whole = append_synthetic_columns(vec![ids(&["a", "b", "c"])], &columns, 7).unwrap();
+        let split =
+            append_synthetic_columns(vec![ids(&["c"]), ids(&["b", "a"])], &columns, 7).unwrap();
+
+        for i in 1..=3 {
+            let whole = whole[0].column(i);
+            assert_eq!(whole.slice(2, 1).as_ref(), split[0].column(i).as_ref());
+            assert_eq!(
+                whole.slice(0, 1).as_ref(),
+                split[1].column(i).slice(1, 1).as_ref()
+            );
+        }
+
+        let reseeded = append_synthetic_columns(vec![ids(&["a", "b", "c"])], &columns, 8).unwrap();
+        assert_ne!(whole[0].column(2).as_ref(), reseeded[0].column(2).as_ref());
+
+        let rand = whole[0]
+            .column(2)
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .unwrap();
+        assert!(rand.values().iter().all(|v| (-5..5).contains(v)));
+    }
+
+    #[test]
+    fn test_name_clash_rejected() {
+        let columns = SyntheticColumn::parse_list("uniform:z_gersid:0..1").unwrap();
+        assert!(output_schema(ids(&["a"]).schema(), &columns).is_err());
+    }
+}
@@ -0,0 +1,333 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Streaming tar archives of dataset files
+//!
+//! Entries are regular files in the POSIX ustar format, read and written
+//! one block at a time so neither side holds a file in memory. Sizes of
+//! 8 GiB and more, which don't fit the octal size field, are stored in the
+//! base-256 form GNU tar and libarchive read. Paths longer than 100 bytes
+//! are split over the ustar name and prefix fields.
+
+use anyhow::{anyhow, Result};
+use std::io::{self, Read, Write};
+
+const BLOCK: usize = 512;
+/// Largest size the 11 octal digits of the size field hold
+const MAX_OCTAL_SIZE: u64 = 0o777_7777_7777;
+
+/// A file entry of an archive
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TarHeader {
+    /// `/` separated path relative to the archive root
+    pub path: String,
+    pub size: u64,
+    /// Modification time in seconds since the epoch
+    pub mtime: u64,
+}
+
+impl TarHeader {
+    fn encode(&self) -> Result<[u8; BLOCK]> {
+        let mut block = [0u8; BLOCK];
+        let (prefix, name) = split_path(&self.path)?;
+        block[..name.len()].copy_from_slice(name.as_bytes());
+        block[345..345 + prefix.len()].copy_from_slice(prefix.as_bytes());
+        octal(&mut block[100..108], 0o644);
+        octal(&mut block[108..116], 0);
+        octal(&mut block[116..124], 0);
+        encode_size(&mut block[124..136], self.size);
+        octal(&mut block[136..148], self.mtime);
+        block[156] = b'0';
+        block[257..263].copy_from_slice(b"ustar\0");
+        block[263..265].copy_from_slice(b"00");
+
+        block[148..156].fill(b' ');
+        let checksum: u32 = block.iter().map(|&b| b as u32).sum();
+        octal(&mut block[148..155], checksum as u64);
+        block[155] = b' ';
+        Ok(block)
+    }
+
+    /// The header of `block`, `None` for a directory entry
+    fn decode(block: &[u8; BLOCK]) -> Result<Option<Self>> {
+        if &block[257..262] != b"ustar" {
+            return Err(anyhow!("Not a ustar archive entry"));
+        }
+        let recorded = parse_octal(&block[148..156])?;
+        let actual: u64 = block
+            .iter()
+            .enumerate()
+            .map(|(i, &b)| if (148..156).contains(&i) { b' ' } else { b } as u64)
+            .sum();
+        if recorded != actual {
+            return Err(anyhow!("Archive header checksum mismatch"));
+        }
+
+        let name = field_str(&block[..100])?;
+        let prefix = field_str(&block[345..500])?;
+        let path = match prefix.is_empty() {
+            true => name.to_string(),
+            false => format!("{prefix}/{name}"),
+        };
+        match block[156] {
+            b'0' | 0 => Ok(Some(Self {
+                path,
+                size: parse_size(&block[124..136])?,
+                mtime: parse_octal(&block[136..148])?,
+            })),
+            b'5' => Ok(None),
+            kind => Err(anyhow!(
+                "Unsupported archive entry type {:?} for {path}",
+                kind as char
+            )),
+        }
+    }
+}
+
+/// Splits `path` into the ustar prefix and name fields
+fn split_path(path: &str) -> Result<(&str, &str)> {
+    if path.len() <= 100 {
+        return Ok(("", path));
+    }
+    path.match_indices('/')
+        .map(|(i, _)| (&path[..i], &path[i + 1..]))
+        .find(|(prefix, name)| prefix.len() <= 155 && name.len() <= 100 && !name.is_empty())
+        .ok_or_else(|| anyhow!("Path {path} is too long for a tar archive"))
+}
+
+/// Writes `value` as zero padded octal digits followed by a NUL
+fn octal(field: &mut [u8], value: u64) {
+    let digits = format!("{value:0width$o}", width = field.len() - 1);
+    field[..digits.len()].copy_from_slice(digits.as_bytes());
+    field[digits.len()] = 0;
+}
+
+fn encode_size(field: &mut [u8], size: u64) {
+    if size <= MAX_OCTAL_SIZE {
+        octal(field, size);
+    } else {
+        field.fill(0);
+        field[0] = 0x80;
+        let len = field.len();
+        field[len - 8..].copy_from_slice(&size.to_be_bytes());
+    }
+}
+
+fn parse_size(field: &[u8]) -> Result<u64> {
+    if field[0] & 0x80 == 0 {
+        return parse_octal(field);
+    }
+    let (high, low) = field.split_at(field.len() - 8);
+    if high[0] != 0x80 || high[1..].iter().any(|&b| b != 0) {
+        return Err(anyhow!("Archive entry size out of range"));
+    }
+    Ok(u64::from_be_bytes(low.try_into().unwrap()))
+}
+
+fn parse_octal(field: &[u8]) -> Result<u64> {
+    let text = field_str(field)?.trim_matches(' ');
+    if text.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(text, 8).map_err(|_| anyhow!("Invalid archive header field {text:?}"))
+}
+
+fn field_str(field: &[u8]) -> Result<&str> {
+    let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    std::str::from_utf8(&field[..end]).map_err(|_| anyhow!("Archive header is not UTF-8"))
+}
+
+fn padding(size: u64) -> usize {
+    (BLOCK - (size % BLOCK as u64) as usize) % BLOCK
+}
+
+/// Appends file entries to a tar stream
+pub struct TarWriter<W: Write> {
+    sink: W,
+}
+
+impl<W: Write> TarWriter<W> {
+    pub fn new(sink: W) -> Self {
+        Self { sink }
+    }
+
+    /// Appends the entry `header`, copying exactly `header.size` bytes of
+    /// `data`
+    pub fn append(&mut self, header: &TarHeader, data: impl Read) -> Result<()> {
+        self.sink.write_all(&header.encode()?)?;
+        let copied = io::copy(&mut data.take(header.size), &mut self.sink)?;
+        if copied != header.size {
+            return Err(anyhow!(
+                "{} changed while archived: expected {} bytes, read {copied}",
+                header.path,
+                header.size
+            ));
+        }
+        self.sink.write_all(&[0; BLOCK][..padding(header.size)])?;
+        Ok(())
+    }
+
+    /// Writes the end of archive marker, returning the sink
+    pub fn finish(mut self) -> Result<W> {
+        self.sink.write_all(&[0; 2 * BLOCK])?;
+        self.sink.flush()?;
+        Ok(self.sink)
+    }
+}
+
+/// Reads the file entries of a tar stream in order
+pub struct TarReader<R: Read> {
+    source: R,
+    /// Unread bytes of the current entry, then its padding
+    remaining: u64,
+    padding: usize,
+}
+
+impl<R: Read> TarReader<R> {
+    pub fn new(source: R) -> Self {
+        Self {
+            source,
+            remaining: 0,
+            padding: 0,
+        }
+    }
+
+    /// The header of the next file entry, skipping what is left of the
+    /// current one, or `None` at the end of the archive
+    pub fn next_entry(&mut self) -> Result<Option<TarHeader>> {
+        self.skip_entry()?;
+        let mut block = [0u8; BLOCK];
+        loop {
+            self.source.read_exact(&mut block)?;
+            if block.iter().all(|&b| b == 0) {
+                return Ok(None);
+            }
+            if let Some(header) = TarHeader::decode(&block)? {
+                self.remaining = header.size;
+                self.padding = padding(header.size);
+                return Ok(Some(header));
+            }
+        }
+    }
+
+    /// Copies the content of the current entry into `sink`
+    pub fn copy_entry(&mut self, sink: &mut impl Write) -> Result<u64> {
+        let expected = self.remaining;
+        let copied = io::copy(&mut (&mut self.source).take(expected), sink)?;
+        self.remaining -= copied;
+        if copied != expected {
+            return Err(anyhow!("Archive ends within an entry"));
+        }
+        Ok(copied)
+    }
+
+    fn skip_entry(&mut self) -> Result<()> {
+        self.copy_entry(&mut io::sink())?;
+        let mut padding = [0u8; BLOCK];
+        self.source.read_exact(&mut padding[..self.padding])?;
+        self.padding = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(path: &str, size: u64) -> TarHeader {
+        TarHeader {
+            path: path.to_string(),
+            size,
+            mtime: 1_700_000_000,
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let long = format!("{}/zone.1.parquet", "d".repeat(120));
+        let entries = [
+            (header("zone.manifest.json", 3), b"{}\n".to_vec()),
+            (header("zone/zone.1.parquet", 1000), vec![7; 1000]),
+            (header("empty", 0), vec![]),
+            (header(&long, 512), vec![1; 512]),
+        ];
+        let mut writer = TarWriter::new(Vec::new());
+        for (header, data) in &entries {
+            writer.append(header, data.as_slice()).unwrap();
+        }
+        let archive = writer.finish().unwrap();
+        assert_eq!(archive.len() % BLOCK, 0);
+
+        let mut reader = TarReader::new(archive.as_slice());
+        for (expected, data) in &entries {
+            let header = reader.next_entry().unwrap().unwrap();
+            assert_eq!(&header, expected);
+            let mut content = Vec::new();
+            reader.copy_entry(&mut content).unwrap();
+            assert_eq!(&content, data);
+        }
+        assert_eq!(reader.next_entry().unwrap(), None);
+    }
+
+    #[test]
+    fn test_skips_unread_entries() {
+        let mut writer = TarWriter::new(Vec::new());
+        writer
+            .append(&header("a", 700), [1u8; 700].as_slice())
+            .unwrap();
+        writer.append(&header("b", 2), [2u8; 2].as_slice()).unwrap();
+        let archive = writer.finish().unwrap();
+
+        let mut reader = TarReader::new(archive.as_slice());
+        assert_eq!(reader.next_entry().unwrap().unwrap().path, "a");
+        let b = reader.next_entry().unwrap().unwrap();
+        assert_eq!(b.path, "b");
+        let mut content = Vec::new();
+        reader.copy_entry(&mut content).unwrap();
+        assert_eq!(content, [2, 2]);
+    }
+
+    #[test]
+    fn test_sizes_over_8_gib_use_base_256() {
+        let size = 10 * 1024 * 1024 * 1024u64;
+        let block = header("zone.parquet", size).encode().unwrap();
+        assert_eq!(block[124], 0x80);
+        let decoded = TarHeader::decode(&block).unwrap().unwrap();
+        assert_eq!(decoded.size, size);
+
+        // Sizes that fit stay octal for every reader
+        let block = header("zone.parquet", MAX_OCTAL_SIZE).encode().unwrap();
+        assert_eq!(&block[124..136], b"77777777777\0");
+    }
+
+    #[test]
+    fn test_rejects_corrupt_header() {
+        let mut block = header("zone.parquet", 1).encode().unwrap();
+        block[0] = b'x';
+        assert!(TarHeader::decode(&block).is_err());
+        assert!(split_path(&"x".repeat(101)).is_err());
+    }
+
+    #[test]
+    fn test_truncated_file_fails() {
+        let mut writer = TarWriter::new(Vec::new());
+        let err = writer
+            .append(&header("zone.parquet", 10), [0u8; 4].as_slice())
+            .unwrap_err();
+        assert!(err.to_string().contains("changed while archived"), "{err}");
+    }
+}
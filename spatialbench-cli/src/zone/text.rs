@@ -0,0 +1,212 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! CSV and TBL encoding of zone batches
+//!
+//! Rows are written as the other tables write them: CSV with a header and
+//! quoted fields where needed, TBL as `|` terminated fields without a
+//! header. `z_boundary` is written as WKT, or as is once encoded as GeoJSON,
+//! other values as Arrow displays them, and nulls as empty fields.
+
+use anyhow::{anyhow, Result};
+use arrow::array::AsArray;
+use arrow::compute::cast;
+use arrow::util::display::{ArrayFormatter, FormatOptions};
+use arrow_array::{Array, RecordBatch};
+use arrow_schema::{DataType, SchemaRef};
+use geozero::wkb::Wkb;
+use geozero::ToWkt;
+use std::io::Write;
+
+/// Geometry column written as WKT
+const GEOMETRY_COLUMN: &str = "z_boundary";
+
+/// Delimited text format of the zone files
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextFormat {
+    Csv,
+    Tbl,
+}
+
+/// Writes zone batches as delimited text rows as they arrive
+pub struct TextWriter<W: Write> {
+    sink: W,
+    format: TextFormat,
+    schema: SchemaRef,
+    batches: usize,
+}
+
+impl<W: Write> TextWriter<W> {
+    /// Starts the file, writing the CSV header
+    pub fn try_new(mut sink: W, format: TextFormat, schema: SchemaRef) -> Result<Self> {
+        if format == TextFormat::Csv {
+            let header: Vec<String> = schema
+                .fields()
+                .iter()
+                .map(|f| csv_field(f.name()))
+                .collect();
+            writeln!(sink, "{}", header.join(","))?;
+        }
+        Ok(Self {
+            sink,
+            format,
+            schema,
+            batches: 0,
+        })
+    }
+
+    pub fn write(&mut self, batch: &RecordBatch) -> Result<()> {
+        let columns = self
+            .schema
+            .fields()
+            .iter()
+            .map(|field| {
+                let column = batch
+                    .column_by_name(field.name())
+                    .ok_or_else(|| anyhow!("Column {} not found in zone batch", field.name()))?;
+                TextColumn::try_new(field.name(), column.as_ref())
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut line = String::new();
+        for row in 0..batch.num_rows() {
+            line.clear();
+            for (i, column) in columns.iter().enumerate() {
+                let value = column.value(row)?;
+                match self.format {
+                    TextFormat::Csv => {
+                        if i > 0 {
+                            line.push(',');
+                        }
+                        line.push_str(&csv_field(&value));
+                    }
+                    TextFormat::Tbl => {
+                        line.push_str(&value);
+                        line.push('|');
+                    }
+                }
+            }
+            line.push('\n');
+            self.sink.write_all(line.as_bytes())?;
+        }
+        self.batches += 1;
+        Ok(())
+    }
+
+    /// Number of batches written
+    pub fn batches_written(&self) -> usize {
+        self.batches
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.sink
+    }
+
+    /// Flushes the rows written, returning the sink
+    pub fn finish(mut self) -> Result<W> {
+        self.sink.flush()?;
+        Ok(self.sink)
+    }
+}
+
+/// How the values of one column are rendered
+enum TextColumn<'a> {
+    Wkt(arrow_array::BinaryArray),
+    Display(ArrayFormatter<'a>),
+}
+
+impl<'a> TextColumn<'a> {
+    fn try_new(name: &str, column: &'a dyn Array) -> Result<Self> {
+        // A z_boundary already encoded as GeoJSON is written as is
+        if name == GEOMETRY_COLUMN && column.data_type() != &DataType::Utf8 {
+            let binary = cast(column, &DataType::Binary)?;
+            return Ok(Self::Wkt(binary.as_binary::<i32>().clone()));
+        }
+        const OPTIONS: FormatOptions = FormatOptions::new();
+        Ok(Self::Display(ArrayFormatter::try_new(column, &OPTIONS)?))
+    }
+
+    fn value(&self, row: usize) -> Result<String> {
+        match self {
+            Self::Wkt(wkb) if wkb.is_null(row) => Ok(String::new()),
+            Self::Wkt(wkb) => Wkb(wkb.value(row))
+                .to_wkt()
+                .map_err(|e| anyhow!("Invalid {GEOMETRY_COLUMN} WKB in row {row}: {e}")),
+            Self::Display(formatter) => Ok(formatter.value(row).to_string()),
+        }
+    }
+}
+
+/// `value` quoted when it holds a separator, quote or line break
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zone::fixtures::wkb_point;
+    use arrow_array::{BinaryArray, Int64Array, StringViewArray};
+    use arrow_schema::{Field, Schema};
+    use std::sync::Arc;
+
+    fn batch() -> RecordBatch {
+        let point = wkb_point(1.5, -2.0);
+        RecordBatch::try_new(
+            Arc::new(Schema::new(vec![
+                Field::new("z_zonekey", DataType::Int64, false),
+                Field::new("z_name", DataType::Utf8View, true),
+                Field::new("z_boundary", DataType::Binary, true),
+            ])),
+            vec![
+                Arc::new(Int64Array::from(vec![1, 2])),
+                Arc::new(StringViewArray::from(vec![Some("King, \"WA\""), None])),
+                Arc::new(BinaryArray::from(vec![Some(point.as_slice()), None])),
+            ],
+        )
+        .unwrap()
+    }
+
+    fn encode(format: TextFormat) -> String {
+        let batch = batch();
+        let mut writer = TextWriter::try_new(Vec::new(), format, batch.schema()).unwrap();
+        writer.write(&batch).unwrap();
+        String::from_utf8(writer.finish().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_csv_quotes_fields() {
+        assert_eq!(
+            encode(TextFormat::Csv),
+            "z_zonekey,z_name,z_boundary\n\
+             1,\"King, \"\"WA\"\"\",POINT(1.5 -2)\n\
+             2,,\n"
+        );
+    }
+
+    #[test]
+    fn test_tbl_terminates_fields() {
+        assert_eq!(
+            encode(TextFormat::Tbl),
+            "1|King, \"WA\"|POINT(1.5 -2)|\n2|||\n"
+        );
+    }
+}
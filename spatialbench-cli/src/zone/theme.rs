@@ -0,0 +1,168 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Overture input themes that can be combined into the zone table
+
+use anyhow::{anyhow, Result};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use url::Url;
+
+use super::datasource::REMOTE_SCHEMES;
+
+/// Columns every theme is projected to before the themes are unioned.
+///
+/// These are the only source columns the zone transform reads.
+pub const ZONE_SOURCE_COLUMNS: &[&str] =
+    &["id", "country", "region", "names", "subtype", "geometry"];
+
+/// Top-level column holding the primary name in inputs without a `names`
+/// struct
+pub const FLAT_NAME_COLUMN: &str = "name";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Theme {
+    /// `divisions/division_area` polygons, filtered by the scale factor's subtypes
+    DivisionArea,
+    /// `divisions/division` features with `subtype = 'locality'`
+    Locality,
+}
+
+impl Theme {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Theme::DivisionArea => "division_area",
+            Theme::Locality => "locality",
+        }
+    }
+}
+
+impl Display for Theme {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// A theme and where to read it from
+#[derive(Clone, Debug, PartialEq)]
+pub struct ThemeInput {
+    pub theme: Theme,
+    /// Local path or URL; `None` uses the built-in source for the theme
+    pub location: Option<String>,
+}
+
+impl ThemeInput {
+    pub fn built_in(theme: Theme) -> Self {
+        Self {
+            theme,
+            location: None,
+        }
+    }
+
+    /// `theme` read from the remote `url`, an `http://`, `https://` or
+    /// `s3://` URL
+    pub fn remote(theme: Theme, url: &str) -> Result<Self> {
+        let parsed = Url::parse(url).map_err(|e| anyhow!("Invalid input URL {url:?}: {e}"))?;
+        if !REMOTE_SCHEMES.contains(&parsed.scheme()) {
+            return Err(anyhow!(
+                "Unsupported input URL {url:?}, expected an http://, https:// or s3:// URL"
+            ));
+        }
+        Ok(Self {
+            theme,
+            location: Some(url.to_string()),
+        })
+    }
+
+    /// Parses a comma separated list such as `division_area,locality=/data/div`
+    pub fn parse_list(spec: &str) -> Result<Vec<Self>> {
+        let inputs = spec
+            .split(',')
+            .filter(|s| !s.trim().is_empty())
+            .map(Self::from_str)
+            .collect::<Result<Vec<_>>>()?;
+
+        if inputs.is_empty() {
+            return Err(anyhow!("--input-theme must name at least one theme"));
+        }
+        for (i, input) in inputs.iter().enumerate() {
+            if inputs[..i].iter().any(|other| other.theme == input.theme) {
+                return Err(anyhow!("Input theme {} listed more than once", input.theme));
+            }
+        }
+        Ok(inputs)
+    }
+}
+
+impl FromStr for ThemeInput {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (name, location) = match s.split_once('=') {
+            Some((name, location)) => (name.trim(), Some(location.trim().to_string())),
+            None => (s.trim(), None),
+        };
+        let theme = match name {
+            "division_area" => Theme::DivisionArea,
+            "locality" => Theme::Locality,
+            _ => {
+                return Err(anyhow!(
+                    "Unknown input theme {name:?}, expected division_area or locality"
+                ))
+            }
+        };
+        Ok(Self { theme, location })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_theme_list() {
+        let inputs = ThemeInput::parse_list("division_area, locality=/data/division").unwrap();
+        assert_eq!(
+            inputs,
+            vec![
+                ThemeInput::built_in(Theme::DivisionArea),
+                ThemeInput {
+                    theme: Theme::Locality,
+                    location: Some("/data/division".to_string()),
+                },
+            ]
+        );
+
+        assert!(ThemeInput::parse_list("division_area,division_area").is_err());
+        assert!(ThemeInput::parse_list("building").is_err());
+        assert!(ThemeInput::parse_list("").is_err());
+    }
+
+    #[test]
+    fn test_remote_input() {
+        let input =
+            ThemeInput::remote(Theme::DivisionArea, "s3://overture/division_area/").unwrap();
+        assert_eq!(
+            input.location.as_deref(),
+            Some("s3://overture/division_area/")
+        );
+        assert!(ThemeInput::remote(Theme::DivisionArea, "https://example.com/a.parquet").is_ok());
+        let error = ThemeInput::remote(Theme::DivisionArea, "gs://bucket/a.parquet").unwrap_err();
+        assert!(error.to_string().contains("Unsupported input URL"));
+        assert!(ThemeInput::remote(Theme::DivisionArea, "/data/a.parquet").is_err());
+    }
+}
@@ -0,0 +1,1003 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Vector tile export of a zone dataset
+//!
+//! Writes a single-file PMTiles v3 archive of Mapbox Vector Tiles (v2) with
+//! one `zone` layer whose features carry `z_zonekey`, `z_name` and
+//! `z_subtype`. Tiles are built one zoom level at a time from a fresh scan
+//! of the parts, so memory holds the tiles of a single zoom; finished tiles
+//! are gzipped into a spill file next to the archive and copied behind the
+//! directories once every zoom is done. Polygons are projected to Web
+//! Mercator, simplified at one tile unit of their zoom, and clipped to the
+//! tile with a small buffer.
+
+use anyhow::{anyhow, Context, Result};
+use arrow::compute::cast;
+use arrow_array::cast::AsArray;
+use arrow_array::types::Int64Type;
+use arrow_array::{Array, RecordBatch};
+use arrow_schema::DataType;
+use flate2::write::GzEncoder;
+use geo::{Geometry, MultiPolygon, Polygon, Simplify};
+use geozero::wkb::Wkb;
+use geozero::ToGeo;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use super::protobuf::{put_varint, zigzag, Message};
+use super::verify::discover_files;
+
+pub const DEFAULT_MIN_ZOOM: u8 = 0;
+pub const DEFAULT_MAX_ZOOM: u8 = 8;
+/// Deepest zoom exported; tiles of one zoom are held in memory together
+pub const MAX_ZOOM: u8 = 16;
+
+/// Name of the vector tile layer
+pub const LAYER: &str = "zone";
+
+/// Tile coordinate extent of the vector tiles
+const EXTENT: f64 = 4096.0;
+/// Tile units drawn beyond each tile edge, so strokes join across tiles
+const BUFFER: f64 = 64.0;
+/// Douglas-Peucker tolerance in tile units of the zoom being built
+const TOLERANCE: f64 = 1.0;
+/// Latitude limit of Web Mercator
+const MAX_LATITUDE: f64 = 85.051_128_779_806_59;
+
+const HEADER_BYTES: usize = 127;
+/// Bytes the header and the root directory may take together
+const ROOT_BYTES: usize = 16_384;
+/// Entries per leaf directory, doubled until the root directory fits
+const LEAF_ENTRIES: usize = 4096;
+
+const GZIP: u8 = 2;
+const MVT: u8 = 1;
+
+/// Feature attributes in their layer key order
+const KEYS: [&str; 3] = ["z_zonekey", "z_name", "z_subtype"];
+const GEOMETRY_COLUMN: &str = "z_boundary";
+
+#[derive(Debug, Serialize)]
+pub struct TilesReport {
+    /// Zones read with a polygonal geometry
+    pub features: u64,
+    /// Tiles written per zoom level
+    pub tiles: BTreeMap<u8, u64>,
+    /// Size of the archive in bytes
+    pub bytes: u64,
+}
+
+/// Exports every zone file in `data_dir` as vector tiles of zooms
+/// `min_zoom..=max_zoom` to the PMTiles archive at `output`
+pub fn export_tiles(
+    data_dir: &Path,
+    output: &Path,
+    min_zoom: u8,
+    max_zoom: u8,
+) -> Result<TilesReport> {
+    if min_zoom > max_zoom {
+        return Err(anyhow!(
+            "--min-zoom {min_zoom} is greater than --max-zoom {max_zoom}"
+        ));
+    }
+    if max_zoom > MAX_ZOOM {
+        return Err(anyhow!("--max-zoom must be at most {MAX_ZOOM}"));
+    }
+    let files: Vec<PathBuf> = discover_files(data_dir)?
+        .into_values()
+        .map(|file| file.path)
+        .collect();
+    if files.is_empty() {
+        return Err(anyhow!("No zone files found in {}", data_dir.display()));
+    }
+
+    let spill_path = output.with_extension("tiles.tmp");
+    let result = write_archive(&files, output, &spill_path, min_zoom, max_zoom);
+    let _ = std::fs::remove_file(&spill_path);
+    result
+}
+
+fn write_archive(
+    files: &[PathBuf],
+    output: &Path,
+    spill_path: &Path,
+    min_zoom: u8,
+    max_zoom: u8,
+) -> Result<TilesReport> {
+    let mut spill = BufWriter::new(File::create(spill_path)?);
+    let mut entries = Vec::new();
+    let mut spilled = 0u64;
+    let mut report = TilesReport {
+        features: 0,
+        tiles: BTreeMap::new(),
+        bytes: 0,
+    };
+    let mut bounds = Bounds::default();
+
+    for zoom in min_zoom..=max_zoom {
+        let mut tiles: HashMap<(u32, u32), TileLayer> = HashMap::new();
+        let mut features = 0;
+        for path in files {
+            let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(path)?)?.build()?;
+            for batch in reader {
+                features += add_batch(&batch?, zoom, &mut tiles, &mut bounds)?;
+            }
+        }
+        report.features = features;
+
+        let mut tiles: Vec<_> = tiles
+            .into_iter()
+            .map(|((x, y), layer)| (tile_id(zoom, x, y), layer))
+            .collect();
+        tiles.sort_unstable_by_key(|(id, _)| *id);
+        report.tiles.insert(zoom, tiles.len() as u64);
+        for (id, layer) in tiles {
+            let tile = gzip(&Message::default().message(3, layer.finish()).0)?;
+            spill.write_all(&tile)?;
+            entries.push(Entry {
+                tile_id: id,
+                offset: spilled,
+                length: tile.len() as u32,
+                run_length: 1,
+            });
+            spilled += tile.len() as u64;
+        }
+    }
+    spill.flush()?;
+    drop(spill);
+
+    let metadata = gzip(
+        serde_json::json!({
+            "name": LAYER,
+            "format": "pbf",
+            "vector_layers": [{
+                "id": LAYER,
+                "fields": {
+                    "z_zonekey": "Number",
+                    "z_name": "String",
+                    "z_subtype": "String",
+                },
+                "minzoom": min_zoom,
+                "maxzoom": max_zoom,
+            }],
+        })
+        .to_string()
+        .as_bytes(),
+    )?;
+    let (root, leaves) = build_directories(&entries)?;
+
+    let header = Header {
+        root_length: root.len() as u64,
+        metadata_length: metadata.len() as u64,
+        leaves_length: leaves.len() as u64,
+        data_length: spilled,
+        tiles: entries.len() as u64,
+        min_zoom,
+        max_zoom,
+        bounds: bounds.finish(),
+    };
+    let mut out = BufWriter::new(File::create(output)?);
+    out.write_all(&header.to_bytes())?;
+    out.write_all(&root)?;
+    out.write_all(&metadata)?;
+    out.write_all(&leaves)?;
+    std::io::copy(&mut File::open(spill_path)?, &mut out)?;
+    out.flush()?;
+
+    report.bytes = std::fs::metadata(output)?.len();
+    Ok(report)
+}
+
+/// Adds the zones of `batch` to the tiles of `zoom` they overlap and returns
+/// the number of zones with a polygonal geometry
+fn add_batch(
+    batch: &RecordBatch,
+    zoom: u8,
+    tiles: &mut HashMap<(u32, u32), TileLayer>,
+    bounds: &mut Bounds,
+) -> Result<u64> {
+    let column = |name: &str, data_type: &DataType| {
+        let column = batch
+            .column_by_name(name)
+            .with_context(|| format!("Zone file has no {name} column"))?;
+        Ok::<_, anyhow::Error>(cast(column, data_type)?)
+    };
+    let keys = column(KEYS[0], &DataType::Int64)?;
+    let keys = keys.as_primitive::<Int64Type>();
+    let names = column(KEYS[1], &DataType::Utf8)?;
+    let names = names.as_string::<i32>();
+    let subtypes = column(KEYS[2], &DataType::Utf8)?;
+    let subtypes = subtypes.as_string::<i32>();
+    let geometries = column(GEOMETRY_COLUMN, &DataType::Binary)?;
+    let geometries = geometries.as_binary::<i32>();
+
+    let tiles_across = 1u32 << zoom;
+    let scale = tiles_across as f64 * EXTENT;
+    let mut features = 0;
+    for row in 0..batch.num_rows() {
+        if geometries.is_null(row) {
+            continue;
+        }
+        let Some(polygons) = Wkb(geometries.value(row)).to_geo().ok().and_then(polygons) else {
+            continue;
+        };
+        features += 1;
+        bounds.update(&polygons);
+
+        let projected = project(&polygons, scale).simplify(TOLERANCE);
+        let Some((min, max)) = extent(&projected) else {
+            continue;
+        };
+        let tile_range = |min: f64, max: f64| {
+            let first = ((min - BUFFER) / EXTENT).floor().max(0.0) as u32;
+            let last = (((max + BUFFER) / EXTENT).floor() as u32).min(tiles_across - 1);
+            first..=last
+        };
+
+        let attributes = [
+            (!keys.is_null(row)).then(|| TileValue::Int(keys.value(row))),
+            (!names.is_null(row)).then(|| TileValue::String(names.value(row).to_string())),
+            (!subtypes.is_null(row)).then(|| TileValue::String(subtypes.value(row).to_string())),
+        ];
+        for x in tile_range(min.0, max.0) {
+            for y in tile_range(min.1, max.1) {
+                let geometry = encode_polygons(&projected, x, y);
+                if geometry.is_empty() {
+                    continue;
+                }
+                let id = (!keys.is_null(row)).then(|| keys.value(row) as u64);
+                tiles
+                    .entry((x, y))
+                    .or_default()
+                    .push(id, &attributes, geometry);
+            }
+        }
+    }
+    Ok(features)
+}
+
+/// The polygons of a zone geometry, if it has any
+fn polygons(geometry: Geometry<f64>) -> Option<MultiPolygon<f64>> {
+    let polygons: Vec<Polygon<f64>> = match geometry {
+        Geometry::Polygon(polygon) => vec![polygon],
+        Geometry::MultiPolygon(multi) => multi.0,
+        Geometry::GeometryCollection(collection) => collection
+            .0
+            .into_iter()
+            .filter_map(polygons)
+            .flat_map(|multi| multi.0)
+            .collect(),
+        _ => Vec::new(),
+    };
+    (!polygons.is_empty()).then_some(MultiPolygon(polygons))
+}
+
+/// Projects lon/lat polygons to Web Mercator scaled so the world spans
+/// `0..scale`, with y growing southward as in tile coordinates
+fn project(polygons: &MultiPolygon<f64>, scale: f64) -> MultiPolygon<f64> {
+    use geo::MapCoords;
+    polygons.map_coords(|c| {
+        let lat = c.y.clamp(-MAX_LATITUDE, MAX_LATITUDE).to_radians();
+        geo::coord! {
+            x: (c.x + 180.0) / 360.0 * scale,
+            y: (1.0 - lat.tan().asinh() / std::f64::consts::PI) / 2.0 * scale,
+        }
+    })
+}
+
+fn extent(polygons: &MultiPolygon<f64>) -> Option<((f64, f64), (f64, f64))> {
+    use geo::BoundingRect;
+    polygons
+        .bounding_rect()
+        .map(|rect| (rect.min().x_y(), rect.max().x_y()))
+}
+
+/// MVT geometry commands of `polygons` clipped to tile `(x, y)`, empty if
+/// nothing of them is left in the tile
+fn encode_polygons(polygons: &MultiPolygon<f64>, x: u32, y: u32) -> Vec<u32> {
+    let origin = (x as f64 * EXTENT, y as f64 * EXTENT);
+    let clip = (-BUFFER, EXTENT + BUFFER);
+    let mut commands = Vec::new();
+    let mut cursor = (0, 0);
+    for polygon in &polygons.0 {
+        let rings = std::iter::once(polygon.exterior()).chain(polygon.interiors());
+        for (index, ring) in rings.enumerate() {
+            let points: Vec<(f64, f64)> = ring
+                .coords()
+                .map(|c| (c.x - origin.0, c.y - origin.1))
+                .collect();
+            let ring = quantize(&clip_ring(&points, clip.0, clip.1));
+            let area = signed_area(&ring);
+            if area == 0 {
+                // A polygon whose exterior vanished keeps none of its holes
+                if index == 0 {
+                    break;
+                }
+                continue;
+            }
+            // Exterior rings have a positive area in tile coordinates
+            let reverse = (index == 0) != (area > 0);
+            let ring: Vec<(i64, i64)> = match reverse {
+                true => ring.into_iter().rev().collect(),
+                false => ring,
+            };
+            encode_ring(&ring, &mut cursor, &mut commands);
+        }
+    }
+    commands
+}
+
+/// Clips a ring to the square `min..=max` on both axes, Sutherland-Hodgman
+/// style; the result is an open ring
+fn clip_ring(points: &[(f64, f64)], min: f64, max: f64) -> Vec<(f64, f64)> {
+    let mut ring: Vec<(f64, f64)> = points.to_vec();
+    if ring.len() > 1 && ring.first() == ring.last() {
+        ring.pop();
+    }
+    type Inside = fn((f64, f64), f64) -> bool;
+    type Cut = fn((f64, f64), (f64, f64), f64) -> (f64, f64);
+    let cut_x: Cut = |a, b, x| (x, a.1 + (b.1 - a.1) * (x - a.0) / (b.0 - a.0));
+    let cut_y: Cut = |a, b, y| (a.0 + (b.0 - a.0) * (y - a.1) / (b.1 - a.1), y);
+    let edges: [(Inside, Cut, f64); 4] = [
+        (|p, v| p.0 >= v, cut_x, min),
+        (|p, v| p.0 <= v, cut_x, max),
+        (|p, v| p.1 >= v, cut_y, min),
+        (|p, v| p.1 <= v, cut_y, max),
+    ];
+    for (inside, cut, value) in edges {
+        if ring.is_empty() {
+            break;
+        }
+        let input = std::mem::take(&mut ring);
+        let mut previous = input[input.len() - 1];
+        for &point in &input {
+            match (inside(point, value), inside(previous, value)) {
+                (true, true) => ring.push(point),
+                (true, false) => {
+                    ring.push(cut(previous, point, value));
+                    ring.push(point);
+                }
+                (false, true) => ring.push(cut(previous, point, value)),
+                (false, false) => {}
+            }
+            previous = point;
+        }
+    }
+    ring
+}
+
+/// Rounds an open ring to integer tile coordinates, dropping repeated points
+fn quantize(ring: &[(f64, f64)]) -> Vec<(i64, i64)> {
+    let mut points: Vec<(i64, i64)> = Vec::with_capacity(ring.len());
+    for &(x, y) in ring {
+        let point = (x.round() as i64, y.round() as i64);
+        if points.last() != Some(&point) {
+            points.push(point);
+        }
+    }
+    while points.len() > 1 && points.first() == points.last() {
+        points.pop();
+    }
+    points
+}
+
+/// Twice the area of an open ring by the surveyor's formula
+fn signed_area(ring: &[(i64, i64)]) -> i64 {
+    if ring.len() < 3 {
+        return 0;
+    }
+    (0..ring.len())
+        .map(|i| {
+            let (a, b) = (ring[i], ring[(i + 1) % ring.len()]);
+            a.0 * b.1 - b.0 * a.1
+        })
+        .sum()
+}
+
+fn encode_ring(ring: &[(i64, i64)], cursor: &mut (i64, i64), commands: &mut Vec<u32>) {
+    let command = |id: u32, count: usize| (id & 7) | ((count as u32) << 3);
+    let mut delta = |point: (i64, i64), commands: &mut Vec<u32>| {
+        commands.push(zigzag(point.0 - cursor.0) as u32);
+        commands.push(zigzag(point.1 - cursor.1) as u32);
+        *cursor = point;
+    };
+    commands.push(command(1, 1));
+    delta(ring[0], commands);
+    commands.push(command(2, ring.len() - 1));
+    for &point in &ring[1..] {
+        delta(point, commands);
+    }
+    commands.push(command(7, 1));
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum TileValue {
+    Int(i64),
+    String(String),
+}
+
+/// The `zone` layer of one tile, its features encoded as they are added
+#[derive(Default)]
+struct TileLayer {
+    features: Vec<Message>,
+    values: Vec<TileValue>,
+    value_index: HashMap<TileValue, u32>,
+}
+
+impl TileLayer {
+    fn push(&mut self, id: Option<u64>, attributes: &[Option<TileValue>], geometry: Vec<u32>) {
+        let mut tags = Vec::with_capacity(attributes.len() * 2);
+        for (key, value) in attributes.iter().enumerate() {
+            let Some(value) = value else {
+                continue;
+            };
+            let index = match self.value_index.get(value) {
+                Some(index) => *index,
+                None => {
+                    let index = self.values.len() as u32;
+                    self.values.push(value.clone());
+                    self.value_index.insert(value.clone(), index);
+                    index
+                }
+            };
+            tags.extend([key as u64, index as u64]);
+        }
+
+        let mut feature = Message::default();
+        if let Some(id) = id {
+            feature = feature.uint(1, id);
+        }
+        self.features.push(
+            feature
+                .packed(2, tags)
+                // POLYGON
+                .uint(3, 3)
+                .packed(4, geometry.into_iter().map(u64::from)),
+        );
+    }
+
+    fn finish(self) -> Message {
+        let mut layer = Message::default().uint(15, 2).bytes(1, LAYER.as_bytes());
+        for feature in self.features {
+            layer = layer.message(2, feature);
+        }
+        for key in KEYS {
+            layer = layer.bytes(3, key.as_bytes());
+        }
+        for value in self.values {
+            let value = match value {
+                TileValue::Int(value) => Message::default().uint(4, value as u64),
+                TileValue::String(value) => Message::default().bytes(1, value.as_bytes()),
+            };
+            layer = layer.message(4, value);
+        }
+        layer.uint(5, EXTENT as u64)
+    }
+}
+
+/// Position of tile `(x, y)` of `zoom` on the Hilbert curves of all zooms,
+/// the PMTiles tile ID
+fn tile_id(zoom: u8, x: u32, y: u32) -> u64 {
+    let zoom = zoom as u32;
+    let before = ((1u64 << (2 * zoom)) - 1) / 3;
+    let (mut x, mut y) = (x as u64, y as u64);
+    let mut distance = 0;
+    let mut s = (1u64 << zoom) / 2;
+    while s > 0 {
+        let rx = (x & s > 0) as u64;
+        let ry = (y & s > 0) as u64;
+        distance += s * s * ((3 * rx) ^ ry);
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - (x & (s - 1));
+                y = s - 1 - (y & (s - 1));
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    before + distance
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    tile_id: u64,
+    offset: u64,
+    length: u32,
+    /// Zero for an entry pointing at a leaf directory
+    run_length: u32,
+}
+
+/// Gzipped root directory and the leaf directories it points at, split so
+/// the root fits the first 16 KiB of the archive with the header
+fn build_directories(entries: &[Entry]) -> Result<(Vec<u8>, Vec<u8>)> {
+    let root = serialize_directory(entries)?;
+    if root.len() <= ROOT_BYTES - HEADER_BYTES {
+        return Ok((root, Vec::new()));
+    }
+    let mut leaf_entries = LEAF_ENTRIES;
+    loop {
+        let mut leaves = Vec::new();
+        let mut root_entries = Vec::new();
+        for chunk in entries.chunks(leaf_entries) {
+            let leaf = serialize_directory(chunk)?;
+            root_entries.push(Entry {
+                tile_id: chunk[0].tile_id,
+                offset: leaves.len() as u64,
+                length: leaf.len() as u32,
+                run_length: 0,
+            });
+            leaves.extend_from_slice(&leaf);
+        }
+        let root = serialize_directory(&root_entries)?;
+        if root.len() <= ROOT_BYTES - HEADER_BYTES {
+            return Ok((root, leaves));
+        }
+        leaf_entries *= 2;
+    }
+}
+
+/// A directory as PMTiles v3 lays it out: delta coded tile IDs, then run
+/// lengths, lengths and offsets, each a column of varints
+fn serialize_directory(entries: &[Entry]) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    put_varint(&mut out, entries.len() as u64);
+    let mut last_id = 0;
+    for entry in entries {
+        put_varint(&mut out, entry.tile_id - last_id);
+        last_id = entry.tile_id;
+    }
+    for entry in entries {
+        put_varint(&mut out, entry.run_length as u64);
+    }
+    for entry in entries {
+        put_varint(&mut out, entry.length as u64);
+    }
+    for (i, entry) in entries.iter().enumerate() {
+        // Zero marks an entry that directly follows the previous one
+        let contiguous = i > 0 && {
+            let previous = entries[i - 1];
+            entry.offset == previous.offset + previous.length as u64
+        };
+        put_varint(&mut out, if contiguous { 0 } else { entry.offset + 1 });
+    }
+    gzip(&out)
+}
+
+fn gzip(bytes: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(bytes)?;
+    Ok(encoder.finish()?)
+}
+
+/// Lon/lat bounds of the exported zones
+#[derive(Default)]
+struct Bounds(Option<geo::Rect<f64>>);
+
+impl Bounds {
+    fn update(&mut self, polygons: &MultiPolygon<f64>) {
+        use geo::BoundingRect;
+        let Some(rect) = polygons.bounding_rect() else {
+            return;
+        };
+        self.0 = Some(match self.0 {
+            None => rect,
+            Some(bounds) => geo::Rect::new(
+                geo::coord! {
+                    x: bounds.min().x.min(rect.min().x),
+                    y: bounds.min().y.min(rect.min().y),
+                },
+                geo::coord! {
+                    x: bounds.max().x.max(rect.max().x),
+                    y: bounds.max().y.max(rect.max().y),
+                },
+            ),
+        });
+    }
+
+    fn finish(self) -> [f64; 4] {
+        match self.0 {
+            Some(rect) => [rect.min().x, rect.min().y, rect.max().x, rect.max().y],
+            None => [-180.0, -MAX_LATITUDE, 180.0, MAX_LATITUDE],
+        }
+    }
+}
+
+struct Header {
+    root_length: u64,
+    metadata_length: u64,
+    leaves_length: u64,
+    data_length: u64,
+    tiles: u64,
+    min_zoom: u8,
+    max_zoom: u8,
+    /// Min lon, min lat, max lon, max lat
+    bounds: [f64; 4],
+}
+
+impl Header {
+    fn to_bytes(&self) -> Vec<u8> {
+        let root_offset = HEADER_BYTES as u64;
+        let metadata_offset = root_offset + self.root_length;
+        let leaves_offset = metadata_offset + self.metadata_length;
+        let data_offset = leaves_offset + self.leaves_length;
+
+        let mut out = Vec::with_capacity(HEADER_BYTES);
+        out.extend_from_slice(b"PMTiles");
+        out.push(3);
+        for value in [
+            root_offset,
+            self.root_length,
+            metadata_offset,
+            self.metadata_length,
+            leaves_offset,
+            self.leaves_length,
+            data_offset,
+            self.data_length,
+            // Addressed tiles, tile entries and tile contents are equal
+            // without run lengths or deduplication
+            self.tiles,
+            self.tiles,
+            self.tiles,
+        ] {
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+        // Clustered: tiles follow in tile ID order
+        out.extend_from_slice(&[1, GZIP, GZIP, MVT, self.min_zoom, self.max_zoom]);
+        let e7 = |degrees: f64| ((degrees * 1e7).round() as i32).to_le_bytes();
+        let [min_lon, min_lat, max_lon, max_lat] = self.bounds;
+        for degrees in self.bounds {
+            out.extend_from_slice(&e7(degrees));
+        }
+        out.push(self.min_zoom);
+        out.extend_from_slice(&e7((min_lon + max_lon) / 2.0));
+        out.extend_from_slice(&e7((min_lat + max_lat) / 2.0));
+        debug_assert_eq!(out.len(), HEADER_BYTES);
+        out
+    }
+}
+
+/// Reads the archives [`export_tiles`] writes, for tests
+#[cfg(test)]
+pub(super) mod reader {
+    use super::super::protobuf::decode::{fields, repeated, uint, varint};
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    pub struct Archive {
+        pub min_zoom: u8,
+        pub max_zoom: u8,
+        pub metadata: serde_json::Value,
+        /// Gunzipped tiles by tile ID
+        pub tiles: BTreeMap<u64, Vec<u8>>,
+    }
+
+    pub struct Layer {
+        pub name: String,
+        pub version: u64,
+        pub extent: u64,
+        pub features: Vec<Feature>,
+    }
+
+    pub struct Feature {
+        pub id: u64,
+        pub geometry_type: u64,
+        pub attributes: BTreeMap<String, String>,
+        pub geometry: Vec<u32>,
+    }
+
+    fn gunzip(bytes: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        GzDecoder::new(bytes).read_to_end(&mut out).unwrap();
+        out
+    }
+
+    fn u64_at(bytes: &[u8], offset: usize) -> u64 {
+        u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+    }
+
+    fn directory(bytes: &[u8]) -> Vec<Entry> {
+        let bytes = gunzip(bytes);
+        let mut buf = bytes.as_slice();
+        let count = varint(&mut buf).unwrap() as usize;
+        let mut entries = vec![
+            Entry {
+                tile_id: 0,
+                offset: 0,
+                length: 0,
+                run_length: 0,
+            };
+            count
+        ];
+        let mut last_id = 0;
+        for entry in entries.iter_mut() {
+            last_id += varint(&mut buf).unwrap();
+            entry.tile_id = last_id;
+        }
+        for entry in entries.iter_mut() {
+            entry.run_length = varint(&mut buf).unwrap() as u32;
+        }
+        for entry in entries.iter_mut() {
+            entry.length = varint(&mut buf).unwrap() as u32;
+        }
+        for i in 0..count {
+            let offset = varint(&mut buf).unwrap();
+            entries[i].offset = match offset {
+                0 => entries[i - 1].offset + entries[i - 1].length as u64,
+                offset => offset - 1,
+            };
+        }
+        entries
+    }
+
+    pub fn read(bytes: &[u8]) -> Archive {
+        assert_eq!(&bytes[..7], b"PMTiles");
+        assert_eq!(bytes[7], 3);
+        let section = |index: usize| {
+            let offset = u64_at(bytes, 8 + index * 16) as usize;
+            let length = u64_at(bytes, 16 + index * 16) as usize;
+            &bytes[offset..offset + length]
+        };
+        let (root, metadata, leaves, data) = (section(0), section(1), section(2), section(3));
+
+        let mut tiles = BTreeMap::new();
+        for entry in directory(root) {
+            let range = entry.offset as usize..(entry.offset + entry.length as u64) as usize;
+            match entry.run_length {
+                0 => {
+                    for tile in directory(&leaves[range]) {
+                        let range =
+                            tile.offset as usize..(tile.offset + tile.length as u64) as usize;
+                        tiles.insert(tile.tile_id, gunzip(&data[range]));
+                    }
+                }
+                _ => {
+                    tiles.insert(entry.tile_id, gunzip(&data[range]));
+                }
+            }
+        }
+        Archive {
+            min_zoom: bytes[100],
+            max_zoom: bytes[101],
+            metadata: serde_json::from_slice(&gunzip(metadata)).unwrap(),
+            tiles,
+        }
+    }
+
+    /// The single layer of a tile
+    pub fn layer(tile: &[u8]) -> Layer {
+        let tile = fields(tile).unwrap();
+        let layers = repeated(&tile, 3);
+        assert_eq!(layers.len(), 1);
+        let layer = fields(layers[0]).unwrap();
+        let keys: Vec<String> = repeated(&layer, 3)
+            .into_iter()
+            .map(|key| String::from_utf8(key.to_vec()).unwrap())
+            .collect();
+        let values: Vec<String> = repeated(&layer, 4)
+            .into_iter()
+            .map(|value| {
+                let value = fields(value).unwrap();
+                match repeated(&value, 1).first() {
+                    Some(string) => String::from_utf8(string.to_vec()).unwrap(),
+                    None => uint(&value, 4).to_string(),
+                }
+            })
+            .collect();
+        let packed = |bytes: &[u8]| {
+            let mut buf = bytes;
+            let mut values = Vec::new();
+            while !buf.is_empty() {
+                values.push(varint(&mut buf).unwrap() as u32);
+            }
+            values
+        };
+        let features = repeated(&layer, 2)
+            .into_iter()
+            .map(|feature| {
+                let feature = fields(feature).unwrap();
+                let tags = packed(repeated(&feature, 2)[0]);
+                Feature {
+                    id: uint(&feature, 1),
+                    geometry_type: uint(&feature, 3),
+                    attributes: tags
+                        .chunks(2)
+                        .map(|tag| {
+                            (
+                                keys[tag[0] as usize].clone(),
+                                values[tag[1] as usize].clone(),
+                            )
+                        })
+                        .collect(),
+                    geometry: packed(repeated(&feature, 4)[0]),
+                }
+            })
+            .collect();
+        Layer {
+            name: String::from_utf8(repeated(&layer, 1)[0].to_vec()).unwrap(),
+            version: uint(&layer, 15),
+            extent: uint(&layer, 5),
+            features,
+        }
+    }
+
+    /// Rings of a polygon geometry as absolute tile coordinates
+    pub fn rings(geometry: &[u32]) -> Vec<Vec<(i64, i64)>> {
+        let unzigzag = |v: u32| ((v >> 1) as i64) ^ -((v & 1) as i64);
+        let mut rings = Vec::new();
+        let mut cursor = (0, 0);
+        let mut i = 0;
+        while i < geometry.len() {
+            let (id, count) = (geometry[i] & 7, (geometry[i] >> 3) as usize);
+            i += 1;
+            match id {
+                1 => rings.push(Vec::new()),
+                7 => continue,
+                _ => {}
+            }
+            for _ in 0..count {
+                cursor.0 += unzigzag(geometry[i]);
+                cursor.1 += unzigzag(geometry[i + 1]);
+                rings.last_mut().unwrap().push(cursor);
+                i += 2;
+            }
+        }
+        rings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zone::fixtures::{wkb_polygon, write_parquet};
+    use arrow_array::{BinaryArray, Int64Array, StringArray};
+    use arrow_schema::{Field, Schema};
+    use std::sync::Arc;
+
+    fn square(lon: f64, lat: f64) -> Vec<u8> {
+        wkb_polygon(&[
+            (lon, lat),
+            (lon + 1.0, lat),
+            (lon + 1.0, lat + 1.0),
+            (lon, lat + 1.0),
+            (lon, lat),
+        ])
+    }
+
+    fn write_zones(dir: &Path) {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("z_zonekey", DataType::Int64, false),
+            Field::new("z_name", DataType::Utf8, true),
+            Field::new("z_subtype", DataType::Utf8, true),
+            Field::new("z_boundary", DataType::Binary, true),
+        ]));
+        let (west, east) = (square(10.0, 10.0), square(100.0, 30.0));
+        let batch = RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(Int64Array::from(vec![1, 2, 3])),
+                Arc::new(StringArray::from(vec![Some("West"), None, Some("East")])),
+                Arc::new(StringArray::from(vec!["county"; 3])),
+                Arc::new(BinaryArray::from(vec![
+                    Some(west.as_slice()),
+                    None,
+                    Some(east.as_slice()),
+                ])),
+            ],
+        )
+        .unwrap();
+        write_parquet(&dir.join("zone.parquet"), &batch);
+    }
+
+    #[test]
+    fn test_tile_ids() {
+        assert_eq!(tile_id(0, 0, 0), 0);
+        assert_eq!(tile_id(1, 0, 0), 1);
+        assert_eq!(tile_id(1, 0, 1), 2);
+        assert_eq!(tile_id(1, 1, 1), 3);
+        assert_eq!(tile_id(1, 1, 0), 4);
+        assert_eq!(tile_id(2, 0, 0), 5);
+        assert_eq!(tile_id(3, 0, 0), 21);
+        // The last tile of a zoom precedes the first of the next
+        assert_eq!(tile_id(3, 7, 0), 84);
+        assert_eq!(tile_id(4, 0, 0), 85);
+    }
+
+    #[test]
+    fn test_clip_ring() {
+        let ring = [(-10.0, 10.0), (10.0, 10.0), (10.0, 20.0), (-10.0, 20.0)];
+        let clipped = quantize(&clip_ring(&ring, 0.0, 15.0));
+        assert_eq!(clipped, vec![(0, 15), (0, 10), (10, 10), (10, 15)]);
+        assert!(clip_ring(&ring, 30.0, 40.0).is_empty());
+    }
+
+    #[test]
+    fn test_export_tiles() {
+        let dir = tempfile::tempdir().unwrap();
+        write_zones(dir.path());
+        let output = dir.path().join("zone.pmtiles");
+
+        let report = export_tiles(dir.path(), &output, 0, 3).unwrap();
+        assert_eq!(report.features, 2);
+        assert_eq!(
+            report.tiles,
+            BTreeMap::from([(0, 1), (1, 1), (2, 2), (3, 2)])
+        );
+        assert!(!output.with_extension("tiles.tmp").exists());
+
+        let archive = reader::read(&std::fs::read(&output).unwrap());
+        assert_eq!((archive.min_zoom, archive.max_zoom), (0, 3));
+        assert_eq!(archive.tiles.len(), 6);
+        assert_eq!(archive.metadata["vector_layers"][0]["id"], LAYER);
+        // Tiles of zoom 3 holding 10°E 10°N and 100°E 30°N
+        assert!(archive.tiles.contains_key(&tile_id(3, 4, 3)));
+        assert!(archive.tiles.contains_key(&tile_id(3, 6, 3)));
+
+        let layer = reader::layer(&archive.tiles[&0]);
+        assert_eq!(layer.name, LAYER);
+        assert_eq!((layer.version, layer.extent), (2, 4096));
+        assert_eq!(layer.features.len(), 2);
+        let west = &layer.features[0];
+        assert_eq!((west.id, west.geometry_type), (1, 3));
+        assert_eq!(
+            west.attributes,
+            BTreeMap::from([
+                ("z_zonekey".to_string(), "1".to_string()),
+                ("z_name".to_string(), "West".to_string()),
+                ("z_subtype".to_string(), "county".to_string()),
+            ])
+        );
+        assert_eq!(layer.features[1].id, 3);
+
+        let rings = reader::rings(&west.geometry);
+        assert_eq!(rings.len(), 1);
+        assert!(signed_area(&rings[0]) > 0);
+        // 10°E lies 190/360 of the way across the world tile
+        let x = rings[0].iter().map(|p| p.0).min().unwrap();
+        assert_eq!(x, (190.0 / 360.0 * EXTENT).round() as i64);
+    }
+
+    #[test]
+    fn test_export_tiles_rejects_zoom_range() {
+        let dir = tempfile::tempdir().unwrap();
+        write_zones(dir.path());
+        let output = dir.path().join("zone.pmtiles");
+        assert!(export_tiles(dir.path(), &output, 4, 2).is_err());
+        assert!(export_tiles(dir.path(), &output, 0, MAX_ZOOM + 1).is_err());
+    }
+
+    #[test]
+    fn test_leaf_directories() {
+        let entries: Vec<Entry> = (0..20_000)
+            .map(|i| Entry {
+                tile_id: i * 3,
+                offset: i * 10,
+                length: 10 + (i % 7) as u32,
+                run_length: 1,
+            })
+            .collect();
+        let (root, leaves) = build_directories(&entries).unwrap();
+        assert!(root.len() <= ROOT_BYTES - HEADER_BYTES);
+        assert!(!leaves.is_empty());
+    }
+}
@@ -15,37 +15,142 @@
 // specific language governing permissions and limitations
 // under the License.
 
-use anyhow::Result;
-use arrow_schema::Schema;
+use anyhow::{anyhow, Result};
+use arrow_array::RecordBatch;
+use arrow_schema::{DataType, Schema, SchemaRef};
 use datafusion::{prelude::*, sql::TableReference};
 use log::{debug, info};
+use std::sync::Arc;
+use tracing::instrument;
+
+use super::area::{self, append_area_column};
+use super::attribution::ATTRIBUTION_COLUMN;
+use super::bbox::{self, append_bbox_column};
+use super::config::{KeyPartition, ZoneTransformOptions};
+use super::country::normalize_country_batches;
+use super::extra_columns::{self, append_extra_columns};
+use super::geometry::normalize_wkb_batches;
+use super::hierarchy::{self, add_hierarchy_columns};
+use super::lonlat::{self, append_lonlat_columns};
+use super::names::{self, common_names_to_json, name_language_column, NAME_COMMON_COLUMN};
+use super::pseudonym::pseudonymize_names;
+use super::simplify::simplify_batches;
+use super::subdivision::{normalize_region_batches, RegionNormalization};
+use super::synthetic::{self, append_synthetic_columns};
+use super::winding::normalize_winding_batches;
+
+pub const SOURCE_COLUMN: &str = "z_source";
+pub const COMPOSITE_KEY_COLUMN: &str = "z_compositekey";
 
 pub struct ZoneTransformer {
     offset: i64,
+    source: String,
+    attribution: String,
 }
 
 impl ZoneTransformer {
     pub fn new(offset: i64) -> Self {
-        Self { offset }
+        Self {
+            offset,
+            source: String::new(),
+            attribution: String::new(),
+        }
+    }
+
+    /// Sets the value of the `z_source` column
+    pub fn with_source(mut self, source: String) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Sets the value of the `z_attribution` column
+    pub fn with_attribution(mut self, attribution: String) -> Self {
+        self.attribution = attribution;
+        self
     }
 
-    pub async fn transform(&self, ctx: &SessionContext, df: DataFrame) -> Result<DataFrame> {
-        ctx.register_table(TableReference::bare("zone_filtered"), df.into_view())?;
+    pub async fn transform(
+        &self,
+        ctx: &SessionContext,
+        options: &ZoneTransformOptions,
+        df: DataFrame,
+    ) -> Result<DataFrame> {
+        let has_common_names =
+            df.schema()
+                .field_with_unqualified_name("names")
+                .is_ok_and(|field| match field.data_type() {
+                    DataType::Struct(fields) => fields.find("common").is_some(),
+                    _ => false,
+                });
+        if options.reads_common_names() && !has_common_names {
+            return Err(anyhow!(
+                "--with-names-common and --names-languages read names.common, which the zone \
+                 input doesn't have"
+            ));
+        }
+
+        let table = TableReference::bare("zone_filtered");
+        ctx.deregister_table(table.clone())?;
+        ctx.register_table(table, df.into_view())?;
         debug!("Registered filtered data as 'zone_filtered' table");
 
+        // Missing languages are empty strings like the other text columns
+        let mut extra_columns = String::new();
+        if options.include_provenance {
+            extra_columns.push_str(&format!(
+                ",\n              '{}' AS {SOURCE_COLUMN}",
+                self.source.replace('\'', "''")
+            ));
+        }
+        if options.attribution_column {
+            extra_columns.push_str(&format!(
+                ",\n              '{}' AS {ATTRIBUTION_COLUMN}",
+                self.attribution.replace('\'', "''")
+            ));
+        }
+        if options.names_common {
+            extra_columns.push_str(&format!(
+                ",\n              names.common AS {NAME_COMMON_COLUMN}"
+            ));
+        }
+        for language in &options.names_languages {
+            extra_columns.push_str(&format!(
+                ",\n              COALESCE(names.common['{language}'], '') AS {}",
+                name_language_column(language)?
+            ));
+        }
+
+        // Keys partitioned by a column are numbered over the whole table, so
+        // the offset of a part doesn't apply to them
+        let key = match options.key_partition {
+            Some(KeyPartition::Country) => {
+                "ROW_NUMBER() OVER (PARTITION BY COALESCE(country, '') ORDER BY id)".to_string()
+            }
+            None => format!("ROW_NUMBER() OVER (ORDER BY id) + {}", self.offset),
+        };
+        if options.composite_key {
+            extra_columns.push_str(&format!(
+                ",\n              CONCAT(COALESCE(country, ''), '-', CAST({key} AS VARCHAR)) \
+                 AS {COMPOSITE_KEY_COLUMN}"
+            ));
+        }
+
+        let geometry = match options.no_geometry {
+            true => "",
+            false => ",\n              geometry                    AS z_boundary",
+        };
         let sql = format!(
             r#"
             SELECT
-              CAST(ROW_NUMBER() OVER (ORDER BY id) + {} AS BIGINT) AS z_zonekey,
+              CAST({} AS BIGINT) AS z_zonekey,
               COALESCE(id, '')            AS z_gersid,
               COALESCE(country, '')       AS z_country,
               COALESCE(region,  '')       AS z_region,
               COALESCE(names.primary, '') AS z_name,
-              COALESCE(subtype, '')       AS z_subtype,
-              geometry                    AS z_boundary
+              COALESCE(subtype, '')       AS z_subtype{}{}
             FROM zone_filtered
             "#,
-            self.offset
+            key, geometry, extra_columns
         );
 
         debug!("Executing SQL transformation with offset: {}", self.offset);
@@ -55,6 +160,124 @@ impl ZoneTransformer {
         Ok(df)
     }
 
+    /// Applies the optional per-row rewrites that run on collected batches
+    #[instrument(
+        name = "zone.transform",
+        skip_all,
+        fields(rows = batches.iter().map(RecordBatch::num_rows).sum::<usize>())
+    )]
+    pub fn apply_batch_transforms(
+        &self,
+        options: &ZoneTransformOptions,
+        batches: Vec<RecordBatch>,
+    ) -> Result<Vec<RecordBatch>> {
+        let mut batches = batches;
+
+        if options.names_common {
+            batches = common_names_to_json(batches)?;
+        }
+
+        if let Some(style) = options.pseudonymize_names {
+            batches = pseudonymize_names(batches, style, options.seed)?;
+        }
+
+        if !options.skip_wkb_normalize && !options.no_geometry {
+            (batches, _) = normalize_wkb_batches(
+                batches,
+                options.keep_zm,
+                options.on_bad_geometry,
+                options.geometry_threads,
+            )?;
+        }
+
+        if options.normalize_country {
+            (batches, _) = normalize_country_batches(batches)?;
+        }
+
+        if let Some(region_map) = &options.region_map {
+            (batches, _) = region_map.apply(batches)?;
+        }
+
+        if options.normalize_region != RegionNormalization::Raw {
+            (batches, _) = normalize_region_batches(batches, options.normalize_region)?;
+        }
+
+        if let Some(tolerance) = options.simplify_tolerance {
+            (batches, _) = simplify_batches(
+                batches,
+                options.simplify_algorithm,
+                tolerance,
+                options.geometry_threads,
+            )?;
+        }
+
+        if options.normalize_winding.is_some() {
+            (batches, _) = normalize_winding_batches(batches, options.geometry_threads)?;
+        }
+
+        if options.include_hierarchy {
+            batches = add_hierarchy_columns(batches)?;
+        }
+
+        if options.bbox_covering {
+            batches = append_bbox_column(
+                batches,
+                options.bbox_layout,
+                options.bbox_inflate,
+                options.geometry_threads,
+            )?;
+        }
+
+        if let Some(crs) = options.area {
+            batches = append_area_column(batches, crs, options.geometry_threads)?;
+        }
+
+        if options.point_as_lonlat {
+            batches = append_lonlat_columns(batches, options.geometry_threads)?;
+        }
+
+        batches = append_extra_columns(batches, &options.extra_columns, options.geometry_threads)?;
+
+        batches = append_synthetic_columns(batches, &options.synthetic_columns, options.seed)?;
+
+        Ok(batches)
+    }
+
+    /// Schema of the written zone files: that of the batches returned by
+    /// [`Self::apply_batch_transforms`] with `z_boundary` in the
+    /// `--geometry-encoding`, narrowed to the `--columns` selection in its
+    /// order. The batches keep every column, as the
+    /// partitioning and key checks read them; the writer projects them.
+    pub fn output_schema(
+        &self,
+        options: &ZoneTransformOptions,
+        df: &DataFrame,
+    ) -> Result<SchemaRef> {
+        let mut schema = Arc::new(self.arrow_schema(df)?);
+        if options.names_common {
+            schema = names::output_schema(schema);
+        }
+        if options.include_hierarchy {
+            schema = hierarchy::output_schema(schema);
+        }
+        if options.bbox_covering {
+            schema = bbox::output_schema(schema, options.bbox_layout);
+        }
+        if options.area.is_some() {
+            schema = area::output_schema(schema);
+        }
+        if options.point_as_lonlat {
+            schema = lonlat::output_schema(schema);
+        }
+        let schema = extra_columns::output_schema(schema, &options.extra_columns);
+        let schema = synthetic::output_schema(schema, &options.synthetic_columns)?;
+        let schema = options.geometry_encoding.output_schema(schema);
+        match &options.columns {
+            Some(selection) => selection.project(&schema),
+            None => Ok(schema),
+        }
+    }
+
     pub fn arrow_schema(&self, df: &DataFrame) -> Result<Schema> {
         Ok(Schema::new(
             df.schema()
@@ -65,3 +288,99 @@ impl ZoneTransformer {
         ))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zone::config::{OnBadGeometry, WindingOrder};
+    use crate::zone::fixtures::wkb_polygon;
+    use arrow_array::{BinaryArray, Int64Array};
+    use arrow_schema::{DataType, Field};
+
+    /// `wkb_polygon` in big-endian byte order
+    fn big_endian_polygon(ring: &[(f64, f64)]) -> Vec<u8> {
+        let mut wkb = vec![0u8];
+        wkb.extend_from_slice(&3u32.to_be_bytes());
+        wkb.extend_from_slice(&1u32.to_be_bytes());
+        wkb.extend_from_slice(&(ring.len() as u32).to_be_bytes());
+        for (x, y) in ring {
+            wkb.extend_from_slice(&x.to_be_bytes());
+            wkb.extend_from_slice(&y.to_be_bytes());
+        }
+        wkb
+    }
+
+    /// Batches of clockwise and counterclockwise, little- and big-endian
+    /// polygons, with a malformed value in every batch
+    fn batches(count: i64, rows: i64) -> Vec<RecordBatch> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("z_zonekey", DataType::Int64, false),
+            Field::new("z_boundary", DataType::Binary, true),
+        ]));
+        (0..count)
+            .map(|batch| {
+                let keys: Vec<i64> = (0..rows).map(|row| batch * rows + row + 1).collect();
+                let boundaries: Vec<Option<Vec<u8>>> = keys
+                    .iter()
+                    .map(|&key| {
+                        let (x, y) = ((key % 360) as f64 - 180.0, (key % 170) as f64 - 85.0);
+                        let mut ring: Vec<_> = (0..=32)
+                            .map(|i| {
+                                let angle = i as f64 / 32.0 * std::f64::consts::TAU;
+                                (x + angle.cos() * 0.5, y + angle.sin() * 0.5)
+                            })
+                            .collect();
+                        if key % 2 == 0 {
+                            ring.reverse();
+                        }
+                        match key % 7 {
+                            0 => Some(vec![1, 3, 0]),
+                            3 => None,
+                            1 | 4 => Some(big_endian_polygon(&ring)),
+                            _ => Some(wkb_polygon(&ring)),
+                        }
+                    })
+                    .collect();
+                RecordBatch::try_new(
+                    schema.clone(),
+                    vec![
+                        Arc::new(Int64Array::from(keys)),
+                        Arc::new(BinaryArray::from_iter(boundaries)),
+                    ],
+                )
+                .unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parallel_geometry_transforms_match_serial() {
+        let input = batches(64, 512);
+        let options = |geometry_threads| ZoneTransformOptions {
+            normalize_winding: Some(WindingOrder::Ogc),
+            on_bad_geometry: OnBadGeometry::Skip,
+            geometry_threads,
+            ..Default::default()
+        };
+        let transformer = ZoneTransformer::new(0);
+
+        let serial = transformer
+            .apply_batch_transforms(&options(None), input.clone())
+            .unwrap();
+        let parallel = transformer
+            .apply_batch_transforms(&options(Some(4)), input)
+            .unwrap();
+
+        // Malformed rows are dropped, every other row stays in place
+        assert_eq!(parallel, serial);
+        let keys: Vec<i64> = parallel
+            .iter()
+            .flat_map(|batch| {
+                let keys = batch.column(0).as_any().downcast_ref::<Int64Array>();
+                keys.unwrap().values().to_vec()
+            })
+            .collect();
+        let expected: Vec<i64> = (1..=64 * 512).filter(|key| key % 7 != 0).collect();
+        assert_eq!(keys, expected);
+    }
+}
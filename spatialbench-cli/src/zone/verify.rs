@@ -0,0 +1,307 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Content verification and comparison of generated zone datasets
+
+use anyhow::{anyhow, Result};
+use log::{debug, info};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use super::attribution::check_attribution;
+use super::filename::{embedded_scale_factor, scale_factor_token};
+use super::hash::{hash_parquet_file, read_footer_hash, read_footer_value};
+use super::manifest::ZoneManifest;
+use super::writer::TRUNCATED_KEY;
+
+/// A zone part file discovered in a dataset directory
+#[derive(Debug, Clone)]
+pub struct DatasetFile {
+    pub path: PathBuf,
+    /// Hash recorded in the manifest or the Parquet footer, if any
+    pub recorded_sha256: Option<String>,
+}
+
+/// Lists the zone Parquet files of a dataset keyed by their relative path,
+/// leaving out the merged copy of the parts recorded in the manifest
+pub fn discover_files(data_dir: &Path) -> Result<BTreeMap<String, DatasetFile>> {
+    if !data_dir.is_dir() {
+        return Err(anyhow!("{} is not a directory", data_dir.display()));
+    }
+
+    let mut candidates = Vec::new();
+    let single = data_dir.join("zone.parquet");
+    if single.is_file() {
+        candidates.push(single);
+    }
+    let parts_dir = data_dir.join("zone");
+    if parts_dir.is_dir() {
+        for entry in std::fs::read_dir(&parts_dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|e| e == "parquet") {
+                candidates.push(path);
+            }
+        }
+    }
+
+    let manifest = ZoneManifest::read(data_dir)?;
+    let mut files = BTreeMap::new();
+    for path in candidates {
+        let relative = path
+            .strip_prefix(data_dir)
+            .unwrap_or(&path)
+            .to_string_lossy()
+            .into_owned();
+        // A merged copy repeats the rows of the parts
+        if manifest
+            .as_ref()
+            .and_then(|m| m.merged.as_ref())
+            .is_some_and(|merged| merged.path == relative)
+        {
+            continue;
+        }
+        let recorded_sha256 = match manifest.as_ref().and_then(|m| m.find(&relative)) {
+            Some(entry) => Some(entry.content_sha256.clone()),
+            None => read_footer_hash(&path)?,
+        };
+        files.insert(
+            relative,
+            DatasetFile {
+                path,
+                recorded_sha256,
+            },
+        );
+    }
+    Ok(files)
+}
+
+/// Refuses a dataset whose zone file names embed more than one scale
+/// factor. Files without an embedded scale factor are not compared.
+pub fn check_single_scale_factor(data_dir: &Path) -> Result<()> {
+    let mut by_scale_factor: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for (name, file) in discover_files(data_dir)? {
+        let file_name = file.path.file_name().unwrap_or_default().to_string_lossy();
+        if let Some(scale_factor) = embedded_scale_factor(&file_name) {
+            by_scale_factor
+                .entry(scale_factor_token(scale_factor))
+                .or_default()
+                .push(name);
+        }
+    }
+    if by_scale_factor.len() <= 1 {
+        return Ok(());
+    }
+    let groups: Vec<String> = by_scale_factor
+        .iter()
+        .map(|(token, names)| format!("sf{token} ({})", names.join(", ")))
+        .collect();
+    Err(anyhow!(
+        "{} mixes zone files of different scale factors: {}. Pass --force to process them \
+         anyway",
+        data_dir.display(),
+        groups.join("; ")
+    ))
+}
+
+/// Result of comparing two datasets part by part
+#[derive(Debug, Default)]
+pub struct CompareReport {
+    pub identical: Vec<String>,
+    pub different: Vec<String>,
+    pub only_in_left: Vec<String>,
+    pub only_in_right: Vec<String>,
+}
+
+impl CompareReport {
+    pub fn is_identical(&self) -> bool {
+        self.different.is_empty() && self.only_in_left.is_empty() && self.only_in_right.is_empty()
+    }
+}
+
+/// Compares the parts of two datasets by content hash
+pub fn compare_dirs(left: &Path, right: &Path) -> Result<CompareReport> {
+    let left_files = discover_files(left)?;
+    let right_files = discover_files(right)?;
+
+    let names: BTreeSet<&String> = left_files.keys().chain(right_files.keys()).collect();
+    let mut report = CompareReport::default();
+    for name in names {
+        match (left_files.get(name), right_files.get(name)) {
+            (Some(l), Some(r)) => {
+                // Only trust recorded hashes when both sides have one, so a
+                // missing hash on one side doesn't force a full stream of both
+                let (lh, rh) = match (&l.recorded_sha256, &r.recorded_sha256) {
+                    (Some(lh), Some(rh)) => (lh.clone(), rh.clone()),
+                    _ => {
+                        debug!("{name}: hash missing on one side, streaming both files");
+                        (hash_parquet_file(&l.path)?, hash_parquet_file(&r.path)?)
+                    }
+                };
+                if lh == rh {
+                    report.identical.push(name.clone());
+                } else {
+                    report.different.push(name.clone());
+                }
+            }
+            (Some(_), None) => report.only_in_left.push(name.clone()),
+            (None, Some(_)) => report.only_in_right.push(name.clone()),
+            (None, None) => unreachable!(),
+        }
+    }
+    Ok(report)
+}
+
+/// Recomputes the content hash of every part and of the merged copy and
+/// checks them against the recorded ones, returning the files that don't
+/// match
+pub fn verify_dir(data_dir: &Path) -> Result<Vec<String>> {
+    let mut mismatched = Vec::new();
+    for (name, file) in discover_files(data_dir)? {
+        let Some(recorded) = &file.recorded_sha256 else {
+            info!("{name}: no recorded content hash, skipping");
+            continue;
+        };
+        let actual = hash_parquet_file(&file.path)?;
+        if &actual != recorded {
+            mismatched.push(name);
+        }
+    }
+    if let Some(merged) = ZoneManifest::read(data_dir)?.and_then(|m| m.merged) {
+        if hash_parquet_file(&data_dir.join(&merged.path))? != merged.content_sha256 {
+            mismatched.push(merged.path);
+        }
+    }
+    Ok(mismatched)
+}
+
+/// What is wrong with the `ATTRIBUTION.txt` of `data_dir`, when its
+/// manifest records one
+pub fn attribution_problem(data_dir: &Path) -> Result<Option<String>> {
+    match ZoneManifest::read(data_dir)?.and_then(|m| m.attribution_sha256) {
+        Some(recorded) => check_attribution(data_dir, &recorded),
+        None => Ok(None),
+    }
+}
+
+/// Lists the zone files cut short by `--limit`, as marked in their footer
+/// or in the manifest. Their rows are not benchmark data.
+pub fn truncated_files(data_dir: &Path) -> Result<Vec<String>> {
+    let manifest_truncated = ZoneManifest::read(data_dir)?.is_some_and(|m| m.truncated);
+    let mut truncated = Vec::new();
+    for (name, file) in discover_files(data_dir)? {
+        if manifest_truncated
+            || read_footer_value(&file.path, TRUNCATED_KEY)?.as_deref() == Some("true")
+        {
+            truncated.push(name);
+        }
+    }
+    Ok(truncated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zone::hash::{ContentHasher, CONTENT_SHA256_KEY};
+    use arrow_array::{Int64Array, RecordBatch};
+    use arrow_schema::{DataType, Field, Schema};
+    use parquet::arrow::ArrowWriter;
+    use parquet::file::metadata::KeyValue;
+    use std::sync::Arc;
+
+    fn write_part(dir: &Path, name: &str, keys: Vec<i64>, with_hash: bool) {
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "z_zonekey",
+            DataType::Int64,
+            false,
+        )]));
+        let batch =
+            RecordBatch::try_new(schema.clone(), vec![Arc::new(Int64Array::from(keys))]).unwrap();
+        std::fs::create_dir_all(dir.join("zone")).unwrap();
+        let file = std::fs::File::create(dir.join("zone").join(name)).unwrap();
+        let mut writer = ArrowWriter::try_new(file, schema.clone(), None).unwrap();
+        writer.write(&batch).unwrap();
+        if with_hash {
+            let mut hasher = ContentHasher::try_new(&schema).unwrap();
+            hasher.update(&batch).unwrap();
+            writer.append_key_value_metadata(KeyValue::new(
+                CONTENT_SHA256_KEY.to_string(),
+                hasher.finish(),
+            ));
+        }
+        writer.close().unwrap();
+    }
+
+    #[test]
+    fn test_check_single_scale_factor() {
+        let dir = tempfile::tempdir().unwrap();
+        write_part(dir.path(), "zone.sf1.1.parquet", vec![1], false);
+        write_part(dir.path(), "zone.sf1.2.parquet", vec![2], false);
+        // Names without a scale factor are not compared
+        write_part(dir.path(), "zone.3.parquet", vec![3], false);
+        check_single_scale_factor(dir.path()).unwrap();
+
+        write_part(dir.path(), "zone.sf0_1.1.parquet", vec![1], false);
+        let error = check_single_scale_factor(dir.path()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "{} mixes zone files of different scale factors: sf0_1 \
+                 (zone/zone.sf0_1.1.parquet); sf1 (zone/zone.sf1.1.parquet, \
+                 zone/zone.sf1.2.parquet). Pass --force to process them anyway",
+                dir.path().display()
+            )
+        );
+    }
+
+    #[test]
+    fn test_compare_dirs_reports_differing_parts() {
+        let a = tempfile::tempdir().unwrap();
+        let b = tempfile::tempdir().unwrap();
+        write_part(a.path(), "zone.1.parquet", vec![1, 2], true);
+        write_part(a.path(), "zone.2.parquet", vec![3, 4], true);
+        write_part(b.path(), "zone.1.parquet", vec![1, 2], false);
+        write_part(b.path(), "zone.2.parquet", vec![3, 5], true);
+
+        let report = compare_dirs(a.path(), b.path()).unwrap();
+        assert_eq!(report.identical, vec!["zone/zone.1.parquet"]);
+        assert_eq!(report.different, vec!["zone/zone.2.parquet"]);
+        assert!(!report.is_identical());
+    }
+
+    #[test]
+    fn test_verify_dir_detects_tampering() {
+        let dir = tempfile::tempdir().unwrap();
+        write_part(dir.path(), "zone.1.parquet", vec![1, 2], true);
+        assert!(verify_dir(dir.path()).unwrap().is_empty());
+
+        let mut manifest = ZoneManifest::new(1.0, 1);
+        manifest.replace_part(
+            1,
+            vec![crate::zone::manifest::ManifestPart {
+                part: 1,
+                path: "zone/zone.1.parquet".to_string(),
+                rows: 2,
+                content_sha256: "0".repeat(64),
+                file_index: None,
+                row_offset: None,
+                footer_bytes: None,
+            }],
+        );
+        manifest.write(dir.path()).unwrap();
+        assert_eq!(verify_dir(dir.path()).unwrap(), vec!["zone/zone.1.parquet"]);
+    }
+}
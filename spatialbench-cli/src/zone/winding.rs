@@ -0,0 +1,134 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Ring orientation normalization of `z_boundary` polygons
+
+use anyhow::Result;
+use arrow_array::RecordBatch;
+use log::{info, warn};
+
+use super::batch::{map_batches, map_binary_column};
+use super::wkb::orient_rings;
+
+#[derive(Debug, Default, PartialEq)]
+pub struct WindingReport {
+    /// Rings whose point order was reversed
+    pub reversed: usize,
+    /// Rings too small or flat to have an orientation, left as-is
+    pub degenerate: usize,
+    /// Geometries that could not be decoded, left as-is
+    pub unreadable: usize,
+}
+
+/// Rewrites `z_boundary` so exterior rings run counterclockwise and holes
+/// clockwise, as every [`WindingOrder`](super::config::WindingOrder) requires.
+/// Batches are rewritten on up to `threads` threads.
+pub fn normalize_winding_batches(
+    batches: Vec<RecordBatch>,
+    threads: Option<usize>,
+) -> Result<(Vec<RecordBatch>, WindingReport)> {
+    let mut report = WindingReport::default();
+
+    let batches = map_batches(&batches, threads, |_, batch| {
+        let mut batch_report = WindingReport::default();
+        let mapped = map_binary_column(batch, "z_boundary", |wkb| {
+            let mut wkb = wkb.to_vec();
+            match orient_rings(&mut wkb) {
+                Ok(result) => {
+                    batch_report.degenerate += result.degenerate;
+                    if result.reversed == 0 {
+                        return Ok(None);
+                    }
+                    batch_report.reversed += result.reversed;
+                    Ok(Some(wkb))
+                }
+                Err(_) => {
+                    batch_report.unreadable += 1;
+                    Ok(None)
+                }
+            }
+        })?;
+        Ok((mapped, batch_report))
+    })?
+    .into_iter()
+    .map(|(batch, batch_report)| {
+        report.reversed += batch_report.reversed;
+        report.degenerate += batch_report.degenerate;
+        report.unreadable += batch_report.unreadable;
+        batch
+    })
+    .collect();
+
+    if report.degenerate > 0 {
+        warn!(
+            "Left {} degenerate ring(s) in z_boundary as-is",
+            report.degenerate
+        );
+    }
+    if report.unreadable > 0 {
+        warn!(
+            "Could not decode {} z_boundary value(s), leaving their winding as-is",
+            report.unreadable
+        );
+    }
+    info!("Reversed {} z_boundary ring(s)", report.reversed);
+
+    Ok((batches, report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zone::fixtures::wkb_polygon;
+    use arrow_array::{Array, BinaryArray};
+    use arrow_schema::{DataType, Field, Schema};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_clockwise_exterior_flipped_to_counterclockwise() {
+        let clockwise = [(0.0, 0.0), (0.0, 1.0), (1.0, 1.0), (1.0, 0.0), (0.0, 0.0)];
+        let mut counterclockwise = clockwise;
+        counterclockwise.reverse();
+
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "z_boundary",
+            DataType::Binary,
+            true,
+        )]));
+        let batch = RecordBatch::try_new(
+            schema,
+            vec![Arc::new(BinaryArray::from(vec![
+                Some(wkb_polygon(&clockwise).as_slice()),
+                Some(wkb_polygon(&counterclockwise).as_slice()),
+                None,
+            ]))],
+        )
+        .unwrap();
+
+        let (batches, report) = normalize_winding_batches(vec![batch], None).unwrap();
+        assert_eq!(report.reversed, 1);
+
+        let column = batches[0]
+            .column(0)
+            .as_any()
+            .downcast_ref::<BinaryArray>()
+            .unwrap();
+        assert_eq!(column.value(0), wkb_polygon(&counterclockwise).as_slice());
+        assert_eq!(column.value(1), wkb_polygon(&counterclockwise).as_slice());
+        assert!(column.is_null(2));
+    }
+}
@@ -0,0 +1,642 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Minimal WKB decoding into a byte order independent form
+
+use anyhow::{anyhow, Result};
+
+const EWKB_Z: u32 = 0x8000_0000;
+const EWKB_M: u32 = 0x4000_0000;
+const EWKB_SRID: u32 = 0x2000_0000;
+
+/// A decoded WKB geometry.
+///
+/// `structure` holds the geometry type codes and ring/part/point counts in
+/// the order they appear, `coords` every ordinate in order. Two geometries
+/// with equal `structure` and `coords` are the same geometry regardless of
+/// byte order or EWKB vs ISO type codes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NormalizedWkb {
+    pub structure: Vec<u32>,
+    pub coords: Vec<f64>,
+    /// Ordinates per point (2 for XY, 3 for XYZ or XYM, 4 for XYZM)
+    pub dims: usize,
+}
+
+impl NormalizedWkb {
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        let mut reader = Reader {
+            bytes,
+            pos: 0,
+            little_endian: true,
+        };
+        let mut geometry = Self {
+            structure: Vec::new(),
+            coords: Vec::new(),
+            dims: 2,
+        };
+        geometry.read_geometry(&mut reader)?;
+        if reader.pos != bytes.len() {
+            return Err(anyhow!(
+                "{} trailing byte(s) after WKB geometry",
+                bytes.len() - reader.pos
+            ));
+        }
+        Ok(geometry)
+    }
+
+    pub fn num_points(&self) -> usize {
+        self.coords.len() / self.dims
+    }
+
+    /// Returns the largest absolute ordinate difference, or `None` if the
+    /// geometries differ in type or shape
+    pub fn max_coordinate_delta(&self, other: &Self) -> Option<f64> {
+        if self.structure != other.structure || self.dims != other.dims {
+            return None;
+        }
+        Some(
+            self.coords
+                .iter()
+                .zip(&other.coords)
+                .map(|(a, b)| (a - b).abs())
+                .fold(0.0, f64::max),
+        )
+    }
+
+    fn read_geometry(&mut self, reader: &mut Reader) -> Result<()> {
+        let (base_type, type_code, dims) = read_header(reader)?;
+        self.dims = dims;
+        self.structure.push(type_code);
+
+        match base_type {
+            1 => self.read_points(reader, 1),
+            2 => {
+                let n = self.read_count(reader)?;
+                self.read_points(reader, n)
+            }
+            3 => {
+                let rings = self.read_count(reader)?;
+                for _ in 0..rings {
+                    let n = self.read_count(reader)?;
+                    self.read_points(reader, n)?;
+                }
+                Ok(())
+            }
+            _ => {
+                let parts = self.read_count(reader)?;
+                for _ in 0..parts {
+                    self.read_geometry(reader)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn read_count(&mut self, reader: &mut Reader) -> Result<u32> {
+        let count = reader.u32()?;
+        self.structure.push(count);
+        Ok(count)
+    }
+
+    fn read_points(&mut self, reader: &mut Reader, n: u32) -> Result<()> {
+        for _ in 0..n as usize * self.dims {
+            self.coords.push(reader.f64()?);
+        }
+        Ok(())
+    }
+}
+
+/// Reads the byte order and type of a geometry, returning the base type
+/// (1 to 7), the equivalent ISO type code and the ordinates per point
+fn read_header(reader: &mut Reader) -> Result<(u32, u32, usize)> {
+    reader.set_byte_order()?;
+    let raw_type = reader.u32()?;
+    if raw_type & EWKB_SRID != 0 {
+        reader.u32()?;
+    }
+
+    let iso_dims = (raw_type & 0xffff) / 1000;
+    let base_type = (raw_type & 0xffff) % 1000;
+    let (has_z, has_m) = match iso_dims {
+        0 => (raw_type & EWKB_Z != 0, raw_type & EWKB_M != 0),
+        1 => (true, false),
+        2 => (false, true),
+        3 => (true, true),
+        _ => return Err(anyhow!("Unsupported WKB geometry type {raw_type}")),
+    };
+    if !(1..=7).contains(&base_type) {
+        return Err(anyhow!("Unsupported WKB geometry type {raw_type}"));
+    }
+    let dims = 2 + has_z as usize + has_m as usize;
+    let type_code = base_type + 1000 * (has_z as u32 + 2 * has_m as u32);
+    Ok((base_type, type_code, dims))
+}
+
+/// Rewrites `wkb` as little-endian ISO WKB without an SRID.
+///
+/// EWKB type flags become ISO type codes. Z and M ordinates are dropped
+/// unless `keep_zm` is set.
+pub fn to_iso_wkb(wkb: &[u8], keep_zm: bool) -> Result<Vec<u8>> {
+    let mut reader = Reader {
+        bytes: wkb,
+        pos: 0,
+        little_endian: true,
+    };
+    let mut out = Vec::with_capacity(wkb.len());
+    write_iso_geometry(&mut reader, &mut out, keep_zm)?;
+    if reader.pos != wkb.len() {
+        return Err(anyhow!(
+            "{} trailing byte(s) after WKB geometry",
+            wkb.len() - reader.pos
+        ));
+    }
+    Ok(out)
+}
+
+fn write_iso_geometry(reader: &mut Reader, out: &mut Vec<u8>, keep_zm: bool) -> Result<()> {
+    let (base_type, type_code, dims) = read_header(reader)?;
+    let (type_code, out_dims) = if keep_zm {
+        (type_code, dims)
+    } else {
+        (base_type, 2)
+    };
+    out.push(1);
+    out.extend_from_slice(&type_code.to_le_bytes());
+
+    let copy_count = |reader: &mut Reader, out: &mut Vec<u8>| -> Result<u32> {
+        let count = reader.u32()?;
+        out.extend_from_slice(&count.to_le_bytes());
+        Ok(count)
+    };
+    let copy_points = |reader: &mut Reader, out: &mut Vec<u8>, n: u32| -> Result<()> {
+        for _ in 0..n {
+            for i in 0..dims {
+                let ordinate = reader.f64()?;
+                if i < out_dims {
+                    out.extend_from_slice(&ordinate.to_le_bytes());
+                }
+            }
+        }
+        Ok(())
+    };
+
+    match base_type {
+        1 => copy_points(reader, out, 1),
+        2 => {
+            let n = copy_count(reader, out)?;
+            copy_points(reader, out, n)
+        }
+        3 => {
+            let rings = copy_count(reader, out)?;
+            for _ in 0..rings {
+                let n = copy_count(reader, out)?;
+                copy_points(reader, out, n)?;
+            }
+            Ok(())
+        }
+        _ => {
+            let parts = copy_count(reader, out)?;
+            for _ in 0..parts {
+                write_iso_geometry(reader, out, keep_zm)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// The base geometry type code of `wkb` (1 for Point to 7 for
+/// GeometryCollection), from its header alone
+pub fn geometry_type(wkb: &[u8]) -> Result<u32> {
+    let mut reader = Reader {
+        bytes: wkb,
+        pos: 0,
+        little_endian: true,
+    };
+    let (base_type, _, _) = read_header(&mut reader)?;
+    Ok(base_type)
+}
+
+/// Counts the points of `wkb` from its headers and counts alone, without
+/// decoding any ordinates
+pub fn count_points(wkb: &[u8]) -> Result<usize> {
+    let mut reader = Reader {
+        bytes: wkb,
+        pos: 0,
+        little_endian: true,
+    };
+    let points = count_geometry_points(&mut reader)?;
+    if reader.pos != wkb.len() {
+        return Err(anyhow!(
+            "{} trailing byte(s) after WKB geometry",
+            wkb.len() - reader.pos
+        ));
+    }
+    Ok(points)
+}
+
+fn count_geometry_points(reader: &mut Reader) -> Result<usize> {
+    let (base_type, _, dims) = read_header(reader)?;
+    let point_size = dims * 8;
+    match base_type {
+        1 => {
+            reader.skip(point_size)?;
+            Ok(1)
+        }
+        2 => {
+            let n = reader.u32()? as usize;
+            reader.skip(n * point_size)?;
+            Ok(n)
+        }
+        3 => {
+            let rings = reader.u32()?;
+            let mut points = 0;
+            for _ in 0..rings {
+                let n = reader.u32()? as usize;
+                reader.skip(n * point_size)?;
+                points += n;
+            }
+            Ok(points)
+        }
+        _ => {
+            let parts = reader.u32()?;
+            let mut points = 0;
+            for _ in 0..parts {
+                points += count_geometry_points(reader)?;
+            }
+            Ok(points)
+        }
+    }
+}
+
+/// Ring counts from [`orient_rings`]
+#[derive(Debug, Default, PartialEq)]
+pub struct RingOrientation {
+    pub reversed: usize,
+    /// Rings with fewer than four points or no area, left as they were
+    pub degenerate: usize,
+}
+
+/// Reverses polygon rings of `wkb` in place so exterior rings run
+/// counterclockwise and holes clockwise.
+///
+/// Only the order of the point records changes, so byte order, type codes
+/// and any Z/M ordinates are kept.
+pub fn orient_rings(wkb: &mut [u8]) -> Result<RingOrientation> {
+    let mut rings = Vec::new();
+    let mut reader = Reader {
+        bytes: wkb,
+        pos: 0,
+        little_endian: true,
+    };
+    collect_rings(&mut reader, &mut rings)?;
+
+    let mut result = RingOrientation::default();
+    for ring in rings {
+        let point_size = ring.dims * 8;
+        let bytes = &mut wkb[ring.offset..ring.offset + ring.points * point_size];
+        let area = signed_area(&ring_points(bytes, point_size, ring.little_endian));
+        if ring.points < 4 || area == 0.0 {
+            result.degenerate += 1;
+            continue;
+        }
+
+        if (area > 0.0) != ring.exterior {
+            let mut points: Vec<Vec<u8>> =
+                bytes.chunks_exact(point_size).map(<[u8]>::to_vec).collect();
+            points.reverse();
+            bytes.copy_from_slice(&points.concat());
+            result.reversed += 1;
+        }
+    }
+    Ok(result)
+}
+
+/// A polygon ring of a WKB geometry, as read by [`polygon_rings`]
+#[derive(Debug, PartialEq)]
+pub struct Ring {
+    /// Whether the ring is the first of its polygon
+    pub exterior: bool,
+    /// XY ordinates of the points, in their WKB order
+    pub points: Vec<(f64, f64)>,
+}
+
+impl Ring {
+    pub fn is_closed(&self) -> bool {
+        self.points.first() == self.points.last()
+    }
+
+    /// Shoelace area, positive when counterclockwise. An unclosed ring is
+    /// taken as closed by a segment from its last point to its first.
+    pub fn signed_area(&self) -> f64 {
+        let closing = match (self.points.first(), self.points.last()) {
+            (Some(first), Some(last)) if !self.is_closed() => {
+                (last.0 * first.1 - first.0 * last.1) / 2.0
+            }
+            _ => 0.0,
+        };
+        signed_area(&self.points) + closing
+    }
+}
+
+/// The polygon rings of `wkb`, in (multi)polygons and geometry collections
+/// alike; other geometry types have none
+pub fn polygon_rings(wkb: &[u8]) -> Result<Vec<Ring>> {
+    let mut spans = Vec::new();
+    let mut reader = Reader {
+        bytes: wkb,
+        pos: 0,
+        little_endian: true,
+    };
+    collect_rings(&mut reader, &mut spans)?;
+    Ok(spans
+        .into_iter()
+        .map(|span| {
+            let point_size = span.dims * 8;
+            Ring {
+                exterior: span.exterior,
+                points: ring_points(
+                    &wkb[span.offset..span.offset + span.points * point_size],
+                    point_size,
+                    span.little_endian,
+                ),
+            }
+        })
+        .collect())
+}
+
+struct RingSpan {
+    offset: usize,
+    points: usize,
+    dims: usize,
+    little_endian: bool,
+    exterior: bool,
+}
+
+fn collect_rings(reader: &mut Reader, rings: &mut Vec<RingSpan>) -> Result<()> {
+    let (base_type, _, dims) = read_header(reader)?;
+    let skip_points = |reader: &mut Reader, n: usize| -> Result<()> {
+        for _ in 0..n * dims {
+            reader.f64()?;
+        }
+        Ok(())
+    };
+
+    match base_type {
+        1 => skip_points(reader, 1),
+        2 => {
+            let n = reader.u32()? as usize;
+            skip_points(reader, n)
+        }
+        3 => {
+            let count = reader.u32()?;
+            for i in 0..count {
+                let points = reader.u32()? as usize;
+                rings.push(RingSpan {
+                    offset: reader.pos,
+                    points,
+                    dims,
+                    little_endian: reader.little_endian,
+                    exterior: i == 0,
+                });
+                skip_points(reader, points)?;
+            }
+            Ok(())
+        }
+        _ => {
+            let parts = reader.u32()?;
+            for _ in 0..parts {
+                collect_rings(reader, rings)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// The XY ordinates of the point records in `bytes`
+fn ring_points(bytes: &[u8], point_size: usize, little_endian: bool) -> Vec<(f64, f64)> {
+    let ordinate = |point: &[u8], i: usize| {
+        let raw: [u8; 8] = point[i * 8..i * 8 + 8].try_into().unwrap();
+        if little_endian {
+            f64::from_le_bytes(raw)
+        } else {
+            f64::from_be_bytes(raw)
+        }
+    };
+    bytes
+        .chunks_exact(point_size)
+        .map(|p| (ordinate(p, 0), ordinate(p, 1)))
+        .collect()
+}
+
+/// Shoelace area of a closed ring's XY ordinates, positive when
+/// counterclockwise
+fn signed_area(points: &[(f64, f64)]) -> f64 {
+    points
+        .windows(2)
+        .map(|w| w[0].0 * w[1].1 - w[1].0 * w[0].1)
+        .sum::<f64>()
+        / 2.0
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    little_endian: bool,
+}
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let bytes = self
+            .bytes
+            .get(self.pos..self.pos + N)
+            .ok_or_else(|| anyhow!("WKB truncated at byte {}", self.pos))?;
+        self.pos += N;
+        Ok(bytes.try_into().unwrap())
+    }
+
+    fn skip(&mut self, n: usize) -> Result<()> {
+        if self.bytes.len() - self.pos < n {
+            return Err(anyhow!("WKB truncated at byte {}", self.pos));
+        }
+        self.pos += n;
+        Ok(())
+    }
+
+    fn set_byte_order(&mut self) -> Result<()> {
+        self.little_endian = match self.take::<1>()?[0] {
+            0 => false,
+            1 => true,
+            other => return Err(anyhow!("Invalid WKB byte order {other}")),
+        };
+        Ok(())
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let bytes = self.take::<4>()?;
+        Ok(if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    }
+
+    fn f64(&mut self) -> Result<f64> {
+        let bytes = self.take::<8>()?;
+        Ok(if self.little_endian {
+            f64::from_le_bytes(bytes)
+        } else {
+            f64::from_be_bytes(bytes)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(little_endian: bool, x: f64, y: f64) -> Vec<u8> {
+        let mut wkb = vec![little_endian as u8];
+        if little_endian {
+            wkb.extend_from_slice(&1u32.to_le_bytes());
+            wkb.extend_from_slice(&x.to_le_bytes());
+            wkb.extend_from_slice(&y.to_le_bytes());
+        } else {
+            wkb.extend_from_slice(&1u32.to_be_bytes());
+            wkb.extend_from_slice(&x.to_be_bytes());
+            wkb.extend_from_slice(&y.to_be_bytes());
+        }
+        wkb
+    }
+
+    #[test]
+    fn test_byte_order_is_normalized() {
+        let le = NormalizedWkb::parse(&point(true, 1.5, -2.0)).unwrap();
+        let be = NormalizedWkb::parse(&point(false, 1.5, -2.0)).unwrap();
+        assert_eq!(le, be);
+        assert_eq!(le.num_points(), 1);
+    }
+
+    #[test]
+    fn test_coordinate_delta() {
+        let a = NormalizedWkb::parse(&point(true, 1.0, 2.0)).unwrap();
+        let b = NormalizedWkb::parse(&point(true, 1.0, 2.25)).unwrap();
+        assert_eq!(a.max_coordinate_delta(&b), Some(0.25));
+
+        let polygon = crate::zone::fixtures::wkb_polygon(&[(0.0, 0.0), (1.0, 1.0), (0.0, 0.0)]);
+        assert_eq!(count_points(&polygon).unwrap(), 3);
+        let polygon = NormalizedWkb::parse(&polygon).unwrap();
+        assert_eq!(polygon.num_points(), 3);
+        assert_eq!(a.max_coordinate_delta(&polygon), None);
+    }
+
+    #[test]
+    fn test_orient_rings_flips_clockwise_exterior() {
+        let clockwise = [(0.0, 0.0), (0.0, 1.0), (1.0, 1.0), (1.0, 0.0), (0.0, 0.0)];
+        let mut wkb = crate::zone::fixtures::wkb_polygon(&clockwise);
+        let result = orient_rings(&mut wkb).unwrap();
+        assert_eq!(result.reversed, 1);
+
+        let mut expected = clockwise;
+        expected.reverse();
+        assert_eq!(wkb, crate::zone::fixtures::wkb_polygon(&expected));
+
+        // Already counterclockwise, so nothing changes the second time
+        assert_eq!(orient_rings(&mut wkb).unwrap(), RingOrientation::default());
+    }
+
+    #[test]
+    fn test_orient_rings_skips_degenerate_ring() {
+        let mut wkb =
+            crate::zone::fixtures::wkb_polygon(&[(0.0, 0.0), (1.0, 1.0), (2.0, 2.0), (0.0, 0.0)]);
+        let original = wkb.clone();
+        let result = orient_rings(&mut wkb).unwrap();
+        assert_eq!(result.degenerate, 1);
+        assert_eq!(wkb, original);
+    }
+
+    /// EWKB polygon with the SRID flag set, in either byte order
+    fn ewkb_polygon_with_srid(little_endian: bool, ring: &[(f64, f64)]) -> Vec<u8> {
+        let u32_bytes = |v: u32| {
+            if little_endian {
+                v.to_le_bytes()
+            } else {
+                v.to_be_bytes()
+            }
+        };
+        let mut wkb = vec![little_endian as u8];
+        wkb.extend_from_slice(&u32_bytes(3 | EWKB_SRID));
+        wkb.extend_from_slice(&u32_bytes(4326));
+        wkb.extend_from_slice(&u32_bytes(1));
+        wkb.extend_from_slice(&u32_bytes(ring.len() as u32));
+        for (x, y) in ring {
+            for v in [x, y] {
+                wkb.extend_from_slice(&if little_endian {
+                    v.to_le_bytes()
+                } else {
+                    v.to_be_bytes()
+                });
+            }
+        }
+        wkb
+    }
+
+    #[test]
+    fn test_to_iso_wkb_strips_srid_and_byte_order() {
+        let ring = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 0.0)];
+        let expected = crate::zone::fixtures::wkb_polygon(&ring);
+
+        let ewkb = ewkb_polygon_with_srid(true, &ring);
+        assert_eq!(to_iso_wkb(&ewkb, false).unwrap(), expected);
+        let big_endian = ewkb_polygon_with_srid(false, &ring);
+        assert_eq!(to_iso_wkb(&big_endian, false).unwrap(), expected);
+        assert_eq!(
+            to_iso_wkb(&point(false, 1.5, -2.0), false).unwrap(),
+            point(true, 1.5, -2.0)
+        );
+
+        // Already normalized input comes back unchanged
+        assert_eq!(to_iso_wkb(&expected, false).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_to_iso_wkb_z_dimension() {
+        // EWKB POINT Z (1 2 3), big-endian
+        let mut ewkb = vec![0u8];
+        ewkb.extend_from_slice(&(1 | EWKB_Z).to_be_bytes());
+        for v in [1.0f64, 2.0, 3.0] {
+            ewkb.extend_from_slice(&v.to_be_bytes());
+        }
+
+        assert_eq!(to_iso_wkb(&ewkb, false).unwrap(), point(true, 1.0, 2.0));
+
+        let mut expected = vec![1u8];
+        expected.extend_from_slice(&1001u32.to_le_bytes());
+        for v in [1.0f64, 2.0, 3.0] {
+            expected.extend_from_slice(&v.to_le_bytes());
+        }
+        assert_eq!(to_iso_wkb(&ewkb, true).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_truncated_wkb_errors() {
+        let wkb = point(true, 1.0, 2.0);
+        assert!(NormalizedWkb::parse(&wkb[..wkb.len() - 1]).is_err());
+        assert!(to_iso_wkb(&wkb[..wkb.len() - 1], false).is_err());
+        assert!(count_points(&wkb[..wkb.len() - 1]).is_err());
+    }
+}
@@ -0,0 +1,351 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Parquet writer settings of the zone files
+
+use anyhow::{anyhow, Result};
+use arrow_schema::Schema;
+use log::warn;
+use parquet::basic::Compression as ParquetCompression;
+use parquet::file::properties::{EnabledStatistics, WriterProperties, WriterVersion};
+use parquet::schema::types::ColumnPath;
+
+use super::extra_columns::ExtraColumn;
+use super::writer::KEY_COLUMN;
+
+/// Columns written without dictionary encoding unless listed in
+/// [`ParquetWriteOptions::dictionary_columns`]. Their values are mostly
+/// unique, so a dictionary only adds a page that is later abandoned.
+pub const DEFAULT_NO_DICTIONARY_COLUMNS: &[&str] = &["z_zonekey", "z_gersid", "z_boundary"];
+
+/// Bytes the min/max statistics and column index values of string and
+/// binary columns are cut to with `--small-footer`
+pub const SMALL_FOOTER_TRUNCATE_LENGTH: usize = 16;
+
+/// Smallest `--parquet-row-group-bytes`, 64 KiB
+pub const MIN_ROW_GROUP_BYTES: i64 = 64 * 1024;
+
+/// Errors unless `bytes` is at least [`MIN_ROW_GROUP_BYTES`]
+pub fn check_row_group_bytes(bytes: i64) -> Result<i64> {
+    if bytes < MIN_ROW_GROUP_BYTES {
+        return Err(anyhow!(
+            "Invalid --parquet-row-group-bytes={bytes}, expected at least {MIN_ROW_GROUP_BYTES} \
+             (64 KiB)"
+        ));
+    }
+    Ok(bytes)
+}
+
+/// The options of the zone Parquet writer, turned into its
+/// [`WriterProperties`] by [`Self::writer_properties`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParquetWriteOptions {
+    pub compression: ParquetCompression,
+    /// Target row group size; the rows per group are derived from it, and
+    /// the writer default is used when it is 0
+    pub row_group_bytes: i64,
+    /// Data page size limit; the Parquet default when `None`
+    pub page_size_bytes: Option<usize>,
+    /// Dictionary page size limit; the Parquet default when `None`
+    pub dictionary_page_size_bytes: Option<usize>,
+    /// Columns to dictionary encode, overriding [`DEFAULT_NO_DICTIONARY_COLUMNS`]
+    pub dictionary_columns: Vec<String>,
+    /// Columns to write without dictionary encoding
+    pub no_dictionary_columns: Vec<String>,
+    /// Rows passed to the Parquet writer per call; whole batches when `None`
+    pub write_batch_size: Option<usize>,
+    /// Spell out the settings whose defaults may change with the parquet
+    /// crate, and keep its version out of the footer
+    pub pinned_defaults: bool,
+    /// Keep the footer small for remote readers: no `z_boundary`
+    /// statistics, string statistics cut to [`SMALL_FOOTER_TRUNCATE_LENGTH`]
+    /// bytes, and no embedded Arrow schema
+    pub small_footer: bool,
+}
+
+impl ParquetWriteOptions {
+    pub fn new(compression: ParquetCompression, row_group_bytes: i64) -> Self {
+        Self {
+            compression,
+            row_group_bytes,
+            page_size_bytes: None,
+            dictionary_page_size_bytes: None,
+            dictionary_columns: vec![],
+            no_dictionary_columns: vec![],
+            write_batch_size: None,
+            pinned_defaults: false,
+            small_footer: false,
+        }
+    }
+
+    pub fn with_page_sizes(
+        mut self,
+        page_size_bytes: Option<usize>,
+        dictionary_page_size_bytes: Option<usize>,
+    ) -> Self {
+        self.page_size_bytes = page_size_bytes;
+        self.dictionary_page_size_bytes = dictionary_page_size_bytes;
+        self
+    }
+
+    pub fn with_dictionary_columns(
+        mut self,
+        dictionary_columns: Vec<String>,
+        no_dictionary_columns: Vec<String>,
+    ) -> Self {
+        self.dictionary_columns = dictionary_columns;
+        self.no_dictionary_columns = no_dictionary_columns;
+        self
+    }
+
+    pub fn with_write_batch_size(mut self, write_batch_size: Option<usize>) -> Self {
+        self.write_batch_size = write_batch_size;
+        self
+    }
+
+    pub fn with_pinned_defaults(mut self, pinned_defaults: bool) -> Self {
+        self.pinned_defaults = pinned_defaults;
+        self
+    }
+
+    pub fn with_small_footer(mut self, small_footer: bool) -> Self {
+        self.small_footer = small_footer;
+        self
+    }
+
+    /// Whether `column` is written with dictionary encoding
+    pub fn dictionary_enabled(&self, column: &str) -> bool {
+        if self.dictionary_columns.iter().any(|c| c == column) {
+            return true;
+        }
+        !self.no_dictionary_columns.iter().any(|c| c == column)
+            && !DEFAULT_NO_DICTIONARY_COLUMNS.contains(&column)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.row_group_bytes != 0 {
+            check_row_group_bytes(self.row_group_bytes)?;
+        }
+        for (flag, value) in [
+            ("--parquet-page-size-bytes", self.page_size_bytes),
+            (
+                "--parquet-dictionary-page-size-bytes",
+                self.dictionary_page_size_bytes,
+            ),
+            ("--write-batch-size", self.write_batch_size),
+        ] {
+            if value == Some(0) {
+                return Err(anyhow!("Invalid {flag}=0"));
+            }
+        }
+
+        if let Some(column) = self
+            .dictionary_columns
+            .iter()
+            .find(|c| self.no_dictionary_columns.contains(c))
+        {
+            return Err(anyhow!(
+                "Column {column} is in both --dictionary-columns and --no-dictionary-columns"
+            ));
+        }
+        Ok(())
+    }
+
+    /// The writer properties of files with `schema` and `rows_per_group`
+    /// rows per row group
+    pub fn writer_properties(&self, schema: &Schema, rows_per_group: usize) -> WriterProperties {
+        let mut props = WriterProperties::builder()
+            .set_compression(self.compression)
+            .set_max_row_group_size(rows_per_group);
+        if self.pinned_defaults {
+            props = props
+                .set_writer_version(WriterVersion::PARQUET_1_0)
+                .set_created_by(format!("spatialbench-cli {}", env!("CARGO_PKG_VERSION")))
+                .set_statistics_enabled(EnabledStatistics::Page)
+                .set_bloom_filter_enabled(false)
+                .set_write_batch_size(1024)
+                .set_data_page_row_count_limit(20_000);
+        }
+        if let Some(bytes) = self.page_size_bytes {
+            props = props.set_data_page_size_limit(bytes);
+        }
+        if let Some(bytes) = self.dictionary_page_size_bytes {
+            props = props.set_dictionary_page_size_limit(bytes);
+        }
+        for field in schema.fields() {
+            props = props.set_column_dictionary_enabled(
+                ColumnPath::from(field.name().as_str()),
+                self.dictionary_enabled(field.name()),
+            );
+        }
+        // Key range queries prune row groups by the key statistics, and
+        // selectivity experiments by those of the helper columns
+        props = props
+            .set_column_statistics_enabled(ColumnPath::from(KEY_COLUMN), EnabledStatistics::Page);
+        for column in ExtraColumn::ALL {
+            if schema.field_with_name(column.name()).is_ok() {
+                props = props.set_column_statistics_enabled(
+                    ColumnPath::from(column.name()),
+                    EnabledStatistics::Page,
+                );
+            }
+        }
+        // Min/max WKB values don't prune anything but make up most of the
+        // footer of a file with many row groups
+        if self.small_footer {
+            props = props
+                .set_column_statistics_enabled(
+                    ColumnPath::from("z_boundary"),
+                    EnabledStatistics::None,
+                )
+                .set_statistics_truncate_length(Some(SMALL_FOOTER_TRUNCATE_LENGTH))
+                .set_column_index_truncate_length(Some(SMALL_FOOTER_TRUNCATE_LENGTH));
+        }
+        for column in self
+            .dictionary_columns
+            .iter()
+            .chain(&self.no_dictionary_columns)
+        {
+            if schema.field_with_name(column).is_err() {
+                warn!("Dictionary encoding option for unknown zone column {column} ignored");
+            }
+        }
+        props.build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_schema::{DataType, Field};
+    use parquet::basic::ZstdLevel;
+
+    fn schema() -> Schema {
+        Schema::new(vec![
+            Field::new("z_zonekey", DataType::Int64, false),
+            Field::new("z_gersid", DataType::Utf8, false),
+            Field::new("z_country", DataType::Utf8, false),
+            Field::new("z_boundary", DataType::Binary, false),
+        ])
+    }
+
+    /// The settings of `props` the options control, comparable with `==`
+    fn settings(props: &WriterProperties) -> Vec<String> {
+        let mut settings = vec![
+            format!("version={:?}", props.writer_version()),
+            format!("created_by={}", props.created_by()),
+            format!("row_group_rows={}", props.max_row_group_size()),
+            format!("page_bytes={}", props.data_page_size_limit()),
+            format!(
+                "dictionary_page_bytes={}",
+                props.dictionary_page_size_limit()
+            ),
+            format!("batch={}", props.write_batch_size()),
+            format!("page_rows={}", props.data_page_row_count_limit()),
+        ];
+        for field in schema().fields() {
+            let column = ColumnPath::from(field.name().as_str());
+            settings.push(format!(
+                "{}: compression={:?} dictionary={} statistics={:?} bloom={:?}",
+                field.name(),
+                props.compression(&column),
+                props.dictionary_enabled(&column),
+                props.statistics_enabled(&column),
+                props.bloom_filter_properties(&column),
+            ));
+        }
+        settings
+    }
+
+    #[test]
+    fn test_row_group_bytes_minimum() {
+        let options = |bytes| ParquetWriteOptions::new(ParquetCompression::SNAPPY, bytes);
+        options(MIN_ROW_GROUP_BYTES).validate().unwrap();
+        options(0).validate().unwrap();
+        for bytes in [1, 8192, MIN_ROW_GROUP_BYTES - 1, -1] {
+            let error = options(bytes).validate().unwrap_err();
+            assert!(
+                error
+                    .to_string()
+                    .starts_with(&format!("Invalid --parquet-row-group-bytes={bytes}")),
+                "{error}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_writer_properties_match_hand_built() {
+        let options = ParquetWriteOptions::new(ParquetCompression::SNAPPY, 1024);
+        let expected = WriterProperties::builder()
+            .set_compression(ParquetCompression::SNAPPY)
+            .set_max_row_group_size(5000)
+            .set_column_dictionary_enabled(ColumnPath::from("z_zonekey"), false)
+            .set_column_dictionary_enabled(ColumnPath::from("z_gersid"), false)
+            .set_column_dictionary_enabled(ColumnPath::from("z_country"), true)
+            .set_column_dictionary_enabled(ColumnPath::from("z_boundary"), false)
+            .set_column_statistics_enabled(ColumnPath::from("z_zonekey"), EnabledStatistics::Page)
+            .build();
+        assert_eq!(
+            settings(&options.writer_properties(&schema(), 5000)),
+            settings(&expected)
+        );
+
+        let zstd = ParquetCompression::ZSTD(ZstdLevel::try_new(9).unwrap());
+        let options = ParquetWriteOptions::new(zstd, 1024)
+            .with_page_sizes(Some(4096), Some(8192))
+            .with_dictionary_columns(vec!["z_gersid".to_string()], vec!["z_country".to_string()])
+            .with_pinned_defaults(true);
+        let expected = WriterProperties::builder()
+            .set_compression(zstd)
+            .set_max_row_group_size(100)
+            .set_writer_version(WriterVersion::PARQUET_1_0)
+            .set_created_by(format!("spatialbench-cli {}", env!("CARGO_PKG_VERSION")))
+            .set_statistics_enabled(EnabledStatistics::Page)
+            .set_bloom_filter_enabled(false)
+            .set_write_batch_size(1024)
+            .set_data_page_row_count_limit(20_000)
+            .set_data_page_size_limit(4096)
+            .set_dictionary_page_size_limit(8192)
+            .set_column_dictionary_enabled(ColumnPath::from("z_zonekey"), false)
+            .set_column_dictionary_enabled(ColumnPath::from("z_gersid"), true)
+            .set_column_dictionary_enabled(ColumnPath::from("z_country"), false)
+            .set_column_dictionary_enabled(ColumnPath::from("z_boundary"), false)
+            .set_column_statistics_enabled(ColumnPath::from("z_zonekey"), EnabledStatistics::Page)
+            .build();
+        assert_eq!(
+            settings(&options.writer_properties(&schema(), 100)),
+            settings(&expected)
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let options = ParquetWriteOptions::new(ParquetCompression::SNAPPY, MIN_ROW_GROUP_BYTES);
+        assert!(options.validate().is_ok());
+        let error = options
+            .clone()
+            .with_write_batch_size(Some(0))
+            .validate()
+            .unwrap_err();
+        assert!(error.to_string().contains("--write-batch-size=0"));
+        let error = options
+            .with_dictionary_columns(vec!["z_name".to_string()], vec!["z_name".to_string()])
+            .validate()
+            .unwrap_err();
+        assert!(error.to_string().contains("in both"));
+    }
+}
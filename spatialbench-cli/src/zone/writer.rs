@@ -16,43 +16,204 @@
 // under the License.
 
 use anyhow::Result;
-use arrow_array::RecordBatch;
-use arrow_schema::SchemaRef;
+use arrow::compute::cast;
+use arrow_array::{Array, Int64Array, RecordBatch};
+use arrow_schema::{DataType, Schema, SchemaRef};
 use log::{debug, info};
-use parquet::{arrow::ArrowWriter, file::properties::WriterProperties};
-use std::{path::PathBuf, sync::Arc, time::Instant};
+use parquet::{
+    arrow::{arrow_writer::ArrowWriterOptions, ArrowWriter},
+    file::{metadata::KeyValue, properties::WriterProperties},
+};
+use std::{
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Instant,
+};
+use tracing::{field, info_span, Span};
 
-use super::config::ZoneDfArgs;
+use crate::avro::{write_avro_schema, AvroHeader, AvroWriter};
+use crate::generation_params::GENERATION_PARAMS_KEY;
+use crate::interrupt::{interrupted_error, OnInterrupt};
+
+use super::attribution::{attribution_sha256, attribution_text, write_attribution};
+use super::bbox::{geo_metadata, GEO_METADATA_KEY};
+use super::config::{ZoneDfArgs, ZoneFileFormat, ZoneLayout};
+use super::geojson::encode_geojson;
+use super::hash::{ContentHasher, CONTENT_SHA256_KEY};
+use super::manifest::{ManifestColumn, ManifestPart, MergedFile, ZoneManifest};
+use super::max_length::{limit_string_lengths, with_max_lengths, TruncationReport};
+use super::orc::{OrcWriter, DEFAULT_ORC_STRIPE_BYTES};
+use super::schema::{SchemaVersion, SCHEMA_VERSION_KEY};
+use super::sink::{
+    sync_closed, sync_dir, throughput, Fsync, PartFile, DEFAULT_STREAM_BUFFER_BYTES,
+};
 use super::stats::ZoneTableStats;
+use super::text::{TextFormat, TextWriter};
+
+pub(super) const KEY_COLUMN: &str = "z_zonekey";
+/// Footer key marking files cut short by `--limit`, set to `true`
+pub const TRUNCATED_KEY: &str = "spatialbench.truncated";
+const GEOMETRY_COLUMN: &str = "z_boundary";
 
+/// Writes the zone files in the [`ZoneDfArgs::file_format`]
 pub struct ParquetWriter {
     output_path: PathBuf,
     schema: SchemaRef,
     props: WriterProperties,
+    /// Rows per ORC stripe
+    rows_per_stripe: usize,
+    /// Zone schema version recorded in the footers and the manifest
+    schema_version: String,
     args: ZoneDfArgs,
 }
 
+/// A part file written batch by batch, see [`ParquetWriter::start_stream`]
+pub struct StreamedPart {
+    writer: FormatWriter<PartFile>,
+    hasher: ContentHasher,
+    rows: usize,
+    truncated: TruncationReport,
+    started: Instant,
+    span: Span,
+}
+
+impl StreamedPart {
+    /// The `zone.write` span of the part, from its start to its finish
+    pub fn span(&self) -> &Span {
+        &self.span
+    }
+}
+
+/// The writer of one file in the output format
+enum FormatWriter<W: Write + Send> {
+    Parquet(ArrowWriter<W>),
+    Orc(OrcWriter<W>),
+    /// Avro metadata lives in the header, so the batches are only written
+    /// on close, once all metadata is known
+    Avro {
+        sink: W,
+        header: AvroHeader,
+        batches: Vec<RecordBatch>,
+    },
+    Text(TextWriter<W>),
+}
+
+impl<W: Write + Send> FormatWriter<W> {
+    fn append_key_value_metadata(&mut self, key: &str, value: String) {
+        match self {
+            FormatWriter::Parquet(writer) => {
+                writer.append_key_value_metadata(KeyValue::new(key.to_string(), value))
+            }
+            FormatWriter::Orc(writer) => writer.append_key_value_metadata(key.to_string(), value),
+            FormatWriter::Avro { header, .. } => header.append_metadata(key, value),
+            // Delimited text has nowhere to keep metadata
+            FormatWriter::Text(_) => {}
+        }
+    }
+
+    /// Ends the row group or stripe being written, so the next rows start
+    /// a new one
+    fn end_group(&mut self) -> Result<()> {
+        match self {
+            FormatWriter::Parquet(writer) => writer.flush()?,
+            FormatWriter::Orc(writer) => writer.flush()?,
+            // Neither has groups to end
+            FormatWriter::Avro { .. } | FormatWriter::Text(_) => {}
+        }
+        Ok(())
+    }
+
+    fn close(self) -> Result<()> {
+        match self {
+            FormatWriter::Parquet(writer) => {
+                writer.close()?;
+            }
+            FormatWriter::Orc(writer) => writer.close()?,
+            FormatWriter::Avro {
+                sink,
+                header,
+                batches,
+            } => {
+                let mut writer = AvroWriter::try_new(sink, &header)?;
+                for batch in &batches {
+                    writer.write(batch)?;
+                }
+                writer.finish()?;
+            }
+            FormatWriter::Text(writer) => {
+                writer.finish()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FormatWriter<PartFile> {
+    /// Passes the row groups, stripes or batches completed so far to the
+    /// file for `--fsync per-rowgroup`
+    fn groups_written(&mut self) -> Result<()> {
+        match self {
+            FormatWriter::Parquet(writer) => {
+                let groups = writer.flushed_row_groups().len();
+                writer.inner_mut().groups_written(groups)?
+            }
+            FormatWriter::Orc(writer) => {
+                let groups = writer.stripes_written();
+                writer.get_mut().groups_written(groups)?
+            }
+            FormatWriter::Text(writer) => {
+                let groups = writer.batches_written();
+                writer.get_mut().groups_written(groups)?
+            }
+            // Avro batches are only encoded on close
+            FormatWriter::Avro { .. } => {}
+        }
+        Ok(())
+    }
+}
+
 impl ParquetWriter {
     pub fn new(args: &ZoneDfArgs, stats: &ZoneTableStats, schema: SchemaRef) -> Self {
         let rows_per_group =
-            stats.compute_rows_per_group(args.parquet_row_group_bytes, 128 * 1024 * 1024);
-
-        let props = WriterProperties::builder()
-            .set_compression(args.parquet_compression)
-            .set_max_row_group_size(rows_per_group)
-            .build();
-
-        debug!("Using row group size: {} rows", rows_per_group);
+            stats.compute_rows_per_group(args.parquet.row_group_bytes, 128 * 1024 * 1024);
+        let props = args.parquet.writer_properties(&schema, rows_per_group);
+        let rows_per_stripe = match &args.file_format {
+            ZoneFileFormat::Orc(orc) => {
+                let rows = stats.compute_rows_per_group(orc.stripe_bytes, DEFAULT_ORC_STRIPE_BYTES);
+                debug!("Using stripe size: {} rows", rows);
+                rows
+            }
+            ZoneFileFormat::Parquet => {
+                debug!("Using row group size: {} rows", rows_per_group);
+                0
+            }
+            ZoneFileFormat::Avro(_) | ZoneFileFormat::Csv | ZoneFileFormat::Tbl => 0,
+        };
 
         Self {
             output_path: args.output_filename(),
-            schema,
+            schema: with_max_lengths(schema, &args.max_string_lengths),
             props,
+            rows_per_stripe,
+            schema_version: SchemaVersion::of(&args.transform).to_string(),
             args: args.clone(),
         }
     }
 
-    pub fn write(&self, batches: &[RecordBatch]) -> Result<()> {
+    /// Records `schema_version` instead of the version of the transform
+    /// options, for files whose rows were written by an earlier run
+    pub fn with_schema_version(mut self, schema_version: String) -> Self {
+        self.schema_version = schema_version;
+        self
+    }
+
+    /// Writes the part, returning the number of rows written or `None` if
+    /// the part already existed and was skipped
+    pub fn write(&self, batches: &[RecordBatch]) -> Result<Option<usize>> {
+        let span = self.write_span();
+        let _entered = span.enter();
+
         // Create parent directory of output file (handles both zone/ subdirectory and base dir)
         let parent_dir = self
             .output_path
@@ -62,50 +223,1066 @@ impl ParquetWriter {
         std::fs::create_dir_all(parent_dir)?;
         debug!("Created output directory: {:?}", parent_dir);
 
+        if self.args.emit_avro_schema {
+            write_avro_schema(&self.args.avro_schema_filename(), "zone", &self.schema)?;
+        }
+        if self.args.include_attribution {
+            write_attribution(&self.args.output_dir, &self.args.themes)?;
+        }
+
         // Check if file already exists
-        if self.output_path.exists() {
+        if let Some(existing) = self.existing_output(parent_dir)? {
+            info!("{} already exists, skipping generation", existing.display());
+            return Ok(None);
+        }
+
+        if self.args.verify_key_order {
+            check_key_order(batches)
+                .map_err(|e| anyhow::anyhow!("Not writing {}: {e}", self.output_path.display()))?;
+        }
+        let batches = &self.limit_string_lengths(batches, true)?;
+
+        let t0 = Instant::now();
+        let total_rows: usize = batches.iter().map(|b| b.num_rows()).sum();
+        let files = match self.args.max_rows_per_file {
+            Some(max_rows) if total_rows > max_rows => self.write_split(batches, max_rows)?,
+            _ => {
+                let content_sha256 = self.write_file(&self.output_path, batches)?;
+                vec![(self.output_path.clone(), total_rows, content_sha256)]
+            }
+        };
+        let duration = t0.elapsed();
+        let bytes = files
+            .iter()
+            .map(|(path, _, _)| Ok(std::fs::metadata(path)?.len()))
+            .sum::<Result<u64>>()?;
+        span.record("rows", total_rows);
+        span.record("bytes", bytes);
+
+        let part = self.args.part.unwrap_or(1);
+        let split = files.len() > 1;
+        let mut row_offset = 0;
+        let mut entries = Vec::with_capacity(files.len());
+        for (index, (path, rows, content_sha256)) in files.into_iter().enumerate() {
+            let footer_bytes = self.footer_bytes(&path)?;
             info!(
-                "{} already exists, skipping generation",
-                self.output_path.display()
+                "Zone -> {} (part {:?}/{:?}). write={:?}, throughput={}, total_rows={}, content_sha256={}",
+                path.display(),
+                self.args.part,
+                self.args.parts,
+                duration,
+                throughput(bytes, duration),
+                rows,
+                content_sha256
             );
-            return Ok(());
+            let relative_path = path.strip_prefix(&self.args.output_dir).unwrap_or(&path);
+            entries.push(ManifestPart {
+                part,
+                path: relative_path.to_string_lossy().into_owned(),
+                rows: rows as u64,
+                content_sha256,
+                file_index: split.then_some(index),
+                row_offset: split.then_some(row_offset),
+                footer_bytes,
+            });
+            row_offset += rows as u64;
         }
 
-        // Write to temp file first
-        let temp_path = self.output_path.with_extension("inprogress");
-        let t0 = Instant::now();
-        let file = std::fs::File::create(&temp_path)?;
-        let mut writer =
-            ArrowWriter::try_new(file, Arc::clone(&self.schema), Some(self.props.clone()))?;
+        self.record_part(part, entries)?;
+        Ok(Some(total_rows))
+    }
 
-        for batch in batches {
-            writer.write(batch)?;
+    /// The span of writing the part, its rows and bytes recorded once written
+    fn write_span(&self) -> Span {
+        info_span!(
+            "zone.write",
+            part = self.args.part.unwrap_or(1),
+            parts = self.args.parts.unwrap_or(1),
+            rows = field::Empty,
+            bytes = field::Empty,
+        )
+    }
+
+    /// Records the files written for `part` in the manifest
+    fn record_part(&self, part: i32, entries: Vec<ManifestPart>) -> Result<()> {
+        run_manifest(&self.args, &self.schema_version, &self.schema).record_part(
+            &self.args.output_dir,
+            part,
+            entries,
+        )
+    }
+
+    /// Starts writing the part from batches streamed in key order, or
+    /// returns `None` if the part already exists.
+    ///
+    /// Only the delimited text formats are streamed: they are written row
+    /// by row, while the other formats take metadata from every batch of
+    /// the part.
+    pub fn start_stream(&self) -> Result<Option<StreamedPart>> {
+        if !matches!(
+            self.args.file_format,
+            ZoneFileFormat::Csv | ZoneFileFormat::Tbl
+        ) {
+            return Err(anyhow::anyhow!(
+                "Only CSV and TBL zone files are written as their rows stream, not {}",
+                self.args.file_extension()
+            ));
+        }
+        let parent_dir = self
+            .output_path
+            .parent()
+            .ok_or_else(|| anyhow::anyhow!("Invalid output path: {:?}", self.output_path))?;
+        std::fs::create_dir_all(parent_dir)?;
+        if self.args.include_attribution {
+            write_attribution(&self.args.output_dir, &self.args.themes)?;
+        }
+        if let Some(existing) = self.existing_output(parent_dir)? {
+            info!("{} already exists, skipping generation", existing.display());
+            return Ok(None);
         }
 
-        writer.close()?;
+        let file = PartFile::create(
+            &self.output_path.with_extension("inprogress"),
+            Some(
+                self.args
+                    .write_buffer_bytes
+                    .unwrap_or(DEFAULT_STREAM_BUFFER_BYTES),
+            ),
+            self.args.fsync,
+        )?;
+        Ok(Some(StreamedPart {
+            writer: self.format_writer(file)?,
+            hasher: ContentHasher::try_new(&self.schema)?,
+            rows: 0,
+            truncated: TruncationReport::default(),
+            started: Instant::now(),
+            span: self.write_span(),
+        }))
+    }
 
-        // Rename temp file to final output
-        std::fs::rename(&temp_path, &self.output_path).map_err(|e| {
-            anyhow::anyhow!(
-                "Failed to rename {:?} to {:?}: {}",
-                temp_path,
-                self.output_path,
-                e
+    /// Writes the next batch of a part started by [`Self::start_stream`]
+    pub fn write_stream(&self, part: &mut StreamedPart, batch: &RecordBatch) -> Result<()> {
+        let batch = if self.args.max_string_lengths.is_empty() {
+            batch.clone()
+        } else {
+            let (mut batches, truncated) = limit_string_lengths(
+                std::slice::from_ref(batch),
+                &self.args.max_string_lengths,
+                self.args.on_overlong,
             )
-        })?;
-
-        let duration = t0.elapsed();
-        let total_rows: usize = batches.iter().map(|b| b.num_rows()).sum();
+            .map_err(|e| anyhow::anyhow!("Not writing {}: {e}", self.output_path.display()))?;
+            part.truncated.extend(truncated);
+            batches.remove(0)
+        };
+        let batch = self.project(&batch)?;
+        part.hasher.update(&batch)?;
+        self.write_batch(&mut part.writer, &batch)?;
+        part.writer.groups_written()?;
+        part.rows += batch.num_rows();
+        Ok(())
+    }
 
+    /// Moves a streamed part into place and records it in the manifest,
+    /// returning the number of rows written
+    pub fn finish_stream(&self, part: StreamedPart) -> Result<usize> {
+        part.writer.close()?;
+        let temp_path = self.output_path.with_extension("inprogress");
+        sync_closed(&temp_path, self.args.fsync)?;
+        let bytes = std::fs::metadata(&temp_path)?.len();
+        rename_into_place(&self.output_path, self.args.fsync)?;
+        if !self.args.max_string_lengths.is_empty() {
+            part.truncated
+                .write_csv(&self.args.truncation_report_filename())?;
+        }
+        part.span.record("rows", part.rows);
+        part.span.record("bytes", bytes);
+        let content_sha256 = part.hasher.finish();
+        let duration = part.started.elapsed();
         info!(
-            "Zone -> {} (part {:?}/{:?}). write={:?}, total_rows={}",
+            "Zone -> {} (part {:?}/{:?}). write={:?}, throughput={}, total_rows={}, content_sha256={}",
             self.output_path.display(),
             self.args.part,
             self.args.parts,
             duration,
-            total_rows
+            throughput(bytes, duration),
+            part.rows,
+            content_sha256
         );
 
+        let relative_path = self
+            .output_path
+            .strip_prefix(&self.args.output_dir)
+            .unwrap_or(&self.output_path);
+        self.record_part(
+            self.args.part.unwrap_or(1),
+            vec![ManifestPart {
+                part: self.args.part.unwrap_or(1),
+                path: relative_path.to_string_lossy().into_owned(),
+                rows: part.rows as u64,
+                content_sha256,
+                file_index: None,
+                row_offset: None,
+                footer_bytes: None,
+            }],
+        )?;
+        Ok(part.rows)
+    }
+
+    /// Removes the file of a streamed part that failed
+    pub fn discard_stream(&self, part: StreamedPart) -> Result<()> {
+        drop(part);
+        std::fs::remove_file(self.output_path.with_extension("inprogress"))?;
+        Ok(())
+    }
+
+    /// Writes all `parts`, the batches of every part in part order, into
+    /// [`ZoneDfArgs::merged_filename`] and records it in the manifest as their merged
+    /// copy. Every part starts a new row group or stripe, so none holds
+    /// rows of two parts. Returns the number of rows written or `None` if
+    /// the file already existed and was skipped.
+    pub fn write_merged(&self, parts: &[Vec<RecordBatch>]) -> Result<Option<usize>> {
+        let path = self.args.merged_filename();
+        if path.exists() {
+            info!("{} already exists, skipping merge", path.display());
+            return Ok(None);
+        }
+
+        let mut part_ends = Vec::with_capacity(parts.len());
+        let mut batches = Vec::new();
+        for part in parts {
+            batches.extend(self.limit_string_lengths(part, false)?);
+            part_ends.push(batches.len());
+        }
+        let batches = &batches;
+        let t0 = Instant::now();
+        let content_sha256 = self.write_temp(&path, batches, &part_ends)?;
+        rename_into_place(&path, self.args.fsync)?;
+        let duration = t0.elapsed();
+        let total_rows: usize = batches.iter().map(|b| b.num_rows()).sum();
+        info!(
+            "Zone parts merged -> {}. write={:?}, throughput={}, total_rows={}, content_sha256={}",
+            path.display(),
+            duration,
+            throughput(std::fs::metadata(&path)?.len(), duration),
+            total_rows,
+            content_sha256
+        );
+        let footer_bytes = self.footer_bytes(&path)?;
+
+        ZoneManifest::record_merged(
+            &self.args.output_dir,
+            MergedFile {
+                path: path
+                    .strip_prefix(&self.args.output_dir)
+                    .unwrap_or(&path)
+                    .to_string_lossy()
+                    .into_owned(),
+                rows: total_rows as u64,
+                content_sha256,
+                footer_bytes,
+            },
+        )?;
+        Ok(Some(total_rows))
+    }
+
+    /// Writes the part as files of at most `max_rows` rows named by
+    /// [`ZoneDfArgs::split_filename`], returning the path, row count and
+    /// content hash of every file. The files are renamed into place once all
+    /// of them are written, so a failed part leaves no file behind.
+    fn write_split(
+        &self,
+        batches: &[RecordBatch],
+        max_rows: usize,
+    ) -> Result<Vec<(PathBuf, usize, String)>> {
+        let mut files = Vec::new();
+        for (index, file_batches) in split_rows(batches, max_rows).iter().enumerate() {
+            let path = self.args.split_filename(index);
+            match self.write_temp(&path, file_batches, &[]) {
+                Ok(content_sha256) => {
+                    let rows = file_batches.iter().map(|b| b.num_rows()).sum();
+                    files.push((path, rows, content_sha256));
+                }
+                Err(e) => {
+                    for (path, _, _) in &files {
+                        std::fs::remove_file(path.with_extension("inprogress"))?;
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        for (path, _, _) in &files {
+            rename_into_place(path, self.args.fsync)?;
+        }
+        Ok(files)
+    }
+
+    /// Writes `batches` to `path` through a temporary file, returning their
+    /// content hash
+    fn write_file(&self, path: &Path, batches: &[RecordBatch]) -> Result<String> {
+        let content_sha256 = self.write_temp(path, batches, &[])?;
+        rename_into_place(path, self.args.fsync)?;
+        Ok(content_sha256)
+    }
+
+    /// Writes `batches` to the temporary file of `path`, returning their
+    /// content hash. A row group or stripe ends after the batches counted
+    /// by every entry of the ascending `group_ends`.
+    fn write_temp(
+        &self,
+        path: &Path,
+        batches: &[RecordBatch],
+        group_ends: &[usize],
+    ) -> Result<String> {
+        let temp_path = path.with_extension("inprogress");
+        let file = PartFile::create(&temp_path, self.args.write_buffer_bytes, self.args.fsync)?;
+        let mut writer = self.format_writer(file)?;
+
+        let mut hasher = ContentHasher::try_new(&self.schema)?;
+        for (index, batch) in batches.iter().enumerate() {
+            if self.args.on_interrupt == OnInterrupt::AbortPart
+                && self.args.cancellation.is_cancelled()
+            {
+                drop(writer);
+                std::fs::remove_file(&temp_path)?;
+                info!("Aborted part {:?}, removed {:?}", self.args.part, temp_path);
+                return Err(interrupted_error().into());
+            }
+            let batch = self.project(batch)?;
+            hasher.update(&batch)?;
+            self.write_batch(&mut writer, &batch)?;
+            if group_ends.binary_search(&(index + 1)).is_ok() {
+                writer.end_group()?;
+            }
+            writer.groups_written()?;
+        }
+
+        self.append_metadata(&mut writer, batches)?;
+        let content_sha256 = hasher.finish();
+        writer.append_key_value_metadata(CONTENT_SHA256_KEY, content_sha256.clone());
+        writer.close()?;
+        sync_closed(&temp_path, self.args.fsync)?;
+        Ok(content_sha256)
+    }
+
+    /// Encodes `batches` into `sink` the way [`Self::write`] does, without
+    /// hashing or touching the output directory
+    pub fn encode<W: Write + Send>(&self, sink: W, batches: &[RecordBatch]) -> Result<()> {
+        let batches = &self.limit_string_lengths(batches, false)?;
+        let mut writer = self.format_writer(sink)?;
+        for batch in batches {
+            self.write_batch(&mut writer, &self.project(batch)?)?;
+        }
+        self.append_metadata(&mut writer, batches)?;
+        writer.close()?;
+        Ok(())
+    }
+
+    /// Cuts the strings of `batches` to their `--max-string-length`,
+    /// writing the report of the cut values for a part when `report` is set
+    fn limit_string_lengths(
+        &self,
+        batches: &[RecordBatch],
+        report: bool,
+    ) -> Result<Vec<RecordBatch>> {
+        if self.args.max_string_lengths.is_empty() {
+            return Ok(batches.to_vec());
+        }
+        let (batches, truncated) = limit_string_lengths(
+            batches,
+            &self.args.max_string_lengths,
+            self.args.on_overlong,
+        )
+        .map_err(|e| anyhow::anyhow!("Not writing {}: {e}", self.output_path.display()))?;
+        if report {
+            truncated.write_csv(&self.args.truncation_report_filename())?;
+        }
+        Ok(batches)
+    }
+
+    /// The columns of `batch` in the written schema, which leaves out and
+    /// reorders columns as `--columns` selects and carries the
+    /// `--max-string-length` field metadata
+    fn project(&self, batch: &RecordBatch) -> Result<RecordBatch> {
+        if batch.schema() == self.schema {
+            return Ok(batch.clone());
+        }
+        let columns = self
+            .schema
+            .fields()
+            .iter()
+            .map(|field| {
+                let column = batch.column_by_name(field.name()).ok_or_else(|| {
+                    anyhow::anyhow!("Column {} not found in zone batch", field.name())
+                })?;
+                // The batches keep z_boundary as WKB; a Utf8 field asks for
+                // --geometry-encoding geojson
+                match field.name() == GEOMETRY_COLUMN
+                    && field.data_type() == &DataType::Utf8
+                    && column.data_type() != &DataType::Utf8
+                {
+                    true => encode_geojson(column),
+                    false => Ok(Arc::clone(column)),
+                }
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(RecordBatch::try_new(Arc::clone(&self.schema), columns)?)
+    }
+
+    /// The footer size of the Parquet file at `path`, logged per file;
+    /// `None` for the other formats
+    fn footer_bytes(&self, path: &Path) -> Result<Option<u64>> {
+        if self.args.file_format != ZoneFileFormat::Parquet {
+            return Ok(None);
+        }
+        let bytes = parquet_footer_bytes(path)?;
+        info!("{} footer: {bytes} bytes", path.display());
+        Ok(Some(bytes))
+    }
+
+    fn format_writer<W: Write + Send>(&self, sink: W) -> Result<FormatWriter<W>> {
+        Ok(match &self.args.file_format {
+            ZoneFileFormat::Parquet => FormatWriter::Parquet(ArrowWriter::try_new_with_options(
+                sink,
+                Arc::clone(&self.schema),
+                ArrowWriterOptions::new()
+                    .with_properties(self.props.clone())
+                    .with_skip_arrow_metadata(self.args.parquet.small_footer),
+            )?),
+            ZoneFileFormat::Orc(orc) => FormatWriter::Orc(OrcWriter::try_new(
+                sink,
+                Arc::clone(&self.schema),
+                orc.compression,
+                self.rows_per_stripe,
+            )?),
+            ZoneFileFormat::Csv => FormatWriter::Text(TextWriter::try_new(
+                sink,
+                TextFormat::Csv,
+                Arc::clone(&self.schema),
+            )?),
+            ZoneFileFormat::Tbl => FormatWriter::Text(TextWriter::try_new(
+                sink,
+                TextFormat::Tbl,
+                Arc::clone(&self.schema),
+            )?),
+            ZoneFileFormat::Avro(codec) => FormatWriter::Avro {
+                sink,
+                header: AvroHeader::try_new("zone", Arc::clone(&self.schema), *codec)?,
+                batches: vec![],
+            },
+        })
+    }
+
+    /// Records the schema version, the generation parameters with
+    /// `--embed-params` and whether `--limit` truncated the table, and writes the GeoParquet metadata declaring the bounds of
+    /// `--with-bbox-covering` when they are written
+    fn append_metadata<W: Write + Send>(
+        &self,
+        writer: &mut FormatWriter<W>,
+        batches: &[RecordBatch],
+    ) -> Result<()> {
+        writer.append_key_value_metadata(SCHEMA_VERSION_KEY, self.schema_version.clone());
+        if let Some(params) = &self.args.generation_params {
+            let params = match params.input {
+                Some(_) => params.clone(),
+                None => params.clone().with_input(self.args.source_provenance()),
+            };
+            writer.append_key_value_metadata(GENERATION_PARAMS_KEY, params.to_json());
+        }
+        if self.args.limit.is_some() {
+            writer.append_key_value_metadata(TRUNCATED_KEY, "true".to_string());
+        }
+        let layout = self.args.transform.bbox_layout;
+        if matches!(writer, FormatWriter::Parquet(_))
+            && self.args.transform.bbox_covering
+            && layout
+                .columns()
+                .iter()
+                .chain([&GEOMETRY_COLUMN])
+                .all(|name| self.schema.field_with_name(name).is_ok())
+        {
+            writer.append_key_value_metadata(GEO_METADATA_KEY, geo_metadata(batches, layout)?);
+        }
         Ok(())
     }
+
+    fn write_batch<W: Write + Send>(
+        &self,
+        writer: &mut FormatWriter<W>,
+        batch: &RecordBatch,
+    ) -> Result<()> {
+        let writer = match writer {
+            FormatWriter::Parquet(writer) => writer,
+            FormatWriter::Orc(writer) => return writer.write(batch),
+            FormatWriter::Text(writer) => return writer.write(batch),
+            FormatWriter::Avro { batches, .. } => {
+                batches.push(batch.clone());
+                return Ok(());
+            }
+        };
+        match self.args.parquet.write_batch_size {
+            Some(rows) => {
+                for offset in (0..batch.num_rows()).step_by(rows) {
+                    writer.write(&batch.slice(offset, rows.min(batch.num_rows() - offset)))?;
+                }
+            }
+            None => writer.write(batch)?,
+        }
+        Ok(())
+    }
+
+    /// Returns the file already written for this part, if any, or the first
+    /// of the files it was split into.
+    ///
+    /// Spark layout names embed a per-invocation uuid, so an earlier run's
+    /// file for the same part is found by its `part-NNNNN-` prefix.
+    fn existing_output(&self, parent_dir: &std::path::Path) -> Result<Option<PathBuf>> {
+        if self.args.layout != ZoneLayout::Spark {
+            let first_split = self.args.split_filename(0);
+            return Ok([&self.output_path, &first_split]
+                .into_iter()
+                .find(|path| path.exists())
+                .cloned());
+        }
+
+        let prefix = format!(
+            "{}-",
+            ZoneDfArgs::spark_part_prefix(self.args.part.unwrap_or(1))
+        );
+        for entry in std::fs::read_dir(parent_dir)? {
+            let path = entry?.path();
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            if name.starts_with(&prefix) && name.ends_with(self.args.file_extension()) {
+                return Ok(Some(path));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Renames the temporary file of `path` written by
+/// [`ParquetWriter::write_temp`] to `path`
+/// Size of the footer of the Parquet file at `path`: the Thrift file
+/// metadata plus its 4-byte length and the closing `PAR1`, read from the
+/// last 8 bytes of the file
+pub(super) fn parquet_footer_bytes(path: &Path) -> Result<u64> {
+    let mut file = std::fs::File::open(path)?;
+    let len = file.metadata()?.len();
+    if len < 8 {
+        return Err(anyhow::anyhow!("{} is not a Parquet file", path.display()));
+    }
+    let mut tail = [0u8; 8];
+    file.seek(SeekFrom::Start(len - 8))?;
+    file.read_exact(&mut tail)?;
+    if &tail[4..] != b"PAR1" {
+        return Err(anyhow::anyhow!("{} is not a Parquet file", path.display()));
+    }
+    Ok(u32::from_le_bytes([tail[0], tail[1], tail[2], tail[3]]) as u64 + 8)
+}
+
+/// The manifest of a run with `args` writing files with `schema`, without
+/// any files
+pub(super) fn run_manifest(
+    args: &ZoneDfArgs,
+    schema_version: &str,
+    schema: &Schema,
+) -> ZoneManifest {
+    ZoneManifest::new(args.scale_factor, args.parts.unwrap_or(1))
+        .with_total_rows(args.total_rows.map(|rows| rows as u64))
+        .with_boundaries(args.part_boundaries.clone())
+        .with_extents(args.part_extents.clone())
+        .with_source(args.source_provenance())
+        .with_seed(Some(args.transform.seed))
+        .with_rows(args.rows)
+        .with_limit(args.limit)
+        .with_keep_geometry_types(args.keep_geometry_type_names())
+        .with_sampling(args.sampling_description())
+        .with_schema_version(Some(schema_version.to_string()))
+        .with_columns(Some(ManifestColumn::of_schema(schema)))
+        .with_attribution_sha256(
+            args.include_attribution
+                .then(|| attribution_sha256(&attribution_text(&args.themes))),
+        )
+}
+
+/// Renames the temporary file of `path` into place, syncing the directory
+/// entry for `--fsync`
+fn rename_into_place(path: &Path, fsync: Fsync) -> Result<()> {
+    let temp_path = path.with_extension("inprogress");
+    std::fs::rename(&temp_path, path)
+        .map_err(|e| anyhow::anyhow!("Failed to rename {:?} to {:?}: {}", temp_path, path, e))?;
+    sync_dir(path, fsync)?;
+    Ok(())
+}
+
+/// Cuts `batches` into runs of at most `max_rows` rows, keeping their order
+fn split_rows(batches: &[RecordBatch], max_rows: usize) -> Vec<Vec<RecordBatch>> {
+    let mut files = vec![Vec::new()];
+    let mut rows = 0;
+    for batch in batches {
+        let mut offset = 0;
+        while offset < batch.num_rows() {
+            if rows == max_rows {
+                files.push(Vec::new());
+                rows = 0;
+            }
+            let length = (max_rows - rows).min(batch.num_rows() - offset);
+            files.last_mut().unwrap().push(batch.slice(offset, length));
+            rows += length;
+            offset += length;
+        }
+    }
+    files
+}
+
+/// Fails unless `z_zonekey` strictly ascends through `batches`
+fn check_key_order(batches: &[RecordBatch]) -> Result<()> {
+    let mut previous: Option<i64> = None;
+    let mut row = 0;
+    for batch in batches {
+        let keys = batch
+            .column_by_name(KEY_COLUMN)
+            .ok_or_else(|| anyhow::anyhow!("Column {KEY_COLUMN} not found in zone batch"))?;
+        let keys = cast(keys, &DataType::Int64)?;
+        let keys = keys.as_any().downcast_ref::<Int64Array>().unwrap();
+        for key in keys.iter() {
+            let key = key.ok_or_else(|| anyhow::anyhow!("{KEY_COLUMN} is null at row {row}"))?;
+            if let Some(previous) = previous.filter(|&previous| key <= previous) {
+                return Err(anyhow::anyhow!(
+                    "{KEY_COLUMN} {key} at row {row} follows {previous}, keys are not ascending"
+                ));
+            }
+            previous = Some(key);
+            row += 1;
+        }
+    }
+    debug!("{KEY_COLUMN} ascends through {row} row(s)");
+    Ok(())
+}
+
+/// Fails unless the `z_zonekey` values of `batches` are exactly
+/// `first..first + rows`, each once, in any order
+pub(super) fn check_contiguous_keys(batches: &[RecordBatch], first: i64) -> Result<()> {
+    let rows: usize = batches.iter().map(|b| b.num_rows()).sum();
+    let last = first + rows as i64 - 1;
+    let mut seen = vec![false; rows];
+    let mut row = 0;
+    for batch in batches {
+        let keys = batch
+            .column_by_name(KEY_COLUMN)
+            .ok_or_else(|| anyhow::anyhow!("Column {KEY_COLUMN} not found in zone batch"))?;
+        let keys = cast(keys, &DataType::Int64)?;
+        let keys = keys.as_any().downcast_ref::<Int64Array>().unwrap();
+        for key in keys.iter() {
+            let key = key.ok_or_else(|| anyhow::anyhow!("{KEY_COLUMN} is null at row {row}"))?;
+            // A key out of range leaves one in range missing, reported below
+            if let Some(seen) = usize::try_from(key - first)
+                .ok()
+                .and_then(|i| seen.get_mut(i))
+            {
+                if *seen {
+                    return Err(anyhow::anyhow!(
+                        "{KEY_COLUMN} is not contiguous: {key} at row {row} is a duplicate \
+                         in {first}..={last}"
+                    ));
+                }
+                *seen = true;
+            }
+            row += 1;
+        }
+    }
+    if let Some(missing) = seen.iter().position(|seen| !seen) {
+        return Err(anyhow::anyhow!(
+            "{KEY_COLUMN} is not contiguous: {} is missing from {first}..={last}",
+            first + missing as i64
+        ));
+    }
+    debug!("{KEY_COLUMN} is {first}..={last}");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interrupt::CancellationFlag;
+    use crate::zone::ParquetWriteOptions;
+    use arrow_schema::{Field, Schema};
+    use parquet::basic::Compression;
+
+    fn write_cancelled(dir: &std::path::Path, on_interrupt: OnInterrupt) -> Result<Option<usize>> {
+        let cancellation = CancellationFlag::default();
+        cancellation.cancel();
+        let args = ZoneDfArgs::new(
+            1.0,
+            dir.to_path_buf(),
+            Some(1),
+            Some(1),
+            None,
+            1024 * 1024,
+            Compression::SNAPPY,
+        )
+        .with_interrupt(cancellation, on_interrupt);
+
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "z_zonekey",
+            DataType::Int64,
+            false,
+        )]));
+        let batch =
+            RecordBatch::try_new(schema.clone(), vec![Arc::new(Int64Array::from(vec![1, 2]))])
+                .unwrap();
+        let stats = ZoneTableStats::new(1.0, Some(1));
+        ParquetWriter::new(&args, &stats, schema).write(&[batch])
+    }
+
+    #[test]
+    fn test_write_span_records_part_rows_and_bytes() {
+        let dir = tempfile::tempdir().unwrap();
+        let args = ZoneDfArgs::new(
+            1.0,
+            dir.path().to_path_buf(),
+            Some(3),
+            Some(2),
+            None,
+            1024 * 1024,
+            Compression::SNAPPY,
+        );
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "z_zonekey",
+            DataType::Int64,
+            false,
+        )]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(Int64Array::from(vec![4, 5, 6]))],
+        )
+        .unwrap();
+        let stats = ZoneTableStats::new(1.0, Some(3));
+
+        let collector = Arc::new(crate::otlp::SpanCollector::default());
+        tracing::subscriber::with_default(Arc::clone(&collector), || {
+            ParquetWriter::new(&args, &stats, schema)
+                .write(&[batch])
+                .unwrap();
+        });
+
+        let (_, body) = collector.take_request();
+        let spans = body["resourceSpans"][0]["scopeSpans"][0]["spans"]
+            .as_array()
+            .unwrap();
+        let write = spans.iter().find(|s| s["name"] == "zone.write").unwrap();
+        let attribute = |key: &str| -> u64 {
+            let attributes = write["attributes"].as_array().unwrap();
+            let attribute = attributes.iter().find(|a| a["key"] == key).unwrap();
+            attribute["value"]["intValue"]
+                .as_str()
+                .unwrap()
+                .parse()
+                .unwrap()
+        };
+        let bytes = std::fs::metadata(args.output_filename()).unwrap().len();
+        assert_eq!(attribute("part"), 2);
+        assert_eq!(attribute("parts"), 3);
+        assert_eq!(attribute("rows"), 3);
+        assert_eq!(attribute("bytes"), bytes);
+    }
+
+    #[test]
+    fn test_check_key_order() {
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "z_zonekey",
+            DataType::Int64,
+            false,
+        )]));
+        let keys = |keys: Vec<i64>| {
+            RecordBatch::try_new(schema.clone(), vec![Arc::new(Int64Array::from(keys))]).unwrap()
+        };
+        check_key_order(&[keys(vec![1, 2]), keys(vec![5, 9])]).unwrap();
+        let error = check_key_order(&[keys(vec![1, 4]), keys(vec![4, 9])]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "z_zonekey 4 at row 2 follows 4, keys are not ascending"
+        );
+    }
+
+    #[test]
+    fn test_check_contiguous_keys() {
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "z_zonekey",
+            DataType::Int64,
+            false,
+        )]));
+        let keys = |keys: Vec<i64>| {
+            RecordBatch::try_new(schema.clone(), vec![Arc::new(Int64Array::from(keys))]).unwrap()
+        };
+        check_contiguous_keys(&[keys(vec![3, 1]), keys(vec![2, 4])], 1).unwrap();
+        check_contiguous_keys(&[keys(vec![11, 12])], 11).unwrap();
+        check_contiguous_keys(&[], 1).unwrap();
+
+        // An off-by-one offset leaves a gap at the start
+        let error = check_contiguous_keys(&[keys(vec![2, 3]), keys(vec![4, 5])], 1).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "z_zonekey is not contiguous: 1 is missing from 1..=4"
+        );
+        let error = check_contiguous_keys(&[keys(vec![1, 2]), keys(vec![4, 5])], 1).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "z_zonekey is not contiguous: 3 is missing from 1..=4"
+        );
+        let error = check_contiguous_keys(&[keys(vec![1, 2]), keys(vec![2, 3])], 1).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "z_zonekey is not contiguous: 2 at row 2 is a duplicate in 1..=4"
+        );
+    }
+
+    #[test]
+    fn test_abort_part_removes_partial_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let err = write_cancelled(dir.path(), OnInterrupt::AbortPart).unwrap_err();
+        let err = err.downcast::<std::io::Error>().unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::Interrupted);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_finish_part_completes_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let written = write_cancelled(dir.path(), OnInterrupt::FinishPart).unwrap();
+        assert_eq!(written, Some(2));
+        assert!(dir.path().join("zone.parquet").exists());
+        let manifest = ZoneManifest::read(dir.path()).unwrap().unwrap();
+        assert_eq!(manifest.files.len(), 1);
+        assert_eq!(manifest.schema_version.as_deref(), Some("1"));
+
+        let file = std::fs::File::open(dir.path().join("zone.parquet")).unwrap();
+        let metadata = parquet::file::metadata::ParquetMetaDataReader::new()
+            .parse_and_finish(&file)
+            .unwrap();
+        let version = metadata
+            .file_metadata()
+            .key_value_metadata()
+            .unwrap()
+            .iter()
+            .find(|kv| kv.key == SCHEMA_VERSION_KEY)
+            .and_then(|kv| kv.value.clone());
+        assert_eq!(version.as_deref(), Some("1"));
+    }
+
+    fn data_page_count(path: &std::path::Path) -> usize {
+        use parquet::file::reader::{FileReader, SerializedFileReader};
+
+        let reader = SerializedFileReader::new(std::fs::File::open(path).unwrap()).unwrap();
+        let mut pages = 0;
+        for i in 0..reader.num_row_groups() {
+            let row_group = reader.get_row_group(i).unwrap();
+            for page in row_group.get_column_page_reader(0).unwrap() {
+                if !page.unwrap().is_dictionary_page() {
+                    pages += 1;
+                }
+            }
+        }
+        pages
+    }
+
+    #[test]
+    fn test_smaller_page_size_writes_more_pages() {
+        let write = |dir: &std::path::Path, page_size: Option<usize>| {
+            let args = ZoneDfArgs::new(
+                1.0,
+                dir.to_path_buf(),
+                None,
+                None,
+                None,
+                128 * 1024 * 1024,
+                Compression::UNCOMPRESSED,
+            )
+            .with_parquet(
+                ParquetWriteOptions::new(Compression::UNCOMPRESSED, 128 * 1024 * 1024)
+                    .with_page_sizes(page_size, None)
+                    .with_write_batch_size(Some(1000)),
+            );
+            let schema = Arc::new(Schema::new(vec![Field::new(
+                "z_zonekey",
+                DataType::Int64,
+                false,
+            )]));
+            let batch = RecordBatch::try_new(
+                schema.clone(),
+                vec![Arc::new(Int64Array::from_iter_values(0..10_000))],
+            )
+            .unwrap();
+            let stats = ZoneTableStats::new(1.0, Some(1));
+            ParquetWriter::new(&args, &stats, schema)
+                .write(&[batch])
+                .unwrap();
+            data_page_count(&dir.join("zone.parquet"))
+        };
+
+        let default_dir = tempfile::tempdir().unwrap();
+        let small_dir = tempfile::tempdir().unwrap();
+        let default_pages = write(default_dir.path(), None);
+        let small_pages = write(small_dir.path(), Some(1024));
+        assert_eq!(default_pages, 1);
+        assert!(small_pages > default_pages, "{small_pages} pages");
+    }
+
+    /// Columns whose first chunk starts with a dictionary page
+    fn dictionary_encoded_columns(path: &std::path::Path) -> Vec<String> {
+        use parquet::file::reader::{FileReader, SerializedFileReader};
+
+        let reader = SerializedFileReader::new(std::fs::File::open(path).unwrap()).unwrap();
+        reader
+            .metadata()
+            .row_group(0)
+            .columns()
+            .iter()
+            .filter(|column| column.dictionary_page_offset().is_some())
+            .map(|column| column.column_path().string())
+            .collect()
+    }
+
+    #[test]
+    fn test_dictionary_columns() {
+        use arrow_array::{BinaryArray, StringArray};
+
+        let write = |dir: &std::path::Path, enabled: &[&str], disabled: &[&str]| {
+            let args = ZoneDfArgs::new(
+                1.0,
+                dir.to_path_buf(),
+                None,
+                None,
+                None,
+                1024 * 1024,
+                Compression::SNAPPY,
+            )
+            .with_parquet(
+                ParquetWriteOptions::new(Compression::SNAPPY, 1024 * 1024).with_dictionary_columns(
+                    enabled.iter().map(|c| c.to_string()).collect(),
+                    disabled.iter().map(|c| c.to_string()).collect(),
+                ),
+            );
+            let schema = Arc::new(Schema::new(vec![
+                Field::new("z_zonekey", DataType::Int64, false),
+                Field::new("z_gersid", DataType::Utf8, false),
+                Field::new("z_country", DataType::Utf8, false),
+                Field::new("z_subtype", DataType::Utf8, false),
+                Field::new("z_boundary", DataType::Binary, false),
+            ]));
+            let batch = RecordBatch::try_new(
+                schema.clone(),
+                vec![
+                    Arc::new(Int64Array::from(vec![1, 2])),
+                    Arc::new(StringArray::from(vec!["g1", "g2"])),
+                    Arc::new(StringArray::from(vec!["US", "US"])),
+                    Arc::new(StringArray::from(vec!["county", "county"])),
+                    Arc::new(BinaryArray::from(vec![b"a".as_slice(), b"b".as_slice()])),
+                ],
+            )
+            .unwrap();
+            let stats = ZoneTableStats::new(1.0, Some(1));
+            ParquetWriter::new(&args, &stats, schema)
+                .write(&[batch])
+                .unwrap();
+            dictionary_encoded_columns(&dir.join("zone.parquet"))
+        };
+
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(write(dir.path(), &[], &[]), vec!["z_country", "z_subtype"]);
+
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(
+            write(dir.path(), &["z_gersid"], &["z_subtype"]),
+            vec!["z_gersid", "z_country"]
+        );
+    }
+
+    #[test]
+    fn test_dictionary_columns_conflict() {
+        let args = ZoneDfArgs::new(
+            1.0,
+            PathBuf::from("unused"),
+            None,
+            None,
+            None,
+            1024 * 1024,
+            Compression::SNAPPY,
+        )
+        .with_parquet(
+            ParquetWriteOptions::new(Compression::SNAPPY, 1024 * 1024)
+                .with_dictionary_columns(vec!["z_name".to_string()], vec!["z_name".to_string()]),
+        );
+        assert!(args.validate().is_err());
+    }
+
+    #[test]
+    fn test_small_footer_shrinks_the_footer() {
+        use crate::zone::fixtures::wkb_polygon;
+        use arrow_array::{BinaryArray, StringArray};
+
+        // 200 parts of 5 rows, merged into as many row groups
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("z_zonekey", DataType::Int64, false),
+            Field::new("z_gersid", DataType::Utf8, false),
+            Field::new("z_boundary", DataType::Binary, false),
+        ]));
+        let parts: Vec<Vec<RecordBatch>> = (0..200)
+            .map(|part| {
+                let keys = (part * 5..part * 5 + 5).collect::<Vec<i64>>();
+                let ids = keys.iter().map(|k| format!("{k:0>32}")).collect::<Vec<_>>();
+                let boundaries = keys
+                    .iter()
+                    .map(|&k| {
+                        let ring = (0..40)
+                            .map(|i| (k as f64 + (i as f64).cos(), (i as f64).sin()))
+                            .chain([(k as f64 + 1.0, 0.0)])
+                            .collect::<Vec<_>>();
+                        wkb_polygon(&ring)
+                    })
+                    .collect::<Vec<_>>();
+                vec![RecordBatch::try_new(
+                    schema.clone(),
+                    vec![
+                        Arc::new(Int64Array::from(keys)),
+                        Arc::new(StringArray::from(ids)),
+                        Arc::new(BinaryArray::from_iter_values(boundaries)),
+                    ],
+                )
+                .unwrap()]
+            })
+            .collect();
+
+        let write = |dir: &std::path::Path, small_footer: bool| {
+            let args = ZoneDfArgs::new(
+                1.0,
+                dir.to_path_buf(),
+                None,
+                None,
+                None,
+                1024 * 1024,
+                Compression::SNAPPY,
+            )
+            .with_parquet(
+                ParquetWriteOptions::new(Compression::SNAPPY, 1024 * 1024)
+                    .with_small_footer(small_footer),
+            );
+            let stats = ZoneTableStats::new(1.0, Some(1));
+            ZoneManifest::new(1.0, 200).write(dir).unwrap();
+            ParquetWriter::new(&args, &stats, schema.clone())
+                .write_merged(&parts)
+                .unwrap();
+            let footer_bytes = parquet_footer_bytes(&dir.join("zone.parquet")).unwrap();
+            let manifest = ZoneManifest::read(dir).unwrap().unwrap();
+            assert_eq!(manifest.merged.unwrap().footer_bytes, Some(footer_bytes));
+            footer_bytes
+        };
+
+        let dir = tempfile::tempdir().unwrap();
+        let full = write(dir.path(), false);
+        let dir = tempfile::tempdir().unwrap();
+        let small = write(dir.path(), true);
+        // The z_boundary statistics alone take the full footer over 64 KiB
+        assert!(full > 64 * 1024, "{full}");
+        assert!(small < 64 * 1024, "{small}");
+    }
 }
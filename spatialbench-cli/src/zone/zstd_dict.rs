@@ -0,0 +1,173 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! ZSTD dictionary training on zone values
+//!
+//! The Parquet writer has no way to compress column chunks with an external
+//! dictionary, and the format has nowhere to embed one for readers. Training
+//! therefore only estimates what a dictionary would gain; generation with
+//! `--parquet-zstd-train-dict` stops with that estimate instead of writing
+//! files that silently ignore the flag.
+
+use anyhow::{anyhow, Result};
+use arrow::compute::cast;
+use arrow_array::{Array, BinaryArray, RecordBatch};
+use arrow_schema::DataType;
+use log::info;
+
+/// Size of the trained dictionary, the `zstd --train` default
+pub const DICTIONARY_BYTES: usize = 112_640;
+
+/// Upper bound on the values sampled for training
+const MAX_SAMPLES: usize = 10_000;
+
+/// Values compressed together when measuring, roughly a small page
+const VALUES_PER_FRAME: usize = 64;
+
+#[derive(Debug)]
+pub struct DictionaryReport {
+    pub samples: usize,
+    pub dictionary_bytes: usize,
+    /// Uncompressed over compressed size of the samples without dictionary
+    pub ratio_without: f64,
+    /// The same with the trained dictionary
+    pub ratio_with: f64,
+}
+
+/// Samples string and binary values evenly across `batches`
+pub fn sample_values(batches: &[RecordBatch]) -> Result<Vec<Vec<u8>>> {
+    let mut values = Vec::new();
+    for batch in batches {
+        for column in batch.columns() {
+            let column = match column.data_type() {
+                DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View => {
+                    cast(&cast(column, &DataType::Utf8)?, &DataType::Binary)?
+                }
+                DataType::Binary | DataType::LargeBinary | DataType::BinaryView => {
+                    cast(column, &DataType::Binary)?
+                }
+                _ => continue,
+            };
+            let column = column.as_any().downcast_ref::<BinaryArray>().unwrap();
+            values.extend(column.iter().flatten().map(<[u8]>::to_vec));
+        }
+    }
+
+    let step = values.len().div_ceil(MAX_SAMPLES).max(1);
+    Ok(values.into_iter().step_by(step).collect())
+}
+
+/// Trains a dictionary on samples of `batches` and measures it against
+/// plain ZSTD at `level`
+pub fn train_dictionary(batches: &[RecordBatch], level: i32) -> Result<DictionaryReport> {
+    let samples = sample_values(batches)?;
+    if samples.is_empty() {
+        return Err(anyhow!(
+            "No string or binary values to train a ZSTD dictionary on"
+        ));
+    }
+    let dictionary = zstd::dict::from_samples(&samples, DICTIONARY_BYTES)
+        .map_err(|e| anyhow!("Training a ZSTD dictionary failed: {e}"))?;
+
+    let mut plain = zstd::bulk::Compressor::new(level)?;
+    let mut trained = zstd::bulk::Compressor::with_dictionary(level, &dictionary)?;
+    let (mut raw, mut without, mut with) = (0, 0, 0);
+    for frame in samples.chunks(VALUES_PER_FRAME) {
+        let frame = frame.concat();
+        raw += frame.len();
+        without += plain.compress(&frame)?.len();
+        with += trained.compress(&frame)?.len();
+    }
+
+    let report = DictionaryReport {
+        samples: samples.len(),
+        dictionary_bytes: dictionary.len(),
+        ratio_without: raw as f64 / without as f64,
+        ratio_with: raw as f64 / with as f64,
+    };
+    info!(
+        "Trained a {} byte ZSTD dictionary on {} value(s): compression ratio {:.2} -> {:.2}",
+        report.dictionary_bytes, report.samples, report.ratio_without, report.ratio_with
+    );
+    Ok(report)
+}
+
+/// The error returned for `--parquet-zstd-train-dict` after training, with
+/// the estimated gain
+pub fn unsupported_error(batches: &[RecordBatch], level: i32) -> anyhow::Error {
+    match train_dictionary(batches, level) {
+        Ok(report) => anyhow!(
+            "--parquet-zstd-train-dict: a {} byte dictionary trained on {} value(s) would \
+             change the sample compression ratio from {:.2} to {:.2}, but Parquet column \
+             chunks can't be written with an external ZSTD dictionary. No files were \
+             written; rerun without the flag",
+            report.dictionary_bytes,
+            report.samples,
+            report.ratio_without,
+            report.ratio_with
+        ),
+        Err(e) => anyhow!("--parquet-zstd-train-dict: {e}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_array::{Int64Array, StringArray};
+    use arrow_schema::{Field, Schema};
+    use std::sync::Arc;
+
+    fn names(n: usize) -> RecordBatch {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("z_zonekey", DataType::Int64, false),
+            Field::new("z_name", DataType::Utf8, false),
+        ]));
+        RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(Int64Array::from_iter_values(0..n as i64)),
+                Arc::new(StringArray::from_iter_values((0..n).map(|i| {
+                    format!("Census Tract {i} of Example County, Washington")
+                }))),
+            ],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_samples_text_columns_only() {
+        let samples = sample_values(&[names(3)]).unwrap();
+        assert_eq!(samples.len(), 3);
+        assert_eq!(samples[0], b"Census Tract 0 of Example County, Washington");
+
+        let samples = sample_values(&[names(MAX_SAMPLES * 3)]).unwrap();
+        assert_eq!(samples.len(), MAX_SAMPLES);
+    }
+
+    #[test]
+    fn test_trained_dictionary_report() {
+        // Whether the dictionary helps depends on the data, only check that
+        // both ratios were measured
+        let report = train_dictionary(&[names(5_000)], 3).unwrap();
+        assert_eq!(report.samples, 5_000);
+        assert!(report.dictionary_bytes > 0);
+        assert!(report.ratio_without > 1.0, "{report:?}");
+        assert!(report.ratio_with > 1.0, "{report:?}");
+
+        assert!(train_dictionary(&[], 3).is_err());
+    }
+}
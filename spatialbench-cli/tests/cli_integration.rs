@@ -19,8 +19,14 @@ use arrow_array::RecordBatch;
 use assert_cmd::Command;
 use parquet::arrow::arrow_reader::{ArrowReaderOptions, ParquetRecordBatchReaderBuilder};
 use parquet::file::metadata::ParquetMetaDataReader;
+use spatialbench::dates::parse_generated_date;
 use spatialbench::generators::TripGenerator;
 use spatialbench_arrow::{RecordBatchIterator, TripArrow};
+use spatialbench_cli::dataset::DatasetManifest;
+use spatialbench_cli::generation_params::{GenerationParams, GENERATION_PARAMS_KEY};
+use spatialbench_cli::output_lock::{
+    config_hash, LockHolder, LockMode, OutputLock, LOCK_FILE_NAME,
+};
 use std::fs;
 use std::fs::File;
 use std::io::Read;
@@ -707,6 +713,554 @@ fn test_spatialbench_cli_too_many_parts() {
         ));
 }
 
+/// Test that trip files of adjacent --time-range windows are contiguous key
+/// ranges that together contain the trips of a window spanning them all
+#[test]
+fn test_spatialbench_cli_time_range() {
+    let temp_dir = tempdir().expect("Failed to create temporary directory");
+    for window in ["1992-01-01..1995-07-01", "1995-07-01..1999-01-01"] {
+        Command::cargo_bin("spatialbench-cli")
+            .expect("Binary not found")
+            .arg("--scale-factor")
+            .arg("0.001")
+            .arg("--format")
+            .arg("tbl")
+            .arg("--output-dir")
+            .arg(temp_dir.path())
+            .arg("--parts")
+            .arg("2")
+            .arg("--tables")
+            .arg("trip")
+            .arg("--time-range")
+            .arg(window)
+            .assert()
+            .success();
+    }
+
+    // Read in window and part order, the keys follow each other
+    let mut lines = Vec::new();
+    for date in ["1992-01-01", "1995-07-01"] {
+        for part in 1..=2 {
+            let path = temp_dir
+                .path()
+                .join(format!("trip/date={date}/trip.{part}.tbl"));
+            let contents = fs::read_to_string(&path).expect("Failed to read window file");
+            lines.extend(contents.lines().map(str::to_string));
+        }
+    }
+
+    let start = parse_generated_date("1992-01-01").unwrap();
+    let end = parse_generated_date("1999-01-01").unwrap();
+    let expected: Vec<String> = TripGenerator::new(0.001, 1, 1)
+        .with_pickup_dates(start..end)
+        .iter()
+        .map(|trip| trip.to_string())
+        .collect();
+    assert_eq!(lines, expected);
+}
+
+#[test]
+fn test_point_distribution_recorded_in_trip_footer() {
+    let temp_dir = tempdir().expect("Failed to create temporary directory");
+    Command::cargo_bin("spatialbench-cli")
+        .expect("Binary not found")
+        .arg("--output-dir")
+        .arg(temp_dir.path())
+        .arg("--tables")
+        .arg("trip,customer")
+        .arg("--rows")
+        .arg("trip=1000,customer=10")
+        .arg("--point-distribution")
+        .arg("population-weighted")
+        .arg("--point-dispersion")
+        .arg("2")
+        .assert()
+        .success();
+
+    let footer_value = |table: &str| {
+        let file = File::open(temp_dir.path().join(format!("{table}.parquet"))).unwrap();
+        let metadata = ParquetMetaDataReader::new()
+            .parse_and_finish(&file)
+            .unwrap();
+        metadata
+            .file_metadata()
+            .key_value_metadata()
+            .into_iter()
+            .flatten()
+            .find(|kv| kv.key == "spatialbench.point_distribution")
+            .and_then(|kv| kv.value.clone())
+    };
+    assert_eq!(footer_value("trip").as_deref(), Some("population-weighted"));
+    assert_eq!(footer_value("customer"), None);
+
+    let config = temp_dir.path().join("spider.yml");
+    fs::write(
+        &config,
+        "trip:\n  dist_type: uniform\n  geom_type: point\n  dim: 2\n  seed: 1\n  \
+        width: 0.0\n  height: 0.0\n  maxseg: 0\n  polysize: 0.0\n  params:\n    type: none\n",
+    )
+    .unwrap();
+    Command::cargo_bin("spatialbench-cli")
+        .expect("Binary not found")
+        .arg("--output-dir")
+        .arg(temp_dir.path().join("configured"))
+        .arg("--tables")
+        .arg("trip")
+        .arg("--config")
+        .arg(&config)
+        .arg("--point-distribution")
+        .arg("uniform")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "--point-distribution can't be combined with the trip configuration",
+        ));
+}
+
+#[test]
+fn test_spatialbench_cli_time_range_outside_generated_dates() {
+    let temp_dir = tempdir().expect("Failed to create temporary directory");
+    Command::cargo_bin("spatialbench-cli")
+        .expect("Binary not found")
+        .arg("--output-dir")
+        .arg(temp_dir.path())
+        .arg("--tables")
+        .arg("trip")
+        .arg("--time-range")
+        .arg("2024-01-01..2024-01-08")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "2024-01-01..2024-01-08 contains no trip pickup dates, which range from 1992-01-01 to 1998-12-31",
+        ));
+}
+
+/// Test that --rows replaces the row count of the scale factor, keeping
+/// the rows a scale factor run starts with
+#[test]
+fn test_spatialbench_cli_rows() {
+    let temp_dir = tempdir().expect("Failed to create temporary directory");
+    Command::cargo_bin("spatialbench-cli")
+        .expect("Binary not found")
+        .arg("--scale-factor")
+        .arg("10")
+        .arg("--format")
+        .arg("tbl")
+        .arg("--output-dir")
+        .arg(temp_dir.path())
+        .arg("--tables")
+        .arg("vehicle,trip")
+        .arg("--rows")
+        .arg("vehicle=2k,trip=1_500")
+        .arg("--parts")
+        .arg("2")
+        .assert()
+        .success();
+
+    let vehicles = fs::read_to_string(temp_dir.path().join("vehicle/vehicle.1.tbl")).unwrap();
+    assert_eq!(vehicles.lines().count(), 2000);
+
+    let mut trips = Vec::new();
+    for part in 1..=2 {
+        let path = temp_dir.path().join(format!("trip/trip.{part}.tbl"));
+        let contents = fs::read_to_string(&path).expect("Failed to read trip file");
+        trips.extend(contents.lines().map(str::to_string));
+    }
+    let expected: Vec<String> = TripGenerator::new(10.0, 1, 1)
+        .iter()
+        .take(1500)
+        .map(|trip| trip.to_string())
+        .collect();
+    assert_eq!(trips, expected);
+}
+
+#[test]
+fn test_dataset_manifest_lists_generated_tables() {
+    let temp_dir = tempdir().expect("Failed to create temporary directory");
+    Command::cargo_bin("spatialbench-cli")
+        .expect("Binary not found")
+        .arg("--output-dir")
+        .arg(temp_dir.path())
+        .arg("--tables")
+        .arg("vehicle,customer,trip")
+        .arg("--rows")
+        .arg("vehicle=200,customer=300,trip=1_500")
+        .arg("--parts")
+        .arg("2")
+        .assert()
+        .success();
+
+    let dataset = DatasetManifest::read(temp_dir.path())
+        .unwrap()
+        .expect("dataset.json written");
+    assert_eq!(dataset.scale_factor, 1.0);
+    assert_eq!(dataset.input, None);
+    let tables: Vec<_> = dataset
+        .tables
+        .iter()
+        .map(|t| (t.name.as_str(), t.rows, t.files.len()))
+        .collect();
+    // Vehicles are always written as a single file
+    assert_eq!(
+        tables,
+        vec![("vehicle", 200, 1), ("customer", 300, 2), ("trip", 1500, 2)]
+    );
+    for table in &dataset.tables {
+        for file in &table.files {
+            let path = temp_dir.path().join(&file.path);
+            assert_eq!(fs::metadata(&path).unwrap().len(), file.bytes);
+            let reader =
+                ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap()).unwrap();
+            assert_eq!(
+                reader.metadata().file_metadata().num_rows() as u64,
+                file.rows
+            );
+        }
+    }
+    assert_eq!(dataset.tables[2].files[1].path, "trip/trip.2.parquet");
+
+    // Runs of a single part leave the dataset to a complete run
+    let single = temp_dir.path().join("single");
+    Command::cargo_bin("spatialbench-cli")
+        .expect("Binary not found")
+        .arg("--output-dir")
+        .arg(&single)
+        .arg("--tables")
+        .arg("trip")
+        .arg("--rows")
+        .arg("trip=100")
+        .arg("--parts")
+        .arg("2")
+        .arg("--part")
+        .arg("1")
+        .assert()
+        .success();
+    assert!(!single.join("dataset.json").exists());
+}
+
+#[test]
+fn test_spatialbench_cli_rows_invalid() {
+    for (rows, message) in [
+        ("trip", "expected TABLE=COUNT"),
+        ("trip=0", "invalid row count"),
+        ("trip=10x", "invalid row count"),
+        (
+            "trip=1,trip=2",
+            "--rows is given more than once for the trip table",
+        ),
+    ] {
+        let temp_dir = tempdir().expect("Failed to create temporary directory");
+        Command::cargo_bin("spatialbench-cli")
+            .expect("Binary not found")
+            .arg("--output-dir")
+            .arg(temp_dir.path())
+            .arg("--tables")
+            .arg("trip")
+            .arg("--rows")
+            .arg(rows)
+            .assert()
+            .failure()
+            .stderr(predicates::str::contains(message));
+    }
+}
+
+#[test]
+fn test_spatialbench_cli_parquet_compression_invalid() {
+    for (compression, message) in [
+        ("zstd(23)", "ZSTD level 23 exceeds max 22"),
+        ("gzip", "GZIP needs a level in 0..=9"),
+        (
+            "lzo",
+            "LZO is not available in this build; available codecs: ",
+        ),
+    ] {
+        let temp_dir = tempdir().expect("Failed to create temporary directory");
+        Command::cargo_bin("spatialbench-cli")
+            .expect("Binary not found")
+            .arg("--output-dir")
+            .arg(temp_dir.path())
+            .arg("--tables")
+            .arg("vehicle")
+            .arg("--parquet-compression")
+            .arg(compression)
+            .assert()
+            .failure()
+            .stderr(predicates::str::contains(message));
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 0);
+    }
+}
+
+/// Writes the zone manifest of an earlier run at scale factor 10
+/// Little-endian WKB of a rectangle polygon
+fn wkb_rectangle(xmin: f64, ymin: f64, xmax: f64, ymax: f64) -> Vec<u8> {
+    let ring = [
+        (xmin, ymin),
+        (xmax, ymin),
+        (xmax, ymax),
+        (xmin, ymax),
+        (xmin, ymin),
+    ];
+    let mut wkb = vec![1u8];
+    wkb.extend_from_slice(&3u32.to_le_bytes());
+    wkb.extend_from_slice(&1u32.to_le_bytes());
+    wkb.extend_from_slice(&(ring.len() as u32).to_le_bytes());
+    for (x, y) in ring {
+        wkb.extend_from_slice(&x.to_le_bytes());
+        wkb.extend_from_slice(&y.to_le_bytes());
+    }
+    wkb
+}
+
+#[test]
+fn test_materialize_zone_containment() {
+    use arrow_array::{Array, BinaryArray, Int64Array};
+    use arrow_schema::{DataType, Field, Schema};
+
+    let temp_dir = tempdir().expect("Failed to create temporary directory");
+    Command::cargo_bin("spatialbench-cli")
+        .expect("Binary not found")
+        .arg("--output-dir")
+        .arg(temp_dir.path())
+        .arg("--tables")
+        .arg("trip")
+        .arg("--rows")
+        .arg("trip=2_000")
+        .arg("--parts")
+        .arg("2")
+        .assert()
+        .success();
+
+    // The whole world, and the smaller western hemisphere that wins there
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("z_zonekey", DataType::Int64, false),
+        Field::new("z_boundary", DataType::Binary, false),
+    ]));
+    let world = wkb_rectangle(-180.0, -90.0, 180.0, 90.0);
+    let west = wkb_rectangle(-180.0, -90.0, 0.0, 90.0);
+    let zones = RecordBatch::try_new(
+        schema.clone(),
+        vec![
+            Arc::new(Int64Array::from(vec![1, 2])),
+            Arc::new(BinaryArray::from(vec![&world[..], &west[..]])),
+        ],
+    )
+    .unwrap();
+    let file = File::create(temp_dir.path().join("zone.parquet")).unwrap();
+    let mut writer = parquet::arrow::ArrowWriter::try_new(file, schema, None).unwrap();
+    writer.write(&zones).unwrap();
+    writer.close().unwrap();
+
+    Command::cargo_bin("spatialbench-cli")
+        .expect("Binary not found")
+        .arg("materialize")
+        .arg("zone-containment")
+        .arg("--data-dir")
+        .arg(temp_dir.path())
+        .arg("--jobs")
+        .arg("2")
+        .assert()
+        .success();
+
+    let read = |path: PathBuf| -> Vec<RecordBatch> {
+        ParquetRecordBatchReaderBuilder::try_new(File::open(path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap()
+            .map(Result::unwrap)
+            .collect()
+    };
+    let mut pickup_x = Vec::new();
+    for part in 1..=2 {
+        for batch in read(temp_dir.path().join(format!("trip/trip.{part}.parquet"))) {
+            let points = batch.column_by_name("t_pickuploc").unwrap();
+            let points = points.as_any().downcast_ref::<BinaryArray>().unwrap();
+            pickup_x.extend(
+                points
+                    .iter()
+                    .map(|wkb| f64::from_le_bytes(wkb.unwrap()[5..13].try_into().unwrap())),
+            );
+        }
+    }
+    let mut zonekeys = Vec::new();
+    for batch in read(temp_dir.path().join("trip_zone.parquet")) {
+        let keys = batch.column_by_name("pickup_zonekey").unwrap();
+        let keys = keys.as_any().downcast_ref::<Int64Array>().unwrap();
+        zonekeys.extend(keys.iter());
+    }
+
+    assert_eq!(zonekeys.len(), 2000);
+    let expected: Vec<_> = pickup_x
+        .iter()
+        .map(|&x| Some(if x <= 0.0 { 2 } else { 1 }))
+        .collect();
+    assert_eq!(zonekeys, expected);
+}
+
+#[test]
+fn test_verify_refuses_mixed_scale_factor_names() {
+    use arrow_array::Int64Array;
+    use arrow_schema::{DataType, Field, Schema};
+
+    let temp_dir = tempdir().expect("Failed to create temporary directory");
+    let schema = Arc::new(Schema::new(vec![Field::new(
+        "z_zonekey",
+        DataType::Int64,
+        false,
+    )]));
+    fs::create_dir_all(temp_dir.path().join("zone")).unwrap();
+    for name in ["zone.sf1.1.parquet", "zone.sf10.2.parquet"] {
+        let batch = RecordBatch::try_new(schema.clone(), vec![Arc::new(Int64Array::from(vec![1]))])
+            .unwrap();
+        let file = File::create(temp_dir.path().join("zone").join(name)).unwrap();
+        let mut writer = parquet::arrow::ArrowWriter::try_new(file, schema.clone(), None).unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+    }
+
+    Command::cargo_bin("spatialbench-cli")
+        .expect("Binary not found")
+        .arg("verify")
+        .arg("--data-dir")
+        .arg(temp_dir.path())
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "mixes zone files of different scale factors: sf1 (zone/zone.sf1.1.parquet); \
+             sf10 (zone/zone.sf10.2.parquet)",
+        ));
+
+    Command::cargo_bin("spatialbench-cli")
+        .expect("Binary not found")
+        .arg("verify")
+        .arg("--data-dir")
+        .arg(temp_dir.path())
+        .arg("--force")
+        .assert()
+        .success();
+}
+
+fn write_earlier_zone_manifest(output_dir: &Path) {
+    fs::create_dir_all(output_dir.join("zone")).unwrap();
+    fs::write(output_dir.join("zone").join("zone.parquet"), b"").unwrap();
+    fs::write(
+        output_dir.join("zone.manifest.json"),
+        r#"{"table": "zone", "scale_factor": 10.0, "parts": 1, "files": [
+            {"part": 1, "path": "zone/zone.parquet", "rows": 1, "content_sha256": ""}
+        ]}"#,
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_spatialbench_cli_refuses_mixed_outputs() {
+    let temp_dir = tempdir().expect("Failed to create temporary directory");
+    write_earlier_zone_manifest(temp_dir.path());
+
+    Command::cargo_bin("spatialbench-cli")
+        .expect("Binary not found")
+        .arg("--output-dir")
+        .arg(temp_dir.path())
+        .arg("--tables")
+        .arg("vehicle")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "scale factor: 10 in the existing outputs, 1 requested",
+        ));
+    assert!(!temp_dir.path().join("vehicle.parquet").exists());
+
+    Command::cargo_bin("spatialbench-cli")
+        .expect("Binary not found")
+        .arg("--output-dir")
+        .arg(temp_dir.path())
+        .arg("--tables")
+        .arg("vehicle")
+        .arg("--allow-mixed-outputs")
+        .assert()
+        .success();
+    assert!(temp_dir.path().join("vehicle.parquet").exists());
+    assert!(temp_dir.path().join("zone").join("zone.parquet").exists());
+
+    Command::cargo_bin("spatialbench-cli")
+        .expect("Binary not found")
+        .arg("--output-dir")
+        .arg(temp_dir.path())
+        .arg("--tables")
+        .arg("vehicle")
+        .arg("--overwrite")
+        .assert()
+        .success();
+    assert!(!temp_dir.path().join("zone").join("zone.parquet").exists());
+    assert!(!temp_dir.path().join("zone.manifest.json").exists());
+}
+
+#[test]
+fn test_spatialbench_cli_refuses_uncreatable_output_dir() {
+    let temp_dir = tempdir().expect("Failed to create temporary directory");
+    let file = temp_dir.path().join("file");
+    fs::write(&file, b"").unwrap();
+
+    Command::cargo_bin("spatialbench-cli")
+        .expect("Binary not found")
+        .arg("--output-dir")
+        .arg(file.join("out"))
+        .arg("--tables")
+        .arg("vehicle")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("Can't create output directory"));
+}
+
+#[test]
+fn test_output_dir_lock() {
+    let temp_dir = tempdir().expect("Failed to create temporary directory");
+    let output_dir = temp_dir.path().to_str().unwrap();
+    let args = [
+        "--output-dir",
+        output_dir,
+        "--tables",
+        "vehicle",
+        "--format",
+        "tbl",
+        "--parts",
+        "2",
+    ];
+    let run = |extra: &[&str]| {
+        Command::cargo_bin("spatialbench-cli")
+            .expect("Binary not found")
+            .args(args)
+            .args(extra)
+            .assert()
+    };
+
+    // Another process running a different configuration
+    let other = LockHolder::current("other", LockMode::Exclusive);
+    let lock = OutputLock::acquire(temp_dir.path(), &other, false).unwrap();
+    run(&["--part", "1"])
+        .failure()
+        .stderr(predicates::str::contains(format!(
+            "is locked by pid {} on {} with a different configuration",
+            other.pid, other.host
+        )));
+    assert!(!temp_dir.path().join("vehicle").exists());
+    run(&["--part", "1", "--force-unlock"]).success();
+    drop(lock);
+
+    // A worker of the same run shares the lock, and the last holder
+    // removes the lock file
+    let worker = LockHolder::current(config_hash(args), LockMode::Shared);
+    let lock = OutputLock::acquire(temp_dir.path(), &worker, false).unwrap();
+    run(&["--part", "2"]).success();
+    run(&[]).failure().stderr(predicates::str::contains(
+        "only --part workers can share an output directory",
+    ));
+    assert!(lock.path().exists());
+    drop(lock);
+    assert!(!temp_dir.path().join(LOCK_FILE_NAME).exists());
+    run(&[]).success();
+    assert!(!temp_dir.path().join(LOCK_FILE_NAME).exists());
+}
+
 #[test]
 fn test_spatialbench_cli_zero_part() {
     let temp_dir = tempdir().expect("Failed to create temporary directory");
@@ -750,163 +1304,765 @@ async fn test_incompatible_options_warnings() {
     let output_dir = tempdir().unwrap();
     Command::cargo_bin("spatialbench-cli")
         .expect("Binary not found")
-        .arg("--format")
-        .arg("csv")
-        .arg("--tables")
-        .arg("trip")
-        .arg("--scale-factor")
-        .arg("0.0001")
-        .arg("--output-dir")
-        .arg(output_dir.path())
-        // pass in parquet options that are incompatible with csv
-        .arg("--parquet-compression")
-        .arg("zstd(1)")
-        .arg("--parquet-row-group-bytes")
-        .arg("8192")
+        .arg("--format")
+        .arg("csv")
+        .arg("--tables")
+        .arg("trip")
+        .arg("--scale-factor")
+        .arg("0.0001")
+        .arg("--output-dir")
+        .arg(output_dir.path())
+        // pass in parquet options that are incompatible with csv
+        .arg("--parquet-compression")
+        .arg("zstd(1)")
+        .arg("--parquet-row-group-bytes")
+        .arg("65536")
+        .assert()
+        // still success, but should see warnings
+        .success()
+        .stderr(predicates::str::contains(
+            "Warning: Parquet compression option set but not generating Parquet files",
+        ))
+        .stderr(predicates::str::contains(
+            "Warning: Parquet row group size option set but not generating Parquet files",
+        ));
+}
+
+/// Zone TBL rows stream into the part files, so options needing the whole
+/// table are rejected before the source is read
+#[test]
+fn test_zone_tbl_rejects_whole_table_options() {
+    let temp_dir = tempdir().expect("Failed to create temporary directory");
+
+    Command::cargo_bin("spatialbench-cli")
+        .expect("Binary not found")
+        .arg("--format")
+        .arg("tbl")
+        .arg("--scale-factor")
+        .arg("1")
+        .arg("--tables")
+        .arg("zone")
+        .arg("--also-merge")
+        .arg("--output-dir")
+        .arg(temp_dir.path())
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "--format tbl streams the zone rows, so it can't be combined with --also-merge",
+        ));
+    assert!(!temp_dir.path().join("zone").exists());
+}
+
+#[test]
+fn test_orc_format_rejects_tables_other_than_zone() {
+    let temp_dir = tempdir().expect("Failed to create temporary directory");
+
+    Command::cargo_bin("spatialbench-cli")
+        .expect("Binary not found")
+        .arg("--format")
+        .arg("orc")
+        .arg("--tables")
+        .arg("zone,driver")
+        .arg("--output-dir")
+        .arg(temp_dir.path())
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "--format orc is only supported for the zone table, not driver",
+        ));
+    assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 0);
+}
+
+#[test]
+fn test_avro_format_writes_parts_and_schema() {
+    let temp_dir = tempdir().expect("Failed to create temporary directory");
+
+    Command::cargo_bin("spatialbench-cli")
+        .expect("Binary not found")
+        .arg("--format")
+        .arg("avro")
+        .arg("--avro-codec")
+        .arg("snappy")
+        .arg("--emit-avro-schema")
+        .arg("--scale-factor")
+        .arg("0.001")
+        .arg("--tables")
+        .arg("trip")
+        .arg("--parts")
+        .arg("2")
+        .arg("--output-dir")
+        .arg(temp_dir.path())
+        .assert()
+        .success();
+
+    for part in 1..=2 {
+        let bytes = fs::read(temp_dir.path().join(format!("trip/trip.{part}.avro"))).unwrap();
+        assert_eq!(&bytes[..4], b"Obj\x01");
+        let header = String::from_utf8_lossy(&bytes[..1024]);
+        // the codec name follows its length, 6 zig-zag encoded
+        assert!(header.contains("avro.codec\u{c}snappy"), "{header}");
+    }
+
+    let avsc: serde_json::Value =
+        serde_json::from_slice(&fs::read(temp_dir.path().join("trip.avsc")).unwrap()).unwrap();
+    assert_eq!(avsc["name"], "trip");
+    assert_eq!(avsc["namespace"], "spatialbench");
+    let field = |name: &str| {
+        avsc["fields"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|field| field["name"] == name)
+            .unwrap()["type"]
+            .clone()
+    };
+    assert_eq!(field("t_tripkey"), "long");
+    assert_eq!(field("t_pickuploc"), "bytes");
+    assert_eq!(field("t_fare")["logicalType"], "decimal");
+    assert_eq!(
+        field("t_pickuptime")["logicalType"],
+        "local-timestamp-millis"
+    );
+}
+
+#[test]
+fn test_emit_avro_schema_requires_avro_format() {
+    let temp_dir = tempdir().expect("Failed to create temporary directory");
+
+    Command::cargo_bin("spatialbench-cli")
+        .expect("Binary not found")
+        .arg("--emit-avro-schema")
+        .arg("--tables")
+        .arg("driver")
+        .arg("--output-dir")
+        .arg(temp_dir.path())
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "--emit-avro-schema requires --format avro",
+        ));
+}
+
+#[tokio::test]
+async fn test_trip_output_file_size() {
+    let temp_dir = tempdir().expect("Failed to create temporary directory");
+
+    // Generate Trip table with max file size of 2MB
+    Command::cargo_bin("spatialbench-cli")
+        .expect("Binary not found")
+        .arg("--format")
+        .arg("parquet")
+        .arg("--scale-factor")
+        .arg("0.01")
+        .arg("--tables")
+        .arg("trip")
+        .arg("--output-dir")
+        .arg(temp_dir.path())
+        .arg("--mb-per-file")
+        .arg("2")
+        .assert()
+        .success();
+
+    let trip_dir = temp_dir.path().join("trip");
+    let output_file_size_mb = 2 * 1024 * 1024; // 2MB in bytes
+
+    // Verify all files are under the max size
+    for entry in fs::read_dir(&trip_dir).expect("Failed to read trip directory") {
+        let entry = entry.expect("Failed to read directory entry");
+        let metadata = entry.metadata().expect("Failed to get file metadata");
+        let file_size = metadata.len();
+
+        assert!(
+            file_size <= output_file_size_mb,
+            "File {} size ({} bytes) exceeds max size ({} bytes)",
+            entry.file_name().to_string_lossy(),
+            file_size,
+            output_file_size_mb
+        );
+    }
+
+    // Verify multiple files were created
+    let file_count = fs::read_dir(&trip_dir)
+        .expect("Failed to read trip directory")
+        .count();
+    assert!(file_count > 1, "Expected multiple files with 2MB limit");
+}
+
+#[tokio::test]
+async fn test_customer_output_file_size() {
+    let temp_dir = tempdir().expect("Failed to create temporary directory");
+
+    // Generate Customer table with approx file size of 1MB
+    Command::cargo_bin("spatialbench-cli")
+        .expect("Binary not found")
+        .arg("--format")
+        .arg("parquet")
+        .arg("--scale-factor")
+        .arg("1")
+        .arg("--tables")
+        .arg("customer")
+        .arg("--output-dir")
+        .arg(temp_dir.path())
+        .arg("--mb-per-file")
+        .arg("1")
+        .assert()
+        .success();
+
+    let customer_dir = temp_dir.path().join("customer");
+    let output_file_size_mb = 1 * 1024 * 1024; // 1MB in bytes
+
+    // Verify all files are under the max size
+    for entry in fs::read_dir(&customer_dir).expect("Failed to read customer directory") {
+        let entry = entry.expect("Failed to read directory entry");
+        let metadata = entry.metadata().expect("Failed to get file metadata");
+        let file_size = metadata.len();
+
+        assert!(
+            file_size <= output_file_size_mb,
+            "File {} size ({} bytes) exceeds max size ({} bytes)",
+            entry.file_name().to_string_lossy(),
+            file_size,
+            output_file_size_mb
+        );
+    }
+
+    // Verify multiple files were created
+    let file_count = fs::read_dir(&customer_dir)
+        .expect("Failed to read trip directory")
+        .count();
+    assert!(file_count > 1, "Expected multiple files with 1MB limit");
+}
+
+#[tokio::test]
+async fn test_zone_file_size_conflicts_with_parts() {
+    let temp_dir = tempdir().expect("Failed to create temporary directory");
+
+    // Should fail when both --mb-per-file and --parts are specified
+    Command::cargo_bin("spatialbench-cli")
+        .expect("Binary not found")
+        .arg("--format")
+        .arg("parquet")
+        .arg("--scale-factor")
+        .arg("1")
+        .arg("--tables")
+        .arg("zone")
+        .arg("--output-dir")
+        .arg(temp_dir.path())
+        .arg("--mb-per-file")
+        .arg("50")
+        .arg("--parts")
+        .arg("10")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("cannot be used with"));
+}
+
+#[test]
+fn test_require_schema_version_refuses_custom_schema() {
+    let temp_dir = tempdir().expect("Failed to create temporary directory");
+
+    // Fails before reading the source, so no zone data is downloaded
+    Command::cargo_bin("spatialbench-cli")
+        .expect("Binary not found")
+        .arg("--format")
+        .arg("parquet")
+        .arg("--tables")
+        .arg("zone")
+        .arg("--output-dir")
+        .arg(temp_dir.path())
+        .arg("--with-bbox-covering")
+        .arg("--require-schema-version")
+        .arg("1")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "--with-bbox-covering change the zone schema to a custom one",
+        ));
+    assert!(!temp_dir.path().join("zone").exists());
+}
+
+#[test]
+fn test_zone_input_url_rejects_unsupported_scheme() {
+    let temp_dir = tempdir().expect("Failed to create temporary directory");
+    Command::cargo_bin("spatialbench-cli")
+        .expect("Binary not found")
+        .arg("--tables")
+        .arg("zone")
+        .arg("--output-dir")
+        .arg(temp_dir.path())
+        .arg("--input-url")
+        .arg("gs://bucket/division_area/")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "expected an http://, https:// or s3:// URL",
+        ));
+}
+
+/// Writes a division_area source of `rows` county points with ids `g1`,
+/// `g2`, ...
+async fn write_zone_source(path: &Path, rows: usize) {
+    datafusion::prelude::SessionContext::new()
+        .sql(&format!(
+            "COPY (SELECT 'g' || CAST(value AS VARCHAR) AS id, 'US' AS country, \
+             'US-WA' AS region, named_struct('primary', 'Zone ' || CAST(value AS VARCHAR)) \
+             AS names, 'county' AS subtype, \
+             X'0101000000000000000000F03F000000000000F03F' AS geometry, true AS is_land \
+             FROM generate_series(1, {rows})) TO '{}' STORED AS PARQUET",
+            path.display()
+        ))
+        .await
+        .unwrap()
+        .collect()
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_count_from_metadata_matches_scan() {
+    let temp_dir = tempdir().expect("Failed to create temporary directory");
+    let source = temp_dir.path().join("division_area.parquet");
+    write_zone_source(&source, 8).await;
+    let count = |from_metadata: bool| -> serde_json::Value {
+        let mut command = Command::cargo_bin("spatialbench-cli").expect("Binary not found");
+        command
+            .arg("count")
+            .arg("--input-theme")
+            .arg(format!("division_area={}", source.display()));
+        if from_metadata {
+            command.arg("--from-metadata");
+        }
+        let output = command.assert().success().get_output().stdout.clone();
+        serde_json::from_slice(&output).unwrap()
+    };
+
+    let scanned = count(false);
+    let metadata = count(true);
+    assert_eq!(scanned["total_rows"], 8);
+    assert_eq!(metadata["total_rows"], scanned["total_rows"]);
+    assert_eq!(metadata["method"], "metadata");
+    assert!(scanned.get("method").is_none());
+}
+
+#[test]
+fn test_negative_scale_factor_is_rejected() {
+    let temp_dir = tempdir().expect("Failed to create temporary directory");
+    Command::cargo_bin("spatialbench-cli")
+        .expect("Binary not found")
+        .arg("--scale-factor=-1")
+        .arg("--tables")
+        .arg("zone")
+        .arg("--output-dir")
+        .arg(temp_dir.path())
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "Invalid --scale-factor -1, expected a finite number above 0",
+        ));
+}
+
+#[test]
+fn test_scale_factor_above_the_ceiling_needs_yes_i_know() {
+    let temp_dir = tempdir().expect("Failed to create temporary directory");
+    Command::cargo_bin("spatialbench-cli")
+        .expect("Binary not found")
+        .arg("--scale-factor=1e12")
+        .arg("--tables")
+        .arg("zone")
+        .arg("--output-dir")
+        .arg(temp_dir.path())
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("pass --yes-i-know"));
+
+    for args in [["--parts=0"], ["--parquet-row-group-bytes=1024"]] {
+        Command::cargo_bin("spatialbench-cli")
+            .expect("Binary not found")
+            .args(args)
+            .arg("--output-dir")
+            .arg(temp_dir.path())
+            .assert()
+            .failure()
+            .stderr(predicates::str::contains("expected at least"));
+    }
+    assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 0);
+}
+
+#[test]
+fn test_negative_bbox_inflate_is_rejected() {
+    let temp_dir = tempdir().expect("Failed to create temporary directory");
+    Command::cargo_bin("spatialbench-cli")
+        .expect("Binary not found")
+        .arg("--tables")
+        .arg("zone")
+        .arg("--with-bbox-covering")
+        .arg("--bbox-inflate=-0.5")
+        .arg("--output-dir")
+        .arg(temp_dir.path())
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "Invalid --bbox-inflate=-0.5, expected a non-negative distance",
+        ));
+}
+
+#[tokio::test]
+async fn test_explain_reports_scanned_columns() {
+    let temp_dir = tempdir().expect("Failed to create temporary directory");
+    let source = temp_dir.path().join("division_area.parquet");
+    write_zone_source(&source, 4).await;
+    Command::cargo_bin("spatialbench-cli")
+        .expect("Binary not found")
+        .arg("--tables")
+        .arg("zone")
+        .arg("--explain")
+        .arg("--output-dir")
+        .arg(temp_dir.path())
+        .arg("--input-theme")
+        .arg(format!("division_area={}", source.display()))
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(
+            "Scanned columns: country, geometry, id, is_land, names, region, subtype",
+        ))
+        .stdout(predicates::str::contains("saved by the projection"));
+}
+
+#[tokio::test]
+async fn test_extend_matches_fresh_parts() {
+    let temp_dir = tempdir().expect("Failed to create temporary directory");
+    let source = temp_dir.path().join("division_area.parquet");
+    write_zone_source(&source, 9).await;
+    let generate = |output_dir: &Path, parts: &str| {
+        Command::cargo_bin("spatialbench-cli")
+            .expect("Binary not found")
+            .arg("--tables")
+            .arg("zone")
+            .arg("--parts")
+            .arg(parts)
+            .arg("--output-dir")
+            .arg(output_dir)
+            .arg("--input-theme")
+            .arg(format!("division_area={}", source.display()))
+            .assert()
+            .success();
+    };
+    let extended = temp_dir.path().join("extended");
+    let fresh = temp_dir.path().join("fresh");
+    generate(&extended, "2");
+    generate(&fresh, "4");
+
+    Command::cargo_bin("spatialbench-cli")
+        .expect("Binary not found")
+        .arg("extend")
+        .arg("--data-dir")
+        .arg(&extended)
+        .arg("--new-parts")
+        .arg("4")
         .assert()
-        // still success, but should see warnings
         .success()
-        .stderr(predicates::str::contains(
-            "Warning: Parquet compression option set but not generating Parquet files",
-        ))
-        .stderr(predicates::str::contains(
-            "Warning: Parquet row group size option set but not generating Parquet files",
+        .stdout(predicates::str::contains(
+            "cut 4 of 4 part(s) from the existing files",
         ));
+    Command::cargo_bin("spatialbench-cli")
+        .expect("Binary not found")
+        .arg("verify")
+        .arg("--compare")
+        .arg(&extended)
+        .arg(&fresh)
+        .assert()
+        .success();
 }
 
-#[test]
-fn test_zone_generation_tbl_fails() {
+#[tokio::test]
+async fn test_verify_checks_the_attribution_notice() {
     let temp_dir = tempdir().expect("Failed to create temporary directory");
-
+    let source = temp_dir.path().join("division_area.parquet");
+    write_zone_source(&source, 3).await;
+    let output = temp_dir.path().join("out");
     Command::cargo_bin("spatialbench-cli")
         .expect("Binary not found")
-        .arg("--format")
-        .arg("tbl")
-        .arg("--scale-factor")
-        .arg("1")
         .arg("--tables")
         .arg("zone")
+        .arg("--include-attribution")
+        .arg("--attribution-column")
         .arg("--output-dir")
-        .arg(temp_dir.path())
+        .arg(&output)
+        .arg("--input-theme")
+        .arg(format!("division_area={}", source.display()))
+        .assert()
+        .success();
+    let dataset = DatasetManifest::read(&output).unwrap().unwrap();
+    assert!(dataset.attribution_sha256.is_some());
+
+    let verify = || {
+        let mut command = Command::cargo_bin("spatialbench-cli").expect("Binary not found");
+        command.arg("verify").arg("--data-dir").arg(&output);
+        command
+    };
+    verify().assert().success();
+    fs::write(output.join("ATTRIBUTION.txt"), "stripped").unwrap();
+    verify()
         .assert()
         .failure()
-        .stderr(predicates::str::contains(
-            "Zone table is only supported in --format=parquet",
+        .stdout(predicates::str::contains(
+            "ATTRIBUTION ATTRIBUTION.txt doesn't match its recorded hash",
         ));
 }
 
+/// The `spatialbench.generation_params` JSON in the footer of `path`
+fn generation_params(path: &Path) -> Option<GenerationParams> {
+    let file = File::open(path).expect("Failed to open Parquet file");
+    let metadata = ParquetMetaDataReader::new()
+        .parse_and_finish(&file)
+        .expect("Failed to read Parquet footer");
+    metadata
+        .file_metadata()
+        .key_value_metadata()?
+        .iter()
+        .find(|kv| kv.key == GENERATION_PARAMS_KEY)
+        .map(|kv| GenerationParams::from_json(kv.value.as_deref().unwrap()).unwrap())
+}
+
 #[tokio::test]
-async fn test_trip_output_file_size() {
+async fn test_generation_params_in_every_part() {
     let temp_dir = tempdir().expect("Failed to create temporary directory");
+    let source = temp_dir.path().join("division_area.parquet");
+    write_zone_source(&source, 6).await;
+    let generate = |output_dir: &Path, embed_params: &str| {
+        Command::cargo_bin("spatialbench-cli")
+            .expect("Binary not found")
+            .arg("--scale-factor")
+            .arg("2")
+            .arg("--tables")
+            .arg("customer,zone")
+            .arg("--parts")
+            .arg("2")
+            .arg("--embed-params")
+            .arg(embed_params)
+            .arg("--output-dir")
+            .arg(output_dir)
+            .arg("--input-theme")
+            .arg(format!("division_area={}", source.display()))
+            .assert()
+            .success();
+    };
+    let embedded = temp_dir.path().join("embedded");
+    generate(&embedded, "true");
+    for table in ["customer", "zone"] {
+        for part in 1..=2 {
+            let path = embedded.join(format!("{table}/{table}.{part}.parquet"));
+            let params = generation_params(&path).expect("no generation parameters");
+            assert_eq!(params.scale_factor, 2.0, "{}", path.display());
+            assert_eq!(params.spatialbench_version, env!("CARGO_PKG_VERSION"));
+            assert!(params.args.contains(&"--parts".to_string()));
+            assert!(params.generated_at.is_some());
+            assert_eq!(
+                params.input.is_some(),
+                table == "zone",
+                "{}",
+                path.display()
+            );
+        }
+    }
 
-    // Generate Trip table with max file size of 2MB
+    let bare = temp_dir.path().join("bare");
+    generate(&bare, "false");
+    assert_eq!(generation_params(&bare.join("zone/zone.1.parquet")), None);
+    assert_eq!(
+        generation_params(&bare.join("customer/customer.1.parquet")),
+        None
+    );
+}
+
+#[tokio::test]
+async fn test_fractional_zone_scale_factor() {
+    let temp_dir = tempdir().expect("Failed to create temporary directory");
+    let source = temp_dir.path().join("division_area.parquet");
+    write_zone_source(&source, 10).await;
+    let generate = |output_dir: &Path, allow_fractional: bool| {
+        let mut command = Command::cargo_bin("spatialbench-cli").expect("Binary not found");
+        command
+            .arg("--scale-factor")
+            .arg("0.3")
+            .arg("--tables")
+            .arg("zone")
+            .arg("--output-dir")
+            .arg(output_dir)
+            .arg("--input-theme")
+            .arg(format!("division_area={}", source.display()));
+        if allow_fractional {
+            command.arg("--allow-fractional-scale");
+        }
+        command.assert().success()
+    };
+    let rows = |output_dir: &Path| {
+        let file = File::open(output_dir.join("zone.parquet")).unwrap();
+        let reader = ParquetRecordBatchReaderBuilder::try_new(file).unwrap();
+        reader.metadata().file_metadata().num_rows()
+    };
+
+    // Raised to scale factor 1 with a warning
+    let floored = temp_dir.path().join("floored");
+    generate(&floored, false).stderr(predicates::str::contains(
+        "generating them at scale factor 1 for --scale-factor 0.3",
+    ));
+    assert_eq!(rows(&floored), 10);
+
+    let sampled = temp_dir.path().join("sampled");
+    generate(&sampled, true);
+    assert_eq!(rows(&sampled), 3);
+    let manifest: serde_json::Value =
+        serde_json::from_slice(&fs::read(sampled.join("zone.manifest.json")).unwrap()).unwrap();
+    assert_eq!(manifest["scale_factor"], 0.3);
+}
+
+#[test]
+fn test_otlp_endpoint_rejects_grpc_port() {
+    let temp_dir = tempdir().expect("Failed to create temporary directory");
+    let output = temp_dir.path().join("out");
     Command::cargo_bin("spatialbench-cli")
         .expect("Binary not found")
-        .arg("--format")
-        .arg("parquet")
-        .arg("--scale-factor")
-        .arg("0.01")
         .arg("--tables")
-        .arg("trip")
+        .arg("vehicle")
         .arg("--output-dir")
-        .arg(temp_dir.path())
-        .arg("--mb-per-file")
-        .arg("2")
+        .arg(&output)
+        .arg("--otlp-endpoint")
+        .arg("http://collector:4317")
         .assert()
-        .success();
-
-    let trip_dir = temp_dir.path().join("trip");
-    let output_file_size_mb = 2 * 1024 * 1024; // 2MB in bytes
-
-    // Verify all files are under the max size
-    for entry in fs::read_dir(&trip_dir).expect("Failed to read trip directory") {
-        let entry = entry.expect("Failed to read directory entry");
-        let metadata = entry.metadata().expect("Failed to get file metadata");
-        let file_size = metadata.len();
-
-        assert!(
-            file_size <= output_file_size_mb,
-            "File {} size ({} bytes) exceeds max size ({} bytes)",
-            entry.file_name().to_string_lossy(),
-            file_size,
-            output_file_size_mb
-        );
-    }
-
-    // Verify multiple files were created
-    let file_count = fs::read_dir(&trip_dir)
-        .expect("Failed to read trip directory")
-        .count();
-    assert!(file_count > 1, "Expected multiple files with 2MB limit");
+        .failure()
+        .stderr(predicates::str::contains("port 4317 is OTLP over gRPC"));
+    assert!(!output.exists());
 }
 
 #[tokio::test]
-async fn test_customer_output_file_size() {
+async fn test_otlp_endpoint_exports_part_write_spans() {
+    use std::io::Write;
+    use std::net::TcpListener;
+
     let temp_dir = tempdir().expect("Failed to create temporary directory");
+    let source = temp_dir.path().join("division_area.parquet");
+    write_zone_source(&source, 8).await;
+
+    // A collector accepting one export request
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    let collector = std::thread::spawn(move || {
+        let (mut socket, _) = listener.accept().unwrap();
+        let mut request = Vec::new();
+        let mut buffer = [0; 65536];
+        loop {
+            let read = socket.read(&mut buffer).unwrap();
+            request.extend_from_slice(&buffer[..read]);
+            let text = String::from_utf8_lossy(&request);
+            if let Some((headers, body)) = text.split_once("\r\n\r\n") {
+                let length = headers
+                    .lines()
+                    .find_map(|line| {
+                        let line = line.to_lowercase();
+                        line.strip_prefix("content-length: ")
+                            .map(|length| length.parse::<usize>().unwrap())
+                    })
+                    .unwrap_or(0);
+                if body.len() >= length {
+                    break;
+                }
+            }
+        }
+        socket
+            .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+            .unwrap();
+        String::from_utf8(request).unwrap()
+    });
 
-    // Generate Customer table with approx file size of 1MB
     Command::cargo_bin("spatialbench-cli")
         .expect("Binary not found")
-        .arg("--format")
-        .arg("parquet")
-        .arg("--scale-factor")
-        .arg("1")
         .arg("--tables")
-        .arg("customer")
+        .arg("zone")
         .arg("--output-dir")
         .arg(temp_dir.path())
-        .arg("--mb-per-file")
-        .arg("1")
+        .arg("--parts")
+        .arg("4")
+        .arg("--input-theme")
+        .arg(format!("division_area={}", source.display()))
+        .arg("--otlp-endpoint")
+        .arg(&endpoint)
         .assert()
         .success();
 
-    let customer_dir = temp_dir.path().join("customer");
-    let output_file_size_mb = 1 * 1024 * 1024; // 1MB in bytes
-
-    // Verify all files are under the max size
-    for entry in fs::read_dir(&customer_dir).expect("Failed to read customer directory") {
-        let entry = entry.expect("Failed to read directory entry");
-        let metadata = entry.metadata().expect("Failed to get file metadata");
-        let file_size = metadata.len();
+    let request = collector.join().unwrap();
+    assert!(request.starts_with("POST /v1/traces "), "{request}");
+    let (_, body) = request.split_once("\r\n\r\n").unwrap();
+    let body: serde_json::Value = serde_json::from_str(body).unwrap();
+    let spans = body["resourceSpans"][0]["scopeSpans"][0]["spans"]
+        .as_array()
+        .unwrap();
+    let attribute = |span: &serde_json::Value, key: &str| -> u64 {
+        let attributes = span["attributes"].as_array().unwrap();
+        let attribute = attributes.iter().find(|a| a["key"] == key).unwrap();
+        attribute["value"]["intValue"]
+            .as_str()
+            .unwrap()
+            .parse()
+            .unwrap()
+    };
+    let nanos = |span: &serde_json::Value, key: &str| -> u128 {
+        span[key].as_str().unwrap().parse().unwrap()
+    };
 
-        assert!(
-            file_size <= output_file_size_mb,
-            "File {} size ({} bytes) exceeds max size ({} bytes)",
-            entry.file_name().to_string_lossy(),
-            file_size,
-            output_file_size_mb
-        );
+    let run = spans.iter().find(|s| s["name"] == "spatialbench").unwrap();
+    let mut writes: Vec<_> = spans.iter().filter(|s| s["name"] == "zone.write").collect();
+    writes.sort_by_key(|span| attribute(span, "part"));
+    assert_eq!(writes.len(), 4);
+    for (part, write) in (1..=4).zip(&writes) {
+        assert_eq!(attribute(write, "part"), part);
+        assert_eq!(attribute(write, "parts"), 4);
+        assert_eq!(attribute(write, "rows"), 2);
+        let bytes = fs::metadata(temp_dir.path().join(format!("zone/zone.{part}.parquet")))
+            .unwrap()
+            .len();
+        assert_eq!(attribute(write, "bytes"), bytes);
+        assert_eq!(write["traceId"], run["traceId"]);
+        assert!(nanos(write, "startTimeUnixNano") <= nanos(write, "endTimeUnixNano"));
+        assert!(nanos(run, "startTimeUnixNano") <= nanos(write, "startTimeUnixNano"));
+        assert!(nanos(write, "endTimeUnixNano") <= nanos(run, "endTimeUnixNano"));
+    }
+    for stage in [
+        "zone.scan",
+        "zone.collect",
+        "zone.transform",
+        "zone.partition",
+    ] {
+        assert!(spans.iter().any(|s| s["name"] == stage), "no {stage} span");
     }
-
-    // Verify multiple files were created
-    let file_count = fs::read_dir(&customer_dir)
-        .expect("Failed to read trip directory")
-        .count();
-    assert!(file_count > 1, "Expected multiple files with 1MB limit");
 }
 
-#[tokio::test]
-async fn test_zone_file_size_conflicts_with_parts() {
+/// Generates zones straight from one remote part of the built-in source.
+/// Run with `cargo test --features remote-tests`.
+#[cfg(feature = "remote-tests")]
+#[test]
+fn test_zone_input_url_reads_remote_parquet() {
     let temp_dir = tempdir().expect("Failed to create temporary directory");
+    let url = "https://huggingface.co/datasets/apache-sedona/spatialbench/resolve/\
+               67822daa2fbc0039681922f0d7fea4157f41d13f/omf-division-area-2025-08-20.1/\
+               part-00000-c998b093-fa14-440c-98f0-bbdb2126ed22-c000.zstd.parquet";
 
-    // Should fail when both --mb-per-file and --parts are specified
     Command::cargo_bin("spatialbench-cli")
         .expect("Binary not found")
-        .arg("--format")
-        .arg("parquet")
-        .arg("--scale-factor")
-        .arg("1")
         .arg("--tables")
         .arg("zone")
         .arg("--output-dir")
         .arg(temp_dir.path())
-        .arg("--mb-per-file")
-        .arg("50")
-        .arg("--parts")
+        .arg("--input-url")
+        .arg(url)
+        .arg("--limit")
         .arg("10")
         .assert()
-        .failure()
-        .stderr(predicates::str::contains("cannot be used with"));
+        .success();
+
+    let file = File::open(temp_dir.path().join("zone.parquet")).expect("zone.parquet written");
+    let reader = ParquetRecordBatchReaderBuilder::try_new(file).unwrap();
+    assert_eq!(reader.metadata().file_metadata().num_rows(), 10);
 }
 
 fn read_gzipped_file_to_string<P: AsRef<Path>>(path: P) -> Result<String, std::io::Error> {
@@ -969,3 +2125,218 @@ fn expect_row_group_sizes(output_dir: &Path, expected_row_groups: Vec<RowGroups>
     let actual_row_groups = format!("{actual_row_groups:#?}");
     assert_eq!(actual_row_groups, expected_row_groups);
 }
+
+#[tokio::test]
+async fn test_augment_adds_columns_to_existing_files() {
+    let temp_dir = tempdir().expect("Failed to create temporary directory");
+    let source = temp_dir.path().join("division_area.parquet");
+    write_zone_source(&source, 3).await;
+    let output = temp_dir.path().join("out");
+    Command::cargo_bin("spatialbench-cli")
+        .expect("Binary not found")
+        .arg("--tables")
+        .arg("zone")
+        .arg("--output-dir")
+        .arg(&output)
+        .arg("--input-theme")
+        .arg(format!("division_area={}", source.display()))
+        .assert()
+        .success();
+
+    let augment = || {
+        let mut command = Command::cargo_bin("spatialbench-cli").expect("Binary not found");
+        command
+            .arg("augment")
+            .arg("--data-dir")
+            .arg(&output)
+            .arg("--add-columns")
+            .arg("bbox,area");
+        command
+    };
+    augment()
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(
+            "ADDED zone.parquet: z_bbox, z_area",
+        ));
+    let file = File::open(output.join("zone.parquet")).unwrap();
+    let schema = ParquetRecordBatchReaderBuilder::try_new(file)
+        .unwrap()
+        .schema()
+        .clone();
+    assert!(schema.field_with_name("z_bbox").is_ok());
+    assert!(schema.field_with_name("z_area").is_ok());
+    let dataset = DatasetManifest::read(&output).unwrap().unwrap();
+    let zone = dataset.tables.iter().find(|t| t.name == "zone").unwrap();
+    assert_eq!(
+        zone.files[0].bytes,
+        fs::metadata(output.join("zone.parquet")).unwrap().len()
+    );
+    assert_eq!(zone.schema_version.as_deref(), Some("custom"));
+
+    augment()
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("SKIPPED zone.parquet"));
+    Command::cargo_bin("spatialbench-cli")
+        .expect("Binary not found")
+        .arg("verify")
+        .arg("--data-dir")
+        .arg(&output)
+        .assert()
+        .success();
+}
+
+#[tokio::test]
+async fn test_validate_geometries_reports_without_changes() {
+    let temp_dir = tempdir().expect("Failed to create temporary directory");
+    let source = temp_dir.path().join("division_area.parquet");
+    write_zone_source(&source, 3).await;
+    let output = temp_dir.path().join("out");
+    Command::cargo_bin("spatialbench-cli")
+        .expect("Binary not found")
+        .arg("--tables")
+        .arg("zone")
+        .arg("--output-dir")
+        .arg(&output)
+        .arg("--input-theme")
+        .arg(format!("division_area={}", source.display()))
+        .assert()
+        .success();
+    let before = fs::read(output.join("zone.parquet")).unwrap();
+
+    let json = temp_dir.path().join("geometries.json");
+    Command::cargo_bin("spatialbench-cli")
+        .expect("Binary not found")
+        .arg("validate-geometries")
+        .arg("--data-dir")
+        .arg(&output)
+        .arg("--json")
+        .arg(&json)
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(
+            "zone.parquet: 3 geometries, 0 null or invalid",
+        ))
+        .stdout(predicates::str::contains(
+            "Overall: 3 geometries, 0 null or invalid",
+        ));
+
+    let report: serde_json::Value = serde_json::from_slice(&fs::read(&json).unwrap()).unwrap();
+    assert_eq!(report["total"]["geometries"], 3);
+    assert_eq!(report["files"]["zone.parquet"]["cw_exteriors"], 0);
+    assert_eq!(fs::read(output.join("zone.parquet")).unwrap(), before);
+}
+
+#[tokio::test]
+async fn test_no_geometry_skips_the_source_geometry() {
+    let temp_dir = tempdir().expect("Failed to create temporary directory");
+    let source = temp_dir.path().join("division_area.parquet");
+    write_zone_source(&source, 4).await;
+    Command::cargo_bin("spatialbench-cli")
+        .expect("Binary not found")
+        .arg("--tables")
+        .arg("zone")
+        .arg("--no-geometry")
+        .arg("--explain")
+        .arg("--output-dir")
+        .arg(temp_dir.path())
+        .arg("--input-theme")
+        .arg(format!("division_area={}", source.display()))
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(
+            "Scanned columns: country, id, is_land, names, region, subtype",
+        ));
+}
+
+#[tokio::test]
+async fn test_diff_against_plans_without_generating() {
+    let temp_dir = tempdir().expect("Failed to create temporary directory");
+    let source = temp_dir.path().join("division_area.parquet");
+    write_zone_source(&source, 4).await;
+    let output = temp_dir.path().join("out");
+    let theme = format!("division_area={}", source.display());
+    let zone = |args: &[&str]| {
+        let mut command = Command::cargo_bin("spatialbench-cli").expect("Binary not found");
+        command
+            .arg("--tables")
+            .arg("zone")
+            .arg("--output-dir")
+            .arg(&output)
+            .arg("--input-theme")
+            .arg(&theme)
+            .args(args);
+        command
+    };
+    zone(&["--parts", "2"]).assert().success();
+    let manifest = output.join("zone.manifest.json");
+    let manifest = manifest.to_str().unwrap();
+    let before = fs::read(manifest).unwrap();
+
+    zone(&["--parts", "2", "--diff-against", manifest])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("No differences from"));
+
+    zone(&["--parts", "4", "--with-area", "--diff-against", manifest])
+        .assert()
+        .code(3)
+        .stdout(predicates::str::contains(
+            "parts: 2 in the existing outputs, 4 requested",
+        ))
+        .stdout(predicates::str::contains("part 1: 2 -> 1 (-1)"))
+        .stdout(predicates::str::contains("part 4: - -> 1"))
+        .stdout(predicates::str::contains("  + z_area: Float64"));
+    assert_eq!(fs::read(manifest).unwrap(), before);
+    assert!(!output.join("zone/zone.3.parquet").exists());
+}
+
+#[test]
+fn test_upload_to_rejects_local_urls_before_generating() {
+    let temp_dir = tempdir().expect("Failed to create temporary directory");
+    let output = temp_dir.path().join("out");
+    Command::cargo_bin("spatialbench-cli")
+        .expect("Binary not found")
+        .arg("--tables")
+        .arg("vehicle")
+        .arg("--output-dir")
+        .arg(&output)
+        .arg("--upload-to")
+        .arg("file:///tmp/spatialbench")
+        .arg("--write-retries")
+        .arg("5")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("Unsupported upload URL"));
+    assert!(!output.exists());
+
+    Command::cargo_bin("spatialbench-cli")
+        .expect("Binary not found")
+        .arg("--output-dir")
+        .arg(&output)
+        .arg("--write-retries")
+        .arg("5")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("--upload-to"));
+}
+
+#[test]
+fn test_zstd_train_dict_requires_zstd_before_generating() {
+    let temp_dir = tempdir().expect("Failed to create temporary directory");
+    let output = temp_dir.path().join("out");
+    Command::cargo_bin("spatialbench-cli")
+        .expect("Binary not found")
+        .arg("--tables")
+        .arg("zone")
+        .arg("--output-dir")
+        .arg(&output)
+        .arg("--parquet-zstd-train-dict")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains(
+            "--parquet-zstd-train-dict requires ZSTD compression, not SNAPPY",
+        ));
+    assert!(!output.exists());
+}
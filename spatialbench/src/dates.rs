@@ -90,13 +90,23 @@ impl GenerateUtils {
             (scale_base as f64 * scale_factor) as i64
         };
 
+        Self::calculate_part_rows(total_row_count, part, part_count).1
+    }
+
+    /// Calculates the start index and row count of a part when
+    /// `total_row_count` rows are split into `part_count` parts
+    pub fn calculate_part_rows(total_row_count: i64, part: i32, part_count: i32) -> (i64, i64) {
         let rows_per_part = total_row_count / part_count as i64;
+        let start_index = rows_per_part * (part as i64 - 1);
 
         if part == part_count {
             // for the last part, add the remainder rows
-            rows_per_part + (total_row_count % part_count as i64)
+            (
+                start_index,
+                rows_per_part + (total_row_count % part_count as i64),
+            )
         } else {
-            rows_per_part
+            (start_index, rows_per_part)
         }
     }
 
@@ -257,6 +267,45 @@ impl TPCHDate {
     }
 }
 
+/// Parses a `yyyy-mm-dd` date into its generated date value, e.g.
+/// `1992-01-01` into [`MIN_GENERATE_DATE`].
+///
+/// Dates before and after the generated range are clamped to
+/// [`MIN_GENERATE_DATE`] and `MIN_GENERATE_DATE + TOTAL_DATE_RANGE`, so they
+/// can be used as the bounds of a range of generated dates. Returns `None`
+/// for malformed dates.
+///
+/// # Example
+/// ```
+/// # use spatialbench::dates::{parse_generated_date, MIN_GENERATE_DATE, TOTAL_DATE_RANGE};
+/// assert_eq!(parse_generated_date("1992-02-11"), Some(MIN_GENERATE_DATE + 41));
+/// assert_eq!(
+///     parse_generated_date("2024-01-01"),
+///     Some(MIN_GENERATE_DATE + TOTAL_DATE_RANGE)
+/// );
+/// assert_eq!(parse_generated_date("1992-13-01"), None);
+/// ```
+pub fn parse_generated_date(date: &str) -> Option<i32> {
+    let bytes = date.as_bytes();
+    let well_formed = bytes.len() == 10
+        && bytes.iter().enumerate().all(|(i, b)| match i {
+            4 | 7 => *b == b'-',
+            _ => b.is_ascii_digit(),
+        });
+    if !well_formed || !("01"..="12").contains(&&date[5..7]) || !("01"..="31").contains(&&date[8..])
+    {
+        return None;
+    }
+    // Formatted dates sort like the dates themselves
+    let (Ok(index) | Err(index)) = DATE_TO_STRING.binary_search_by(|d| d.as_str().cmp(date));
+    Some(index as i32 + MIN_GENERATE_DATE)
+}
+
+/// Formats a generated date value as `yyyy-mm-dd`
+pub fn format_generated_date(date: i32) -> &'static str {
+    &DATE_TO_STRING[(date - MIN_GENERATE_DATE) as usize]
+}
+
 /// Creates a index table of formatted strings
 ///
 /// index: dates generated by the data generator
@@ -37,6 +37,7 @@ use rand::{Rng, SeedableRng};
 use std::convert::TryInto;
 use std::fmt;
 use std::fmt::Display;
+use std::ops::Range;
 
 /// A Vehicle Manufacturer, formatted as `"Manufacturer#<n>"`
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -109,6 +110,7 @@ pub struct VehicleGenerator<'a> {
     scale_factor: f64,
     part: i32,
     part_count: i32,
+    row_count: Option<i64>,
     distributions: &'a Distributions,
     text_pool: &'a TextPool,
 }
@@ -154,11 +156,20 @@ impl<'a> VehicleGenerator<'a> {
             scale_factor,
             part,
             part_count,
+            row_count: None,
             distributions,
             text_pool,
         }
     }
 
+    /// Generates `row_count` rows across all parts instead of the count
+    /// derived from the scale factor. Keys referring to other tables still
+    /// range over the scale factor sizes of those tables.
+    pub fn with_row_count(mut self, row_count: i64) -> Self {
+        self.row_count = Some(row_count);
+        self
+    }
+
     /// Return the row count for the given scale factor and generator part count
     pub fn calculate_row_count(scale_factor: f64, part: i32, part_count: i32) -> i64 {
         GenerateUtils::calculate_row_count(Self::SCALE_BASE, scale_factor, part, part_count)
@@ -166,17 +177,19 @@ impl<'a> VehicleGenerator<'a> {
 
     /// Returns an iterator over the part rows
     pub fn iter(&self) -> VehicleGeneratorIterator<'a> {
-        VehicleGeneratorIterator::new(
-            self.distributions,
-            self.text_pool,
-            GenerateUtils::calculate_start_index(
-                Self::SCALE_BASE,
-                self.scale_factor,
-                self.part,
-                self.part_count,
+        let (start_index, row_count) = match self.row_count {
+            Some(total) => GenerateUtils::calculate_part_rows(total, self.part, self.part_count),
+            None => (
+                GenerateUtils::calculate_start_index(
+                    Self::SCALE_BASE,
+                    self.scale_factor,
+                    self.part,
+                    self.part_count,
+                ),
+                Self::calculate_row_count(self.scale_factor, self.part, self.part_count),
             ),
-            Self::calculate_row_count(self.scale_factor, self.part, self.part_count),
-        )
+        };
+        VehicleGeneratorIterator::new(self.distributions, self.text_pool, start_index, row_count)
     }
 }
 
@@ -376,6 +389,7 @@ pub struct DriverGenerator<'a> {
     scale_factor: f64,
     part: i32,
     part_count: i32,
+    row_count: Option<i64>,
     distributions: &'a Distributions,
     text_pool: &'a TextPool,
 }
@@ -429,11 +443,20 @@ impl<'a> DriverGenerator<'a> {
             scale_factor,
             part,
             part_count,
+            row_count: None,
             distributions,
             text_pool,
         }
     }
 
+    /// Generates `row_count` rows across all parts instead of the count
+    /// derived from the scale factor. Keys referring to other tables still
+    /// range over the scale factor sizes of those tables.
+    pub fn with_row_count(mut self, row_count: i64) -> Self {
+        self.row_count = Some(row_count);
+        self
+    }
+
     /// Return the row count for the given scale factor and generator part count
     pub fn calculate_row_count(scale_factor: f64, part: i32, part_count: i32) -> i64 {
         GenerateUtils::calculate_row_count(Self::SCALE_BASE, scale_factor, part, part_count)
@@ -441,17 +464,19 @@ impl<'a> DriverGenerator<'a> {
 
     /// Returns an iterator over the Driver rows
     pub fn iter(&self) -> DriverGeneratorIterator<'a> {
-        DriverGeneratorIterator::new(
-            self.distributions,
-            self.text_pool,
-            GenerateUtils::calculate_start_index(
-                Self::SCALE_BASE,
-                self.scale_factor,
-                self.part,
-                self.part_count,
+        let (start_index, row_count) = match self.row_count {
+            Some(total) => GenerateUtils::calculate_part_rows(total, self.part, self.part_count),
+            None => (
+                GenerateUtils::calculate_start_index(
+                    Self::SCALE_BASE,
+                    self.scale_factor,
+                    self.part,
+                    self.part_count,
+                ),
+                Self::calculate_row_count(self.scale_factor, self.part, self.part_count),
             ),
-            Self::calculate_row_count(self.scale_factor, self.part, self.part_count),
-        )
+        };
+        DriverGeneratorIterator::new(self.distributions, self.text_pool, start_index, row_count)
     }
 }
 
@@ -662,6 +687,7 @@ pub struct CustomerGenerator<'a> {
     scale_factor: f64,
     part: i32,
     part_count: i32,
+    row_count: Option<i64>,
     distributions: &'a Distributions,
     text_pool: &'a TextPool,
 }
@@ -703,11 +729,20 @@ impl<'a> CustomerGenerator<'a> {
             scale_factor,
             part,
             part_count,
+            row_count: None,
             distributions,
             text_pool,
         }
     }
 
+    /// Generates `row_count` rows across all parts instead of the count
+    /// derived from the scale factor. Keys referring to other tables still
+    /// range over the scale factor sizes of those tables.
+    pub fn with_row_count(mut self, row_count: i64) -> Self {
+        self.row_count = Some(row_count);
+        self
+    }
+
     /// Return the row count for the given scale factor and generator part count
     pub fn calculate_row_count(scale_factor: f64, part: i32, part_count: i32) -> i64 {
         GenerateUtils::calculate_row_count(Self::SCALE_BASE, scale_factor, part, part_count)
@@ -715,17 +750,19 @@ impl<'a> CustomerGenerator<'a> {
 
     /// Returns an iterator over the customer rows
     pub fn iter(&self) -> CustomerGeneratorIterator<'a> {
-        CustomerGeneratorIterator::new(
-            self.distributions,
-            self.text_pool,
-            GenerateUtils::calculate_start_index(
-                Self::SCALE_BASE,
-                self.scale_factor,
-                self.part,
-                self.part_count,
+        let (start_index, row_count) = match self.row_count {
+            Some(total) => GenerateUtils::calculate_part_rows(total, self.part, self.part_count),
+            None => (
+                GenerateUtils::calculate_start_index(
+                    Self::SCALE_BASE,
+                    self.scale_factor,
+                    self.part,
+                    self.part_count,
+                ),
+                Self::calculate_row_count(self.scale_factor, self.part, self.part_count),
             ),
-            Self::calculate_row_count(self.scale_factor, self.part, self.part_count),
-        )
+        };
+        CustomerGeneratorIterator::new(self.distributions, self.text_pool, start_index, row_count)
     }
 }
 
@@ -896,11 +933,13 @@ pub struct TripGenerator {
     scale_factor: f64,
     part: i32,
     part_count: i32,
+    row_count: Option<i64>,
     distributions: Distributions,
     text_pool: TextPool,
     distance_kde: crate::kde::DistanceKDE,
     spatial_gen: SpatialGenerator,
     continent_cdf: Vec<WeightedTarget>,
+    pickup_dates: Option<Range<i32>>,
 }
 
 impl TripGenerator {
@@ -950,14 +989,61 @@ impl TripGenerator {
             scale_factor,
             part,
             part_count,
+            row_count: None,
             distributions: distributions.clone(),
             text_pool: text_pool.clone(),
             distance_kde,
             spatial_gen,
             continent_cdf,
+            pickup_dates: None,
         }
     }
 
+    /// Only generates the trips picked up on `pickup_dates`, generated date
+    /// values (see [`dates::parse_generated_date`]).
+    ///
+    /// Pickup dates are then assigned in key order, spread evenly over the
+    /// generated dates by [`Self::ordered_pickup_date`], rather than drawn at
+    /// random. The trips of a window are the contiguous range of keys given
+    /// by [`Self::pickup_date_rows`], which the parts split among themselves;
+    /// all other values are those of the same key without this option. A
+    /// series of windows covering the generated dates thus yields the trips
+    /// of a single window spanning them all.
+    pub fn with_pickup_dates(mut self, pickup_dates: Range<i32>) -> Self {
+        self.pickup_dates = Some(pickup_dates);
+        self
+    }
+
+    /// The pickup date of the trip at 0-based `index` of `total_rows` when
+    /// pickup dates are assigned in key order. Non-decreasing in `index` and
+    /// covering the generated dates evenly.
+    pub fn ordered_pickup_date(index: i64, total_rows: i64) -> i32 {
+        let offset = index as i128 * dates::TOTAL_DATE_RANGE as i128 / total_rows.max(1) as i128;
+        dates::MIN_GENERATE_DATE + offset as i32
+    }
+
+    /// The 0-based indexes of the trips of `total_rows` whose
+    /// [`Self::ordered_pickup_date`] falls in `pickup_dates`
+    pub fn pickup_date_rows(total_rows: i64, pickup_dates: &Range<i32>) -> Range<i64> {
+        // The first index whose ordered date is at least `date`
+        let first_index = |date: i32| {
+            let offset = (date - dates::MIN_GENERATE_DATE).clamp(0, dates::TOTAL_DATE_RANGE);
+            let rows = offset as i128 * total_rows as i128;
+            let range = dates::TOTAL_DATE_RANGE as i128;
+            ((rows + range - 1) / range) as i64
+        };
+        let start = first_index(pickup_dates.start);
+        start..first_index(pickup_dates.end).max(start)
+    }
+
+    /// Generates `row_count` rows across all parts instead of the count
+    /// derived from the scale factor. Keys referring to other tables still
+    /// range over the scale factor sizes of those tables.
+    pub fn with_row_count(mut self, row_count: i64) -> Self {
+        self.row_count = Some(row_count);
+        self
+    }
+
     /// Return the row count for the given scale factor and generator part count
     pub fn calculate_row_count(scale_factor: f64, part: i32, part_count: i32) -> i64 {
         GenerateUtils::calculate_row_count(Self::SCALE_BASE, scale_factor, part, part_count)
@@ -965,25 +1051,42 @@ impl TripGenerator {
 
     /// Returns an iterator over the trip rows
     pub fn iter(&self) -> TripGeneratorIterator {
+        let total_rows = self
+            .row_count
+            .unwrap_or_else(|| Self::calculate_row_count(self.scale_factor, 1, 1));
+        let (start_index, row_count) = match (&self.pickup_dates, self.row_count) {
+            (Some(pickup_dates), _) => {
+                let rows = Self::pickup_date_rows(total_rows, pickup_dates);
+                let (offset, row_count) = GenerateUtils::calculate_part_rows(
+                    rows.end - rows.start,
+                    self.part,
+                    self.part_count,
+                );
+                (rows.start + offset, row_count)
+            }
+            (None, Some(total)) => {
+                GenerateUtils::calculate_part_rows(total, self.part, self.part_count)
+            }
+            (None, None) => (
+                GenerateUtils::calculate_start_index(
+                    Self::SCALE_BASE,
+                    self.scale_factor,
+                    self.part,
+                    self.part_count,
+                ),
+                Self::calculate_row_count(self.scale_factor, self.part, self.part_count),
+            ),
+        };
         TripGeneratorIterator::new(
             &self.distributions,
             &self.text_pool,
             self.scale_factor,
-            GenerateUtils::calculate_start_index(
-                Self::SCALE_BASE,
-                self.scale_factor,
-                self.part,
-                self.part_count,
-            ),
-            GenerateUtils::calculate_row_count(
-                Self::SCALE_BASE,
-                self.scale_factor,
-                self.part,
-                self.part_count,
-            ),
+            start_index,
+            row_count,
             self.distance_kde.clone(), // Add the KDE model
             self.spatial_gen.clone(),
             self.continent_cdf.clone(),
+            self.pickup_dates.as_ref().map(|_| total_rows),
         )
     }
 }
@@ -1011,6 +1114,8 @@ pub struct TripGeneratorIterator {
     distance_kde: crate::kde::DistanceKDE,
     spatial_gen: SpatialGenerator,
     continent_cdf: Vec<WeightedTarget>,
+    /// Total trips the pickup dates are assigned over in key order, if so
+    ordered_dates_rows: Option<i64>,
 
     scale_factor: f64,
     start_index: i64,
@@ -1032,6 +1137,7 @@ impl TripGeneratorIterator {
         distance_kde: crate::kde::DistanceKDE,
         spatial_gen: SpatialGenerator,
         continent_cdf: Vec<WeightedTarget>,
+        ordered_dates_rows: Option<i64>,
     ) -> Self {
         // Create all the randomizers
         let max_customer_key = (CustomerGenerator::SCALE_BASE as f64 * scale_factor) as i64;
@@ -1089,6 +1195,7 @@ impl TripGeneratorIterator {
             distance_kde,
             spatial_gen,
             continent_cdf,
+            ordered_dates_rows,
 
             scale_factor,
             start_index,
@@ -1118,7 +1225,10 @@ impl TripGeneratorIterator {
             self.scale_factor,
         );
 
-        let pickup_date_value = self.pickup_date_random.next_value();
+        let pickup_date_value = match self.ordered_dates_rows {
+            Some(total_rows) => TripGenerator::ordered_pickup_date(trip_key - 1, total_rows),
+            None => self.pickup_date_random.next_value(),
+        };
         let pickup_time = self.pickup_time_random.next_value();
         let pickup_date = TPCHDate::new_with_time(pickup_date_value, pickup_time);
 
@@ -1268,6 +1378,7 @@ pub struct BuildingGenerator<'a> {
     scale_factor: f64,
     part: i32,
     part_count: i32,
+    row_count: Option<i64>,
     distributions: &'a Distributions,
     text_pool: &'a TextPool,
     spatial_gen: SpatialGenerator,
@@ -1316,6 +1427,7 @@ impl<'a> BuildingGenerator<'a> {
             scale_factor,
             part,
             part_count,
+            row_count: None,
             distributions,
             text_pool,
             spatial_gen,
@@ -1323,6 +1435,14 @@ impl<'a> BuildingGenerator<'a> {
         }
     }
 
+    /// Generates `row_count` rows across all parts instead of the count
+    /// derived from the scale factor. Keys referring to other tables still
+    /// range over the scale factor sizes of those tables.
+    pub fn with_row_count(mut self, row_count: i64) -> Self {
+        self.row_count = Some(row_count);
+        self
+    }
+
     /// Return the row count for the given scale factor and generator part count
     pub fn calculate_row_count(scale_factor: f64, part: i32, part_count: i32) -> i64 {
         GenerateUtils::calculate_logarithmic_row_count(
@@ -1335,16 +1455,23 @@ impl<'a> BuildingGenerator<'a> {
 
     /// Returns an iterator over the part rows
     pub fn iter(&self) -> BuildingGeneratorIterator<'a> {
+        let (start_index, row_count) = match self.row_count {
+            Some(total) => GenerateUtils::calculate_part_rows(total, self.part, self.part_count),
+            None => (
+                GenerateUtils::calculate_start_index(
+                    Self::SCALE_BASE,
+                    self.scale_factor,
+                    self.part,
+                    self.part_count,
+                ),
+                Self::calculate_row_count(self.scale_factor, self.part, self.part_count),
+            ),
+        };
         BuildingGeneratorIterator::new(
             self.distributions,
             self.text_pool,
-            GenerateUtils::calculate_start_index(
-                Self::SCALE_BASE,
-                self.scale_factor,
-                self.part,
-                self.part_count,
-            ),
-            Self::calculate_row_count(self.scale_factor, self.part, self.part_count),
+            start_index,
+            row_count,
             self.spatial_gen.clone(),
             self.continent_cdf.clone(),
         )
@@ -1456,6 +1583,8 @@ impl<'a> Iterator for BuildingGeneratorIterator<'a> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::spatial::PointDistribution;
+
     #[test]
     fn test_vehicle_generation() {
         // Create a generator with a small scale factor
@@ -1566,6 +1695,95 @@ mod tests {
         assert_eq!(first.to_string(), "2|172|1|1|1997-12-24 08:47:14|1997-12-24 09:28:57|0.03|0.00|0.04|0.01|POINT(94.423867952 29.887250009)|POINT(94.43760277 29.88940658)|");
     }
 
+    #[test]
+    fn test_trip_pickup_date_windows_match_full_run() {
+        let split = dates::parse_generated_date("1995-07-01").unwrap();
+        let end = dates::MIN_GENERATE_DATE + dates::TOTAL_DATE_RANGE;
+        let full: Vec<_> = TripGenerator::new(0.001, 1, 1)
+            .with_pickup_dates(dates::MIN_GENERATE_DATE..end)
+            .iter()
+            .collect();
+        assert_eq!(full.len(), 6000);
+        assert!(full
+            .windows(2)
+            .all(|pair| pair[0].t_pickuptime.into_inner() <= pair[1].t_pickuptime.into_inner()));
+
+        let mut windowed = Vec::new();
+        for window in [dates::MIN_GENERATE_DATE..split, split..end] {
+            // The keys of a window are a contiguous range, split across parts
+            let rows = TripGenerator::pickup_date_rows(6000, &window);
+            let mut keys = Vec::new();
+            for part in 1..=3 {
+                for trip in TripGenerator::new(0.001, part, 3).with_pickup_dates(window.clone()) {
+                    let pickup = trip.t_pickuptime.into_inner() + dates::MIN_GENERATE_DATE;
+                    assert!(window.contains(&pickup), "{trip}");
+                    keys.push(trip.t_tripkey);
+                    windowed.push(trip);
+                }
+            }
+            assert_eq!(keys, (rows.start + 1..=rows.end).collect::<Vec<_>>());
+        }
+        assert_eq!(windowed, full);
+
+        // Only the pickup and dropoff times differ from random pickup dates
+        let random: Vec<_> = TripGenerator::new(0.001, 1, 1).iter().collect();
+        for (ordered, random) in full.iter().zip(&random) {
+            assert_eq!(
+                (ordered.t_tripkey, ordered.t_custkey, ordered.t_driverkey),
+                (random.t_tripkey, random.t_custkey, random.t_driverkey)
+            );
+            assert_eq!(
+                (ordered.t_fare, ordered.t_tip, ordered.t_distance),
+                (random.t_fare, random.t_tip, random.t_distance)
+            );
+            assert_eq!(ordered.t_pickuploc, random.t_pickuploc);
+        }
+    }
+
+    #[test]
+    fn test_pickup_date_rows() {
+        let start = dates::MIN_GENERATE_DATE;
+        let end = start + dates::TOTAL_DATE_RANGE;
+        assert_eq!(TripGenerator::pickup_date_rows(10, &(start..end)), 0..10);
+        assert_eq!(
+            TripGenerator::pickup_date_rows(10, &(start - 5..start)),
+            0..0
+        );
+        assert_eq!(TripGenerator::pickup_date_rows(10, &(end..end + 5)), 10..10);
+
+        // Every row falls in exactly the window its date says
+        let total = 100_000;
+        let window = start + 100..start + 107;
+        let rows = TripGenerator::pickup_date_rows(total, &window);
+        assert!(!rows.is_empty());
+        for index in rows.start - 1..=rows.end {
+            let date = TripGenerator::ordered_pickup_date(index, total);
+            assert_eq!(window.contains(&date), rows.contains(&index), "{index}");
+        }
+    }
+
+    #[test]
+    fn test_row_count_overrides_scale_factor() {
+        let full: Vec<_> = TripGenerator::new(0.001, 1, 1).iter().collect();
+
+        // The explicit count is split across parts like a scaled one
+        let mut trips = Vec::new();
+        for part in 1..=3 {
+            let part_trips: Vec<_> = TripGenerator::new(0.001, part, 3)
+                .with_row_count(100)
+                .iter()
+                .collect();
+            assert_eq!(part_trips.len(), if part == 3 { 34 } else { 33 });
+            trips.extend(part_trips);
+        }
+        assert_eq!(trips, full[..100]);
+
+        let vehicles = VehicleGenerator::new(1.0, 1, 1).with_row_count(7);
+        assert_eq!(vehicles.iter().count(), 7);
+        let buildings = BuildingGenerator::new(1.0, 1, 1).with_row_count(7);
+        assert_eq!(buildings.iter().count(), 7);
+    }
+
     #[test]
     fn test_building_generation() {
         // Create a generator with a small scale factor
@@ -1591,4 +1809,72 @@ mod tests {
         assert_eq!(first.b_buildingkey, 2);
         assert_eq!(first.to_string(), "2|blush|POLYGON((124.218033476 10.538071565,124.217919324 10.539075339,124.212486371 10.539913704,124.214352934 10.536014944,124.215762091 10.536069114,124.218033476 10.538071565))|")
     }
+
+    fn trip_pickups(distribution: PointDistribution, part: i32, part_count: i32) -> Vec<Point> {
+        TripGenerator::new_with_distributions_and_text_pool(
+            1.0,
+            part,
+            part_count,
+            Distributions::static_default(),
+            TextPool::get_or_init_default(),
+            crate::kde::default_distance_kde(),
+            SpatialDefaults::trip_with_distribution(distribution, 1.0),
+        )
+        .with_row_count(2000)
+        .iter()
+        .map(|trip| trip.t_pickuploc)
+        .collect()
+    }
+
+    /// Median distance, in degrees, from each point to its nearest neighbor
+    fn median_nearest_neighbor(points: &[Point]) -> f64 {
+        let mut distances: Vec<f64> = points
+            .iter()
+            .enumerate()
+            .map(|(i, p)| {
+                points
+                    .iter()
+                    .enumerate()
+                    .filter(|(j, _)| *j != i)
+                    .map(|(_, q)| (p.x() - q.x()).hypot(p.y() - q.y()))
+                    .fold(f64::INFINITY, f64::min)
+            })
+            .collect();
+        distances.sort_by(f64::total_cmp);
+        distances[distances.len() / 2]
+    }
+
+    #[test]
+    fn test_point_distributions() {
+        let clustered = trip_pickups(PointDistribution::Clustered, 1, 1);
+        let weighted = trip_pickups(PointDistribution::PopulationWeighted, 1, 1);
+        let uniform = trip_pickups(PointDistribution::Uniform, 1, 1);
+
+        // the default trips are the clustered ones
+        let default: Vec<Point> = TripGenerator::new(1.0, 1, 1)
+            .with_row_count(2000)
+            .iter()
+            .map(|trip| trip.t_pickuploc)
+            .collect();
+        assert_eq!(clustered, default);
+
+        // the same points however the trips are split
+        for distribution in [
+            PointDistribution::Uniform,
+            PointDistribution::PopulationWeighted,
+        ] {
+            let mut parts = trip_pickups(distribution, 1, 2);
+            parts.extend(trip_pickups(distribution, 2, 2));
+            assert_eq!(parts, trip_pickups(distribution, 1, 1));
+        }
+
+        let clustered = median_nearest_neighbor(&clustered);
+        let weighted = median_nearest_neighbor(&weighted);
+        let uniform = median_nearest_neighbor(&uniform);
+        assert!(
+            clustered * 2.0 < weighted && weighted * 2.0 < uniform,
+            "median nearest neighbor distances: clustered {clustered}, \
+            population-weighted {weighted}, uniform {uniform}"
+        );
+    }
 }
@@ -26,6 +26,54 @@ pub enum DistributionType {
     HierarchicalThomas,
 }
 
+/// How the trip pickup points are spread, the generate `--point-distribution`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PointDistribution {
+    /// Points around Pareto-weighted city hotspots, then subclusters within
+    /// each city; the default trip distribution
+    Clustered,
+    /// Points uniformly within the continent bounding boxes
+    Uniform,
+    /// Points around many town centers with heavy-tailed, population-like
+    /// weights, spread more widely than the city hotspots
+    PopulationWeighted,
+}
+
+impl PointDistribution {
+    /// The names accepted by [`str::parse`], in declaration order
+    pub const NAMES: [&'static str; 3] = ["clustered", "uniform", "population-weighted"];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Clustered => "clustered",
+            Self::Uniform => "uniform",
+            Self::PopulationWeighted => "population-weighted",
+        }
+    }
+}
+
+impl std::fmt::Display for PointDistribution {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl std::str::FromStr for PointDistribution {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "clustered" => Ok(Self::Clustered),
+            "uniform" => Ok(Self::Uniform),
+            "population-weighted" => Ok(Self::PopulationWeighted),
+            _ => Err(format!(
+                "unknown point distribution: {s}, expected one of {}",
+                Self::NAMES.join(", ")
+            )),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum GeomType {
     Polygon,
@@ -16,8 +16,8 @@
 // under the License.
 
 use crate::spatial::{
-    ContinentAffines, DistributionParams, DistributionType, GeomType, SpatialConfig,
-    SpatialGenerator,
+    ContinentAffines, DistributionParams, DistributionType, GeomType, PointDistribution,
+    SpatialConfig, SpatialGenerator,
 };
 use std::sync::OnceLock;
 
@@ -71,6 +71,47 @@ impl SpatialDefaults {
         SpatialGenerator::new(config, OnceLock::new(), OnceLock::new())
     }
 
+    /// The trip pickup generator for `distribution`, with the cluster spread
+    /// scaled by `dispersion`. Clustered points with a dispersion of 1 are
+    /// the [`Self::trip_default`] points; uniform points ignore the
+    /// dispersion. Every mode keeps the trip seed, so the points depend only
+    /// on the trip key and not on how the trips are partitioned.
+    pub fn trip_with_distribution(
+        distribution: PointDistribution,
+        dispersion: f64,
+    ) -> SpatialGenerator {
+        let mut generator = Self::trip_default();
+        let config = &mut generator.config;
+        match distribution {
+            PointDistribution::Clustered => {
+                if let DistributionParams::HierarchicalThomas {
+                    sigma_city,
+                    sigma_sub,
+                    ..
+                } = &mut config.params
+                {
+                    *sigma_city *= dispersion;
+                    *sigma_sub *= dispersion;
+                }
+            }
+            PointDistribution::Uniform => {
+                config.dist_type = DistributionType::Uniform;
+                config.params = DistributionParams::None;
+            }
+            PointDistribution::PopulationWeighted => {
+                config.dist_type = DistributionType::Thomas;
+                config.params = DistributionParams::Thomas {
+                    parents: 2000,
+                    mean_offspring: 1.0,
+                    sigma: 0.01 * dispersion,
+                    pareto_alpha: 1.0,
+                    pareto_xm: 1.0,
+                };
+            }
+        }
+        generator
+    }
+
     pub fn building_default() -> SpatialGenerator {
         let config = SpatialConfig {
             dist_type: DistributionType::HierarchicalThomas,